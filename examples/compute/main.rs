@@ -19,10 +19,7 @@ use hal::{
 };
 use hal::{queue, pso, memory, buffer, pool, command};
 
-extern crate glsl_to_spirv;
-
 use std::fs;
-use std::io::Read;
 
 #[cfg(any(feature = "vulkan", feature = "dx12", feature = "metal"))]
 fn main() {
@@ -52,12 +49,7 @@ fn main() {
         .unwrap();
 
     let glsl = fs::read_to_string("compute/shader/collatz.comp").unwrap();
-    let spirv: Vec<u8> = glsl_to_spirv::compile(&glsl, glsl_to_spirv::ShaderType::Compute)
-        .unwrap()
-        .bytes()
-        .map(|b| b.unwrap())
-        .collect();
-    let shader = device.create_shader_module(&spirv).unwrap();
+    let shader = device.create_shader_module_from_glsl(pso::Stage::Compute, &glsl).unwrap();
 
     let (pipeline_layout, pipeline, set_layout, mut desc_pool) = {
         let set_layout = device.create_descriptor_set_layout(
@@ -137,7 +129,8 @@ fn main() {
             memory::Dependencies::empty(),
             Some(memory::Barrier::Buffer {
                 states: buffer::Access::TRANSFER_WRITE .. buffer::Access::SHADER_READ | buffer::Access::SHADER_WRITE,
-                target: &device_buffer
+                target: &device_buffer,
+                families: None,
             }),
         );
         command_buffer.bind_compute_pipeline(&pipeline);
@@ -148,7 +141,8 @@ fn main() {
             memory::Dependencies::empty(),
             Some(memory::Barrier::Buffer {
                 states: buffer::Access::SHADER_READ | buffer::Access::SHADER_WRITE .. buffer::Access::TRANSFER_READ,
-                target: &device_buffer
+                target: &device_buffer,
+                families: None,
             }),
         );
         command_buffer.copy_buffer(&device_buffer, &staging_buffer, &[command::BufferCopy { src: 0, dst: 0, size: stride * numbers.len() as u64}]);
@@ -0,0 +1,172 @@
+//! Renders to several windows at once, spreading their work over multiple queues
+//! and recreating the device if it gets lost.
+
+#![cfg_attr(
+    not(any(feature = "vulkan", feature = "dx12", feature = "metal")),
+    allow(dead_code, unused_extern_crates, unused_imports)
+)]
+
+extern crate env_logger;
+#[cfg(feature = "dx12")]
+extern crate gfx_backend_dx12 as back;
+#[cfg(feature = "metal")]
+extern crate gfx_backend_metal as back;
+#[cfg(feature = "vulkan")]
+extern crate gfx_backend_vulkan as back;
+extern crate gfx_hal as hal;
+extern crate gfx_helpers as helpers;
+extern crate gfx_support as support;
+
+use hal::{command, pso, Backend, Instance};
+use hal::pso::PipelineStage;
+use hal::queue::Submission;
+use helpers::frame::FrameContext;
+use support::{Context, DeviceLost, EventLoop, Target};
+
+const WINDOW_COUNT: usize = 3;
+const QUEUE_COUNT: usize = 2;
+const FRAMES_IN_FLIGHT: usize = 2;
+const MAX_DEVICE_LOSSES: usize = 3;
+
+const COLORS: [[f32; 4]; WINDOW_COUNT] = [
+    [0.8, 0.2, 0.2, 1.0],
+    [0.2, 0.8, 0.2, 1.0],
+    [0.2, 0.2, 0.8, 1.0],
+];
+
+#[cfg(any(feature = "vulkan", feature = "dx12", feature = "metal"))]
+fn main() {
+    env_logger::init();
+
+    let mut events_loop = EventLoop::new();
+    let instance = back::Instance::create("gfx-rs multi_window", 1);
+    let mut targets = (0 .. WINDOW_COUNT)
+        .map(|i| {
+            let window = events_loop.create_window(&format!("multi_window {}", i), 400, 300);
+            let surface = instance.create_surface(&window);
+            Target::new(window, surface)
+        })
+        .collect::<Vec<_>>();
+
+    let mut losses = 0;
+    while let Err(DeviceLost) = run(&instance, &mut events_loop, &mut targets) {
+        losses += 1;
+        if losses > MAX_DEVICE_LOSSES {
+            println!("Device lost {} times, giving up", losses);
+            break;
+        }
+        println!("Device lost, recreating it");
+    }
+}
+
+#[cfg(not(any(feature = "vulkan", feature = "dx12", feature = "metal")))]
+fn main() {
+    println!("You need to enable the native API feature (vulkan/dx12/metal) in order to run this example");
+}
+
+/// Open a device and render until the application quits or the device is lost.
+fn run<I: Instance>(
+    instance: &I,
+    events_loop: &mut EventLoop,
+    targets: &mut Vec<Target<I::Backend>>,
+) -> Result<(), DeviceLost> {
+    let mut adapter = instance.enumerate_adapters().remove(0);
+    println!("{:?}", adapter.info);
+
+    let mut context = {
+        let surfaces = targets.iter().map(|target| &target.surface).collect::<Vec<_>>();
+        Context::new(&mut adapter, &surfaces, QUEUE_COUNT).expect("Unable to open a device")
+    };
+    println!("Rendering {} windows with {} queues", targets.len(), context.queue_count());
+
+    let mut frames = targets
+        .iter()
+        .map(|_| FrameContext::new(&context.device, &context.queue_group, FRAMES_IN_FLIGHT))
+        .collect::<Vec<_>>();
+
+    let result = render_loop(&adapter.physical_device, &mut context, events_loop, targets, &mut frames);
+
+    // Resources need to be released even if the device has been lost,
+    // the windows and their surfaces are kept for the next device.
+    let _ = context.wait_idle();
+    for frame in frames {
+        frame.destroy(&context.device);
+    }
+    for target in targets.iter_mut() {
+        target.release(&context.device);
+    }
+    result
+}
+
+fn render_loop<B: Backend>(
+    physical_device: &B::PhysicalDevice,
+    context: &mut Context<B>,
+    events_loop: &mut EventLoop,
+    targets: &mut Vec<Target<B>>,
+    frames: &mut Vec<FrameContext<B, hal::Graphics>>,
+) -> Result<(), DeviceLost> {
+    loop {
+        let events = events_loop.poll();
+        if events.quit {
+            return Ok(());
+        }
+        if !events.resized.is_empty() {
+            context.wait_idle()?;
+            for target in targets.iter_mut().filter(|target| events.resized.contains(&target.id())) {
+                target.invalidate();
+            }
+        }
+        if !events.closed.is_empty() {
+            context.wait_idle()?;
+            for id in events.closed {
+                if let Some(pos) = targets.iter().position(|target| target.id() == id) {
+                    frames.remove(pos).destroy(&context.device);
+                    targets.remove(pos).release(&context.device);
+                }
+            }
+            if targets.is_empty() {
+                return Ok(());
+            }
+        }
+
+        for (i, (target, frame_context)) in targets.iter_mut().zip(frames.iter_mut()).enumerate() {
+            frame_context.begin_frame(&context.device);
+            let slot = frame_context.frame_index();
+
+            let acquired = match target.acquire(&context.device, physical_device, slot) {
+                Some(acquired) => acquired,
+                None => continue,
+            };
+            let index = acquired.index;
+
+            let submit = {
+                let rect = pso::Rect {
+                    x: 0,
+                    y: 0,
+                    w: acquired.extent.width as _,
+                    h: acquired.extent.height as _,
+                };
+                let mut cmd_buffer = frame_context
+                    .current_frame()
+                    .command_pool()
+                    .acquire_command_buffer::<command::OneShot>(false);
+                cmd_buffer.begin_render_pass_inline(
+                    acquired.render_pass,
+                    acquired.framebuffer,
+                    rect,
+                    &[command::ClearValue::Color(command::ClearColor::Float(COLORS[i % COLORS.len()]))],
+                );
+                cmd_buffer.finish()
+            };
+            let submission = Submission::new()
+                .wait_on(&[(acquired.acquired, PipelineStage::COLOR_ATTACHMENT_OUTPUT)])
+                .signal(&[acquired.rendered])
+                .submit(Some(submit));
+
+            // Windows are spread over the queues, switching queues between frames.
+            let queue = context.next_queue();
+            frame_context.end_frame(queue, submission);
+            target.present(queue, index, slot);
+        }
+    }
+}
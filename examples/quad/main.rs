@@ -17,7 +17,6 @@ extern crate gfx_hal as hal;
 #[cfg(feature = "gl")]
 use back::glutin::GlContext;
 
-extern crate glsl_to_spirv;
 extern crate image;
 extern crate winit;
 
@@ -33,7 +32,7 @@ use hal::pso::{PipelineStage, ShaderStageFlags, Specialization};
 use hal::queue::Submission;
 
 use std::fs;
-use std::io::{Cursor, Read};
+use std::io::Cursor;
 
 const DIMS: Extent2D = Extent2D { width: 1024, height: 768 };
 
@@ -291,6 +290,7 @@ fn main() {
                     ..(i::Access::TRANSFER_WRITE, i::Layout::TransferDstOptimal),
                 target: &image_logo,
                 range: COLOR_RANGE.clone(),
+                families: None,
             };
 
             cmd_buffer.pipeline_barrier(
@@ -326,6 +326,7 @@ fn main() {
                     ..(i::Access::SHADER_READ, i::Layout::ShaderReadOnlyOptimal),
                 target: &image_logo,
                 range: COLOR_RANGE.clone(),
+                families: None,
             };
             cmd_buffer.pipeline_barrier(
                 PipelineStage::TRANSFER..PipelineStage::FRAGMENT_SHADER,
@@ -471,7 +472,11 @@ fn main() {
         command_pool.reset();
         let frame: hal::SwapImageIndex = {
             match swap_chain.acquire_image(FrameSync::Semaphore(&mut frame_semaphore)) {
-                Ok(i) => i,
+                Ok((i, suboptimal)) => {
+                    // still render this frame, recreate before the next one
+                    recreate_swapchain = suboptimal.is_some();
+                    i
+                }
                 Err(_) => {
                     recreate_swapchain = true;
                     continue;
@@ -513,8 +518,9 @@ fn main() {
         device.wait_for_fence(&frame_fence, !0);
 
         // present frame
-        if let Err(_) = swap_chain.present(&mut queue_group.queues[0], frame, &[]) {
-            recreate_swapchain = true;
+        match swap_chain.present(&mut queue_group.queues[0], frame, &[]) {
+            Ok(None) => {}
+            Ok(Some(_)) | Err(_) => recreate_swapchain = true,
         }
     }
 
@@ -575,9 +581,9 @@ fn swapchain_stuff(
     <back::Backend as hal::Backend>::PipelineLayout,
     Extent2D,
 ) {
-    let (caps, formats, _present_modes) = surface.compatibility(physical_device);
-    println!("formats: {:?}", formats);
-    let format = formats.
+    let caps = surface.capabilities(physical_device);
+    println!("formats: {:?}", caps.formats);
+    let format = caps.formats.as_ref().
         map_or(f::Format::Rgba8Srgb, |formats| {
             formats
                 .iter()
@@ -595,12 +601,7 @@ fn swapchain_stuff(
             let window = surface.get_window();
 
             let window_size = window.get_inner_size().unwrap().to_physical(window.get_hidpi_factor());
-            let mut extent = hal::window::Extent2D { width: window_size.width as _, height: window_size.height as _};
-
-            extent.width = extent.width.max(caps.extents.start.width).min(caps.extents.end.width);
-            extent.height = extent.height.max(caps.extents.start.height).min(caps.extents.end.height);
-
-            extent
+            caps.clamp_extent(hal::window::Extent2D { width: window_size.width as _, height: window_size.height as _})
         }
     };
 
@@ -680,21 +681,11 @@ fn swapchain_stuff(
     let pipeline = {
         let vs_module = {
             let glsl = fs::read_to_string("quad/data/quad.vert").unwrap();
-            let spirv: Vec<u8> = glsl_to_spirv::compile(&glsl, glsl_to_spirv::ShaderType::Vertex)
-                .unwrap()
-                .bytes()
-                .map(|b| b.unwrap())
-                .collect();
-            device.create_shader_module(&spirv).unwrap()
+            device.create_shader_module_from_glsl(pso::Stage::Vertex, &glsl).unwrap()
         };
         let fs_module = {
             let glsl = fs::read_to_string("quad/data/quad.frag").unwrap();
-            let spirv: Vec<u8> = glsl_to_spirv::compile(&glsl, glsl_to_spirv::ShaderType::Fragment)
-                .unwrap()
-                .bytes()
-                .map(|b| b.unwrap())
-                .collect();
-            device.create_shader_module(&spirv).unwrap()
+            device.create_shader_module_from_glsl(pso::Stage::Fragment, &glsl).unwrap()
         };
 
         let pipeline = {
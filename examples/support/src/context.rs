@@ -0,0 +1,96 @@
+use std::fmt;
+use std::error::Error;
+
+use hal::{Adapter, Backend, CommandQueue, Device, Graphics, QueueFamily, QueueGroup, Surface};
+use hal::error::{DeviceCreationError, HostExecutionError};
+
+/// The device has been lost and needs to be recreated, together with all its resources.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct DeviceLost;
+
+impl fmt::Display for DeviceLost {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.description())
+    }
+}
+
+impl Error for DeviceLost {
+    fn description(&self) -> &str {
+        "Device lost"
+    }
+}
+
+/// A logical device with a group of graphics queues able to present to a set of surfaces.
+pub struct Context<B: Backend> {
+    /// Logical device.
+    pub device: B::Device,
+    /// Graphics queues, all of them supporting presentation.
+    pub queue_group: QueueGroup<B, Graphics>,
+    next_queue: usize,
+}
+
+impl<B: Backend> Context<B> {
+    /// Open a device with up to `queue_count` graphics queues, from a family
+    /// able to present to all the `surfaces`.
+    pub fn new(
+        adapter: &mut Adapter<B>, surfaces: &[&B::Surface], queue_count: usize
+    ) -> Result<Self, DeviceCreationError> {
+        let supports_surfaces = |family: &B::QueueFamily| {
+            surfaces.iter().all(|surface| surface.supports_queue_family(family))
+        };
+        let max_queues = adapter.queue_families
+            .iter()
+            .filter(|family| family.supports_graphics() && supports_surfaces(family))
+            .map(|family| family.max_queues())
+            .max()
+            .ok_or(DeviceCreationError::InitializationFailed)?;
+        let count = queue_count.min(max_queues).max(1);
+        if count < queue_count {
+            warn!("Requested {} queues, only {} available", queue_count, count);
+        }
+
+        let (device, queue_group) = adapter.open_with::<_, Graphics>(count, supports_surfaces)?;
+        Ok(Context {
+            device,
+            queue_group,
+            next_queue: 0,
+        })
+    }
+
+    /// Returns the number of queues in the context.
+    pub fn queue_count(&self) -> usize {
+        self.queue_group.queues.len()
+    }
+
+    /// Returns the next queue, cycling through all the queues of the context.
+    pub fn next_queue(&mut self) -> &mut CommandQueue<B, Graphics> {
+        let index = self.next_queue % self.queue_group.queues.len();
+        self.next_queue = index + 1;
+        &mut self.queue_group.queues[index]
+    }
+
+    /// Wait for all the work on the device to complete.
+    pub fn wait_idle(&self) -> Result<(), DeviceLost> {
+        check(self.device.wait_idle())
+    }
+}
+
+/// Convert a host execution error, treating anything but device loss as fatal.
+pub(crate) fn check<T>(result: Result<T, HostExecutionError>) -> Result<T, DeviceLost> {
+    match result {
+        Ok(value) => Ok(value),
+        Err(HostExecutionError::DeviceLost) => {
+            error!("Device lost");
+            Err(DeviceLost)
+        }
+        Err(err) => panic!("Unexpected error: {:?}", err),
+    }
+}
+
+impl<B: Backend> fmt::Debug for Context<B> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Context")
+            .field("queues", &self.queue_group.queues.len())
+            .finish()
+    }
+}
@@ -0,0 +1,79 @@
+use winit::{self, dpi, Window, WindowBuilder, WindowId};
+
+/// Window events collected during one `EventLoop::poll`.
+#[derive(Debug, Default)]
+pub struct Events {
+    /// The user asked to quit the application.
+    pub quit: bool,
+    /// Windows the user asked to close.
+    pub closed: Vec<WindowId>,
+    /// Windows which have been resized, requiring their swapchain to be recreated.
+    pub resized: Vec<WindowId>,
+}
+
+/// Event loop shared by all the windows of the application.
+pub struct EventLoop {
+    raw: winit::EventsLoop,
+}
+
+impl EventLoop {
+    /// Create a new event loop.
+    pub fn new() -> Self {
+        EventLoop {
+            raw: winit::EventsLoop::new(),
+        }
+    }
+
+    /// Get the underlying `winit` event loop.
+    pub fn raw(&self) -> &winit::EventsLoop {
+        &self.raw
+    }
+
+    /// Create a window with the given title and physical size.
+    pub fn create_window(&self, title: &str, width: u32, height: u32) -> Window {
+        WindowBuilder::new()
+            .with_dimensions(dpi::LogicalSize::from_physical(
+                dpi::PhysicalSize {
+                    width: width as _,
+                    height: height as _,
+                },
+                1.0,
+            ))
+            .with_title(title.to_string())
+            .build(&self.raw)
+            .unwrap()
+    }
+
+    /// Collect the pending events without blocking.
+    pub fn poll(&mut self) -> Events {
+        let mut events = Events::default();
+        self.raw.poll_events(|event| {
+            if let winit::Event::WindowEvent { window_id, event } = event {
+                match event {
+                    winit::WindowEvent::KeyboardInput {
+                        input:
+                            winit::KeyboardInput {
+                                virtual_keycode: Some(winit::VirtualKeyCode::Escape),
+                                ..
+                            },
+                        ..
+                    } => events.quit = true,
+                    winit::WindowEvent::CloseRequested |
+                    winit::WindowEvent::Destroyed => {
+                        if !events.closed.contains(&window_id) {
+                            events.closed.push(window_id);
+                        }
+                    }
+                    winit::WindowEvent::Resized(_) |
+                    winit::WindowEvent::HiDpiFactorChanged(_) => {
+                        if !events.resized.contains(&window_id) {
+                            events.resized.push(window_id);
+                        }
+                    }
+                    _ => (),
+                }
+            }
+        });
+        events
+    }
+}
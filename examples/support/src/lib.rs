@@ -0,0 +1,19 @@
+//! Shared scaffolding for the examples.
+//!
+//! Provides the windowing and event loop boilerplate needed to drive several
+//! swapchains from a single device, spread their work over multiple queues and
+//! recover from device loss. The types are generic over the backend, so any
+//! backend with window surfaces can use them.
+
+extern crate gfx_hal as hal;
+#[macro_use]
+extern crate log;
+extern crate winit;
+
+mod context;
+mod events;
+mod target;
+
+pub use context::{Context, DeviceLost};
+pub use events::{EventLoop, Events};
+pub use target::{Frame, Target};
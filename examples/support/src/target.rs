@@ -0,0 +1,257 @@
+use hal::{format as f, image as i, pass, Backend, Device};
+use hal::{Backbuffer, CommandQueue, FrameSync, Surface, SwapImageIndex, Swapchain, SwapchainConfig};
+use hal::pso::PipelineStage;
+use hal::window::Extent2D;
+use winit::{Window, WindowId};
+
+const COLOR_RANGE: i::SubresourceRange = i::SubresourceRange {
+    aspects: f::Aspects::COLOR,
+    levels: 0 .. 1,
+    layers: 0 .. 1,
+};
+
+/// Swapchain of a window together with everything derived from it.
+struct SwapchainState<B: Backend> {
+    raw: B::Swapchain,
+    render_pass: B::RenderPass,
+    views: Vec<B::ImageView>,
+    framebuffers: Vec<B::Framebuffer>,
+    extent: Extent2D,
+}
+
+/// A swapchain image acquired for rendering.
+pub struct Frame<'a, B: Backend> {
+    /// Index of the acquired image.
+    pub index: SwapImageIndex,
+    /// Render pass clearing the image and transitioning it for presentation.
+    pub render_pass: &'a B::RenderPass,
+    /// Framebuffer containing the image.
+    pub framebuffer: &'a B::Framebuffer,
+    /// Extent of the image.
+    pub extent: Extent2D,
+    /// Semaphore signalled once the image is available,
+    /// which the rendering submission needs to wait on.
+    pub acquired: &'a B::Semaphore,
+    /// Semaphore for the rendering submission to signal,
+    /// which the presentation waits on.
+    pub rendered: &'a B::Semaphore,
+}
+
+/// A window with its surface and swapchain.
+///
+/// The swapchain is created lazily and recreated whenever it becomes out of date,
+/// while the window and the surface outlive device loss.
+pub struct Target<B: Backend> {
+    /// The window rendered to.
+    pub window: Window,
+    /// The surface of the window.
+    pub surface: B::Surface,
+    swapchain: Option<SwapchainState<B>>,
+    /// Acquisition and rendering semaphores per frame slot.
+    semaphores: Vec<(B::Semaphore, B::Semaphore)>,
+    out_of_date: bool,
+}
+
+impl<B: Backend> Target<B> {
+    /// Create a target for the window and its surface.
+    pub fn new(window: Window, surface: B::Surface) -> Self {
+        Target {
+            window,
+            surface,
+            swapchain: None,
+            semaphores: Vec::new(),
+            out_of_date: true,
+        }
+    }
+
+    /// Returns the id of the window.
+    pub fn id(&self) -> WindowId {
+        self.window.id()
+    }
+
+    /// Request the swapchain to be recreated before the next frame, e.g. after a resize.
+    pub fn invalidate(&mut self) {
+        self.out_of_date = true;
+    }
+
+    /// Acquire the next swapchain image, (re)creating the swapchain if needed.
+    ///
+    /// `frame_slot` selects the semaphores used for the frame and must not be
+    /// reused before the submission of the frame that used it has completed,
+    /// for example by passing `FrameContext::frame_index`.
+    ///
+    /// Returns `None` if no image could be acquired, in which case the frame is
+    /// skipped and the swapchain recreated on the next call.
+    pub fn acquire(
+        &mut self,
+        device: &B::Device,
+        physical_device: &B::PhysicalDevice,
+        frame_slot: usize,
+    ) -> Option<Frame<B>> {
+        if self.out_of_date {
+            self.recreate(device, physical_device);
+        }
+        while self.semaphores.len() <= frame_slot {
+            self.semaphores.push((device.create_semaphore(), device.create_semaphore()));
+        }
+
+        let result = {
+            let semaphore = &self.semaphores[frame_slot].0;
+            self.swapchain.as_mut().unwrap().raw.acquire_image(FrameSync::Semaphore(semaphore))
+        };
+        let index = match result {
+            Ok((index, suboptimal)) => {
+                // Keep rendering into the acquired image, recreate on the next frame.
+                if suboptimal.is_some() {
+                    self.out_of_date = true;
+                }
+                index
+            }
+            Err(_) => {
+                self.out_of_date = true;
+                return None;
+            }
+        };
+
+        let swapchain = self.swapchain.as_ref().unwrap();
+        Some(Frame {
+            index,
+            render_pass: &swapchain.render_pass,
+            framebuffer: &swapchain.framebuffers[index as usize],
+            extent: swapchain.extent,
+            acquired: &self.semaphores[frame_slot].0,
+            rendered: &self.semaphores[frame_slot].1,
+        })
+    }
+
+    /// Present an image acquired with `acquire` in the same `frame_slot`,
+    /// once its `rendered` semaphore is signalled.
+    pub fn present<C>(
+        &mut self,
+        queue: &mut CommandQueue<B, C>,
+        index: SwapImageIndex,
+        frame_slot: usize,
+    ) {
+        let swapchain = self.swapchain.as_ref().expect("No swapchain to present");
+        let rendered = &self.semaphores[frame_slot].1;
+        match swapchain.raw.present(queue, index, Some(rendered)) {
+            Ok(None) => {}
+            Ok(Some(_)) | Err(_) => self.out_of_date = true,
+        }
+    }
+
+    /// Destroy the swapchain and everything derived from it, keeping the surface.
+    ///
+    /// Needs to be called before destroying the device, including when it has been lost.
+    /// The device must be idle or lost.
+    pub fn release(&mut self, device: &B::Device) {
+        if let Some(swapchain) = self.swapchain.take() {
+            let raw = destroy_derived(device, swapchain);
+            device.destroy_swapchain(raw);
+        }
+        for (acquired, rendered) in self.semaphores.drain(..) {
+            device.destroy_semaphore(acquired);
+            device.destroy_semaphore(rendered);
+        }
+        self.out_of_date = true;
+    }
+
+    fn recreate(&mut self, device: &B::Device, physical_device: &B::PhysicalDevice) {
+        // The old swapchain is handed over to the new one, to aid in resource reuse.
+        let old = self.swapchain.take().map(|swapchain| destroy_derived(device, swapchain));
+
+        let caps = self.surface.capabilities(physical_device);
+        let format = caps.formats.as_ref().map_or(f::Format::Rgba8Srgb, |formats| {
+            formats
+                .iter()
+                .find(|format| format.base_format().1 == f::ChannelType::Srgb)
+                .cloned()
+                .unwrap_or(formats[0])
+        });
+        let extent = caps.current_extent.unwrap_or_else(|| {
+            let size = self.window
+                .get_inner_size()
+                .unwrap()
+                .to_physical(self.window.get_hidpi_factor());
+            caps.clamp_extent(Extent2D {
+                width: size.width as u32,
+                height: size.height as u32,
+            })
+        });
+        debug!("Recreating swapchain of {:?} with {:?} {:?}", self.window.id(), format, extent);
+
+        let config = SwapchainConfig::new()
+            .with_color(format)
+            .with_image_count(caps.image_count.start)
+            .with_image_usage(i::Usage::COLOR_ATTACHMENT);
+        let (raw, backbuffer) = device.create_swapchain(&mut self.surface, config, old, &extent);
+
+        let render_pass = {
+            let attachment = pass::Attachment {
+                format: Some(format),
+                samples: 1,
+                ops: pass::AttachmentOps::new(pass::AttachmentLoadOp::Clear, pass::AttachmentStoreOp::Store),
+                stencil_ops: pass::AttachmentOps::DONT_CARE,
+                layouts: i::Layout::Undefined .. i::Layout::Present,
+            };
+            let subpass = pass::SubpassDesc {
+                colors: &[(0, i::Layout::ColorAttachmentOptimal)],
+                depth_stencil: None,
+                inputs: &[],
+                resolves: &[],
+                preserves: &[],
+            };
+            let dependency = pass::SubpassDependency {
+                passes: pass::SubpassRef::External .. pass::SubpassRef::Pass(0),
+                stages: PipelineStage::COLOR_ATTACHMENT_OUTPUT .. PipelineStage::COLOR_ATTACHMENT_OUTPUT,
+                accesses: i::Access::empty() .. (i::Access::COLOR_ATTACHMENT_READ | i::Access::COLOR_ATTACHMENT_WRITE),
+            };
+            device.create_render_pass(&[attachment], &[subpass], &[dependency])
+        };
+
+        let (views, framebuffers) = match backbuffer {
+            Backbuffer::Images(images) => {
+                let fb_extent = i::Extent {
+                    width: extent.width,
+                    height: extent.height,
+                    depth: 1,
+                };
+                let views = images
+                    .iter()
+                    .map(|image| {
+                        device
+                            .create_image_view(image, i::ViewKind::D2, format, f::Swizzle::NO, COLOR_RANGE.clone())
+                            .unwrap()
+                    })
+                    .collect::<Vec<_>>();
+                let framebuffers = views
+                    .iter()
+                    .map(|view| device.create_framebuffer(&render_pass, Some(view), fb_extent).unwrap())
+                    .collect();
+                (views, framebuffers)
+            }
+            Backbuffer::Framebuffer(framebuffer) => (Vec::new(), vec![framebuffer]),
+        };
+
+        self.swapchain = Some(SwapchainState {
+            raw,
+            render_pass,
+            views,
+            framebuffers,
+            extent,
+        });
+        self.out_of_date = false;
+    }
+}
+
+/// Destroy the resources derived from the swapchain, returning the swapchain itself.
+fn destroy_derived<B: Backend>(device: &B::Device, swapchain: SwapchainState<B>) -> B::Swapchain {
+    for framebuffer in swapchain.framebuffers {
+        device.destroy_framebuffer(framebuffer);
+    }
+    for view in swapchain.views {
+        device.destroy_image_view(view);
+    }
+    device.destroy_render_pass(swapchain.render_pass);
+    swapchain.raw
+}
@@ -111,7 +111,7 @@ pub fn typeless_format(format: DXGI_FORMAT) -> Option<(DXGI_FORMAT, DXGI_FORMAT)
 
 
         /*R5g6b5Unorm => DXGI_FORMAT_B5G6R5_UNORM,
-        R5g5b5a1Unorm => DXGI_FORMAT_B5G5R5A1_UNORM,
+        A1r5g5b5Unorm => DXGI_FORMAT_B5G5R5A1_UNORM,
         A2b10g10r10Unorm => DXGI_FORMAT_R10G10B10A2_UNORM,
         A2b10g10r10Uint => DXGI_FORMAT_R10G10B10A2_UINT,
         B10g11r11Ufloat => DXGI_FORMAT_R11G11B10_FLOAT,
@@ -143,8 +143,11 @@ pub fn map_format(format: Format) -> Option<DXGI_FORMAT> {
     use hal::format::Format::*;
 
     let format = match format {
+        // Packed formats are named from the most significant bit in Vulkan,
+        // and from the least significant bit in DXGI. D3D11 has no shader component
+        // mapping, so packed formats with another channel order are unsupported.
         R5g6b5Unorm => DXGI_FORMAT_B5G6R5_UNORM,
-        R5g5b5a1Unorm => DXGI_FORMAT_B5G5R5A1_UNORM,
+        A1r5g5b5Unorm => DXGI_FORMAT_B5G5R5A1_UNORM,
         R8Unorm => DXGI_FORMAT_R8_UNORM,
         R8Inorm => DXGI_FORMAT_R8_SNORM,
         R8Uint => DXGI_FORMAT_R8_UINT,
@@ -271,6 +274,10 @@ fn map_cull_mode(mode: Face) -> D3D11_CULL_MODE {
 }
 
 pub(crate) fn map_rasterizer_desc(desc: &Rasterizer) -> D3D11_RASTERIZER_DESC {
+    // Points are always rasterized with a size of 1, point list pipelines with
+    // `program_point_size` are rejected at creation.
+    // TODO: emulate by expanding points into quads in a geometry shader
+
     D3D11_RASTERIZER_DESC {
         FillMode: map_fill_mode(desc.polygon_mode),
         CullMode: map_cull_mode(desc.cull_face),
@@ -458,18 +465,6 @@ pub(crate) fn map_depth_stencil_desc(desc: &DepthStencilDesc) -> (D3D11_DEPTH_ST
     }, stencil_ref)
 }
 
-pub fn map_execution_model(model: spirv::ExecutionModel) -> Stage {
-    match model {
-        spirv::ExecutionModel::Vertex => Stage::Vertex,
-        spirv::ExecutionModel::Fragment => Stage::Fragment,
-        spirv::ExecutionModel::Geometry => Stage::Geometry,
-        spirv::ExecutionModel::GlCompute => Stage::Compute,
-        spirv::ExecutionModel::TessellationControl => Stage::Hull,
-        spirv::ExecutionModel::TessellationEvaluation => Stage::Domain,
-        spirv::ExecutionModel::Kernel => panic!("Kernel is not a valid execution model."),
-    }
-}
-
 pub fn map_stage(stage: Stage) -> spirv::ExecutionModel {
     match stage {
         Stage::Vertex => spirv::ExecutionModel::Vertex,
@@ -1,30 +1,37 @@
 use hal;
+use hal::backend::{FastHashMap, StatisticsCollector};
+use hal::profiler::FrameStatistics;
 use hal::queue::QueueFamilyId;
 use hal::range::RangeArg;
+use hal::sync::HostTimeline;
 use hal::{buffer, device, error, format, image, mapping, memory, pass, pool, pso, query, window};
 
 use winapi::Interface;
 use winapi::shared::dxgi::{IDXGISwapChain, DXGI_SWAP_CHAIN_DESC, DXGI_SWAP_EFFECT_DISCARD};
-use winapi::shared::minwindef::{TRUE};
+use winapi::shared::minwindef::{TRUE, UINT};
 use winapi::shared::{dxgiformat, dxgitype, winerror};
-use winapi::um::{d3d11, d3dcommon};
+use winapi::um::{d3d11, d3d11sdklayers, d3dcommon};
 
 use wio::com::ComPtr;
 
 use std::borrow::Borrow;
 use std::cell::RefCell;
+use std::cmp;
+use std::ffi::CStr;
 use std::mem;
 use std::ops::Range;
 use std::ptr;
+use std::sync::{Arc, Mutex};
+use std::thread;
 
 use {
     Backend, Buffer, BufferView, CommandPool, ComputePipeline, DescriptorPool, DescriptorSetLayout,
     Fence, Framebuffer, GraphicsPipeline, Image, ImageView, InternalBuffer, InternalImage, Memory,
-    PipelineLayout, QueryPool, RenderPass, Sampler, Semaphore, ShaderModule, Surface, Swapchain,
-    UnboundBuffer, UnboundImage, ViewInfo, PipelineBinding, Descriptor
+    PipelineLayout, QueryPool, RenderPass, Sampler, Semaphore, ShaderModule, SubpassDesc, Surface, Swapchain,
+    UnboundBuffer, UnboundImage, ViewInfo, PipelineBinding, Descriptor, ConstantRange,
 };
 
-use {conv, internal, shader};
+use {conv, internal, shader, supports_constant_buffer_offsetting, MAX_PUSH_CONSTANT_SIZE};
 
 struct InputLayout {
     raw: ComPtr<d3d11::ID3D11InputLayout>,
@@ -36,9 +43,19 @@ struct InputLayout {
 
 pub struct Device {
     raw: ComPtr<d3d11::ID3D11Device>,
-    pub(crate) context: ComPtr<d3d11::ID3D11DeviceContext>,
+    // The immediate context is shared with the queues. Contrary to the device
+    // it isn't thread-safe, so every use has to lock it.
+    pub(crate) context: Arc<Mutex<ComPtr<d3d11::ID3D11DeviceContext>>>,
     memory_properties: hal::MemoryProperties,
-    pub(crate) internal: internal::Internal
+    features: hal::Features,
+    pub(crate) internal: internal::Internal,
+    // Sampler states shared between identical sampler descriptions, keeping the number
+    // of unique states below the limit of 4096.
+    samplers: Mutex<FastHashMap<image::SamplerInfo, ComPtr<d3d11::ID3D11SamplerState>>>,
+    // Whether command buffers can bind ranges of constant buffers.
+    constant_buffer_offsetting: bool,
+    // Command statistics, accumulated by the queues on submission.
+    pub(crate) statistics: Arc<StatisticsCollector>,
 }
 
 unsafe impl Send for Device { }
@@ -49,15 +66,71 @@ impl Device {
         self.raw.as_raw()
     }
 
-    pub fn new(device: ComPtr<d3d11::ID3D11Device>, context: ComPtr<d3d11::ID3D11DeviceContext>, memory_properties: hal::MemoryProperties) -> Self {
+    pub fn new(device: ComPtr<d3d11::ID3D11Device>, context: ComPtr<d3d11::ID3D11DeviceContext>, memory_properties: hal::MemoryProperties, features: hal::Features) -> Self {
         Device {
             raw: device.clone(),
-            context,
+            context: Arc::new(Mutex::new(context)),
             memory_properties,
-            internal: internal::Internal::new(&device)
+            features,
+            internal: internal::Internal::new(&device),
+            samplers: Mutex::new(FastHashMap::default()),
+            constant_buffer_offsetting: supports_constant_buffer_offsetting(&device),
+            statistics: Arc::new(StatisticsCollector::default()),
         }
     }
 
+    fn create_query(&self, query_ty: d3d11::D3D11_QUERY) -> ComPtr<d3d11::ID3D11Query> {
+        let desc = d3d11::D3D11_QUERY_DESC {
+            Query: query_ty,
+            MiscFlags: 0,
+        };
+
+        let mut query = ptr::null_mut();
+        assert_eq!(winerror::S_OK, unsafe {
+            self.raw.CreateQuery(&desc, &mut query)
+        });
+        unsafe { ComPtr::from_raw(query) }
+    }
+
+    // Reads back the data of an issued query from the immediate context.
+    // Returns `false` if the data is not available yet and `wait` isn't set.
+    fn get_query_data<T>(&self, query: *mut d3d11::ID3D11Asynchronous, data: &mut T, wait: bool) -> bool {
+        let context = self.context.lock().unwrap();
+        loop {
+            let hr = unsafe {
+                context.GetData(
+                    query,
+                    data as *mut T as *mut _,
+                    mem::size_of::<T>() as UINT,
+                    0,
+                )
+            };
+            match hr {
+                winerror::S_OK => return true,
+                winerror::S_FALSE if wait => thread::yield_now(),
+                winerror::S_FALSE => return false,
+                _ => panic!("Failed to get query data: {:x}", hr),
+            }
+        }
+    }
+
+    // The timestamp frequency is only exposed by disjoint queries,
+    // issue one on the immediate context and wait for it.
+    fn timestamp_frequency(&self) -> u64 {
+        let disjoint = self.create_query(d3d11::D3D11_QUERY_TIMESTAMP_DISJOINT);
+        let raw = disjoint.as_raw() as *mut d3d11::ID3D11Asynchronous;
+        let mut data: d3d11::D3D11_QUERY_DATA_TIMESTAMP_DISJOINT = unsafe { mem::zeroed() };
+        {
+            let context = self.context.lock().unwrap();
+            unsafe {
+                context.Begin(raw);
+                context.End(raw);
+            }
+        }
+        self.get_query_data(raw, &mut data, true);
+        data.Frequency
+    }
+
     fn create_rasterizer_state(&self, rasterizer_desc: &pso::Rasterizer) -> Result<ComPtr<d3d11::ID3D11RasterizerState>, pso::CreationError> {
         let mut rasterizer = ptr::null_mut();
         let desc = conv::map_rasterizer_desc(rasterizer_desc);
@@ -214,6 +287,63 @@ impl Device {
         }
     }
 
+    fn create_hull_shader(&self, blob: ComPtr<d3dcommon::ID3DBlob>) -> Result<ComPtr<d3d11::ID3D11HullShader>, pso::CreationError> {
+        let mut hs = ptr::null_mut();
+
+        let hr = unsafe {
+            self.raw.CreateHullShader(
+                blob.GetBufferPointer(),
+                blob.GetBufferSize(),
+                ptr::null_mut(),
+                &mut hs as *mut *mut _ as *mut *mut _
+            )
+        };
+
+        if winerror::SUCCEEDED(hr) {
+            Ok(unsafe { ComPtr::from_raw(hs) })
+        } else {
+            Err(pso::CreationError::Other)
+        }
+    }
+
+    fn create_domain_shader(&self, blob: ComPtr<d3dcommon::ID3DBlob>) -> Result<ComPtr<d3d11::ID3D11DomainShader>, pso::CreationError> {
+        let mut ds = ptr::null_mut();
+
+        let hr = unsafe {
+            self.raw.CreateDomainShader(
+                blob.GetBufferPointer(),
+                blob.GetBufferSize(),
+                ptr::null_mut(),
+                &mut ds as *mut *mut _ as *mut *mut _
+            )
+        };
+
+        if winerror::SUCCEEDED(hr) {
+            Ok(unsafe { ComPtr::from_raw(ds) })
+        } else {
+            Err(pso::CreationError::Other)
+        }
+    }
+
+    fn create_geometry_shader(&self, blob: ComPtr<d3dcommon::ID3DBlob>) -> Result<ComPtr<d3d11::ID3D11GeometryShader>, pso::CreationError> {
+        let mut gs = ptr::null_mut();
+
+        let hr = unsafe {
+            self.raw.CreateGeometryShader(
+                blob.GetBufferPointer(),
+                blob.GetBufferSize(),
+                ptr::null_mut(),
+                &mut gs as *mut *mut _ as *mut *mut _
+            )
+        };
+
+        if winerror::SUCCEEDED(hr) {
+            Ok(unsafe { ComPtr::from_raw(gs) })
+        } else {
+            Err(pso::CreationError::Other)
+        }
+    }
+
     fn create_pixel_shader(&self, blob: ComPtr<d3dcommon::ID3DBlob>) -> Result<ComPtr<d3d11::ID3D11PixelShader>, pso::CreationError> {
         let mut ps = ptr::null_mut();
 
@@ -257,6 +387,7 @@ impl Device {
         stage: pso::Stage,
         source: &pso::EntryPoint<Backend>,
         layout: &PipelineLayout,
+        features: hal::Features,
     ) -> Result<Option<ComPtr<d3dcommon::ID3DBlob>>, device::ShaderError> {
         // TODO: entrypoint stuff
         match *source.module {
@@ -265,8 +396,12 @@ impl Device {
 
                 // Ok(Some(shader))
             }
-            ShaderModule::Spirv(ref raw_data) => {
-                Ok(shader::compile_spirv_entrypoint(raw_data, stage, source, layout)?)
+            ShaderModule::Spirv(ref module) => {
+                let missing = module.required_features() - features;
+                if !missing.is_empty() {
+                    return Err(device::ShaderError::MissingFeatures(missing));
+                }
+                Ok(shader::compile_spirv_entrypoint(module, stage, source, layout)?)
             }
         }
     }
@@ -341,11 +476,26 @@ impl Device {
         let mut desc: d3d11::D3D11_RENDER_TARGET_VIEW_DESC = unsafe { mem::zeroed() };
         desc.Format = info.format;
 
+        #[allow(non_snake_case)]
+        let MipSlice = info.range.levels.start as _;
+        #[allow(non_snake_case)]
+        let FirstArraySlice = info.range.layers.start as _;
+        #[allow(non_snake_case)]
+        let ArraySize = (info.range.layers.end - info.range.layers.start) as _;
+
         match info.view_kind {
             image::ViewKind::D2 => {
                 desc.ViewDimension = d3d11::D3D11_RTV_DIMENSION_TEXTURE2D;
                 *unsafe{ desc.u.Texture2D_mut() } = d3d11::D3D11_TEX2D_RTV {
-                    MipSlice: 0,
+                    MipSlice,
+                }
+            },
+            image::ViewKind::D2Array => {
+                desc.ViewDimension = d3d11::D3D11_RTV_DIMENSION_TEXTURE2DARRAY;
+                *unsafe{ desc.u.Texture2DArray_mut() } = d3d11::D3D11_TEX2D_ARRAY_RTV {
+                    MipSlice,
+                    FirstArraySlice,
+                    ArraySize,
                 }
             },
             _ => unimplemented!()
@@ -371,11 +521,26 @@ impl Device {
         let mut desc: d3d11::D3D11_DEPTH_STENCIL_VIEW_DESC = unsafe { mem::zeroed() };
         desc.Format = info.format;
 
+        #[allow(non_snake_case)]
+        let MipSlice = info.range.levels.start as _;
+        #[allow(non_snake_case)]
+        let FirstArraySlice = info.range.layers.start as _;
+        #[allow(non_snake_case)]
+        let ArraySize = (info.range.layers.end - info.range.layers.start) as _;
+
         match info.view_kind {
             image::ViewKind::D2 => {
                 desc.ViewDimension = d3d11::D3D11_DSV_DIMENSION_TEXTURE2D;
                 *unsafe{ desc.u.Texture2D_mut() } = d3d11::D3D11_TEX2D_DSV {
-                    MipSlice: 0,
+                    MipSlice,
+                }
+            },
+            image::ViewKind::D2Array => {
+                desc.ViewDimension = d3d11::D3D11_DSV_DIMENSION_TEXTURE2DARRAY;
+                *unsafe{ desc.u.Texture2DArray_mut() } = d3d11::D3D11_TEX2D_ARRAY_DSV {
+                    MipSlice,
+                    FirstArraySlice,
+                    ArraySize,
                 }
             },
             _ => unimplemented!()
@@ -397,6 +562,174 @@ impl Device {
             Err(image::ViewError::Unsupported)
         }
     }
+
+    /// Raw view of a byte range of a storage buffer, created on first use and kept
+    /// alive by the buffer.
+    fn buffer_range_uav(&self, buffer: &Buffer, range: Range<u64>) -> *mut d3d11::ID3D11UnorderedAccessView {
+        // Raw views address the buffer in 32-bit elements.
+        assert_eq!(range.start % 4, 0);
+        assert!(range.start <= range.end && range.end <= buffer.size);
+
+        let mut range_uavs = buffer.range_uavs.lock().unwrap();
+        let uav = range_uavs.entry((range.start, range.end)).or_insert_with(|| {
+            let mut desc = unsafe { mem::zeroed::<d3d11::D3D11_UNORDERED_ACCESS_VIEW_DESC>() };
+            desc.Format = dxgiformat::DXGI_FORMAT_R32_TYPELESS;
+            desc.ViewDimension = d3d11::D3D11_UAV_DIMENSION_BUFFER;
+            unsafe {
+                *desc.u.Buffer_mut() = d3d11::D3D11_BUFFER_UAV {
+                    FirstElement: (range.start / 4) as _,
+                    NumElements: ((range.end - range.start + 3) / 4) as _,
+                    Flags: d3d11::D3D11_BUFFER_UAV_FLAG_RAW
+                };
+            };
+
+            let mut uav = ptr::null_mut();
+            let hr = unsafe {
+                self.raw.CreateUnorderedAccessView(
+                    buffer.internal.raw as *mut _,
+                    &desc,
+                    &mut uav as *mut *mut _ as *mut *mut _
+                )
+            };
+            assert!(winerror::SUCCEEDED(hr), "CreateUnorderedAccessView failed: 0x{:x}", hr);
+            unsafe { ComPtr::from_raw(uav) }
+        });
+        uav.as_raw()
+    }
+
+    /// Run `fun`, collecting the messages the debug layer stores in the info queue meanwhile.
+    fn capture_messages<R, F: FnOnce() -> R>(&self, fun: F) -> (R, error::ErrorContext) {
+        let info_queue = if cfg!(debug_assertions) {
+            self.raw.cast::<d3d11sdklayers::ID3D11InfoQueue>().ok()
+        } else {
+            None
+        };
+        let info_queue = match info_queue {
+            Some(info_queue) => info_queue,
+            None => return (fun(), error::ErrorContext::default()),
+        };
+
+        let start = unsafe { info_queue.GetNumStoredMessages() };
+        let result = fun();
+        let end = unsafe { info_queue.GetNumStoredMessages() };
+
+        // Old messages are discarded once the queue is full, shifting the indices.
+        let start = if end < start { 0 } else { start };
+        let messages = (start .. end)
+            .filter_map(|index| unsafe {
+                let mut size = 0;
+                info_queue.GetMessage(index, ptr::null_mut(), &mut size);
+                // The description is stored right after `D3D11_MESSAGE`, keep the storage aligned.
+                let mut storage = vec![0u64; (size + 7) / 8];
+                let message = storage.as_mut_ptr() as *mut d3d11sdklayers::D3D11_MESSAGE;
+                if !winerror::SUCCEEDED(info_queue.GetMessage(index, message, &mut size)) {
+                    return None;
+                }
+                let description = CStr::from_ptr((*message).pDescription);
+                Some(description.to_string_lossy().into_owned())
+            })
+            .collect();
+
+        (result, error::ErrorContext { messages })
+    }
+
+    fn build_graphics_pipeline<'a>(
+        &self,
+        desc: &pso::GraphicsPipelineDesc<'a, Backend>,
+    ) -> Result<GraphicsPipeline, pso::CreationError> {
+        // Points are always rasterized with a size of one pixel.
+        if desc.input_assembler.primitive == hal::Primitive::PointList && desc.rasterizer.program_point_size {
+            error!("Points can't take their size from `PointSize`");
+            return Err(pso::CreationError::Other);
+        }
+        let build_shader =
+            |stage: pso::Stage, source: Option<&pso::EntryPoint<'a, Backend>>| {
+                let source = match source {
+                    Some(src) => src,
+                    None => return Ok(None),
+                };
+
+                Self::extract_entry_point(stage, source, desc.layout, self.features)
+                    .map_err(|err| pso::CreationError::Shader(err))
+            };
+
+        let vs = build_shader(pso::Stage::Vertex, Some(&desc.shaders.vertex))?.unwrap();
+        let hs = build_shader(pso::Stage::Hull, desc.shaders.hull.as_ref())?;
+        let ds = build_shader(pso::Stage::Domain, desc.shaders.domain.as_ref())?;
+        let gs = build_shader(pso::Stage::Geometry, desc.shaders.geometry.as_ref())?;
+        let ps = build_shader(pso::Stage::Fragment, desc.shaders.fragment.as_ref())?;
+
+        let layout = self.create_input_layout(vs.clone(), &desc.vertex_buffers, &desc.attributes, &desc.input_assembler)?;
+        let rasterizer_state = self.create_rasterizer_state(&desc.rasterizer)?;
+        if cfg!(debug_assertions) && desc.blender.srgb == pso::SrgbConversion::Encoded {
+            warn!("Blending on encoded sRGB values is not supported, sRGB targets are blended \
+                in linear space, render into a UNORM view of the target instead");
+        }
+        let blend_state = self.create_blend_state(&desc.blender)?;
+        let depth_stencil_state = Some(self.create_depth_stencil_state(&desc.depth_stencil)?);
+
+        let vs = self.create_vertex_shader(vs)?;
+        let hs = if let Some(blob) = hs {
+            Some(self.create_hull_shader(blob)?)
+        } else {
+            None
+        };
+        let ds = if let Some(blob) = ds {
+            Some(self.create_domain_shader(blob)?)
+        } else {
+            None
+        };
+        let gs = if let Some(blob) = gs {
+            Some(self.create_geometry_shader(blob)?)
+        } else {
+            None
+        };
+        let ps = if let Some(blob) = ps {
+            Some(self.create_pixel_shader(blob)?)
+        } else {
+            None
+        };
+
+        Ok(GraphicsPipeline {
+            vs,
+            hs,
+            ds,
+            gs,
+            ps,
+            topology: layout.topology,
+            input_layout: layout.raw,
+            rasterizer_state,
+            blend_state,
+            depth_stencil_state,
+            baked_states: desc.baked_states.clone(),
+            required_bindings: layout.required_bindings,
+            max_vertex_bindings: layout.max_vertex_bindings,
+            strides: layout.vertex_strides,
+        })
+    }
+
+    fn build_compute_pipeline<'a>(
+        &self,
+        desc: &pso::ComputePipelineDesc<'a, Backend>,
+    ) -> Result<ComputePipeline, pso::CreationError> {
+        let build_shader =
+            |stage: pso::Stage, source: Option<&pso::EntryPoint<'a, Backend>>| {
+                let source = match source {
+                    Some(src) => src,
+                    None => return Ok(None),
+                };
+
+                Self::extract_entry_point(stage, source, desc.layout, self.features)
+                    .map_err(|err| pso::CreationError::Shader(err))
+            };
+
+        let cs = build_shader(pso::Stage::Compute, Some(&desc.shader))?.unwrap();
+        let cs = self.create_compute_shader(cs)?;
+
+        Ok(ComputePipeline {
+            cs
+        })
+    }
 }
 
 impl hal::Device<Backend> for Device {
@@ -453,6 +786,7 @@ impl hal::Device<Backend> for Device {
         CommandPool {
             device: self.raw.clone(),
             internal: self.internal.clone(),
+            constant_buffer_offsetting: self.constant_buffer_offsetting,
         }
     }
 
@@ -464,7 +798,7 @@ impl hal::Device<Backend> for Device {
     fn create_render_pass<'a, IA, IS, ID>(
         &self,
         _attachments: IA,
-        _subpasses: IS,
+        subpasses: IS,
         _dependencies: ID,
     ) -> RenderPass
     where
@@ -475,15 +809,31 @@ impl hal::Device<Backend> for Device {
         ID: IntoIterator,
         ID::Item: Borrow<pass::SubpassDependency>,
     {
-        // TODO: renderpass
+        // Subpasses are executed as separate passes, the resolves of a subpass are
+        // executed at its end. Dependencies are implicit in the D3D11 runtime.
+        let subpasses = subpasses
+            .into_iter()
+            .map(|subpass| {
+                let subpass = subpass.borrow();
+                SubpassDesc {
+                    color_attachments: subpass.colors.iter().map(|&(id, _)| id).collect(),
+                    depth_stencil_attachment: subpass.depth_stencil.map(|&(id, _)| id),
+                    resolves: subpass.colors
+                        .iter()
+                        .zip(subpass.resolves)
+                        .map(|(&(color, _), &(resolve, _))| (color, resolve))
+                        .collect(),
+                }
+            })
+            .collect();
 
-        RenderPass
+        RenderPass { subpasses }
     }
 
     fn create_pipeline_layout<IS, IR>(
         &self,
         set_layouts: IS,
-        _push_constant_ranges: IR,
+        push_constant_ranges: IR,
     ) -> PipelineLayout
     where
         IS: IntoIterator,
@@ -577,8 +927,17 @@ impl hal::Device<Backend> for Device {
             set_bindings.push(optimized_bindings);
         }
 
+        let push_constants = push_constant_ranges
+            .into_iter()
+            .map(|range| range.borrow().clone())
+            .collect::<Vec<_>>();
+        for &(_, ref range) in &push_constants {
+            assert!(range.end * 4 <= MAX_PUSH_CONSTANT_SIZE, "Push constant range {:?} is too large", range);
+        }
+
         PipelineLayout {
-            set_bindings
+            set_bindings,
+            push_constants,
         }
     }
 
@@ -586,72 +945,16 @@ impl hal::Device<Backend> for Device {
         &self,
         desc: &pso::GraphicsPipelineDesc<'a, Backend>,
     ) -> Result<GraphicsPipeline, pso::CreationError> {
-        let build_shader =
-            |stage: pso::Stage, source: Option<&pso::EntryPoint<'a, Backend>>| {
-                let source = match source {
-                    Some(src) => src,
-                    None => return Ok(None),
-                };
-
-                Self::extract_entry_point(stage, source, desc.layout)
-                    .map_err(|err| pso::CreationError::Shader(err))
-            };
-
-        let vs = build_shader(pso::Stage::Vertex, Some(&desc.shaders.vertex))?.unwrap();
-        let ps = build_shader(pso::Stage::Fragment, desc.shaders.fragment.as_ref())?;
-        // TODO:
-        /*let gs = build_shader(pso::Stage::Geometry, desc.shaders.geometry.as_ref())?;
-        let ds = build_shader(pso::Stage::Domain, desc.shaders.domain.as_ref())?;
-        let hs = build_shader(pso::Stage::Hull, desc.shaders.hull.as_ref())?;*/
-
-        let layout = self.create_input_layout(vs.clone(), &desc.vertex_buffers, &desc.attributes, &desc.input_assembler)?;
-        let rasterizer_state = self.create_rasterizer_state(&desc.rasterizer)?;
-        let blend_state = self.create_blend_state(&desc.blender)?;
-        let depth_stencil_state = Some(self.create_depth_stencil_state(&desc.depth_stencil)?);
-
-        let vs = self.create_vertex_shader(vs)?;
-        let ps = if let Some(blob) = ps {
-            Some(self.create_pixel_shader(blob)?)
-        } else {
-            None
-        };
-
-        Ok(GraphicsPipeline {
-            vs,
-            ps,
-            topology: layout.topology,
-            input_layout: layout.raw,
-            rasterizer_state,
-            blend_state,
-            depth_stencil_state,
-            baked_states: desc.baked_states.clone(),
-            required_bindings: layout.required_bindings,
-            max_vertex_bindings: layout.max_vertex_bindings,
-            strides: layout.vertex_strides,
-        })
+        let (result, context) = self.capture_messages(|| self.build_graphics_pipeline(desc));
+        result.map_err(|err| err.with_context(context))
     }
 
     fn create_compute_pipeline<'a>(
         &self,
         desc: &pso::ComputePipelineDesc<'a, Backend>,
     ) -> Result<ComputePipeline, pso::CreationError> {
-        let build_shader =
-            |stage: pso::Stage, source: Option<&pso::EntryPoint<'a, Backend>>| {
-                let source = match source {
-                    Some(src) => src,
-                    None => return Ok(None),
-                };
-
-                Self::extract_entry_point(stage, source, desc.layout)
-                    .map_err(|err| pso::CreationError::Shader(err))
-            };
-
-        let cs = build_shader(pso::Stage::Compute, Some(&desc.shader))?.unwrap();
-        let cs = self.create_compute_shader(cs)?;
-
-        Ok(ComputePipeline {
-            cs
-        })
+        let (result, context) = self.capture_messages(|| self.build_compute_pipeline(desc));
+        result.map_err(|err| err.with_context(context))
     }
 
     fn create_framebuffer<I>(
@@ -670,8 +973,27 @@ impl hal::Device<Backend> for Device {
         })
     }
 
+    fn create_imageless_framebuffer<I>(
+        &self,
+        _renderpass: &RenderPass,
+        _attachments: I,
+        extent: image::Extent,
+    ) -> Result<Framebuffer, device::FramebufferError>
+    where
+        I: IntoIterator,
+        I::Item: Borrow<pass::FramebufferAttachment>,
+    {
+        Ok(Framebuffer {
+            attachments: Vec::new(),
+            layers: extent.depth as _,
+        })
+    }
+
     fn create_shader_module(&self, raw_data: &[u8]) -> Result<ShaderModule, device::ShaderError> {
-        Ok(ShaderModule::Spirv(raw_data.into()))
+        if cfg!(debug_assertions) {
+            pso::validate_spirv(raw_data)?;
+        }
+        Ok(ShaderModule::Spirv(shader::SpirvModule::new(raw_data)?))
     }
 
     fn create_buffer(
@@ -724,11 +1046,14 @@ impl hal::Device<Backend> for Device {
         debug!("usage={:?}, props={:b}", unbound_buffer.usage, memory.properties);
 
         #[allow(non_snake_case)]
-        let MiscFlags = if unbound_buffer.bind & (d3d11::D3D11_BIND_SHADER_RESOURCE | d3d11::D3D11_BIND_UNORDERED_ACCESS) != 0 {
+        let mut MiscFlags = if unbound_buffer.bind & (d3d11::D3D11_BIND_SHADER_RESOURCE | d3d11::D3D11_BIND_UNORDERED_ACCESS) != 0 {
             d3d11::D3D11_RESOURCE_MISC_BUFFER_ALLOW_RAW_VIEWS
         } else {
             0
         };
+        if unbound_buffer.usage.contains(buffer::Usage::INDIRECT) {
+            MiscFlags |= d3d11::D3D11_RESOURCE_MISC_DRAWINDIRECT_ARGS;
+        }
 
         let initial_data = memory.host_visible.as_ref().map(|p| d3d11::D3D11_SUBRESOURCE_DATA {
             pSysMem: unsafe { p.borrow().as_ptr().offset(offset as isize) as _ },
@@ -874,17 +1199,100 @@ impl hal::Device<Backend> for Device {
 
         Ok(Buffer {
             internal: buffer,
-            size: unbound_buffer.size
+            size: unbound_buffer.size,
+            range_uavs: Mutex::new(FastHashMap::default()),
         })
     }
 
     fn create_buffer_view<R: RangeArg<u64>>(
         &self,
-        _buffer: &Buffer,
-        _format: Option<format::Format>,
-        _range: R,
+        buffer: &Buffer,
+        format: Option<format::Format>,
+        range: R,
     ) -> Result<BufferView, buffer::ViewCreationError> {
-        unimplemented!()
+        // Buffers don't carry a format, so any number of views with different
+        // element formats can alias the same range.
+        let (dxgi_format, format_desc) = match format.and_then(|f| conv::map_format(f).map(|df| (df, f.surface_desc()))) {
+            Some(pair) => pair,
+            None => return Err(buffer::ViewCreationError::UnsupportedFormat { format }),
+        };
+        if !format_desc.aspects.contains(format::Aspects::COLOR) || format_desc.is_compressed() {
+            return Err(buffer::ViewCreationError::UnsupportedFormat { format });
+        }
+
+        let start = *range.start().unwrap_or(&0);
+        let end = *range.end().unwrap_or(&buffer.size);
+        let bytes_per_texel = (format_desc.bits / 8) as u64;
+        assert_eq!(start % bytes_per_texel, 0);
+        let first_element = (start / bytes_per_texel) as u32;
+        let num_elements = ((end - start) / bytes_per_texel) as u32;
+
+        let srv_handle = if buffer.internal.srv.is_some() {
+            let mut desc = unsafe { mem::zeroed::<d3d11::D3D11_SHADER_RESOURCE_VIEW_DESC>() };
+            desc.Format = dxgi_format;
+            desc.ViewDimension = d3dcommon::D3D11_SRV_DIMENSION_BUFFER;
+            unsafe {
+                let buffer_srv = desc.u.Buffer_mut();
+                *buffer_srv.u1.FirstElement_mut() = first_element;
+                *buffer_srv.u2.NumElements_mut() = num_elements;
+            };
+
+            let mut srv = ptr::null_mut();
+            let hr = unsafe {
+                self.raw.CreateShaderResourceView(
+                    buffer.internal.raw as *mut _,
+                    &desc,
+                    &mut srv as *mut *mut _ as *mut *mut _
+                )
+            };
+
+            if !winerror::SUCCEEDED(hr) {
+                error!("CreateShaderResourceView failed: 0x{:x}", hr);
+
+                return Err(buffer::ViewCreationError::UnsupportedFormat { format });
+            }
+
+            Some(unsafe { ComPtr::from_raw(srv) })
+        } else {
+            None
+        };
+
+        let uav_handle = if buffer.internal.uav.is_some() {
+            let mut desc = unsafe { mem::zeroed::<d3d11::D3D11_UNORDERED_ACCESS_VIEW_DESC>() };
+            desc.Format = dxgi_format;
+            desc.ViewDimension = d3d11::D3D11_UAV_DIMENSION_BUFFER;
+            unsafe {
+                *desc.u.Buffer_mut() = d3d11::D3D11_BUFFER_UAV {
+                    FirstElement: first_element,
+                    NumElements: num_elements,
+                    Flags: 0,
+                };
+            };
+
+            let mut uav = ptr::null_mut();
+            let hr = unsafe {
+                self.raw.CreateUnorderedAccessView(
+                    buffer.internal.raw as *mut _,
+                    &desc,
+                    &mut uav as *mut *mut _ as *mut *mut _
+                )
+            };
+
+            if !winerror::SUCCEEDED(hr) {
+                error!("CreateUnorderedAccessView failed: 0x{:x}", hr);
+
+                return Err(buffer::ViewCreationError::UnsupportedFormat { format });
+            }
+
+            Some(unsafe { ComPtr::from_raw(uav) })
+        } else {
+            None
+        };
+
+        Ok(BufferView {
+            srv_handle,
+            uav_handle,
+        })
     }
 
     fn create_image(
@@ -900,6 +1308,10 @@ impl hal::Device<Backend> for Device {
         //
         // TODO: create desc
 
+        if conv::map_format(format).is_none() {
+            return Err(image::CreationError::Format(format));
+        }
+
         let surface_desc = format.base_format().0.desc();
         let bytes_per_texel  = surface_desc.bits / 8;
         let ext = kind.extent();
@@ -911,9 +1323,12 @@ impl hal::Device<Backend> for Device {
            usage.contains(Usage::SAMPLED) ||
            usage.contains(Usage::STORAGE) { bind |= d3d11::D3D11_BIND_SHADER_RESOURCE; }
 
+        // clears through `TRANSFER_DST` go through the RTV/DSV of each subresource
+        let is_color = surface_desc.aspects.contains(format::Aspects::COLOR);
         if usage.contains(Usage::COLOR_ATTACHMENT) ||
-           usage.contains(Usage::TRANSFER_DST) { bind |= d3d11::D3D11_BIND_RENDER_TARGET; }
-        if usage.contains(Usage::DEPTH_STENCIL_ATTACHMENT) { bind |= d3d11::D3D11_BIND_DEPTH_STENCIL; }
+           (is_color && usage.contains(Usage::TRANSFER_DST)) { bind |= d3d11::D3D11_BIND_RENDER_TARGET; }
+        if usage.contains(Usage::DEPTH_STENCIL_ATTACHMENT) ||
+           (!is_color && usage.contains(Usage::TRANSFER_DST)) { bind |= d3d11::D3D11_BIND_DEPTH_STENCIL; }
 
         // TODO: how to do buffer copies
         if usage.contains(Usage::TRANSFER_DST) ||
@@ -1087,9 +1502,11 @@ impl hal::Device<Backend> for Device {
         };
 
         let mut render_target_views = Vec::new();
+        let aspects = image.format.surface_desc().aspects;
 
-        if image.usage.contains(image::Usage::COLOR_ATTACHMENT) ||
-           image.usage.contains(image::Usage::TRANSFER_DST)
+        if aspects.contains(format::Aspects::COLOR) &&
+            (image.usage.contains(image::Usage::COLOR_ATTACHMENT) ||
+             image.usage.contains(image::Usage::TRANSFER_DST))
         {
             for layer in 0..image.kind.num_layers() {
                 for mip in 0..image.mip_levels {
@@ -1097,7 +1514,7 @@ impl hal::Device<Backend> for Device {
                         resource,
                         kind: image.kind,
                         flags: image::StorageFlags::empty(),
-                        view_kind: image::ViewKind::D2,
+                        view_kind: image::ViewKind::D2Array,
                         format: dxgi_format,
                         range: image::SubresourceRange {
                             aspects: format::Aspects::COLOR,
@@ -1111,12 +1528,39 @@ impl hal::Device<Backend> for Device {
             }
         };
 
+        let mut depth_stencil_views = Vec::new();
+
+        if aspects.intersects(format::Aspects::DEPTH | format::Aspects::STENCIL) &&
+            (image.usage.contains(image::Usage::DEPTH_STENCIL_ATTACHMENT) ||
+             image.usage.contains(image::Usage::TRANSFER_DST))
+        {
+            for layer in 0..image.kind.num_layers() {
+                for mip in 0..image.mip_levels {
+                    let view = ViewInfo {
+                        resource,
+                        kind: image.kind,
+                        flags: image::StorageFlags::empty(),
+                        view_kind: image::ViewKind::D2Array,
+                        format: dxgi_format,
+                        range: image::SubresourceRange {
+                            aspects,
+                            levels: mip..(mip + 1),
+                            layers: layer..(layer + 1)
+                        }
+                    };
+
+                    depth_stencil_views.push(self.view_image_as_depth_stencil(&view).map_err(|_| device::BindError::WrongMemory)?);
+                }
+            }
+        };
+
         let internal = InternalImage {
             raw: resource,
             copy_srv,
             srv,
             unordered_access_views,
             render_target_views,
+            depth_stencil_views,
         };
 
         Ok(Image {
@@ -1142,6 +1586,7 @@ impl hal::Device<Backend> for Device {
         _swizzle: format::Swizzle,
         range: image::SubresourceRange,
     ) -> Result<ImageView, image::ViewError> {
+        let subresource = image.calc_subresource(range.levels.start as _, range.layers.start as _);
         let info = ViewInfo {
             resource: image.internal.raw,
             kind: image.kind,
@@ -1153,6 +1598,9 @@ impl hal::Device<Backend> for Device {
         };
 
         Ok(ImageView {
+            resource: image.internal.raw,
+            subresource,
+            format: info.format,
             srv_handle: if image.usage.contains(image::Usage::SAMPLED) {
                 Some(self.view_image_as_shader_resource(&info)?)
             } else {
@@ -1178,6 +1626,13 @@ impl hal::Device<Backend> for Device {
     }
 
     fn create_sampler(&self, info: image::SamplerInfo) -> Sampler {
+        let mut samplers = self.samplers.lock().unwrap();
+        if let Some(sampler) = samplers.get(&info) {
+            return Sampler {
+                sampler_handle: sampler.clone(),
+            };
+        }
+
         let op = match info.comparison {
             Some(_) => d3d11::D3D11_FILTER_REDUCTION_TYPE_COMPARISON,
             None => d3d11::D3D11_FILTER_REDUCTION_TYPE_STANDARD,
@@ -1209,8 +1664,11 @@ impl hal::Device<Backend> for Device {
 
         assert_eq!(true, winerror::SUCCEEDED(hr));
 
+        let sampler_handle = unsafe { ComPtr::from_raw(sampler) };
+        samplers.insert(info, sampler_handle.clone());
+
         Sampler {
-            sampler_handle: unsafe { ComPtr::from_raw(sampler) }
+            sampler_handle,
         }
     }
 
@@ -1298,7 +1756,9 @@ impl hal::Device<Backend> for Device {
         J: IntoIterator,
         J::Item: Borrow<pso::Descriptor<'a, Backend>>,
     {
+        let mut statistics = FrameStatistics::default();
         for write in write_iter {
+            statistics.descriptor_updates += 1;
             let target_binding = write.binding as usize;
             let (handle_offset, ty) = write.set.offset_mapping[target_binding];
 
@@ -1315,21 +1775,43 @@ impl hal::Device<Backend> for Device {
                 let handle = unsafe { write.set.handles.offset(handle_offset as isize) };
 
                 match *descriptor.borrow() {
-                    // TODO: binding range
-                    pso::Descriptor::Buffer(buffer, ref _range) => {
+                    pso::Descriptor::Buffer(buffer, ref range) => {
                         match ty {
-                            pso::DescriptorType::UniformBuffer => {
-                                unsafe { *handle = Descriptor(buffer.internal.raw as *mut _); }
+                            pso::DescriptorType::UniformBuffer |
+                            pso::DescriptorType::UniformBufferDynamic => {
+                                let start = range.start.unwrap_or(0);
+                                let end = range.end.unwrap_or(buffer.size);
+                                let constants = ((end - start + 15) / 16) as UINT;
+                                let range = ConstantRange {
+                                    first: (start / 16) as _,
+                                    // The number of constants must be a multiple of 16.
+                                    count: cmp::min(
+                                        (constants + 15) & !15,
+                                        d3d11::D3D11_REQ_CONSTANT_BUFFER_ELEMENT_COUNT,
+                                    ),
+                                };
+                                unsafe {
+                                    *handle = Descriptor(buffer.internal.raw as *mut _);
+                                    *write.set.constant_ranges.offset(handle_offset as isize) = range;
+                                }
                             },
                             pso::DescriptorType::StorageBuffer => {
-                                unsafe { *handle = Descriptor(buffer.internal.uav.unwrap() as *mut _); }
+                                let start = range.start.unwrap_or(0);
+                                let end = range.end.unwrap_or(buffer.size);
+                                let uav = if start == 0 && end == buffer.size {
+                                    buffer.internal.uav.unwrap()
+                                } else {
+                                    self.buffer_range_uav(buffer, start .. end)
+                                };
+                                unsafe { *handle = Descriptor(uav as *mut _); }
                             },
                             _ => unreachable!()
                         }
                     }
                     pso::Descriptor::Image(image, _layout) => {
                         match ty {
-                            pso::DescriptorType::SampledImage => {
+                            pso::DescriptorType::SampledImage |
+                            pso::DescriptorType::InputAttachment => {
                                 unsafe { *handle = Descriptor(image.srv_handle.clone().unwrap().as_raw() as *mut _); }
                             },
                             pso::DescriptorType::StorageImage => {
@@ -1345,13 +1827,16 @@ impl hal::Device<Backend> for Device {
                         unsafe { *handle = Descriptor(image.srv_handle.clone().unwrap().as_raw() as *mut _); }
                         unsafe { *(handle.offset(1)) = Descriptor(sampler.sampler_handle.as_raw() as *mut _); }
                     }
-                    pso::Descriptor::UniformTexelBuffer(_buffer_view) => {
+                    pso::Descriptor::UniformTexelBuffer(buffer_view) => {
+                        unsafe { *handle = Descriptor(buffer_view.srv_handle.clone().unwrap().as_raw() as *mut _); }
                     }
-                    pso::Descriptor::StorageTexelBuffer(_buffer_view) => {
+                    pso::Descriptor::StorageTexelBuffer(buffer_view) => {
+                        unsafe { *handle = Descriptor(buffer_view.uav_handle.clone().unwrap().as_raw() as *mut _); }
                     }
                 }
             }
         }
+        self.statistics.add(&statistics);
     }
 
     fn copy_descriptor_sets<'a, I>(&self, copy_iter: I)
@@ -1359,8 +1844,10 @@ impl hal::Device<Backend> for Device {
         I: IntoIterator,
         I::Item: Borrow<pso::DescriptorSetCopy<'a, Backend>>,
     {
+        let mut statistics = FrameStatistics::default();
         for copy in copy_iter {
             let copy = copy.borrow();
+            statistics.descriptor_updates += 1;
 
             for offset in 0..copy.count {
                 let (dst_handle_offset, dst_ty) = copy.dst_set.offset_mapping[copy.dst_binding as usize + offset];
@@ -1381,6 +1868,7 @@ impl hal::Device<Backend> for Device {
                 }
             }
         }
+        self.statistics.add(&statistics);
     }
 
     fn map_memory<R>(&self, memory: &Memory, range: R) -> Result<*mut u8, mapping::Error>
@@ -1412,12 +1900,13 @@ impl hal::Device<Backend> for Device {
         R: RangeArg<u64>,
     {
 
+        let context = self.context.lock().unwrap();
         // go through every range we wrote to
         for range in ranges.into_iter() {
             let &(memory, ref range) = range.borrow();
             let range = memory.resolve(range);
 
-            memory.flush(&self.context, range);
+            memory.flush(&context, range);
         }
     }
 
@@ -1427,12 +1916,13 @@ impl hal::Device<Backend> for Device {
         I::Item: Borrow<(&'a Memory, R)>,
         R: RangeArg<u64>,
     {
+        let context = self.context.lock().unwrap();
         // go through every range we want to read from
         for range in ranges.into_iter() {
             let &(memory, ref range) = range.borrow();
             let range = *range.start().unwrap_or(&0)..*range.end().unwrap_or(&memory.size);
 
-            memory.invalidate(&self.context, range);
+            memory.invalidate(&context, range);
         }
     }
 
@@ -1463,6 +1953,35 @@ impl hal::Device<Backend> for Device {
         unimplemented!()
     }
 
+    fn create_timeline_semaphore(&self, initial_value: u64) -> HostTimeline {
+        HostTimeline::new(initial_value)
+    }
+
+    fn get_timeline_semaphore_value(&self, semaphore: &HostTimeline) -> u64 {
+        semaphore.value()
+    }
+
+    fn signal_timeline_semaphore(&self, semaphore: &HostTimeline, value: u64) {
+        semaphore.signal(value);
+    }
+
+    fn wait_for_timeline_semaphores<'a, I>(&self, semaphores: I, wait: device::WaitFor, timeout_ms: u32) -> bool
+    where
+        I: IntoIterator<Item = (&'a HostTimeline, u64)>,
+    {
+        HostTimeline::wait_many(semaphores, wait, timeout_ms)
+    }
+
+    fn destroy_timeline_semaphore(&self, _semaphore: HostTimeline) {
+    }
+
+    fn create_event(&self) -> () {
+        // Events are waited on with full barriers.
+    }
+
+    fn destroy_event(&self, _: ()) {
+    }
+
     fn free_memory(&self, memory: Memory) {
         for (_range, internal) in memory.local_buffers.borrow_mut().iter() {
             unsafe {
@@ -1474,12 +1993,82 @@ impl hal::Device<Backend> for Device {
         }
     }
 
-    fn create_query_pool(&self, _query_ty: query::QueryType, _count: u32) -> QueryPool {
-        unimplemented!()
+    fn create_query_pool(&self, query_ty: query::QueryType, count: u32) -> QueryPool {
+        let native_ty = match query_ty {
+            query::QueryType::Occlusion => d3d11::D3D11_QUERY_OCCLUSION,
+            query::QueryType::PipelineStatistics(_) => d3d11::D3D11_QUERY_PIPELINE_STATISTICS,
+            query::QueryType::Timestamp => d3d11::D3D11_QUERY_TIMESTAMP,
+        };
+
+        let queries = (0 .. count)
+            .map(|_| self.create_query(native_ty))
+            .collect();
+
+        let frequency = match query_ty {
+            query::QueryType::Timestamp => self.timestamp_frequency(),
+            _ => 0,
+        };
+
+        QueryPool {
+            queries,
+            ty: query_ty,
+            frequency,
+        }
     }
 
     fn destroy_query_pool(&self, _pool: QueryPool) {
-        unimplemented!()
+        // Just drop
+    }
+
+    fn get_query_pool_results(
+        &self, pool: &QueryPool, queries: Range<query::QueryId>, data: &mut [u64], wait: bool,
+    ) -> Result<bool, error::HostExecutionError> {
+        let values_per_query = match pool.ty {
+            query::QueryType::PipelineStatistics(statistics) => statistics.bits().count_ones() as usize,
+            _ => 1,
+        };
+        assert!(data.len() >= (queries.end - queries.start) as usize * values_per_query);
+
+        for (i, id) in queries.enumerate() {
+            let out = &mut data[i * values_per_query .. (i + 1) * values_per_query];
+            match pool.ty {
+                query::QueryType::PipelineStatistics(statistics) => {
+                    let mut stats: d3d11::D3D11_QUERY_DATA_PIPELINE_STATISTICS = unsafe { mem::zeroed() };
+                    if !self.get_query_data(pool.get(id), &mut stats, wait) {
+                        return Ok(false);
+                    }
+                    // Same order as the `PipelineStatistic` bits.
+                    let all = [
+                        stats.IAVertices, stats.IAPrimitives, stats.VSInvocations,
+                        stats.GSInvocations, stats.GSPrimitives, stats.CInvocations,
+                        stats.CPrimitives, stats.PSInvocations, stats.HSInvocations,
+                        stats.DSInvocations, stats.CSInvocations,
+                    ];
+                    let enabled = all
+                        .iter()
+                        .enumerate()
+                        .filter(|&(bit, _)| statistics.bits() & (1 << bit) != 0);
+                    for (value, (_, &stat)) in out.iter_mut().zip(enabled) {
+                        *value = stat;
+                    }
+                }
+                query::QueryType::Timestamp => {
+                    let mut ticks = 0u64;
+                    if !self.get_query_data(pool.get(id), &mut ticks, wait) {
+                        return Ok(false);
+                    }
+                    // Report nanoseconds, matching the default `timestamp_period` of 1.
+                    out[0] = (ticks as f64 * 1_000_000_000.0 / pool.frequency as f64) as u64;
+                }
+                query::QueryType::Occlusion => {
+                    if !self.get_query_data(pool.get(id), &mut out[0], wait) {
+                        return Ok(false);
+                    }
+                }
+            }
+        }
+
+        Ok(true)
     }
 
     fn destroy_shader_module(&self, _shader_lib: ShaderModule) {
@@ -1493,6 +2082,26 @@ impl hal::Device<Backend> for Device {
         //unimplemented!()
     }
 
+    fn create_pipeline_cache(&self, _data: Option<&[u8]>) -> Result<(), device::OutOfMemory> {
+        // D3D11 drivers cache compiled shaders internally.
+        Ok(())
+    }
+
+    fn get_pipeline_cache_data(&self, _cache: &()) -> Result<Vec<u8>, device::OutOfMemory> {
+        Ok(Vec::new())
+    }
+
+    fn merge_pipeline_caches<I>(&self, _target: &(), _sources: I) -> Result<(), device::OutOfMemory>
+    where
+        I: IntoIterator,
+        I::Item: Borrow<()>,
+    {
+        Ok(())
+    }
+
+    fn destroy_pipeline_cache(&self, _cache: ()) {
+    }
+
     fn destroy_graphics_pipeline(&self, _pipeline: GraphicsPipeline) {
     }
 
@@ -1516,6 +2125,23 @@ impl hal::Device<Backend> for Device {
         // unimplemented!()
     }
 
+    fn create_sparse_buffer(
+        &self, _size: u64, _usage: buffer::Usage,
+    ) -> Result<(Buffer, memory::Requirements), buffer::CreationError> {
+        unimplemented!()
+    }
+
+    fn create_sparse_image(
+        &self,
+        _kind: image::Kind,
+        _mip_levels: image::Level,
+        _format: format::Format,
+        _usage: image::Usage,
+        _storage_flags: image::StorageFlags,
+    ) -> Result<(Image, hal::sparse::SparseImageRequirements), image::CreationError> {
+        unimplemented!()
+    }
+
     fn destroy_image_view(&self, _view: ImageView) {
         //unimplemented!()
     }
@@ -1543,8 +2169,8 @@ impl hal::Device<Backend> for Device {
         &self,
         surface: &mut Surface,
         config: hal::SwapchainConfig,
-        _old_swapchain: Option<Swapchain>,
-        _extent: &window::Extent2D,
+        old_swapchain: Option<Swapchain>,
+        extent: &window::Extent2D,
     ) -> (Swapchain, hal::Backbuffer<Backend>) {
         // TODO: use IDXGIFactory2 for >=11.1
         // TODO: this function should be able to fail (Result)?
@@ -1553,6 +2179,9 @@ impl hal::Device<Backend> for Device {
 
         debug!("{:#?}", config);
 
+        surface.width = extent.width;
+        surface.height = extent.height;
+
         let (non_srgb_format, format) = {
             // NOTE: DXGI doesn't allow sRGB format on the swapchain, but
             //       creating RTV of swapchain buffers with sRGB works
@@ -1593,7 +2222,26 @@ impl hal::Device<Backend> for Device {
             SwapEffect: DXGI_SWAP_EFFECT_DISCARD,
             Flags: 0
         };
-        let swapchain = {
+        let swapchain = if let Some(old_swapchain) = old_swapchain {
+            // Resize the buffers of the retired swapchain in place, all references
+            // to them have been released together with the backbuffer images.
+            let swapchain = old_swapchain.dxgi_swapchain;
+            let hr = unsafe {
+                swapchain.ResizeBuffers(
+                    config.image_count,
+                    surface.width,
+                    surface.height,
+                    non_srgb_format,
+                    0,
+                )
+            };
+
+            if !winerror::SUCCEEDED(hr) {
+                error!("error on swapchain resize 0x{:x}", hr);
+            }
+
+            swapchain
+        } else {
             let mut swapchain: *mut IDXGISwapChain = ptr::null_mut();
             let hr = unsafe {
                 surface.factory.CreateSwapChain(
@@ -1656,7 +2304,8 @@ impl hal::Device<Backend> for Device {
                 copy_srv: None,
                 srv: None,
                 unordered_access_views: Vec::new(),
-                render_target_views: vec![unsafe { ComPtr::from_raw(rtv) }]
+                render_target_views: vec![unsafe { ComPtr::from_raw(rtv) }],
+                depth_stencil_views: Vec::new(),
             };
 
             Image {
@@ -1675,7 +2324,17 @@ impl hal::Device<Backend> for Device {
             }
         }).collect();
 
-        (Swapchain { dxgi_swapchain: swapchain }, hal::Backbuffer::Images(images))
+        let swapchain = Swapchain {
+            dxgi_swapchain: swapchain,
+            wnd_handle: surface.wnd_handle,
+            extent: *extent,
+            sync_interval: match config.present_mode {
+                hal::PresentMode::Immediate => 0,
+                _ => 1,
+            },
+        };
+
+        (swapchain, hal::Backbuffer::Images(images))
     }
 
     fn destroy_swapchain(&self, _swapchain: Swapchain) {
@@ -1683,7 +2342,11 @@ impl hal::Device<Backend> for Device {
     }
 
     fn wait_idle(&self) -> Result<(), error::HostExecutionError> {
-        unimplemented!()
+        ::wait_idle(&self.context.lock().unwrap())
+    }
+
+    fn frame_statistics(&self) -> Option<FrameStatistics> {
+        Some(self.statistics.take())
     }
 
 }
@@ -1,7 +1,9 @@
 use hal::AdapterInfo;
+use hal::adapter::DeviceType;
 
 use winapi::shared::guiddef::GUID;
 use winapi::shared::{dxgi, dxgi1_2, dxgi1_3, dxgi1_4, dxgi1_5, winerror};
+use winapi::shared::minwindef::UINT;
 use winapi::um::unknwnbase::IUnknown;
 use winapi::Interface;
 
@@ -129,6 +131,18 @@ fn enum_adapters1(idx: u32, factory: *mut dxgi::IDXGIFactory) -> Result<ComPtr<d
     }
 }
 
+// DXGI doesn't expose the kind of the adapter, integrated GPUs are told apart by
+// their small dedicated memory carve-out.
+fn device_type(flags: UINT, dedicated_memory: usize) -> DeviceType {
+    if flags & dxgi::DXGI_ADAPTER_FLAG_SOFTWARE != 0 {
+        DeviceType::Cpu
+    } else if dedicated_memory <= 512 << 20 {
+        DeviceType::IntegratedGpu
+    } else {
+        DeviceType::DiscreteGpu
+    }
+}
+
 fn get_adapter_desc(adapter: *mut dxgi::IDXGIAdapter, version: DxgiVersion) -> AdapterInfo {
     match version {
         DxgiVersion::Dxgi1_0 => {
@@ -146,6 +160,7 @@ fn get_adapter_desc(adapter: *mut dxgi::IDXGIAdapter, version: DxgiVersion) -> A
                 vendor: desc.VendorId as usize,
                 device: desc.DeviceId as usize,
                 software_rendering: (desc.Flags & dxgi::DXGI_ADAPTER_FLAG_SOFTWARE) != 0,
+                device_type: device_type(desc.Flags, desc.DedicatedVideoMemory),
             }
         },
         DxgiVersion::Dxgi1_2 | 
@@ -166,6 +181,7 @@ fn get_adapter_desc(adapter: *mut dxgi::IDXGIAdapter, version: DxgiVersion) -> A
                 vendor: desc.VendorId as usize,
                 device: desc.DeviceId as usize,
                 software_rendering: (desc.Flags & dxgi::DXGI_ADAPTER_FLAG_SOFTWARE) != 0,
+                device_type: device_type(desc.Flags, desc.DedicatedVideoMemory),
             }
         }
     }
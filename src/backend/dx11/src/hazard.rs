@@ -0,0 +1,317 @@
+//! Tracking of resources bound as shader input and output at the same time.
+//!
+//! The D3D11 runtime unbinds shader resource views whose resource gets bound as render target,
+//! depth stencil or unordered access view, and silently drops shader resource views whose
+//! resource is currently bound as output. Command buffers track these conflicts instead:
+//! conflicting shader resource views are unbound while their resource is bound as output and
+//! rebound once the output is unbound again.
+//!
+//! Conflicts are tracked per subresource, from the mip levels and array slices of the views,
+//! so that e.g. one mip level of a texture can be sampled while rendering to the next one.
+
+use hal::pso;
+
+use winapi::shared::minwindef::UINT;
+use winapi::um::{d3d11, d3dcommon};
+
+use wio::com::ComPtr;
+
+use std::ops::Range;
+use std::{mem, ptr};
+
+// Resource referenced by a view.
+unsafe fn view_resource(view: *mut d3d11::ID3D11View) -> *mut d3d11::ID3D11Resource {
+    let mut resource = ptr::null_mut();
+    (*view).GetResource(&mut resource);
+    // The view keeps the resource alive, only the identity is needed.
+    (*resource).Release();
+    resource
+}
+
+// Range of `count` mip levels or array slices, where `!0` selects all remaining ones.
+fn range(first: UINT, count: UINT) -> Range<UINT> {
+    first .. if count == !0 { !0 } else { first.saturating_add(count) }
+}
+
+fn all() -> Range<UINT> {
+    0 .. !0
+}
+
+// Mip levels and array slices of a resource referenced by a view. Buffers and
+// the depth slices of 3D textures are treated as a whole.
+#[derive(Clone, Debug)]
+struct Subresources {
+    resource: *mut d3d11::ID3D11Resource,
+    levels: Range<UINT>,
+    layers: Range<UINT>,
+}
+
+impl Subresources {
+    fn overlaps(&self, other: &Subresources) -> bool {
+        self.resource == other.resource &&
+        self.levels.start < other.levels.end && other.levels.start < self.levels.end &&
+        self.layers.start < other.layers.end && other.layers.start < self.layers.end
+    }
+
+    unsafe fn of_srv(view: *mut d3d11::ID3D11ShaderResourceView) -> Self {
+        let mut desc: d3d11::D3D11_SHADER_RESOURCE_VIEW_DESC = mem::zeroed();
+        (*view).GetDesc(&mut desc);
+        let (levels, layers) = match desc.ViewDimension {
+            d3dcommon::D3D11_SRV_DIMENSION_TEXTURE1D => {
+                let tex = desc.u.Texture1D();
+                (range(tex.MostDetailedMip, tex.MipLevels), 0 .. 1)
+            }
+            d3dcommon::D3D11_SRV_DIMENSION_TEXTURE1DARRAY => {
+                let tex = desc.u.Texture1DArray();
+                (range(tex.MostDetailedMip, tex.MipLevels), range(tex.FirstArraySlice, tex.ArraySize))
+            }
+            d3dcommon::D3D11_SRV_DIMENSION_TEXTURE2D => {
+                let tex = desc.u.Texture2D();
+                (range(tex.MostDetailedMip, tex.MipLevels), 0 .. 1)
+            }
+            d3dcommon::D3D11_SRV_DIMENSION_TEXTURE2DARRAY => {
+                let tex = desc.u.Texture2DArray();
+                (range(tex.MostDetailedMip, tex.MipLevels), range(tex.FirstArraySlice, tex.ArraySize))
+            }
+            d3dcommon::D3D11_SRV_DIMENSION_TEXTURE2DMS => (0 .. 1, 0 .. 1),
+            d3dcommon::D3D11_SRV_DIMENSION_TEXTURE2DMSARRAY => {
+                let tex = desc.u.Texture2DMSArray();
+                (0 .. 1, range(tex.FirstArraySlice, tex.ArraySize))
+            }
+            d3dcommon::D3D11_SRV_DIMENSION_TEXTURE3D => {
+                let tex = desc.u.Texture3D();
+                (range(tex.MostDetailedMip, tex.MipLevels), all())
+            }
+            d3dcommon::D3D11_SRV_DIMENSION_TEXTURECUBE => {
+                let tex = desc.u.TextureCube();
+                (range(tex.MostDetailedMip, tex.MipLevels), 0 .. 6)
+            }
+            d3dcommon::D3D11_SRV_DIMENSION_TEXTURECUBEARRAY => {
+                let tex = desc.u.TextureCubeArray();
+                (range(tex.MostDetailedMip, tex.MipLevels), range(tex.First2DArrayFace, tex.NumCubes.saturating_mul(6)))
+            }
+            _ => (all(), all()),
+        };
+        Subresources { resource: view_resource(view as *mut _), levels, layers }
+    }
+
+    unsafe fn of_rtv(view: *mut d3d11::ID3D11RenderTargetView) -> Self {
+        let mut desc: d3d11::D3D11_RENDER_TARGET_VIEW_DESC = mem::zeroed();
+        (*view).GetDesc(&mut desc);
+        let (levels, layers) = match desc.ViewDimension {
+            d3d11::D3D11_RTV_DIMENSION_TEXTURE1D => (range(desc.u.Texture1D().MipSlice, 1), 0 .. 1),
+            d3d11::D3D11_RTV_DIMENSION_TEXTURE1DARRAY => {
+                let tex = desc.u.Texture1DArray();
+                (range(tex.MipSlice, 1), range(tex.FirstArraySlice, tex.ArraySize))
+            }
+            d3d11::D3D11_RTV_DIMENSION_TEXTURE2D => (range(desc.u.Texture2D().MipSlice, 1), 0 .. 1),
+            d3d11::D3D11_RTV_DIMENSION_TEXTURE2DARRAY => {
+                let tex = desc.u.Texture2DArray();
+                (range(tex.MipSlice, 1), range(tex.FirstArraySlice, tex.ArraySize))
+            }
+            d3d11::D3D11_RTV_DIMENSION_TEXTURE2DMS => (0 .. 1, 0 .. 1),
+            d3d11::D3D11_RTV_DIMENSION_TEXTURE2DMSARRAY => {
+                let tex = desc.u.Texture2DMSArray();
+                (0 .. 1, range(tex.FirstArraySlice, tex.ArraySize))
+            }
+            d3d11::D3D11_RTV_DIMENSION_TEXTURE3D => (range(desc.u.Texture3D().MipSlice, 1), all()),
+            _ => (all(), all()),
+        };
+        Subresources { resource: view_resource(view as *mut _), levels, layers }
+    }
+
+    unsafe fn of_dsv(view: *mut d3d11::ID3D11DepthStencilView) -> Self {
+        let mut desc: d3d11::D3D11_DEPTH_STENCIL_VIEW_DESC = mem::zeroed();
+        (*view).GetDesc(&mut desc);
+        let (levels, layers) = match desc.ViewDimension {
+            d3d11::D3D11_DSV_DIMENSION_TEXTURE1D => (range(desc.u.Texture1D().MipSlice, 1), 0 .. 1),
+            d3d11::D3D11_DSV_DIMENSION_TEXTURE1DARRAY => {
+                let tex = desc.u.Texture1DArray();
+                (range(tex.MipSlice, 1), range(tex.FirstArraySlice, tex.ArraySize))
+            }
+            d3d11::D3D11_DSV_DIMENSION_TEXTURE2D => (range(desc.u.Texture2D().MipSlice, 1), 0 .. 1),
+            d3d11::D3D11_DSV_DIMENSION_TEXTURE2DARRAY => {
+                let tex = desc.u.Texture2DArray();
+                (range(tex.MipSlice, 1), range(tex.FirstArraySlice, tex.ArraySize))
+            }
+            d3d11::D3D11_DSV_DIMENSION_TEXTURE2DMS => (0 .. 1, 0 .. 1),
+            d3d11::D3D11_DSV_DIMENSION_TEXTURE2DMSARRAY => {
+                let tex = desc.u.Texture2DMSArray();
+                (0 .. 1, range(tex.FirstArraySlice, tex.ArraySize))
+            }
+            _ => (all(), all()),
+        };
+        Subresources { resource: view_resource(view as *mut _), levels, layers }
+    }
+
+    unsafe fn of_uav(view: *mut d3d11::ID3D11UnorderedAccessView) -> Self {
+        let mut desc: d3d11::D3D11_UNORDERED_ACCESS_VIEW_DESC = mem::zeroed();
+        (*view).GetDesc(&mut desc);
+        let (levels, layers) = match desc.ViewDimension {
+            d3d11::D3D11_UAV_DIMENSION_TEXTURE1D => (range(desc.u.Texture1D().MipSlice, 1), 0 .. 1),
+            d3d11::D3D11_UAV_DIMENSION_TEXTURE1DARRAY => {
+                let tex = desc.u.Texture1DArray();
+                (range(tex.MipSlice, 1), range(tex.FirstArraySlice, tex.ArraySize))
+            }
+            d3d11::D3D11_UAV_DIMENSION_TEXTURE2D => (range(desc.u.Texture2D().MipSlice, 1), 0 .. 1),
+            d3d11::D3D11_UAV_DIMENSION_TEXTURE2DARRAY => {
+                let tex = desc.u.Texture2DArray();
+                (range(tex.MipSlice, 1), range(tex.FirstArraySlice, tex.ArraySize))
+            }
+            d3d11::D3D11_UAV_DIMENSION_TEXTURE3D => (range(desc.u.Texture3D().MipSlice, 1), all()),
+            _ => (all(), all()),
+        };
+        Subresources { resource: view_resource(view as *mut _), levels, layers }
+    }
+}
+
+unsafe fn set_shader_resource(
+    context: &ComPtr<d3d11::ID3D11DeviceContext>,
+    stage: pso::Stage,
+    slot: UINT,
+    view: *mut d3d11::ID3D11ShaderResourceView,
+) {
+    let views = [view];
+    match stage {
+        pso::Stage::Vertex => context.VSSetShaderResources(slot, 1, views.as_ptr()),
+        pso::Stage::Hull => context.HSSetShaderResources(slot, 1, views.as_ptr()),
+        pso::Stage::Domain => context.DSSetShaderResources(slot, 1, views.as_ptr()),
+        pso::Stage::Geometry => context.GSSetShaderResources(slot, 1, views.as_ptr()),
+        pso::Stage::Fragment => context.PSSetShaderResources(slot, 1, views.as_ptr()),
+        pso::Stage::Compute => context.CSSetShaderResources(slot, 1, views.as_ptr()),
+    }
+}
+
+#[derive(Clone, Debug)]
+struct Input {
+    stage: pso::Stage,
+    slot: UINT,
+    view: *mut d3d11::ID3D11ShaderResourceView,
+    subresources: Subresources,
+    // Unbound, as the resource is currently bound as output.
+    suppressed: bool,
+}
+
+#[derive(Clone, Debug, Default)]
+pub(crate) struct HazardTracker {
+    // Shader resource views bound by the user.
+    inputs: Vec<Input>,
+    // Subresources of the bound render target and depth stencil views.
+    targets: Vec<Subresources>,
+    // Subresources of the bound unordered access views, by slot.
+    uavs: Vec<(UINT, Subresources)>,
+}
+
+impl HazardTracker {
+    fn is_output(&self, subresources: &Subresources) -> bool {
+        self.targets.iter().any(|target| target.overlaps(subresources)) ||
+        self.uavs.iter().any(|&(_, ref uav)| uav.overlaps(subresources))
+    }
+
+    /// Bind shader resource views, leaving views of resources currently bound as output unbound.
+    pub(crate) unsafe fn set_shader_resources(
+        &mut self,
+        context: &ComPtr<d3d11::ID3D11DeviceContext>,
+        stage: pso::Stage,
+        start: UINT,
+        views: &[*mut d3d11::ID3D11ShaderResourceView],
+    ) {
+        for (slot, &view) in (start ..).zip(views) {
+            self.clear_input(stage, slot);
+            if view.is_null() {
+                set_shader_resource(context, stage, slot, view);
+                continue;
+            }
+
+            let subresources = Subresources::of_srv(view);
+            let suppressed = self.is_output(&subresources);
+            if suppressed {
+                debug!("Delaying binding of {:?} shader resource {} until its resource is unbound as output", stage, slot);
+            }
+            set_shader_resource(context, stage, slot, if suppressed { ptr::null_mut() } else { view });
+            self.inputs.push(Input { stage, slot, view, subresources, suppressed });
+        }
+    }
+
+    /// Bind render target and depth stencil views.
+    pub(crate) unsafe fn set_render_targets(
+        &mut self,
+        context: &ComPtr<d3d11::ID3D11DeviceContext>,
+        color_views: &[*mut d3d11::ID3D11RenderTargetView],
+        depth_view: *mut d3d11::ID3D11DepthStencilView,
+    ) {
+        self.targets = color_views
+            .iter()
+            .filter(|view| !view.is_null())
+            .map(|&view| Subresources::of_rtv(view))
+            .collect();
+        if !depth_view.is_null() {
+            self.targets.push(Subresources::of_dsv(depth_view));
+        }
+
+        self.suppress_inputs(context);
+        context.OMSetRenderTargets(color_views.len() as _, color_views.as_ptr(), depth_view);
+        self.restore_inputs(context);
+    }
+
+    /// Bind unordered access views of the compute stage.
+    pub(crate) unsafe fn set_unordered_access_views(
+        &mut self,
+        context: &ComPtr<d3d11::ID3D11DeviceContext>,
+        start: UINT,
+        views: &[*mut d3d11::ID3D11UnorderedAccessView],
+    ) {
+        for (slot, &view) in (start ..).zip(views) {
+            self.uavs.retain(|&(uav_slot, _)| uav_slot != slot);
+            if !view.is_null() {
+                self.uavs.push((slot, Subresources::of_uav(view)));
+            }
+        }
+
+        self.suppress_inputs(context);
+        context.CSSetUnorderedAccessViews(start, views.len() as _, views.as_ptr(), ptr::null_mut());
+        self.restore_inputs(context);
+    }
+
+    /// Forget a shader resource view and unordered access view slot, which has been
+    /// unbound by an internal operation.
+    pub(crate) fn clear_slot(&mut self, stage: pso::Stage, slot: UINT) {
+        self.clear_input(stage, slot);
+        if stage == pso::Stage::Compute {
+            self.uavs.retain(|&(uav_slot, _)| uav_slot != slot);
+        }
+    }
+
+    /// Forget the render targets, which have been unbound by an internal operation.
+    pub(crate) fn clear_targets(&mut self) {
+        self.targets.clear();
+    }
+
+    fn clear_input(&mut self, stage: pso::Stage, slot: UINT) {
+        self.inputs.retain(|input| input.stage != stage || input.slot != slot);
+    }
+
+    // Unbind shader resource views whose resource is bound as output.
+    unsafe fn suppress_inputs(&mut self, context: &ComPtr<d3d11::ID3D11DeviceContext>) {
+        for i in 0 .. self.inputs.len() {
+            if !self.inputs[i].suppressed && self.is_output(&self.inputs[i].subresources) {
+                let input = &mut self.inputs[i];
+                debug!("Unbinding {:?} shader resource {} as its resource is bound as output", input.stage, input.slot);
+                set_shader_resource(context, input.stage, input.slot, ptr::null_mut());
+                input.suppressed = true;
+            }
+        }
+    }
+
+    // Rebind shader resource views whose resource isn't bound as output anymore.
+    unsafe fn restore_inputs(&mut self, context: &ComPtr<d3d11::ID3D11DeviceContext>) {
+        for i in 0 .. self.inputs.len() {
+            if self.inputs[i].suppressed && !self.is_output(&self.inputs[i].subresources) {
+                let input = &mut self.inputs[i];
+                debug!("Rebinding {:?} shader resource {}", input.stage, input.slot);
+                set_shader_resource(context, input.stage, input.slot, input.view);
+                input.suppressed = false;
+            }
+        }
+    }
+}
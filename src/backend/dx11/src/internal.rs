@@ -13,7 +13,7 @@ use std::borrow::Borrow;
 use spirv_cross;
 use shader;
 
-use {Buffer, Image};
+use {Buffer, Image, MAX_PUSH_CONSTANT_SIZE};
 
 #[repr(C)]
 struct BufferCopy {
@@ -97,6 +97,7 @@ pub struct Internal {
     cs_copy_buffer_image2d_r8: ComPtr<d3d11::ID3D11ComputeShader>,
 
     copy_info: ComPtr<d3d11::ID3D11Buffer>,
+    push_constants: ComPtr<d3d11::ID3D11Buffer>,
 }
 
 fn compile_blob(src: &[u8], entrypoint: &str, stage: Stage) -> ComPtr<d3dcommon::ID3DBlob> {
@@ -160,9 +161,9 @@ fn compile_cs(device: &ComPtr<d3d11::ID3D11Device>, src: &[u8], entrypoint: &str
 
 impl Internal {
     pub fn new(device: &ComPtr<d3d11::ID3D11Device>) -> Self {
-        let copy_info = {
+        let create_constant_buffer = |size: usize| {
             let desc = d3d11::D3D11_BUFFER_DESC {
-                ByteWidth: mem::size_of::<BufferImageCopyInfo>() as _,
+                ByteWidth: size as _,
                 Usage: d3d11::D3D11_USAGE_DYNAMIC,
                 BindFlags: d3d11::D3D11_BIND_CONSTANT_BUFFER,
                 CPUAccessFlags: d3d11::D3D11_CPU_ACCESS_WRITE,
@@ -180,8 +181,10 @@ impl Internal {
             };
             assert_eq!(true, winerror::SUCCEEDED(hr));
 
-            unsafe { ComPtr::from_raw(buffer) }
+            unsafe { ComPtr::<d3d11::ID3D11Buffer>::from_raw(buffer) }
         };
+        let copy_info = create_constant_buffer(mem::size_of::<BufferImageCopyInfo>());
+        let push_constants = create_constant_buffer(MAX_PUSH_CONSTANT_SIZE as usize);
 
         let (sampler_nearest, sampler_linear) = {
             let mut desc = d3d11::D3D11_SAMPLER_DESC {
@@ -262,10 +265,35 @@ impl Internal {
             cs_copy_buffer_image2d_r8g8: compile_cs(device, copy_shaders, "cs_copy_buffer_image2d_r8g8"),
             cs_copy_buffer_image2d_r8: compile_cs(device, copy_shaders, "cs_copy_buffer_image2d_r8"),
 
-            copy_info
+            copy_info,
+            push_constants,
         }
     }
 
+    /// Upload the push constants of a command buffer, returning the buffer to bind.
+    pub fn update_push_constants(
+        &mut self, context: &ComPtr<d3d11::ID3D11DeviceContext>, data: &[u32],
+    ) -> *mut d3d11::ID3D11Buffer {
+        let mut mapped = unsafe { mem::zeroed::<d3d11::D3D11_MAPPED_SUBRESOURCE>() };
+        let hr = unsafe {
+            context.Map(
+                self.push_constants.as_raw() as _,
+                0,
+                d3d11::D3D11_MAP_WRITE_DISCARD,
+                0,
+                &mut mapped
+            )
+        };
+        assert_eq!(winerror::S_OK, hr);
+
+        unsafe {
+            ptr::copy_nonoverlapping(data.as_ptr(), mapped.pData as *mut u32, data.len());
+            context.Unmap(self.push_constants.as_raw() as _, 0);
+        }
+
+        self.push_constants.as_raw()
+    }
+
     fn map(&mut self, context: &ComPtr<d3d11::ID3D11DeviceContext>) -> *mut u8 {
         let mut mapped = unsafe { mem::zeroed::<d3d11::D3D11_MAPPED_SUBRESOURCE>() };
         let hr = unsafe {
@@ -393,7 +421,7 @@ impl Internal {
                     let info = region.borrow();
                     self.update_image(context, &info);
 
-                    let uav = dst.get_uav(info.dst_subresource.level, 0).unwrap().as_raw();
+                    let uav = dst.get_uav(info.dst_subresource.level, info.dst_subresource.layers.start).unwrap().as_raw();
                     context.CSSetUnorderedAccessViews(0, 1, [uav].as_ptr(), ptr::null_mut());
 
                     context.Dispatch(
@@ -412,26 +440,27 @@ impl Internal {
             for region in regions.into_iter() {
                 let info = region.borrow();
 
-                // TODO: layer subresources
-                unsafe {
-                    context.CopySubresourceRegion(
-
-                        dst.internal.raw as _,
-                        src.calc_subresource(info.src_subresource.level as _, 0),
-                        info.dst_offset.x as _,
-                        info.dst_offset.y as _,
-                        info.dst_offset.z as _,
-                        src.internal.raw as _,
-                        dst.calc_subresource(info.dst_subresource.level as _, 0),
-                        &d3d11::D3D11_BOX {
-                            left: info.src_offset.x as _,
-                            top: info.src_offset.y as _,
-                            front: info.src_offset.z as _,
-                            right: info.extent.width as _,
-                            bottom: info.extent.height as _,
-                            back: info.extent.depth as _,
-                        }
-                    );
+                let num_layers = info.src_subresource.layers.end - info.src_subresource.layers.start;
+                for layer in 0 .. num_layers {
+                    unsafe {
+                        context.CopySubresourceRegion(
+                            dst.internal.raw as _,
+                            dst.calc_subresource(info.dst_subresource.level as _, (info.dst_subresource.layers.start + layer) as _),
+                            info.dst_offset.x as _,
+                            info.dst_offset.y as _,
+                            info.dst_offset.z as _,
+                            src.internal.raw as _,
+                            src.calc_subresource(info.src_subresource.level as _, (info.src_subresource.layers.start + layer) as _),
+                            &d3d11::D3D11_BOX {
+                                left: info.src_offset.x as _,
+                                top: info.src_offset.y as _,
+                                front: info.src_offset.z as _,
+                                right: info.src_offset.x as u32 + info.extent.width,
+                                bottom: info.src_offset.y as u32 + info.extent.height,
+                                back: info.src_offset.z as u32 + info.extent.depth,
+                            }
+                        );
+                    }
                 }
             }
         }
@@ -524,7 +553,7 @@ impl Internal {
                 let info = copy.borrow();
                 self.update_buffer_image(context, &info);
 
-                let uav = dst.get_uav(info.image_layers.level, 0).unwrap().as_raw();
+                let uav = dst.get_uav(info.image_layers.level, info.image_layers.layers.start).unwrap().as_raw();
                 context.CSSetUnorderedAccessViews(0, 1, [uav].as_ptr(), ptr::null_mut());
 
                 context.Dispatch(
@@ -14,31 +14,38 @@ extern crate winapi;
 extern crate winit;
 extern crate wio;
 
-use hal::{buffer, command, error, format, image, memory, query, pso, Features, Limits, QueueType};
+use hal::{buffer, command, error, format, image, memory, pass, query, pso, Features, Limits, QueueType};
 use hal::{DrawCount, SwapImageIndex, IndexCount, InstanceCount, VertexCount, VertexOffset, WorkGroupCount};
 use hal::queue::{QueueFamilyId, Queues};
-use hal::backend::RawQueueGroup;
+use hal::backend::{FastHashMap, RawQueueGroup, StatisticsCollector};
+use hal::profiler::FrameStatistics;
 use hal::range::RangeArg;
+use hal::sync::HostTimeline;
 
 use range_alloc::RangeAllocator;
 
 use winapi::shared::{dxgiformat, winerror};
 
 use winapi::shared::dxgi::{IDXGIFactory, IDXGIAdapter, IDXGISwapChain};
-use winapi::shared::minwindef::{FALSE, UINT};
+use winapi::shared::minwindef::{BOOL, FALSE, UINT};
 use winapi::shared::windef::{HWND, RECT};
 use winapi::um::winuser::{GetClientRect};
-use winapi::um::{d3d11, d3dcommon};
+use winapi::um::{d3d11, d3d11_1, d3dcommon};
 
 use wio::com::ComPtr;
 
+use smallvec::SmallVec;
+
 use std::ptr;
 use std::mem;
+use std::slice;
 use std::ops::Range;
 use std::cell::RefCell;
+use std::sync::{Arc, Mutex};
 use std::borrow::Borrow;
 
 use std::os::raw::c_void;
+use std::thread;
 
 #[path = "../../auxil/range_alloc.rs"]
 mod range_alloc;
@@ -46,6 +53,7 @@ mod conv;
 mod dxgi;
 mod shader;
 mod internal;
+mod hazard;
 mod device;
 
 #[derive(Clone, Derivative)]
@@ -83,19 +91,13 @@ impl Instance {
     }
 
     pub fn create_surface_from_hwnd(&self, hwnd: *mut c_void) -> Surface {
-        let (width, height) = unsafe {
-            let mut rect: RECT = mem::zeroed();
-            if GetClientRect(hwnd as *mut _, &mut rect as *mut RECT) == 0 {
-                panic!("GetClientRect failed");
-            }
-            ((rect.right - rect.left) as u32, (rect.bottom - rect.top) as u32)
-        };
+        let extent = client_extent(hwnd as *mut _);
 
         Surface {
             factory: self.factory.clone(),
             wnd_handle: hwnd as *mut _,
-            width: width,
-            height: height,
+            width: extent.width,
+            height: extent.height,
         }
     }
 
@@ -106,17 +108,61 @@ impl Instance {
     }
 }
 
-fn get_features(_device: ComPtr<d3d11::ID3D11Device>, _feature_level: d3dcommon::D3D_FEATURE_LEVEL) -> hal::Features {
+/// Constant buffer slot emulating push constants, the last one of each stage.
+/// Descriptor sets must not bind constant buffers to it.
+pub(crate) const PUSH_CONSTANT_SLOT: UINT = d3d11::D3D11_COMMONSHADER_CONSTANT_BUFFER_API_SLOT_COUNT - 1;
+/// Size in bytes of the push constant buffer.
+pub(crate) const MAX_PUSH_CONSTANT_SIZE: u32 = 256;
+
+fn get_features(device: ComPtr<d3d11::ID3D11Device>, feature_level: d3dcommon::D3D_FEATURE_LEVEL) -> hal::Features {
     use hal::Features;
 
-    let features =
+    let mut features =
         Features::ROBUST_BUFFER_ACCESS |
         Features::FULL_DRAW_INDEX_U32 |
-        Features::FORMAT_BC;
+        Features::FORMAT_BC |
+        Features::DYNAMIC_RENDERING |
+        Features::IMAGELESS_FRAMEBUFFER;
+
+    if feature_level >= d3dcommon::D3D_FEATURE_LEVEL_10_0 {
+        features |= Features::GEOMETRY_SHADER;
+    }
+
+    // Indirect draws require feature level 11.0, multiple draws are issued one by one.
+    if feature_level >= d3dcommon::D3D_FEATURE_LEVEL_11_0 {
+        features |= Features::MULTI_DRAW_INDIRECT | Features::DRAW_INDIRECT_FIRST_INSTANCE;
+        features |= Features::TESSELLATION_SHADER;
+    }
+
+    // D3D11.3 options, not available on older runtimes.
+    let mut options2: d3d11::D3D11_FEATURE_DATA_D3D11_OPTIONS2 = unsafe { mem::zeroed() };
+    let hr = unsafe {
+        device.CheckFeatureSupport(
+            d3d11::D3D11_FEATURE_D3D11_OPTIONS2,
+            &mut options2 as *mut _ as *mut _,
+            mem::size_of::<d3d11::D3D11_FEATURE_DATA_D3D11_OPTIONS2>() as UINT
+        )
+    };
+    if hr == winerror::S_OK && options2.PSSpecifiedStencilRefSupported != FALSE {
+        features |= Features::SHADER_STENCIL_EXPORT;
+    }
 
     features
 }
 
+// Binding ranges of constant buffers with `*SetConstantBuffers1` requires the D3D11.1 runtime.
+fn supports_constant_buffer_offsetting(device: &ComPtr<d3d11::ID3D11Device>) -> bool {
+    let mut options: d3d11::D3D11_FEATURE_DATA_D3D11_OPTIONS = unsafe { mem::zeroed() };
+    let hr = unsafe {
+        device.CheckFeatureSupport(
+            d3d11::D3D11_FEATURE_D3D11_OPTIONS,
+            &mut options as *mut _ as *mut _,
+            mem::size_of::<d3d11::D3D11_FEATURE_DATA_D3D11_OPTIONS>() as UINT
+        )
+    };
+    hr == winerror::S_OK && options.ConstantBufferOffsetting != FALSE
+}
+
 fn get_format_properties(device: ComPtr<d3d11::ID3D11Device>) -> [format::Properties; format::NUM_FORMATS] {
     let mut format_properties = [format::Properties::default(); format::NUM_FORMATS];
     for (i, props) in &mut format_properties.iter_mut().enumerate().skip(1) {
@@ -178,7 +224,6 @@ fn get_format_properties(device: ComPtr<d3d11::ID3D11Device>) -> [format::Proper
                 props.optimal_tiling |= format::ImageFeature::DEPTH_STENCIL_ATTACHMENT;
             }
             if support.OutFormatSupport & d3d11::D3D11_FORMAT_SUPPORT_SHADER_LOAD != 0 {
-                //TODO: check d3d12::D3D12_FORMAT_SUPPORT2_UAV_TYPED_LOAD ?
                 if can_buffer {
                     props.buffer_features |= format::BufferFeature::UNIFORM_TEXEL;
                 }
@@ -208,6 +253,14 @@ fn get_format_properties(device: ComPtr<d3d11::ID3D11Device>) -> [format::Proper
                     if can_image {
                         props.optimal_tiling |= format::ImageFeature::STORAGE;
                     }
+                    if support_2.OutFormatSupport2 & d3d11::D3D11_FORMAT_SUPPORT2_UAV_TYPED_LOAD != 0 {
+                        if can_buffer {
+                            props.buffer_features |= format::BufferFeature::STORAGE_TEXEL_READ;
+                        }
+                        if can_image {
+                            props.optimal_tiling |= format::ImageFeature::STORAGE_READ;
+                        }
+                    }
                 }
             }
         }
@@ -295,13 +348,16 @@ impl hal::Instance for Instance {
                 min_buffer_copy_offset_alignment: 1,    // TODO
                 min_buffer_copy_pitch_alignment: 1,     // TODO
                 min_texel_buffer_offset_alignment: 1,   // TODO
-                min_uniform_buffer_offset_alignment: 16, // TODO: verify
-                min_storage_buffer_offset_alignment: 1, // TODO
+                // Constant buffer offsets are specified in multiples of 16 constants.
+                min_uniform_buffer_offset_alignment: 256,
+                min_storage_buffer_offset_alignment: 4, // Raw UAVs address 32-bit elements
                 framebuffer_color_samples_count: 1,     // TODO
                 framebuffer_depth_samples_count: 1,     // TODO
                 framebuffer_stencil_samples_count: 1,   // TODO
                 max_color_attachments: 1,               // TODO
                 non_coherent_atom_size: 0,              // TODO
+                min_subgroup_size: 0,                   // No wave operations before SM6
+                max_subgroup_size: 0,
             };
 
             let features = get_features(device.clone(), feature_level);
@@ -440,7 +496,7 @@ impl hal::PhysicalDevice<Backend> for PhysicalDevice {
             unsafe { (ComPtr::from_raw(device), ComPtr::from_raw(cxt)) }
         };
 
-        let device = device::Device::new(device, cxt, self.memory_properties.clone());
+        let device = device::Device::new(device, cxt, self.memory_properties.clone(), self.features);
 
         // TODO: deferred context => 1 cxt/queue?
         let queues = Queues::new(
@@ -453,6 +509,7 @@ impl hal::PhysicalDevice<Backend> for PhysicalDevice {
                     // TODO: multiple queues?
                     let queue = CommandQueue {
                         context: device.context.clone(),
+                        statistics: device.statistics.clone(),
                     };
                     group.add_queue(queue);
                     group
@@ -574,6 +631,20 @@ impl hal::PhysicalDevice<Backend> for PhysicalDevice {
 
 }
 
+// Current size of the client area of the window.
+fn client_extent(wnd_handle: HWND) -> hal::window::Extent2D {
+    unsafe {
+        let mut rect: RECT = mem::zeroed();
+        if GetClientRect(wnd_handle, &mut rect as *mut RECT) == 0 {
+            panic!("GetClientRect failed");
+        }
+        hal::window::Extent2D {
+            width: (rect.right - rect.left) as u32,
+            height: (rect.bottom - rect.top) as u32,
+        }
+    }
+}
+
 pub struct Surface {
     pub(crate) factory: ComPtr<IDXGIFactory>,
     wnd_handle: HWND,
@@ -598,23 +669,9 @@ impl hal::Surface<Backend> for Surface {
         image::Kind::D2(self.width, self.height, 1, 1)
     }
 
-    fn compatibility(
-        &self, _: &PhysicalDevice
-    ) -> (hal::SurfaceCapabilities, Option<Vec<format::Format>>, Vec<hal::PresentMode>) {
-        let extent = hal::window::Extent2D {
-            width: self.width,
-            height: self.height,
-        };
-
-        // TODO: flip swap effects require dx11.1/windows8
-        // NOTE: some swap effects affect msaa capabilities..
-        // TODO: _DISCARD swap effects can only have one image?
-        let capabilities = hal::SurfaceCapabilities {
-            image_count: 1..16, // TODO:
-            current_extent: Some(extent),
-            extents: extent..extent,
-            max_image_layers: 1,
-        };
+    fn capabilities(&self, _: &PhysicalDevice) -> hal::SurfaceCapabilities {
+        // The window may have been resized since the surface was created.
+        let extent = client_extent(self.wnd_handle);
 
         let formats = vec![
             format::Format::Bgra8Srgb,
@@ -625,28 +682,56 @@ impl hal::Surface<Backend> for Surface {
             format::Format::Rgba16Float,
         ];
 
+        // Bitblt model swapchains tear when presenting without v-sync.
         let present_modes = vec![
-            hal::PresentMode::Fifo //TODO
+            hal::PresentMode::Fifo,
+            hal::PresentMode::Immediate,
         ];
 
-        (capabilities, Some(formats), present_modes)
+        // TODO: flip swap effects require dx11.1/windows8
+        // NOTE: some swap effects affect msaa capabilities..
+        // TODO: _DISCARD swap effects can only have one image?
+        hal::SurfaceCapabilities {
+            image_count: 1..16, // TODO:
+            current_extent: Some(extent),
+            extents: extent..extent,
+            max_image_layers: 1,
+            current_transform: hal::SurfaceTransform::IDENTITY,
+            supported_transforms: hal::SurfaceTransform::IDENTITY,
+            formats: Some(formats),
+            present_modes,
+        }
     }
 
 }
 
 pub struct Swapchain {
     dxgi_swapchain: ComPtr<IDXGISwapChain>,
+    wnd_handle: HWND,
+    extent: hal::window::Extent2D,
+    // Sync interval passed on presentation.
+    sync_interval: UINT,
+}
+
+impl Swapchain {
+    // DXGI stretches the buffers on presentation if the window got resized,
+    // so the swapchain never goes out of date.
+    fn suboptimal(&self) -> Option<hal::window::Suboptimal> {
+        hal::window::Suboptimal::from_extent(client_extent(self.wnd_handle), self.extent)
+    }
 }
 
 unsafe impl Send for Swapchain { }
 unsafe impl Sync for Swapchain { }
 
 impl hal::Swapchain<Backend> for Swapchain {
-    fn acquire_image(&mut self, _sync: hal::FrameSync<Backend>) -> Result<hal::SwapImageIndex, ()> {
+    fn acquire_image(
+        &mut self, _sync: hal::FrameSync<Backend>
+    ) -> Result<(hal::SwapImageIndex, Option<hal::window::Suboptimal>), hal::window::AcquireError> {
         // TODO: non-`_DISCARD` swap effects have more than one buffer, `FLIP`
         //       effects are dxgi 1.3 (w10+?) in which case there is
         //       `GetCurrentBackBufferIndex()` on the swapchain
-        Ok(0)
+        Ok((0, self.suboptimal()))
     }
 }
 
@@ -660,11 +745,61 @@ impl hal::QueueFamily for QueueFamily {
     fn id(&self) -> QueueFamilyId { QueueFamilyId(0) }
 }
 
+/// Block until all the work submitted to the immediate `context` has completed,
+/// by flushing it and waiting for an event query issued at the end.
+pub(crate) fn wait_idle(context: &ComPtr<d3d11::ID3D11DeviceContext>) -> Result<(), error::HostExecutionError> {
+    let mut device = ptr::null_mut();
+    unsafe { context.GetDevice(&mut device) };
+    let device = unsafe { ComPtr::<d3d11::ID3D11Device>::from_raw(device) };
+
+    let desc = d3d11::D3D11_QUERY_DESC {
+        Query: d3d11::D3D11_QUERY_EVENT,
+        MiscFlags: 0,
+    };
+    let mut query = ptr::null_mut();
+    let hr = unsafe {
+        device.CreateQuery(&desc, &mut query as *mut *mut _ as *mut *mut _)
+    };
+    if !winerror::SUCCEEDED(hr) {
+        error!("CreateQuery failed: 0x{:x}", hr);
+        return Err(error::HostExecutionError::OutOfHostMemory);
+    }
+    let query = unsafe { ComPtr::<d3d11::ID3D11Query>::from_raw(query) };
+
+    unsafe {
+        context.End(query.as_raw() as *mut _);
+        context.Flush();
+    }
+
+    loop {
+        let mut done: BOOL = FALSE;
+        let hr = unsafe {
+            context.GetData(
+                query.as_raw() as *mut _,
+                &mut done as *mut _ as *mut _,
+                mem::size_of::<BOOL>() as _,
+                0,
+            )
+        };
+        match hr {
+            winerror::S_OK if done != FALSE => return Ok(()),
+            winerror::S_OK | winerror::S_FALSE => thread::yield_now(),
+            winerror::DXGI_ERROR_DEVICE_REMOVED => return Err(error::HostExecutionError::DeviceLost),
+            _ => {
+                error!("GetData failed: 0x{:x}", hr);
+                return Err(error::HostExecutionError::DeviceLost);
+            }
+        }
+    }
+}
+
 #[derive(Derivative, Clone)]
 #[derivative(Debug)]
 pub struct CommandQueue {
+    // Immediate context, shared with the device.
     #[derivative(Debug="ignore")]
-    context: ComPtr<d3d11::ID3D11DeviceContext>
+    context: Arc<Mutex<ComPtr<d3d11::ID3D11DeviceContext>>>,
+    statistics: Arc<StatisticsCollector>,
 }
 
 unsafe impl Send for CommandQueue { }
@@ -676,30 +811,70 @@ impl hal::queue::RawCommandQueue<Backend> for CommandQueue {
         IC: IntoIterator,
         IC::Item: Borrow<CommandBuffer>,
     {
+        // Timeline semaphores are emulated on the host, and the immediate context executes
+        // submissions in order, so only waits for host signals can actually block here.
+        for &(semaphore, value, _) in submission.wait_timeline_semaphores {
+            semaphore.wait(value, !0);
+        }
+
+        let context = self.context.lock().unwrap();
+        let mut statistics = FrameStatistics::default();
         for cmd_buf in submission.cmd_buffers.into_iter() {
             let cmd_buf = cmd_buf.borrow();
-            self.context.ExecuteCommandList(cmd_buf.as_raw_list().as_raw(), FALSE);
+            statistics.add(&cmd_buf.statistics);
+            context.ExecuteCommandList(cmd_buf.as_raw_list().as_raw(), FALSE);
+        }
+        self.statistics.add(&statistics);
+
+        if !submission.signal_timeline_semaphores.is_empty() {
+            if let Err(err) = wait_idle(&context) {
+                error!("Error when waiting for the submission: {:?}", err);
+            }
+            for &(semaphore, value) in submission.signal_timeline_semaphores {
+                semaphore.signal(value);
+            }
         }
     }
 
-    fn present<IS, S, IW>(&mut self, swapchains: IS, _wait_semaphores: IW) -> Result<(), ()>
+    fn present<IS, S, IW>(
+        &mut self, swapchains: IS, _wait_semaphores: IW
+    ) -> Result<Option<hal::window::Suboptimal>, hal::window::PresentError>
     where
         IS: IntoIterator<Item = (S, SwapImageIndex)>,
         S: Borrow<Swapchain>,
         IW: IntoIterator,
         IW::Item: Borrow<Semaphore>,
     {
+        // Presenting flushes the immediate context.
+        let _context = self.context.lock().unwrap();
+        let mut suboptimal = None;
         for (swapchain, _) in swapchains {
-            unsafe { swapchain.borrow().dxgi_swapchain.Present(1, 0); }
+            let swapchain = swapchain.borrow();
+            let hr = unsafe { swapchain.dxgi_swapchain.Present(swapchain.sync_interval, 0) };
+            match hr {
+                winerror::DXGI_ERROR_DEVICE_REMOVED |
+                winerror::DXGI_ERROR_DEVICE_RESET => {
+                    return Err(hal::window::PresentError::DeviceLost);
+                }
+                _ if !winerror::SUCCEEDED(hr) => {
+                    error!("error on present 0x{:x}", hr);
+                }
+                _ => {}
+            }
+            suboptimal = suboptimal.or(swapchain.suboptimal());
         }
 
-        Ok(())
+        Ok(suboptimal)
     }
 
-    fn wait_idle(&self) -> Result<(), error::HostExecutionError> {
+    unsafe fn bind_sparse(&mut self, _info: hal::sparse::SparseBindInfo<Backend>, _fence: Option<&Fence>) {
         unimplemented!()
     }
 
+    fn wait_idle(&self) -> Result<(), error::HostExecutionError> {
+        wait_idle(&self.context.lock().unwrap())
+    }
+
 }
 
 #[derive(Derivative, Clone)]
@@ -710,6 +885,11 @@ pub struct CommandBuffer {
     internal: internal::Internal,
     #[derivative(Debug="ignore")]
     context: ComPtr<d3d11::ID3D11DeviceContext>,
+    // Required for binding ranges of constant buffers, `None` if not supported.
+    #[derivative(Debug="ignore")]
+    context1: Option<ComPtr<d3d11_1::ID3D11DeviceContext1>>,
+    // Conflicts between shader resource views and bound outputs.
+    hazards: RefCell<hazard::HazardTracker>,
     #[derivative(Debug="ignore")]
     list: Option<ComPtr<d3d11::ID3D11CommandList>>,
 
@@ -727,22 +907,44 @@ pub struct CommandBuffer {
     vertex_buffers: Vec<*mut d3d11::ID3D11Buffer>,
     vertex_offsets: Vec<u32>,
     vertex_strides: Vec<u32>,
+    // Push constants of all stages, uploaded as a whole on every update.
+    push_constants: Vec<u32>,
+
+    // Render pass in progress, its subpasses are bound one after another.
+    #[derivative(Debug="ignore")]
+    render_pass: Option<RenderPassCache>,
+
+    // Commands recorded since `begin`, accumulated by the queue on submission.
+    statistics: FrameStatistics,
 }
 
 unsafe impl Send for CommandBuffer {}
 unsafe impl Sync for CommandBuffer {}
 
 impl CommandBuffer {
-    fn create_deferred(device: ComPtr<d3d11::ID3D11Device>, internal: internal::Internal) -> Self {
+    fn create_deferred(
+        device: ComPtr<d3d11::ID3D11Device>,
+        internal: internal::Internal,
+        constant_buffer_offsetting: bool,
+    ) -> Self {
         let mut context: *mut d3d11::ID3D11DeviceContext = ptr::null_mut();
         let hr = unsafe {
             device.CreateDeferredContext(0, &mut context as *mut *mut _ as *mut *mut _)
         };
         assert_eq!(hr, winerror::S_OK);
 
+        let context = unsafe { ComPtr::from_raw(context) };
+        let context1 = if constant_buffer_offsetting {
+            context.cast::<d3d11_1::ID3D11DeviceContext1>().ok()
+        } else {
+            None
+        };
+
         CommandBuffer {
             internal,
-            context: unsafe { ComPtr::from_raw(context) },
+            context,
+            context1,
+            hazards: RefCell::new(hazard::HazardTracker::default()),
             list: None,
             bound_bindings: 0,
             required_bindings: None,
@@ -750,6 +952,48 @@ impl CommandBuffer {
             vertex_buffers: Vec::new(),
             vertex_offsets: Vec::new(),
             vertex_strides: Vec::new(),
+            push_constants: vec![0; MAX_PUSH_CONSTANT_SIZE as usize / 4],
+            render_pass: None,
+            statistics: FrameStatistics::default(),
+        }
+    }
+
+    fn bind_subpass(&self, subpass: &SubpassDesc, attachments: &[ImageView]) {
+        let color_views = subpass.color_attachments
+            .iter()
+            .map(|&id| attachments[id].rtv_handle.clone().unwrap().as_raw())
+            .collect::<Vec<_>>();
+
+        let depth_view = subpass.depth_stencil_attachment
+            .map_or(ptr::null_mut(), |id| attachments[id].dsv_handle.clone().unwrap().as_raw());
+
+        unsafe {
+            self.hazards.borrow_mut().set_render_targets(&self.context, &color_views, depth_view);
+        }
+    }
+
+    // Unbind the targets of the current subpass and resolve its color attachments.
+    //
+    // Input attachments of the following subpass are read through shader resource
+    // views, which can only be bound once the attachments stopped being targets.
+    fn finish_subpass(&mut self) {
+        unsafe {
+            self.hazards.get_mut().set_render_targets(&self.context, &[ptr::null_mut(); 8], ptr::null_mut());
+        }
+
+        if let Some(ref pass) = self.render_pass {
+            for &(src, dst) in &pass.subpasses[pass.current].resolves {
+                let (src, dst) = (&pass.attachments[src], &pass.attachments[dst]);
+                unsafe {
+                    self.context.ResolveSubresource(
+                        dst.resource,
+                        dst.subresource,
+                        src.resource,
+                        src.subresource,
+                        dst.format,
+                    );
+                }
+            }
         }
     }
 
@@ -757,6 +1001,25 @@ impl CommandBuffer {
         self.list.clone().unwrap().clone()
     }
 
+    /// Copy the hidden counter of an append or consume buffer into `dst` at `dst_offset`.
+    ///
+    /// Allows feeding the instance count of an indirect draw with the number of elements
+    /// produced by a compute pass, without reading it back on the CPU.
+    ///
+    /// # Safety
+    ///
+    /// `src` has to be a valid unordered access view created with
+    /// `D3D11_BUFFER_UAV_FLAG_APPEND` or `D3D11_BUFFER_UAV_FLAG_COUNTER` on the same device.
+    pub unsafe fn copy_structure_count(
+        &mut self,
+        src: *mut d3d11::ID3D11UnorderedAccessView,
+        dst: &Buffer,
+        dst_offset: buffer::Offset,
+    ) {
+        assert_eq!(dst_offset % 4, 0, "Structure count offset has to be 4 byte aligned");
+        self.context.CopyStructureCount(dst.internal.raw, dst_offset as _, src);
+    }
+
     fn set_vertex_buffers(&self) {
         if let Some(binding_count) = self.max_bindings {
             if self.vertex_buffers.len() >= binding_count as usize &&
@@ -775,90 +1038,255 @@ impl CommandBuffer {
         }
     }
 
-    unsafe fn bind_vertex_descriptor(&self, context: &ComPtr<d3d11::ID3D11DeviceContext>, binding: &PipelineBinding, handles: *mut Descriptor) {
+    unsafe fn set_shader_resources(&self, context: &ComPtr<d3d11::ID3D11DeviceContext>, stage: pso::Stage, start: UINT, len: UINT, handles: *mut Descriptor) {
+        let views = slice::from_raw_parts(handles as *const *mut _, len as usize);
+        self.hazards.borrow_mut().set_shader_resources(context, stage, start, views);
+    }
+
+    unsafe fn bind_vertex_descriptor(&self, context: &ComPtr<d3d11::ID3D11DeviceContext>, binding: &PipelineBinding, set: &DescriptorSet, dynamic_offsets: &[(u32, u32)]) {
         use pso::DescriptorType::*;
 
-        let handles = handles.offset(binding.handle_offset as isize);
+        let handles = set.handles.offset(binding.handle_offset as isize);
         let start = binding.binding_range.start as UINT;
         let len = binding.binding_range.end as UINT - start;
 
         match binding.ty {
             Sampler => context.VSSetSamplers(start, len, handles as *const *mut _ as *const *mut _),
-            SampledImage => context.VSSetShaderResources(start, len, handles as *const *mut _ as *const *mut _),
+            SampledImage => self.set_shader_resources(context, pso::Stage::Vertex, start, len, handles),
             CombinedImageSampler => {
-                context.VSSetShaderResources(start, len, handles as *const *mut _ as *const *mut _);
+                self.set_shader_resources(context, pso::Stage::Vertex, start, len, handles);
                 context.VSSetSamplers(start, len, handles.offset(1) as *const *mut _ as *const *mut _);
             },
             UniformBuffer |
-            UniformBufferDynamic => context.VSSetConstantBuffers(start, len, handles as *const *mut _ as *const *mut _),
+            UniformBufferDynamic => match self.context1 {
+                Some(ref context1) => {
+                    let (first, num) = Self::constant_ranges(binding, set, dynamic_offsets);
+                    context1.VSSetConstantBuffers1(start, len, handles as *const *mut _ as *const *mut _, first.as_ptr(), num.as_ptr())
+                }
+                None => context.VSSetConstantBuffers(start, len, handles as *const *mut _ as *const *mut _),
+            },
+            _ => unimplemented!()
+        }
+    }
+
+    unsafe fn bind_hull_descriptor(&self, context: &ComPtr<d3d11::ID3D11DeviceContext>, binding: &PipelineBinding, set: &DescriptorSet, dynamic_offsets: &[(u32, u32)]) {
+        use pso::DescriptorType::*;
+
+        let handles = set.handles.offset(binding.handle_offset as isize);
+        let start = binding.binding_range.start as UINT;
+        let len = binding.binding_range.end as UINT - start;
+
+        match binding.ty {
+            Sampler => context.HSSetSamplers(start, len, handles as *const *mut _ as *const *mut _),
+            SampledImage => self.set_shader_resources(context, pso::Stage::Hull, start, len, handles),
+            CombinedImageSampler => {
+                self.set_shader_resources(context, pso::Stage::Hull, start, len, handles);
+                context.HSSetSamplers(start, len, handles.offset(1) as *const *mut _ as *const *mut _);
+            },
+            UniformBuffer |
+            UniformBufferDynamic => match self.context1 {
+                Some(ref context1) => {
+                    let (first, num) = Self::constant_ranges(binding, set, dynamic_offsets);
+                    context1.HSSetConstantBuffers1(start, len, handles as *const *mut _ as *const *mut _, first.as_ptr(), num.as_ptr())
+                }
+                None => context.HSSetConstantBuffers(start, len, handles as *const *mut _ as *const *mut _),
+            },
+            _ => unimplemented!()
+        }
+    }
+
+    unsafe fn bind_domain_descriptor(&self, context: &ComPtr<d3d11::ID3D11DeviceContext>, binding: &PipelineBinding, set: &DescriptorSet, dynamic_offsets: &[(u32, u32)]) {
+        use pso::DescriptorType::*;
+
+        let handles = set.handles.offset(binding.handle_offset as isize);
+        let start = binding.binding_range.start as UINT;
+        let len = binding.binding_range.end as UINT - start;
+
+        match binding.ty {
+            Sampler => context.DSSetSamplers(start, len, handles as *const *mut _ as *const *mut _),
+            SampledImage => self.set_shader_resources(context, pso::Stage::Domain, start, len, handles),
+            CombinedImageSampler => {
+                self.set_shader_resources(context, pso::Stage::Domain, start, len, handles);
+                context.DSSetSamplers(start, len, handles.offset(1) as *const *mut _ as *const *mut _);
+            },
+            UniformBuffer |
+            UniformBufferDynamic => match self.context1 {
+                Some(ref context1) => {
+                    let (first, num) = Self::constant_ranges(binding, set, dynamic_offsets);
+                    context1.DSSetConstantBuffers1(start, len, handles as *const *mut _ as *const *mut _, first.as_ptr(), num.as_ptr())
+                }
+                None => context.DSSetConstantBuffers(start, len, handles as *const *mut _ as *const *mut _),
+            },
             _ => unimplemented!()
         }
     }
 
-    unsafe fn bind_fragment_descriptor(&self, context: &ComPtr<d3d11::ID3D11DeviceContext>, binding: &PipelineBinding, handles: *mut Descriptor) {
+    unsafe fn bind_geometry_descriptor(&self, context: &ComPtr<d3d11::ID3D11DeviceContext>, binding: &PipelineBinding, set: &DescriptorSet, dynamic_offsets: &[(u32, u32)]) {
         use pso::DescriptorType::*;
 
-        let handles = handles.offset(binding.handle_offset as isize);
+        let handles = set.handles.offset(binding.handle_offset as isize);
+        let start = binding.binding_range.start as UINT;
+        let len = binding.binding_range.end as UINT - start;
+
+        match binding.ty {
+            Sampler => context.GSSetSamplers(start, len, handles as *const *mut _ as *const *mut _),
+            SampledImage => self.set_shader_resources(context, pso::Stage::Geometry, start, len, handles),
+            CombinedImageSampler => {
+                self.set_shader_resources(context, pso::Stage::Geometry, start, len, handles);
+                context.GSSetSamplers(start, len, handles.offset(1) as *const *mut _ as *const *mut _);
+            },
+            UniformBuffer |
+            UniformBufferDynamic => match self.context1 {
+                Some(ref context1) => {
+                    let (first, num) = Self::constant_ranges(binding, set, dynamic_offsets);
+                    context1.GSSetConstantBuffers1(start, len, handles as *const *mut _ as *const *mut _, first.as_ptr(), num.as_ptr())
+                }
+                None => context.GSSetConstantBuffers(start, len, handles as *const *mut _ as *const *mut _),
+            },
+            _ => unimplemented!()
+        }
+    }
+
+    unsafe fn bind_fragment_descriptor(&self, context: &ComPtr<d3d11::ID3D11DeviceContext>, binding: &PipelineBinding, set: &DescriptorSet, dynamic_offsets: &[(u32, u32)]) {
+        use pso::DescriptorType::*;
+
+        let handles = set.handles.offset(binding.handle_offset as isize);
         let start = binding.binding_range.start as UINT;
         let len = binding.binding_range.end as UINT - start;
 
         match binding.ty {
             Sampler => context.PSSetSamplers(start, len, handles as *const *mut _ as *const *mut _),
-            SampledImage => context.PSSetShaderResources(start, len, handles as *const *mut _ as *const *mut _),
+            SampledImage |
+            InputAttachment => self.set_shader_resources(context, pso::Stage::Fragment, start, len, handles),
             CombinedImageSampler => {
-                context.PSSetShaderResources(start, len, handles as *const *mut _ as *const *mut _);
+                self.set_shader_resources(context, pso::Stage::Fragment, start, len, handles);
                 context.PSSetSamplers(start, len, handles.offset(1) as *const *mut _ as *const *mut _);
             },
             UniformBuffer |
-            UniformBufferDynamic => context.PSSetConstantBuffers(start, len, handles as *const *mut _ as *const *mut _),
+            UniformBufferDynamic => match self.context1 {
+                Some(ref context1) => {
+                    let (first, num) = Self::constant_ranges(binding, set, dynamic_offsets);
+                    context1.PSSetConstantBuffers1(start, len, handles as *const *mut _ as *const *mut _, first.as_ptr(), num.as_ptr())
+                }
+                None => context.PSSetConstantBuffers(start, len, handles as *const *mut _ as *const *mut _),
+            },
             _ => unimplemented!()
         }
     }
 
-    unsafe fn bind_compute_descriptor(&self, context: &ComPtr<d3d11::ID3D11DeviceContext>, binding: &PipelineBinding, handles: *mut Descriptor) {
+    unsafe fn bind_compute_descriptor(&self, context: &ComPtr<d3d11::ID3D11DeviceContext>, binding: &PipelineBinding, set: &DescriptorSet, dynamic_offsets: &[(u32, u32)]) {
         use pso::DescriptorType::*;
 
-        let handles = handles.offset(binding.handle_offset as isize);
+        let handles = set.handles.offset(binding.handle_offset as isize);
         let start = binding.binding_range.start as UINT;
         let len = binding.binding_range.end as UINT - start;
 
         match binding.ty {
             Sampler => context.CSSetSamplers(start, len, handles as *const *mut _ as *const *mut _),
-            SampledImage => context.CSSetShaderResources(start, len, handles as *const *mut _ as *const *mut _),
+            SampledImage => self.set_shader_resources(context, pso::Stage::Compute, start, len, handles),
             CombinedImageSampler => {
-                context.CSSetShaderResources(start, len, handles as *const *mut _ as *const *mut _);
+                self.set_shader_resources(context, pso::Stage::Compute, start, len, handles);
                 context.CSSetSamplers(start, len, handles.offset(1) as *const *mut _ as *const *mut _);
             },
             UniformBuffer |
-            UniformBufferDynamic => context.CSSetConstantBuffers(start, len, handles as *const *mut _ as *const *mut _),
+            UniformBufferDynamic => match self.context1 {
+                Some(ref context1) => {
+                    let (first, num) = Self::constant_ranges(binding, set, dynamic_offsets);
+                    context1.CSSetConstantBuffers1(start, len, handles as *const *mut _ as *const *mut _, first.as_ptr(), num.as_ptr())
+                }
+                None => context.CSSetConstantBuffers(start, len, handles as *const *mut _ as *const *mut _),
+            },
             StorageImage |
-            StorageBuffer => context.CSSetUnorderedAccessViews(start, len, handles as *const *mut _ as *const *mut _, ptr::null_mut()),
+            StorageBuffer => {
+                let views = slice::from_raw_parts(handles as *const *mut _, len as usize);
+                self.hazards.borrow_mut().set_unordered_access_views(context, start, views);
+            }
             _ => unimplemented!()
         }
     }
 
-    fn bind_descriptor(&self, context: &ComPtr<d3d11::ID3D11DeviceContext>, binding: &PipelineBinding, handles: *mut Descriptor) {
+    fn bind_descriptor(&self, context: &ComPtr<d3d11::ID3D11DeviceContext>, binding: &PipelineBinding, set: &DescriptorSet, dynamic_offsets: &[(u32, u32)]) {
         //use pso::ShaderStageFlags::*;
 
         unsafe {
             if binding.stage.contains(pso::ShaderStageFlags::VERTEX) {
-                self.bind_vertex_descriptor(context, binding, handles);
+                self.bind_vertex_descriptor(context, binding, set, dynamic_offsets);
+            }
+
+            if binding.stage.contains(pso::ShaderStageFlags::HULL) {
+                self.bind_hull_descriptor(context, binding, set, dynamic_offsets);
+            }
+
+            if binding.stage.contains(pso::ShaderStageFlags::DOMAIN) {
+                self.bind_domain_descriptor(context, binding, set, dynamic_offsets);
+            }
+
+            if binding.stage.contains(pso::ShaderStageFlags::GEOMETRY) {
+                self.bind_geometry_descriptor(context, binding, set, dynamic_offsets);
             }
 
             if binding.stage.contains(pso::ShaderStageFlags::FRAGMENT) {
-                self.bind_fragment_descriptor(context, binding, handles);
+                self.bind_fragment_descriptor(context, binding, set, dynamic_offsets);
             }
 
             if binding.stage.contains(pso::ShaderStageFlags::COMPUTE) {
-                self.bind_compute_descriptor(context, binding, handles);
+                self.bind_compute_descriptor(context, binding, set, dynamic_offsets);
             }
         }
     }
+
+    // Constant ranges of the buffers bound by `binding`, with dynamic offsets applied.
+    unsafe fn constant_ranges(
+        binding: &PipelineBinding,
+        set: &DescriptorSet,
+        dynamic_offsets: &[(u32, u32)],
+    ) -> (SmallVec<[UINT; 16]>, SmallVec<[UINT; 16]>) {
+        let len = binding.binding_range.end - binding.binding_range.start;
+        (0 .. len)
+            .map(|i| {
+                let handle_offset = binding.handle_offset + i;
+                let range = *set.constant_ranges.offset(handle_offset as isize);
+                let offset = dynamic_offsets
+                    .iter()
+                    .find(|&&(handle, _)| handle == handle_offset)
+                    .map_or(0, |&(_, offset)| offset);
+                (range.first + offset / 16, range.count)
+            })
+            .unzip()
+    }
+
+    // Pairs the dynamic uniform buffers of a set with their dynamic offsets,
+    // which are consumed in binding order.
+    fn dynamic_offsets<J>(&self, set: &DescriptorSet, offsets: &mut J) -> SmallVec<[(u32, u32); 8]>
+    where
+        J: Iterator<Item = command::DescriptorSetOffset>,
+    {
+        let dynamic_offsets = set.offset_mapping
+            .iter()
+            .filter(|&&(_, ty)| ty == pso::DescriptorType::UniformBufferDynamic)
+            .map(|&(handle_offset, _)| {
+                (handle_offset, offsets.next().expect("Missing dynamic offset"))
+            })
+            .collect::<SmallVec<[_; 8]>>();
+
+        if self.context1.is_none() && dynamic_offsets.iter().any(|&(_, offset)| offset != 0) {
+            error!("Constant buffer offsets are not supported by the D3D11 runtime");
+        }
+
+        dynamic_offsets
+    }
 }
 
 impl hal::command::RawCommandBuffer<Backend> for CommandBuffer {
 
-    fn begin(&mut self, _flags: command::CommandBufferFlags, _info: command::CommandBufferInheritanceInfo<Backend>) {
+    fn begin(&mut self, _flags: command::CommandBufferFlags, info: command::CommandBufferInheritanceInfo<Backend>) {
+        self.statistics = FrameStatistics::default();
+
+        // Deferred contexts start without any state, secondary command buffers
+        // continuing a render pass bind the targets of the inherited framebuffer.
+        if let (Some(subpass), Some(framebuffer)) = (info.subpass, info.framebuffer) {
+            self.bind_subpass(&subpass.main_pass.subpasses[subpass.index], &framebuffer.attachments);
+        }
     }
 
     fn finish(&mut self) {
@@ -875,21 +1303,13 @@ impl hal::command::RawCommandBuffer<Backend> for CommandBuffer {
         unimplemented!()
     }
 
-    fn begin_render_pass<T>(&mut self, _render_pass: &RenderPass, framebuffer: &Framebuffer, _target_rect: pso::Rect, clear_values: T, _first_subpass: command::SubpassContents)
+    fn begin_render_pass<T>(&mut self, render_pass: &RenderPass, framebuffer: &Framebuffer, _target_rect: pso::Rect, clear_values: T, _first_subpass: command::SubpassContents)
     where
         T: IntoIterator,
         T::Item: Borrow<command::ClearValueRaw>,
     {
         // TODO: very temp
 
-        let color_views = framebuffer.attachments.iter()
-            .filter(|a| a.rtv_handle.is_some())
-            .map(|a| a.rtv_handle.clone().unwrap().as_raw())
-            .collect::<Vec<_>>();
-
-        let depth_view = framebuffer.attachments.iter().find(|a| a.dsv_handle.is_some());
-
-
         unsafe {
             for (clear, view) in clear_values.into_iter().zip(framebuffer.attachments.iter()) {
                 let clear = clear.borrow();
@@ -902,53 +1322,146 @@ impl hal::command::RawCommandBuffer<Backend> for CommandBuffer {
                     self.context.ClearDepthStencilView(handle.clone().as_raw(), d3d11::D3D11_CLEAR_DEPTH, clear.depth_stencil.depth, 0);
                 }
             }
-
-            self.context.OMSetRenderTargets(
-                color_views.len() as _,
-                color_views.as_ptr(),
-                if let Some(depth_attachment) = depth_view {
-                    depth_attachment.dsv_handle.clone().unwrap().as_raw()
-                } else {
-                    ptr::null_mut()
-                },
-            );
         }
-        // TODO: begin render pass
-        //unimplemented!()
+
+        // Subpasses are emulated as separate passes over the framebuffer attachments.
+        let pass = RenderPassCache {
+            subpasses: render_pass.subpasses.clone(),
+            attachments: framebuffer.attachments.clone(),
+            current: 0,
+        };
+        self.bind_subpass(&pass.subpasses[0], &pass.attachments);
+        self.render_pass = Some(pass);
+    }
+
+    fn begin_render_pass_imageless<T, I>(
+        &mut self,
+        render_pass: &RenderPass,
+        framebuffer: &Framebuffer,
+        attachments: I,
+        target_rect: pso::Rect,
+        clear_values: T,
+        first_subpass: command::SubpassContents,
+    ) where
+        T: IntoIterator,
+        T::Item: Borrow<command::ClearValueRaw>,
+        I: IntoIterator,
+        I::Item: Borrow<ImageView>,
+    {
+        let framebuffer = Framebuffer {
+            attachments: attachments.into_iter().map(|att| att.borrow().clone()).collect(),
+            layers: framebuffer.layers,
+        };
+        self.begin_render_pass(render_pass, &framebuffer, target_rect, clear_values, first_subpass);
     }
 
     fn next_subpass(&mut self, _contents: command::SubpassContents) {
-        unimplemented!()
+        self.finish_subpass();
+
+        if let Some(ref mut pass) = self.render_pass {
+            pass.current += 1;
+        }
+        if let Some(ref pass) = self.render_pass {
+            self.bind_subpass(&pass.subpasses[pass.current], &pass.attachments);
+        }
     }
 
     fn end_render_pass(&mut self) {
+        self.finish_subpass();
+        self.render_pass = None;
+    }
+
+    fn begin_rendering(&mut self, info: &command::RenderingInfo<Backend>) {
+        let color_views = info.colors
+            .iter()
+            .map(|att| att.view.rtv_handle.clone().unwrap().as_raw())
+            .collect::<Vec<_>>();
+
         unsafe {
-            self.context.OMSetRenderTargets(8, [ptr::null_mut(); 8].as_ptr(), ptr::null_mut());
+            for att in info.colors {
+                if att.ops.load == pass::AttachmentLoadOp::Clear {
+                    let handle = att.view.rtv_handle.clone().unwrap();
+                    self.context.ClearRenderTargetView(handle.as_raw(), &att.clear_value.color.float32);
+                }
+            }
+
+            let depth_view = info.depth_stencil.as_ref().map(|att| {
+                let handle = att.view.dsv_handle.clone().unwrap();
+
+                let mut flags = 0;
+                if att.ops.load == pass::AttachmentLoadOp::Clear {
+                    flags |= d3d11::D3D11_CLEAR_DEPTH;
+                }
+                if att.stencil_ops.load == pass::AttachmentLoadOp::Clear {
+                    flags |= d3d11::D3D11_CLEAR_STENCIL;
+                }
+                if flags != 0 {
+                    let ds = att.clear_value.depth_stencil;
+                    self.context.ClearDepthStencilView(handle.as_raw(), flags, ds.depth, ds.stencil as _);
+                }
+
+                handle.as_raw()
+            });
+
+            self.hazards.get_mut().set_render_targets(
+                &self.context,
+                &color_views,
+                depth_view.unwrap_or(ptr::null_mut()),
+            );
         }
     }
 
+    fn end_rendering(&mut self) {
+        self.end_render_pass();
+    }
+
     fn pipeline_barrier<'a, T>(&mut self, _stages: Range<pso::PipelineStage>, _dependencies: memory::Dependencies, _barriers: T)
     where
         T: IntoIterator,
         T::Item: Borrow<memory::Barrier<'a, Backend>>,
     {
+        self.statistics.barriers += 1;
         // TODO: should we track and assert on resource states?
         // unimplemented!()
     }
 
-    fn clear_image<T>(&mut self, image: &Image, _: image::Layout, color: command::ClearColorRaw, _depth_stencil: command::ClearDepthStencilRaw, subresource_ranges: T)
+    fn clear_image<T>(&mut self, image: &Image, _: image::Layout, color: command::ClearColorRaw, depth_stencil: command::ClearDepthStencilRaw, subresource_ranges: T)
     where
         T: IntoIterator,
         T::Item: Borrow<image::SubresourceRange>,
     {
-        // TODO: use a internal program to clear for subregions in the image
         for subresource_range in subresource_ranges {
-            let _sub = subresource_range.borrow();
-            unsafe {
-                self.context.ClearRenderTargetView(
-                    image.get_rtv(0, 0).unwrap().as_raw(),
-                    &color.float32
-                );
+            let sub = subresource_range.borrow();
+            for layer in sub.layers.clone() {
+                for level in sub.levels.clone() {
+                    if sub.aspects.contains(format::Aspects::COLOR) {
+                        unsafe {
+                            self.context.ClearRenderTargetView(
+                                image.get_rtv(level, layer).unwrap().as_raw(),
+                                &color.float32
+                            );
+                        }
+                    }
+
+                    let mut depth_stencil_flags = 0;
+                    if sub.aspects.contains(format::Aspects::DEPTH) {
+                        depth_stencil_flags |= d3d11::D3D11_CLEAR_DEPTH;
+                    }
+                    if sub.aspects.contains(format::Aspects::STENCIL) {
+                        depth_stencil_flags |= d3d11::D3D11_CLEAR_STENCIL;
+                    }
+
+                    if depth_stencil_flags != 0 {
+                        unsafe {
+                            self.context.ClearDepthStencilView(
+                                image.get_dsv(level, layer).unwrap().as_raw(),
+                                depth_stencil_flags,
+                                depth_stencil.depth,
+                                depth_stencil.stencil as _
+                            );
+                        }
+                    }
+                }
             }
         }
     }
@@ -963,12 +1476,31 @@ impl hal::command::RawCommandBuffer<Backend> for CommandBuffer {
         unimplemented!()
     }
 
-    fn resolve_image<T>(&mut self, _src: &Image, _src_layout: image::Layout, _dst: &Image, _dst_layout: image::Layout, _regions: T)
+    fn resolve_image<T>(&mut self, src: &Image, _src_layout: image::Layout, dst: &Image, _dst_layout: image::Layout, regions: T)
     where
         T: IntoIterator,
         T::Item: Borrow<command::ImageResolve>,
     {
-        unimplemented!()
+        for region in regions {
+            let r = region.borrow();
+            // `ResolveSubresource` always resolves whole subresources.
+            if r.src_offset != image::Offset::ZERO || r.dst_offset != image::Offset::ZERO {
+                warn!("Resolve offsets are not supported, resolving the whole subresource");
+            }
+
+            let num_layers = r.src_subresource.layers.end - r.src_subresource.layers.start;
+            for layer in 0 .. num_layers {
+                unsafe {
+                    self.context.ResolveSubresource(
+                        dst.internal.raw,
+                        dst.calc_subresource(r.dst_subresource.level as _, (r.dst_subresource.layers.start + layer) as _),
+                        src.internal.raw,
+                        src.calc_subresource(r.src_subresource.level as _, (r.src_subresource.layers.start + layer) as _),
+                        dst.dxgi_format,
+                    );
+                }
+            }
+        }
     }
 
     fn blit_image<T>(&mut self, src: &Image, _src_layout: image::Layout, dst: &Image, _dst_layout: image::Layout, filter: image::Filter, regions: T)
@@ -977,6 +1509,9 @@ impl hal::command::RawCommandBuffer<Backend> for CommandBuffer {
         T::Item: Borrow<command::ImageBlit>
     {
         self.internal.blit_2d_image(&self.context, src, dst, filter, regions);
+        let hazards = self.hazards.get_mut();
+        hazards.clear_slot(pso::Stage::Fragment, 0);
+        hazards.clear_targets();
     }
 
     fn bind_index_buffer(&mut self, ibv: buffer::IndexBufferView<Backend>) {
@@ -1002,7 +1537,7 @@ impl hal::command::RawCommandBuffer<Backend> for CommandBuffer {
         for (i, (buf, offset)) in buffers.into_iter().enumerate() {
             let idx = i + first_binding as usize;
 
-            self.bound_bindings |= 1 << i as u32;
+            self.bound_bindings |= 1 << idx as u32;
 
             if idx >= self.vertex_buffers.len() {
                 self.vertex_buffers.push(buf.borrow().internal.raw);
@@ -1073,6 +1608,7 @@ impl hal::command::RawCommandBuffer<Backend> for CommandBuffer {
     }
 
     fn bind_graphics_pipeline(&mut self, pipeline: &GraphicsPipeline) {
+        self.statistics.pipeline_binds += 1;
         self.vertex_strides.clear();
         self.vertex_strides.extend(&pipeline.strides);
 
@@ -1086,6 +1622,13 @@ impl hal::command::RawCommandBuffer<Backend> for CommandBuffer {
             self.context.IASetInputLayout(pipeline.input_layout.as_raw());
 
             self.context.VSSetShader(pipeline.vs.as_raw(), ptr::null_mut(), 0);
+            // Unbind the optional stages of a previous pipeline, unlike pixel shaders they change the output.
+            let hs = pipeline.hs.as_ref().map_or(ptr::null_mut(), |hs| hs.as_raw());
+            self.context.HSSetShader(hs, ptr::null_mut(), 0);
+            let ds = pipeline.ds.as_ref().map_or(ptr::null_mut(), |ds| ds.as_raw());
+            self.context.DSSetShader(ds, ptr::null_mut(), 0);
+            let gs = pipeline.gs.as_ref().map_or(ptr::null_mut(), |gs| gs.as_raw());
+            self.context.GSSetShader(gs, ptr::null_mut(), 0);
             if let Some(ref ps) = pipeline.ps {
                 self.context.PSSetShader(ps.as_raw(), ptr::null_mut(), 0);
             }
@@ -1114,7 +1657,7 @@ impl hal::command::RawCommandBuffer<Backend> for CommandBuffer {
         }
     }
 
-    fn bind_graphics_descriptor_sets<'a, I, J>(&mut self, layout: &PipelineLayout, first_set: usize, sets: I, _offsets: J)
+    fn bind_graphics_descriptor_sets<'a, I, J>(&mut self, layout: &PipelineLayout, first_set: usize, sets: I, offsets: J)
     where
         I: IntoIterator,
         I::Item: Borrow<DescriptorSet>,
@@ -1123,31 +1666,31 @@ impl hal::command::RawCommandBuffer<Backend> for CommandBuffer {
     {
         // TODO: find a better solution to invalidating old bindings..
         unsafe {
-            self.context.CSSetUnorderedAccessViews(0, 16, [ptr::null_mut(); 16].as_ptr(), ptr::null_mut());
+            self.hazards.get_mut().set_unordered_access_views(&self.context, 0, &[ptr::null_mut(); 16]);
         }
 
-        //let offsets: Vec<command::DescriptorSetOffset> = offsets.into_iter().map(|o| *o.borrow()).collect();
-
+        let mut offsets = offsets.into_iter().map(|o| *o.borrow());
         let iter = sets.into_iter().zip(layout.set_bindings.iter().skip(first_set));
 
         for (set, bindings) in iter {
             let set = set.borrow();
+            let dynamic_offsets = self.dynamic_offsets(set, &mut offsets);
 
-            // TODO: offsets
             for binding in bindings.iter() {
-                self.bind_descriptor(&self.context, binding, set.handles);
+                self.bind_descriptor(&self.context, binding, set, &dynamic_offsets);
             }
         }
     }
 
     fn bind_compute_pipeline(&mut self, pipeline: &ComputePipeline) {
+        self.statistics.pipeline_binds += 1;
         unsafe {
             self.context.CSSetShader(pipeline.cs.as_raw(), ptr::null_mut(), 0);
         }
     }
 
 
-    fn bind_compute_descriptor_sets<I, J>(&mut self, layout: &PipelineLayout, first_set: usize, sets: I, _offsets: J)
+    fn bind_compute_descriptor_sets<I, J>(&mut self, layout: &PipelineLayout, first_set: usize, sets: I, offsets: J)
     where
         I: IntoIterator,
         I::Item: Borrow<DescriptorSet>,
@@ -1155,28 +1698,45 @@ impl hal::command::RawCommandBuffer<Backend> for CommandBuffer {
         J::Item: Borrow<command::DescriptorSetOffset>,
     {
         unsafe {
-            self.context.CSSetUnorderedAccessViews(0, 16, [ptr::null_mut(); 16].as_ptr(), ptr::null_mut());
+            self.hazards.get_mut().set_unordered_access_views(&self.context, 0, &[ptr::null_mut(); 16]);
         }
+        let mut offsets = offsets.into_iter().map(|o| *o.borrow());
         let iter = sets.into_iter().zip(layout.set_bindings.iter().skip(first_set));
 
         for (set, bindings) in iter {
             let set = set.borrow();
+            let dynamic_offsets = self.dynamic_offsets(set, &mut offsets);
 
-            // TODO: offsets
             for binding in bindings.iter() {
-                self.bind_descriptor(&self.context, binding, set.handles);
+                self.bind_descriptor(&self.context, binding, set, &dynamic_offsets);
             }
         }
     }
 
     fn dispatch(&mut self, count: WorkGroupCount) {
+        self.statistics.dispatches += 1;
         unsafe {
             self.context.Dispatch(count[0], count[1], count[2]);
         }
     }
 
-    fn dispatch_indirect(&mut self, _buffer: &Buffer, _offset: buffer::Offset) {
-        unimplemented!()
+    fn dispatch_base(&mut self, base: WorkGroupCount, count: WorkGroupCount) {
+        // D3D11 has no notion of a base workgroup, the dispatch is skipped.
+        if base != [0; 3] {
+            debug_assert!(false, "Non-zero dispatch base requires `Features::DISPATCH_BASE`");
+            return;
+        }
+        self.statistics.dispatches += 1;
+        unsafe {
+            self.context.Dispatch(count[0], count[1], count[2]);
+        }
+    }
+
+    fn dispatch_indirect(&mut self, buffer: &Buffer, offset: buffer::Offset) {
+        self.statistics.dispatches += 1;
+        unsafe {
+            self.context.DispatchIndirect(buffer.internal.raw, offset as _);
+        }
     }
 
     fn fill_buffer<R>(&mut self, _buffer: &Buffer, _range: R, _data: u32)
@@ -1226,6 +1786,7 @@ impl hal::command::RawCommandBuffer<Backend> for CommandBuffer {
         T::Item: Borrow<command::ImageCopy>,
     {
         self.internal.copy_image_2d(&self.context, src, dst, regions);
+        self.hazards.get_mut().clear_slot(pso::Stage::Compute, 0);
     }
 
     fn copy_buffer_to_image<T>(&mut self, buffer: &Buffer, image: &Image, _: image::Layout, regions: T)
@@ -1234,6 +1795,7 @@ impl hal::command::RawCommandBuffer<Backend> for CommandBuffer {
         T::Item: Borrow<command::BufferImageCopy>,
     {
         self.internal.copy_buffer_to_image_2d(&self.context, buffer, image, regions);
+        self.hazards.get_mut().clear_slot(pso::Stage::Compute, 0);
     }
 
     fn copy_image_to_buffer<T>(&mut self, image: &Image, _: image::Layout, buffer: &Buffer, regions: T)
@@ -1242,11 +1804,13 @@ impl hal::command::RawCommandBuffer<Backend> for CommandBuffer {
         T::Item: Borrow<command::BufferImageCopy>,
     {
         self.internal.copy_image_2d_to_buffer(&self.context, image, buffer, regions);
+        self.hazards.get_mut().clear_slot(pso::Stage::Compute, 0);
     }
 
     fn draw(&mut self, vertices: Range<VertexCount>, instances: Range<InstanceCount>) {
         debug_assert!((self.bound_bindings | self.required_bindings.unwrap_or(!0)) == self.bound_bindings);
 
+        self.statistics.draw_calls += 1;
         unsafe {
             self.context.DrawInstanced(
                 vertices.end - vertices.start,
@@ -1260,6 +1824,7 @@ impl hal::command::RawCommandBuffer<Backend> for CommandBuffer {
     fn draw_indexed(&mut self, indices: Range<IndexCount>, base_vertex: VertexOffset, instances: Range<InstanceCount>) {
         debug_assert!((self.bound_bindings | self.required_bindings.unwrap_or(!0)) == self.bound_bindings);
 
+        self.statistics.draw_calls += 1;
         unsafe {
             self.context.DrawIndexedInstanced(
                 indices.end - indices.start,
@@ -1271,44 +1836,102 @@ impl hal::command::RawCommandBuffer<Backend> for CommandBuffer {
         }
     }
 
-    fn draw_indirect(&mut self, _buffer: &Buffer, _offset: buffer::Offset, _draw_count: DrawCount, _stride: u32) {
-        unimplemented!()
+    fn draw_indirect(&mut self, buffer: &Buffer, offset: buffer::Offset, draw_count: DrawCount, stride: u32) {
+        debug_assert!((self.bound_bindings | self.required_bindings.unwrap_or(!0)) == self.bound_bindings);
+
+        // The argument layout matches `DrawInstancedIndirect`, but there is
+        // no multi-draw variant so each draw is issued separately.
+        self.statistics.draw_calls += draw_count as u64;
+        for i in 0 .. draw_count as u64 {
+            unsafe {
+                self.context.DrawInstancedIndirect(
+                    buffer.internal.raw,
+                    (offset + i * stride as u64) as _,
+                );
+            }
+        }
     }
 
-    fn draw_indexed_indirect(&mut self, _buffer: &Buffer, _offset: buffer::Offset, _draw_count: DrawCount, _stride: u32) {
-        unimplemented!()
+    fn draw_indexed_indirect(&mut self, buffer: &Buffer, offset: buffer::Offset, draw_count: DrawCount, stride: u32) {
+        debug_assert!((self.bound_bindings | self.required_bindings.unwrap_or(!0)) == self.bound_bindings);
+
+        self.statistics.draw_calls += draw_count as u64;
+        for i in 0 .. draw_count as u64 {
+            unsafe {
+                self.context.DrawIndexedInstancedIndirect(
+                    buffer.internal.raw,
+                    (offset + i * stride as u64) as _,
+                );
+            }
+        }
     }
 
-    fn begin_query(&mut self, _query: query::Query<Backend>, _flags: query::QueryControl) {
-        unimplemented!()
+    fn begin_query(&mut self, query: query::Query<Backend>, _flags: query::QueryControl) {
+        // D3D11 occlusion queries always count the exact number of samples.
+        unsafe { self.context.Begin(query.pool.get(query.id)); }
     }
 
-    fn end_query(&mut self, _query: query::Query<Backend>) {
-        unimplemented!()
+    fn end_query(&mut self, query: query::Query<Backend>) {
+        unsafe { self.context.End(query.pool.get(query.id)); }
     }
 
     fn reset_query_pool(&mut self, _pool: &QueryPool, _queries: Range<query::QueryId>) {
-        unimplemented!()
+        // Queries are implicitly reset by issuing them again.
     }
 
-    fn write_timestamp(&mut self, _: pso::PipelineStage, _query: query::Query<Backend>) {
-        unimplemented!()
+    fn write_timestamp(&mut self, _: pso::PipelineStage, query: query::Query<Backend>) {
+        unsafe { self.context.End(query.pool.get(query.id)); }
     }
 
-    fn push_graphics_constants(&mut self, _layout: &PipelineLayout, _stages: pso::ShaderStageFlags, _offset: u32, _constants: &[u32]) {
-        unimplemented!()
+    fn push_graphics_constants(&mut self, _layout: &PipelineLayout, stages: pso::ShaderStageFlags, offset: u32, constants: &[u32]) {
+        let offset = offset as usize;
+        self.push_constants[offset .. offset + constants.len()].copy_from_slice(constants);
+        let buffer = self.internal.update_push_constants(&self.context, &self.push_constants);
+        unsafe {
+            if stages.contains(pso::ShaderStageFlags::VERTEX) {
+                self.context.VSSetConstantBuffers(PUSH_CONSTANT_SLOT, 1, &buffer);
+            }
+            if stages.contains(pso::ShaderStageFlags::HULL) {
+                self.context.HSSetConstantBuffers(PUSH_CONSTANT_SLOT, 1, &buffer);
+            }
+            if stages.contains(pso::ShaderStageFlags::DOMAIN) {
+                self.context.DSSetConstantBuffers(PUSH_CONSTANT_SLOT, 1, &buffer);
+            }
+            if stages.contains(pso::ShaderStageFlags::GEOMETRY) {
+                self.context.GSSetConstantBuffers(PUSH_CONSTANT_SLOT, 1, &buffer);
+            }
+            if stages.contains(pso::ShaderStageFlags::FRAGMENT) {
+                self.context.PSSetConstantBuffers(PUSH_CONSTANT_SLOT, 1, &buffer);
+            }
+        }
     }
 
-    fn push_compute_constants(&mut self, _layout: &PipelineLayout, _offset: u32, _constants: &[u32]) {
-        unimplemented!()
+    fn push_compute_constants(&mut self, _layout: &PipelineLayout, offset: u32, constants: &[u32]) {
+        let offset = offset as usize;
+        self.push_constants[offset .. offset + constants.len()].copy_from_slice(constants);
+        let buffer = self.internal.update_push_constants(&self.context, &self.push_constants);
+        unsafe {
+            self.context.CSSetConstantBuffers(PUSH_CONSTANT_SLOT, 1, &buffer);
+        }
     }
 
-    fn execute_commands<I>(&mut self, _buffers: I)
+    fn execute_commands<I>(&mut self, buffers: I)
     where
         I: IntoIterator,
         I::Item: Borrow<CommandBuffer>,
     {
-        unimplemented!()
+        for buffer in buffers {
+            let buffer = buffer.borrow();
+            self.statistics.add(&buffer.statistics);
+            unsafe {
+                self.context.ExecuteCommandList(buffer.as_raw_list().as_raw(), FALSE);
+            }
+        }
+
+        // Not restoring the context state clears it, like the state of a primary
+        // command buffer becomes undefined after executing secondary ones.
+        *self.hazards.get_mut() = hazard::HazardTracker::default();
+        self.bound_bindings = 0;
     }
 }
 
@@ -1471,6 +2094,7 @@ impl Memory {
 pub struct CommandPool {
     device: ComPtr<d3d11::ID3D11Device>,
     internal: internal::Internal,
+    constant_buffer_offsetting: bool,
 }
 
 unsafe impl Send for CommandPool {}
@@ -1483,7 +2107,11 @@ impl hal::pool::RawCommandPool<Backend> for CommandPool {
 
     fn allocate(&mut self, num: usize, _level: command::RawLevel) -> Vec<CommandBuffer> {
         (0..num)
-            .map(|_| CommandBuffer::create_deferred(self.device.clone(), self.internal.clone()))
+            .map(|_| CommandBuffer::create_deferred(
+                self.device.clone(),
+                self.internal.clone(),
+                self.constant_buffer_offsetting,
+            ))
             .collect()
     }
 
@@ -1499,7 +2127,7 @@ impl hal::pool::RawCommandPool<Backend> for CommandPool {
 //#[derivative(Debug)]
 pub enum ShaderModule {
     Dxbc(Vec<u8>),
-    Spirv(Vec<u8>)
+    Spirv(shader::SpirvModule)
 }
 
 // TODO: temporary
@@ -1512,8 +2140,26 @@ impl ::std::fmt::Debug for ShaderModule {
 unsafe impl Send for ShaderModule { }
 unsafe impl Sync for ShaderModule { }
 
+// Attachments written by a subpass, input attachments are bound through descriptor sets.
+#[derive(Clone, Debug)]
+struct SubpassDesc {
+    color_attachments: Vec<pass::AttachmentId>,
+    depth_stencil_attachment: Option<pass::AttachmentId>,
+    // Color attachments paired with the attachments they get resolved into.
+    resolves: Vec<(pass::AttachmentId, pass::AttachmentId)>,
+}
+
 #[derive(Debug)]
-pub struct RenderPass;
+pub struct RenderPass {
+    subpasses: Vec<SubpassDesc>,
+}
+
+// Render pass state of a command buffer between `begin_render_pass` and `end_render_pass`.
+struct RenderPassCache {
+    subpasses: Vec<SubpassDesc>,
+    attachments: Vec<ImageView>,
+    current: usize,
+}
 #[derive(Debug)]
 pub struct Framebuffer {
     attachments: Vec<ImageView>,
@@ -1540,13 +2186,26 @@ pub struct InternalBuffer {
 pub struct Buffer {
     internal: InternalBuffer,
     size: u64,
+    // Views of byte ranges of the buffer, bound by storage buffer descriptors
+    // which don't cover the whole buffer.
+    #[derivative(Debug="ignore")]
+    range_uavs: Mutex<FastHashMap<(u64, u64), ComPtr<d3d11::ID3D11UnorderedAccessView>>>,
 }
 
 unsafe impl Send for Buffer {}
 unsafe impl Sync for Buffer {}
 
-#[derive(Debug)]
-pub struct BufferView;
+#[derive(Derivative)]
+#[derivative(Debug)]
+pub struct BufferView {
+    #[derivative(Debug="ignore")]
+    srv_handle: Option<ComPtr<d3d11::ID3D11ShaderResourceView>>,
+    #[derivative(Debug="ignore")]
+    uav_handle: Option<ComPtr<d3d11::ID3D11UnorderedAccessView>>,
+}
+
+unsafe impl Send for BufferView { }
+unsafe impl Sync for BufferView { }
 #[derive(Debug)]
 pub struct UnboundImage {
     kind: image::Kind,
@@ -1591,6 +2250,9 @@ pub struct InternalImage {
     /// Contains RTVs for all subresources
     #[derivative(Debug="ignore")]
     render_target_views: Vec<ComPtr<d3d11::ID3D11RenderTargetView>>,
+    /// Contains DSVs for all subresources
+    #[derivative(Debug="ignore")]
+    depth_stencil_views: Vec<ComPtr<d3d11::ID3D11DepthStencilView>>,
 }
 
 unsafe impl Send for Image { }
@@ -1608,11 +2270,20 @@ impl Image {
     pub fn get_rtv(&self, mip_level: image::Level, layer: image::Layer) -> Option<&ComPtr<d3d11::ID3D11RenderTargetView>> {
         self.internal.render_target_views.get(self.calc_subresource(mip_level as _, layer as _) as usize)
     }
+
+    pub fn get_dsv(&self, mip_level: image::Level, layer: image::Layer) -> Option<&ComPtr<d3d11::ID3D11DepthStencilView>> {
+        self.internal.depth_stencil_views.get(self.calc_subresource(mip_level as _, layer as _) as usize)
+    }
 }
 
 #[derive(Derivative, Clone)]
 #[derivative(Debug)]
 pub struct ImageView {
+    // Viewed resource and its first subresource, required for resolves.
+    #[derivative(Debug="ignore")]
+    resource: *mut d3d11::ID3D11Resource,
+    subresource: UINT,
+    format: dxgiformat::DXGI_FORMAT,
     #[derivative(Debug="ignore")]
     rtv_handle: Option<ComPtr<d3d11::ID3D11RenderTargetView>>,
     #[derivative(Debug="ignore")]
@@ -1654,10 +2325,15 @@ unsafe impl Sync for ComputePipeline { }
 #[derive(Derivative)]
 #[derivative(Debug)]
 pub struct GraphicsPipeline {
-    // TODO: gs, hs, ds
     #[derivative(Debug="ignore")]
     vs: ComPtr<d3d11::ID3D11VertexShader>,
     #[derivative(Debug="ignore")]
+    hs: Option<ComPtr<d3d11::ID3D11HullShader>>,
+    #[derivative(Debug="ignore")]
+    ds: Option<ComPtr<d3d11::ID3D11DomainShader>>,
+    #[derivative(Debug="ignore")]
+    gs: Option<ComPtr<d3d11::ID3D11GeometryShader>>,
+    #[derivative(Debug="ignore")]
     ps: Option<ComPtr<d3d11::ID3D11PixelShader>>,
     #[derivative(Debug="ignore")]
     topology: d3d11::D3D11_PRIMITIVE_TOPOLOGY,
@@ -1690,7 +2366,22 @@ struct PipelineBinding {
 /// belonging to the pipeline object.
 #[derive(Debug)]
 pub struct PipelineLayout {
-    set_bindings: Vec<Vec<PipelineBinding>>
+    set_bindings: Vec<Vec<PipelineBinding>>,
+    /// Push constant ranges, in 32-bit words.
+    push_constants: Vec<(pso::ShaderStageFlags, Range<u32>)>,
+}
+
+impl PipelineLayout {
+    /// Size in bytes of the push constants visible to `stage`, which are emulated
+    /// with a constant buffer bound to `PUSH_CONSTANT_SLOT`.
+    pub(crate) fn push_constant_size(&self, stage: pso::ShaderStageFlags) -> u32 {
+        self.push_constants
+            .iter()
+            .filter(|&&(stages, _)| stages.contains(stage))
+            .map(|&(_, ref range)| range.end * 4)
+            .max()
+            .unwrap_or(0)
+    }
 }
 
 /// The descriptor set layout contains mappings from a given binding to the offset in our
@@ -1708,12 +2399,27 @@ pub struct DescriptorSetLayout {
 #[repr(C)]
 struct Descriptor(*mut d3d11::ID3D11DeviceChild);
 
+/// Bound range of a constant buffer descriptor, in constants of 16 bytes.
+#[derive(Debug, Copy, Clone)]
+struct ConstantRange {
+    first: UINT,
+    count: UINT,
+}
+
+// Whole buffer, limited to the maximum size of a constant buffer.
+const WHOLE_CONSTANT_RANGE: ConstantRange = ConstantRange {
+    first: 0,
+    count: d3d11::D3D11_REQ_CONSTANT_BUFFER_ELEMENT_COUNT,
+};
+
 #[derive(Derivative)]
 #[derivative(Debug)]
 pub struct DescriptorSet {
     offset: usize,
     len: usize,
     handles: *mut Descriptor,
+    // Ranges of constant buffer descriptors, parallel to `handles`.
+    constant_ranges: *mut ConstantRange,
     offset_mapping: Vec<(u32, pso::DescriptorType)>,
 }
 
@@ -1723,6 +2429,7 @@ unsafe impl Sync for DescriptorSet {}
 #[derive(Debug)]
 pub struct DescriptorPool {
     handles: Vec<Descriptor>,
+    constant_ranges: Vec<ConstantRange>,
     allocator: RangeAllocator<usize>
 }
 
@@ -1733,6 +2440,7 @@ impl DescriptorPool {
     pub fn with_capacity(size: usize) -> Self {
         DescriptorPool {
             handles: vec![Descriptor(ptr::null_mut()); size],
+            constant_ranges: vec![WHOLE_CONSTANT_RANGE; size],
             allocator: RangeAllocator::new(0..size)
         }
     }
@@ -1749,6 +2457,7 @@ impl hal::DescriptorPool<Backend> for DescriptorPool {
                 offset: range.start,
                 len,
                 handles: unsafe { self.handles.as_mut_ptr().offset(range.start as _) },
+                constant_ranges: unsafe { self.constant_ranges.as_mut_ptr().offset(range.start as _) },
                 offset_mapping: layout.offset_mapping.clone(),
             }
         }).map_err(|_| pso::AllocationError::OutOfPoolMemory)
@@ -1772,8 +2481,24 @@ impl hal::DescriptorPool<Backend> for DescriptorPool {
 pub struct Fence;
 #[derive(Debug)]
 pub struct Semaphore;
-#[derive(Debug)]
-pub struct QueryPool;
+#[derive(Derivative)]
+#[derivative(Debug)]
+pub struct QueryPool {
+    #[derivative(Debug="ignore")]
+    queries: Vec<ComPtr<d3d11::ID3D11Query>>,
+    ty: query::QueryType,
+    // Timestamp ticks per second, zero for other query types.
+    frequency: u64,
+}
+
+unsafe impl Send for QueryPool {}
+unsafe impl Sync for QueryPool {}
+
+impl QueryPool {
+    fn get(&self, id: query::QueryId) -> *mut d3d11::ID3D11Asynchronous {
+        self.queries[id as usize].as_raw() as *mut _
+    }
+}
 
 #[derive(Copy, Clone, Debug, Eq, Hash, PartialEq)]
 pub enum Backend {}
@@ -1807,12 +2532,15 @@ impl hal::Backend for Backend {
     type ComputePipeline = ComputePipeline;
     type GraphicsPipeline = GraphicsPipeline;
     type PipelineLayout = PipelineLayout;
+    type PipelineCache = ();
     type DescriptorSetLayout = DescriptorSetLayout;
     type DescriptorPool = DescriptorPool;
     type DescriptorSet = DescriptorSet;
 
     type Fence = Fence;
     type Semaphore = Semaphore;
+    type TimelineSemaphore = HostTimeline;
+    type Event = ();
     type QueryPool = QueryPool;
 }
 
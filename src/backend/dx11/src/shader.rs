@@ -1,4 +1,5 @@
 use std::{ffi, mem, ptr, slice};
+use std::sync::Mutex;
 
 use spirv_cross::{hlsl, spirv, ErrorCode as SpirvErrorCode};
 
@@ -6,9 +7,10 @@ use winapi::um::{d3dcommon, d3dcompiler};
 use winapi::shared::{winerror};
 use wio::com::ComPtr;
 
-use hal::{device, pso};
+use hal::{self, device, pso};
+use hal::backend::FastHashMap;
 
-use {conv, Backend, PipelineLayout};
+use {conv, Backend, PipelineLayout, PUSH_CONSTANT_SLOT};
 
 
 /// Emit error during shader module creation. Used if we don't expect an error
@@ -30,14 +32,133 @@ fn gen_query_error(err: SpirvErrorCode) -> device::ShaderError {
     device::ShaderError::CompilationFailed(msg)
 }
 
+// Name the resources left unnamed by the SPIR-V after their block type, binding or
+// location, so the generated code is debuggable in captures instead of using `_34`
+// identifiers. Names given by the SPIR-V are kept.
+fn name_shader_resources(ast: &mut spirv::Ast<hlsl::Target>) -> Result<(), device::ShaderError> {
+    let resources = ast.get_shader_resources().map_err(gen_query_error)?;
+    let is_unnamed = |ast: &mut spirv::Ast<hlsl::Target>, id| {
+        ast.get_name(id).map(|name| name.is_empty()).map_err(gen_query_error)
+    };
+    let decoration = |ast: &mut spirv::Ast<hlsl::Target>, id, kind| {
+        ast.get_decoration(id, kind).map_err(gen_query_error)
+    };
+
+    let blocks = resources.uniform_buffers
+        .iter()
+        .chain(&resources.storage_buffers)
+        .map(|resource| (resource, false))
+        .chain(resources.push_constant_buffers.iter().map(|resource| (resource, true)));
+    for (resource, push_constants) in blocks {
+        let binding = if push_constants {
+            "push_constants".to_owned()
+        } else {
+            format!(
+                "set{}_binding{}",
+                decoration(ast, resource.id, spirv::Decoration::DescriptorSet)?,
+                decoration(ast, resource.id, spirv::Decoration::Binding)?,
+            )
+        };
+        let type_name = ast.get_name(resource.base_type_id).map_err(gen_query_error)?;
+        if type_name.is_empty() {
+            ast.set_name(resource.base_type_id, &format!("{}_block", binding))
+                .map_err(gen_unexpected_error)?;
+        }
+        if is_unnamed(ast, resource.id)? {
+            let name = if type_name.is_empty() { binding } else { format!("{}_var", type_name) };
+            ast.set_name(resource.id, &name).map_err(gen_unexpected_error)?;
+        }
+    }
+
+    let bindings = resources.separate_images
+        .iter()
+        .chain(&resources.separate_samplers)
+        .chain(&resources.sampled_images)
+        .chain(&resources.storage_images)
+        .chain(&resources.subpass_inputs);
+    for resource in bindings {
+        if is_unnamed(ast, resource.id)? {
+            let name = format!(
+                "set{}_binding{}",
+                decoration(ast, resource.id, spirv::Decoration::DescriptorSet)?,
+                decoration(ast, resource.id, spirv::Decoration::Binding)?,
+            );
+            ast.set_name(resource.id, &name).map_err(gen_unexpected_error)?;
+        }
+    }
+
+    let interface = resources.stage_inputs
+        .iter()
+        .map(|resource| (resource, "in"))
+        .chain(resources.stage_outputs.iter().map(|resource| (resource, "out")));
+    for (resource, direction) in interface {
+        if is_unnamed(ast, resource.id)? {
+            let location = decoration(ast, resource.id, spirv::Decoration::Location)?;
+            ast.set_name(resource.id, &format!("{}_location{}", direction, location))
+                .map_err(gen_unexpected_error)?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Entry point name, stage, specialization constants (id and value bits)
+/// and size of the push constants visible to the stage.
+type EntryPointKey = (String, pso::Stage, Vec<(u32, u64)>, u32);
+
+/// A SPIR-V shader module, reflected once on creation.
+///
+/// Compiled entry points are cached, so pipelines sharing a module only
+/// translate and compile each specialized entry point once.
+pub struct SpirvModule {
+    raw_data: Vec<u8>,
+    info: pso::ShaderInfo,
+    compiled: Mutex<FastHashMap<EntryPointKey, ComPtr<d3dcommon::ID3DBlob>>>,
+}
+
+impl SpirvModule {
+    pub(crate) fn new(raw_data: &[u8]) -> Result<Self, device::ShaderError> {
+        Ok(SpirvModule {
+            raw_data: raw_data.to_vec(),
+            info: pso::reflect_spirv(raw_data)?,
+            compiled: Mutex::new(FastHashMap::default()),
+        })
+    }
+
+    pub(crate) fn required_features(&self) -> hal::Features {
+        self.info.required_features
+    }
+}
+
 pub(crate) fn compile_spirv_entrypoint(
-    raw_data: &[u8],
+    module: &SpirvModule,
     stage: pso::Stage,
     source: &pso::EntryPoint<Backend>,
     layout: &PipelineLayout,
 ) -> Result<Option<ComPtr<d3dcommon::ID3DBlob>>, device::ShaderError> {
+    let has_entry_point = module.info.entry_points
+        .iter()
+        .any(|ep| ep.name == source.entry && ep.stage == Some(stage));
+    if !has_entry_point {
+        return Err(device::ShaderError::MissingEntryPoint(source.entry.into()));
+    }
 
-    let mut ast = parse_spirv(raw_data)?;
+    // The pipeline layout only affects the translation through the push constants.
+    // Register spaces aren't available in shader model 5.0, so descriptor sets aren't remapped.
+    let key = (
+        source.entry.to_string(),
+        stage,
+        source.specialization
+            .iter()
+            .map(|c| (c.id, c.value.to_bits()))
+            .collect::<Vec<_>>(),
+        layout.push_constant_size(stage.into()),
+    );
+    if let Some(shader) = module.compiled.lock().unwrap().get(&key) {
+        return Ok(Some(shader.clone()));
+    }
+
+    let mut ast = parse_spirv(&module.raw_data)?;
     let spec_constants = ast
         .get_specialization_constants()
         .map_err(gen_query_error)?;
@@ -49,45 +170,29 @@ pub(crate) fn compile_spirv_entrypoint(
             .find(|c| c.id == spec_constant.constant_id)
         {
             // Override specialization constant values
-            unsafe {
-                let value = match constant.value {
-                    pso::Constant::Bool(v) => v as u64,
-                    pso::Constant::U32(v) => v as u64,
-                    pso::Constant::U64(v) => v,
-                    pso::Constant::I32(v) => *(&v as *const _ as *const u64),
-                    pso::Constant::I64(v) => *(&v as *const _ as *const u64),
-                    pso::Constant::F32(v) => *(&v as *const _ as *const u64),
-                    pso::Constant::F64(v) => *(&v as *const _ as *const u64),
-                };
-                ast.set_scalar_constant(spec_constant.id, value).map_err(gen_query_error)?;
-            }
+            ast.set_scalar_constant(spec_constant.id, constant.value.to_bits())
+                .map_err(gen_query_error)?;
         }
     }
 
-    patch_spirv_resources(&mut ast, Some(layout))?;
+    // D3D11 only consumes shader model 5.0 bytecode, later models require the D3D12 backend.
     let shader_model = hlsl::ShaderModel::V5_0;
-    let shader_code = translate_spirv(&mut ast, shader_model, layout, stage)?;
+    let shader_code = translate_spirv(&mut ast, shader_model, key.3)?;
 
     let real_name = ast
         .get_cleansed_entry_point_name(source.entry, conv::map_stage(stage))
         .map_err(gen_query_error)?;
 
-    // TODO: opt: don't query *all* entry points.
-    let entry_points = ast.get_entry_points().map_err(gen_query_error)?;
-    entry_points
-        .iter()
-        .find(|entry_point| entry_point.name == real_name)
-        .ok_or(device::ShaderError::MissingEntryPoint(source.entry.into()))
-        .and_then(|entry_point| {
-            let stage = conv::map_execution_model(entry_point.execution_model);
-            let shader = compile_hlsl_shader(
-                stage,
-                shader_model,
-                &entry_point.name,
-                shader_code.as_bytes(),
-            )?;
-            Ok(Some(unsafe { ComPtr::from_raw(shader) }))
-        })
+    let shader = compile_hlsl_shader(
+        stage,
+        shader_model,
+        &real_name,
+        shader_code.as_bytes(),
+    )?;
+    let shader = unsafe { ComPtr::from_raw(shader) };
+
+    module.compiled.lock().unwrap().insert(key, shader.clone());
+    Ok(Some(shader))
 }
 
 pub(crate) fn compile_hlsl_shader(
@@ -99,9 +204,11 @@ pub(crate) fn compile_hlsl_shader(
     let stage_to_str = |stage, shader_model| {
         let stage = match stage {
             pso::Stage::Vertex => "vs",
+            pso::Stage::Hull => "hs",
+            pso::Stage::Domain => "ds",
+            pso::Stage::Geometry => "gs",
             pso::Stage::Fragment => "ps",
             pso::Stage::Compute => "cs",
-            _ => unimplemented!(),
         };
 
         let model = match shader_model {
@@ -171,90 +278,34 @@ fn parse_spirv(raw_data: &[u8]) -> Result<spirv::Ast<hlsl::Target>, device::Shad
         })
 }
 
-fn patch_spirv_resources(
-    ast: &mut spirv::Ast<hlsl::Target>,
-    _layout: Option<&PipelineLayout>,
-) -> Result<(), device::ShaderError> {
-    // Patch descriptor sets due to the splitting of descriptor heaps into
-    // SrvCbvUav and sampler heap. Each set will have a new location to match
-    // the layout of the root signatures.
-
-    // TODO:
-    let space_offset = 1;
-
-    let shader_resources = ast.get_shader_resources().map_err(gen_query_error)?;
-    for image in &shader_resources.separate_images {
-        let set = ast.get_decoration(image.id, spirv::Decoration::DescriptorSet).map_err(gen_query_error)?;
-        ast.set_decoration(image.id, spirv::Decoration::DescriptorSet, space_offset + set)
-           .map_err(gen_unexpected_error)?;
-    }
-
-    for uniform_buffer in &shader_resources.uniform_buffers {
-        let set = ast.get_decoration(uniform_buffer.id, spirv::Decoration::DescriptorSet).map_err(gen_query_error)?;
-        ast.set_decoration(uniform_buffer.id, spirv::Decoration::DescriptorSet, space_offset + set)
-           .map_err(gen_unexpected_error)?;
-    }
-
-    for storage_buffer in &shader_resources.storage_buffers {
-        let set = ast.get_decoration(storage_buffer.id, spirv::Decoration::DescriptorSet).map_err(gen_query_error)?;
-        ast.set_decoration(storage_buffer.id, spirv::Decoration::DescriptorSet, space_offset + set)
-           .map_err(gen_unexpected_error)?;
-    }
-
-    for image in &shader_resources.storage_images {
-        let set = ast.get_decoration(image.id, spirv::Decoration::DescriptorSet).map_err(gen_query_error)?;
-        ast.set_decoration(image.id, spirv::Decoration::DescriptorSet, space_offset + set)
-           .map_err(gen_unexpected_error)?;
-    }
-
-    for sampler in &shader_resources.separate_samplers {
-        let set = ast.get_decoration(sampler.id, spirv::Decoration::DescriptorSet).map_err(gen_query_error)?;
-        ast.set_decoration(sampler.id, spirv::Decoration::DescriptorSet, space_offset + set)
-           .map_err(gen_unexpected_error)?;
-    }
-
-    for image in &shader_resources.sampled_images {
-        let set = ast.get_decoration(image.id, spirv::Decoration::DescriptorSet).map_err(gen_query_error)?;
-        ast.set_decoration(image.id, spirv::Decoration::DescriptorSet, space_offset + set)
-           .map_err(gen_unexpected_error)?;
-    }
-
-    // tODO: other resources
-
-    Ok(())
-}
-
 fn translate_spirv(
     ast: &mut spirv::Ast<hlsl::Target>,
     shader_model: hlsl::ShaderModel,
-    _layout: &PipelineLayout,
-    _stage: pso::Stage,
+    push_constant_size: u32,
 ) -> Result<String, device::ShaderError> {
     let mut compile_options = hlsl::CompilerOptions::default();
     compile_options.shader_model = shader_model;
     compile_options.vertex.invert_y = true;
 
-    //let stage_flag = stage.into();
-    
-    // TODO:
-    /*let root_constant_layout = layout
-        .root_constants
-        .iter()
-        .filter_map(|constant| if constant.stages.contains(stage_flag) {
-            Some(hlsl::RootConstant {
-                start: constant.range.start * 4,
-                end: constant.range.end * 4,
-                binding: constant.range.start,
-                space: 0,
-            })
-        } else {
-            None
-        })
-        .collect();*/
+    // Push constants are emulated with a single constant buffer, laid out
+    // like the push constant block.
+    let root_constant_layout = if push_constant_size > 0 {
+        vec![hlsl::RootConstant {
+            start: 0,
+            end: push_constant_size,
+            binding: PUSH_CONSTANT_SLOT,
+            space: 0,
+        }]
+    } else {
+        Vec::new()
+    };
+    if cfg!(debug_assertions) {
+        name_shader_resources(ast)?;
+    }
     ast.set_compiler_options(&compile_options)
         .map_err(gen_unexpected_error)?;
-    //ast.set_root_constant_layout(root_constant_layout)
-    //    .map_err(gen_unexpected_error)?;
+    ast.set_root_constant_layout(root_constant_layout)
+        .map_err(gen_unexpected_error)?;
     ast.compile()
         .map_err(|err| {
             let msg = match err {
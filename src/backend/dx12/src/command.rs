@@ -3,12 +3,13 @@ use hal::{buffer, command as com, format, image, memory, pass, pso, query};
 use hal::{DrawCount, IndexCount, IndexType, InstanceCount, VertexCount, VertexOffset, WorkGroupCount};
 use hal::backend::FastHashMap;
 use hal::format::Aspects;
+use hal::profiler::FrameStatistics;
 use hal::range::RangeArg;
 
 use std::{cmp, iter, mem, ptr};
 use std::borrow::Borrow;
 use std::ops::Range;
-use std::sync::Arc;
+use std::sync::{Arc, Mutex};
 
 use winapi::Interface;
 use winapi::um::{d3d12, d3dcommon};
@@ -17,7 +18,7 @@ use winapi::shared::{dxgiformat, winerror};
 
 use wio::com::ComPtr;
 
-use {conv, device, descriptors_cpu, internal, native as n, Backend, Device, Shared, MAX_VERTEX_BUFFERS, validate_line_width};
+use {conv, device, descriptors_cpu, descriptors_gpu, internal, native as n, Backend, Device, Shared, MAX_VERTEX_BUFFERS, validate_line_width};
 use device::ViewInfo;
 use root_constants::RootConstant;
 use smallvec::SmallVec;
@@ -156,14 +157,14 @@ struct PipelineCache {
 }
 
 impl PipelineCache {
-    fn new() -> Self {
+    fn new(shared: &Shared) -> Self {
         PipelineCache {
             pipeline: None,
             num_parameter_slots: 0,
             root_constants: Vec::new(),
             user_data: UserData::new(),
-            srv_cbv_uav_start: 0,
-            sampler_start: 0,
+            srv_cbv_uav_start: shared.heap_srv_cbv_uav.start.gpu.ptr,
+            sampler_start: shared.heap_sampler.start.gpu.ptr,
         }
     }
 
@@ -173,8 +174,7 @@ impl PipelineCache {
         first_set: usize,
         sets: I,
         offsets: J,
-    ) -> [*mut d3d12::ID3D12DescriptorHeap; 2]
-    where
+    ) where
         I: IntoIterator,
         I::Item: Borrow<n::DescriptorSet>,
         J: IntoIterator,
@@ -182,21 +182,9 @@ impl PipelineCache {
     {
         assert!(offsets.into_iter().next().is_none()); //TODO
 
-        let mut sets = sets.into_iter().peekable();
-        let (
-            srv_cbv_uav_start, sampler_start,
-            heap_srv_cbv_uav, heap_sampler,
-        ) = if let Some(set_0) = sets.peek().map(Borrow::borrow) {
-            (
-                set_0.srv_cbv_uav_gpu_start().ptr, set_0.sampler_gpu_start().ptr,
-                set_0.heap_srv_cbv_uav.as_raw(), set_0.heap_samplers.as_raw(),
-            )
-        } else {
-            return [ptr::null_mut(); 2];
-        };
-
-        self.srv_cbv_uav_start = srv_cbv_uav_start;
-        self.sampler_start = sampler_start;
+        // All descriptor sets are allocated from the shader visible heaps of the device.
+        let srv_cbv_uav_start = self.srv_cbv_uav_start;
+        let sampler_start = self.sampler_start;
 
         let mut table_id = 0;
         for table in &layout.tables[..first_set] {
@@ -213,7 +201,7 @@ impl PipelineCache {
             .iter()
             .fold(0, |sum, c| sum + c.range.end - c.range.start);
 
-        for (set, table) in sets.zip(layout.tables[first_set..].iter()) {
+        for (set, table) in sets.into_iter().zip(layout.tables[first_set..].iter()) {
             let set = set.borrow();
             set.first_gpu_view.map(|gpu| {
                 assert!(table.contains(n::SRV_CBV_UAV));
@@ -242,8 +230,6 @@ impl PipelineCache {
                 table_id += 1;
             });
         }
-
-        [heap_srv_cbv_uav, heap_sampler]
     }
 }
 
@@ -288,9 +274,10 @@ pub struct CommandBuffer {
     // D3D12 only has one slot for both bindpoints. Need to rebind everything if we want to switch
     // between different bind points (ie. calling draw or dispatch).
     active_bindpoint: BindPoint,
-    // Current descriptor heaps heaps (CBV/SRV/UAV and Sampler).
-    // Required for resetting due to internal descriptor heaps.
-    active_descriptor_heaps: [*mut d3d12::ID3D12DescriptorHeap; 2],
+    // Transient descriptors for internal operations, allocated from the
+    // shader visible CBV/SRV/UAV heap.
+    // Shared between clones as they record into the same command list.
+    transient_views: Arc<Mutex<descriptors_gpu::TransientDescriptors>>,
 
     // Active queries in the command buffer.
     // Queries must begin and end in the same command buffer, which allows us to track them.
@@ -316,10 +303,10 @@ pub struct CommandBuffer {
 
     // HACK: renderdoc workaround for temporary RTVs
     rtv_pools: Vec<ComPtr<d3d12::ID3D12DescriptorHeap>>,
-    // Temporary gpu descriptor heaps (internal).
-    temporary_gpu_heaps: Vec<ComPtr<d3d12::ID3D12DescriptorHeap>>,
     // Resources that need to be alive till the end of the GPU execution.
     retained_resources: Vec<ComPtr<d3d12::ID3D12Resource>>,
+    // Commands recorded since the last reset, accumulated by the queue on submission.
+    pub(crate) statistics: FrameStatistics,
 }
 
 unsafe impl Send for CommandBuffer { }
@@ -339,17 +326,20 @@ impl CommandBuffer {
         allocator: ComPtr<d3d12::ID3D12CommandAllocator>,
         shared: Arc<Shared>,
     ) -> Self {
+        let transient_views = descriptors_gpu::TransientDescriptors::new(
+            shared.heap_srv_cbv_uav.clone(),
+        );
+
         CommandBuffer {
             raw,
             allocator,
-            shared,
             pass_cache: None,
             cur_subpass: !0,
-            gr_pipeline: PipelineCache::new(),
+            gr_pipeline: PipelineCache::new(&shared),
             primitive_topology: d3dcommon::D3D_PRIMITIVE_TOPOLOGY_UNDEFINED,
-            comp_pipeline: PipelineCache::new(),
+            comp_pipeline: PipelineCache::new(&shared),
             active_bindpoint: BindPoint::Graphics { internal: false },
-            active_descriptor_heaps: [ptr::null_mut(); 2],
+            transient_views: Arc::new(Mutex::new(transient_views)),
             occlusion_query: None,
             pipeline_stats_query: None,
             vertex_bindings_remap: [None; MAX_VERTEX_BUFFERS],
@@ -358,8 +348,9 @@ impl CommandBuffer {
             viewport_cache: SmallVec::new(),
             scissor_cache: SmallVec::new(),
             rtv_pools: Vec::new(),
-            temporary_gpu_heaps: Vec::new(),
             retained_resources: Vec::new(),
+            statistics: FrameStatistics::default(),
+            shared,
         }
     }
 
@@ -371,18 +362,50 @@ impl CommandBuffer {
         unsafe { self.raw.Reset(self.allocator.as_raw(), ptr::null_mut()); }
         self.pass_cache = None;
         self.cur_subpass = !0;
-        self.gr_pipeline = PipelineCache::new();
+        self.gr_pipeline = PipelineCache::new(&self.shared);
         self.primitive_topology = d3dcommon::D3D_PRIMITIVE_TOPOLOGY_UNDEFINED;
-        self.comp_pipeline = PipelineCache::new();
+        self.comp_pipeline = PipelineCache::new(&self.shared);
         self.active_bindpoint = BindPoint::Graphics { internal: false };
-        self.active_descriptor_heaps = [ptr::null_mut(); 2];
+        self.transient_views.lock().unwrap().reset();
         self.occlusion_query = None;
         self.pipeline_stats_query = None;
         self.vertex_bindings_remap = [None; MAX_VERTEX_BUFFERS];
         self.vertex_buffer_views = [NULL_VERTEX_BUFFER_VIEW; MAX_VERTEX_BUFFERS];
         self.rtv_pools.clear();
-        self.temporary_gpu_heaps.clear();
         self.retained_resources.clear();
+        self.statistics = FrameStatistics::default();
+
+        // The descriptor heaps are shared by all descriptor sets, so they only need
+        // to be bound once per command list. Copy lists can't bind descriptor heaps.
+        if unsafe { self.raw.GetType() } != d3d12::D3D12_COMMAND_LIST_TYPE_COPY {
+            let mut heaps = [
+                self.shared.heap_srv_cbv_uav.raw.as_raw(),
+                self.shared.heap_sampler.raw.as_raw(),
+            ];
+            unsafe { self.raw.SetDescriptorHeaps(2, heaps.as_mut_ptr()); }
+        }
+    }
+
+    // Resolve a finished query into the readback buffer of its pool and
+    // mark it as available.
+    fn resolve_query(&self, pool: &n::QueryPool, query_ty: d3d12::D3D12_QUERY_TYPE, id: query::QueryId) {
+        unsafe {
+            self.raw.ResolveQueryData(
+                pool.raw.as_raw(),
+                query_ty,
+                id,
+                1,
+                pool.resolve.as_raw(),
+                pool.stride * id as u64,
+            );
+            self.raw.CopyBufferRegion(
+                pool.resolve.as_raw(),
+                pool.availability_offset(id),
+                pool.availability.as_raw(),
+                8 * pool.count as u64,
+                8,
+            );
+        }
     }
 
     // Indicates that the pipeline slot has been overriden with an internal pipeline.
@@ -393,10 +416,6 @@ impl CommandBuffer {
         self.gr_pipeline.user_data.dirty_all();
     }
 
-    fn bind_descriptor_heaps(&mut self) {
-        unsafe { self.raw.SetDescriptorHeaps(2, self.active_descriptor_heaps.as_mut_ptr()); }
-    }
-
     fn insert_subpass_barriers(&self, insertion: BarrierPoint) {
         let state = self.pass_cache.as_ref().unwrap();
         let proto_barriers =  match state.render_pass.subpasses.get(self.cur_subpass) {
@@ -409,21 +428,29 @@ impl CommandBuffer {
 
         let transition_barriers = proto_barriers
             .iter()
-            .map(|barrier| {
-                let mut resource_barrier = d3d12::D3D12_RESOURCE_BARRIER {
-                    Type: d3d12::D3D12_RESOURCE_BARRIER_TYPE_TRANSITION,
-                    Flags: barrier.flags,
-                    u: unsafe { mem::zeroed() },
+            .flat_map(|barrier| {
+                let view = &state.framebuffer.attachments[barrier.attachment_id];
+                // Depth and stencil planes in different states are transitioned separately.
+                let subresources: Vec<UINT> = match barrier.plane {
+                    Some(plane) => view.plane_subresources(plane).collect(),
+                    None => vec![d3d12::D3D12_RESOURCE_BARRIER_ALL_SUBRESOURCES],
                 };
+                subresources.into_iter().map(move |subresource| {
+                    let mut resource_barrier = d3d12::D3D12_RESOURCE_BARRIER {
+                        Type: d3d12::D3D12_RESOURCE_BARRIER_TYPE_TRANSITION,
+                        Flags: barrier.flags,
+                        u: unsafe { mem::zeroed() },
+                    };
 
-                *unsafe { resource_barrier.u.Transition_mut() } = d3d12::D3D12_RESOURCE_TRANSITION_BARRIER {
-                    pResource: state.framebuffer.attachments[barrier.attachment_id].resource,
-                    Subresource: d3d12::D3D12_RESOURCE_BARRIER_ALL_SUBRESOURCES,
-                    StateBefore: barrier.states.start,
-                    StateAfter: barrier.states.end,
-                };
+                    *unsafe { resource_barrier.u.Transition_mut() } = d3d12::D3D12_RESOURCE_TRANSITION_BARRIER {
+                        pResource: view.resource,
+                        Subresource: subresource,
+                        StateBefore: barrier.states.start,
+                        StateAfter: barrier.states.end,
+                    };
 
-                resource_barrier
+                    resource_barrier
+                })
             })
             .collect::<Vec<_>>();
 
@@ -446,17 +473,16 @@ impl CommandBuffer {
             .iter()
             .map(|&(id, _)| state.framebuffer.attachments[id].handle_rtv.unwrap())
             .collect::<Vec<_>>();
-        let ds_view = match subpass.depth_stencil_attachment {
-            Some((id, _)) => state.framebuffer.attachments[id].handle_dsv.as_ref().unwrap() as *const _,
-            None => ptr::null(),
-        };
+        // read-only aspects of the layout are bound through a read-only view
+        let ds_view = subpass.depth_stencil_attachment
+            .map(|(id, layout)| state.framebuffer.attachments[id].dsv_for_layout(layout).unwrap());
         // set render targets
         unsafe {
             self.raw.OMSetRenderTargets(
                 color_views.len() as UINT,
                 color_views.as_ptr(),
                 FALSE,
-                ds_view,
+                ds_view.as_ref().map_or(ptr::null(), |handle| handle as *const _),
             );
         }
 
@@ -585,7 +611,6 @@ impl CommandBuffer {
                     self.raw.SetPipelineState(pipeline);
                     self.raw.SetGraphicsRootSignature(signature);
                 }
-                self.bind_descriptor_heaps();
             }
             BindPoint::Graphics { internal: false } => {}
         }
@@ -665,7 +690,6 @@ impl CommandBuffer {
                 self.active_bindpoint = BindPoint::Compute;
 
                 if internal {
-                    self.bind_descriptor_heaps();
                     // Rebind the graphics root signature as we come from an internal graphics.
                     // Issuing a draw call afterwards would hide the information that we internally
                     // changed the graphics root signature.
@@ -773,6 +797,163 @@ impl CommandBuffer {
         }
     }
 
+    fn fill_transition_barriers<'a, T>(
+        raw_barriers: &mut Vec<d3d12::D3D12_RESOURCE_BARRIER>,
+        barriers: T,
+        flags: d3d12::D3D12_RESOURCE_BARRIER_FLAGS,
+    ) where
+        T: IntoIterator,
+        T::Item: Borrow<memory::Barrier<'a, Backend>>,
+    {
+        for barrier in barriers {
+            match *barrier.borrow() {
+                // Covered by the global UAV barrier, see `barrier_impl`.
+                memory::Barrier::AllBuffers(_) |
+                memory::Barrier::AllImages(_) => {}
+                // Resources aren't owned by queue families, the `families` of ownership
+                // transfers are ignored and the transition is recorded as is.
+                memory::Barrier::Buffer { ref states, target, .. } => {
+                    let state_src = conv::map_buffer_resource_state(states.start);
+                    let state_dst = conv::map_buffer_resource_state(states.end);
+
+                    if state_src == state_dst {
+                        continue;
+                    }
+
+                    let mut bar = Self::transition_barrier(
+                        d3d12::D3D12_RESOURCE_TRANSITION_BARRIER {
+                            pResource: target.resource,
+                            Subresource: d3d12::D3D12_RESOURCE_BARRIER_ALL_SUBRESOURCES,
+                            StateBefore: state_src,
+                            StateAfter: state_dst,
+                        }
+                    );
+                    bar.Flags = flags;
+
+                    raw_barriers.push(bar);
+                }
+                memory::Barrier::Image { ref states, target, ref range, .. } => {
+                    let format_aspects = target.surface_type.desc().aspects;
+                    // Depth-stencil formats keep stencil in a separate plane,
+                    // which may be in a different state than depth.
+                    let is_depth_stencil = format_aspects.contains(Aspects::DEPTH | Aspects::STENCIL);
+                    let plane_states = |plane| {
+                        let aspect = match plane {
+                            0 if is_depth_stencil => Aspects::DEPTH,
+                            0 => format_aspects,
+                            _ => Aspects::STENCIL,
+                        };
+                        conv::map_image_plane_resource_state(states.start.0, states.start.1, aspect) ..
+                        conv::map_image_plane_resource_state(states.end.0, states.end.1, aspect)
+                    };
+                    let first_states = plane_states(0);
+                    let same_planes = !is_depth_stencil || plane_states(1) == first_states;
+
+                    if same_planes && first_states.start == first_states.end {
+                        continue;
+                    }
+
+                    let mut bar = Self::transition_barrier(
+                        d3d12::D3D12_RESOURCE_TRANSITION_BARRIER {
+                            pResource: target.resource,
+                            Subresource: d3d12::D3D12_RESOURCE_BARRIER_ALL_SUBRESOURCES,
+                            StateBefore: first_states.start,
+                            StateAfter: first_states.end,
+                        }
+                    );
+                    bar.Flags = flags;
+
+                    if same_planes && *range == target.to_subresource_range(format_aspects) {
+                        // Only one barrier if it affects the whole image.
+                        raw_barriers.push(bar);
+                    } else {
+                        let planes = if is_depth_stencil {
+                            let depth = range.aspects.contains(Aspects::DEPTH);
+                            let stencil = range.aspects.contains(Aspects::STENCIL);
+                            (if depth { 0 } else { 1 }) .. (if stencil { 2 } else { 1 })
+                        } else {
+                            0 .. 1
+                        };
+
+                        // Generate barrier for each plane/layer/level combination.
+                        for plane in planes {
+                            let states = plane_states(plane);
+                            if states.start == states.end {
+                                continue;
+                            }
+                            for level in range.levels.clone() {
+                                for layer in range.layers.clone() {
+                                    {
+                                        let transition_barrier = &mut *unsafe { bar.u.Transition_mut() };
+                                        transition_barrier.Subresource = target.calc_subresource(level as _, layer as _, plane);
+                                        transition_barrier.StateBefore = states.start;
+                                        transition_barrier.StateAfter = states.end;
+                                    }
+                                    raw_barriers.push(bar);
+                                }
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    fn barrier_impl<'a, T>(&mut self, barriers: T, flags: d3d12::D3D12_RESOURCE_BARRIER_FLAGS)
+    where
+        T: IntoIterator,
+        T::Item: Borrow<memory::Barrier<'a, Backend>>,
+    {
+        let mut raw_barriers = Vec::new();
+
+        // transition barriers
+        Self::fill_transition_barriers(&mut raw_barriers, barriers, flags);
+
+        // UAV barriers
+        //
+        // Aliasing barrier with NULL resource is the closest we can get to
+        // a global memory barrier in Vulkan.
+        // Was suggested by a Microsoft representative as well as some of the IHVs.
+        //
+        // TODO: Currently always add a global UAV barrier.
+        //       WAR only requires an execution barrier but D3D12 seems to need
+        //       a UAV barrier for this according to docs. Can we make this better?
+        {
+            let mut barrier = d3d12::D3D12_RESOURCE_BARRIER {
+                Type: d3d12::D3D12_RESOURCE_BARRIER_TYPE_UAV,
+                Flags: d3d12::D3D12_RESOURCE_BARRIER_FLAG_NONE,
+                u: unsafe { mem::zeroed() },
+            };
+            *unsafe { barrier.u.UAV_mut() } = d3d12::D3D12_RESOURCE_UAV_BARRIER {
+                pResource: ptr::null_mut(),
+            };
+            raw_barriers.push(barrier);
+        }
+
+        // Alias barriers
+        //
+        // TODO: Optimize, don't always add an alias barrier
+        {
+            let mut barrier = d3d12::D3D12_RESOURCE_BARRIER {
+                Type: d3d12::D3D12_RESOURCE_BARRIER_TYPE_ALIASING,
+                Flags: d3d12::D3D12_RESOURCE_BARRIER_FLAG_NONE,
+                u: unsafe { mem::zeroed() },
+            };
+            *unsafe { barrier.u.Aliasing_mut() } = d3d12::D3D12_RESOURCE_ALIASING_BARRIER {
+                pResourceBefore: ptr::null_mut(),
+                pResourceAfter: ptr::null_mut(),
+            };
+            raw_barriers.push(barrier);
+        }
+
+        unsafe {
+            self.raw.ResourceBarrier(
+                raw_barriers.len() as _,
+                raw_barriers.as_ptr(),
+            );
+        }
+    }
+
     fn transition_barrier(transition: d3d12::D3D12_RESOURCE_TRANSITION_BARRIER) ->  d3d12::D3D12_RESOURCE_BARRIER {
         let mut barrier = d3d12::D3D12_RESOURCE_BARRIER {
             Type: d3d12::D3D12_RESOURCE_BARRIER_TYPE_TRANSITION,
@@ -972,7 +1153,9 @@ impl CommandBuffer {
 
 impl com::RawCommandBuffer<Backend> for CommandBuffer {
     fn begin(&mut self, _flags: com::CommandBufferFlags, _info: com::CommandBufferInheritanceInfo<Backend>) {
-        // TODO: Implement flags and secondary command buffers (bundles).
+        // TODO: Implement flags.
+        // Bundles inherit the render targets of the executing command list,
+        // so the inheritance info isn't needed.
         self.reset();
     }
 
@@ -1046,6 +1229,27 @@ impl com::RawCommandBuffer<Backend> for CommandBuffer {
         self.bind_targets();
     }
 
+    fn begin_render_pass_imageless<T, I>(
+        &mut self,
+        render_pass: &n::RenderPass,
+        framebuffer: &n::Framebuffer,
+        attachments: I,
+        target_rect: pso::Rect,
+        clear_values: T,
+        first_subpass: com::SubpassContents,
+    ) where
+        T: IntoIterator,
+        T::Item: Borrow<com::ClearValueRaw>,
+        I: IntoIterator,
+        I::Item: Borrow<n::ImageView>,
+    {
+        let framebuffer = n::Framebuffer {
+            attachments: attachments.into_iter().map(|att| *att.borrow()).collect(),
+            layers: framebuffer.layers,
+        };
+        self.begin_render_pass(render_pass, &framebuffer, target_rect, clear_values, first_subpass);
+    }
+
     fn next_subpass(&mut self, _contents: com::SubpassContents) {
         self.insert_subpass_barriers(BarrierPoint::Post);
         self.resolve_attachments();
@@ -1064,6 +1268,64 @@ impl com::RawCommandBuffer<Backend> for CommandBuffer {
         self.pass_cache = None;
     }
 
+    fn begin_rendering(&mut self, info: &com::RenderingInfo<Backend>) {
+        // D3D12 binds views directly, so the rendering is recorded as a render pass
+        // with a single subpass and no barriers: the attachments are expected to be
+        // in their layouts already.
+        let attachments = info.colors.iter().chain(info.depth_stencil.iter());
+        let num_colors = info.colors.len();
+
+        let render_pass = n::RenderPass {
+            attachments: attachments
+                .clone()
+                .map(|att| pass::Attachment {
+                    format: None,
+                    samples: 1,
+                    ops: att.ops,
+                    stencil_ops: att.stencil_ops,
+                    layouts: att.layout .. att.layout,
+                })
+                .collect(),
+            subpasses: vec![n::SubpassDesc {
+                color_attachments: info.colors
+                    .iter()
+                    .enumerate()
+                    .map(|(i, att)| (i, att.layout))
+                    .collect(),
+                depth_stencil_attachment: info.depth_stencil
+                    .as_ref()
+                    .map(|att| (num_colors, att.layout)),
+                input_attachments: Vec::new(),
+                resolve_attachments: Vec::new(),
+                pre_barriers: Vec::new(),
+                post_barriers: Vec::new(),
+            }],
+            post_barriers: Vec::new(),
+        };
+        let framebuffer = n::Framebuffer {
+            attachments: attachments.clone().map(|att| att.view.clone()).collect(),
+            layers: 1,
+        };
+        let clear_values = attachments
+            .filter(|att| {
+                att.ops.load == pass::AttachmentLoadOp::Clear ||
+                att.stencil_ops.load == pass::AttachmentLoadOp::Clear
+            })
+            .map(|att| att.clear_value);
+
+        self.begin_render_pass(
+            &render_pass,
+            &framebuffer,
+            info.render_area,
+            clear_values,
+            com::SubpassContents::Inline,
+        );
+    }
+
+    fn end_rendering(&mut self) {
+        self.end_render_pass();
+    }
+
     fn pipeline_barrier<'a, T>(
         &mut self,
         _stages: Range<pso::PipelineStage>,
@@ -1073,121 +1335,43 @@ impl com::RawCommandBuffer<Backend> for CommandBuffer {
         T: IntoIterator,
         T::Item: Borrow<memory::Barrier<'a, Backend>>,
     {
-        let mut raw_barriers = Vec::new();
-
-        // transition barriers
-        for barrier in barriers {
-            match *barrier.borrow() {
-                memory::Barrier::AllBuffers(_) |
-                memory::Barrier::AllImages(_) => {
-                    // Aliasing barrier with NULL resource is the closest we can get to
-                    // a global memory barrier in Vulkan.
-                    // Was suggested by a Microsoft representative as well as some of the IHVs.
-                    let mut bar = d3d12::D3D12_RESOURCE_BARRIER {
-                        Type: d3d12::D3D12_RESOURCE_BARRIER_TYPE_UAV,
-                        Flags: d3d12::D3D12_RESOURCE_BARRIER_FLAG_NONE,
-                        u: unsafe { mem::zeroed() },
-                    };
-                    *unsafe { bar.u.UAV_mut() } = d3d12::D3D12_RESOURCE_UAV_BARRIER {
-                        pResource: ptr::null_mut(),
-                    };
-                    raw_barriers.push(bar);
-                }
-                memory::Barrier::Buffer { ref states, target } => {
-                    let state_src = conv::map_buffer_resource_state(states.start);
-                    let state_dst = conv::map_buffer_resource_state(states.end);
-
-                    if state_src == state_dst {
-                        continue;
-                    }
-
-                    let bar = Self::transition_barrier(
-                        d3d12::D3D12_RESOURCE_TRANSITION_BARRIER {
-                            pResource: target.resource,
-                            Subresource: d3d12::D3D12_RESOURCE_BARRIER_ALL_SUBRESOURCES,
-                            StateBefore: state_src,
-                            StateAfter: state_dst,
-                        }
-                    );
-
-                    raw_barriers.push(bar);
-                }
-                memory::Barrier::Image { ref states, target, ref range } => {
-                    let _ = range; //TODO: use subresource range
-                    let state_src = conv::map_image_resource_state(states.start.0, states.start.1);
-                    let state_dst = conv::map_image_resource_state(states.end.0, states.end.1);
-
-                    if state_src == state_dst {
-                        continue;
-                    }
+        self.statistics.barriers += 1;
+        self.barrier_impl(barriers, d3d12::D3D12_RESOURCE_BARRIER_FLAG_NONE);
+    }
 
-                    let mut bar = Self::transition_barrier(
-                        d3d12::D3D12_RESOURCE_TRANSITION_BARRIER {
-                            pResource: target.resource,
-                            Subresource: d3d12::D3D12_RESOURCE_BARRIER_ALL_SUBRESOURCES,
-                            StateBefore: state_src,
-                            StateAfter: state_dst,
-                        }
-                    );
+    fn begin_split_barrier<'a, T>(
+        &mut self,
+        _stages: Range<pso::PipelineStage>,
+        barriers: T,
+    ) where
+        T: IntoIterator,
+        T::Item: Borrow<memory::Barrier<'a, Backend>>,
+    {
+        // Only transitions can be split, UAV and aliasing barriers are
+        // issued when the split barrier ends.
+        let mut raw_barriers = Vec::new();
+        Self::fill_transition_barriers(&mut raw_barriers, barriers, d3d12::D3D12_RESOURCE_BARRIER_FLAG_BEGIN_ONLY);
 
-                    if *range == target.to_subresource_range(range.aspects) {
-                        // Only one barrier if it affects the whole image.
-                        raw_barriers.push(bar);
-                    } else {
-                        // Generate barrier for each layer/level combination.
-                        for level in range.levels.clone() {
-                            for layer in range.layers.clone() {
-                                {
-                                    let transition_barrier = &mut *unsafe { bar.u.Transition_mut() };
-                                    transition_barrier.Subresource = target.calc_subresource(level as _, layer as _, 0);
-                                }
-                                raw_barriers.push(bar);
-                            }
-                        }
-                    }
-                }
+        if !raw_barriers.is_empty() {
+            unsafe {
+                self.raw.ResourceBarrier(
+                    raw_barriers.len() as _,
+                    raw_barriers.as_ptr(),
+                );
             }
         }
+    }
 
-        // UAV barriers
-        //
-        // TODO: Currently always add a global UAV barrier.
-        //       WAR only requires an execution barrier but D3D12 seems to need
-        //       a UAV barrier for this according to docs. Can we make this better?
-        {
-            let mut barrier = d3d12::D3D12_RESOURCE_BARRIER {
-                Type: d3d12::D3D12_RESOURCE_BARRIER_TYPE_UAV,
-                Flags: d3d12::D3D12_RESOURCE_BARRIER_FLAG_NONE,
-                u: unsafe { mem::zeroed() },
-            };
-            *unsafe { barrier.u.UAV_mut() } = d3d12::D3D12_RESOURCE_UAV_BARRIER {
-                pResource: ptr::null_mut(),
-            };
-            raw_barriers.push(barrier);
-        }
-
-        // Alias barriers
-        //
-        // TODO: Optimize, don't always add an alias barrier
-        {
-            let mut barrier = d3d12::D3D12_RESOURCE_BARRIER {
-                Type: d3d12::D3D12_RESOURCE_BARRIER_TYPE_ALIASING,
-                Flags: d3d12::D3D12_RESOURCE_BARRIER_FLAG_NONE,
-                u: unsafe { mem::zeroed() },
-            };
-            *unsafe { barrier.u.Aliasing_mut() } = d3d12::D3D12_RESOURCE_ALIASING_BARRIER {
-                pResourceBefore: ptr::null_mut(),
-                pResourceAfter: ptr::null_mut(),
-            };
-            raw_barriers.push(barrier);
-        }
-
-        unsafe {
-            self.raw.ResourceBarrier(
-                raw_barriers.len() as _,
-                raw_barriers.as_ptr(),
-            );
-        }
+    fn end_split_barrier<'a, T>(
+        &mut self,
+        _stages: Range<pso::PipelineStage>,
+        _dependencies: memory::Dependencies,
+        barriers: T,
+    ) where
+        T: IntoIterator,
+        T::Item: Borrow<memory::Barrier<'a, Backend>>,
+    {
+        self.barrier_impl(barriers, d3d12::D3D12_RESOURCE_BARRIER_FLAG_END_ONLY);
     }
 
     fn clear_image<T>(
@@ -1203,19 +1387,21 @@ impl com::RawCommandBuffer<Backend> for CommandBuffer {
     {
         for subresource_range in subresource_ranges {
             let sub = subresource_range.borrow();
-            assert_eq!(sub.levels, 0 .. 1); //TODO
             for layer in sub.layers.clone() {
-                if sub.aspects.contains(Aspects::COLOR) {
-                    let rtv = image.clear_cv[layer as usize];
-                    self.clear_render_target_view(rtv, color, &[]);
-                }
-                if sub.aspects.contains(Aspects::DEPTH) {
-                    let dsv = image.clear_dv[layer as usize];
-                    self.clear_depth_stencil_view(dsv, Some(depth_stencil.depth), None, &[]);
-                }
-                if sub.aspects.contains(Aspects::STENCIL) {
-                    let dsv = image.clear_sv[layer as usize];
-                    self.clear_depth_stencil_view(dsv, None, Some(depth_stencil.stencil as _), &[]);
+                for level in sub.levels.clone() {
+                    let index = image.calc_subresource(level as _, layer as _, 0) as usize;
+                    if sub.aspects.contains(Aspects::COLOR) {
+                        let rtv = image.clear_cv[index];
+                        self.clear_render_target_view(rtv, color, &[]);
+                    }
+                    if sub.aspects.contains(Aspects::DEPTH) {
+                        let dsv = image.clear_dv[index];
+                        self.clear_depth_stencil_view(dsv, Some(depth_stencil.depth), None, &[]);
+                    }
+                    if sub.aspects.contains(Aspects::STENCIL) {
+                        let dsv = image.clear_sv[index];
+                        self.clear_depth_stencil_view(dsv, None, Some(depth_stencil.stencil as _), &[]);
+                    }
                 }
             }
         }
@@ -1264,6 +1450,7 @@ impl com::RawCommandBuffer<Backend> for CommandBuffer {
                             flags: image::StorageFlags::empty(),
                             view_kind: image::ViewKind::D2Array,
                             format: attachment.dxgi_format,
+                            component_mapping: device::IDENTITY_MAPPING,
                             range: image::SubresourceRange {
                                 aspects: Aspects::COLOR,
                                 levels: attachment.mip_levels.0 .. attachment.mip_levels.1,
@@ -1305,6 +1492,7 @@ impl com::RawCommandBuffer<Backend> for CommandBuffer {
                             flags: image::StorageFlags::empty(),
                             view_kind: image::ViewKind::D2Array,
                             format: attachment.dxgi_format,
+                            component_mapping: device::IDENTITY_MAPPING,
                             range: image::SubresourceRange {
                                 aspects: if depth.is_some()  { Aspects::DEPTH } else { Aspects::empty() } |
                                     if stencil.is_some() { Aspects::STENCIL } else {Aspects::empty() },
@@ -1316,7 +1504,8 @@ impl com::RawCommandBuffer<Backend> for CommandBuffer {
                         Device::view_image_as_depth_stencil_impl(
                             &mut device,
                             dsv,
-                            view_info
+                            view_info,
+                            d3d12::D3D12_DSV_FLAG_NONE,
                         ).unwrap();
 
                         self.clear_depth_stencil_view(
@@ -1410,14 +1599,14 @@ impl com::RawCommandBuffer<Backend> for CommandBuffer {
             _ => unimplemented!(),
         }
 
-        // Descriptor heap for the current blit, only storing the src image
-        let srv_heap = Device::create_descriptor_heap_impl(
-            &mut device.clone(),
-            d3d12::D3D12_DESCRIPTOR_HEAP_TYPE_CBV_SRV_UAV,
-            true,
-            1,
-        );
-        let srv_handle = srv_heap.at(0, 0);
+        // Transient descriptor for the current blit, only storing the src image
+        let srv_handle = match self.transient_views.lock().unwrap().alloc(1) {
+            Ok(handle) => handle,
+            Err(_) => {
+                error!("Out of shader visible descriptors, skipping the blit");
+                return;
+            }
+        };
 
         let srv_desc = Device::build_image_as_shader_resource_desc(
             &ViewInfo {
@@ -1426,6 +1615,7 @@ impl com::RawCommandBuffer<Backend> for CommandBuffer {
                 flags: src.storage_flags,
                 view_kind: image::ViewKind::D2Array, // TODO
                 format: src.descriptor.Format,
+                component_mapping: device::IDENTITY_MAPPING,
                 range: image::SubresourceRange {
                     aspects: format::Aspects::COLOR, // TODO
                     levels: 0..src.descriptor.MipLevels as _,
@@ -1435,9 +1625,7 @@ impl com::RawCommandBuffer<Backend> for CommandBuffer {
         ).unwrap();
         unsafe {
             device.CreateShaderResourceView(src.resource, &srv_desc, srv_handle.cpu);
-            self.raw.SetDescriptorHeaps(1, &mut srv_heap.raw.as_raw());
         }
-        self.temporary_gpu_heaps.push(srv_heap.raw);
 
         let filter = match filter {
             image::Filter::Nearest => d3d12::D3D12_FILTER_MIN_MAG_MIP_POINT,
@@ -1753,6 +1941,7 @@ impl com::RawCommandBuffer<Backend> for CommandBuffer {
     }
 
     fn bind_graphics_pipeline(&mut self, pipeline: &n::GraphicsPipeline) {
+        self.statistics.pipeline_binds += 1;
         unsafe {
             match self.gr_pipeline.pipeline {
                 Some((_, signature)) if signature == pipeline.signature => {
@@ -1801,11 +1990,11 @@ impl com::RawCommandBuffer<Backend> for CommandBuffer {
         J: IntoIterator,
         J::Item: Borrow<com::DescriptorSetOffset>,
     {
-        self.active_descriptor_heaps = self.gr_pipeline.bind_descriptor_sets(layout, first_set, sets, offsets);
-        self.bind_descriptor_heaps();
+        self.gr_pipeline.bind_descriptor_sets(layout, first_set, sets, offsets);
     }
 
     fn bind_compute_pipeline(&mut self, pipeline: &n::ComputePipeline) {
+        self.statistics.pipeline_binds += 1;
         unsafe {
             match self.comp_pipeline.pipeline {
                 Some((_, signature)) if signature == pipeline.signature => {
@@ -1838,11 +2027,24 @@ impl com::RawCommandBuffer<Backend> for CommandBuffer {
         J: IntoIterator,
         J::Item: Borrow<com::DescriptorSetOffset>,
     {
-        self.active_descriptor_heaps = self.comp_pipeline.bind_descriptor_sets(layout, first_set, sets, offsets);
-        self.bind_descriptor_heaps();
+        self.comp_pipeline.bind_descriptor_sets(layout, first_set, sets, offsets);
     }
 
     fn dispatch(&mut self, count: WorkGroupCount) {
+        self.statistics.dispatches += 1;
+        self.set_compute_bind_point();
+        unsafe {
+            self.raw.Dispatch(count[0], count[1], count[2]);
+        }
+    }
+
+    fn dispatch_base(&mut self, base: WorkGroupCount, count: WorkGroupCount) {
+        // D3D12 has no notion of a base workgroup, the dispatch is skipped.
+        if base != [0; 3] {
+            debug_assert!(false, "Non-zero dispatch base requires `Features::DISPATCH_BASE`");
+            return;
+        }
+        self.statistics.dispatches += 1;
         self.set_compute_bind_point();
         unsafe {
             self.raw.Dispatch(count[0], count[1], count[2]);
@@ -1850,6 +2052,7 @@ impl com::RawCommandBuffer<Backend> for CommandBuffer {
     }
 
     fn dispatch_indirect(&mut self, buffer: &n::Buffer, offset: buffer::Offset) {
+        self.statistics.dispatches += 1;
         self.set_compute_bind_point();
         unsafe {
             self.raw.ExecuteIndirect(
@@ -2003,6 +2206,11 @@ impl com::RawCommandBuffer<Backend> for CommandBuffer {
                         src.descriptor.Format, dst.descriptor.Format);
                     return
                 }
+                n::Place::Reserved { .. } => {
+                    error!("Unable to copy from a sparse image with format conversion: {:?} -> {:?}",
+                        src.descriptor.Format, dst.descriptor.Format);
+                    return
+                }
                 n::Place::Heap { ref raw, offset } => (raw.as_raw(), offset),
             };
             assert_eq!(winerror::S_OK, unsafe {
@@ -2218,6 +2426,7 @@ impl com::RawCommandBuffer<Backend> for CommandBuffer {
     }
 
     fn draw(&mut self, vertices: Range<VertexCount>, instances: Range<InstanceCount>) {
+        self.statistics.draw_calls += 1;
         self.set_graphics_bind_point();
         unsafe {
             self.raw.DrawInstanced(
@@ -2235,6 +2444,7 @@ impl com::RawCommandBuffer<Backend> for CommandBuffer {
         base_vertex: VertexOffset,
         instances: Range<InstanceCount>,
     ) {
+        self.statistics.draw_calls += 1;
         self.set_graphics_bind_point();
         unsafe {
             self.raw.DrawIndexedInstanced(
@@ -2254,17 +2464,26 @@ impl com::RawCommandBuffer<Backend> for CommandBuffer {
         draw_count: DrawCount,
         stride: u32,
     ) {
-        assert_eq!(stride, 16);
+        self.statistics.draw_calls += 1;
         self.set_graphics_bind_point();
-        unsafe {
-            self.raw.ExecuteIndirect(
-                self.shared.signatures.draw.as_raw(),
-                draw_count,
-                buffer.resource,
-                offset,
-                ptr::null_mut(),
-                0,
-            );
+        // The command signature is created for tightly packed arguments,
+        // other strides issue one draw per `ExecuteIndirect`.
+        let (count, executions) = if stride == mem::size_of::<com::DrawIndirectCommand>() as u32 || draw_count <= 1 {
+            (draw_count, 1)
+        } else {
+            (1, draw_count)
+        };
+        for i in 0 .. executions as u64 {
+            unsafe {
+                self.raw.ExecuteIndirect(
+                    self.shared.signatures.draw.as_raw(),
+                    count,
+                    buffer.resource,
+                    offset + i * stride as u64,
+                    ptr::null_mut(),
+                    0,
+                );
+            }
         }
     }
 
@@ -2275,17 +2494,26 @@ impl com::RawCommandBuffer<Backend> for CommandBuffer {
         draw_count: DrawCount,
         stride: u32,
     ) {
-        assert_eq!(stride, 20);
+        self.statistics.draw_calls += 1;
         self.set_graphics_bind_point();
-        unsafe {
-            self.raw.ExecuteIndirect(
-                self.shared.signatures.draw_indexed.as_raw(),
-                draw_count,
-                buffer.resource,
-                offset,
-                ptr::null_mut(),
-                0,
-            );
+        // The command signature is created for tightly packed arguments,
+        // other strides issue one draw per `ExecuteIndirect`.
+        let (count, executions) = if stride == mem::size_of::<com::DrawIndexedIndirectCommand>() as u32 || draw_count <= 1 {
+            (draw_count, 1)
+        } else {
+            (1, draw_count)
+        };
+        for i in 0 .. executions as u64 {
+            unsafe {
+                self.raw.ExecuteIndirect(
+                    self.shared.signatures.draw_indexed.as_raw(),
+                    count,
+                    buffer.resource,
+                    offset + i * stride as u64,
+                    ptr::null_mut(),
+                    0,
+                );
+            }
         }
     }
 
@@ -2307,7 +2535,7 @@ impl com::RawCommandBuffer<Backend> for CommandBuffer {
                 }
             }
             d3d12::D3D12_QUERY_HEAP_TYPE_TIMESTAMP => {
-                panic!("Timestap queries are issued via ")
+                panic!("Timestamp queries are issued via `write_timestamp`")
             }
             d3d12::D3D12_QUERY_HEAP_TYPE_PIPELINE_STATISTICS => {
                 self.pipeline_stats_query = Some(query.id);
@@ -2359,19 +2587,32 @@ impl com::RawCommandBuffer<Backend> for CommandBuffer {
                 id,
             );
         }
+        self.resolve_query(query.pool, query_ty, id);
     }
 
     fn reset_query_pool(
         &mut self,
-        _pool: &n::QueryPool,
-        _queries: Range<query::QueryId>,
+        pool: &n::QueryPool,
+        queries: Range<query::QueryId>,
     ) {
-        // Nothing to do here
         // vkCmdResetQueryPool sets the queries to `unavailable` but the specification
         // doesn't state an affect on the `active` state. Every queries at the end of the command
         // buffer must be made inactive, which can only be done with EndQuery.
         // Therefore, every `begin_query` must follow a `end_query` state, the resulting values
         // after calling are undefined.
+        // Only the availability words need to be cleared.
+        if queries.start == queries.end {
+            return;
+        }
+        unsafe {
+            self.raw.CopyBufferRegion(
+                pool.resolve.as_raw(),
+                pool.availability_offset(queries.start),
+                pool.availability.as_raw(),
+                0,
+                8 * (queries.end - queries.start) as u64,
+            );
+        }
     }
 
     fn write_timestamp(
@@ -2386,6 +2627,7 @@ impl com::RawCommandBuffer<Backend> for CommandBuffer {
                 query.id,
             );
         }
+        self.resolve_query(query.pool, d3d12::D3D12_QUERY_TYPE_TIMESTAMP, query.id);
     }
 
     fn push_graphics_constants(
@@ -2414,8 +2656,10 @@ impl com::RawCommandBuffer<Backend> for CommandBuffer {
         I: IntoIterator,
         I::Item: Borrow<CommandBuffer>,
     {
-        for _cmd_buf in buffers {
-            error!("TODO: execute_commands");
+        for cmd_buf in buffers {
+            let cmd_buf = cmd_buf.borrow();
+            self.statistics.add(&cmd_buf.statistics);
+            unsafe { self.raw.ExecuteBundle(cmd_buf.raw.as_raw()); }
         }
     }
 }
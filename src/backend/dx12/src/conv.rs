@@ -5,11 +5,11 @@ use spirv_cross::spirv;
 
 use winapi::shared::basetsd::UINT8;
 use winapi::shared::dxgiformat::*;
-use winapi::shared::minwindef::{FALSE, INT, TRUE};
+use winapi::shared::minwindef::{FALSE, INT, TRUE, UINT};
 use winapi::um::d3d12::*;
 use winapi::um::d3dcommon::*;
 
-use hal::format::{Format, ImageFeature, SurfaceType};
+use hal::format::{Aspects, Format, ImageFeature, SurfaceType, Swizzle};
 use hal::{buffer, image, pso, Primitive};
 use hal::pso::DescriptorSetLayoutBinding;
 
@@ -19,11 +19,10 @@ pub fn map_format(format: Format) -> Option<DXGI_FORMAT> {
     // Handling packed formats according to the platform endianness.
     let reverse = unsafe { 1 == *(&1u32 as *const _ as *const u8) };
     let format = match format {
-        Bgra4Unorm    if !reverse => DXGI_FORMAT_B4G4R4A4_UNORM,
-        R5g6b5Unorm    if reverse => DXGI_FORMAT_B5G6R5_UNORM,
-        B5g6r5Unorm   if !reverse => DXGI_FORMAT_B5G6R5_UNORM,
-        B5g5r5a1Unorm if !reverse => DXGI_FORMAT_B5G5R5A1_UNORM,
-        A1r5g5b5Unorm if reverse  => DXGI_FORMAT_B5G5R5A1_UNORM,
+        // Packed formats are named from the most significant bit in Vulkan,
+        // and from the least significant bit in DXGI.
+        R5g6b5Unorm => DXGI_FORMAT_B5G6R5_UNORM,
+        A1r5g5b5Unorm => DXGI_FORMAT_B5G5R5A1_UNORM,
         R8Unorm => DXGI_FORMAT_R8_UNORM,
         R8Inorm => DXGI_FORMAT_R8_SNORM,
         R8Uint => DXGI_FORMAT_R8_UINT,
@@ -101,6 +100,117 @@ pub fn map_format(format: Format) -> Option<DXGI_FORMAT> {
     Some(format)
 }
 
+/// Returns the typeless format of the family `format` belongs to, allowing
+/// views of a resource to reinterpret it with a different typed format.
+pub fn map_format_typeless(format: DXGI_FORMAT) -> Option<DXGI_FORMAT> {
+    Some(match format {
+        DXGI_FORMAT_R8_UNORM |
+        DXGI_FORMAT_R8_SNORM |
+        DXGI_FORMAT_R8_UINT |
+        DXGI_FORMAT_R8_SINT => DXGI_FORMAT_R8_TYPELESS,
+        DXGI_FORMAT_R8G8_UNORM |
+        DXGI_FORMAT_R8G8_SNORM |
+        DXGI_FORMAT_R8G8_UINT |
+        DXGI_FORMAT_R8G8_SINT => DXGI_FORMAT_R8G8_TYPELESS,
+        DXGI_FORMAT_R8G8B8A8_UNORM |
+        DXGI_FORMAT_R8G8B8A8_UNORM_SRGB |
+        DXGI_FORMAT_R8G8B8A8_SNORM |
+        DXGI_FORMAT_R8G8B8A8_UINT |
+        DXGI_FORMAT_R8G8B8A8_SINT => DXGI_FORMAT_R8G8B8A8_TYPELESS,
+        DXGI_FORMAT_B8G8R8A8_UNORM |
+        DXGI_FORMAT_B8G8R8A8_UNORM_SRGB => DXGI_FORMAT_B8G8R8A8_TYPELESS,
+        DXGI_FORMAT_R10G10B10A2_UNORM |
+        DXGI_FORMAT_R10G10B10A2_UINT => DXGI_FORMAT_R10G10B10A2_TYPELESS,
+        DXGI_FORMAT_R16_UNORM |
+        DXGI_FORMAT_R16_SNORM |
+        DXGI_FORMAT_R16_UINT |
+        DXGI_FORMAT_R16_SINT |
+        DXGI_FORMAT_R16_FLOAT => DXGI_FORMAT_R16_TYPELESS,
+        DXGI_FORMAT_R16G16_UNORM |
+        DXGI_FORMAT_R16G16_SNORM |
+        DXGI_FORMAT_R16G16_UINT |
+        DXGI_FORMAT_R16G16_SINT |
+        DXGI_FORMAT_R16G16_FLOAT => DXGI_FORMAT_R16G16_TYPELESS,
+        DXGI_FORMAT_R16G16B16A16_UNORM |
+        DXGI_FORMAT_R16G16B16A16_SNORM |
+        DXGI_FORMAT_R16G16B16A16_UINT |
+        DXGI_FORMAT_R16G16B16A16_SINT |
+        DXGI_FORMAT_R16G16B16A16_FLOAT => DXGI_FORMAT_R16G16B16A16_TYPELESS,
+        DXGI_FORMAT_R32_UINT |
+        DXGI_FORMAT_R32_SINT |
+        DXGI_FORMAT_R32_FLOAT => DXGI_FORMAT_R32_TYPELESS,
+        DXGI_FORMAT_R32G32_UINT |
+        DXGI_FORMAT_R32G32_SINT |
+        DXGI_FORMAT_R32G32_FLOAT => DXGI_FORMAT_R32G32_TYPELESS,
+        DXGI_FORMAT_R32G32B32_UINT |
+        DXGI_FORMAT_R32G32B32_SINT |
+        DXGI_FORMAT_R32G32B32_FLOAT => DXGI_FORMAT_R32G32B32_TYPELESS,
+        DXGI_FORMAT_R32G32B32A32_UINT |
+        DXGI_FORMAT_R32G32B32A32_SINT |
+        DXGI_FORMAT_R32G32B32A32_FLOAT => DXGI_FORMAT_R32G32B32A32_TYPELESS,
+        DXGI_FORMAT_BC1_UNORM |
+        DXGI_FORMAT_BC1_UNORM_SRGB => DXGI_FORMAT_BC1_TYPELESS,
+        DXGI_FORMAT_BC2_UNORM |
+        DXGI_FORMAT_BC2_UNORM_SRGB => DXGI_FORMAT_BC2_TYPELESS,
+        DXGI_FORMAT_BC3_UNORM |
+        DXGI_FORMAT_BC3_UNORM_SRGB => DXGI_FORMAT_BC3_TYPELESS,
+        DXGI_FORMAT_BC4_UNORM |
+        DXGI_FORMAT_BC4_SNORM => DXGI_FORMAT_BC4_TYPELESS,
+        DXGI_FORMAT_BC5_UNORM |
+        DXGI_FORMAT_BC5_SNORM => DXGI_FORMAT_BC5_TYPELESS,
+        DXGI_FORMAT_BC6H_UF16 |
+        DXGI_FORMAT_BC6H_SF16 => DXGI_FORMAT_BC6H_TYPELESS,
+        DXGI_FORMAT_BC7_UNORM |
+        DXGI_FORMAT_BC7_UNORM_SRGB => DXGI_FORMAT_BC7_TYPELESS,
+        _ => return None,
+    })
+}
+
+/// Map a format to a DXGI format, including formats whose channel order is not
+/// available in DXGI and has to be emulated by swizzling shader resource views.
+///
+/// Returns the DXGI format and the swizzle to apply on top of it.
+pub fn map_format_with_swizzle(format: Format) -> Option<(DXGI_FORMAT, Swizzle)> {
+    use hal::format::Component::*;
+    use hal::format::Format::*;
+
+    match format {
+        B5g6r5Unorm => Some((DXGI_FORMAT_B5G6R5_UNORM, Swizzle(B, G, R, A))),
+        Bgra4Unorm => Some((DXGI_FORMAT_B4G4R4A4_UNORM, Swizzle(G, R, A, B))),
+        Rgba4Unorm => Some((DXGI_FORMAT_B4G4R4A4_UNORM, Swizzle(A, R, G, B))),
+        _ => map_format(format).map(|format| (format, Swizzle::NO)),
+    }
+}
+
+/// Apply the `outer` swizzle to the result of the `inner` one.
+pub fn compose_swizzle(inner: Swizzle, outer: Swizzle) -> Swizzle {
+    use hal::format::Component;
+
+    let map = |component| match component {
+        Component::R => inner.0,
+        Component::G => inner.1,
+        Component::B => inner.2,
+        Component::A => inner.3,
+        Component::Zero | Component::One => component,
+    };
+    Swizzle(map(outer.0), map(outer.1), map(outer.2), map(outer.3))
+}
+
+pub fn map_swizzle(swizzle: Swizzle) -> UINT {
+    use hal::format::Component::*;
+
+    let map = |component| match component {
+        R => D3D12_SHADER_COMPONENT_MAPPING_FROM_MEMORY_COMPONENT_0,
+        G => D3D12_SHADER_COMPONENT_MAPPING_FROM_MEMORY_COMPONENT_1,
+        B => D3D12_SHADER_COMPONENT_MAPPING_FROM_MEMORY_COMPONENT_2,
+        A => D3D12_SHADER_COMPONENT_MAPPING_FROM_MEMORY_COMPONENT_3,
+        Zero => D3D12_SHADER_COMPONENT_MAPPING_FORCE_VALUE_0,
+        One => D3D12_SHADER_COMPONENT_MAPPING_FORCE_VALUE_1,
+    };
+    // D3D12_ENCODE_SHADER_4_COMPONENT_MAPPING
+    map(swizzle.0) | map(swizzle.1) << 3 | map(swizzle.2) << 6 | map(swizzle.3) << 9 | 1 << 12
+}
+
 pub fn map_format_dsv(surface: SurfaceType) -> Option<DXGI_FORMAT> {
     Some(match surface {
         SurfaceType::D16    => DXGI_FORMAT_D16_UNORM,
@@ -150,6 +260,10 @@ pub fn map_rasterizer(rasterizer: &pso::Rasterizer) -> D3D12_RASTERIZER_DESC {
     use hal::pso::PolygonMode::*;
     use hal::pso::FrontFace::*;
 
+    // Points are always rasterized with a size of 1, point list pipelines with
+    // `program_point_size` are rejected at creation.
+    // TODO: emulate by expanding points into quads in a geometry shader
+
     D3D12_RASTERIZER_DESC {
         FillMode: match rasterizer.polygon_mode {
             Point => {
@@ -453,7 +567,71 @@ pub fn map_image_resource_state(access: image::Access, layout: image::Layout) ->
     state
 }
 
-pub fn map_descriptor_range(bind: &DescriptorSetLayoutBinding, register_space: u32, sampler: bool) -> D3D12_DESCRIPTOR_RANGE {
+/// Aspects which are read-only in a depth-stencil layout.
+pub fn map_read_only_aspects(layout: image::Layout) -> Aspects {
+    match layout {
+        image::Layout::DepthStencilReadOnlyOptimal => Aspects::DEPTH | Aspects::STENCIL,
+        image::Layout::DepthReadOnlyStencilAttachmentOptimal => Aspects::DEPTH,
+        image::Layout::DepthAttachmentStencilReadOnlyOptimal => Aspects::STENCIL,
+        _ => Aspects::empty(),
+    }
+}
+
+/// Resource state of the plane holding `aspect` of an image,
+/// where depth and stencil may be in different states depending on the layout.
+pub fn map_image_plane_resource_state(
+    access: image::Access, layout: image::Layout, aspect: Aspects
+) -> D3D12_RESOURCE_STATES {
+    let access = if map_read_only_aspects(layout).contains(aspect) {
+        access - image::Access::DEPTH_STENCIL_ATTACHMENT_WRITE
+    } else {
+        access
+    };
+    map_image_resource_state(access, layout)
+}
+
+/// Resource states of the planes of an attachment with the given format `aspects`,
+/// used in a subpass with `layout`. The second plane only exists if the format
+/// has both depth and stencil, otherwise both states are the same.
+pub fn map_depth_stencil_attachment_states(
+    layout: image::Layout, aspects: Aspects
+) -> (D3D12_RESOURCE_STATES, D3D12_RESOURCE_STATES) {
+    let read_only = map_read_only_aspects(layout) & aspects;
+    let state = |aspect| if read_only.contains(aspect) {
+        D3D12_RESOURCE_STATE_DEPTH_READ |
+        D3D12_RESOURCE_STATE_PIXEL_SHADER_RESOURCE |
+        D3D12_RESOURCE_STATE_NON_PIXEL_SHADER_RESOURCE
+    } else {
+        D3D12_RESOURCE_STATE_DEPTH_WRITE
+    };
+    if aspects.contains(Aspects::DEPTH | Aspects::STENCIL) {
+        (state(Aspects::DEPTH), state(Aspects::STENCIL))
+    } else {
+        (state(aspects), state(aspects))
+    }
+}
+
+/// Flags of a depth-stencil view with `read_only` aspects.
+pub fn map_dsv_flags(read_only: Aspects) -> D3D12_DSV_FLAGS {
+    let mut flags = D3D12_DSV_FLAG_NONE;
+    if read_only.contains(Aspects::DEPTH) {
+        flags |= D3D12_DSV_FLAG_READ_ONLY_DEPTH;
+    }
+    if read_only.contains(Aspects::STENCIL) {
+        flags |= D3D12_DSV_FLAG_READ_ONLY_STENCIL;
+    }
+    flags
+}
+
+/// `D3D12_DESCRIPTOR_RANGE_UNBOUNDED`, the size of a range extending to the end of the heap.
+const DESCRIPTOR_RANGE_UNBOUNDED: u32 = !0;
+
+pub fn map_descriptor_range(
+    bind: &DescriptorSetLayoutBinding,
+    register_space: u32,
+    sampler: bool,
+    unbounded: bool,
+) -> D3D12_DESCRIPTOR_RANGE {
     D3D12_DESCRIPTOR_RANGE {
         RangeType: match bind.ty {
             pso::DescriptorType::Sampler => D3D12_DESCRIPTOR_RANGE_TYPE_SAMPLER,
@@ -472,13 +650,24 @@ pub fn map_descriptor_range(bind: &DescriptorSetLayoutBinding, register_space: u
                 D3D12_DESCRIPTOR_RANGE_TYPE_SRV
             }
         },
-        NumDescriptors: bind.count as _,
+        NumDescriptors: if unbounded { DESCRIPTOR_RANGE_UNBOUNDED } else { bind.count as _ },
         BaseShaderRegister: bind.binding as _,
         RegisterSpace: register_space,
         OffsetInDescriptorsFromTableStart: D3D12_DESCRIPTOR_RANGE_OFFSET_APPEND,
     }
 }
 
+pub fn map_shader_visibility(stages: pso::ShaderStageFlags) -> D3D12_SHADER_VISIBILITY {
+    match stages {
+        pso::ShaderStageFlags::VERTEX => D3D12_SHADER_VISIBILITY_VERTEX,
+        pso::ShaderStageFlags::HULL => D3D12_SHADER_VISIBILITY_HULL,
+        pso::ShaderStageFlags::DOMAIN => D3D12_SHADER_VISIBILITY_DOMAIN,
+        pso::ShaderStageFlags::GEOMETRY => D3D12_SHADER_VISIBILITY_GEOMETRY,
+        pso::ShaderStageFlags::FRAGMENT => D3D12_SHADER_VISIBILITY_PIXEL,
+        _ => D3D12_SHADER_VISIBILITY_ALL,
+    }
+}
+
 pub fn map_buffer_flags(usage: buffer::Usage) -> D3D12_RESOURCE_FLAGS {
     let mut flags = D3D12_RESOURCE_FLAG_NONE;
 
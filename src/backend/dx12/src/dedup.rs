@@ -0,0 +1,573 @@
+//! Deduplication of pipeline state objects.
+//!
+//! Material systems frequently request identical pipelines. Pipelines created from
+//! SPIR-V with the same shaders, specialization constants, layout and states share
+//! a single reference counted `ID3D12PipelineState`.
+
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+use std::sync::Mutex;
+
+use hal::backend::FastHashMap;
+use hal::format::Format;
+use hal::pso;
+use hal::Primitive;
+
+use winapi::um::d3d12;
+use wio::com::ComPtr;
+
+use {native as n, Backend};
+
+/// Shader of a single stage: FNV-1a hash of the SPIR-V code, entry point name
+/// and specialization constant values.
+#[derive(Debug, Hash, PartialEq, Eq)]
+struct ShaderKey {
+    module: u64,
+    entry: String,
+    specialization: Vec<(u32, u64)>,
+}
+
+impl ShaderKey {
+    // Precompiled modules are identified by their blob pointers only,
+    // which may be reused after the module is destroyed, so these aren't deduplicated.
+    fn new(source: &pso::EntryPoint<Backend>) -> Option<Self> {
+        match *source.module {
+            n::ShaderModule::Spirv(ref raw_data) => {
+                Some(ShaderKey {
+                    module: pso::fnv1a(raw_data),
+                    entry: source.entry.to_string(),
+                    specialization: source.specialization
+                        .iter()
+                        .map(|c| (c.id, c.value.to_bits()))
+                        .collect(),
+                })
+            }
+            n::ShaderModule::Compiled(_) => None,
+        }
+    }
+}
+
+fn shader_key(source: Option<&pso::EntryPoint<Backend>>) -> Option<Option<ShaderKey>> {
+    match source {
+        Some(source) => ShaderKey::new(source).map(Some),
+        None => Some(None),
+    }
+}
+
+fn hash_of<T: Hash>(value: &T) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    value.hash(&mut hasher);
+    hasher.finish()
+}
+
+/// Little-endian byte encoding of the pipeline states, hashed into the keys of
+/// `PipelineCache` entries, which need to stay the same across runs and builds.
+#[derive(Default)]
+struct Encoder(Vec<u8>);
+
+impl Encoder {
+    fn u8(&mut self, value: u8) {
+        self.0.push(value);
+    }
+
+    fn bool(&mut self, value: bool) {
+        self.u8(value as u8);
+    }
+
+    fn u32(&mut self, value: u32) {
+        for i in 0 .. 4 {
+            self.0.push((value >> (i * 8)) as u8);
+        }
+    }
+
+    fn u64(&mut self, value: u64) {
+        for i in 0 .. 8 {
+            self.0.push((value >> (i * 8)) as u8);
+        }
+    }
+
+    fn f32(&mut self, value: f32) {
+        self.u32(value.to_bits());
+    }
+
+    fn len(&mut self, len: usize) {
+        self.u64(len as u64);
+    }
+
+    fn option<T, F: FnOnce(&mut Self, &T)>(&mut self, value: Option<&T>, encode: F) {
+        match value {
+            Some(value) => {
+                self.u8(1);
+                encode(self, value);
+            }
+            None => self.u8(0),
+        }
+    }
+
+    fn state(&mut self, state: pso::State<u32>) {
+        match state {
+            pso::State::Static(value) => {
+                self.u8(1);
+                self.u32(value);
+            }
+            pso::State::Dynamic => self.u8(0),
+        }
+    }
+
+    fn rect(&mut self, rect: &pso::Rect) {
+        for &value in &[rect.x, rect.y, rect.w, rect.h] {
+            self.u32(value as u16 as u32);
+        }
+    }
+
+    fn format(&mut self, format: Option<&Format>) {
+        self.option(format, |enc, &format| enc.u32(format as u32));
+    }
+
+    fn shader(&mut self, shader: &ShaderKey) {
+        self.u64(shader.module);
+        self.len(shader.entry.len());
+        self.0.extend_from_slice(shader.entry.as_bytes());
+        self.len(shader.specialization.len());
+        for &(id, value) in &shader.specialization {
+            self.u32(id);
+            self.u64(value);
+        }
+    }
+
+    fn layout(&mut self, layout: &n::PipelineLayout) {
+        self.len(layout.tables.len());
+        for table in &layout.tables {
+            self.u8(table.bits());
+        }
+        self.len(layout.root_constants.len());
+        for constant in &layout.root_constants {
+            self.u32(constant.stages.bits());
+            self.u32(constant.range.start);
+            self.u32(constant.range.end);
+        }
+        self.len(layout.num_parameter_slots);
+    }
+
+    fn rasterizer(&mut self, rasterizer: &pso::Rasterizer) {
+        match rasterizer.polygon_mode {
+            pso::PolygonMode::Point => self.u8(0),
+            pso::PolygonMode::Line(width) => {
+                self.u8(1);
+                self.f32(width);
+            }
+            pso::PolygonMode::Fill => self.u8(2),
+        }
+        self.u32(rasterizer.cull_face.bits());
+        self.u8(rasterizer.front_face as u8);
+        self.bool(rasterizer.depth_clamping);
+        self.option(rasterizer.depth_bias.as_ref(), |enc, bias| {
+            enc.f32(bias.const_factor);
+            enc.f32(bias.clamp);
+            enc.f32(bias.slope_factor);
+        });
+        self.bool(rasterizer.conservative);
+        self.bool(rasterizer.program_point_size);
+    }
+
+    fn vertex_input(&mut self, buffers: &[pso::VertexBufferDesc], attributes: &[pso::AttributeDesc]) {
+        self.len(buffers.len());
+        for buffer in buffers {
+            self.u32(buffer.binding);
+            self.u32(buffer.stride);
+            self.u8(buffer.rate);
+        }
+        self.len(attributes.len());
+        for attribute in attributes {
+            self.u32(attribute.location);
+            self.u32(attribute.binding);
+            self.u32(attribute.element.format as u32);
+            self.u32(attribute.element.offset);
+        }
+    }
+
+    fn input_assembler(&mut self, input_assembler: &pso::InputAssemblerDesc) {
+        match input_assembler.primitive {
+            Primitive::PointList => self.u8(0),
+            Primitive::LineList => self.u8(1),
+            Primitive::LineStrip => self.u8(2),
+            Primitive::TriangleList => self.u8(3),
+            Primitive::TriangleStrip => self.u8(4),
+            Primitive::LineListAdjacency => self.u8(5),
+            Primitive::LineStripAdjacency => self.u8(6),
+            Primitive::TriangleListAdjacency => self.u8(7),
+            Primitive::TriangleStripAdjacency => self.u8(8),
+            Primitive::PatchList(size) => {
+                self.u8(9);
+                self.u8(size);
+            }
+        }
+        self.u8(input_assembler.primitive_restart as u8);
+    }
+
+    fn blend_op(&mut self, op: pso::BlendOp) {
+        match op {
+            pso::BlendOp::Add { src, dst } => {
+                self.u8(0);
+                self.u8(src as u8);
+                self.u8(dst as u8);
+            }
+            pso::BlendOp::Sub { src, dst } => {
+                self.u8(1);
+                self.u8(src as u8);
+                self.u8(dst as u8);
+            }
+            pso::BlendOp::RevSub { src, dst } => {
+                self.u8(2);
+                self.u8(src as u8);
+                self.u8(dst as u8);
+            }
+            pso::BlendOp::Min => self.u8(3),
+            pso::BlendOp::Max => self.u8(4),
+        }
+    }
+
+    fn blender(&mut self, blender: &pso::BlendDesc) {
+        self.option(blender.logic_op.as_ref(), |enc, op| enc.u8(op.clone() as u8));
+        self.len(blender.targets.len());
+        for &pso::ColorBlendDesc(mask, state) in &blender.targets {
+            self.u8(mask.bits());
+            match state {
+                pso::BlendState::On { color, alpha } => {
+                    self.u8(1);
+                    self.blend_op(color);
+                    self.blend_op(alpha);
+                }
+                pso::BlendState::Off => self.u8(0),
+            }
+        }
+        self.u8(blender.srgb as u8);
+    }
+
+    fn stencil_face(&mut self, face: &pso::StencilFace) {
+        self.u8(face.fun as u8);
+        self.state(face.mask_read);
+        self.state(face.mask_write);
+        self.u8(face.op_fail as u8);
+        self.u8(face.op_depth_fail as u8);
+        self.u8(face.op_pass as u8);
+        self.state(face.reference);
+    }
+
+    fn depth_stencil(&mut self, depth_stencil: &pso::DepthStencilDesc) {
+        match depth_stencil.depth {
+            pso::DepthTest::On { fun, write } => {
+                self.u8(1);
+                self.u8(fun as u8);
+                self.bool(write);
+            }
+            pso::DepthTest::Off => self.u8(0),
+        }
+        self.bool(depth_stencil.depth_bounds);
+        match depth_stencil.stencil {
+            pso::StencilTest::On { ref front, ref back } => {
+                self.u8(1);
+                self.stencil_face(front);
+                self.stencil_face(back);
+            }
+            pso::StencilTest::Off => self.u8(0),
+        }
+    }
+
+    fn multisampling(&mut self, multisampling: Option<&pso::Multisampling>) {
+        self.option(multisampling, |enc, multisampling| {
+            enc.u8(multisampling.rasterization_samples);
+            enc.option(multisampling.sample_shading.as_ref(), |enc, &shading| enc.f32(shading));
+            enc.u64(multisampling.sample_mask);
+            enc.bool(multisampling.alpha_coverage);
+            enc.bool(multisampling.alpha_to_one);
+        });
+    }
+
+    fn baked_states(&mut self, states: &pso::BakedStates) {
+        self.option(states.viewport.as_ref(), |enc, viewport| {
+            enc.rect(&viewport.rect);
+            enc.f32(viewport.depth.start);
+            enc.f32(viewport.depth.end);
+        });
+        self.option(states.scissor.as_ref(), |enc, rect| enc.rect(rect));
+        self.option(states.blend_color.as_ref(), |enc, color| {
+            for &value in color {
+                enc.f32(value);
+            }
+        });
+        self.option(states.depth_bounds.as_ref(), |enc, bounds| {
+            enc.f32(bounds.start);
+            enc.f32(bounds.end);
+        });
+    }
+
+    fn finish(&self) -> u64 {
+        pso::fnv1a(&self.0)
+    }
+}
+
+/// Everything a graphics pipeline state object depends on.
+#[derive(Debug, PartialEq)]
+pub(crate) struct GraphicsPipelineKey {
+    // Vertex, hull, domain, geometry and fragment shaders.
+    shaders: [Option<ShaderKey>; 5],
+    signature: usize,
+    rasterizer: pso::Rasterizer,
+    vertex_buffers: Vec<pso::VertexBufferDesc>,
+    attributes: Vec<pso::AttributeDesc>,
+    input_assembler: pso::InputAssemblerDesc,
+    blender: pso::BlendDesc,
+    depth_stencil: pso::DepthStencilDesc,
+    multisampling: Option<pso::Multisampling>,
+    baked_states: pso::BakedStates,
+    flags: pso::PipelineCreationFlags,
+    // Formats of the color and depth-stencil attachments of the subpass.
+    color_formats: Vec<Option<Format>>,
+    depth_stencil_format: Option<Format>,
+}
+
+impl GraphicsPipelineKey {
+    /// Returns `None` if the pipeline can't be deduplicated.
+    pub(crate) fn new(desc: &pso::GraphicsPipelineDesc<Backend>) -> Option<Self> {
+        let subpass = desc.subpass.main_pass.subpasses.get(desc.subpass.index)?;
+        let attachments = &desc.subpass.main_pass.attachments;
+        let shaders = &desc.shaders;
+
+        Some(GraphicsPipelineKey {
+            shaders: [
+                shader_key(Some(&shaders.vertex))?,
+                shader_key(shaders.hull.as_ref())?,
+                shader_key(shaders.domain.as_ref())?,
+                shader_key(shaders.geometry.as_ref())?,
+                shader_key(shaders.fragment.as_ref())?,
+            ],
+            signature: desc.layout.raw as usize,
+            rasterizer: desc.rasterizer.clone(),
+            vertex_buffers: desc.vertex_buffers.clone(),
+            attributes: desc.attributes.clone(),
+            input_assembler: desc.input_assembler.clone(),
+            blender: desc.blender.clone(),
+            depth_stencil: desc.depth_stencil,
+            multisampling: desc.multisampling.clone(),
+            baked_states: desc.baked_states.clone(),
+            flags: desc.flags,
+            color_formats: subpass.color_attachments
+                .iter()
+                .map(|&(id, _)| attachments[id].format)
+                .collect(),
+            depth_stencil_format: subpass.depth_stencil_attachment
+                .and_then(|(id, _)| attachments[id].format),
+        })
+    }
+
+    fn bucket(&self) -> u64 {
+        hash_of(&(&self.shaders, self.signature))
+    }
+
+    /// Key of the pipeline in a `PipelineCache`, which stays the same across runs.
+    pub(crate) fn cache_key(&self, layout: &n::PipelineLayout) -> u64 {
+        let mut enc = Encoder::default();
+        for shader in &self.shaders {
+            enc.option(shader.as_ref(), Encoder::shader);
+        }
+        enc.layout(layout);
+        enc.rasterizer(&self.rasterizer);
+        enc.vertex_input(&self.vertex_buffers, &self.attributes);
+        enc.input_assembler(&self.input_assembler);
+        enc.blender(&self.blender);
+        enc.depth_stencil(&self.depth_stencil);
+        enc.multisampling(self.multisampling.as_ref());
+        enc.baked_states(&self.baked_states);
+        enc.u32(self.flags.bits());
+        enc.len(self.color_formats.len());
+        for format in &self.color_formats {
+            enc.format(format.as_ref());
+        }
+        enc.format(self.depth_stencil_format.as_ref());
+        enc.finish()
+    }
+}
+
+/// Everything a compute pipeline state object depends on.
+#[derive(Debug, PartialEq)]
+pub(crate) struct ComputePipelineKey {
+    shader: ShaderKey,
+    signature: usize,
+    flags: pso::PipelineCreationFlags,
+}
+
+impl ComputePipelineKey {
+    /// Returns `None` if the pipeline can't be deduplicated.
+    pub(crate) fn new(desc: &pso::ComputePipelineDesc<Backend>) -> Option<Self> {
+        Some(ComputePipelineKey {
+            shader: ShaderKey::new(&desc.shader)?,
+            signature: desc.layout.raw as usize,
+            flags: desc.flags,
+        })
+    }
+
+    fn bucket(&self) -> u64 {
+        hash_of(&(&self.shader, self.signature))
+    }
+
+    /// Key of the pipeline in a `PipelineCache`, which stays the same across runs.
+    pub(crate) fn cache_key(&self, layout: &n::PipelineLayout) -> u64 {
+        let mut enc = Encoder::default();
+        enc.shader(&self.shader);
+        enc.layout(layout);
+        enc.u32(self.flags.bits());
+        enc.finish()
+    }
+}
+
+struct Entry<K, P> {
+    key: K,
+    pipeline: P,
+    // Number of pipelines handed out for this entry and not destroyed yet.
+    refs: usize,
+    // Keeps the root signature alive, so its address can't be reused
+    // by another pipeline layout while the entry exists.
+    _signature: ComPtr<d3d12::ID3D12RootSignature>,
+}
+
+fn share_signature(signature: *mut d3d12::ID3D12RootSignature) -> ComPtr<d3d12::ID3D12RootSignature> {
+    unsafe {
+        (*signature).AddRef();
+        ComPtr::from_raw(signature)
+    }
+}
+
+/// Created pipelines, each holding a reference to its pipeline state object.
+///
+/// An entry is evicted once the last pipeline handed out for it is destroyed.
+#[derive(Default)]
+pub(crate) struct PipelineDedup {
+    graphics: Mutex<FastHashMap<u64, Vec<Entry<GraphicsPipelineKey, n::GraphicsPipeline>>>>,
+    compute: Mutex<FastHashMap<u64, Vec<Entry<ComputePipelineKey, n::ComputePipeline>>>>,
+}
+
+impl PipelineDedup {
+    pub(crate) fn find_graphics(&self, key: &GraphicsPipelineKey) -> Option<n::GraphicsPipeline> {
+        let mut graphics = self.graphics.lock().unwrap();
+        let entry = graphics.get_mut(&key.bucket())?.iter_mut().find(|entry| entry.key == *key)?;
+        entry.refs += 1;
+        let pipeline = &entry.pipeline;
+        unsafe { (*pipeline.raw).AddRef(); }
+
+        Some(n::GraphicsPipeline {
+            raw: pipeline.raw,
+            signature: pipeline.signature,
+            num_parameter_slots: pipeline.num_parameter_slots,
+            topology: pipeline.topology,
+            constants: pipeline.constants.clone(),
+            vertex_bindings: pipeline.vertex_bindings,
+            baked_states: pipeline.baked_states.clone(),
+        })
+    }
+
+    pub(crate) fn insert_graphics(&self, key: GraphicsPipelineKey, pipeline: &n::GraphicsPipeline) {
+        unsafe { (*pipeline.raw).AddRef(); }
+        let entry = Entry {
+            pipeline: n::GraphicsPipeline {
+                raw: pipeline.raw,
+                signature: pipeline.signature,
+                num_parameter_slots: pipeline.num_parameter_slots,
+                topology: pipeline.topology,
+                constants: pipeline.constants.clone(),
+                vertex_bindings: pipeline.vertex_bindings,
+                baked_states: pipeline.baked_states.clone(),
+            },
+            _signature: share_signature(pipeline.signature),
+            refs: 1,
+            key,
+        };
+        self.graphics
+            .lock()
+            .unwrap()
+            .entry(entry.key.bucket())
+            .or_insert_with(Vec::new)
+            .push(entry);
+    }
+
+    pub(crate) fn find_compute(&self, key: &ComputePipelineKey) -> Option<n::ComputePipeline> {
+        let mut compute = self.compute.lock().unwrap();
+        let entry = compute.get_mut(&key.bucket())?.iter_mut().find(|entry| entry.key == *key)?;
+        entry.refs += 1;
+        let pipeline = &entry.pipeline;
+        unsafe { (*pipeline.raw).AddRef(); }
+
+        Some(n::ComputePipeline {
+            raw: pipeline.raw,
+            signature: pipeline.signature,
+            num_parameter_slots: pipeline.num_parameter_slots,
+            constants: pipeline.constants.clone(),
+        })
+    }
+
+    pub(crate) fn insert_compute(&self, key: ComputePipelineKey, pipeline: &n::ComputePipeline) {
+        unsafe { (*pipeline.raw).AddRef(); }
+        let entry = Entry {
+            pipeline: n::ComputePipeline {
+                raw: pipeline.raw,
+                signature: pipeline.signature,
+                num_parameter_slots: pipeline.num_parameter_slots,
+                constants: pipeline.constants.clone(),
+            },
+            _signature: share_signature(pipeline.signature),
+            refs: 1,
+            key,
+        };
+        self.compute
+            .lock()
+            .unwrap()
+            .entry(entry.key.bucket())
+            .or_insert_with(Vec::new)
+            .push(entry);
+    }
+
+    /// Called when a pipeline is destroyed, the reference of the cache to the pipeline
+    /// state is released with the last pipeline handed out for it.
+    ///
+    /// Has to be called before the pipeline releases its own reference,
+    /// so the pipeline state can't be replaced by another one at the same address.
+    pub(crate) fn release(&self, raw: *mut d3d12::ID3D12PipelineState) {
+        let mut graphics = self.graphics.lock().unwrap();
+        for entries in graphics.values_mut() {
+            if let Some(pos) = entries.iter().position(|entry| entry.pipeline.raw == raw) {
+                entries[pos].refs -= 1;
+                if entries[pos].refs == 0 {
+                    let entry = entries.swap_remove(pos);
+                    unsafe { (*entry.pipeline.raw).Release(); }
+                }
+                return;
+            }
+        }
+        drop(graphics);
+
+        let mut compute = self.compute.lock().unwrap();
+        for entries in compute.values_mut() {
+            if let Some(pos) = entries.iter().position(|entry| entry.pipeline.raw == raw) {
+                entries[pos].refs -= 1;
+                if entries[pos].refs == 0 {
+                    let entry = entries.swap_remove(pos);
+                    unsafe { (*entry.pipeline.raw).Release(); }
+                }
+                return;
+            }
+        }
+    }
+}
+
+impl Drop for PipelineDedup {
+    fn drop(&mut self) {
+        let graphics = self.graphics.get_mut().unwrap();
+        for entry in graphics.values().flat_map(|entries| entries.iter()) {
+            unsafe { (*entry.pipeline.raw).Release(); }
+        }
+        let compute = self.compute.get_mut().unwrap();
+        for entry in compute.values().flat_map(|entries| entries.iter()) {
+            unsafe { (*entry.pipeline.raw).Release(); }
+        }
+    }
+}
@@ -122,9 +122,22 @@ impl Heap {
         }
     }
 
+    pub fn free_handle(&mut self, handle: d3d12::D3D12_CPU_DESCRIPTOR_HANDLE) {
+        let slot = (handle.ptr - self.start.ptr) / self.handle_size;
+        assert!(slot < HEAP_SIZE_FIXED);
+        assert_eq!(self.availability & (1 << slot), 0);
+        // Set the slot as free.
+        self.availability ^= 1 << slot;
+    }
+
     pub fn is_full(&self) -> bool {
         self.availability == 0
     }
+
+    fn contains(&self, handle: d3d12::D3D12_CPU_DESCRIPTOR_HANDLE) -> bool {
+        handle.ptr >= self.start.ptr &&
+            handle.ptr < self.start.ptr + self.handle_size * HEAP_SIZE_FIXED
+    }
 }
 
 pub struct DescriptorCpuPool {
@@ -167,5 +180,13 @@ impl DescriptorCpuPool {
         handle
     }
 
-    // TODO: free handles
+    pub fn free_handle(&mut self, handle: d3d12::D3D12_CPU_DESCRIPTOR_HANDLE) {
+        let heap_id = self
+            .heaps
+            .iter()
+            .position(|heap| heap.contains(handle))
+            .expect("Descriptor handle not allocated from this pool");
+        self.heaps[heap_id].free_handle(handle);
+        self.free_list.insert(heap_id);
+    }
 }
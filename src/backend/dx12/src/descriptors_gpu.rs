@@ -0,0 +1,166 @@
+//! Shader visible descriptor heaps.
+//!
+//! Only a single CBV/SRV/UAV and a single sampler heap can be bound to a command list,
+//! and switching heaps may cause the GPU to flush. Each device therefore owns one shader
+//! visible heap per type, which is bound once at the beginning of every command list.
+//!
+//! The heaps are split into two parts:
+//!  - Persistent ranges, managed by a free-list. Descriptor pools allocate their storage
+//!    from these and return it on destruction.
+//!  - Transient segments of fixed size, recycled in ring order. Command buffers acquire
+//!    segments for internal descriptors (e.g. blit sources) and release them on reset.
+//!    Once all segments are in use, command buffers take segments from the persistent
+//!    ranges instead.
+
+use std::collections::VecDeque;
+use std::ops::Range;
+use std::sync::{Arc, Mutex};
+
+use hal::device::OutOfMemory;
+
+use winapi::um::d3d12;
+use wio::com::ComPtr;
+
+use native::{DescriptorHeap, DualHandle};
+use range_alloc::RangeAllocator;
+
+/// Number of descriptors in a transient segment.
+pub(crate) const SEGMENT_SIZE: u64 = 256;
+
+pub struct HeapGpu {
+    pub(crate) raw: ComPtr<d3d12::ID3D12DescriptorHeap>,
+    pub(crate) handle_size: u64,
+    pub(crate) start: DualHandle,
+    persistent: Mutex<RangeAllocator<u64>>,
+    // Start indices of the free transient segments, oldest first.
+    segments: Mutex<VecDeque<u64>>,
+}
+
+unsafe impl Send for HeapGpu {}
+unsafe impl Sync for HeapGpu {}
+
+impl HeapGpu {
+    /// Split a shader visible heap, reserving `num_segments` transient segments at the end.
+    pub(crate) fn new(heap: DescriptorHeap, num_segments: u64) -> Self {
+        assert!(num_segments * SEGMENT_SIZE < heap.total_handles);
+        let transient_start = heap.total_handles - num_segments * SEGMENT_SIZE;
+
+        HeapGpu {
+            raw: heap.raw,
+            handle_size: heap.handle_size,
+            start: heap.start,
+            persistent: Mutex::new(RangeAllocator::new(0 .. transient_start)),
+            segments: Mutex::new(
+                (0 .. num_segments)
+                    .map(|i| transient_start + i * SEGMENT_SIZE)
+                    .collect(),
+            ),
+        }
+    }
+
+    pub(crate) fn at(&self, index: u64, size: u64) -> DualHandle {
+        DualHandle {
+            cpu: d3d12::D3D12_CPU_DESCRIPTOR_HANDLE { ptr: self.start.cpu.ptr + (self.handle_size * index) as usize },
+            gpu: d3d12::D3D12_GPU_DESCRIPTOR_HANDLE { ptr: self.start.gpu.ptr + self.handle_size * index },
+            size,
+        }
+    }
+
+    /// Allocate a persistent range of descriptors.
+    pub(crate) fn allocate(&self, count: u64) -> Option<Range<u64>> {
+        if count == 0 {
+            return Some(0 .. 0);
+        }
+        self.persistent
+            .lock()
+            .unwrap()
+            .allocate_range(count)
+            .ok()
+    }
+
+    /// Free a persistent range previously returned by `allocate`.
+    pub(crate) fn free(&self, range: Range<u64>) {
+        if range.start < range.end {
+            self.persistent.lock().unwrap().free_range(range);
+        }
+    }
+
+    fn acquire_segment(&self) -> Option<u64> {
+        self.segments.lock().unwrap().pop_front()
+    }
+
+    fn release_segment(&self, segment: u64) {
+        self.segments.lock().unwrap().push_back(segment);
+    }
+}
+
+// Segment of descriptors acquired by a command buffer.
+enum Segment {
+    // Transient segment, starting at the given index.
+    Ring(u64),
+    // Persistent range, used once all transient segments are in use.
+    Persistent(Range<u64>),
+}
+
+impl Segment {
+    fn start(&self) -> u64 {
+        match *self {
+            Segment::Ring(start) => start,
+            Segment::Persistent(ref range) => range.start,
+        }
+    }
+}
+
+/// Transient descriptors of a command buffer, allocated linearly from heap segments.
+///
+/// Segments are released on reset, at which point the GPU must have finished
+/// executing the command buffer.
+pub struct TransientDescriptors {
+    heap: Arc<HeapGpu>,
+    segments: Vec<Segment>,
+    // Number of descriptors used in the last segment.
+    offset: u64,
+}
+
+impl TransientDescriptors {
+    pub(crate) fn new(heap: Arc<HeapGpu>) -> Self {
+        TransientDescriptors {
+            heap,
+            segments: Vec::new(),
+            offset: 0,
+        }
+    }
+
+    /// Allocate `count` consecutive descriptors, failing if the heap is full.
+    pub(crate) fn alloc(&mut self, count: u64) -> Result<DualHandle, OutOfMemory> {
+        assert!(0 < count && count <= SEGMENT_SIZE);
+        if self.segments.is_empty() || self.offset + count > SEGMENT_SIZE {
+            let segment = match self.heap.acquire_segment() {
+                Some(start) => Segment::Ring(start),
+                None => Segment::Persistent(self.heap.allocate(SEGMENT_SIZE).ok_or(OutOfMemory)?),
+            };
+            self.segments.push(segment);
+            self.offset = 0;
+        }
+
+        let index = self.segments.last().unwrap().start() + self.offset;
+        self.offset += count;
+        Ok(self.heap.at(index, count))
+    }
+
+    pub(crate) fn reset(&mut self) {
+        for segment in self.segments.drain(..) {
+            match segment {
+                Segment::Ring(start) => self.heap.release_segment(start),
+                Segment::Persistent(range) => self.heap.free(range),
+            }
+        }
+        self.offset = 0;
+    }
+}
+
+impl Drop for TransientDescriptors {
+    fn drop(&mut self) {
+        self.reset();
+    }
+}
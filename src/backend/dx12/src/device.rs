@@ -1,12 +1,12 @@
 use std::borrow::Borrow;
 use std::collections::{BTreeMap, VecDeque};
 use std::ops::Range;
-use std::{ffi, mem, ptr, slice};
+use std::{ffi, mem, ptr, slice, thread, time};
 
 use spirv_cross::{hlsl, spirv, ErrorCode as SpirvErrorCode};
 
 use winapi::Interface;
-use winapi::um::{d3d12, d3dcommon, d3dcompiler, synchapi, winbase, winnt};
+use winapi::um::{d3d12, d3d12sdklayers, d3dcommon, d3dcompiler, synchapi, winbase, winnt};
 use winapi::shared::minwindef::{FALSE, TRUE, UINT};
 use winapi::shared::{dxgi, dxgi1_2, dxgi1_4, dxgiformat, dxgitype, winerror};
 use wio::com::ComPtr;
@@ -15,11 +15,13 @@ use hal::{self, buffer, device as d, error, format, image, mapping, memory, pass
 use hal::format::{Aspects, Format};
 use hal::memory::Requirements;
 use hal::pool::CommandPoolCreateFlags;
+use hal::profiler::FrameStatistics;
 use hal::queue::{RawCommandQueue, QueueFamilyId};
 use hal::range::RangeArg;
+use hal::sparse::SparseImageRequirements;
 
 use {
-    conv, descriptors_cpu, native as n, root_constants, window as w,
+    conv, dedup, descriptors_cpu, native as n, root_constants, window as w,
     Backend as B, Device, MemoryGroup, QUEUE_FAMILIES, MAX_VERTEX_BUFFERS, NUM_HEAP_PROPERTIES,
 };
 use pool::RawCommandPool;
@@ -27,7 +29,9 @@ use range_alloc::RangeAllocator;
 use root_constants::RootConstant;
 
 // Register space used for root constants.
-const ROOT_CONSTANT_SPACE: u32 = 0;
+// Descriptor set `N` uses `spaceN`, the number of sets is bounded by the
+// root signature size of 64 DWORDs.
+const ROOT_CONSTANT_SPACE: u32 = 64;
 
 const MEM_TYPE_MASK: u64 = 0x7;
 const MEM_TYPE_SHIFT: u64 = 3;
@@ -56,6 +60,76 @@ fn gen_query_error(err: SpirvErrorCode) -> d::ShaderError {
     d::ShaderError::CompilationFailed(msg)
 }
 
+// Name the resources left unnamed by the SPIR-V after their block type, binding or
+// location, so the generated code is debuggable in captures instead of using `_34`
+// identifiers. Names given by the SPIR-V are kept.
+fn name_shader_resources(ast: &mut spirv::Ast<hlsl::Target>) -> Result<(), d::ShaderError> {
+    let resources = ast.get_shader_resources().map_err(gen_query_error)?;
+    let is_unnamed = |ast: &mut spirv::Ast<hlsl::Target>, id| {
+        ast.get_name(id).map(|name| name.is_empty()).map_err(gen_query_error)
+    };
+    let decoration = |ast: &mut spirv::Ast<hlsl::Target>, id, kind| {
+        ast.get_decoration(id, kind).map_err(gen_query_error)
+    };
+
+    let blocks = resources.uniform_buffers
+        .iter()
+        .chain(&resources.storage_buffers)
+        .map(|resource| (resource, false))
+        .chain(resources.push_constant_buffers.iter().map(|resource| (resource, true)));
+    for (resource, push_constants) in blocks {
+        let binding = if push_constants {
+            "push_constants".to_owned()
+        } else {
+            format!(
+                "set{}_binding{}",
+                decoration(ast, resource.id, spirv::Decoration::DescriptorSet)?,
+                decoration(ast, resource.id, spirv::Decoration::Binding)?,
+            )
+        };
+        let type_name = ast.get_name(resource.base_type_id).map_err(gen_query_error)?;
+        if type_name.is_empty() {
+            ast.set_name(resource.base_type_id, &format!("{}_block", binding))
+                .map_err(gen_unexpected_error)?;
+        }
+        if is_unnamed(ast, resource.id)? {
+            let name = if type_name.is_empty() { binding } else { format!("{}_var", type_name) };
+            ast.set_name(resource.id, &name).map_err(gen_unexpected_error)?;
+        }
+    }
+
+    let bindings = resources.separate_images
+        .iter()
+        .chain(&resources.separate_samplers)
+        .chain(&resources.sampled_images)
+        .chain(&resources.storage_images)
+        .chain(&resources.subpass_inputs);
+    for resource in bindings {
+        if is_unnamed(ast, resource.id)? {
+            let name = format!(
+                "set{}_binding{}",
+                decoration(ast, resource.id, spirv::Decoration::DescriptorSet)?,
+                decoration(ast, resource.id, spirv::Decoration::Binding)?,
+            );
+            ast.set_name(resource.id, &name).map_err(gen_unexpected_error)?;
+        }
+    }
+
+    let interface = resources.stage_inputs
+        .iter()
+        .map(|resource| (resource, "in"))
+        .chain(resources.stage_outputs.iter().map(|resource| (resource, "out")));
+    for (resource, direction) in interface {
+        if is_unnamed(ast, resource.id)? {
+            let location = decoration(ast, resource.id, spirv::Decoration::Location)?;
+            ast.set_name(resource.id, &format!("{}_location{}", direction, location))
+                .map_err(gen_unexpected_error)?;
+        }
+    }
+
+    Ok(())
+}
+
 pub(crate) fn shader_bytecode(shader: *mut d3dcommon::ID3DBlob) -> d3d12::D3D12_SHADER_BYTECODE {
     unsafe {
         d3d12::D3D12_SHADER_BYTECODE {
@@ -73,6 +147,28 @@ pub(crate) fn shader_bytecode(shader: *mut d3dcommon::ID3DBlob) -> d3d12::D3D12_
     }
 }
 
+fn cached_pipeline_state(binary: &[u8]) -> d3d12::D3D12_CACHED_PIPELINE_STATE {
+    d3d12::D3D12_CACHED_PIPELINE_STATE {
+        pCachedBlob: if binary.is_empty() { ptr::null() } else { binary.as_ptr() as *const _ },
+        CachedBlobSizeInBytes: binary.len(),
+    }
+}
+
+/// Copies the cached blob of a pipeline state object, if the driver provides one.
+fn get_cached_blob(pipeline: *mut d3d12::ID3D12PipelineState) -> Option<Vec<u8>> {
+    let mut blob = ptr::null_mut();
+    let hr = unsafe { (*pipeline).GetCachedBlob(&mut blob) };
+    if !winerror::SUCCEEDED(hr) || blob.is_null() {
+        return None;
+    }
+
+    let blob = unsafe { ComPtr::<d3dcommon::ID3DBlob>::from_raw(blob) };
+    let data = unsafe {
+        slice::from_raw_parts(blob.GetBufferPointer() as *const u8, blob.GetBufferSize())
+    };
+    Some(data.to_vec())
+}
+
 #[derive(Clone, Debug)]
 pub(crate) struct ViewInfo {
     pub(crate) resource: *mut d3d12::ID3D12Resource,
@@ -80,9 +176,13 @@ pub(crate) struct ViewInfo {
     pub(crate) flags: image::StorageFlags,
     pub(crate) view_kind: image::ViewKind,
     pub(crate) format: dxgiformat::DXGI_FORMAT,
+    pub(crate) component_mapping: UINT,
     pub(crate) range: image::SubresourceRange,
 }
 
+/// Shader component mapping returning the components unchanged.
+pub(crate) const IDENTITY_MAPPING: UINT = 0x1688;
+
 pub(crate) enum CommandSignature {
     Draw,
     DrawIndexed,
@@ -114,6 +214,27 @@ pub struct UnboundImage {
     num_levels: image::Level,
 }
 
+/// Target profile of a shader stage, e.g. `ps_5_1`.
+pub(crate) fn shader_profile(stage: pso::Stage, shader_model: hlsl::ShaderModel) -> String {
+    let stage = match stage {
+        pso::Stage::Vertex => "vs",
+        pso::Stage::Hull => "hs",
+        pso::Stage::Domain => "ds",
+        pso::Stage::Geometry => "gs",
+        pso::Stage::Fragment => "ps",
+        pso::Stage::Compute => "cs",
+    };
+
+    let model = match shader_model {
+        hlsl::ShaderModel::V5_0 => "5_0",
+        hlsl::ShaderModel::V5_1 => "5_1",
+        hlsl::ShaderModel::V6_0 => "6_0",
+        _ => unimplemented!(),
+    };
+
+    format!("{}_{}", stage, model)
+}
+
 /// Compile a single shader entry point from a HLSL text shader
 pub(crate) fn compile_shader(
     stage: pso::Stage,
@@ -121,24 +242,6 @@ pub(crate) fn compile_shader(
     entry: &str,
     code: &[u8],
 ) -> Result<*mut d3dcommon::ID3DBlob, d::ShaderError> {
-    let stage_to_str = |stage, shader_model| {
-        let stage = match stage {
-            pso::Stage::Vertex => "vs",
-            pso::Stage::Fragment => "ps",
-            pso::Stage::Compute => "cs",
-            _ => unimplemented!(),
-        };
-
-        let model = match shader_model {
-            hlsl::ShaderModel::V5_0 => "5_0",
-            hlsl::ShaderModel::V5_1 => "5_1",
-            hlsl::ShaderModel::V6_0 => "6_0",
-            _ => unimplemented!(),
-        };
-
-        format!("{}_{}\0", stage, model)
-    };
-
     let mut blob = ptr::null_mut();
     let mut error = ptr::null_mut();
     let entry = ffi::CString::new(entry).unwrap();
@@ -150,7 +253,7 @@ pub(crate) fn compile_shader(
             ptr::null(),
             ptr::null_mut(),
             entry.as_ptr() as *const _,
-            stage_to_str(stage, shader_model).as_ptr() as *const i8,
+            format!("{}\0", shader_profile(stage, shader_model)).as_ptr() as *const i8,
             1,
             0,
             &mut blob as *mut *mut _,
@@ -256,6 +359,44 @@ impl GraphicsPipelineStateSubobjectStream {
 }
 
 impl Device {
+    /// Run `fun`, collecting the messages the debug layer stores in the info queue meanwhile.
+    ///
+    /// The info queue is only available if the debug layer is enabled in debug builds.
+    fn capture_messages<R, F: FnOnce() -> R>(&self, fun: F) -> (R, error::ErrorContext) {
+        let info_queue = if cfg!(debug_assertions) {
+            self.raw.cast::<d3d12sdklayers::ID3D12InfoQueue>().ok()
+        } else {
+            None
+        };
+        let info_queue = match info_queue {
+            Some(info_queue) => info_queue,
+            None => return (fun(), error::ErrorContext::default()),
+        };
+
+        let start = unsafe { info_queue.GetNumStoredMessages() };
+        let result = fun();
+        let end = unsafe { info_queue.GetNumStoredMessages() };
+
+        // Old messages are discarded once the queue is full, shifting the indices.
+        let start = if end < start { 0 } else { start };
+        let messages = (start .. end)
+            .filter_map(|index| unsafe {
+                let mut size = 0;
+                info_queue.GetMessage(index, ptr::null_mut(), &mut size);
+                // The description is stored right after `D3D12_MESSAGE`, keep the storage aligned.
+                let mut storage = vec![0u64; (size + 7) / 8];
+                let message = storage.as_mut_ptr() as *mut d3d12sdklayers::D3D12_MESSAGE;
+                if !winerror::SUCCEEDED(info_queue.GetMessage(index, message, &mut size)) {
+                    return None;
+                }
+                let description = ffi::CStr::from_ptr((*message).pDescription);
+                Some(description.to_string_lossy().into_owned())
+            })
+            .collect();
+
+        (result, error::ErrorContext { messages })
+    }
+
     fn parse_spirv(raw_data: &[u8]) -> Result<spirv::Ast<hlsl::Target>, d::ShaderError> {
         // spec requires "codeSize must be a multiple of 4"
         assert_eq!(raw_data.len() & 3, 0);
@@ -277,66 +418,6 @@ impl Device {
             })
     }
 
-    fn patch_spirv_resources(
-        ast: &mut spirv::Ast<hlsl::Target>,
-        layout: Option<&n::PipelineLayout>,
-    ) -> Result<(), d::ShaderError> {
-        // Patch descriptor sets due to the splitting of descriptor heaps into
-        // SrvCbvUav and sampler heap. Each set will have a new location to match
-        // the layout of the root signatures.
-        let space_offset = match layout {
-            Some(layout) if !layout.root_constants.is_empty() => 1,
-            _ => 0,
-        };
-
-        let shader_resources = ast.get_shader_resources().map_err(gen_query_error)?;
-        for image in &shader_resources.separate_images {
-            let set = ast.get_decoration(image.id, spirv::Decoration::DescriptorSet).map_err(gen_query_error)?;
-            ast.set_decoration(image.id, spirv::Decoration::DescriptorSet, space_offset + set)
-               .map_err(gen_unexpected_error)?;
-        }
-
-        for uniform_buffer in &shader_resources.uniform_buffers {
-            let set = ast.get_decoration(uniform_buffer.id, spirv::Decoration::DescriptorSet).map_err(gen_query_error)?;
-            ast.set_decoration(uniform_buffer.id, spirv::Decoration::DescriptorSet, space_offset + set)
-               .map_err(gen_unexpected_error)?;
-        }
-
-        for storage_buffer in &shader_resources.storage_buffers {
-            let set = ast.get_decoration(storage_buffer.id, spirv::Decoration::DescriptorSet).map_err(gen_query_error)?;
-            ast.set_decoration(storage_buffer.id, spirv::Decoration::DescriptorSet, space_offset + set)
-               .map_err(gen_unexpected_error)?;
-        }
-
-        for image in &shader_resources.storage_images {
-            let set = ast.get_decoration(image.id, spirv::Decoration::DescriptorSet).map_err(gen_query_error)?;
-            ast.set_decoration(image.id, spirv::Decoration::DescriptorSet, space_offset + set)
-               .map_err(gen_unexpected_error)?;
-        }
-
-        for sampler in &shader_resources.separate_samplers {
-            let set = ast.get_decoration(sampler.id, spirv::Decoration::DescriptorSet).map_err(gen_query_error)?;
-            ast.set_decoration(sampler.id, spirv::Decoration::DescriptorSet, space_offset + set)
-               .map_err(gen_unexpected_error)?;
-        }
-
-        for image in &shader_resources.sampled_images {
-            let set = ast.get_decoration(image.id, spirv::Decoration::DescriptorSet).map_err(gen_query_error)?;
-            ast.set_decoration(image.id, spirv::Decoration::DescriptorSet, space_offset + set)
-               .map_err(gen_unexpected_error)?;
-        }
-
-        for input in &shader_resources.subpass_inputs {
-            let set = ast.get_decoration(input.id, spirv::Decoration::DescriptorSet).map_err(gen_query_error)?;
-            ast.set_decoration(input.id, spirv::Decoration::DescriptorSet, space_offset + set)
-               .map_err(gen_unexpected_error)?;
-        }
-
-        // TODO: other resources
-
-        Ok(())
-    }
-
     fn translate_spirv(
         ast: &mut spirv::Ast<hlsl::Target>,
         shader_model: hlsl::ShaderModel,
@@ -356,12 +437,16 @@ impl Device {
                     start: constant.range.start * 4,
                     end: constant.range.end * 4,
                     binding: constant.range.start,
-                    space: 0,
+                    space: ROOT_CONSTANT_SPACE,
                 })
             } else {
                 None
             })
-            .collect();
+            .collect::<Vec<_>>();
+        Self::check_push_constants(ast, &root_constant_layout, stage)?;
+        if cfg!(debug_assertions) {
+            name_shader_resources(ast)?;
+        }
         ast.set_compiler_options(&compile_options)
             .map_err(gen_unexpected_error)?;
         ast.set_root_constant_layout(root_constant_layout)
@@ -376,10 +461,57 @@ impl Device {
             })
     }
 
+    // Without a root constant covering a member of the push constant block, SPIRV-Cross
+    // falls back to a constant buffer which is never bound.
+    fn check_push_constants(
+        ast: &spirv::Ast<hlsl::Target>,
+        root_constants: &[hlsl::RootConstant],
+        stage: pso::Stage,
+    ) -> Result<(), d::ShaderError> {
+        let resources = ast.get_shader_resources().map_err(gen_unexpected_error)?;
+        for pc in &resources.push_constant_buffers {
+            let member_count = match ast.get_type(pc.base_type_id).map_err(gen_unexpected_error)? {
+                spirv::Type::Struct { member_types, .. } => member_types.len(),
+                _ => continue,
+            };
+            for index in 0 .. member_count as u32 {
+                let offset = ast
+                    .get_member_decoration(pc.base_type_id, index, spirv::Decoration::Offset)
+                    .map_err(gen_unexpected_error)?;
+                if !root_constants.iter().any(|constant| constant.start <= offset && offset < constant.end) {
+                    return Err(d::ShaderError::CompilationFailed(format!(
+                        "Push constant at offset {} of the {:?} shader is outside of the pipeline layout push constant ranges",
+                        offset,
+                        stage,
+                    )));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    // Compile HLSL with `D3DCompile`, or DXC for shader model 6.
+    fn compile_hlsl(
+        &self,
+        stage: pso::Stage,
+        shader_model: hlsl::ShaderModel,
+        entry: &str,
+        code: &[u8],
+    ) -> Result<*mut d3dcommon::ID3DBlob, d::ShaderError> {
+        match shader_model {
+            hlsl::ShaderModel::V6_0 => match self.dxc {
+                Some(ref dxc) => dxc.compile(&shader_profile(stage, shader_model), entry, code),
+                None => Err(d::ShaderError::CompilationFailed("Shader model 6.0 requires dxcompiler.dll".into())),
+            },
+            _ => compile_shader(stage, shader_model, entry, code),
+        }
+    }
+
     // Extract entry point from shader module on pipeline creation.
     // Returns compiled shader blob and bool to indicate if the shader should be
     // destroyed after pipeline creation
     fn extract_entry_point(
+        &self,
         stage: pso::Stage,
         source: &pso::EntryPoint<B>,
         layout: &n::PipelineLayout,
@@ -394,6 +526,11 @@ impl Device {
                     .ok_or(d::ShaderError::MissingEntryPoint(source.entry.into()))
             }
             n::ShaderModule::Spirv(ref raw_data) => {
+                let required_features = pso::reflect_spirv(raw_data)?.required_features;
+                let missing = required_features - self.features;
+                if !missing.is_empty() {
+                    return Err(d::ShaderError::MissingFeatures(missing));
+                }
                 let mut ast = Self::parse_spirv(raw_data)?;
                 let spec_constants = ast
                     .get_specialization_constants()
@@ -406,23 +543,20 @@ impl Device {
                         .find(|c| c.id == spec_constant.constant_id)
                     {
                         // Override specialization constant values
-                        unsafe {
-                            let value = match constant.value {
-                                pso::Constant::Bool(v) => v as u64,
-                                pso::Constant::U32(v) => v as u64,
-                                pso::Constant::U64(v) => v,
-                                pso::Constant::I32(v) => *(&v as *const _ as *const u64),
-                                pso::Constant::I64(v) => *(&v as *const _ as *const u64),
-                                pso::Constant::F32(v) => *(&v as *const _ as *const u64),
-                                pso::Constant::F64(v) => *(&v as *const _ as *const u64),
-                            };
-                            ast.set_scalar_constant(spec_constant.id, value).map_err(gen_query_error)?;
-                        }
+                        ast.set_scalar_constant(spec_constant.id, constant.value.to_bits())
+                            .map_err(gen_query_error)?;
                     }
                 }
 
-                Self::patch_spirv_resources(&mut ast, Some(layout))?;
-                let shader_model = hlsl::ShaderModel::V5_1;
+                // Wave intrinsics are only available from shader model 6.0.
+                let wave_ops = hal::Features::SUBGROUP_BASIC | hal::Features::SUBGROUP_VOTE |
+                    hal::Features::SUBGROUP_ARITHMETIC | hal::Features::SUBGROUP_BALLOT |
+                    hal::Features::SUBGROUP_QUAD;
+                let shader_model = if required_features.intersects(wave_ops) {
+                    hlsl::ShaderModel::V6_0
+                } else {
+                    self.shader_model
+                };
                 let shader_code = Self::translate_spirv(&mut ast, shader_model, layout, stage)?;
                 debug!("SPIRV-Cross generated shader:\n{}", shader_code);
 
@@ -437,7 +571,7 @@ impl Device {
                     .ok_or(d::ShaderError::MissingEntryPoint(source.entry.into()))
                     .and_then(|entry_point| {
                         let stage = conv::map_execution_model(entry_point.execution_model);
-                        let shader = compile_shader(
+                        let shader = self.compile_hlsl(
                             stage,
                             shader_model,
                             &entry_point.name,
@@ -458,7 +592,7 @@ impl Device {
         code: &[u8],
     ) -> Result<n::ShaderModule, d::ShaderError> {
         let mut shader_map = BTreeMap::new();
-        let blob = compile_shader(stage, hlsl::ShaderModel::V5_1, hlsl_entry, code)?;
+        let blob = self.compile_hlsl(stage, self.shader_model, hlsl_entry, code)?;
         shader_map.insert(entry_point.into(), blob);
         Ok(n::ShaderModule::Compiled(shader_map))
     }
@@ -559,6 +693,52 @@ impl Device {
         }
     }
 
+    /// Create a buffer in its own implicit heap, used for internal resources
+    /// which are not backed by user allocated memory.
+    fn create_committed_buffer(
+        &self,
+        heap_type: d3d12::D3D12_HEAP_TYPE,
+        size: u64,
+        state: d3d12::D3D12_RESOURCE_STATES,
+    ) -> ComPtr<d3d12::ID3D12Resource> {
+        let properties = d3d12::D3D12_HEAP_PROPERTIES {
+            Type: heap_type,
+            CPUPageProperty: d3d12::D3D12_CPU_PAGE_PROPERTY_UNKNOWN,
+            MemoryPoolPreference: d3d12::D3D12_MEMORY_POOL_UNKNOWN,
+            CreationNodeMask: 0,
+            VisibleNodeMask: 0,
+        };
+        let desc = d3d12::D3D12_RESOURCE_DESC {
+            Dimension: d3d12::D3D12_RESOURCE_DIMENSION_BUFFER,
+            Alignment: 0,
+            Width: size,
+            Height: 1,
+            DepthOrArraySize: 1,
+            MipLevels: 1,
+            Format: dxgiformat::DXGI_FORMAT_UNKNOWN,
+            SampleDesc: dxgitype::DXGI_SAMPLE_DESC {
+                Count: 1,
+                Quality: 0,
+            },
+            Layout: d3d12::D3D12_TEXTURE_LAYOUT_ROW_MAJOR,
+            Flags: d3d12::D3D12_RESOURCE_FLAG_NONE,
+        };
+
+        let mut resource = ptr::null_mut();
+        assert_eq!(winerror::S_OK, unsafe {
+            self.raw.clone().CreateCommittedResource(
+                &properties,
+                d3d12::D3D12_HEAP_FLAG_NONE,
+                &desc,
+                state,
+                ptr::null(),
+                &d3d12::ID3D12Resource::uuidof(),
+                &mut resource,
+            )
+        });
+        unsafe { ComPtr::from_raw(resource as *mut _) }
+    }
+
     pub(crate) fn view_image_as_render_target_impl(
         device: &mut ComPtr<d3d12::ID3D12Device>,
         handle: d3d12::D3D12_CPU_DESCRIPTOR_HANDLE,
@@ -663,13 +843,14 @@ impl Device {
         device: &mut ComPtr<d3d12::ID3D12Device>,
         handle: d3d12::D3D12_CPU_DESCRIPTOR_HANDLE,
         info: ViewInfo,
+        flags: d3d12::D3D12_DSV_FLAGS,
     ) -> Result<(), image::ViewError> {
         #![allow(non_snake_case)]
 
         let mut desc = d3d12::D3D12_DEPTH_STENCIL_VIEW_DESC {
             Format: info.format,
             ViewDimension: 0,
-            Flags: 0,
+            Flags: flags,
             u: unsafe { mem::zeroed() },
         };
 
@@ -737,10 +918,10 @@ impl Device {
     }
 
     fn view_image_as_depth_stencil(
-        &self, info: ViewInfo
+        &self, info: ViewInfo, flags: d3d12::D3D12_DSV_FLAGS,
     ) -> Result<d3d12::D3D12_CPU_DESCRIPTOR_HANDLE, image::ViewError> {
         let handle = self.dsv_pool.lock().unwrap().alloc_handle();
-        Self::view_image_as_depth_stencil_impl(&mut self.raw.clone(), handle, info)
+        Self::view_image_as_depth_stencil_impl(&mut self.raw.clone(), handle, info, flags)
             .map(|_| handle)
     }
 
@@ -750,7 +931,7 @@ impl Device {
         let mut desc = d3d12::D3D12_SHADER_RESOURCE_VIEW_DESC {
             Format: info.format,
             ViewDimension: 0,
-            Shader4ComponentMapping: 0x1688, // TODO: map swizzle
+            Shader4ComponentMapping: info.component_mapping,
             u: unsafe { mem::zeroed() },
         };
 
@@ -949,6 +1130,49 @@ impl Device {
         Ok(handle)
     }
 
+    /// Block until all or one of the fences reach their values.
+    fn wait_for_raw_fences<'a, I>(&self, fences: I, wait: d::WaitFor, timeout_ms: u32) -> bool
+    where
+        I: IntoIterator<Item = (&'a ComPtr<d3d12::ID3D12Fence>, u64)>,
+    {
+        let fences = fences.into_iter().collect::<Vec<_>>();
+        let mut events = self.events.lock().unwrap();
+        for _ in events.len() .. fences.len() {
+            events.push(unsafe {
+                synchapi::CreateEventA(
+                    ptr::null_mut(),
+                    FALSE,
+                    FALSE,
+                    ptr::null(),
+                )
+            });
+        }
+
+        for (&event, &(fence, value)) in events.iter().zip(fences.iter()) {
+            assert_eq!(winerror::S_OK, unsafe {
+                synchapi::ResetEvent(event);
+                fence.clone().SetEventOnCompletion(value, event)
+            });
+        }
+
+        let all = match wait {
+            d::WaitFor::Any => FALSE,
+            d::WaitFor::All => TRUE,
+        };
+        let hr = unsafe {
+            synchapi::WaitForMultipleObjects(fences.len() as u32, events.as_ptr(), all, timeout_ms)
+        };
+
+        const WAIT_OBJECT_LAST: u32 = winbase::WAIT_OBJECT_0 + winnt::MAXIMUM_WAIT_OBJECTS;
+        const WAIT_ABANDONED_LAST: u32 = winbase::WAIT_ABANDONED_0 + winnt::MAXIMUM_WAIT_OBJECTS;
+        match hr {
+            winbase::WAIT_OBJECT_0 ... WAIT_OBJECT_LAST => true,
+            winbase::WAIT_ABANDONED_0 ... WAIT_ABANDONED_LAST => true, //TODO?
+            winerror::WAIT_TIMEOUT => false,
+            _ => panic!("Unexpected wait status 0x{:X}", hr),
+        }
+    }
+
     pub(crate) fn create_raw_fence(&self, signalled: bool) -> *mut d3d12::ID3D12Fence {
         let mut handle = ptr::null_mut();
         assert_eq!(winerror::S_OK, unsafe {
@@ -961,67 +1185,250 @@ impl Device {
         });
         handle as *mut _
     }
-}
 
-impl d::Device<B> for Device {
-    fn allocate_memory(
+    /// Wrap an existing texture resource into an `Image`,
+    /// e.g. a swapchain image handed over by OpenXR.
+    ///
+    /// The parameters must match the description of the resource. The image doesn't take
+    /// ownership of the resource and must not be destroyed with `destroy_image`.
+    pub unsafe fn image_from_raw(
         &self,
-        mem_type: hal::MemoryTypeId,
-        size: u64,
-    ) -> Result<n::Memory, d::OutOfMemory> {
-        let mem_type = mem_type.0;
-        let mem_base_id = mem_type % NUM_HEAP_PROPERTIES;
-        let heap_property = &self.heap_properties[mem_base_id];
+        resource: *mut d3d12::ID3D12Resource,
+        kind: image::Kind,
+        mip_levels: image::Level,
+        format: Format,
+        usage: image::Usage,
+        storage_flags: image::StorageFlags,
+    ) -> Result<n::Image, image::CreationError> {
+        let image = d::Device::create_image(
+            self,
+            kind,
+            mip_levels,
+            format,
+            image::Tiling::Optimal,
+            usage,
+            storage_flags,
+        )?;
+        Ok(self.image_from_resource(resource, image, n::Place::SwapChain))
+    }
 
-        let properties = d3d12::D3D12_HEAP_PROPERTIES {
-            Type: d3d12::D3D12_HEAP_TYPE_CUSTOM,
-            CPUPageProperty: heap_property.page_property,
-            MemoryPoolPreference: heap_property.memory_pool,
-            CreationNodeMask: 0,
-            VisibleNodeMask: 0,
-        };
+    fn buffer_from_resource(
+        &self,
+        resource: *mut d3d12::ID3D12Resource,
+        size: u64,
+        usage: buffer::Usage,
+    ) -> n::Buffer {
+        let clear_uav = if usage.contains(buffer::Usage::TRANSFER_DST) {
+            let handle = self.srv_uav_pool.lock().unwrap().alloc_handle();
+            let mut view_desc = d3d12::D3D12_UNORDERED_ACCESS_VIEW_DESC {
+                Format: dxgiformat::DXGI_FORMAT_R32_TYPELESS,
+                ViewDimension: d3d12::D3D12_UAV_DIMENSION_BUFFER,
+                u: unsafe { mem::zeroed() },
+            };
 
-        // Exposed memory types are grouped according to their capabilities.
-        // See `MemoryGroup` for more details.
-        let mem_group = mem_type / NUM_HEAP_PROPERTIES;
+           *unsafe { view_desc.u.Buffer_mut() } = d3d12::D3D12_BUFFER_UAV {
+                FirstElement: 0,
+                NumElements: (size / 4) as _,
+                StructureByteStride: 0,
+                CounterOffsetInBytes: 0,
+                Flags: d3d12::D3D12_BUFFER_UAV_FLAG_RAW,
+            };
 
-        let desc = d3d12::D3D12_HEAP_DESC {
-            SizeInBytes: size,
-            Properties: properties,
-            Alignment: d3d12::D3D12_DEFAULT_MSAA_RESOURCE_PLACEMENT_ALIGNMENT as _, // TODO: not always..?
-            Flags: match mem_group {
-                0 => d3d12::D3D12_HEAP_FLAG_ALLOW_ALL_BUFFERS_AND_TEXTURES,
-                1 => d3d12::D3D12_HEAP_FLAG_ALLOW_ONLY_BUFFERS,
-                2 => d3d12::D3D12_HEAP_FLAG_ALLOW_ONLY_NON_RT_DS_TEXTURES,
-                3 => d3d12::D3D12_HEAP_FLAG_ALLOW_ONLY_RT_DS_TEXTURES,
-                _ => unreachable!()
-            },
+            unsafe {
+                self.raw.clone().CreateUnorderedAccessView(
+                    resource,
+                    ptr::null_mut(),
+                    &view_desc,
+                    handle,
+                );
+            }
+            Some(handle)
+        } else {
+            None
         };
 
-        let mut heap = ptr::null_mut();
-        let hr = unsafe {
-            self.raw.clone().CreateHeap(&desc, &d3d12::ID3D12Heap::uuidof(), &mut heap)
-        };
-        if hr == winerror::E_OUTOFMEMORY {
-            return Err(d::OutOfMemory);
+        n::Buffer {
+            resource,
+            size_in_bytes: size as _,
+            clear_uav,
         }
-        assert_eq!(winerror::S_OK, hr);
+    }
 
-        // The first memory heap of each group corresponds to the default heap, which is can never
-        // be mapped.
-        // Devices supporting heap tier 1 can only created buffers on mem group 1 (ALLOW_ONLY_BUFFERS).
-        // Devices supporting heap tier 2 always expose only mem group 0 and don't have any further restrictions.
-        let is_mapable = mem_base_id != 0 &&
-            (mem_group == MemoryGroup::Universal as _ || mem_group == MemoryGroup::BufferOnly as _);
+    fn image_from_resource(
+        &self,
+        resource: *mut d3d12::ID3D12Resource,
+        image: UnboundImage,
+        place: n::Place,
+    ) -> n::Image {
+        use self::image::Usage;
 
-        // Create a buffer resource covering the whole memory slice to be able to map the whole memory.
-        let resource = if is_mapable {
-            let mut resource = ptr::null_mut();
-            let desc = d3d12::D3D12_RESOURCE_DESC {
-                Dimension: d3d12::D3D12_RESOURCE_DIMENSION_BUFFER,
-                Alignment: 0,
-                Width: size,
-                Height: 1,
+        let num_layers = image.kind.num_layers();
+        let num_levels = image.desc.MipLevels as image::Level;
+
+        let info = ViewInfo {
+            resource,
+            kind: image.kind,
+            flags: image::StorageFlags::empty(),
+            view_kind: match image.kind {
+                image::Kind::D1(..) => image::ViewKind::D1Array,
+                image::Kind::D2(..) => image::ViewKind::D2Array,
+                image::Kind::D3(..) => image::ViewKind::D3,
+            },
+            format: image.desc.Format,
+            component_mapping: IDENTITY_MAPPING,
+            range: image::SubresourceRange {
+                aspects: Aspects::empty(),
+                levels: 0 .. 0,
+                layers: 0 .. 0,
+            },
+        };
+
+        //TODO: the clear_Xv is incomplete. We should support clearing images created without XXX_ATTACHMENT usage.
+        // for this, we need to check the format and force the `RENDER_TARGET` flag behind the user's back
+        // if the format supports being rendered into, allowing us to create clear_Xv
+        let format_properties = &self.format_properties[image.format as usize];
+        let props = match image.tiling {
+            image::Tiling::Optimal => format_properties.optimal_tiling,
+            image::Tiling::Linear => format_properties.linear_tiling,
+        };
+        let can_clear_color = image.usage.intersects(Usage::TRANSFER_DST | Usage::COLOR_ATTACHMENT) &&
+            props.contains(format::ImageFeature::COLOR_ATTACHMENT);
+        let can_clear_depth = image.usage.intersects(Usage::TRANSFER_DST | Usage::DEPTH_STENCIL_ATTACHMENT) &&
+            props.contains(format::ImageFeature::DEPTH_STENCIL_ATTACHMENT);
+        let aspects = image.format.surface_desc().aspects;
+
+        n::Image {
+            resource,
+            place,
+            surface_type: image.format.base_format().0,
+            kind: image.kind,
+            usage: image.usage,
+            storage_flags: image.storage_flags,
+            descriptor: image.desc,
+            bytes_per_block: image.bytes_per_block,
+            block_dim: image.block_dim,
+            clear_cv: if aspects.contains(Aspects::COLOR) && can_clear_color {
+                // One view per subresource, indexed by `Image::calc_subresource`.
+                (0 .. num_layers)
+                    .flat_map(|layer| (0 .. num_levels).map(move |level| (level, layer)))
+                    .map(|(level, layer)| {
+                        self.view_image_as_render_target(
+                            ViewInfo {
+                                range: image::SubresourceRange {
+                                    aspects: Aspects::COLOR,
+                                    levels: level .. level + 1,
+                                    layers: layer .. layer + 1,
+                                },
+                                .. info.clone()
+                            }).unwrap()
+                    })
+                    .collect()
+            } else {
+                Vec::new()
+            },
+            clear_dv: if aspects.contains(Aspects::DEPTH) && can_clear_depth {
+                // One view per subresource, indexed by `Image::calc_subresource`.
+                (0 .. num_layers)
+                    .flat_map(|layer| (0 .. num_levels).map(move |level| (level, layer)))
+                    .map(|(level, layer)| {
+                        self.view_image_as_depth_stencil(
+                            ViewInfo {
+                                format: image.dsv_format,
+                                range: image::SubresourceRange {
+                                    aspects: Aspects::DEPTH,
+                                    levels: level .. level + 1,
+                                    layers: layer .. layer + 1,
+                                },
+                                .. info.clone()
+                            }, d3d12::D3D12_DSV_FLAG_NONE).unwrap()
+                    })
+                    .collect()
+            } else {
+                Vec::new()
+            },
+            clear_sv: if aspects.contains(Aspects::STENCIL) && can_clear_depth {
+                // One view per subresource, indexed by `Image::calc_subresource`.
+                (0 .. num_layers)
+                    .flat_map(|layer| (0 .. num_levels).map(move |level| (level, layer)))
+                    .map(|(level, layer)| {
+                        self.view_image_as_depth_stencil(
+                            ViewInfo {
+                                format: image.dsv_format,
+                                range: image::SubresourceRange {
+                                    aspects: Aspects::STENCIL,
+                                    levels: level .. level + 1,
+                                    layers: layer .. layer + 1,
+                                },
+                                .. info.clone()
+                            }, d3d12::D3D12_DSV_FLAG_NONE).unwrap()
+                    })
+                    .collect()
+            } else {
+                Vec::new()
+            },
+        }
+    }
+}
+
+impl d::Device<B> for Device {
+    fn allocate_memory(
+        &self,
+        mem_type: hal::MemoryTypeId,
+        size: u64,
+    ) -> Result<n::Memory, d::OutOfMemory> {
+        let mem_type = mem_type.0;
+        let mem_base_id = mem_type % NUM_HEAP_PROPERTIES;
+        let heap_property = &self.heap_properties[mem_base_id];
+
+        let properties = d3d12::D3D12_HEAP_PROPERTIES {
+            Type: d3d12::D3D12_HEAP_TYPE_CUSTOM,
+            CPUPageProperty: heap_property.page_property,
+            MemoryPoolPreference: heap_property.memory_pool,
+            CreationNodeMask: 0,
+            VisibleNodeMask: 0,
+        };
+
+        // Exposed memory types are grouped according to their capabilities.
+        // See `MemoryGroup` for more details.
+        let mem_group = mem_type / NUM_HEAP_PROPERTIES;
+
+        let desc = d3d12::D3D12_HEAP_DESC {
+            SizeInBytes: size,
+            Properties: properties,
+            Alignment: d3d12::D3D12_DEFAULT_MSAA_RESOURCE_PLACEMENT_ALIGNMENT as _, // TODO: not always..?
+            Flags: match mem_group {
+                0 => d3d12::D3D12_HEAP_FLAG_ALLOW_ALL_BUFFERS_AND_TEXTURES,
+                1 => d3d12::D3D12_HEAP_FLAG_ALLOW_ONLY_BUFFERS,
+                2 => d3d12::D3D12_HEAP_FLAG_ALLOW_ONLY_NON_RT_DS_TEXTURES,
+                3 => d3d12::D3D12_HEAP_FLAG_ALLOW_ONLY_RT_DS_TEXTURES,
+                _ => unreachable!()
+            },
+        };
+
+        let mut heap = ptr::null_mut();
+        let hr = unsafe {
+            self.raw.clone().CreateHeap(&desc, &d3d12::ID3D12Heap::uuidof(), &mut heap)
+        };
+        if hr == winerror::E_OUTOFMEMORY {
+            return Err(d::OutOfMemory);
+        }
+        assert_eq!(winerror::S_OK, hr);
+
+        // The first memory heap of each group corresponds to the default heap, which is can never
+        // be mapped.
+        // Devices supporting heap tier 1 can only created buffers on mem group 1 (ALLOW_ONLY_BUFFERS).
+        // Devices supporting heap tier 2 always expose only mem group 0 and don't have any further restrictions.
+        let is_mapable = mem_base_id != 0 &&
+            (mem_group == MemoryGroup::Universal as _ || mem_group == MemoryGroup::BufferOnly as _);
+
+        // Create a buffer resource covering the whole memory slice to be able to map the whole memory.
+        let resource = if is_mapable {
+            let mut resource = ptr::null_mut();
+            let desc = d3d12::D3D12_RESOURCE_DESC {
+                Dimension: d3d12::D3D12_RESOURCE_DIMENSION_BUFFER,
+                Alignment: 0,
+                Width: size,
+                Height: 1,
                 DepthOrArraySize: 1,
                 MipLevels: 1,
                 Format: dxgiformat::DXGI_FORMAT_UNKNOWN,
@@ -1080,6 +1487,7 @@ impl d::Device<B> for Device {
             inner: unsafe { ComPtr::from_raw(command_allocator) },
             device: self.raw.clone(),
             list_type,
+            bundle_allocator: None,
             shared: self.shared.clone(),
         }
     }
@@ -1102,9 +1510,12 @@ impl d::Device<B> for Device {
         ID: IntoIterator,
         ID::Item: Borrow<pass::SubpassDependency>,
     {
+        // States of the first plane, and of the stencil plane for depth-stencil formats.
+        type PlaneStates = (d3d12::D3D12_RESOURCE_STATES, d3d12::D3D12_RESOURCE_STATES);
+
         #[derive(Copy, Clone, Debug, PartialEq)]
         pub enum SubState {
-            New(d3d12::D3D12_RESOURCE_STATES),
+            New(PlaneStates),
             // Color attachment which will be resolved at the end of the subpass
             Resolve(d3d12::D3D12_RESOURCE_STATES),
             Preserve,
@@ -1112,11 +1523,27 @@ impl d::Device<B> for Device {
         }
         struct AttachmentInfo {
             sub_states: Vec<SubState>,
-            target_state: d3d12::D3D12_RESOURCE_STATES,
-            last_state: d3d12::D3D12_RESOURCE_STATES,
+            last_state: PlaneStates,
             barrier_start_index: usize,
         }
 
+        // Transition all planes at once unless they end up in different states.
+        fn plane_barriers(
+            att_id: pass::AttachmentId, from: PlaneStates, to: PlaneStates
+        ) -> Vec<n::BarrierDesc> {
+            if from.0 == from.1 && to.0 == to.1 {
+                return vec![n::BarrierDesc::new(att_id, from.0 .. to.0)];
+            }
+            let mut barriers = Vec::new();
+            if from.0 != to.0 {
+                barriers.push(n::BarrierDesc::new(att_id, from.0 .. to.0).for_plane(0));
+            }
+            if from.1 != to.1 {
+                barriers.push(n::BarrierDesc::new(att_id, from.1 .. to.1).for_plane(1));
+            }
+            barriers
+        }
+
         let attachments = attachments.into_iter()
                                      .map(|attachment| attachment.borrow().clone())
                                      .collect::<Vec<_>>();
@@ -1124,15 +1551,13 @@ impl d::Device<B> for Device {
         let dependencies = dependencies.into_iter().collect::<Vec<_>>();
         let mut att_infos = attachments
             .iter()
-            .map(|att| AttachmentInfo {
-                sub_states: vec![SubState::Undefined; subpasses.len()],
-                target_state: if att.format.map_or(false, |f| f.is_depth()) {
-                    d3d12::D3D12_RESOURCE_STATE_DEPTH_WRITE //TODO?
-                } else {
-                    d3d12::D3D12_RESOURCE_STATE_RENDER_TARGET
-                },
-                last_state: conv::map_image_resource_state(image::Access::empty(), att.layouts.start),
-                barrier_start_index: 0,
+            .map(|att| {
+                let state = conv::map_image_resource_state(image::Access::empty(), att.layouts.start);
+                AttachmentInfo {
+                    sub_states: vec![SubState::Undefined; subpasses.len()],
+                    last_state: (state, state),
+                    barrier_start_index: 0,
+                }
             })
             .collect::<Vec<_>>();
 
@@ -1140,26 +1565,28 @@ impl d::Device<B> for Device {
         for (sid, sub) in subpasses.iter().enumerate() {
             let sub = sub.borrow();
             for (i, &(id, _layout)) in sub.colors.iter().enumerate() {
-                let dst_state = att_infos[id].target_state;
+                let dst_state = d3d12::D3D12_RESOURCE_STATE_RENDER_TARGET;
                 let state = match sub.resolves.get(i) {
                     Some(_) => SubState::Resolve(dst_state),
-                    None => SubState::New(dst_state),
+                    None => SubState::New((dst_state, dst_state)),
                 };
                 let old = mem::replace(&mut att_infos[id].sub_states[sid], state);
                 debug_assert_eq!(SubState::Undefined, old);
             }
-            for &(id, _layout) in sub.depth_stencil {
-                let state = SubState::New(att_infos[id].target_state);
+            for &(id, layout) in sub.depth_stencil {
+                let aspects = attachments[id].format.map_or(Aspects::DEPTH, |f| f.surface_desc().aspects);
+                let state = SubState::New(conv::map_depth_stencil_attachment_states(layout, aspects));
                 let old = mem::replace(&mut att_infos[id].sub_states[sid], state);
                 debug_assert_eq!(SubState::Undefined, old);
             }
             for &(id, _layout) in sub.inputs {
-                let state = SubState::New(d3d12::D3D12_RESOURCE_STATE_PIXEL_SHADER_RESOURCE);
-                let old = mem::replace(&mut att_infos[id].sub_states[sid], state);
+                let state = d3d12::D3D12_RESOURCE_STATE_PIXEL_SHADER_RESOURCE;
+                let old = mem::replace(&mut att_infos[id].sub_states[sid], SubState::New((state, state)));
                 debug_assert_eq!(SubState::Undefined, old);
             }
             for &(id, _layout) in sub.resolves {
-                let state = SubState::New(d3d12::D3D12_RESOURCE_STATE_RESOLVE_DEST);
+                let state = d3d12::D3D12_RESOURCE_STATE_RESOLVE_DEST;
+                let state = SubState::New((state, state));
                 let old = mem::replace(&mut att_infos[id].sub_states[sid], state);
                 debug_assert_eq!(SubState::Undefined, old);
             }
@@ -1207,22 +1634,7 @@ impl d::Device<B> for Device {
                         ai.barrier_start_index = rp.subpasses.len() + 1;
                     },
                     SubState::New(state) if state != ai.last_state => {
-                        let barrier = n::BarrierDesc::new(att_id, ai.last_state .. state);
-                        match rp.subpasses.get_mut(ai.barrier_start_index) {
-                            Some(past_subpass) => {
-                                let split = barrier.split();
-                                past_subpass.pre_barriers.push(split.start);
-                                pre_barriers.push(split.end);
-                            },
-                            None => pre_barriers.push(barrier),
-                        }
-                        ai.last_state = state;
-                        ai.barrier_start_index = rp.subpasses.len() + 1;
-                    },
-                    SubState::Resolve(state) => {
-                        // 1. Standard pre barrier to update state from previous pass into desired substate.
-                        if state != ai.last_state {
-                            let barrier = n::BarrierDesc::new(att_id, ai.last_state .. state);
+                        for barrier in plane_barriers(att_id, ai.last_state, state) {
                             match rp.subpasses.get_mut(ai.barrier_start_index) {
                                 Some(past_subpass) => {
                                     let split = barrier.split();
@@ -1232,13 +1644,30 @@ impl d::Device<B> for Device {
                                 None => pre_barriers.push(barrier),
                             }
                         }
+                        ai.last_state = state;
+                        ai.barrier_start_index = rp.subpasses.len() + 1;
+                    },
+                    SubState::Resolve(state) => {
+                        // 1. Standard pre barrier to update state from previous pass into desired substate.
+                        if (state, state) != ai.last_state {
+                            for barrier in plane_barriers(att_id, ai.last_state, (state, state)) {
+                                match rp.subpasses.get_mut(ai.barrier_start_index) {
+                                    Some(past_subpass) => {
+                                        let split = barrier.split();
+                                        past_subpass.pre_barriers.push(split.start);
+                                        pre_barriers.push(split.end);
+                                    },
+                                    None => pre_barriers.push(barrier),
+                                }
+                            }
+                        }
 
                         // 2. Post Barrier at the end of the subpass into RESOLVE_SOURCE.
                         let resolve_state = d3d12::D3D12_RESOURCE_STATE_RESOLVE_SOURCE;
                         let barrier = n::BarrierDesc::new(att_id, state .. resolve_state);
                         post_barriers.push(barrier);
 
-                        ai.last_state = resolve_state;
+                        ai.last_state = (resolve_state, resolve_state);
                         ai.barrier_start_index = rp.subpasses.len() + 1;
                     },
                     _ => { }
@@ -1261,17 +1690,18 @@ impl d::Device<B> for Device {
         // take care of the post-pass transitions at the end of the renderpass.
         for (att_id, (ai, att)) in att_infos.iter().zip(attachments.iter()).enumerate() {
             let state_dst = conv::map_image_resource_state(image::Access::empty(), att.layouts.end);
-            if state_dst == ai.last_state {
+            if (state_dst, state_dst) == ai.last_state {
                 continue;
             }
-            let barrier = n::BarrierDesc::new(att_id, ai.last_state .. state_dst);
-            match rp.subpasses.get_mut(ai.barrier_start_index) {
-                Some(past_subpass) => {
-                    let split = barrier.split();
-                    past_subpass.pre_barriers.push(split.start);
-                    rp.post_barriers.push(split.end);
-                },
-                None => rp.post_barriers.push(barrier),
+            for barrier in plane_barriers(att_id, ai.last_state, (state_dst, state_dst)) {
+                match rp.subpasses.get_mut(ai.barrier_start_index) {
+                    Some(past_subpass) => {
+                        let split = barrier.split();
+                        past_subpass.pre_barriers.push(split.start);
+                        rp.post_barriers.push(split.end);
+                    },
+                    None => rp.post_barriers.push(barrier),
+                }
             }
         }
 
@@ -1321,7 +1751,7 @@ impl d::Device<B> for Device {
         for root_constant in root_constants.iter() {
             let mut param = d3d12::D3D12_ROOT_PARAMETER {
                 ParameterType: d3d12::D3D12_ROOT_PARAMETER_TYPE_32BIT_CONSTANTS,
-                ShaderVisibility: d3d12::D3D12_SHADER_VISIBILITY_ALL, //TODO
+                ShaderVisibility: conv::map_shader_visibility(root_constant.stages),
                 .. unsafe { mem::zeroed() }
             };
 
@@ -1334,10 +1764,6 @@ impl d::Device<B> for Device {
             parameters.push(param);
         }
 
-        // Offest of `spaceN` for descriptor tables. Root constants will be in
-        // `space0`.
-        let table_space_offset = if !root_constants.is_empty() { 1 } else { 0 };
-
         // Collect the whole number of bindings we will create upfront.
         // It allows us to preallocate enough storage to avoid reallocation,
         // which could cause invalid pointers.
@@ -1374,11 +1800,14 @@ impl d::Device<B> for Device {
             };
 
             let range_base = ranges.len();
+            // Variable sized arrays are the last binding of a set and map to an unbounded range,
+            // which is only valid at the end of a descriptor table.
             ranges.extend(set
                 .bindings
                 .iter()
-                .filter(|bind| bind.ty != pso::DescriptorType::Sampler)
-                .map(|bind| conv::map_descriptor_range(bind, (table_space_offset + i) as u32, false)));
+                .enumerate()
+                .filter(|&(_, bind)| bind.ty != pso::DescriptorType::Sampler)
+                .map(|(index, bind)| conv::map_descriptor_range(bind, i as u32, false, set.is_variable(index))));
 
             if ranges.len() > range_base {
                 *unsafe{ param.u.DescriptorTable_mut() } = d3d12::D3D12_ROOT_DESCRIPTOR_TABLE {
@@ -1394,12 +1823,14 @@ impl d::Device<B> for Device {
             ranges.extend(set
                 .bindings
                 .iter()
-                .filter(|bind| bind.ty == pso::DescriptorType::Sampler || bind.ty == pso::DescriptorType::CombinedImageSampler)
-                .map(|bind| {
+                .enumerate()
+                .filter(|&(_, bind)| bind.ty == pso::DescriptorType::Sampler || bind.ty == pso::DescriptorType::CombinedImageSampler)
+                .map(|(index, bind)| {
                     conv::map_descriptor_range(
                         bind,
-                        (table_space_offset + i) as u32,
+                        i as u32,
                         true,
+                        set.is_variable(index),
                     )
                 }));
 
@@ -1474,6 +1905,65 @@ impl d::Device<B> for Device {
         &self,
         desc: &pso::GraphicsPipelineDesc<'a, B>,
     ) -> Result<n::GraphicsPipeline, pso::CreationError> {
+        self.create_graphics_pipeline_with_binary(desc, &[])
+    }
+
+    fn create_graphics_pipeline_with_cache<'a>(
+        &self,
+        desc: &pso::GraphicsPipelineDesc<'a, B>,
+        cache: Option<&n::PipelineCache>,
+    ) -> Result<n::GraphicsPipeline, pso::CreationError> {
+        // Precompiled shader modules can't be identified across runs.
+        let (cache, key) = match (cache, dedup::GraphicsPipelineKey::new(desc)) {
+            (Some(cache), Some(key)) => (cache, key.cache_key(desc.layout)),
+            _ => return self.create_graphics_pipeline(desc),
+        };
+
+        let binary = cache.blobs.lock().unwrap().get(&key).cloned();
+        let pipeline = self.create_graphics_pipeline_with_binary(
+            desc,
+            binary.as_ref().map_or(&[][..], |binary| &binary[..]),
+        )?;
+        // Also replaces blobs rejected by the driver.
+        if let Some(blob) = get_cached_blob(pipeline.raw) {
+            if binary.as_ref() != Some(&blob) {
+                cache.blobs.lock().unwrap().insert(key, blob);
+            }
+        }
+        Ok(pipeline)
+    }
+
+    fn create_graphics_pipelines_with_cache<'a, I>(
+        &self,
+        descs: I,
+        cache: Option<&n::PipelineCache>,
+    ) -> Vec<Result<n::GraphicsPipeline, pso::CreationError>>
+    where
+        I: IntoIterator,
+        I::Item: Borrow<pso::GraphicsPipelineDesc<'a, B>>,
+    {
+        descs
+            .into_iter()
+            .map(|desc| self.create_graphics_pipeline_with_cache(desc.borrow(), cache))
+            .collect()
+    }
+
+    fn create_graphics_pipeline_with_binary<'a>(
+        &self,
+        desc: &pso::GraphicsPipelineDesc<'a, B>,
+        binary: &[u8],
+    ) -> Result<n::GraphicsPipeline, pso::CreationError> {
+        // Points are always rasterized with a size of one pixel.
+        if desc.input_assembler.primitive == hal::Primitive::PointList && desc.rasterizer.program_point_size {
+            error!("Points can't take their size from `PointSize`");
+            return Err(pso::CreationError::Other);
+        }
+
+        let key = dedup::GraphicsPipelineKey::new(desc);
+        if let Some(pipeline) = key.as_ref().and_then(|key| self.pipeline_dedup.find_graphics(key)) {
+            return Ok(pipeline);
+        }
+
         let build_shader =
             |stage: pso::Stage, source: Option<&pso::EntryPoint<'a, B>>| {
                 let source = match source {
@@ -1481,7 +1971,7 @@ impl d::Device<B> for Device {
                     None => return Ok((ptr::null_mut(), false)),
                 };
 
-                Self::extract_entry_point(stage, source, desc.layout)
+                self.extract_entry_point(stage, source, desc.layout)
                     .map_err(|err| pso::CreationError::Shader(err))
             };
 
@@ -1599,6 +2089,16 @@ impl d::Device<B> for Device {
             (rtvs, num_rtvs)
         };
 
+        if cfg!(debug_assertions) && desc.blender.srgb == pso::SrgbConversion::Encoded {
+            let formats = pass.color_attachments
+                .iter()
+                .map(|target| desc.subpass.main_pass.attachments[target.0].format);
+            if desc.blender.blends_srgb(formats) {
+                warn!("Blending on encoded sRGB values is not supported, sRGB targets are blended \
+                    in linear space, render into a UNORM view of the target instead");
+            }
+        }
+
         let sample_desc = dxgitype::DXGI_SAMPLE_DESC {
             Count: match desc.multisampling {
                 Some(ref ms) => ms.rasterization_samples as _,
@@ -1608,7 +2108,7 @@ impl d::Device<B> for Device {
         };
 
         // Setup pipeline description
-        let pso_desc = d3d12::D3D12_GRAPHICS_PIPELINE_STATE_DESC {
+        let mut pso_desc = d3d12::D3D12_GRAPHICS_PIPELINE_STATE_DESC {
             pRootSignature: desc.layout.raw,
             VS: shader_bytecode(vs),
             PS: shader_bytecode(fs),
@@ -1652,10 +2152,7 @@ impl d::Device<B> for Device {
                 .unwrap_or(dxgiformat::DXGI_FORMAT_UNKNOWN),
             SampleDesc: sample_desc,
             NodeMask: 0,
-            CachedPSO: d3d12::D3D12_CACHED_PIPELINE_STATE {
-                pCachedBlob: ptr::null(),
-                CachedBlobSizeInBytes: 0,
-            },
+            CachedPSO: cached_pipeline_state(binary),
             Flags: d3d12::D3D12_PIPELINE_STATE_FLAG_NONE,
         };
 
@@ -1663,33 +2160,47 @@ impl d::Device<B> for Device {
 
         // Create PSO
         let mut pipeline = ptr::null_mut();
-        let hr = if desc.depth_stencil.depth_bounds {
-            // The DepthBoundsTestEnable option isn't available in the original D3D12_GRAPHICS_PIPELINE_STATE_DESC struct.
-            // Instead, we must use the newer subobject stream method.
-            match self.raw.cast::<d3d12::ID3D12Device2>() {
-                Err(hr) => hr,
-                Ok(device2) => {
-                    let mut pss_stream = GraphicsPipelineStateSubobjectStream::new(&pso_desc, true);
-                    let pss_desc = d3d12::D3D12_PIPELINE_STATE_STREAM_DESC {
-                        SizeInBytes: mem::size_of_val(&pss_stream),
-                        pPipelineStateSubobjectStream: &mut pss_stream as *mut _ as _,
-                    };
+        let (hr, context) = self.capture_messages(|| {
+            let mut create_pipeline = |pso_desc: &d3d12::D3D12_GRAPHICS_PIPELINE_STATE_DESC| {
+                if desc.depth_stencil.depth_bounds {
+                    // The DepthBoundsTestEnable option isn't available in the original D3D12_GRAPHICS_PIPELINE_STATE_DESC struct.
+                    // Instead, we must use the newer subobject stream method.
+                    match self.raw.cast::<d3d12::ID3D12Device2>() {
+                        Err(hr) => hr,
+                        Ok(device2) => {
+                            let mut pss_stream = GraphicsPipelineStateSubobjectStream::new(pso_desc, true);
+                            let pss_desc = d3d12::D3D12_PIPELINE_STATE_STREAM_DESC {
+                                SizeInBytes: mem::size_of_val(&pss_stream),
+                                pPipelineStateSubobjectStream: &mut pss_stream as *mut _ as _,
+                            };
+                            unsafe {
+                                device2.CreatePipelineState(
+                                    &pss_desc,
+                                    &d3d12::ID3D12PipelineState::uuidof(),
+                                    &mut pipeline as *mut *mut _ as *mut *mut _)
+                            }
+                        }
+                    }
+                } else {
                     unsafe {
-                        device2.CreatePipelineState(
-                            &pss_desc,
+                        self.raw.clone().CreateGraphicsPipelineState(
+                            pso_desc,
                             &d3d12::ID3D12PipelineState::uuidof(),
                             &mut pipeline as *mut *mut _ as *mut *mut _)
                     }
                 }
+            };
+
+            let mut hr = create_pipeline(&pso_desc);
+            if !winerror::SUCCEEDED(hr) && !binary.is_empty() {
+                // The cached blob is rejected on a different adapter or driver version,
+                // fall back to compiling the pipeline.
+                warn!("Cached pipeline binary rejected: {:x}", hr);
+                pso_desc.CachedPSO = cached_pipeline_state(&[]);
+                hr = create_pipeline(&pso_desc);
             }
-        } else {
-            unsafe {
-                self.raw.clone().CreateGraphicsPipelineState(
-                    &pso_desc,
-                    &d3d12::ID3D12PipelineState::uuidof(),
-                    &mut pipeline as *mut *mut _ as *mut *mut _)
-            }
-        };
+            hr
+        });
 
         let destroy_shader = |shader: *mut d3dcommon::ID3DBlob| unsafe { (*shader).Release() };
 
@@ -1705,7 +2216,7 @@ impl d::Device<B> for Device {
                 baked_states.depth_bounds = None;
             }
 
-            Ok(n::GraphicsPipeline {
+            let pipeline = n::GraphicsPipeline {
                 raw: pipeline,
                 signature: desc.layout.raw,
                 num_parameter_slots: desc.layout.num_parameter_slots,
@@ -1713,9 +2224,13 @@ impl d::Device<B> for Device {
                 constants: desc.layout.root_constants.clone(),
                 vertex_bindings,
                 baked_states,
-            })
+            };
+            if let Some(key) = key {
+                self.pipeline_dedup.insert_graphics(key, &pipeline);
+            }
+            Ok(pipeline)
         } else {
-            Err(pso::CreationError::Other)
+            Err(pso::CreationError::Other.with_context(context))
         }
     }
 
@@ -1723,47 +2238,111 @@ impl d::Device<B> for Device {
         &self,
         desc: &pso::ComputePipelineDesc<'a, B>,
     ) -> Result<n::ComputePipeline, pso::CreationError> {
+        self.create_compute_pipeline_with_binary(desc, &[])
+    }
+
+    fn create_compute_pipeline_with_cache<'a>(
+        &self,
+        desc: &pso::ComputePipelineDesc<'a, B>,
+        cache: Option<&n::PipelineCache>,
+    ) -> Result<n::ComputePipeline, pso::CreationError> {
+        // Precompiled shader modules can't be identified across runs.
+        let (cache, key) = match (cache, dedup::ComputePipelineKey::new(desc)) {
+            (Some(cache), Some(key)) => (cache, key.cache_key(desc.layout)),
+            _ => return self.create_compute_pipeline(desc),
+        };
+
+        let binary = cache.blobs.lock().unwrap().get(&key).cloned();
+        let pipeline = self.create_compute_pipeline_with_binary(
+            desc,
+            binary.as_ref().map_or(&[][..], |binary| &binary[..]),
+        )?;
+        // Also replaces blobs rejected by the driver.
+        if let Some(blob) = get_cached_blob(pipeline.raw) {
+            if binary.as_ref() != Some(&blob) {
+                cache.blobs.lock().unwrap().insert(key, blob);
+            }
+        }
+        Ok(pipeline)
+    }
+
+    fn create_compute_pipelines_with_cache<'a, I>(
+        &self,
+        descs: I,
+        cache: Option<&n::PipelineCache>,
+    ) -> Vec<Result<n::ComputePipeline, pso::CreationError>>
+    where
+        I: IntoIterator,
+        I::Item: Borrow<pso::ComputePipelineDesc<'a, B>>,
+    {
+        descs
+            .into_iter()
+            .map(|desc| self.create_compute_pipeline_with_cache(desc.borrow(), cache))
+            .collect()
+    }
+
+    fn create_compute_pipeline_with_binary<'a>(
+        &self,
+        desc: &pso::ComputePipelineDesc<'a, B>,
+        binary: &[u8],
+    ) -> Result<n::ComputePipeline, pso::CreationError> {
+        let key = dedup::ComputePipelineKey::new(desc);
+        if let Some(pipeline) = key.as_ref().and_then(|key| self.pipeline_dedup.find_compute(key)) {
+            return Ok(pipeline);
+        }
+
         let (cs, cs_destroy) =
-            Self::extract_entry_point(
+            self.extract_entry_point(
                 pso::Stage::Compute,
                 &desc.shader,
                 desc.layout,
             )
             .map_err(|err| pso::CreationError::Shader(err))?;
 
-        let pso_desc = d3d12::D3D12_COMPUTE_PIPELINE_STATE_DESC {
+        let mut pso_desc = d3d12::D3D12_COMPUTE_PIPELINE_STATE_DESC {
             pRootSignature: desc.layout.raw,
             CS: shader_bytecode(cs),
             NodeMask: 0,
-            CachedPSO: d3d12::D3D12_CACHED_PIPELINE_STATE {
-                pCachedBlob: ptr::null(),
-                CachedBlobSizeInBytes: 0,
-            },
+            CachedPSO: cached_pipeline_state(binary),
             Flags: d3d12::D3D12_PIPELINE_STATE_FLAG_NONE,
         };
 
         // Create PSO
         let mut pipeline = ptr::null_mut();
-        let hr = unsafe {
-            self.raw.clone().CreateComputePipelineState(
-                &pso_desc,
-                &d3d12::ID3D12PipelineState::uuidof(),
-                &mut pipeline as *mut *mut _ as *mut *mut _)
-        };
+        let (hr, context) = self.capture_messages(|| {
+            let mut create_pipeline = |pso_desc: &d3d12::D3D12_COMPUTE_PIPELINE_STATE_DESC| unsafe {
+                self.raw.clone().CreateComputePipelineState(
+                    pso_desc,
+                    &d3d12::ID3D12PipelineState::uuidof(),
+                    &mut pipeline as *mut *mut _ as *mut *mut _)
+            };
+
+            let mut hr = create_pipeline(&pso_desc);
+            if !winerror::SUCCEEDED(hr) && !binary.is_empty() {
+                warn!("Cached pipeline binary rejected: {:x}", hr);
+                pso_desc.CachedPSO = cached_pipeline_state(&[]);
+                hr = create_pipeline(&pso_desc);
+            }
+            hr
+        });
 
         if cs_destroy {
             unsafe { (*cs).Release(); }
         }
 
         if winerror::SUCCEEDED(hr) {
-            Ok(n::ComputePipeline {
+            let pipeline = n::ComputePipeline {
                 raw: pipeline,
                 signature: desc.layout.raw,
                 num_parameter_slots: desc.layout.num_parameter_slots,
                 constants: desc.layout.root_constants.clone(),
-            })
+            };
+            if let Some(key) = key {
+                self.pipeline_dedup.insert_compute(key, &pipeline);
+            }
+            Ok(pipeline)
         } else {
-            Err(pso::CreationError::Other)
+            Err(pso::CreationError::Other.with_context(context))
         }
     }
 
@@ -1783,7 +2362,27 @@ impl d::Device<B> for Device {
         })
     }
 
+    fn create_imageless_framebuffer<I>(
+        &self,
+        _renderpass: &n::RenderPass,
+        _attachments: I,
+        extent: image::Extent,
+    ) -> Result<n::Framebuffer, d::FramebufferError>
+    where
+        I: IntoIterator,
+        I::Item: Borrow<pass::FramebufferAttachment>,
+    {
+        // Views are bound directly at `begin_render_pass_imageless`.
+        Ok(n::Framebuffer {
+            attachments: Vec::new(),
+            layers: extent.depth as _,
+        })
+    }
+
     fn create_shader_module(&self, raw_data: &[u8]) -> Result<n::ShaderModule, d::ShaderError> {
+        if cfg!(debug_assertions) {
+            pso::validate_spirv(raw_data)?;
+        }
         Ok(n::ShaderModule::Spirv(raw_data.into()))
     }
 
@@ -1869,40 +2468,7 @@ impl d::Device<B> for Device {
             )
         });
 
-        let clear_uav = if buffer.usage.contains(buffer::Usage::TRANSFER_DST) {
-            let handle = self.srv_uav_pool.lock().unwrap().alloc_handle();
-            let mut view_desc = d3d12::D3D12_UNORDERED_ACCESS_VIEW_DESC {
-                Format: dxgiformat::DXGI_FORMAT_R32_TYPELESS,
-                ViewDimension: d3d12::D3D12_UAV_DIMENSION_BUFFER,
-                u: unsafe { mem::zeroed() },
-            };
-
-           *unsafe { view_desc.u.Buffer_mut() } = d3d12::D3D12_BUFFER_UAV {
-                FirstElement: 0,
-                NumElements: (buffer.requirements.size / 4) as _,
-                StructureByteStride: 0,
-                CounterOffsetInBytes: 0,
-                Flags: d3d12::D3D12_BUFFER_UAV_FLAG_RAW,
-            };
-
-            unsafe {
-                self.raw.clone().CreateUnorderedAccessView(
-                    resource as *mut _,
-                    ptr::null_mut(),
-                    &view_desc,
-                    handle,
-                );
-            }
-            Some(handle)
-        } else {
-            None
-        };
-
-        Ok(n::Buffer {
-            resource: resource as *mut _,
-            size_in_bytes: buffer.requirements.size as _,
-            clear_uav,
-        })
+        Ok(self.buffer_from_resource(resource as *mut _, buffer.requirements.size, buffer.usage))
     }
 
     fn create_buffer_view<R: RangeArg<u64>>(
@@ -1933,14 +2499,15 @@ impl d::Device<B> for Device {
             let mut desc = d3d12::D3D12_SHADER_RESOURCE_VIEW_DESC {
                 Format: format,
                 ViewDimension: d3d12::D3D12_SRV_DIMENSION_BUFFER,
-                Shader4ComponentMapping: 0x1688, // TODO: verify
+                Shader4ComponentMapping: IDENTITY_MAPPING,
                 u: unsafe { mem::zeroed() },
             };
 
             *unsafe{ desc.u.Buffer_mut() } = d3d12::D3D12_BUFFER_SRV {
                 FirstElement: first_element,
                 NumElements: num_elements as _,
-                StructureByteStride: bytes_per_texel as _,
+                // Typed views must not specify a stride, only structured ones do.
+                StructureByteStride: 0,
                 Flags: d3d12::D3D12_BUFFER_SRV_FLAG_NONE,
             };
 
@@ -1963,7 +2530,7 @@ impl d::Device<B> for Device {
             *unsafe{ desc.u.Buffer_mut() } = d3d12::D3D12_BUFFER_UAV {
                 FirstElement: first_element,
                 NumElements: num_elements as _,
-                StructureByteStride: bytes_per_texel as _,
+                StructureByteStride: 0,
                 Flags: d3d12::D3D12_BUFFER_UAV_FLAG_NONE,
                 CounterOffsetInBytes: 0,
             };
@@ -2021,8 +2588,12 @@ impl d::Device<B> for Device {
                 kind.num_layers() as _
             },
             MipLevels: mip_levels as _,
-            Format: match conv::map_format(format) {
-                Some(format) => format,
+            Format: match conv::map_format_with_swizzle(format) {
+                // Formats with emulated channel order can only be sampled from
+                Some((_, swizzle)) if swizzle != format::Swizzle::NO &&
+                    usage.intersects(image::Usage::COLOR_ATTACHMENT | image::Usage::STORAGE) =>
+                    return Err(image::CreationError::Format(format)),
+                Some((format, _)) => format,
                 None => return Err(image::CreationError::Format(format)),
             },
             SampleDesc: dxgitype::DXGI_SAMPLE_DESC {
@@ -2110,8 +2681,6 @@ impl d::Device<B> for Device {
         offset: u64,
         image: UnboundImage,
     ) -> Result<n::Image, d::BindError> {
-        use self::image::Usage;
-
         if image.requirements.type_mask & (1 << memory.type_id) == 0 {
             error!("Bind memory failure: supported mask 0x{:x}, given id {}",
                 image.requirements.type_mask, memory.type_id);
@@ -2122,115 +2691,33 @@ impl d::Device<B> for Device {
         }
 
         let mut resource = ptr::null_mut();
-        let num_layers = image.kind.num_layers();
+
+        // Mutable images are backed by a typeless resource, all internal views
+        // keep using the typed format from the image description.
+        let mut resource_desc = image.desc;
+        if image.storage_flags.contains(image::StorageFlags::MUTABLE_FORMAT) {
+            if let Some(format) = conv::map_format_typeless(image.desc.Format) {
+                resource_desc.Format = format;
+            }
+        }
 
         assert_eq!(winerror::S_OK, unsafe {
             self.raw.clone().CreatePlacedResource(
                 memory.heap.as_raw(),
                 offset,
-                &image.desc,
+                &resource_desc,
                 d3d12::D3D12_RESOURCE_STATE_COMMON,
-                ptr::null(),
-                &d3d12::ID3D12Resource::uuidof(),
-                &mut resource,
-            )
-        });
-
-        let info = ViewInfo {
-            resource: resource as *mut _,
-            kind: image.kind,
-            flags: image::StorageFlags::empty(),
-            view_kind: match image.kind {
-                image::Kind::D1(..) => image::ViewKind::D1Array,
-                image::Kind::D2(..) => image::ViewKind::D2Array,
-                image::Kind::D3(..) => image::ViewKind::D3,
-            },
-            format: image.desc.Format,
-            range: image::SubresourceRange {
-                aspects: Aspects::empty(),
-                levels: 0 .. 0,
-                layers: 0 .. 0,
-            },
-        };
-
-        //TODO: the clear_Xv is incomplete. We should support clearing images created without XXX_ATTACHMENT usage.
-        // for this, we need to check the format and force the `RENDER_TARGET` flag behind the user's back
-        // if the format supports being rendered into, allowing us to create clear_Xv
-        let format_properties = &self.format_properties[image.format as usize];
-        let props = match image.tiling {
-            image::Tiling::Optimal => format_properties.optimal_tiling,
-            image::Tiling::Linear => format_properties.linear_tiling,
-        };
-        let can_clear_color = image.usage.intersects(Usage::TRANSFER_DST | Usage::COLOR_ATTACHMENT) &&
-            props.contains(format::ImageFeature::COLOR_ATTACHMENT);
-        let can_clear_depth = image.usage.intersects(Usage::TRANSFER_DST | Usage::DEPTH_STENCIL_ATTACHMENT) &&
-            props.contains(format::ImageFeature::DEPTH_STENCIL_ATTACHMENT);
-        let aspects = image.format.surface_desc().aspects;
-
-        Ok(n::Image {
-            resource: resource as *mut _,
-            place: n::Place::Heap { raw: memory.heap.clone(), offset },
-            surface_type: image.format.base_format().0,
-            kind: image.kind,
-            usage: image.usage,
-            storage_flags: image.storage_flags,
-            descriptor: image.desc,
-            bytes_per_block: image.bytes_per_block,
-            block_dim: image.block_dim,
-            clear_cv: if aspects.contains(Aspects::COLOR) && can_clear_color {
-                (0 .. num_layers)
-                    .map(|layer| {
-                        self.view_image_as_render_target(
-                            ViewInfo {
-                                range: image::SubresourceRange {
-                                    aspects: Aspects::COLOR,
-                                    levels: 0 .. 1, //TODO?
-                                    layers: layer .. layer + 1,
-                                },
-                                .. info.clone()
-                            }).unwrap()
-                    })
-                    .collect()
-            } else {
-                Vec::new()
-            },
-            clear_dv: if aspects.contains(Aspects::DEPTH) && can_clear_depth {
-                (0 .. num_layers)
-                    .map(|layer| {
-                        self.view_image_as_depth_stencil(
-                            ViewInfo {
-                                format: image.dsv_format,
-                                range: image::SubresourceRange {
-                                    aspects: Aspects::DEPTH,
-                                    levels: 0 .. 1, //TODO?
-                                    layers: layer .. layer + 1,
-                                },
-                                .. info.clone()
-                            }).unwrap()
-                    })
-                    .collect()
-            } else {
-                Vec::new()
-            },
-            clear_sv: if aspects.contains(Aspects::STENCIL) && can_clear_depth {
-                (0 .. num_layers)
-                    .map(|layer| {
-                        self.view_image_as_depth_stencil(
-                            ViewInfo {
-                                format: image.dsv_format,
-                                range: image::SubresourceRange {
-                                    aspects: Aspects::STENCIL,
-                                    levels: 0 .. 1, //TODO?
-                                    layers: layer .. layer + 1,
-                                },
-                                .. info.clone()
-                            }).unwrap()
-                    })
-                    .collect()
-            } else {
-                Vec::new()
-            },
-        })
+                ptr::null(),
+                &d3d12::ID3D12Resource::uuidof(),
+                &mut resource,
+            )
+        });
+
+        Ok(self.image_from_resource(
+            resource as *mut _,
+            image,
+            n::Place::Heap { raw: memory.heap.clone(), offset },
+        ))
     }
 
     fn create_image_view(
@@ -2238,22 +2725,64 @@ impl d::Device<B> for Device {
         image: &n::Image,
         view_kind: image::ViewKind,
         format: format::Format,
-        _swizzle: format::Swizzle,
+        swizzle: format::Swizzle,
         range: image::SubresourceRange,
     ) -> Result<n::ImageView, image::ViewError> {
         let mip_levels = (range.levels.start, range.levels.end);
         let layers = (range.layers.start, range.layers.end);
+        let (dxgi_format, emulation) = conv::map_format_with_swizzle(format)
+            .ok_or(image::ViewError::BadFormat)?;
 
         let info = ViewInfo {
             resource: image.resource,
             kind: image.kind,
             flags: image.storage_flags,
             view_kind,
-            format: conv::map_format(format)
-                .ok_or(image::ViewError::BadFormat)?,
+            format: dxgi_format,
+            component_mapping: conv::map_swizzle(conv::compose_swizzle(emulation, swizzle)),
             range,
         };
 
+        if info.format != image.descriptor.Format {
+            // Typed resources can't be reinterpreted, only the typeless
+            // resources of mutable images can.
+            let typeless = conv::map_format_typeless(info.format);
+            let mut castable = image.storage_flags.contains(image::StorageFlags::MUTABLE_FORMAT) &&
+                typeless.is_some() &&
+                typeless == conv::map_format_typeless(image.descriptor.Format);
+            // Flip model back buffers only allow the sRGB and linear views of their format.
+            if let n::Place::SwapChain = image.place {
+                let (surface, channel) = format.base_format();
+                castable &= surface == image.surface_type &&
+                    (channel == format::ChannelType::Unorm || channel == format::ChannelType::Srgb);
+            }
+            if !castable {
+                return Err(image::ViewError::BadFormat);
+            }
+        }
+
+        let (handle_dsv, handle_dsv_read_only) = if image.usage.contains(image::Usage::DEPTH_STENCIL_ATTACHMENT) {
+            let dsv_info = ViewInfo {
+                format: conv::map_format_dsv(format.base_format().0)
+                    .ok_or(image::ViewError::BadFormat)?,
+                .. info.clone()
+            };
+            // Read-only views are bound for attachments in read-only layouts,
+            // restricted to the aspects of the format.
+            let aspects = format.surface_desc().aspects;
+            let mut read_only = [None; 3];
+            let read_only_aspects = [Aspects::DEPTH, Aspects::STENCIL, Aspects::DEPTH | Aspects::STENCIL];
+            for (handle, &read_only_aspects) in read_only.iter_mut().zip(read_only_aspects.iter()) {
+                if aspects.intersects(read_only_aspects) {
+                    let flags = conv::map_dsv_flags(aspects & read_only_aspects);
+                    *handle = Some(self.view_image_as_depth_stencil(dsv_info.clone(), flags)?);
+                }
+            }
+            (Some(self.view_image_as_depth_stencil(dsv_info, d3d12::D3D12_DSV_FLAG_NONE)?), read_only)
+        } else {
+            (None, [None; 3])
+        };
+
         Ok(n::ImageView {
             resource: image.resource,
             handle_srv: if image.usage.intersects(image::Usage::SAMPLED | image::Usage::INPUT_ATTACHMENT) {
@@ -2271,15 +2800,8 @@ impl d::Device<B> for Device {
             } else {
                 None
             },
-            handle_dsv: if image.usage.contains(image::Usage::DEPTH_STENCIL_ATTACHMENT) {
-                Some(self.view_image_as_depth_stencil(ViewInfo {
-                    format: conv::map_format_dsv(format.base_format().0)
-                        .ok_or(image::ViewError::BadFormat)?,
-                    .. info
-                })?)
-            } else {
-                None
-            },
+            handle_dsv,
+            handle_dsv_read_only,
             dxgi_format: image.descriptor.Format,
             num_levels: image.descriptor.MipLevels as image::Level,
             mip_levels,
@@ -2289,6 +2811,11 @@ impl d::Device<B> for Device {
     }
 
     fn create_sampler(&self, info: image::SamplerInfo) -> n::Sampler {
+        let mut samplers = self.samplers.lock().unwrap();
+        if let Some(&handle) = samplers.get(&info) {
+            return n::Sampler { handle };
+        }
+
         let handle = self.sampler_pool.lock().unwrap().alloc_handle();
 
         let op = match info.comparison {
@@ -2315,6 +2842,7 @@ impl d::Device<B> for Device {
             self.raw.clone().CreateSampler(&desc, handle);
         }
 
+        samplers.insert(info, handle);
         n::Sampler { handle }
     }
 
@@ -2349,38 +2877,28 @@ impl d::Device<B> for Device {
         }
 
         let heap_srv_cbv_uav = {
-            let mut heap_srv_cbv_uav = self
-                .heap_srv_cbv_uav
-                .lock()
-                .unwrap();
-
-            let range = heap_srv_cbv_uav
-                .range_allocator
-                .allocate_range(num_srv_cbv_uav as _)
+            let heap = &self.shared.heap_srv_cbv_uav;
+            let range = heap
+                .allocate(num_srv_cbv_uav as _)
                 .unwrap(); // TODO: error/resize
             n::DescriptorHeapSlice {
-                heap: heap_srv_cbv_uav.raw.clone(),
-                handle_size: heap_srv_cbv_uav.handle_size as _,
-                range_allocator: RangeAllocator::new(range),
-                start: heap_srv_cbv_uav.start,
+                handle_size: heap.handle_size,
+                range_allocator: RangeAllocator::new(range.clone()),
+                range,
+                start: heap.start,
             }
         };
 
         let heap_sampler = {
-            let mut heap_sampler = self
-                .heap_sampler
-                .lock()
-                .unwrap();
-
-            let range = heap_sampler
-                .range_allocator
-                .allocate_range(num_samplers as _)
+            let heap = &self.shared.heap_sampler;
+            let range = heap
+                .allocate(num_samplers as _)
                 .unwrap(); // TODO: error/resize
             n::DescriptorHeapSlice {
-                heap: heap_sampler.raw.clone(),
-                handle_size: heap_sampler.handle_size as _,
-                range_allocator: RangeAllocator::new(range),
-                start: heap_sampler.start,
+                handle_size: heap.handle_size,
+                range_allocator: RangeAllocator::new(range.clone()),
+                range,
+                start: heap.start,
             }
         };
 
@@ -2392,8 +2910,40 @@ impl d::Device<B> for Device {
         }
     }
 
+    fn create_descriptor_pool_with_flags<I>(
+        &self,
+        max_sets: usize,
+        descriptor_pools: I,
+        flags: pso::DescriptorPoolCreateFlags,
+    ) -> n::DescriptorPool
+    where
+        I: IntoIterator,
+        I::Item: Borrow<pso::DescriptorRangeDesc>
+    {
+        // Descriptor heaps don't distinguish sets updated after binding.
+        if flags.contains(pso::DescriptorPoolCreateFlags::UPDATE_AFTER_BIND) {
+            assert!(self.features.contains(hal::Features::DESCRIPTOR_INDEXING));
+        }
+        self.create_descriptor_pool(max_sets, descriptor_pools)
+    }
+
     fn create_descriptor_set_layout<I, J>(
-        &self, bindings: I, _immutable_samplers: J
+        &self, bindings: I, immutable_samplers: J
+    ) -> n::DescriptorSetLayout
+    where
+        I: IntoIterator,
+        I::Item: Borrow<pso::DescriptorSetLayoutBinding>,
+        J: IntoIterator,
+        J::Item: Borrow<n::Sampler>,
+    {
+        self.create_descriptor_set_layout_with_flags(bindings, &[], immutable_samplers)
+    }
+
+    fn create_descriptor_set_layout_with_flags<I, J>(
+        &self,
+        bindings: I,
+        binding_flags: &[pso::DescriptorBindingFlags],
+        _immutable_samplers: J,
     ) -> n::DescriptorSetLayout
     where
         I: IntoIterator,
@@ -2401,8 +2951,14 @@ impl d::Device<B> for Device {
         J: IntoIterator,
         J::Item: Borrow<n::Sampler>,
     {
+        // Root signature 1.0 treats all descriptors as volatile, so they can be
+        // updated after binding and left unbound without any further flags.
+        if binding_flags.iter().any(|flags| !flags.is_empty()) {
+            assert!(self.features.contains(hal::Features::DESCRIPTOR_INDEXING));
+        }
         n::DescriptorSetLayout {
-            bindings: bindings.into_iter().map(|b| b.borrow().clone()).collect()
+            bindings: bindings.into_iter().map(|b| b.borrow().clone()).collect(),
+            binding_flags: binding_flags.to_vec(),
         }
     }
 
@@ -2414,6 +2970,7 @@ impl d::Device<B> for Device {
     {
         let mut descriptor_update_pools = self.descriptor_update_pools.lock().unwrap();
         let mut update_pool_index = 0;
+        let mut statistics = FrameStatistics::default();
 
         //TODO: combine destination ranges
         let mut dst_samplers = Vec::new();
@@ -2424,6 +2981,7 @@ impl d::Device<B> for Device {
         let mut num_views = Vec::new();
 
         for write in write_iter {
+            statistics.descriptor_updates += 1;
             let mut offset = write.array_offset as u64;
             let mut target_binding = write.binding as usize;
             let mut bind_info = &write.set.binding_infos[target_binding];
@@ -2457,6 +3015,8 @@ impl d::Device<B> for Device {
                         let end = range.end.unwrap_or(buffer.size_in_bytes as _);
 
                         if bind_info.is_uav {
+                            // Raw views address the buffer in 32-bit elements.
+                            assert_eq!(start % 4, 0);
                             assert_eq!((end - start) % 4, 0);
                             let mut desc = d3d12::D3D12_UNORDERED_ACCESS_VIEW_DESC {
                                 Format: dxgiformat::DXGI_FORMAT_R32_TYPELESS,
@@ -2464,7 +3024,7 @@ impl d::Device<B> for Device {
                                 u: unsafe { mem::zeroed() },
                             };
                            *unsafe { desc.u.Buffer_mut() } = d3d12::D3D12_BUFFER_UAV {
-                                FirstElement: start as _,
+                                FirstElement: (start / 4) as _,
                                 NumElements: ((end - start) / 4) as _,
                                 StructureByteStride: 0,
                                 CounterOffsetInBytes: 0,
@@ -2570,6 +3130,8 @@ impl d::Device<B> for Device {
         for buffer_desc_pool in descriptor_update_pools.iter_mut() {
             buffer_desc_pool.clear();
         }
+
+        self.statistics.add(&statistics);
     }
 
     fn copy_descriptor_sets<'a, I>(&self, copy_iter: I)
@@ -2583,9 +3145,11 @@ impl d::Device<B> for Device {
         let mut src_views = Vec::new();
         let mut num_samplers = Vec::new();
         let mut num_views = Vec::new();
+        let mut statistics = FrameStatistics::default();
 
         for copy_wrap in copy_iter {
             let copy = copy_wrap.borrow();
+            statistics.descriptor_updates += 1;
             let src_info = &copy.src_set.binding_infos[copy.src_binding as usize];
             let dst_info = &copy.dst_set.binding_infos[copy.dst_binding as usize];
             if let (Some(src_range), Some(dst_range)) = (src_info.view_range.as_ref(), dst_info.view_range.as_ref()) {
@@ -2630,6 +3194,8 @@ impl d::Device<B> for Device {
                 );
             }
         }
+
+        self.statistics.add(&statistics);
     }
 
     fn map_memory<R>(&self, memory: &n::Memory, range: R) -> Result<*mut u8, mapping::Error>
@@ -2774,41 +3340,7 @@ impl d::Device<B> for Device {
         I::Item: Borrow<n::Fence>,
     {
         let fences = fences.into_iter().collect::<Vec<_>>();
-        let mut events = self.events.lock().unwrap();
-        for _ in events.len() .. fences.len() {
-            events.push(unsafe {
-                synchapi::CreateEventA(
-                    ptr::null_mut(),
-                    FALSE,
-                    FALSE,
-                    ptr::null(),
-                )
-            });
-        }
-
-        for (&event, fence) in events.iter().zip(fences.iter()) {
-            assert_eq!(winerror::S_OK, unsafe {
-                synchapi::ResetEvent(event);
-                fence.borrow().raw.clone().SetEventOnCompletion(1, event)
-            });
-        }
-
-        let all = match wait {
-            d::WaitFor::Any => FALSE,
-            d::WaitFor::All => TRUE,
-        };
-        let hr = unsafe {
-            synchapi::WaitForMultipleObjects(fences.len() as u32, events.as_ptr(), all, timeout_ms)
-        };
-
-        const WAIT_OBJECT_LAST: u32 = winbase::WAIT_OBJECT_0 + winnt::MAXIMUM_WAIT_OBJECTS;
-        const WAIT_ABANDONED_LAST: u32 = winbase::WAIT_ABANDONED_0 + winnt::MAXIMUM_WAIT_OBJECTS;
-        match hr {
-            winbase::WAIT_OBJECT_0 ... WAIT_OBJECT_LAST => true,
-            winbase::WAIT_ABANDONED_0 ... WAIT_ABANDONED_LAST => true, //TODO?
-            winerror::WAIT_TIMEOUT => false,
-            _ => panic!("Unexpected wait status 0x{:X}", hr),
-        }
+        self.wait_for_raw_fences(fences.iter().map(|fence| (&fence.borrow().raw, 1)), wait, timeout_ms)
     }
 
     fn get_fence_status(&self, _fence: &n::Fence) -> bool {
@@ -2822,13 +3354,16 @@ impl d::Device<B> for Device {
     }
 
     fn create_query_pool(&self, query_ty: query::QueryType, count: u32) -> n::QueryPool {
-        let heap_ty = match query_ty {
+        let (heap_ty, stride, statistics) = match query_ty {
             query::QueryType::Occlusion =>
-                d3d12::D3D12_QUERY_HEAP_TYPE_OCCLUSION,
-            query::QueryType::PipelineStatistics(_) =>
+                (d3d12::D3D12_QUERY_HEAP_TYPE_OCCLUSION, 8, query::PipelineStatistic::empty()),
+            query::QueryType::PipelineStatistics(statistics) => (
                 d3d12::D3D12_QUERY_HEAP_TYPE_PIPELINE_STATISTICS,
+                mem::size_of::<d3d12::D3D12_QUERY_DATA_PIPELINE_STATISTICS>() as u64,
+                statistics,
+            ),
             query::QueryType::Timestamp =>
-                d3d12::D3D12_QUERY_HEAP_TYPE_TIMESTAMP,
+                (d3d12::D3D12_QUERY_HEAP_TYPE_TIMESTAMP, 8, query::PipelineStatistic::empty()),
         };
 
         let desc = d3d12::D3D12_QUERY_HEAP_DESC {
@@ -2846,14 +3381,120 @@ impl d::Device<B> for Device {
             )
         });
 
+        let resolve_size = (stride + 8) * count as u64;
+        let resolve = self.create_committed_buffer(
+            d3d12::D3D12_HEAP_TYPE_READBACK,
+            resolve_size,
+            d3d12::D3D12_RESOURCE_STATE_COPY_DEST,
+        );
+        let availability = self.create_committed_buffer(
+            d3d12::D3D12_HEAP_TYPE_UPLOAD,
+            8 * (count as u64 + 1),
+            d3d12::D3D12_RESOURCE_STATE_GENERIC_READ,
+        );
+
+        // The readback buffer stays mapped for the lifetime of the pool,
+        // which is allowed for readback heaps.
+        let mut mapped = ptr::null_mut();
+        assert_eq!(winerror::S_OK, unsafe {
+            resolve.Map(0, ptr::null(), &mut mapped)
+        });
+        unsafe {
+            let mut words = ptr::null_mut();
+            assert_eq!(winerror::S_OK, availability.Map(0, &d3d12::D3D12_RANGE { Begin: 0, End: 0 }, &mut words));
+            let words = slice::from_raw_parts_mut(words as *mut u64, count as usize + 1);
+            for word in words.iter_mut() {
+                *word = 0;
+            }
+            words[count as usize] = 1;
+            availability.Unmap(0, ptr::null());
+            // Nothing has been resolved yet, mark all queries as unavailable.
+            ptr::write_bytes((mapped as *mut u8).offset((stride * count as u64) as isize), 0, 8 * count as usize);
+        }
+
+        let frequency = if heap_ty == d3d12::D3D12_QUERY_HEAP_TYPE_TIMESTAMP {
+            let mut frequency = 0;
+            assert_eq!(winerror::S_OK, unsafe {
+                self.present_queue.GetTimestampFrequency(&mut frequency)
+            });
+            frequency
+        } else {
+            0
+        };
+
         n::QueryPool {
             raw: unsafe { ComPtr::from_raw(handle as *mut _) },
             ty: heap_ty,
+            count,
+            stride,
+            statistics,
+            resolve,
+            mapped: mapped as *const u8,
+            availability,
+            frequency,
         }
     }
 
-    fn destroy_query_pool(&self, _pool: n::QueryPool) {
-        // Just drop
+    fn destroy_query_pool(&self, pool: n::QueryPool) {
+        unsafe { pool.resolve.Unmap(0, &d3d12::D3D12_RANGE { Begin: 0, End: 0 }); }
+    }
+
+    fn get_query_pool_results(
+        &self,
+        pool: &n::QueryPool,
+        queries: Range<query::QueryId>,
+        data: &mut [u64],
+        wait: bool,
+    ) -> Result<bool, error::HostExecutionError> {
+        let values_per_query = if pool.ty == d3d12::D3D12_QUERY_HEAP_TYPE_PIPELINE_STATISTICS {
+            pool.statistics.bits().count_ones() as usize
+        } else {
+            1
+        };
+        assert!(data.len() >= (queries.end - queries.start) as usize * values_per_query);
+
+        let is_available = |id: query::QueryId| unsafe {
+            let word = pool.mapped.offset(pool.availability_offset(id) as isize) as *const u64;
+            ptr::read_volatile(word) != 0
+        };
+
+        for (i, id) in queries.enumerate() {
+            if !is_available(id) {
+                if !wait {
+                    return Ok(false);
+                }
+                while !is_available(id) {
+                    thread::sleep(time::Duration::from_millis(1));
+                }
+            }
+
+            let result = unsafe { pool.mapped.offset((pool.stride * id as u64) as isize) };
+            let out = &mut data[i * values_per_query .. (i + 1) * values_per_query];
+            match pool.ty {
+                d3d12::D3D12_QUERY_HEAP_TYPE_PIPELINE_STATISTICS => {
+                    // The fields of `D3D12_QUERY_DATA_PIPELINE_STATISTICS` are laid out
+                    // in the same order as the `PipelineStatistic` bits.
+                    let all = unsafe { &*(result as *const [u64; 11]) };
+                    let enabled = all
+                        .iter()
+                        .enumerate()
+                        .filter(|&(bit, _)| pool.statistics.bits() & (1 << bit) != 0);
+                    for (value, (_, &stat)) in out.iter_mut().zip(enabled) {
+                        *value = stat;
+                    }
+                }
+                d3d12::D3D12_QUERY_HEAP_TYPE_TIMESTAMP => {
+                    // Report nanoseconds, matching the default `timestamp_period` of 1.
+                    let ticks = unsafe { ptr::read(result as *const u64) };
+                    out[0] = (ticks as f64 * 1_000_000_000.0 / pool.frequency as f64) as u64;
+                }
+                _ => {
+                    out[0] = unsafe { ptr::read(result as *const u64) };
+                }
+            }
+        }
+
+        Ok(true)
     }
 
     fn destroy_shader_module(&self, shader_lib: n::ShaderModule) {
@@ -2872,11 +3513,53 @@ impl d::Device<B> for Device {
         unsafe { (*layout.raw).Release(); }
     }
 
+    fn create_pipeline_cache(&self, data: Option<&[u8]>) -> Result<n::PipelineCache, d::OutOfMemory> {
+        Ok(match data {
+            Some(data) => n::PipelineCache::from_data(data),
+            None => n::PipelineCache::default(),
+        })
+    }
+
+    fn get_pipeline_cache_data(&self, cache: &n::PipelineCache) -> Result<Vec<u8>, d::OutOfMemory> {
+        Ok(cache.to_data())
+    }
+
+    fn merge_pipeline_caches<I>(&self, target: &n::PipelineCache, sources: I) -> Result<(), d::OutOfMemory>
+    where
+        I: IntoIterator,
+        I::Item: Borrow<n::PipelineCache>,
+    {
+        let mut blobs = target.blobs.lock().unwrap();
+        for source in sources {
+            let source = source.borrow();
+            if source as *const _ == target as *const _ {
+                continue;
+            }
+            for (&key, blob) in source.blobs.lock().unwrap().iter() {
+                blobs.entry(key).or_insert_with(|| blob.clone());
+            }
+        }
+        Ok(())
+    }
+
+    fn destroy_pipeline_cache(&self, _cache: n::PipelineCache) {
+    }
+
+    fn get_graphics_pipeline_binary(&self, pipeline: &n::GraphicsPipeline) -> Option<Vec<u8>> {
+        get_cached_blob(pipeline.raw)
+    }
+
+    fn get_compute_pipeline_binary(&self, pipeline: &n::ComputePipeline) -> Option<Vec<u8>> {
+        get_cached_blob(pipeline.raw)
+    }
+
     fn destroy_graphics_pipeline(&self, pipeline: n::GraphicsPipeline) {
+        self.pipeline_dedup.release(pipeline.raw);
         unsafe { (*pipeline.raw).Release(); }
     }
 
     fn destroy_compute_pipeline(&self, pipeline: n::ComputePipeline) {
+        self.pipeline_dedup.release(pipeline.raw);
         unsafe { (*pipeline.raw).Release(); }
     }
 
@@ -2896,17 +3579,152 @@ impl d::Device<B> for Device {
         unsafe { (*image.resource).Release(); }
     }
 
-    fn destroy_image_view(&self, _view: n::ImageView) {
-        // Just drop
+    fn create_sparse_buffer(
+        &self, size: u64, usage: buffer::Usage,
+    ) -> Result<(n::Buffer, Requirements), buffer::CreationError> {
+        let tile_size = d3d12::D3D12_TILED_RESOURCE_TILE_SIZE_IN_BYTES as u64;
+        let unbound = d::Device::create_buffer(self, size, usage)?;
+        let requirements = memory::Requirements {
+            size: (unbound.requirements.size + tile_size - 1) / tile_size * tile_size,
+            alignment: tile_size,
+            .. unbound.requirements
+        };
+
+        let mut resource = ptr::null_mut();
+        let desc = d3d12::D3D12_RESOURCE_DESC {
+            Dimension: d3d12::D3D12_RESOURCE_DIMENSION_BUFFER,
+            Alignment: 0,
+            Width: requirements.size,
+            Height: 1,
+            DepthOrArraySize: 1,
+            MipLevels: 1,
+            Format: dxgiformat::DXGI_FORMAT_UNKNOWN,
+            SampleDesc: dxgitype::DXGI_SAMPLE_DESC {
+                Count: 1,
+                Quality: 0,
+            },
+            Layout: d3d12::D3D12_TEXTURE_LAYOUT_ROW_MAJOR,
+            Flags: conv::map_buffer_flags(usage),
+        };
+
+        assert_eq!(winerror::S_OK, unsafe {
+            self.raw.clone().CreateReservedResource(
+                &desc,
+                d3d12::D3D12_RESOURCE_STATE_COMMON,
+                ptr::null(),
+                &d3d12::ID3D12Resource::uuidof(),
+                &mut resource,
+            )
+        });
+
+        let buffer = self.buffer_from_resource(resource as *mut _, requirements.size, usage);
+        Ok((buffer, requirements))
+    }
+
+    fn create_sparse_image(
+        &self,
+        kind: image::Kind,
+        mip_levels: image::Level,
+        format: format::Format,
+        usage: image::Usage,
+        storage_flags: image::StorageFlags,
+    ) -> Result<(n::Image, SparseImageRequirements), image::CreationError> {
+        let tile_size = d3d12::D3D12_TILED_RESOURCE_TILE_SIZE_IN_BYTES as u64;
+        let mut image = d::Device::create_image(
+            self,
+            kind,
+            mip_levels,
+            format,
+            image::Tiling::Optimal,
+            usage,
+            storage_flags | image::StorageFlags::SPARSE_BINDING,
+        )?;
+        // Reserved textures use the standard tile layout, the swizzle within a tile is undefined.
+        image.desc.Layout = d3d12::D3D12_TEXTURE_LAYOUT_64KB_UNDEFINED_SWIZZLE;
+
+        let mut resource = ptr::null_mut();
+        let mut resource_desc = image.desc;
+        if image.storage_flags.contains(image::StorageFlags::MUTABLE_FORMAT) {
+            if let Some(format) = conv::map_format_typeless(image.desc.Format) {
+                resource_desc.Format = format;
+            }
+        }
+
+        assert_eq!(winerror::S_OK, unsafe {
+            self.raw.clone().CreateReservedResource(
+                &resource_desc,
+                d3d12::D3D12_RESOURCE_STATE_COMMON,
+                ptr::null(),
+                &d3d12::ID3D12Resource::uuidof(),
+                &mut resource,
+            )
+        });
+
+        let mut num_tiles = 0;
+        let mut packed_mips: d3d12::D3D12_PACKED_MIP_INFO = unsafe { mem::zeroed() };
+        let mut tile_shape: d3d12::D3D12_TILE_SHAPE = unsafe { mem::zeroed() };
+        let mut num_subresource_tilings = 0;
+        unsafe {
+            self.raw.GetResourceTiling(
+                resource as *mut _,
+                &mut num_tiles,
+                &mut packed_mips,
+                &mut tile_shape,
+                &mut num_subresource_tilings,
+                0,
+                ptr::null_mut(),
+            );
+        }
+
+        let requirements = SparseImageRequirements {
+            memory: memory::Requirements {
+                size: num_tiles as u64 * tile_size,
+                alignment: tile_size,
+                type_mask: image.requirements.type_mask,
+            },
+            tile_extent: image::Extent {
+                width: tile_shape.WidthInTexels,
+                height: tile_shape.HeightInTexels,
+                depth: tile_shape.DepthInTexels,
+            },
+            mip_tail_first_level: packed_mips.NumStandardMips,
+            mip_tail_size: packed_mips.NumTilesForPackedMips as u64 * tile_size,
+            // Packed mips are addressed separately from the standard tiles.
+            mip_tail_offset: 0,
+        };
+        let place = n::Place::Reserved {
+            tile_shape: [tile_shape.WidthInTexels, tile_shape.HeightInTexels, tile_shape.DepthInTexels],
+            standard_mips: packed_mips.NumStandardMips,
+            packed_tiles: packed_mips.NumTilesForPackedMips,
+        };
+
+        Ok((self.image_from_resource(resource as *mut _, image, place), requirements))
+    }
+
+    fn destroy_image_view(&self, view: n::ImageView) {
+        if let Some(handle) = view.handle_srv {
+            self.srv_uav_pool.lock().unwrap().free_handle(handle);
+        }
+        if let Some(handle) = view.handle_uav {
+            self.srv_uav_pool.lock().unwrap().free_handle(handle);
+        }
+        if let Some(handle) = view.handle_rtv {
+            self.rtv_pool.lock().unwrap().free_handle(handle);
+        }
+        let mut dsv_pool = self.dsv_pool.lock().unwrap();
+        for &handle in view.handle_dsv.iter().chain(view.handle_dsv_read_only.iter()).flat_map(|h| h) {
+            dsv_pool.free_handle(handle);
+        }
     }
 
     fn destroy_sampler(&self, _sampler: n::Sampler) {
         // Just drop
     }
 
-    fn destroy_descriptor_pool(&self, _pool: n::DescriptorPool) {
-        // Just drop
+    fn destroy_descriptor_pool(&self, pool: n::DescriptorPool) {
         // Allocated descriptor sets don't need to be freed beforehand.
+        self.shared.heap_srv_cbv_uav.free(pool.heap_srv_cbv_uav.range);
+        self.shared.heap_sampler.free(pool.heap_sampler.range);
     }
 
     fn destroy_descriptor_set_layout(&self, _layout: n::DescriptorSetLayout) {
@@ -2921,14 +3739,62 @@ impl d::Device<B> for Device {
         // Just drop, ComPtr backed
     }
 
+    fn create_timeline_semaphore(&self, initial_value: u64) -> n::TimelineSemaphore {
+        let mut handle = ptr::null_mut();
+        assert_eq!(winerror::S_OK, unsafe {
+            self.raw.clone().CreateFence(
+                initial_value,
+                d3d12::D3D12_FENCE_FLAG_NONE,
+                &d3d12::ID3D12Fence::uuidof(),
+                &mut handle,
+            )
+        });
+        n::TimelineSemaphore {
+            raw: unsafe { ComPtr::from_raw(handle as *mut _) },
+        }
+    }
+
+    fn get_timeline_semaphore_value(&self, semaphore: &n::TimelineSemaphore) -> u64 {
+        unsafe { semaphore.raw.clone().GetCompletedValue() }
+    }
+
+    fn signal_timeline_semaphore(&self, semaphore: &n::TimelineSemaphore, value: u64) {
+        assert_eq!(winerror::S_OK, unsafe {
+            semaphore.raw.clone().Signal(value)
+        });
+    }
+
+    fn wait_for_timeline_semaphores<'a, I>(&self, semaphores: I, wait: d::WaitFor, timeout_ms: u32) -> bool
+    where
+        I: IntoIterator<Item = (&'a n::TimelineSemaphore, u64)>,
+    {
+        self.wait_for_raw_fences(
+            semaphores.into_iter().map(|(semaphore, value)| (&semaphore.raw, value)),
+            wait,
+            timeout_ms,
+        )
+    }
+
+    fn destroy_timeline_semaphore(&self, _semaphore: n::TimelineSemaphore) {
+        // Just drop, ComPtr backed
+    }
+
+    fn create_event(&self) -> () {
+        // Events are mapped onto split barriers.
+    }
+
+    fn destroy_event(&self, _: ()) {
+    }
+
     fn create_swapchain(
         &self,
         surface: &mut w::Surface,
         config: hal::SwapchainConfig,
-        _old_swapchain: Option<w::Swapchain>,
-        _extent: &window::Extent2D,
+        old_swapchain: Option<w::Swapchain>,
+        extent: &window::Extent2D,
     ) -> (w::Swapchain, hal::Backbuffer<B>) {
-        let mut swap_chain: *mut dxgi1_2::IDXGISwapChain1 = ptr::null_mut();
+        surface.width = extent.width;
+        surface.height = extent.height;
 
         let format = match config.color_format {
             // Apparently, swap chain doesn't like sRGB, but the RTV can still have some:
@@ -2956,6 +3822,13 @@ impl d::Device<B> for Device {
             config.image_count as _,
         );
 
+        // Tearing can't be enabled when resizing, allow it whenever supported.
+        let flags = if surface.supports_tearing() {
+            w::SWAP_CHAIN_FLAG_ALLOW_TEARING
+        } else {
+            0
+        };
+
         // TODO: double-check values
         let desc = dxgi1_2::DXGI_SWAP_CHAIN_DESC1 {
             AlphaMode: dxgi1_2::DXGI_ALPHA_MODE_IGNORE,
@@ -2963,7 +3836,7 @@ impl d::Device<B> for Device {
             Width: surface.width,
             Height: surface.height,
             Format: format,
-            Flags: 0,
+            Flags: flags,
             BufferUsage: dxgitype::DXGI_USAGE_RENDER_TARGET_OUTPUT,
             SampleDesc: dxgitype::DXGI_SAMPLE_DESC {
                 Count: 1,
@@ -2974,23 +3847,47 @@ impl d::Device<B> for Device {
             SwapEffect: dxgi::DXGI_SWAP_EFFECT_FLIP_DISCARD,
         };
 
-        let hr = unsafe {
-            // TODO
-            surface.factory.CreateSwapChainForHwnd(
-                self.present_queue.as_raw() as *mut _,
-                surface.wnd_handle,
-                &desc,
-                ptr::null(),
-                ptr::null_mut(),
-                &mut swap_chain as *mut *mut _,
-            )
-        };
+        let swap_chain = match old_swapchain {
+            // A window can only be associated with a single flip model swapchain,
+            // so the buffers of the retired one are resized in place instead.
+            Some(old_swapchain) => {
+                let swap_chain = old_swapchain.release_buffers();
+                let hr = unsafe {
+                    swap_chain.ResizeBuffers(
+                        config.image_count,
+                        surface.width,
+                        surface.height,
+                        format,
+                        flags,
+                    )
+                };
 
-        if !winerror::SUCCEEDED(hr) {
-            error!("error on swapchain creation 0x{:x}", hr);
-        }
+                if !winerror::SUCCEEDED(hr) {
+                    error!("error on swapchain resize 0x{:x}", hr);
+                }
+
+                swap_chain
+            }
+            None => {
+                let mut swap_chain: *mut dxgi1_2::IDXGISwapChain1 = ptr::null_mut();
+                let hr = unsafe {
+                    surface.factory.CreateSwapChainForHwnd(
+                        self.present_queue.as_raw() as *mut _,
+                        surface.wnd_handle,
+                        &desc,
+                        ptr::null(),
+                        ptr::null_mut(),
+                        &mut swap_chain as *mut *mut _,
+                    )
+                };
+
+                if !winerror::SUCCEEDED(hr) {
+                    error!("error on swapchain creation 0x{:x}", hr);
+                }
 
-        let swap_chain = unsafe { ComPtr::<dxgi1_4::IDXGISwapChain3>::from_raw(swap_chain as _) };
+                unsafe { ComPtr::<dxgi1_4::IDXGISwapChain3>::from_raw(swap_chain as _) }
+            }
+        };
 
         // Get backbuffer images
         let mut resources: Vec<ComPtr<d3d12::ID3D12Resource>> = Vec::new();
@@ -3027,7 +3924,9 @@ impl d::Device<B> for Device {
                 surface_type,
                 kind,
                 usage: config.image_usage,
-                storage_flags: image::StorageFlags::empty(),
+                // Flip model back buffers allow the sRGB and linear views of their format,
+                // see `create_image_view`.
+                storage_flags: image::StorageFlags::MUTABLE_FORMAT,
                 descriptor: d3d12::D3D12_RESOURCE_DESC {
                     Dimension: d3d12::D3D12_RESOURCE_DIMENSION_TEXTURE2D,
                     Alignment: 0,
@@ -3050,6 +3949,10 @@ impl d::Device<B> for Device {
 
         let swapchain = w::Swapchain {
             inner: swap_chain,
+            wnd_handle: surface.wnd_handle,
+            extent: *extent,
+            present_mode: config.present_mode,
+            flags,
             next_frame: 0,
             frame_queue: VecDeque::new(),
             rtv_heap,
@@ -3069,4 +3972,8 @@ impl d::Device<B> for Device {
         }
         Ok(())
     }
+
+    fn frame_statistics(&self) -> Option<FrameStatistics> {
+        Some(self.statistics.take())
+    }
 }
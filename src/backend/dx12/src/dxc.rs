@@ -0,0 +1,247 @@
+//! Shader model 6 compilation with the DirectX Shader Compiler.
+//!
+//! `D3DCompile` only targets shader models up to 5.1. DXC is loaded at runtime from
+//! `dxcompiler.dll`, the DXIL it emits is only accepted by the runtime when signed,
+//! which additionally requires `dxil.dll` to be found next to it.
+
+use std::sync::Mutex;
+use std::{mem, ptr, slice};
+
+use hal::device::ShaderError;
+use winapi::Interface;
+use winapi::shared::basetsd::{SIZE_T, UINT32};
+use winapi::shared::guiddef::{REFCLSID, REFIID};
+use winapi::shared::minwindef::{BOOL, LPCVOID, LPVOID};
+use winapi::shared::winerror;
+use winapi::um::libloaderapi;
+use winapi::um::unknwnbase::{IUnknown, IUnknownVtbl};
+use winapi::um::winnt::{HRESULT, LPCWSTR};
+use winapi::um::d3dcommon;
+use wio::com::ComPtr;
+
+DEFINE_GUID!{CLSID_DxcLibrary,
+    0x6245d6af, 0x66e0, 0x48fd, 0x80, 0xb4, 0x4d, 0x27, 0x17, 0x96, 0x74, 0x8c}
+DEFINE_GUID!{CLSID_DxcCompiler,
+    0x73e22d93, 0xe6ce, 0x47f3, 0xb5, 0xbf, 0xf0, 0x66, 0x4f, 0x39, 0xc1, 0xb0}
+
+// Layout compatible with `ID3DBlob`.
+RIDL!{#[uuid(0x8ba5fb08, 0x5195, 0x40e2, 0xac, 0x58, 0x0d, 0x98, 0x9c, 0x3a, 0x01, 0x02)]
+interface IDxcBlob(IDxcBlobVtbl): IUnknown(IUnknownVtbl) {
+    fn GetBufferPointer() -> LPVOID,
+    fn GetBufferSize() -> SIZE_T,
+}}
+
+RIDL!{#[uuid(0x7241d424, 0x2646, 0x4191, 0x97, 0xc0, 0x98, 0xe9, 0x6e, 0x42, 0xfc, 0x68)]
+interface IDxcBlobEncoding(IDxcBlobEncodingVtbl): IDxcBlob(IDxcBlobVtbl) {
+    fn GetEncoding(
+        pKnown: *mut BOOL,
+        pCodePage: *mut UINT32,
+    ) -> HRESULT,
+}}
+
+// Only the methods up to the ones used are declared.
+RIDL!{#[uuid(0xe5204dc7, 0xd18c, 0x4c3c, 0xbd, 0xfb, 0x85, 0x16, 0x73, 0x98, 0x0f, 0xe7)]
+interface IDxcLibrary(IDxcLibraryVtbl): IUnknown(IUnknownVtbl) {
+    fn SetMalloc(
+        pMalloc: *mut IUnknown,
+    ) -> HRESULT,
+    fn CreateBlobFromBlob(
+        pBlob: *mut IDxcBlob,
+        offset: UINT32,
+        length: UINT32,
+        ppResult: *mut *mut IDxcBlob,
+    ) -> HRESULT,
+    fn CreateBlobFromFile(
+        pFileName: LPCWSTR,
+        codePage: *mut UINT32,
+        pBlobEncoding: *mut *mut IDxcBlobEncoding,
+    ) -> HRESULT,
+    fn CreateBlobWithEncodingFromPinned(
+        pText: LPCVOID,
+        size: UINT32,
+        codePage: UINT32,
+        pBlobEncoding: *mut *mut IDxcBlobEncoding,
+    ) -> HRESULT,
+}}
+
+RIDL!{#[uuid(0xcedb484a, 0xd4e9, 0x445a, 0xb9, 0x91, 0xca, 0x21, 0xca, 0x15, 0x7d, 0xc2)]
+interface IDxcOperationResult(IDxcOperationResultVtbl): IUnknown(IUnknownVtbl) {
+    fn GetStatus(
+        pStatus: *mut HRESULT,
+    ) -> HRESULT,
+    fn GetResult(
+        pResult: *mut *mut IDxcBlob,
+    ) -> HRESULT,
+    fn GetErrorBuffer(
+        pErrors: *mut *mut IDxcBlobEncoding,
+    ) -> HRESULT,
+}}
+
+RIDL!{#[uuid(0x8c210bf3, 0x011f, 0x4422, 0x8d, 0x70, 0x6f, 0x9a, 0xcb, 0x8d, 0xb6, 0x17)]
+interface IDxcCompiler(IDxcCompilerVtbl): IUnknown(IUnknownVtbl) {
+    fn Compile(
+        pSource: *mut IDxcBlob,
+        pSourceName: LPCWSTR,
+        pEntryPoint: LPCWSTR,
+        pTargetProfile: LPCWSTR,
+        pArguments: *mut LPCWSTR,
+        argCount: UINT32,
+        pDefines: *const LPCVOID,
+        defineCount: UINT32,
+        pIncludeHandler: *mut IUnknown,
+        ppResult: *mut *mut IDxcOperationResult,
+    ) -> HRESULT,
+}}
+
+type DxcCreateInstanceProc = unsafe extern "system" fn(REFCLSID, REFIID, *mut LPVOID) -> HRESULT;
+
+const CP_UTF8: UINT32 = 65001;
+
+fn wide(s: &str) -> Vec<u16> {
+    s.encode_utf16().chain(Some(0)).collect()
+}
+
+/// Blob contents as text, used for the error messages.
+unsafe fn blob_text(blob: *mut IDxcBlob) -> String {
+    let pointer = (*blob).GetBufferPointer();
+    let size = (*blob).GetBufferSize();
+    let text = slice::from_raw_parts(pointer as *const u8, size as usize);
+    String::from_utf8_lossy(text).trim_right_matches('\0').to_owned()
+}
+
+struct Objects {
+    library: ComPtr<IDxcLibrary>,
+    compiler: ComPtr<IDxcCompiler>,
+}
+
+/// DXC library and compiler instances.
+///
+/// The compiler objects aren't thread safe, compilations are serialized.
+pub(crate) struct Dxc {
+    objects: Mutex<Objects>,
+}
+
+unsafe impl Send for Dxc { }
+unsafe impl Sync for Dxc { }
+
+impl Dxc {
+    /// Load `dxcompiler.dll`, returns `None` if it isn't available.
+    ///
+    /// `dxil.dll` needs to be available as well, the runtime rejects the DXIL
+    /// bytecode not signed by it. The libraries are never unloaded.
+    pub fn load() -> Option<Self> {
+        let dxil = unsafe { libloaderapi::LoadLibraryA(b"dxil.dll\0".as_ptr() as *const _) };
+        if dxil.is_null() {
+            info!("dxil.dll not found, shader model 6 is not available");
+            return None;
+        }
+        let module = unsafe { libloaderapi::LoadLibraryA(b"dxcompiler.dll\0".as_ptr() as *const _) };
+        if module.is_null() {
+            info!("dxcompiler.dll not found, shader model 6 is not available");
+            return None;
+        }
+        let create_instance = unsafe {
+            let proc_address = libloaderapi::GetProcAddress(module, b"DxcCreateInstance\0".as_ptr() as *const _);
+            if proc_address.is_null() {
+                return None;
+            }
+            mem::transmute::<_, DxcCreateInstanceProc>(proc_address)
+        };
+
+        let mut library: *mut IDxcLibrary = ptr::null_mut();
+        let mut compiler: *mut IDxcCompiler = ptr::null_mut();
+        let (hr_library, hr_compiler) = unsafe {
+            (
+                create_instance(
+                    &CLSID_DxcLibrary,
+                    &IDxcLibrary::uuidof(),
+                    &mut library as *mut *mut _ as *mut _,
+                ),
+                create_instance(
+                    &CLSID_DxcCompiler,
+                    &IDxcCompiler::uuidof(),
+                    &mut compiler as *mut *mut _ as *mut _,
+                ),
+            )
+        };
+        if !winerror::SUCCEEDED(hr_library) || !winerror::SUCCEEDED(hr_compiler) {
+            error!("DxcCreateInstance error {:x} {:x}", hr_library, hr_compiler);
+            unsafe {
+                if !library.is_null() { (*library).Release(); }
+                if !compiler.is_null() { (*compiler).Release(); }
+            }
+            return None;
+        }
+
+        Some(Dxc {
+            objects: Mutex::new(Objects {
+                library: unsafe { ComPtr::from_raw(library) },
+                compiler: unsafe { ComPtr::from_raw(compiler) },
+            }),
+        })
+    }
+
+    /// Compile a HLSL entry point for the given target profile, e.g. `ps_6_0`.
+    ///
+    /// The returned blob is layout compatible with `ID3DBlob`.
+    pub fn compile(
+        &self,
+        profile: &str,
+        entry: &str,
+        code: &[u8],
+    ) -> Result<*mut d3dcommon::ID3DBlob, ShaderError> {
+        let objects = self.objects.lock().unwrap();
+
+        let source = unsafe {
+            let mut source = ptr::null_mut();
+            let hr = objects.library.CreateBlobWithEncodingFromPinned(
+                code.as_ptr() as *const _,
+                code.len() as UINT32,
+                CP_UTF8,
+                &mut source,
+            );
+            if !winerror::SUCCEEDED(hr) {
+                return Err(ShaderError::CompilationFailed(format!("Unable to create the source blob: {:x}", hr)));
+            }
+            ComPtr::from_raw(source)
+        };
+
+        let entry = wide(entry);
+        let profile = wide(profile);
+        let result = unsafe {
+            let mut result = ptr::null_mut();
+            let hr = objects.compiler.Compile(
+                source.as_raw() as *mut IDxcBlob,
+                ptr::null(),
+                entry.as_ptr(),
+                profile.as_ptr(),
+                ptr::null_mut(),
+                0,
+                ptr::null(),
+                0,
+                ptr::null_mut(),
+                &mut result,
+            );
+            if !winerror::SUCCEEDED(hr) {
+                return Err(ShaderError::CompilationFailed(format!("DXC compile error {:x}", hr)));
+            }
+            ComPtr::from_raw(result)
+        };
+
+        unsafe {
+            let mut status = winerror::S_OK;
+            result.GetStatus(&mut status);
+            if winerror::SUCCEEDED(status) {
+                let mut blob = ptr::null_mut();
+                result.GetResult(&mut blob);
+                Ok(blob as *mut d3dcommon::ID3DBlob)
+            } else {
+                let mut errors = ptr::null_mut();
+                result.GetErrorBuffer(&mut errors);
+                let errors = ComPtr::from_raw(errors);
+                error!("DXC compile error {:x}", status);
+                Err(ShaderError::CompilationFailed(blob_text(errors.as_raw() as *mut IDxcBlob)))
+            }
+        }
+    }
+}
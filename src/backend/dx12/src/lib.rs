@@ -7,6 +7,7 @@ extern crate gfx_hal as hal;
 extern crate log;
 extern crate smallvec;
 extern crate spirv_cross;
+#[macro_use]
 extern crate winapi;
 #[cfg(feature = "winit")]
 extern crate winit;
@@ -16,8 +17,11 @@ extern crate wio;
 mod range_alloc;
 mod command;
 mod conv;
+mod dedup;
 mod descriptors_cpu;
+mod descriptors_gpu;
 mod device;
+mod dxc;
 mod internal;
 mod native;
 mod pool;
@@ -25,6 +29,8 @@ mod root_constants;
 mod window;
 
 use hal::{error, format as f, image, memory, Features, SwapImageIndex, Limits, QueueType};
+use hal::backend::{FastHashMap, StatisticsCollector};
+use hal::profiler::FrameStatistics;
 use hal::queue::{QueueFamilyId, Queues};
 use descriptors_cpu::DescriptorCpuPool;
 
@@ -40,6 +46,8 @@ use std::os::windows::ffi::OsStringExt;
 use std::ffi::OsString;
 use std::sync::{Arc, Mutex};
 
+pub use spirv_cross::hlsl::ShaderModel;
+
 pub(crate) struct HeapProperties {
     pub page_property: d3d12::D3D12_CPU_PAGE_PROPERTY,
     pub memory_pool: d3d12::D3D12_MEMORY_POOL,
@@ -51,6 +59,10 @@ const MAX_VERTEX_BUFFERS: usize = 16;
 
 const NUM_HEAP_PROPERTIES: usize = 3;
 
+// Number of transient segments reserved in the shader visible CBV/SRV/UAV heap,
+// shared by all command buffers of a device.
+const NUM_TRANSIENT_SEGMENTS: u64 = 64;
+
 // Memory types are grouped according to the supported resources.
 // Grouping is done to circumvent the limitations of heap tier 1 devices.
 // Devices with Tier 1 will expose `BuffersOnl`, `ImageOnly` and `TargetOnly`.
@@ -144,6 +156,11 @@ impl hal::QueueFamily for QueueFamily {
             QueueFamily::Normal(_) => MAX_QUEUES,
         }
     }
+    fn supports_sparse_binding(&self) -> bool {
+        // Tile mappings can be updated on all queue types,
+        // availability is governed by `Features::SPARSE_BINDING`.
+        true
+    }
     fn id(&self) -> QueueFamilyId {
         // This must match the order exposed by `QUEUE_FAMILIES`
         QueueFamilyId(match *self {
@@ -183,6 +200,7 @@ pub struct PhysicalDevice {
     private_caps: Capabilities,
     heap_properties: &'static [HeapProperties; NUM_HEAP_PROPERTIES],
     memory_properties: hal::MemoryProperties,
+    dxc: Option<Arc<dxc::Dxc>>,
     // Indicates that there is currently an active logical device.
     // Opening the same adapter multiple times will return the same D3D12Device again.
     is_open: Arc<Mutex<bool>>,
@@ -191,6 +209,15 @@ pub struct PhysicalDevice {
 unsafe impl Send for PhysicalDevice { }
 unsafe impl Sync for PhysicalDevice { }
 
+impl PhysicalDevice {
+    /// Highest HLSL shader model supported by the device and the available compilers.
+    ///
+    /// Shader model 6.0 requires the device support and `dxcompiler.dll`.
+    pub fn max_shader_model(&self) -> ShaderModel {
+        self.private_caps.max_shader_model
+    }
+}
+
 impl hal::PhysicalDevice<Backend> for PhysicalDevice {
     fn open(
         &self, families: &[(&QueueFamily, &[hal::QueuePriority])]
@@ -273,6 +300,7 @@ impl hal::PhysicalDevice<Backend> for PhysicalDevice {
                             raw: device.present_queue.clone(),
                             idle_fence: device.create_raw_fence(false),
                             idle_event: create_idle_event(),
+                            statistics: device.statistics.clone(),
                         };
                         device.append_queue(queue.clone());
                         group.add_queue(queue);
@@ -300,6 +328,7 @@ impl hal::PhysicalDevice<Backend> for PhysicalDevice {
                                     raw: unsafe { ComPtr::from_raw(queue) },
                                     idle_fence: device.create_raw_fence(false),
                                     idle_event: create_idle_event(),
+                                    statistics: device.statistics.clone(),
                                 };
                                 device.append_queue(queue.clone());
                                 group.add_queue(queue);
@@ -331,7 +360,7 @@ impl hal::PhysicalDevice<Backend> for PhysicalDevice {
         &self, format: f::Format, dimensions: u8, tiling: image::Tiling,
         usage: image::Usage, storage_flags: image::StorageFlags,
     ) -> Option<image::FormatProperties> {
-        conv::map_format(format)?; //filter out unknown formats
+        conv::map_format_with_swizzle(format)?; //filter out unknown formats
 
         let supported_usage = {
             use hal::image::Usage as U;
@@ -432,11 +461,52 @@ pub struct CommandQueue {
     pub(crate) raw: ComPtr<d3d12::ID3D12CommandQueue>,
     idle_fence: *mut d3d12::ID3D12Fence,
     idle_event: winnt::HANDLE,
+    statistics: Arc<StatisticsCollector>,
 }
 
 unsafe impl Send for CommandQueue {}
 unsafe impl Sync for CommandQueue {}
 
+impl CommandQueue {
+    /// Get the native d3d12 command queue.
+    ///
+    /// Required for FFI with libraries like OpenXR.
+    pub unsafe fn as_raw(&self) -> *mut d3d12::ID3D12CommandQueue {
+        self.raw.as_raw()
+    }
+
+    /// Map a region of tiles of a reserved resource to a heap range, or unmap it.
+    unsafe fn update_tile_mapping(
+        &self,
+        resource: *mut d3d12::ID3D12Resource,
+        coordinate: d3d12::D3D12_TILED_RESOURCE_COORDINATE,
+        region: d3d12::D3D12_TILE_REGION_SIZE,
+        memory: Option<(&native::Memory, u64)>,
+    ) {
+        let tile_size = d3d12::D3D12_TILED_RESOURCE_TILE_SIZE_IN_BYTES as u64;
+        let (heap, flags, heap_offset) = match memory {
+            Some((memory, offset)) => (
+                memory.heap.as_raw(),
+                d3d12::D3D12_TILE_RANGE_FLAG_NONE,
+                (offset / tile_size) as u32,
+            ),
+            None => (ptr::null_mut(), d3d12::D3D12_TILE_RANGE_FLAG_NULL, 0),
+        };
+        self.raw.UpdateTileMappings(
+            resource,
+            1,
+            &coordinate,
+            &region,
+            heap,
+            1,
+            &flags,
+            &heap_offset,
+            &region.NumTiles,
+            d3d12::D3D12_TILE_MAPPING_FLAG_NONE,
+        );
+    }
+}
+
 impl hal::queue::RawCommandQueue<Backend> for CommandQueue {
     unsafe fn submit_raw<IC>(
         &mut self,
@@ -452,13 +522,130 @@ impl hal::queue::RawCommandQueue<Backend> for CommandQueue {
         synchapi::ResetEvent(self.idle_event);
 
         // TODO: semaphores
+        for &(semaphore, value, _) in submission.wait_timeline_semaphores {
+            assert_eq!(winerror::S_OK,
+                self.raw.Wait(semaphore.raw.as_raw(), value)
+            );
+        }
+
+        let mut statistics = FrameStatistics::default();
         let mut lists = submission
             .cmd_buffers
             .into_iter()
-            .map(|buf| buf.borrow().as_raw_list())
+            .map(|buf| {
+                let buf = buf.borrow();
+                statistics.add(&buf.statistics);
+                buf.as_raw_list()
+            })
             .collect::<Vec<_>>();
+        self.statistics.add(&statistics);
         self.raw.ExecuteCommandLists(lists.len() as _, lists.as_mut_ptr());
 
+        for &(semaphore, value) in submission.signal_timeline_semaphores {
+            assert_eq!(winerror::S_OK,
+                self.raw.Signal(semaphore.raw.as_raw(), value)
+            );
+        }
+
+        if let Some(fence) = fence {
+            assert_eq!(winerror::S_OK,
+                self.raw.Signal(fence.raw.as_raw(), 1)
+            );
+        }
+    }
+
+    unsafe fn bind_sparse(
+        &mut self,
+        info: hal::sparse::SparseBindInfo<Backend>,
+        fence: Option<&native::Fence>,
+    ) {
+        let tile_size = d3d12::D3D12_TILED_RESOURCE_TILE_SIZE_IN_BYTES as u64;
+        let num_tiles = |size: u64| ((size + tile_size - 1) / tile_size) as u32;
+        // TODO: semaphores
+
+        for &(buffer, binds) in info.buffer_binds {
+            for bind in binds {
+                let coordinate = d3d12::D3D12_TILED_RESOURCE_COORDINATE {
+                    X: (bind.resource_offset / tile_size) as u32,
+                    Y: 0,
+                    Z: 0,
+                    Subresource: 0,
+                };
+                let region = d3d12::D3D12_TILE_REGION_SIZE {
+                    NumTiles: num_tiles(bind.size),
+                    UseBox: FALSE,
+                    Width: 0,
+                    Height: 0,
+                    Depth: 0,
+                };
+                self.update_tile_mapping(buffer.resource, coordinate, region, bind.memory);
+            }
+        }
+
+        // Opaque binds address the packed mip tails of all layers, laid out one after another.
+        for &(image, binds) in info.image_opaque_binds {
+            let (standard_mips, packed_tiles) = match image.place {
+                native::Place::Reserved { standard_mips, packed_tiles, .. } => (standard_mips, packed_tiles),
+                _ => panic!("Opaque sparse bind of a non-sparse image"),
+            };
+            for bind in binds {
+                let mut tile = (bind.resource_offset / tile_size) as u32;
+                let mut remaining = num_tiles(bind.size);
+                let mut memory = bind.memory;
+                while remaining != 0 {
+                    let (layer, offset) = (tile / packed_tiles, tile % packed_tiles);
+                    let count = remaining.min(packed_tiles - offset);
+                    let coordinate = d3d12::D3D12_TILED_RESOURCE_COORDINATE {
+                        X: offset,
+                        Y: 0,
+                        Z: 0,
+                        Subresource: image.calc_subresource(standard_mips as _, layer, 0),
+                    };
+                    let region = d3d12::D3D12_TILE_REGION_SIZE {
+                        NumTiles: count,
+                        UseBox: FALSE,
+                        Width: 0,
+                        Height: 0,
+                        Depth: 0,
+                    };
+                    self.update_tile_mapping(image.resource, coordinate, region, memory);
+                    memory = memory.map(|(memory, offset)| (memory, offset + count as u64 * tile_size));
+                    tile += count;
+                    remaining -= count;
+                }
+            }
+        }
+
+        for &(image, binds) in info.image_binds {
+            let tile_shape = match image.place {
+                native::Place::Reserved { tile_shape, .. } => tile_shape,
+                _ => panic!("Sparse bind of a non-sparse image"),
+            };
+            for bind in binds {
+                let width = (bind.extent.width + tile_shape[0] - 1) / tile_shape[0];
+                let height = (bind.extent.height + tile_shape[1] - 1) / tile_shape[1];
+                let depth = (bind.extent.depth + tile_shape[2] - 1) / tile_shape[2];
+                let coordinate = d3d12::D3D12_TILED_RESOURCE_COORDINATE {
+                    X: bind.offset.x as u32 / tile_shape[0],
+                    Y: bind.offset.y as u32 / tile_shape[1],
+                    Z: bind.offset.z as u32 / tile_shape[2],
+                    Subresource: image.calc_subresource(
+                        bind.subresource.level as _,
+                        bind.subresource.layer as _,
+                        0,
+                    ),
+                };
+                let region = d3d12::D3D12_TILE_REGION_SIZE {
+                    NumTiles: width * height * depth,
+                    UseBox: TRUE,
+                    Width: width,
+                    Height: height as _,
+                    Depth: depth as _,
+                };
+                self.update_tile_mapping(image.resource, coordinate, region, bind.memory);
+            }
+        }
+
         if let Some(fence) = fence {
             assert_eq!(winerror::S_OK,
                 self.raw.Signal(fence.raw.as_raw(), 1)
@@ -466,7 +653,9 @@ impl hal::queue::RawCommandQueue<Backend> for CommandQueue {
         }
     }
 
-    fn present<IS, S, IW>(&mut self, swapchains: IS, _wait_semaphores: IW) -> Result<(), ()>
+    fn present<IS, S, IW>(
+        &mut self, swapchains: IS, _wait_semaphores: IW
+    ) -> Result<Option<hal::window::Suboptimal>, hal::window::PresentError>
     where
         IS: IntoIterator<Item = (S, SwapImageIndex)>,
         S: Borrow<window::Swapchain>,
@@ -474,11 +663,25 @@ impl hal::queue::RawCommandQueue<Backend> for CommandQueue {
         IW::Item: Borrow<native::Semaphore>,
     {
         // TODO: semaphores
+        let mut suboptimal = None;
         for (swapchain, _) in swapchains {
-            unsafe { swapchain.borrow().inner.Present(1, 0); }
+            let swapchain = swapchain.borrow();
+            let (sync_interval, flags) = swapchain.present_params();
+            let hr = unsafe { swapchain.inner.Present(sync_interval, flags) };
+            match hr {
+                winerror::DXGI_ERROR_DEVICE_REMOVED |
+                winerror::DXGI_ERROR_DEVICE_RESET => {
+                    return Err(hal::window::PresentError::DeviceLost);
+                }
+                _ if !winerror::SUCCEEDED(hr) => {
+                    error!("error on present 0x{:x}", hr);
+                }
+                _ => {}
+            }
+            suboptimal = suboptimal.or(swapchain.suboptimal());
         }
 
-        Ok(())
+        Ok(suboptimal)
     }
 
     fn wait_idle(&self) -> Result<(), error::HostExecutionError> {
@@ -503,6 +706,7 @@ enum MemoryArchitecture {
 pub struct Capabilities {
     heterogeneous_resource_heaps: bool,
     memory_architecture: MemoryArchitecture,
+    max_shader_model: ShaderModel,
 }
 
 #[derive(Clone)]
@@ -516,11 +720,15 @@ struct CmdSignatures {
 struct Shared {
     pub signatures: CmdSignatures,
     pub service_pipes: internal::ServicePipes,
+    // Shader visible descriptor heaps, bound once per command list.
+    pub heap_srv_cbv_uav: Arc<descriptors_gpu::HeapGpu>,
+    pub heap_sampler: Arc<descriptors_gpu::HeapGpu>,
 }
 
 pub struct Device {
     raw: ComPtr<d3d12::ID3D12Device>,
     private_caps: Capabilities,
+    features: Features,
     format_properties: Arc<[f::Properties; f::NUM_FORMATS]>,
     heap_properties: &'static [HeapProperties],
     // CPU only pools
@@ -529,9 +737,6 @@ pub struct Device {
     srv_uav_pool: Mutex<DescriptorCpuPool>,
     sampler_pool: Mutex<DescriptorCpuPool>,
     descriptor_update_pools: Mutex<Vec<descriptors_cpu::HeapLinear>>,
-    // CPU/GPU descriptor heaps
-    heap_srv_cbv_uav: Mutex<native::DescriptorHeap>,
-    heap_sampler: Mutex<native::DescriptorHeap>,
     events: Mutex<Vec<winnt::HANDLE>>,
     shared: Arc<Shared>,
     // Present queue exposed by the `Present` queue family.
@@ -542,6 +747,16 @@ pub struct Device {
     queues: Vec<CommandQueue>,
     // Indicates that there is currently an active device.
     open: Arc<Mutex<bool>>,
+    // Created pipelines, shared between identical pipeline descriptions.
+    pipeline_dedup: dedup::PipelineDedup,
+    // Sampler descriptors, shared between identical sampler descriptions.
+    // CPU descriptors are never freed, so these live as long as the device.
+    samplers: Mutex<FastHashMap<image::SamplerInfo, d3d12::D3D12_CPU_DESCRIPTOR_HANDLE>>,
+    // Command statistics, accumulated by the queues on submission.
+    statistics: Arc<StatisticsCollector>,
+    // Shader model of the HLSL translated from SPIR-V or compiled from source.
+    shader_model: ShaderModel,
+    dxc: Option<Arc<dxc::Dxc>>,
 }
 unsafe impl Send for Device {} //blocked by ComPtr
 unsafe impl Sync for Device {} //blocked by ComPtr
@@ -596,11 +811,18 @@ impl Device {
         let shared = Shared {
             signatures,
             service_pipes,
+            heap_srv_cbv_uav: Arc::new(descriptors_gpu::HeapGpu::new(
+                heap_srv_cbv_uav,
+                NUM_TRANSIENT_SEGMENTS,
+            )),
+            // Sampler heaps are small, internal samplers are static samplers instead.
+            heap_sampler: Arc::new(descriptors_gpu::HeapGpu::new(heap_sampler, 0)),
         };
 
         Device {
             raw: device,
             private_caps: physical_device.private_caps,
+            features: physical_device.features,
             format_properties: physical_device.format_properties.clone(),
             heap_properties: physical_device.heap_properties,
             rtv_pool: Mutex::new(rtv_pool),
@@ -608,13 +830,16 @@ impl Device {
             srv_uav_pool: Mutex::new(srv_uav_pool),
             sampler_pool: Mutex::new(sampler_pool),
             descriptor_update_pools: Mutex::new(Vec::new()),
-            heap_srv_cbv_uav: Mutex::new(heap_srv_cbv_uav),
-            heap_sampler: Mutex::new(heap_sampler),
             events: Mutex::new(Vec::new()),
             shared: Arc::new(shared),
             present_queue,
             queues: Vec::new(),
             open: physical_device.is_open.clone(),
+            pipeline_dedup: dedup::PipelineDedup::default(),
+            samplers: Mutex::new(FastHashMap::default()),
+            statistics: Arc::new(StatisticsCollector::default()),
+            shader_model: ShaderModel::V5_1,
+            dxc: physical_device.dxc.clone(),
         }
     }
 
@@ -628,6 +853,26 @@ impl Device {
     pub unsafe fn as_raw(&self) -> *mut d3d12::ID3D12Device {
         self.raw.as_raw()
     }
+
+    /// Shader model targeted by pipeline and shader module creation.
+    pub fn shader_model(&self) -> ShaderModel {
+        self.shader_model
+    }
+
+    /// Change the targeted shader model, 5.1 by default.
+    ///
+    /// Shaders using subgroup operations always target shader model 6.0, which
+    /// allows them to use wave intrinsics.
+    pub fn set_shader_model(&mut self, shader_model: ShaderModel) {
+        let supported = match (shader_model, self.private_caps.max_shader_model) {
+            (ShaderModel::V5_0, _) |
+            (ShaderModel::V5_1, _) |
+            (ShaderModel::V6_0, ShaderModel::V6_0) => true,
+            _ => false,
+        };
+        assert!(supported, "Shader model is not supported by the device");
+        self.shader_model = shader_model;
+    }
 }
 
 impl Drop for Device {
@@ -644,6 +889,7 @@ impl Drop for Device {
 
 pub struct Instance {
     pub(crate) factory: ComPtr<dxgi1_4::IDXGIFactory4>,
+    dxc: Option<Arc<dxc::Dxc>>,
 }
 
 unsafe impl Send for Instance { }
@@ -683,8 +929,23 @@ impl Instance {
 
         Instance {
             factory: unsafe { ComPtr::from_raw(dxgi_factory) },
+            dxc: dxc::Dxc::load().map(Arc::new),
         }
     }
+
+    /// Get the adapter with the given LUID.
+    ///
+    /// Required for OpenXR, which requests the adapter to be used by the runtime
+    /// with `xrGetD3D12GraphicsRequirementsKHR`.
+    pub fn adapter_from_luid(&self, luid: winnt::LUID) -> Option<hal::Adapter<Backend>> {
+        hal::Instance::enumerate_adapters(self)
+            .into_iter()
+            .find(|adapter| {
+                let mut desc: dxgi1_2::DXGI_ADAPTER_DESC2 = unsafe { mem::zeroed() };
+                unsafe { adapter.physical_device.adapter.GetDesc2(&mut desc); }
+                desc.AdapterLuid.LowPart == luid.LowPart && desc.AdapterLuid.HighPart == luid.HighPart
+            })
+    }
 }
 
 impl hal::Instance for Instance {
@@ -744,11 +1005,12 @@ impl hal::Instance for Instance {
                 name.to_string_lossy().into_owned()
             };
 
-            let info = hal::AdapterInfo {
+            let mut info = hal::AdapterInfo {
                 name: device_name,
                 vendor: desc.VendorId as usize,
                 device: desc.DeviceId as usize,
                 software_rendering: (desc.Flags & dxgi::DXGI_ADAPTER_FLAG_SOFTWARE) != 0,
+                device_type: hal::adapter::DeviceType::DiscreteGpu,
             };
 
             let mut features: d3d12::D3D12_FEATURE_DATA_D3D12_OPTIONS = unsafe { mem::zeroed() };
@@ -785,14 +1047,59 @@ impl hal::Instance for Instance {
                 }
             };
 
+            // Shader model 6 can only be targeted by DXC.
+            let max_shader_model = {
+                let mut shader_model = d3d12::D3D12_FEATURE_DATA_SHADER_MODEL {
+                    HighestShaderModel: d3d12::D3D_SHADER_MODEL_6_0,
+                };
+                let hr = unsafe {
+                    device.CheckFeatureSupport(
+                        d3d12::D3D12_FEATURE_SHADER_MODEL,
+                        &mut shader_model as *mut _ as *mut _,
+                        mem::size_of::<d3d12::D3D12_FEATURE_DATA_SHADER_MODEL>() as _,
+                    )
+                };
+                if hr == winerror::S_OK &&
+                    shader_model.HighestShaderModel >= d3d12::D3D_SHADER_MODEL_6_0 &&
+                    self.dxc.is_some()
+                {
+                    ShaderModel::V6_0
+                } else {
+                    ShaderModel::V5_1
+                }
+            };
+
+            // Wave intrinsics require shader model 6, without it only the lane counts
+            // are reported and no `Features::SUBGROUP_*` are exposed.
+            let wave_lane_counts = {
+                let mut options1: d3d12::D3D12_FEATURE_DATA_D3D12_OPTIONS1 = unsafe { mem::zeroed() };
+                let hr = unsafe {
+                    device.CheckFeatureSupport(
+                        d3d12::D3D12_FEATURE_D3D12_OPTIONS1,
+                        &mut options1 as *mut _ as *mut _,
+                        mem::size_of::<d3d12::D3D12_FEATURE_DATA_D3D12_OPTIONS1>() as _,
+                    )
+                };
+                if hr == winerror::S_OK && options1.WaveOps != 0 {
+                    (options1.WaveLaneCountMin, options1.WaveLaneCountMax)
+                } else {
+                    (0, 0)
+                }
+            };
+            let wave_ops = match max_shader_model {
+                ShaderModel::V6_0 => wave_lane_counts.1 != 0,
+                _ => false,
+            };
+
             let mut format_properties = [f::Properties::default(); f::NUM_FORMATS];
             for (i, props) in &mut format_properties.iter_mut().enumerate().skip(1) {
                 let format: f::Format = unsafe { mem::transmute(i as u32) };
+                let (dxgi_format, emulated) = match conv::map_format_with_swizzle(format) {
+                    Some((format, swizzle)) => (format, swizzle != f::Swizzle::NO),
+                    None => continue,
+                };
                 let mut data = d3d12::D3D12_FEATURE_DATA_FORMAT_SUPPORT {
-                    Format: match conv::map_format(format) {
-                        Some(format) => format,
-                        None => continue,
-                    },
+                    Format: dxgi_format,
                     Support1: unsafe { mem::zeroed() },
                     Support2: unsafe { mem::zeroed() },
                 };
@@ -836,7 +1143,6 @@ impl hal::Instance for Instance {
                     props.optimal_tiling |= f::ImageFeature::DEPTH_STENCIL_ATTACHMENT;
                 }
                 if data.Support1 & d3d12::D3D12_FORMAT_SUPPORT1_SHADER_LOAD != 0 {
-                    //TODO: check d3d12::D3D12_FORMAT_SUPPORT2_UAV_TYPED_LOAD ?
                     if can_buffer {
                         props.buffer_features |= f::BufferFeature::UNIFORM_TEXEL;
                     }
@@ -857,8 +1163,23 @@ impl hal::Instance for Instance {
                     if can_image {
                         props.optimal_tiling |= f::ImageFeature::STORAGE;
                     }
+                    if data.Support2 & d3d12::D3D12_FORMAT_SUPPORT2_UAV_TYPED_LOAD != 0 {
+                        if can_buffer {
+                            props.buffer_features |= f::BufferFeature::STORAGE_TEXEL_READ;
+                        }
+                        if can_image {
+                            props.optimal_tiling |= f::ImageFeature::STORAGE_READ;
+                        }
+                    }
                 }
                 //TODO: blits, linear tiling
+                if emulated {
+                    // The channel order is emulated by swizzling the shader resource views
+                    let sampled = f::ImageFeature::SAMPLED | f::ImageFeature::SAMPLED_LINEAR | f::ImageFeature::BLIT_SRC;
+                    props.optimal_tiling &= sampled;
+                    props.linear_tiling &= sampled;
+                    props.buffer_features = f::BufferFeature::empty();
+                }
             }
 
             let heterogeneous_resource_heaps = features.ResourceHeapTier != d3d12::D3D12_RESOURCE_HEAP_TIER_1;
@@ -866,6 +1187,15 @@ impl hal::Instance for Instance {
             let uma = features_architecture.UMA == TRUE;
             let cc_uma = features_architecture.CacheCoherentUMA == TRUE;
 
+            // Adapters sharing the memory of the host are integrated ones.
+            info.device_type = if info.software_rendering {
+                hal::adapter::DeviceType::Cpu
+            } else if uma {
+                hal::adapter::DeviceType::IntegratedGpu
+            } else {
+                hal::adapter::DeviceType::DiscreteGpu
+            };
+
             let (memory_architecture, heap_properties) = match (uma, cc_uma) {
                 (true, true)  => (MemoryArchitecture::CacheCoherentUMA, &HEAPS_CCUMA),
                 (true, false) => (MemoryArchitecture::UMA, &HEAPS_UMA),
@@ -998,6 +1328,8 @@ impl hal::Instance for Instance {
 
             let physical_device = PhysicalDevice {
                 adapter,
+                // `SV_Barycentrics` requires shader model 6.1, which can't be targeted by
+                // the SPIR-V translation, `Features::FRAGMENT_BARYCENTRIC` isn't exposed.
                 features:
                     // TODO: add more features, based on
                     // https://msdn.microsoft.com/de-de/library/windows/desktop/mt186615(v=vs.85).aspx
@@ -1007,10 +1339,45 @@ impl hal::Instance for Instance {
                     Features::TESSELLATION_SHADER |
                     Features::NON_FILL_POLYGON_MODE |
                     //logic_op: false, // Optional on feature level 11_0
+                    // Native 16-bit types require shader model 6.2, which `D3DCompile` can't target.
                     Features::MULTI_DRAW_INDIRECT |
                     Features::FORMAT_BC |
                     Features::INSTANCE_RATE |
-                    if depth_bounds_test_supported { Features::DEPTH_BOUNDS } else { Features::empty() },
+                    Features::DYNAMIC_RENDERING |
+                    Features::IMAGELESS_FRAMEBUFFER |
+                    Features::PIPELINE_BINARY |
+                    Features::TIMELINE_SEMAPHORE |
+                    if features.TiledResourcesTier != d3d12::D3D12_TILED_RESOURCES_TIER_NOT_SUPPORTED {
+                        Features::SPARSE_BINDING |
+                        Features::SPARSE_RESIDENCY_BUFFER |
+                        Features::SHADER_RESIDENCY_IMAGE_2D |
+                        Features::SPARSE_RESIDENCY_ALIASED
+                    } else {
+                        Features::empty()
+                    } |
+                    if depth_bounds_test_supported { Features::DEPTH_BOUNDS } else { Features::empty() } |
+                    if features.PSSpecifiedStencilRefSupported != 0 {
+                        Features::SHADER_STENCIL_EXPORT
+                    } else {
+                        Features::empty()
+                    } |
+                    // Tier 2 lifts the limits on the size of descriptor tables with SRVs
+                    // and samplers, required for unbounded descriptor ranges.
+                    if features.ResourceBindingTier >= d3d12::D3D12_RESOURCE_BINDING_TIER_2 {
+                        Features::DESCRIPTOR_INDEXING
+                    } else {
+                        Features::empty()
+                    } |
+                    // Wave intrinsics only read lanes by absolute index, relative shuffles are missing.
+                    if wave_ops {
+                        Features::SUBGROUP_BASIC |
+                        Features::SUBGROUP_VOTE |
+                        Features::SUBGROUP_ARITHMETIC |
+                        Features::SUBGROUP_BALLOT |
+                        Features::SUBGROUP_QUAD
+                    } else {
+                        Features::empty()
+                    },
                 limits: Limits { // TODO
                     max_texture_size: 0,
                     max_patch_size: 0,
@@ -1034,7 +1401,7 @@ impl hal::Instance for Instance {
                     min_buffer_copy_pitch_alignment: d3d12::D3D12_TEXTURE_DATA_PITCH_ALIGNMENT as _,
                     min_texel_buffer_offset_alignment: 1, // TODO
                     min_uniform_buffer_offset_alignment: 256, // Required alignment for CBVs
-                    min_storage_buffer_offset_alignment: 1, // TODO
+                    min_storage_buffer_offset_alignment: 4, // Raw UAVs address 32-bit elements
                     // TODO: query supported sample count for all framebuffer formats and increase the limit
                     //       if possible.
                     framebuffer_color_samples_count: 0b101,
@@ -1042,11 +1409,14 @@ impl hal::Instance for Instance {
                     framebuffer_stencil_samples_count: 0b101,
                     max_color_attachments: 1, // TODO
                     non_coherent_atom_size: 1, //TODO: confirm
+                    min_subgroup_size: wave_lane_counts.0,
+                    max_subgroup_size: wave_lane_counts.1,
                 },
                 format_properties: Arc::new(format_properties),
                 private_caps: Capabilities {
                     heterogeneous_resource_heaps,
                     memory_architecture,
+                    max_shader_model,
                 },
                 heap_properties,
                 memory_properties: hal::MemoryProperties {
@@ -1054,6 +1424,7 @@ impl hal::Instance for Instance {
                     memory_heaps,
                 },
                 is_open: Arc::new(Mutex::new(false)),
+                dxc: self.dxc.clone(),
             };
 
             let queue_families = QUEUE_FAMILIES.to_vec();
@@ -1099,12 +1470,15 @@ impl hal::Backend for Backend {
     type ComputePipeline = native::ComputePipeline;
     type GraphicsPipeline = native::GraphicsPipeline;
     type PipelineLayout = native::PipelineLayout;
+    type PipelineCache = native::PipelineCache;
     type DescriptorSetLayout = native::DescriptorSetLayout;
     type DescriptorPool = native::DescriptorPool;
     type DescriptorSet = native::DescriptorSet;
 
     type Fence = native::Fence;
     type Semaphore = native::Semaphore;
+    type TimelineSemaphore = native::TimelineSemaphore;
+    type Event = ();
     type QueryPool = native::QueryPool;
 }
 
@@ -4,12 +4,14 @@ use winapi::um::{d3d12, d3dcommon};
 use wio::com::ComPtr;
 
 use range_alloc::RangeAllocator;
-use hal::{format, image, pass, pso, DescriptorPool as HalDescriptorPool};
+use hal::{format, image, pass, pso, query, DescriptorPool as HalDescriptorPool};
+use hal::backend::FastHashMap;
 use {Backend, MAX_VERTEX_BUFFERS};
 use root_constants::RootConstant;
 
 use std::collections::BTreeMap;
 use std::ops::Range;
+use std::sync::Mutex;
 
 // ShaderModule is either a precompiled if the source comes from HLSL or
 // the SPIR-V module doesn't contain specialization constants or push constants
@@ -27,6 +29,8 @@ pub struct BarrierDesc {
     pub(crate) attachment_id: pass::AttachmentId,
     pub(crate) states: Range<d3d12::D3D12_RESOURCE_STATES>,
     pub(crate) flags: d3d12::D3D12_RESOURCE_BARRIER_FLAGS,
+    /// Plane of a depth-stencil attachment to transition, all planes if `None`.
+    pub(crate) plane: Option<UINT>,
 }
 
 impl BarrierDesc {
@@ -38,6 +42,14 @@ impl BarrierDesc {
             attachment_id,
             states,
             flags: d3d12::D3D12_RESOURCE_BARRIER_FLAG_NONE,
+            plane: None,
+        }
+    }
+
+    pub(crate) fn for_plane(self, plane: UINT) -> Self {
+        BarrierDesc {
+            plane: Some(plane),
+            .. self
         }
     }
 
@@ -145,6 +157,77 @@ pub struct PipelineLayout {
 unsafe impl Send for PipelineLayout { }
 unsafe impl Sync for PipelineLayout { }
 
+/// Cached pipeline state blobs, keyed by a hash of the pipeline description.
+#[derive(Debug, Default)]
+pub struct PipelineCache {
+    pub(crate) blobs: Mutex<FastHashMap<u64, Vec<u8>>>,
+}
+
+impl PipelineCache {
+    const MAGIC: [u8; 4] = *b"D3DC";
+
+    /// Load the blobs from data written by `to_data`. Invalid data results in an empty cache.
+    pub(crate) fn from_data(data: &[u8]) -> Self {
+        fn read(data: &mut &[u8], size: usize) -> Option<u64> {
+            if data.len() < size {
+                return None;
+            }
+            let value = data[.. size]
+                .iter()
+                .enumerate()
+                .fold(0, |value, (i, &byte)| value | (byte as u64) << (i * 8));
+            *data = &data[size ..];
+            Some(value)
+        }
+
+        let mut blobs = FastHashMap::default();
+        if data.starts_with(&Self::MAGIC) {
+            let mut data = &data[Self::MAGIC.len() ..];
+            while !data.is_empty() {
+                let entry = read(&mut data, 8).and_then(|key| {
+                    let size = read(&mut data, 4)? as usize;
+                    if data.len() < size {
+                        return None;
+                    }
+                    let (blob, rest) = data.split_at(size);
+                    data = rest;
+                    Some((key, blob.to_vec()))
+                });
+                match entry {
+                    Some((key, blob)) => { blobs.insert(key, blob); }
+                    None => {
+                        warn!("Discarding truncated pipeline cache data");
+                        blobs.clear();
+                        break;
+                    }
+                }
+            }
+        }
+
+        PipelineCache {
+            blobs: Mutex::new(blobs),
+        }
+    }
+
+    /// Serialize the blobs as the magic followed by `(key: u64, size: u32, blob)` entries.
+    pub(crate) fn to_data(&self) -> Vec<u8> {
+        fn write(data: &mut Vec<u8>, value: u64, size: usize) {
+            for i in 0 .. size {
+                data.push((value >> (i * 8)) as u8);
+            }
+        }
+
+        let blobs = self.blobs.lock().unwrap();
+        let mut data = Self::MAGIC.to_vec();
+        for (&key, blob) in blobs.iter() {
+            write(&mut data, key, 8);
+            write(&mut data, blob.len() as u64, 4);
+            data.extend_from_slice(blob);
+        }
+        data
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct Framebuffer {
     pub(crate) attachments: Vec<ImageView>,
@@ -180,6 +263,15 @@ unsafe impl Sync for BufferView { }
 pub enum Place {
     SwapChain,
     Heap { raw: ComPtr<d3d12::ID3D12Heap>, offset: u64 },
+    /// Reserved (tiled) resource, memory is bound per tile.
+    Reserved {
+        /// Tile dimensions in texels.
+        tile_shape: [u32; 3],
+        /// Number of mip levels not packed into the mip tail.
+        standard_mips: u8,
+        /// Number of tiles of the packed mip tail of each array layer.
+        packed_tiles: u32,
+    },
 }
 
 #[derive(Clone, Derivative)]
@@ -234,6 +326,10 @@ pub struct ImageView {
     pub(crate) handle_rtv: Option<d3d12::D3D12_CPU_DESCRIPTOR_HANDLE>,
     #[derivative(Debug="ignore")]
     pub(crate) handle_dsv: Option<d3d12::D3D12_CPU_DESCRIPTOR_HANDLE>,
+    /// Depth-stencil views with read-only depth, read-only stencil and both read-only,
+    /// for the aspects of the format.
+    #[derivative(Debug="ignore")]
+    pub(crate) handle_dsv_read_only: [Option<d3d12::D3D12_CPU_DESCRIPTOR_HANDLE>; 3],
     #[derivative(Debug="ignore")]
     pub(crate) handle_uav: Option<d3d12::D3D12_CPU_DESCRIPTOR_HANDLE>,
     // Required for attachment resolves.
@@ -250,6 +346,23 @@ impl ImageView {
     pub fn calc_subresource(&self, mip_level: UINT, layer: UINT) -> UINT {
         mip_level + (layer * self.num_levels as UINT)
     }
+
+    /// Subresources of the whole image in `plane`.
+    pub fn plane_subresources(&self, plane: UINT) -> Range<UINT> {
+        let count = self.num_levels as UINT * self.kind.num_layers() as UINT;
+        plane * count .. (plane + 1) * count
+    }
+
+    /// Depth-stencil view to bind for an attachment in `layout`.
+    pub fn dsv_for_layout(&self, layout: image::Layout) -> Option<d3d12::D3D12_CPU_DESCRIPTOR_HANDLE> {
+        let index = match layout {
+            image::Layout::DepthReadOnlyStencilAttachmentOptimal => 0,
+            image::Layout::DepthAttachmentStencilReadOnlyOptimal => 1,
+            image::Layout::DepthStencilReadOnlyOptimal => 2,
+            _ => return self.handle_dsv,
+        };
+        self.handle_dsv_read_only[index].or(self.handle_dsv)
+    }
 }
 
 #[derive(Derivative)]
@@ -262,6 +375,18 @@ pub struct Sampler {
 #[derive(Debug)]
 pub struct DescriptorSetLayout {
     pub(crate) bindings: Vec<pso::DescriptorSetLayoutBinding>,
+    // Flags of each binding, empty if none were specified.
+    pub(crate) binding_flags: Vec<pso::DescriptorBindingFlags>,
+}
+
+impl DescriptorSetLayout {
+    /// Check if the binding at the given index has a variable number of descriptors,
+    /// mapped to an unbounded descriptor range.
+    pub(crate) fn is_variable(&self, index: usize) -> bool {
+        self.binding_flags
+            .get(index)
+            .map_or(false, |flags| flags.contains(pso::DescriptorBindingFlags::VARIABLE_DESCRIPTOR_COUNT))
+    }
 }
 
 #[derive(Derivative)]
@@ -283,6 +408,17 @@ pub struct Semaphore {
 unsafe impl Send for Semaphore {}
 unsafe impl Sync for Semaphore {}
 
+/// Timeline semaphore, mapped directly to a fence.
+#[derive(Derivative)]
+#[derivative(Debug)]
+pub struct TimelineSemaphore {
+    #[derivative(Debug="ignore")]
+    pub(crate) raw: ComPtr<d3d12::ID3D12Fence>,
+}
+
+unsafe impl Send for TimelineSemaphore {}
+unsafe impl Sync for TimelineSemaphore {}
+
 #[derive(Derivative)]
 #[derivative(Debug)]
 pub struct Memory {
@@ -324,12 +460,6 @@ pub struct DescriptorBindingInfo {
 #[derive(Derivative)]
 #[derivative(Debug)]
 pub struct DescriptorSet {
-    // Required for binding at command buffer
-    #[derivative(Debug="ignore")]
-    pub(crate) heap_srv_cbv_uav: ComPtr<d3d12::ID3D12DescriptorHeap>,
-    #[derivative(Debug="ignore")]
-    pub(crate) heap_samplers: ComPtr<d3d12::ID3D12DescriptorHeap>,
-
     pub(crate) binding_infos: Vec<DescriptorBindingInfo>,
 
     #[derivative(Debug="ignore")]
@@ -342,24 +472,6 @@ pub struct DescriptorSet {
 unsafe impl Send for DescriptorSet {}
 unsafe impl Sync for DescriptorSet {}
 
-impl DescriptorSet {
-    pub fn srv_cbv_uav_gpu_start(&self) -> d3d12::D3D12_GPU_DESCRIPTOR_HANDLE {
-        unsafe {
-            self
-                .heap_srv_cbv_uav
-                .GetGPUDescriptorHandleForHeapStart()
-        }
-    }
-
-    pub fn sampler_gpu_start(&self) -> d3d12::D3D12_GPU_DESCRIPTOR_HANDLE {
-        unsafe {
-            self
-                .heap_samplers
-                .GetGPUDescriptorHandleForHeapStart()
-        }
-    }
-}
-
 #[derive(Copy, Clone, Derivative)]
 #[derivative(Debug)]
 pub struct DualHandle {
@@ -393,14 +505,13 @@ impl DescriptorHeap {
     }
 }
 
-/// Slice of an descriptor heap, which is allocated for a pool.
+/// Slice of a shader visible descriptor heap, which is allocated for a pool.
 /// Pools will create descriptor sets inside this slice.
-#[derive(Derivative)]
-#[derivative(Debug)]
+#[derive(Debug)]
 pub struct DescriptorHeapSlice {
-    #[derivative(Debug="ignore")]
-    pub(crate) heap: ComPtr<d3d12::ID3D12DescriptorHeap>,
     pub(crate) start: DualHandle,
+    // Range of the slice in the heap, returned to the heap on pool destruction.
+    pub(crate) range: Range<u64>,
     pub(crate) handle_size: u64,
     pub(crate) range_allocator: RangeAllocator<u64>,
 }
@@ -438,21 +549,29 @@ pub struct DescriptorPool {
 unsafe impl Send for DescriptorPool {}
 unsafe impl Sync for DescriptorPool {}
 
-impl HalDescriptorPool<Backend> for DescriptorPool {
-    fn allocate_set(&mut self, layout: &DescriptorSetLayout) -> Result<DescriptorSet, pso::AllocationError> {
+impl DescriptorPool {
+    fn allocate(
+        &mut self,
+        layout: &DescriptorSetLayout,
+        variable_count: Option<pso::DescriptorArrayIndex>,
+    ) -> Result<DescriptorSet, pso::AllocationError> {
         let mut binding_infos = Vec::new();
         let mut first_gpu_sampler = None;
         let mut first_gpu_view = None;
 
-        for binding in &layout.bindings {
+        for (index, binding) in layout.bindings.iter().enumerate() {
+            let count = match variable_count {
+                Some(count) if layout.is_variable(index) => count.min(binding.count),
+                _ => binding.count,
+            };
             let HeapProperties { has_view, has_sampler, is_uav } = HeapProperties::from(binding.ty);
             while binding_infos.len() <= binding.binding as usize {
                 binding_infos.push(DescriptorBindingInfo::default());
             }
             binding_infos[binding.binding as usize] = DescriptorBindingInfo {
-                count: binding.count as _,
+                count: count as _,
                 view_range: if has_view {
-                    let handle = self.heap_srv_cbv_uav.alloc_handles(binding.count as u64)
+                    let handle = self.heap_srv_cbv_uav.alloc_handles(count as u64)
                         .ok_or(pso::AllocationError::OutOfPoolMemory)?;
                     if first_gpu_view.is_none() {
                         first_gpu_view = Some(handle.gpu);
@@ -460,14 +579,14 @@ impl HalDescriptorPool<Backend> for DescriptorPool {
                     Some(DescriptorRange {
                         handle,
                         ty: binding.ty,
-                        count: binding.count as _,
+                        count: count as _,
                         handle_size: self.heap_srv_cbv_uav.handle_size,
                     })
                 } else {
                     None
                 },
                 sampler_range: if has_sampler {
-                    let handle = self.heap_sampler.alloc_handles(binding.count as u64)
+                    let handle = self.heap_sampler.alloc_handles(count as u64)
                         .ok_or(pso::AllocationError::OutOfPoolMemory)?;
                     if first_gpu_sampler.is_none() {
                         first_gpu_sampler = Some(handle.gpu);
@@ -475,7 +594,7 @@ impl HalDescriptorPool<Backend> for DescriptorPool {
                     Some(DescriptorRange {
                         handle,
                         ty: binding.ty,
-                        count: binding.count as _,
+                        count: count as _,
                         handle_size: self.heap_sampler.handle_size,
                     })
                 } else {
@@ -486,13 +605,25 @@ impl HalDescriptorPool<Backend> for DescriptorPool {
         }
 
         Ok(DescriptorSet {
-            heap_srv_cbv_uav: self.heap_srv_cbv_uav.heap.clone(),
-            heap_samplers: self.heap_sampler.heap.clone(),
             binding_infos,
             first_gpu_sampler,
             first_gpu_view,
         })
     }
+}
+
+impl HalDescriptorPool<Backend> for DescriptorPool {
+    fn allocate_set(&mut self, layout: &DescriptorSetLayout) -> Result<DescriptorSet, pso::AllocationError> {
+        self.allocate(layout, None)
+    }
+
+    fn allocate_set_with_count(
+        &mut self,
+        layout: &DescriptorSetLayout,
+        count: pso::DescriptorArrayIndex,
+    ) -> Result<DescriptorSet, pso::AllocationError> {
+        self.allocate(layout, Some(count))
+    }
 
     fn free_sets<I>(&mut self, descriptor_sets: I)
     where
@@ -561,6 +692,30 @@ pub struct QueryPool {
     #[derivative(Debug="ignore")]
     pub(crate) raw: ComPtr<d3d12::ID3D12QueryHeap>,
     pub(crate) ty: d3d12::D3D12_QUERY_HEAP_TYPE,
+    pub(crate) count: u32,
+    // Size in bytes of the native result of a single query.
+    pub(crate) stride: u64,
+    // Statistics requested by the user, pipeline statistics heaps always
+    // record all of them.
+    pub(crate) statistics: query::PipelineStatistic,
+    // Readback buffer the results are resolved into, followed by one
+    // availability word per query. Persistently mapped at `mapped`.
+    #[derivative(Debug="ignore")]
+    pub(crate) resolve: ComPtr<d3d12::ID3D12Resource>,
+    #[derivative(Debug="ignore")]
+    pub(crate) mapped: *const u8,
+    // Upload buffer with `count` zero words followed by a single one,
+    // copied into the availability words on reset and resolve.
+    #[derivative(Debug="ignore")]
+    pub(crate) availability: ComPtr<d3d12::ID3D12Resource>,
+    // Timestamp ticks per second, zero for other query types.
+    pub(crate) frequency: u64,
+}
+
+impl QueryPool {
+    pub(crate) fn availability_offset(&self, id: query::QueryId) -> u64 {
+        self.stride * self.count as u64 + 8 * id as u64
+    }
 }
 
 unsafe impl Send for QueryPool {}
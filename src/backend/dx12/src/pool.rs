@@ -14,19 +14,48 @@ pub struct RawCommandPool {
     pub(crate) inner: ComPtr<d3d12::ID3D12CommandAllocator>,
     pub(crate) device: ComPtr<d3d12::ID3D12Device>,
     pub(crate) list_type: d3d12::D3D12_COMMAND_LIST_TYPE,
+    // Allocator of the secondary command buffers, created on first use.
+    pub(crate) bundle_allocator: Option<ComPtr<d3d12::ID3D12CommandAllocator>>,
     pub(crate) shared: Arc<Shared>,
 }
 
 impl RawCommandPool {
-    fn create_command_list(&mut self) -> ComPtr<d3d12::ID3D12GraphicsCommandList> {
+    fn bundle_allocator(&mut self) -> ComPtr<d3d12::ID3D12CommandAllocator> {
+        if let Some(ref allocator) = self.bundle_allocator {
+            return allocator.clone();
+        }
+
+        let mut allocator: *mut d3d12::ID3D12CommandAllocator = ptr::null_mut();
+        let hr = unsafe {
+            self.device.CreateCommandAllocator(
+                d3d12::D3D12_COMMAND_LIST_TYPE_BUNDLE,
+                &d3d12::ID3D12CommandAllocator::uuidof(),
+                &mut allocator as *mut *mut _ as *mut *mut _,
+            )
+        };
+        // TODO: error handling
+        if !SUCCEEDED(hr) {
+            error!("error on bundle allocator creation: {:x}", hr);
+        }
+
+        let allocator = unsafe { ComPtr::from_raw(allocator) };
+        self.bundle_allocator = Some(allocator.clone());
+        allocator
+    }
+
+    fn create_command_list(
+        &mut self,
+        list_type: d3d12::D3D12_COMMAND_LIST_TYPE,
+        allocator: &ComPtr<d3d12::ID3D12CommandAllocator>,
+    ) -> ComPtr<d3d12::ID3D12GraphicsCommandList> {
         // allocate command lists
         let command_list = {
             let mut command_list: *mut d3d12::ID3D12GraphicsCommandList = ptr::null_mut();
             let hr = unsafe {
                 self.device.CreateCommandList(
                     0, // single gpu only atm
-                    self.list_type,
-                    self.inner.as_raw(),
+                    list_type,
+                    allocator.as_raw(),
                     ptr::null_mut(),
                     &d3d12::ID3D12GraphicsCommandList::uuidof(),
                     &mut command_list as *mut *mut _ as *mut *mut _,
@@ -55,16 +84,27 @@ unsafe impl Sync for RawCommandPool { }
 impl pool::RawCommandPool<Backend> for RawCommandPool {
     fn reset(&mut self) {
         unsafe { self.inner.Reset(); }
+        if let Some(ref allocator) = self.bundle_allocator {
+            unsafe { allocator.Reset(); }
+        }
     }
 
     fn allocate(
         &mut self, num: usize, level: command::RawLevel
-    ) -> Vec<CommandBuffer> { // TODO: Implement secondary buffers
-        assert_eq!(level, command::RawLevel::Primary);
+    ) -> Vec<CommandBuffer> {
+        // Secondary command buffers are bundles, which can only be executed
+        // by direct command lists.
+        let (list_type, allocator) = match level {
+            command::RawLevel::Primary => (self.list_type, self.inner.clone()),
+            command::RawLevel::Secondary => {
+                assert_eq!(self.list_type, d3d12::D3D12_COMMAND_LIST_TYPE_DIRECT);
+                (d3d12::D3D12_COMMAND_LIST_TYPE_BUNDLE, self.bundle_allocator())
+            }
+        };
         (0..num)
             .map(|_| CommandBuffer::new(
-                self.create_command_list(),
-                self.inner.clone(),
+                self.create_command_list(list_type, &allocator),
+                allocator.clone(),
                 self.shared.clone(),
             ))
             .collect()
@@ -4,8 +4,10 @@ use std::mem;
 #[cfg(feature = "winit")]
 use winit;
 
-use winapi::shared::dxgi1_4;
+use winapi::shared::{dxgi1_4, dxgi1_5};
+use winapi::shared::minwindef::{BOOL, FALSE};
 use winapi::shared::windef::{HWND, RECT};
+use winapi::shared::winerror;
 use winapi::um::winuser::GetClientRect;
 use winapi::um::d3d12;
 use wio::com::ComPtr;
@@ -15,21 +17,29 @@ use {native as n, Backend, Instance, PhysicalDevice, QueueFamily};
 
 use std::os::raw::c_void;
 
+// Current size of the client area of the window.
+fn client_extent(wnd_handle: HWND) -> hal::window::Extent2D {
+    unsafe {
+        let mut rect: RECT = mem::zeroed();
+        if GetClientRect(wnd_handle, &mut rect as *mut RECT) == 0 {
+            panic!("GetClientRect failed");
+        }
+        hal::window::Extent2D {
+            width: (rect.right - rect.left) as u32,
+            height: (rect.bottom - rect.top) as u32,
+        }
+    }
+}
+
 impl Instance {
     pub fn create_surface_from_hwnd(&self, hwnd: *mut c_void) -> Surface {
-        let (width, height) = unsafe {
-            let mut rect: RECT = mem::zeroed();
-            if GetClientRect(hwnd as *mut _, &mut rect as *mut RECT) == 0 {
-                panic!("GetClientRect failed");
-            }
-            ((rect.right - rect.left) as u32, (rect.bottom - rect.top) as u32)
-        };
+        let extent = client_extent(hwnd as *mut _);
 
         Surface {
             factory: self.factory.clone(),
             wnd_handle: hwnd as *mut _,
-            width: width,
-            height: height,
+            width: extent.width,
+            height: extent.height,
         }
     }
 
@@ -50,6 +60,31 @@ pub struct Surface {
 unsafe impl Send for Surface { }
 unsafe impl Sync for Surface { }
 
+/// `DXGI_SWAP_CHAIN_FLAG_ALLOW_TEARING`, required on creation for presenting with tearing.
+pub(crate) const SWAP_CHAIN_FLAG_ALLOW_TEARING: u32 = 0x800;
+/// `DXGI_PRESENT_ALLOW_TEARING`, presents immediately on flip model swapchains.
+pub(crate) const PRESENT_ALLOW_TEARING: u32 = 0x200;
+
+impl Surface {
+    /// Check if presenting with tearing is supported, for variable refresh rate
+    /// displays and `PresentMode::Immediate`.
+    pub(crate) fn supports_tearing(&self) -> bool {
+        let factory5 = match self.factory.cast::<dxgi1_5::IDXGIFactory5>() {
+            Ok(factory5) => factory5,
+            Err(_) => return false,
+        };
+        let mut allow_tearing: BOOL = FALSE;
+        let hr = unsafe {
+            factory5.CheckFeatureSupport(
+                dxgi1_5::DXGI_FEATURE_PRESENT_ALLOW_TEARING,
+                &mut allow_tearing as *mut _ as *mut _,
+                mem::size_of::<BOOL>() as _,
+            )
+        };
+        winerror::SUCCEEDED(hr) && allow_tearing != FALSE
+    }
+}
+
 impl hal::Surface<Backend> for Surface {
     fn supports_queue_family(&self, queue_family: &QueueFamily) -> bool {
         match queue_family {
@@ -62,20 +97,9 @@ impl hal::Surface<Backend> for Surface {
         i::Kind::D2(self.width, self.height, 1, 1)
     }
 
-    fn compatibility(
-        &self, _: &PhysicalDevice,
-    ) -> (hal::SurfaceCapabilities, Option<Vec<f::Format>>, Vec<hal::PresentMode>) {
-        let extent = hal::window::Extent2D {
-            width: self.width,
-            height: self.height,
-        };
-
-        let capabilities = hal::SurfaceCapabilities {
-            image_count: 2..16, // we currently use a flip effect which supports 2..16 buffers
-            current_extent: Some(extent),
-            extents: extent..extent,
-            max_image_layers: 1,
-        };
+    fn capabilities(&self, _: &PhysicalDevice) -> hal::SurfaceCapabilities {
+        // The window may have been resized since the surface was created.
+        let extent = client_extent(self.wnd_handle);
 
         // Sticking to FLIP swap effects for the moment.
         // We also expose sRGB buffers but they are handled internally as UNORM.
@@ -89,16 +113,36 @@ impl hal::Surface<Backend> for Surface {
             f::Format::Rgba16Float,
         ];
 
-        let present_modes = vec![
-            hal::PresentMode::Fifo //TODO
+        // Flip model swapchains replace the queued frame when presenting without
+        // v-sync, unless tearing is explicitly allowed.
+        let mut present_modes = vec![
+            hal::PresentMode::Fifo,
+            hal::PresentMode::Mailbox,
         ];
+        if self.supports_tearing() {
+            present_modes.push(hal::PresentMode::Immediate);
+        }
 
-        (capabilities, Some(formats), present_modes)
+        hal::SurfaceCapabilities {
+            image_count: 2..16, // we currently use a flip effect which supports 2..16 buffers
+            current_extent: Some(extent),
+            extents: extent..extent,
+            max_image_layers: 1,
+            current_transform: hal::SurfaceTransform::IDENTITY,
+            supported_transforms: hal::SurfaceTransform::IDENTITY,
+            formats: Some(formats),
+            present_modes,
+        }
     }
 }
 
 pub struct Swapchain {
     pub(crate) inner: ComPtr<dxgi1_4::IDXGISwapChain3>,
+    pub(crate) wnd_handle: HWND,
+    pub(crate) extent: hal::window::Extent2D,
+    pub(crate) present_mode: hal::PresentMode,
+    // Creation flags, which have to be passed again on resizing.
+    pub(crate) flags: u32,
     pub(crate) next_frame: usize,
     pub(crate) frame_queue: VecDeque<usize>,
     #[allow(dead_code)]
@@ -108,8 +152,34 @@ pub struct Swapchain {
     pub(crate) _resources: Vec<ComPtr<d3d12::ID3D12Resource>>,
 }
 
+impl Swapchain {
+    /// Flip model swapchains never go out of date, the presentation engine stretches
+    /// the images if the window got resized instead.
+    pub(crate) fn suboptimal(&self) -> Option<hal::window::Suboptimal> {
+        hal::window::Suboptimal::from_extent(client_extent(self.wnd_handle), self.extent)
+    }
+
+    /// Sync interval and flags to present with.
+    pub(crate) fn present_params(&self) -> (u32, u32) {
+        match self.present_mode {
+            hal::PresentMode::Immediate if self.flags & SWAP_CHAIN_FLAG_ALLOW_TEARING != 0 => {
+                (0, PRESENT_ALLOW_TEARING)
+            }
+            hal::PresentMode::Immediate | hal::PresentMode::Mailbox => (0, 0),
+            hal::PresentMode::Fifo | hal::PresentMode::Relaxed => (1, 0),
+        }
+    }
+
+    /// Release all references to the back buffers, returning the raw swapchain for resizing.
+    pub(crate) fn release_buffers(self) -> ComPtr<dxgi1_4::IDXGISwapChain3> {
+        self.inner
+    }
+}
+
 impl hal::Swapchain<Backend> for Swapchain {
-    fn acquire_image(&mut self, _sync: hal::FrameSync<Backend>) -> Result<hal::SwapImageIndex, ()> {
+    fn acquire_image(
+        &mut self, _sync: hal::FrameSync<Backend>
+    ) -> Result<(hal::SwapImageIndex, Option<hal::window::Suboptimal>), hal::window::AcquireError> {
         // TODO: sync
 
         if false {
@@ -122,7 +192,8 @@ impl hal::Swapchain<Backend> for Swapchain {
         }
 
         // TODO:
-        Ok(unsafe { self.inner.GetCurrentBackBufferIndex() })
+        let index = unsafe { self.inner.GetCurrentBackBufferIndex() };
+        Ok((index, self.suboptimal()))
     }
 }
 
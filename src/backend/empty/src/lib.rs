@@ -43,12 +43,15 @@ impl hal::Backend for Backend {
     type ComputePipeline = ();
     type GraphicsPipeline = ();
     type PipelineLayout = ();
+    type PipelineCache = ();
     type DescriptorSetLayout = ();
     type DescriptorPool = DescriptorPool;
     type DescriptorSet = ();
 
     type Fence = ();
     type Semaphore = ();
+    type TimelineSemaphore = ();
+    type Event = ();
     type QueryPool = ();
 }
 
@@ -96,7 +99,7 @@ impl queue::RawCommandQueue<Backend> for RawCommandQueue {
         unimplemented!()
     }
 
-    fn present<IS, S, IW>(&mut self, _: IS, _: IW) -> Result<(), ()>
+    fn present<IS, S, IW>(&mut self, _: IS, _: IW) -> Result<Option<hal::window::Suboptimal>, hal::window::PresentError>
     where
         IS: IntoIterator<Item = (S, hal::SwapImageIndex)>,
         S: Borrow<Swapchain>,
@@ -106,6 +109,10 @@ impl queue::RawCommandQueue<Backend> for RawCommandQueue {
         unimplemented!()
     }
 
+    unsafe fn bind_sparse(&mut self, _: hal::sparse::SparseBindInfo<Backend>, _: Option<&()>) {
+        unimplemented!()
+    }
+
     fn wait_idle(&self) -> Result<(), error::HostExecutionError> {
         unimplemented!()
     }
@@ -158,6 +165,16 @@ impl hal::Device<Backend> for Device {
         unimplemented!()
     }
 
+    fn create_imageless_framebuffer<I>(
+        &self, _: &(), _: I, _: image::Extent
+    ) -> Result<(), device::FramebufferError>
+    where
+        I: IntoIterator,
+        I::Item: Borrow<pass::FramebufferAttachment>,
+    {
+        unimplemented!()
+    }
+
     fn create_shader_module(&self, _: &[u8]) -> Result<(), device::ShaderError> {
         unimplemented!()
     }
@@ -263,6 +280,22 @@ impl hal::Device<Backend> for Device {
         unimplemented!()
     }
 
+    fn create_timeline_semaphore(&self, _: u64) -> () {
+        unimplemented!()
+    }
+
+    fn get_timeline_semaphore_value(&self, _: &()) -> u64 {
+        unimplemented!()
+    }
+
+    fn signal_timeline_semaphore(&self, _: &(), _: u64) {
+        unimplemented!()
+    }
+
+    fn wait_for_timeline_semaphore(&self, _: &(), _: u64, _: u32) -> bool {
+        unimplemented!()
+    }
+
     fn create_query_pool(&self, _: query::QueryType, _: u32) -> () {
         unimplemented!()
     }
@@ -312,6 +345,22 @@ impl hal::Device<Backend> for Device {
     fn destroy_pipeline_layout(&self, _: ()) {
         unimplemented!()
     }
+    fn create_pipeline_cache(&self, _: Option<&[u8]>) -> Result<(), device::OutOfMemory> {
+        unimplemented!()
+    }
+    fn get_pipeline_cache_data(&self, _: &()) -> Result<Vec<u8>, device::OutOfMemory> {
+        unimplemented!()
+    }
+    fn merge_pipeline_caches<I>(&self, _: &(), _: I) -> Result<(), device::OutOfMemory>
+    where
+        I: IntoIterator,
+        I::Item: Borrow<()>,
+    {
+        unimplemented!()
+    }
+    fn destroy_pipeline_cache(&self, _: ()) {
+        unimplemented!()
+    }
     fn destroy_graphics_pipeline(&self, _: ()) {
         unimplemented!()
     }
@@ -331,6 +380,14 @@ impl hal::Device<Backend> for Device {
     fn destroy_image(&self, _: ()) {
         unimplemented!()
     }
+    fn create_sparse_buffer(&self, _: u64, _: buffer::Usage) -> Result<((), memory::Requirements), buffer::CreationError> {
+        unimplemented!()
+    }
+    fn create_sparse_image(
+        &self, _: image::Kind, _: image::Level, _: format::Format, _: image::Usage, _: image::StorageFlags,
+    ) -> Result<((), hal::sparse::SparseImageRequirements), image::CreationError> {
+        unimplemented!()
+    }
     fn destroy_image_view(&self, _: ()) {
         unimplemented!()
     }
@@ -354,6 +411,18 @@ impl hal::Device<Backend> for Device {
         unimplemented!()
     }
 
+    fn destroy_timeline_semaphore(&self, _: ()) {
+        unimplemented!()
+    }
+
+    fn create_event(&self) -> () {
+        unimplemented!()
+    }
+
+    fn destroy_event(&self, _: ()) {
+        unimplemented!()
+    }
+
     fn create_swapchain(
         &self,
         _: &mut Surface,
@@ -565,6 +634,23 @@ impl command::RawCommandBuffer<Backend> for RawCommandBuffer {
         unimplemented!()
     }
 
+    fn begin_render_pass_imageless<T, I>(
+        &mut self,
+        _: &(),
+        _: &(),
+        _: I,
+        _: pso::Rect,
+        _: T,
+        _: command::SubpassContents,
+    ) where
+        T: IntoIterator,
+        T::Item: Borrow<command::ClearValueRaw>,
+        I: IntoIterator,
+        I::Item: Borrow<()>,
+    {
+        unimplemented!()
+    }
+
     fn next_subpass(&mut self, _: command::SubpassContents) {
         unimplemented!()
     }
@@ -573,6 +659,14 @@ impl command::RawCommandBuffer<Backend> for RawCommandBuffer {
         unimplemented!()
     }
 
+    fn begin_rendering(&mut self, _: &command::RenderingInfo<Backend>) {
+        panic!("`begin_rendering` requires `Features::DYNAMIC_RENDERING`, which isn't supported by the empty backend");
+    }
+
+    fn end_rendering(&mut self) {
+        unreachable!("`begin_rendering` isn't supported by the empty backend")
+    }
+
     fn bind_graphics_pipeline(&mut self, _: &()) {
         unimplemented!()
     }
@@ -605,6 +699,10 @@ impl command::RawCommandBuffer<Backend> for RawCommandBuffer {
         unimplemented!()
     }
 
+    fn dispatch_base(&mut self, _: hal::WorkGroupCount, _: hal::WorkGroupCount) {
+        unimplemented!()
+    }
+
     fn dispatch_indirect(&mut self, _: &(), _: buffer::Offset) {
         unimplemented!()
     }
@@ -778,9 +876,7 @@ impl hal::Surface<Backend> for Surface {
         unimplemented!()
     }
 
-    fn compatibility(
-        &self, _: &PhysicalDevice,
-    ) -> (hal::SurfaceCapabilities, Option<Vec<format::Format>>, Vec<hal::PresentMode>) {
+    fn capabilities(&self, _: &PhysicalDevice) -> hal::SurfaceCapabilities {
         unimplemented!()
     }
 
@@ -792,7 +888,9 @@ impl hal::Surface<Backend> for Surface {
 /// Dummy swapchain.
 pub struct Swapchain;
 impl hal::Swapchain<Backend> for Swapchain {
-    fn acquire_image(&mut self, _: hal::FrameSync<Backend>) -> Result<hal::SwapImageIndex, ()> {
+    fn acquire_image(
+        &mut self, _: hal::FrameSync<Backend>
+    ) -> Result<(hal::SwapImageIndex, Option<hal::window::Suboptimal>), hal::window::AcquireError> {
         unimplemented!()
     }
 }
@@ -3,10 +3,11 @@
 use gl;
 
 use hal::{self, buffer, command, image, memory, pass, pso, query, ColorSlot};
-use hal::format::ChannelType;
+use hal::format::{Aspects, ChannelType};
+use hal::profiler::FrameStatistics;
 use hal::range::RangeArg;
 
-use {native as n, Backend};
+use {conv, native as n, Backend};
 use pool::{self, BufferMemory};
 
 use std::borrow::Borrow;
@@ -70,6 +71,21 @@ pub enum Command {
         base_vertex: hal::VertexOffset,
         instances: Range<hal::InstanceCount>,
     },
+    DrawIndirect {
+        primitive: gl::types::GLenum,
+        buffer: gl::types::GLuint,
+        offset: buffer::Offset,
+        draw_count: hal::DrawCount,
+        stride: u32,
+    },
+    DrawIndexedIndirect {
+        primitive: gl::types::GLenum,
+        index_type: gl::types::GLenum,
+        buffer: gl::types::GLuint,
+        offset: buffer::Offset,
+        draw_count: hal::DrawCount,
+        stride: u32,
+    },
     BindIndexBuffer(gl::types::GLuint),
     //BindVertexBuffers(BufferSlice),
     SetViewports {
@@ -79,6 +95,7 @@ pub enum Command {
     },
     SetScissors(u32, BufferSlice),
     SetBlendColor(pso::ColorValue),
+    SetDepthState(pso::DepthTest),
 
     /// Clear floating-point color drawbuffer of bound framebuffer.
     ClearBufferColorF(DrawBuffer, [f32; 4]),
@@ -97,6 +114,9 @@ pub enum Command {
     BindTargetView(FrameBufferTarget, AttachmentPoint, n::ImageView),
     SetDrawColorBuffers(usize),
     SetPatchSize(gl::types::GLint),
+    SetProgramPointSize(bool),
+    SetFramebufferSrgb(bool),
+    MemoryBarrier(gl::types::GLbitfield),
     BindProgram(gl::types::GLuint),
     BindBlendSlot(ColorSlot, pso::ColorBlendDesc),
     BindAttribute(n::AttributeDesc, gl::types::GLuint, gl::types::GLsizei, n::VertexAttribFunction),
@@ -112,6 +132,28 @@ pub enum Command {
     BindBufferRange(gl::types::GLenum, gl::types::GLuint, n::RawBuffer, gl::types::GLintptr, gl::types::GLsizeiptr),
     BindTexture(gl::types::GLenum, n::Texture),
     BindSampler(gl::types::GLuint, n::Texture),
+    /// Set a push constant uniform of the bound program.
+    /// The buffer slice contains the tightly packed uniform data.
+    SetUniform(n::PushConstantUniform, BufferSlice),
+}
+
+impl Command {
+    /// Offset the data slices of the command by `offset` bytes, for moving
+    /// the command along with its data into another command buffer.
+    fn rebase(&mut self, offset: u32) {
+        match *self {
+            Command::SetViewports { ref mut viewport_ptr, ref mut depth_range_ptr, .. } => {
+                viewport_ptr.offset += offset;
+                depth_range_ptr.offset += offset;
+            }
+            Command::SetScissors(_, ref mut ptr) |
+            Command::DrawBuffers(ref mut ptr) |
+            Command::SetUniform(_, ref mut ptr) => {
+                ptr.offset += offset;
+            }
+            _ => {}
+        }
+    }
 }
 
 pub type FrameBufferTarget = gl::types::GLenum;
@@ -139,6 +181,8 @@ struct Cache {
     primitive: Option<gl::types::GLenum>,
     // Active index type, set by the current index buffer.
     index_type: Option<hal::IndexType>,
+    // Byte offset into the current index buffer.
+    index_offset: buffer::Offset,
     // Stencil reference values (front, back).
     stencil_ref: Option<(pso::StencilValue, pso::StencilValue)>,
     // Blend color.
@@ -150,16 +194,29 @@ struct Cache {
     error_state: bool,
     // Vertices per patch for tessellation primitives (patches).
     patch_size: Option<gl::types::GLint>,
+    // Whether point size is taken from the shader.
+    program_point_size: Option<bool>,
+    // Whether colors written to sRGB targets are converted.
+    framebuffer_srgb: Option<bool>,
     // Active program name.
     program: Option<gl::types::GLuint>,
     // Blend per attachment.
     blend_targets: Option<Vec<Option<pso::ColorBlendDesc>>>,
-    // Maps bound vertex buffer offset (index) to handle.
-    vertex_buffers: Vec<gl::types::GLuint>,
+    // Depth test, set by the current pipeline.
+    depth: Option<pso::DepthTest>,
+    // Maps bound vertex buffer binding (index) to handle and byte offset.
+    vertex_buffers: Vec<(gl::types::GLuint, buffer::Offset)>,
     // Active vertex buffer descriptions.
     vertex_buffer_descs: Vec<Option<pso::VertexBufferDesc>>,
     // Active attributes.
     attributes: Vec<n::AttributeDesc>,
+    // Push constant uniforms of the active program.
+    push_constant_uniforms: Vec<n::PushConstantUniform>,
+    // Whether the active program is a compute program.
+    compute_bound: bool,
+    // Push constant data of the graphics and compute bind points, in words.
+    graphics_push_constants: Vec<u32>,
+    compute_push_constants: Vec<u32>,
 }
 
 impl Cache {
@@ -167,16 +224,24 @@ impl Cache {
         Cache {
             primitive: None,
             index_type: None,
+            index_offset: 0,
             stencil_ref: None,
             blend_color: None,
             framebuffer: None,
             error_state: false,
             patch_size: None,
+            program_point_size: None,
+            framebuffer_srgb: None,
             program: None,
             blend_targets: None,
+            depth: None,
             vertex_buffers: Vec::new(),
             vertex_buffer_descs: Vec::new(),
             attributes: Vec::new(),
+            push_constant_uniforms: Vec::new(),
+            compute_bound: false,
+            graphics_push_constants: Vec::new(),
+            compute_push_constants: Vec::new(),
         }
     }
 }
@@ -227,6 +292,8 @@ pub struct RawCommandBuffer {
 
     limits: Limits,
     active_attribs: usize,
+    /// Commands recorded since the last reset, accumulated by the queue on submission.
+    pub(crate) statistics: FrameStatistics,
 }
 
 impl RawCommandBuffer {
@@ -264,6 +331,7 @@ impl RawCommandBuffer {
             cur_subpass: !0,
             limits,
             active_attribs: 0,
+            statistics: FrameStatistics::default(),
         }
     }
 
@@ -274,12 +342,53 @@ impl RawCommandBuffer {
         self.cache = Cache::new();
         self.pass_cache = None;
         self.cur_subpass = !0;
+        self.statistics = FrameStatistics::default();
     }
 
     fn push_cmd(&mut self, cmd: Command) {
         push_cmd_internal(&self.id, &mut self.memory, &mut self.buf, cmd);
     }
 
+    /// Upload the push constant uniforms of the active program overlapping
+    /// the given byte range of the push constant data.
+    fn update_push_constants(&mut self, compute: bool, range: Range<u32>) {
+        if self.cache.compute_bound != compute {
+            // Uploaded once a pipeline of this bind point gets bound.
+            return;
+        }
+
+        let uniforms = self.cache.push_constant_uniforms.clone();
+        for uniform in uniforms {
+            // Push constants follow the std430 layout, where matrix columns
+            // and array elements with 3 rows are aligned to 4 words.
+            let rows = uniform.rows as usize;
+            let column_stride = if rows == 3 { 4 } else { rows };
+            let element_stride = column_stride * uniform.columns as usize;
+            let start = uniform.offset;
+            let end = start + (element_stride * uniform.count as usize * 4) as u32;
+            if end <= range.start || range.end <= start {
+                continue;
+            }
+
+            let data = if compute {
+                &self.cache.compute_push_constants
+            } else {
+                &self.cache.graphics_push_constants
+            };
+            let base = start as usize / 4;
+            let mut words = Vec::with_capacity(uniform.word_count() as usize);
+            for element in 0 .. uniform.count as usize {
+                for column in 0 .. uniform.columns as usize {
+                    let first = base + element * element_stride + column * column_stride;
+                    words.extend((first .. first + rows).map(|i| data.get(i).cloned().unwrap_or(0)));
+                }
+            }
+
+            let slice = self.add(&words);
+            self.push_cmd(Command::SetUniform(uniform, slice));
+        }
+    }
+
     /// Copy a given vector slice into the data buffer.
     fn add<T>(&mut self, data: &[T]) -> BufferSlice {
         self.add_raw(unsafe {
@@ -367,9 +476,15 @@ impl RawCommandBuffer {
 
             if vertex_buffers.len() <= binding {
                 error!("No vertex buffer bound at {}", binding);
+                continue;
             }
 
-            let handle = vertex_buffers[binding];
+            let (handle, buffer_offset) = vertex_buffers[binding];
+            // The buffer offset is folded into the attribute pointer offset.
+            let attribute = n::AttributeDesc {
+                offset: attribute.offset + buffer_offset as u32,
+                .. *attribute
+            };
 
             match vertex_buffer_descs.get(binding) {
                 Some(&Some(desc)) => {
@@ -378,7 +493,7 @@ impl RawCommandBuffer {
                         &self.id,
                         &mut self.memory,
                         &mut self.buf,
-                        Command::BindAttribute(*attribute, handle, desc.stride as _, attribute.vertex_attrib_fn)
+                        Command::BindAttribute(attribute, handle, desc.stride as _, attribute.vertex_attrib_fn)
                     );
                 }
                 _ => error!("No vertex buffer description bound at {}", binding),
@@ -527,12 +642,27 @@ impl command::RawCommandBuffer<Backend> for RawCommandBuffer {
         &mut self,
         _stages: Range<hal::pso::PipelineStage>,
         _dependencies: memory::Dependencies,
-        _barriers: T,
+        barriers: T,
     ) where
         T: IntoIterator,
         T::Item: Borrow<memory::Barrier<'a, Backend>>,
     {
-        // TODO
+        self.statistics.barriers += 1;
+        // GL only requires explicit barriers for incoherent accesses,
+        // which are made visible to the destination accesses.
+        let mut bits = 0;
+        for barrier in barriers {
+            bits |= match *barrier.borrow() {
+                memory::Barrier::AllBuffers(ref access) => conv::buffer_access_to_barrier(access.end),
+                memory::Barrier::AllImages(ref access) => conv::image_access_to_barrier(access.end),
+                memory::Barrier::Buffer { ref states, .. } => conv::buffer_access_to_barrier(states.end),
+                memory::Barrier::Image { ref states, .. } => conv::image_access_to_barrier(states.end.0),
+            };
+        }
+
+        if bits != 0 {
+            self.push_cmd(Command::MemoryBarrier(bits));
+        }
     }
 
     fn fill_buffer<R>(&mut self, _buffer: &n::Buffer, _range: R, _data: u32)
@@ -605,6 +735,23 @@ impl command::RawCommandBuffer<Backend> for RawCommandBuffer {
         self.begin_subpass();
     }
 
+    fn begin_render_pass_imageless<T, I>(
+        &mut self,
+        _render_pass: &n::RenderPass,
+        _framebuffer: &n::FrameBuffer,
+        _attachments: I,
+        _render_area: pso::Rect,
+        _clear_values: T,
+        _first_subpass: command::SubpassContents,
+    ) where
+        T: IntoIterator,
+        T::Item: Borrow<command::ClearValueRaw>,
+        I: IntoIterator,
+        I::Item: Borrow<n::ImageView>,
+    {
+        unimplemented!()
+    }
+
     fn next_subpass(&mut self, _contents: command::SubpassContents) {
         unimplemented!()
     }
@@ -613,13 +760,21 @@ impl command::RawCommandBuffer<Backend> for RawCommandBuffer {
         // TODO
     }
 
+    fn begin_rendering(&mut self, _info: &command::RenderingInfo<Backend>) {
+        panic!("`begin_rendering` requires `Features::DYNAMIC_RENDERING`, which isn't supported by GL");
+    }
+
+    fn end_rendering(&mut self) {
+        unreachable!("`begin_rendering` isn't supported by GL")
+    }
+
     fn clear_image<T>(
         &mut self,
         image: &n::Image,
         _: image::Layout,
         color: command::ClearColorRaw,
-        _depth_stencil: command::ClearDepthStencilRaw,
-        _subresource_ranges: T,
+        depth_stencil: command::ClearDepthStencilRaw,
+        subresource_ranges: T,
     ) where
         T: IntoIterator,
         T::Item: Borrow<image::SubresourceRange>,
@@ -632,20 +787,50 @@ impl command::RawCommandBuffer<Backend> for RawCommandBuffer {
         // 2. ClearBuffer
         // TODO: reset color mask
         let fbo = self.fbo;
-        let view = match image.kind {
-            n::ImageKind::Surface(id) => n::ImageView::Surface(id),
-            n::ImageKind::Texture(id) => n::ImageView::Texture(id, 0), //TODO
-        };
         self.push_cmd(Command::BindFrameBuffer(gl::DRAW_FRAMEBUFFER, fbo));
-        self.push_cmd(Command::BindTargetView(gl::DRAW_FRAMEBUFFER, gl::COLOR_ATTACHMENT0, view));
-        self.push_cmd(Command::SetDrawColorBuffers(1));
-
-        match image.channel {
-            ChannelType::Unorm | ChannelType::Inorm | ChannelType::Ufloat |
-            ChannelType::Float | ChannelType::Srgb | ChannelType::Uscaled |
-            ChannelType::Iscaled => self.push_cmd(Command::ClearBufferColorF(0, unsafe { color.float32 })),
-            ChannelType::Uint => self.push_cmd(Command::ClearBufferColorU(0, unsafe { color.uint32 })),
-            ChannelType::Int => self.push_cmd(Command::ClearBufferColorI(0, unsafe { color.int32 })),
+
+        for subresource_range in subresource_ranges {
+            let sub = subresource_range.borrow();
+            // Each (level, layer) pair is attached and cleared separately,
+            // non-array textures are attached as a whole.
+            let views = match image.kind {
+                n::ImageKind::Surface(id) => vec![n::ImageView::Surface(id)],
+                n::ImageKind::Texture(id) if sub.layers == (0 .. 1) => sub.levels
+                    .clone()
+                    .map(|level| n::ImageView::Texture(id, level))
+                    .collect(),
+                n::ImageKind::Texture(id) => sub.levels
+                    .clone()
+                    .flat_map(|level| sub.layers.clone().map(move |layer| n::ImageView::TextureLayer(id, level, layer)))
+                    .collect(),
+            };
+
+            for view in views {
+                if sub.aspects.contains(Aspects::COLOR) {
+                    self.push_cmd(Command::BindTargetView(gl::DRAW_FRAMEBUFFER, gl::COLOR_ATTACHMENT0, view));
+                    self.push_cmd(Command::SetDrawColorBuffers(1));
+
+                    match image.channel {
+                        ChannelType::Unorm | ChannelType::Inorm | ChannelType::Ufloat |
+                        ChannelType::Float | ChannelType::Srgb | ChannelType::Uscaled |
+                        ChannelType::Iscaled => self.push_cmd(Command::ClearBufferColorF(0, unsafe { color.float32 })),
+                        ChannelType::Uint => self.push_cmd(Command::ClearBufferColorU(0, unsafe { color.uint32 })),
+                        ChannelType::Int => self.push_cmd(Command::ClearBufferColorI(0, unsafe { color.int32 })),
+                    }
+                }
+
+                let depth = if sub.aspects.contains(Aspects::DEPTH) { Some(depth_stencil.depth) } else { None };
+                let stencil = if sub.aspects.contains(Aspects::STENCIL) { Some(depth_stencil.stencil) } else { None };
+                if depth.is_some() || stencil.is_some() {
+                    let attachment = match (depth, stencil) {
+                        (Some(_), Some(_)) => gl::DEPTH_STENCIL_ATTACHMENT,
+                        (Some(_), None) => gl::DEPTH_ATTACHMENT,
+                        _ => gl::STENCIL_ATTACHMENT,
+                    };
+                    self.push_cmd(Command::BindTargetView(gl::DRAW_FRAMEBUFFER, attachment, view));
+                    self.push_cmd(Command::ClearBufferDepthStencil(depth, stencil));
+                }
+            }
         }
     }
 
@@ -689,12 +874,8 @@ impl command::RawCommandBuffer<Backend> for RawCommandBuffer {
     }
 
     fn bind_index_buffer(&mut self, ibv: buffer::IndexBufferView<Backend>) {
-        // TODO: how can we incorporate the buffer offset?
-        if ibv.offset > 0 {
-            warn!("Non-zero index buffer offset currently not handled.");
-        }
-
         self.cache.index_type = Some(ibv.index_type);
+        self.cache.index_offset = ibv.offset;
         self.push_cmd(Command::BindIndexBuffer(ibv.buffer.raw));
     }
 
@@ -706,12 +887,9 @@ impl command::RawCommandBuffer<Backend> for RawCommandBuffer {
         for (i, (buffer, offset)) in buffers.into_iter().enumerate() {
             let index = first_binding as usize + i;
             if self.cache.vertex_buffers.len() <= index {
-                self.cache.vertex_buffers.resize(index+1, 0);
-            }
-            self.cache.vertex_buffers[index] = buffer.borrow().raw;
-            if offset != 0 {
-                error!("Vertex buffer offset {} is not supported", offset);
+                self.cache.vertex_buffers.resize(index+1, (0, 0));
             }
+            self.cache.vertex_buffers[index] = (buffer.borrow().raw, offset);
         }
     }
 
@@ -835,13 +1013,18 @@ impl command::RawCommandBuffer<Backend> for RawCommandBuffer {
     }
 
     fn bind_graphics_pipeline(&mut self, pipeline: &n::GraphicsPipeline) {
+        self.statistics.pipeline_binds += 1;
         let n::GraphicsPipeline {
             primitive,
             patch_size,
+            program_point_size,
+            framebuffer_srgb,
             program,
             ref blend_targets,
+            depth,
             ref attributes,
             ref vertex_buffers,
+            ref push_constants,
         } = *pipeline;
 
         if self.cache.primitive != Some(primitive) {
@@ -855,16 +1038,35 @@ impl command::RawCommandBuffer<Backend> for RawCommandBuffer {
             }
         }
 
+        if self.cache.program_point_size != Some(program_point_size) {
+            self.cache.program_point_size = Some(program_point_size);
+            self.push_cmd(Command::SetProgramPointSize(program_point_size));
+        }
+
+        if self.cache.framebuffer_srgb != Some(framebuffer_srgb) {
+            self.cache.framebuffer_srgb = Some(framebuffer_srgb);
+            self.push_cmd(Command::SetFramebufferSrgb(framebuffer_srgb));
+        }
+
         if self.cache.program != Some(program) {
             self.cache.program = Some(program);
             self.push_cmd(Command::BindProgram(program));
         }
 
+        if self.cache.depth != Some(depth) {
+            self.cache.depth = Some(depth);
+            self.push_cmd(Command::SetDepthState(depth));
+        }
+
         self.cache.attributes = attributes.clone();
 
         self.cache.vertex_buffer_descs = vertex_buffers.clone();
 
         self.update_blend_targets(blend_targets);
+
+        self.cache.push_constant_uniforms = push_constants.clone();
+        self.cache.compute_bound = false;
+        self.update_push_constants(false, 0 .. !0);
     }
 
     fn bind_graphics_descriptor_sets<I, J>(
@@ -926,14 +1128,20 @@ impl command::RawCommandBuffer<Backend> for RawCommandBuffer {
     }
 
     fn bind_compute_pipeline(&mut self, pipeline: &n::ComputePipeline) {
+        self.statistics.pipeline_binds += 1;
         let n::ComputePipeline {
             program,
+            ref push_constants,
         } = *pipeline;
 
         if self.cache.program != Some(program) {
             self.cache.program = Some(program);
             self.push_cmd(Command::BindProgram(program));
         }
+
+        self.cache.push_constant_uniforms = push_constants.clone();
+        self.cache.compute_bound = true;
+        self.update_push_constants(true, 0 .. !0);
     }
 
     fn bind_compute_descriptor_sets<I, J>(
@@ -952,10 +1160,22 @@ impl command::RawCommandBuffer<Backend> for RawCommandBuffer {
     }
 
     fn dispatch(&mut self, count: hal::WorkGroupCount) {
+        self.statistics.dispatches += 1;
+        self.push_cmd(Command::Dispatch(count));
+    }
+
+    fn dispatch_base(&mut self, base: hal::WorkGroupCount, count: hal::WorkGroupCount) {
+        // GL has no notion of a base workgroup, the dispatch is skipped.
+        if base != [0; 3] {
+            debug_assert!(false, "Non-zero dispatch base requires `Features::DISPATCH_BASE`");
+            return;
+        }
+        self.statistics.dispatches += 1;
         self.push_cmd(Command::Dispatch(count));
     }
 
     fn dispatch_indirect(&mut self, buffer: &n::Buffer, offset: buffer::Offset) {
+        self.statistics.dispatches += 1;
         self.push_cmd(Command::DispatchIndirect(buffer.raw, offset));
     }
 
@@ -1065,6 +1285,7 @@ impl command::RawCommandBuffer<Backend> for RawCommandBuffer {
 
         match self.cache.primitive {
             Some(primitive) => {
+                self.statistics.draw_calls += 1;
                 self.push_cmd(
                     Command::Draw {
                         primitive,
@@ -1099,12 +1320,13 @@ impl command::RawCommandBuffer<Backend> for RawCommandBuffer {
         };
         match self.cache.primitive {
             Some(primitive) => {
+                self.statistics.draw_calls += 1;
                 self.push_cmd(
                     Command::DrawIndexed {
                         primitive,
                         index_type,
                         index_count: indices.end - indices.start,
-                        index_buffer_offset: start as _,
+                        index_buffer_offset: self.cache.index_offset + start as buffer::Offset,
                         base_vertex,
                         instances,
                     }
@@ -1119,22 +1341,76 @@ impl command::RawCommandBuffer<Backend> for RawCommandBuffer {
 
     fn draw_indirect(
         &mut self,
-        _buffer: &n::Buffer,
-        _offset: buffer::Offset,
-        _draw_count: hal::DrawCount,
-        _stride: u32,
+        buffer: &n::Buffer,
+        offset: buffer::Offset,
+        draw_count: hal::DrawCount,
+        stride: u32,
     ) {
-        unimplemented!()
+        self.bind_attributes();
+
+        match self.cache.primitive {
+            Some(primitive) => {
+                self.statistics.draw_calls += draw_count as u64;
+                self.push_cmd(
+                    Command::DrawIndirect {
+                        primitive,
+                        buffer: buffer.raw,
+                        offset,
+                        draw_count,
+                        stride,
+                    }
+                );
+            }
+            None => {
+                warn!("No primitive bound. An active pipeline needs to be bound before calling `draw_indirect`.");
+                self.cache.error_state = true;
+            }
+        }
     }
 
     fn draw_indexed_indirect(
         &mut self,
-        _buffer: &n::Buffer,
-        _offset: buffer::Offset,
-        _draw_count: hal::DrawCount,
-        _stride: u32,
+        buffer: &n::Buffer,
+        offset: buffer::Offset,
+        draw_count: hal::DrawCount,
+        stride: u32,
     ) {
-        unimplemented!()
+        self.bind_attributes();
+
+        let index_type = match self.cache.index_type {
+            Some(hal::IndexType::U16) => gl::UNSIGNED_SHORT,
+            Some(hal::IndexType::U32) => gl::UNSIGNED_INT,
+            None => {
+                warn!("No index type bound. An index buffer needs to be bound before calling `draw_indexed_indirect`.");
+                self.cache.error_state = true;
+                return;
+            }
+        };
+        // The first index of indirect draws is relative to the start of the index buffer.
+        if self.cache.index_offset != 0 {
+            warn!("Index buffer offsets are not supported by `draw_indexed_indirect`.");
+            self.cache.error_state = true;
+            return;
+        }
+        match self.cache.primitive {
+            Some(primitive) => {
+                self.statistics.draw_calls += draw_count as u64;
+                self.push_cmd(
+                    Command::DrawIndexedIndirect {
+                        primitive,
+                        index_type,
+                        buffer: buffer.raw,
+                        offset,
+                        draw_count,
+                        stride,
+                    }
+                );
+            }
+            None => {
+                warn!("No primitive bound. An active pipeline needs to be bound before calling `draw_indexed_indirect`.");
+                self.cache.error_state = true;
+            }
+        }
     }
 
     fn begin_query(
@@ -1149,10 +1425,12 @@ impl command::RawCommandBuffer<Backend> for RawCommandBuffer {
         &mut self,
         _layout: &n::PipelineLayout,
         _stages: pso::ShaderStageFlags,
-        _offset: u32,
-        _constants: &[u32],
+        offset: u32,
+        constants: &[u32],
     ) {
-        unimplemented!()
+        write_push_constants(&mut self.cache.graphics_push_constants, offset, constants);
+        let end = offset + constants.len() as u32;
+        self.update_push_constants(false, offset * 4 .. end * 4);
     }
 
     fn end_query(
@@ -1181,20 +1459,51 @@ impl command::RawCommandBuffer<Backend> for RawCommandBuffer {
     fn push_compute_constants(
         &mut self,
         _layout: &n::PipelineLayout,
-        _offset: u32,
-        _constants: &[u32],
+        offset: u32,
+        constants: &[u32],
     ) {
-        unimplemented!()
+        write_push_constants(&mut self.cache.compute_push_constants, offset, constants);
+        let end = offset + constants.len() as u32;
+        self.update_push_constants(true, offset * 4 .. end * 4);
     }
 
     fn execute_commands<I>(
         &mut self,
-        _buffers: I,
+        buffers: I,
     ) where
         I: IntoIterator,
         I::Item: Borrow<RawCommandBuffer>
     {
-        unimplemented!()
+        for buffer in buffers {
+            let buffer = buffer.borrow();
+            // Secondary command buffers may share the memory of our pool,
+            // copy their commands out before recording them again.
+            let (commands, data) = {
+                let memory = buffer
+                    .memory
+                    .try_lock()
+                    .expect("Trying to execute a command buffer, while its memory is in-use.");
+                let owned = match *memory {
+                    BufferMemory::Linear(ref owned) => owned,
+                    BufferMemory::Individual { ref storage, .. } => &storage[&buffer.id],
+                };
+                let start = buffer.buf.offset as usize;
+                let end = start + buffer.buf.size as usize;
+                (owned.commands[start .. end].to_vec(), owned.data.clone())
+            };
+
+            let data_slice = self.add_raw(&data);
+            for mut cmd in commands {
+                cmd.rebase(data_slice.offset);
+                self.push_cmd(cmd);
+            }
+            self.statistics.add(&buffer.statistics);
+        }
+
+        // State set by the secondary command buffers isn't tracked.
+        let error_state = self.cache.error_state;
+        self.cache = Cache::new();
+        self.cache.error_state = error_state;
     }
 }
 
@@ -1203,6 +1512,16 @@ impl command::RawCommandBuffer<Backend> for RawCommandBuffer {
 /// `push_cmd`, but this is needed when the caller would like to perform a
 /// partial borrow to `self`. For example, iterating through a field on
 /// `self` and calling `self.push_cmd` per iteration.
+/// Write `constants` at the word `offset` of the push constant data.
+fn write_push_constants(data: &mut Vec<u32>, offset: u32, constants: &[u32]) {
+    let start = offset as usize;
+    let end = start + constants.len();
+    if data.len() < end {
+        data.resize(end, 0);
+    }
+    data[start .. end].copy_from_slice(constants);
+}
+
 fn push_cmd_internal(id: &u64, memory: &mut Arc<Mutex<BufferMemory>>, buffer: &mut BufferSlice, cmd: Command) {
     let mut memory = memory
         .try_lock()
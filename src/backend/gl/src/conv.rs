@@ -1,7 +1,7 @@
 use gl::{self, types as t};
 use hal::{buffer, image as i, Primitive};
 use hal::format::Format;
-use native::VertexAttribFunction;
+use native::{UniformBase, VertexAttribFunction};
 
 /*
 pub fn _image_kind_to_gl(kind: i::Kind) -> t::GLenum {
@@ -71,6 +71,34 @@ pub fn primitive_to_gl_primitive(primitive: Primitive) -> t::GLenum {
     }
 }
 
+/// Map an active uniform type to its scalar type, rows and columns.
+pub fn uniform_type_to_layout(ty: t::GLenum) -> Option<(UniformBase, u8, u8)> {
+    Some(match ty {
+        gl::FLOAT => (UniformBase::Float, 1, 1),
+        gl::FLOAT_VEC2 => (UniformBase::Float, 2, 1),
+        gl::FLOAT_VEC3 => (UniformBase::Float, 3, 1),
+        gl::FLOAT_VEC4 => (UniformBase::Float, 4, 1),
+        gl::INT | gl::BOOL => (UniformBase::Int, 1, 1),
+        gl::INT_VEC2 | gl::BOOL_VEC2 => (UniformBase::Int, 2, 1),
+        gl::INT_VEC3 | gl::BOOL_VEC3 => (UniformBase::Int, 3, 1),
+        gl::INT_VEC4 | gl::BOOL_VEC4 => (UniformBase::Int, 4, 1),
+        gl::UNSIGNED_INT => (UniformBase::Uint, 1, 1),
+        gl::UNSIGNED_INT_VEC2 => (UniformBase::Uint, 2, 1),
+        gl::UNSIGNED_INT_VEC3 => (UniformBase::Uint, 3, 1),
+        gl::UNSIGNED_INT_VEC4 => (UniformBase::Uint, 4, 1),
+        gl::FLOAT_MAT2 => (UniformBase::Float, 2, 2),
+        gl::FLOAT_MAT3 => (UniformBase::Float, 3, 3),
+        gl::FLOAT_MAT4 => (UniformBase::Float, 4, 4),
+        gl::FLOAT_MAT2x3 => (UniformBase::Float, 3, 2),
+        gl::FLOAT_MAT2x4 => (UniformBase::Float, 4, 2),
+        gl::FLOAT_MAT3x2 => (UniformBase::Float, 2, 3),
+        gl::FLOAT_MAT3x4 => (UniformBase::Float, 4, 3),
+        gl::FLOAT_MAT4x2 => (UniformBase::Float, 2, 4),
+        gl::FLOAT_MAT4x3 => (UniformBase::Float, 3, 4),
+        _ => return None,
+    })
+}
+
 pub fn format_to_gl_format(format: Format) -> Option<(gl::types::GLint, gl::types::GLenum, VertexAttribFunction)> {
     use hal::format::Format::*;
     use gl::*;
@@ -111,3 +139,62 @@ pub fn format_to_gl_format(format: Format) -> Option<(gl::types::GLint, gl::type
 
     Some(format)
 }
+
+pub fn buffer_access_to_barrier(access: buffer::Access) -> t::GLbitfield {
+    use self::buffer::Access;
+    let mut bits = 0;
+
+    if access.contains(Access::INDIRECT_COMMAND_READ) {
+        bits |= gl::COMMAND_BARRIER_BIT;
+    }
+    if access.contains(Access::INDEX_BUFFER_READ) {
+        bits |= gl::ELEMENT_ARRAY_BARRIER_BIT;
+    }
+    if access.contains(Access::VERTEX_BUFFER_READ) {
+        bits |= gl::VERTEX_ATTRIB_ARRAY_BARRIER_BIT;
+    }
+    if access.contains(Access::CONSTANT_BUFFER_READ) {
+        bits |= gl::UNIFORM_BARRIER_BIT;
+    }
+    if access.intersects(Access::SHADER_READ | Access::SHADER_WRITE) {
+        bits |= gl::SHADER_STORAGE_BARRIER_BIT | gl::TEXTURE_FETCH_BARRIER_BIT;
+    }
+    if access.intersects(Access::TRANSFER_READ | Access::TRANSFER_WRITE) {
+        bits |= gl::BUFFER_UPDATE_BARRIER_BIT;
+    }
+    if access.intersects(Access::HOST_READ | Access::HOST_WRITE) {
+        bits |= gl::BUFFER_UPDATE_BARRIER_BIT | gl::PIXEL_BUFFER_BARRIER_BIT;
+    }
+    if access.intersects(Access::MEMORY_READ | Access::MEMORY_WRITE) {
+        bits = gl::ALL_BARRIER_BITS;
+    }
+
+    bits
+}
+
+pub fn image_access_to_barrier(access: i::Access) -> t::GLbitfield {
+    use self::i::Access;
+    let mut bits = 0;
+
+    if access.contains(Access::SHADER_READ) {
+        bits |= gl::TEXTURE_FETCH_BARRIER_BIT | gl::SHADER_IMAGE_ACCESS_BARRIER_BIT;
+    }
+    if access.contains(Access::SHADER_WRITE) {
+        bits |= gl::SHADER_IMAGE_ACCESS_BARRIER_BIT;
+    }
+    if access.intersects(
+        Access::INPUT_ATTACHMENT_READ |
+        Access::COLOR_ATTACHMENT_READ | Access::COLOR_ATTACHMENT_WRITE |
+        Access::DEPTH_STENCIL_ATTACHMENT_READ | Access::DEPTH_STENCIL_ATTACHMENT_WRITE
+    ) {
+        bits |= gl::FRAMEBUFFER_BARRIER_BIT;
+    }
+    if access.intersects(Access::TRANSFER_READ | Access::TRANSFER_WRITE | Access::HOST_READ | Access::HOST_WRITE) {
+        bits |= gl::TEXTURE_UPDATE_BARRIER_BIT | gl::PIXEL_BUFFER_BARRIER_BIT;
+    }
+    if access.intersects(Access::MEMORY_READ | Access::MEMORY_WRITE) {
+        bits = gl::ALL_BARRIER_BITS;
+    }
+
+    bits
+}
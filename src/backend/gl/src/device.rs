@@ -12,8 +12,10 @@ use hal::{self as c, device as d, error, image as i, memory, pass, pso, buffer,
 use hal::backend::FastHashMap;
 use hal::format::{ChannelType, Format, Swizzle};
 use hal::pool::CommandPoolCreateFlags;
+use hal::profiler::FrameStatistics;
 use hal::queue::QueueFamilyId;
 use hal::range::RangeArg;
+use hal::sync::HostTimeline;
 
 use spirv_cross::{glsl, spirv, ErrorCode as SpirvErrorCode};
 
@@ -44,6 +46,9 @@ fn get_program_iv(gl: &gl::Gl, name: n::Program, query: GLenum) -> gl::types::GL
     iv
 }
 
+/// Name given to the push constant block instance in the generated GLSL.
+const PUSH_CONSTANT_BLOCK: &str = "GFX_HAL_PUSH_CONSTANTS";
+
 fn get_shader_log(gl: &gl::Gl, name: n::Shader) -> String {
     let mut length = get_shader_iv(gl, name, gl::INFO_LOG_LENGTH);
     if length > 0 {
@@ -162,17 +167,23 @@ impl Device {
         }
     }
 
-    fn bind_target_compat(gl: &gl::Gl, point: GLenum, attachment: GLenum, view: &n::ImageView) {
+    fn bind_target_compat(
+        gl: &gl::Gl,
+        bindings: &mut state::BindingCache,
+        point: GLenum,
+        attachment: GLenum,
+        view: &n::ImageView,
+    ) {
         match *view {
             n::ImageView::Surface(surface) => unsafe {
                 gl.FramebufferRenderbuffer(point, attachment, gl::RENDERBUFFER, surface);
             },
             n::ImageView::Texture(texture, level) => unsafe {
-                gl.BindTexture(gl::TEXTURE_2D, texture);
+                bindings.bind_texture(gl, 0, texture);
                 gl.FramebufferTexture2D(point, attachment, gl::TEXTURE_2D, texture, level as _);
             },
             n::ImageView::TextureLayer(texture, level, layer) => unsafe {
-                gl.BindTexture(gl::TEXTURE_2D, texture);
+                bindings.bind_texture(gl, 0, texture);
                 gl.FramebufferTexture3D(point, attachment, gl::TEXTURE_2D, texture, level as _, layer as _);
             },
         }
@@ -228,18 +239,8 @@ impl Device {
                 .find(|c| c.id == spec_constant.constant_id)
             {
                 // Override specialization constant values
-                unsafe {
-                    let value = match constant.value {
-                        pso::Constant::Bool(v) => v as u64,
-                        pso::Constant::U32(v) => v as u64,
-                        pso::Constant::U64(v) => v,
-                        pso::Constant::I32(v) => *(&v as *const _ as *const u64),
-                        pso::Constant::I64(v) => *(&v as *const _ as *const u64),
-                        pso::Constant::F32(v) => *(&v as *const _ as *const u64),
-                        pso::Constant::F64(v) => *(&v as *const _ as *const u64),
-                    };
-                    ast.set_scalar_constant(spec_constant.id, value).map_err(gen_unexpected_error)?;
-                }
+                ast.set_scalar_constant(spec_constant.id, constant.value.to_bits())
+                    .map_err(gen_unexpected_error)?;
             }
         }
 
@@ -320,6 +321,93 @@ impl Device {
         }
     }
 
+    fn remap_push_constants(
+        &self,
+        ast: &mut spirv::Ast<glsl::Target>,
+        offsets: &mut FastHashMap<String, u32>,
+    ) {
+        let res = ast.get_shader_resources().unwrap();
+        for pc in &res.push_constant_buffers {
+            // Without Vulkan GLSL the block is emitted as a plain uniform struct,
+            // make its members addressable by a known name.
+            ast.set_name(pc.id, PUSH_CONSTANT_BLOCK).unwrap();
+            let member_count = match ast.get_type(pc.base_type_id).unwrap() {
+                spirv::Type::Struct { member_types, .. } => member_types.len(),
+                _ => continue,
+            };
+            for index in 0 .. member_count as u32 {
+                let name = ast.get_member_name(pc.base_type_id, index).unwrap();
+                let offset = ast
+                    .get_member_decoration(pc.base_type_id, index, spirv::Decoration::Offset)
+                    .unwrap();
+                offsets.insert(name, offset);
+            }
+        }
+    }
+
+    fn query_push_constants(
+        &self,
+        program: n::Program,
+        offsets: &FastHashMap<String, u32>,
+    ) -> Vec<n::PushConstantUniform> {
+        let gl = &self.share.context;
+        let count = get_program_iv(gl, program, gl::ACTIVE_UNIFORMS);
+        let max_length = get_program_iv(gl, program, gl::ACTIVE_UNIFORM_MAX_LENGTH);
+        let prefix = format!("{}.", PUSH_CONSTANT_BLOCK);
+        let mut uniforms = Vec::new();
+
+        for index in 0 .. count as u32 {
+            let mut name = vec![0u8; max_length.max(1) as usize];
+            let (mut length, mut size, mut ty) = (0, 0, 0);
+            unsafe {
+                gl.GetActiveUniform(
+                    program,
+                    index,
+                    max_length,
+                    &mut length,
+                    &mut size,
+                    &mut ty,
+                    name.as_mut_ptr() as _,
+                );
+            }
+            name.truncate(length as usize);
+            let name = String::from_utf8(name).unwrap();
+
+            let member = match name.starts_with(&prefix) {
+                true => name[prefix.len() ..].trim_right_matches("[0]"),
+                false => continue,
+            };
+            let offset = match offsets.get(member) {
+                Some(&offset) => offset,
+                None => {
+                    warn!("Unsupported push constant member {}", name);
+                    continue
+                }
+            };
+            let (base, rows, columns) = match conv::uniform_type_to_layout(ty) {
+                Some(layout) => layout,
+                None => {
+                    warn!("Unsupported push constant type {:x} for {}", ty, name);
+                    continue
+                }
+            };
+            let location = unsafe {
+                gl.GetUniformLocation(program, format!("{}\0", name).as_ptr() as _)
+            };
+
+            uniforms.push(n::PushConstantUniform {
+                location,
+                offset,
+                base,
+                rows,
+                columns,
+                count: size,
+            });
+        }
+
+        uniforms
+    }
+
     fn combine_separate_images_and_samplers(
         &self,
         ast: &mut spirv::Ast<glsl::Target>,
@@ -376,12 +464,21 @@ impl Device {
         }
     }
 
+    fn check_shader_features(&self, point: &pso::EntryPoint<B>) -> Result<(), pso::CreationError> {
+        match *point.module {
+            n::ShaderModule::Raw(_) => Ok(()),
+            n::ShaderModule::Spirv(ref spirv) => pso::check_spirv_features(spirv, self.share.features)
+                .map_err(pso::CreationError::Shader),
+        }
+    }
+
     fn compile_shader(
         &self,
         point: &pso::EntryPoint<B>,
         stage: pso::Stage,
         desc_remap_data: &mut n::DescRemapData,
         name_binding_map: &mut FastHashMap<String, pso::DescriptorBinding>,
+        push_constant_offsets: &mut FastHashMap<String, u32>,
     ) -> n::Shader {
         assert_eq!(point.entry, "main");
         match *point.module {
@@ -395,6 +492,7 @@ impl Device {
                 self.specialize_ast(&mut ast, point.specialization).unwrap();
                 self.remap_bindings(&mut ast, desc_remap_data, name_binding_map);
                 self.combine_separate_images_and_samplers(&mut ast, desc_remap_data, name_binding_map);
+                self.remap_push_constants(&mut ast, push_constant_offsets);
 
                 let glsl = self.translate_spirv(&mut ast).unwrap();
                 info!("Generated:\n{:?}", glsl);
@@ -448,6 +546,7 @@ impl d::Device<B> for Device {
 
     fn destroy_command_pool(&self, pool: RawCommandPool) {
         let gl = &self.share.context;
+        self.share.bindings.borrow_mut().forget_framebuffer(pool.fbo);
         unsafe {
             gl.DeleteFramebuffers(1, &pool.fbo);
         }
@@ -555,7 +654,29 @@ impl d::Device<B> for Device {
             }
         };
 
-        let program = {
+        let framebuffer_srgb = desc.blender.srgb == pso::SrgbConversion::Linear;
+        if cfg!(debug_assertions) && framebuffer_srgb && !share.legacy_features.contains(LegacyFeatures::SRGB_COLOR) {
+            let formats = subpass.color_attachments
+                .iter()
+                .map(|&id| desc.subpass.main_pass.attachments[id].format);
+            if desc.blender.blends_srgb(formats) {
+                warn!("Blending into sRGB targets happens on the encoded values without \
+                    GL_ARB_framebuffer_sRGB, the output differs from the other backends");
+            }
+        }
+
+        let points = [
+            Some(&desc.shaders.vertex),
+            desc.shaders.hull.as_ref(),
+            desc.shaders.domain.as_ref(),
+            desc.shaders.geometry.as_ref(),
+            desc.shaders.fragment.as_ref(),
+        ];
+        for point in points.iter().filter_map(|point| *point) {
+            self.check_shader_features(point)?;
+        }
+
+        let (program, push_constants) = {
             let name = unsafe { gl.CreateProgram() };
 
             // Attach shaders to program
@@ -568,6 +689,7 @@ impl d::Device<B> for Device {
             ];
 
             let mut name_binding_map = FastHashMap::<String, pso::DescriptorBinding>::default();
+            let mut push_constant_offsets = FastHashMap::default();
             let shader_names = &shaders
                 .iter()
                 .filter_map(|&(stage, point_maybe)| {
@@ -577,6 +699,7 @@ impl d::Device<B> for Device {
                             stage,
                             &mut desc.layout.desc_remap_data.write().unwrap(),
                             &mut name_binding_map,
+                            &mut push_constant_offsets,
                         );
                         unsafe { gl.AttachShader(name, shader_name); }
                         shader_name
@@ -608,8 +731,8 @@ impl d::Device<B> for Device {
 
             if !self.share.legacy_features.contains(LegacyFeatures::EXPLICIT_LAYOUTS_IN_SHADER) {
                 let gl = &self.share.context;
+                self.share.bindings.borrow_mut().bind_program(gl, name);
                 unsafe {
-                    gl.UseProgram(name);
                     for (bname, binding) in name_binding_map.iter() {
                         let loc = gl.GetUniformLocation(name, bname.as_ptr() as _);
                         gl.Uniform1i(loc, *binding as _);
@@ -627,7 +750,8 @@ impl d::Device<B> for Device {
                 return Err(pso::CreationError::Shader(d::ShaderError::CompilationFailed(log)));
             }
 
-            name
+            let push_constants = self.query_push_constants(name, &push_constant_offsets);
+            (name, push_constants)
         };
 
         let patch_size = match desc.input_assembler.primitive {
@@ -647,8 +771,12 @@ impl d::Device<B> for Device {
             program,
             primitive: conv::primitive_to_gl_primitive(desc.input_assembler.primitive),
             patch_size,
+            program_point_size: desc.rasterizer.program_point_size,
+            framebuffer_srgb,
             blend_targets: desc.blender.targets.clone(),
+            depth: desc.depth_stencil.depth,
             vertex_buffers,
+            push_constants,
             attributes: desc.attributes
                 .iter()
                 .map(|&a| {
@@ -673,15 +801,19 @@ impl d::Device<B> for Device {
         let gl = &self.share.context;
         let share = &self.share;
 
-        let program = {
+        self.check_shader_features(&desc.shader)?;
+
+        let (program, push_constants) = {
             let name = unsafe { gl.CreateProgram() };
 
             let mut name_binding_map = FastHashMap::<String, pso::DescriptorBinding>::default();
+            let mut push_constant_offsets = FastHashMap::default();
             let shader = self.compile_shader(
                 &desc.shader,
                 pso::Stage::Compute,
                 &mut desc.layout.desc_remap_data.write().unwrap(),
                 &mut name_binding_map,
+                &mut push_constant_offsets,
             );
             unsafe { gl.AttachShader(name, shader) };
 
@@ -698,8 +830,8 @@ impl d::Device<B> for Device {
 
             if !self.share.legacy_features.contains(LegacyFeatures::EXPLICIT_LAYOUTS_IN_SHADER) {
                 let gl = &self.share.context;
+                self.share.bindings.borrow_mut().bind_program(gl, name);
                 unsafe {
-                    gl.UseProgram(name);
                     for (bname, binding) in name_binding_map.iter() {
                         let loc = gl.GetUniformLocation(name, bname.as_ptr() as _);
                         gl.Uniform1i(loc, *binding as _);
@@ -714,14 +846,17 @@ impl d::Device<B> for Device {
                     warn!("\tLog: {}", log);
                 }
             } else {
-                return Err(pso::CreationError::Other);
+                let context = error::ErrorContext { messages: vec![log] };
+                return Err(pso::CreationError::Other.with_context(context));
             }
 
-            name
+            let push_constants = self.query_push_constants(name, &push_constant_offsets);
+            (name, push_constants)
         };
 
         Ok(n::ComputePipeline {
             program,
+            push_constants,
         })
     }
 
@@ -740,12 +875,13 @@ impl d::Device<B> for Device {
         }
 
         let gl = &self.share.context;
+        let mut bindings = self.share.bindings.borrow_mut();
         let target = gl::DRAW_FRAMEBUFFER;
         let mut name = 0;
         unsafe {
             gl.GenFramebuffers(1, &mut name);
-            gl.BindFramebuffer(target, name);
         }
+        bindings.bind_framebuffer(gl, target, name);
 
         let att_points = [
             gl::COLOR_ATTACHMENT0,
@@ -761,7 +897,7 @@ impl d::Device<B> for Device {
             if self.share.private_caps.framebuffer_texture {
                 Self::bind_target(gl, target, att_point, view.borrow());
             } else {
-                Self::bind_target_compat(gl, target, att_point, view.borrow());
+                Self::bind_target_compat(gl, &mut bindings, target, att_point, view.borrow());
             }
         }
         assert_eq!(attachments_len, pass.attachments.len());
@@ -771,7 +907,7 @@ impl d::Device<B> for Device {
             assert!(pass.attachments.len() <= att_points.len());
             gl.DrawBuffers(attachments_len as _, att_points.as_ptr());
             let status = gl.CheckFramebufferStatus(target);
-            gl.BindFramebuffer(target, 0);
+            bindings.bind_framebuffer(gl, target, 0);
         }
         if let Err(err) = self.share.check() {
             //TODO: attachments have been consumed
@@ -782,10 +918,27 @@ impl d::Device<B> for Device {
         Ok(name)
     }
 
+    fn create_imageless_framebuffer<I>(
+        &self,
+        _pass: &n::RenderPass,
+        _attachments: I,
+        _extent: i::Extent,
+    ) -> Result<n::FrameBuffer, d::FramebufferError>
+    where
+        I: IntoIterator,
+        I::Item: Borrow<pass::FramebufferAttachment>,
+    {
+        error!("Imageless framebuffers are not supported");
+        Err(d::FramebufferError)
+    }
+
     fn create_shader_module(
         &self,
         raw_data: &[u8],
     ) -> Result<n::ShaderModule, d::ShaderError> {
+        if cfg!(debug_assertions) {
+            pso::validate_spirv(raw_data)?;
+        }
         Ok(n::ShaderModule::Spirv(raw_data.into()))
     }
 
@@ -1038,7 +1191,7 @@ impl d::Device<B> for Device {
         let int_format = match format {
             Format::Rgba8Unorm => gl::RGBA8,
             Format::Rgba8Srgb => gl::SRGB8_ALPHA8,
-            _ => unimplemented!()
+            _ => return Err(i::CreationError::Format(format)),
         };
 
         let channel = format.base_format().1;
@@ -1051,7 +1204,7 @@ impl d::Device<B> for Device {
             unsafe { gl.GenTextures(1, &mut name) };
             match kind {
                 i::Kind::D2(w, h, 1, 1) => unsafe {
-                    gl.BindTexture(gl::TEXTURE_2D, name);
+                    self.share.bindings.borrow_mut().bind_texture(gl, 0, name);
                     gl.TexStorage2D(gl::TEXTURE_2D, num_levels as _, int_format, w as _, h as _);
                 }
                 _ => unimplemented!(),
@@ -1172,29 +1325,29 @@ impl d::Device<B> for Device {
         J: IntoIterator,
         J::Item: Borrow<pso::Descriptor<'a, B>>,
     {
+        let mut statistics = FrameStatistics::default();
         for mut write in writes {
+            statistics.descriptor_updates += 1;
             let set = &mut write.set;
             let mut bindings = set.bindings.lock().unwrap();
             let binding = write.binding;
-            let mut offset = write.array_offset as _;
 
             for descriptor in write.descriptors {
                 match descriptor.borrow() {
                     pso::Descriptor::Buffer(buffer, ref range) => {
+                        // Only the range is bound, so suballocated buffers can share a GL buffer.
                         let start = range.start.unwrap_or(0);
                         let end = range.end.unwrap_or(buffer.size);
-                        let size = (end - start) as _;
+                        assert!(start <= end && end <= buffer.size);
 
                         bindings
                             .push(n::DescSetBindings::Buffer {
                                 ty: n::BindingTypes::UniformBuffers,
                                 binding,
                                 buffer: buffer.raw,
-                                offset,
-                                size,
+                                offset: start as _,
+                                size: (end - start) as _,
                             });
-
-                        offset += size;
                     },
                     pso::Descriptor::CombinedImageSampler(view, _layout, sampler) => {
                         match view {
@@ -1233,6 +1386,7 @@ impl d::Device<B> for Device {
                 }
             }
         }
+        self.share.statistics.add(&statistics);
     }
 
     fn copy_descriptor_sets<'a, I>(&self, copies: I)
@@ -1301,6 +1455,35 @@ impl d::Device<B> for Device {
         unimplemented!()
     }
 
+    fn create_timeline_semaphore(&self, initial_value: u64) -> HostTimeline {
+        HostTimeline::new(initial_value)
+    }
+
+    fn get_timeline_semaphore_value(&self, semaphore: &HostTimeline) -> u64 {
+        semaphore.value()
+    }
+
+    fn signal_timeline_semaphore(&self, semaphore: &HostTimeline, value: u64) {
+        semaphore.signal(value);
+    }
+
+    fn wait_for_timeline_semaphores<'a, I>(&self, semaphores: I, wait: d::WaitFor, timeout_ms: u32) -> bool
+    where
+        I: IntoIterator<Item = (&'a HostTimeline, u64)>,
+    {
+        HostTimeline::wait_many(semaphores, wait, timeout_ms)
+    }
+
+    fn destroy_timeline_semaphore(&self, _: HostTimeline) {
+    }
+
+    fn create_event(&self) -> () {
+        // Events are waited on with full barriers.
+    }
+
+    fn destroy_event(&self, _: ()) {
+    }
+
     fn free_memory(&self, _memory: n::Memory) {
         // Nothing to do
     }
@@ -1325,6 +1508,26 @@ impl d::Device<B> for Device {
         // Nothing to do
     }
 
+    fn create_pipeline_cache(&self, _data: Option<&[u8]>) -> Result<(), d::OutOfMemory> {
+        // Program binaries aren't used, drivers cache compiled programs internally.
+        Ok(())
+    }
+
+    fn get_pipeline_cache_data(&self, _cache: &()) -> Result<Vec<u8>, d::OutOfMemory> {
+        Ok(Vec::new())
+    }
+
+    fn merge_pipeline_caches<I>(&self, _target: &(), _sources: I) -> Result<(), d::OutOfMemory>
+    where
+        I: IntoIterator,
+        I::Item: Borrow<()>,
+    {
+        Ok(())
+    }
+
+    fn destroy_pipeline_cache(&self, _cache: ()) {
+    }
+
     fn destroy_graphics_pipeline(&self, pipeline: n::GraphicsPipeline) {
         unsafe {
             self.share.context.DeleteProgram(pipeline.program);
@@ -1339,10 +1542,12 @@ impl d::Device<B> for Device {
 
     fn destroy_framebuffer(&self, frame_buffer: n::FrameBuffer) {
         let gl = &self.share.context;
+        self.share.bindings.borrow_mut().forget_framebuffer(frame_buffer);
         unsafe { gl.DeleteFramebuffers(1, &frame_buffer); }
     }
 
     fn destroy_buffer(&self, buffer: n::Buffer) {
+        self.share.bindings.borrow_mut().forget_buffer(buffer.raw);
         unsafe {
             self.share.context.DeleteBuffers(1, &buffer.raw);
         }
@@ -1355,10 +1560,30 @@ impl d::Device<B> for Device {
         let gl = &self.share.context;
         match image.kind {
             n::ImageKind::Surface(rb) => unsafe { gl.DeleteRenderbuffers(1, &rb) },
-            n::ImageKind::Texture(t) => unsafe { gl.DeleteTextures(1, &t) },
+            n::ImageKind::Texture(t) => unsafe {
+                self.share.bindings.borrow_mut().forget_texture(t);
+                gl.DeleteTextures(1, &t)
+            },
         }
     }
 
+    fn create_sparse_buffer(
+        &self, _size: u64, _usage: buffer::Usage,
+    ) -> Result<(n::Buffer, memory::Requirements), buffer::CreationError> {
+        unimplemented!()
+    }
+
+    fn create_sparse_image(
+        &self,
+        _kind: i::Kind,
+        _mip_levels: i::Level,
+        _format: c::format::Format,
+        _usage: i::Usage,
+        _storage_flags: i::StorageFlags,
+    ) -> Result<(n::Image, c::sparse::SparseImageRequirements), i::CreationError> {
+        unimplemented!()
+    }
+
     fn destroy_image_view(&self, _image_view: n::ImageView) {
         // Nothing to do
     }
@@ -1366,7 +1591,10 @@ impl d::Device<B> for Device {
     fn destroy_sampler(&self, sampler: n::FatSampler) {
         let gl = &self.share.context;
         match sampler {
-            n::FatSampler::Sampler(s) => unsafe { gl.DeleteSamplers(1, &s) },
+            n::FatSampler::Sampler(s) => unsafe {
+                self.share.bindings.borrow_mut().forget_sampler(s);
+                gl.DeleteSamplers(1, &s)
+            },
             _ => (),
         }
     }
@@ -1394,9 +1622,9 @@ impl d::Device<B> for Device {
         surface: &mut Surface,
         config: c::SwapchainConfig,
         _old_swapchain: Option<Swapchain>,
-        _extent: &window::Extent2D,
+        extent: &window::Extent2D,
     ) -> (Swapchain, c::Backbuffer<B>) {
-        self.create_swapchain_impl(surface, config)
+        self.create_swapchain_impl(surface, config, extent)
     }
 
     fn destroy_swapchain(&self, _swapchain: Swapchain) {
@@ -1407,6 +1635,10 @@ impl d::Device<B> for Device {
         unsafe { self.share.context.Finish(); }
         Ok(())
     }
+
+    fn frame_statistics(&self) -> Option<FrameStatistics> {
+        Some(self.share.statistics.take())
+    }
 }
 
 pub fn wait_fence(fence: &n::Fence, gl: &gl::Gl, timeout_ms: u32) -> GLenum {
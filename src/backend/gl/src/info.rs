@@ -173,6 +173,8 @@ pub struct PrivateCaps {
     pub program_interface: bool,
     pub frag_data_location: bool,
     pub sync: bool,
+    /// Can order incoherent memory accesses with `glMemoryBarrier`
+    pub memory_barrier: bool,
     /// Can map memory
     pub map: bool,
     /// Indicates if we only have support via the EXT.
@@ -305,11 +307,22 @@ pub fn query_all(gl: &gl::Gl) -> (Info, Features, LegacyFeatures, Limits, Privat
         min_buffer_copy_offset_alignment: 1,
         min_buffer_copy_pitch_alignment: 1,
         min_texel_buffer_offset_alignment: 1, // TODO
-        min_uniform_buffer_offset_alignment: 1, // TODO
+        min_uniform_buffer_offset_alignment: 1,
         min_storage_buffer_offset_alignment: 1, // TODO
         .. Limits::default()
     };
 
+    if info.is_supported(&[
+        Core(3,1),
+        Es(3,0),
+        Ext("GL_ARB_uniform_buffer_object"),
+    ]) {
+        // Uniform buffer descriptors bind a range of the buffer, which has to start
+        // at a multiple of this alignment.
+        limits.min_uniform_buffer_offset_alignment =
+            get_usize(gl, gl::UNIFORM_BUFFER_OFFSET_ALIGNMENT) as _;
+    }
+
     if info.is_supported(&[
         Core(4,0),
         Ext("GL_ARB_tessellation_shader"),
@@ -361,6 +374,11 @@ pub fn query_all(gl: &gl::Gl) -> (Info, Features, LegacyFeatures, Limits, Privat
     ]) {
         features |= Features::INSTANCE_RATE;
     }
+    if info.is_supported(&[
+        Ext("GL_ARB_shader_stencil_export"),
+    ]) {
+        features |= Features::SHADER_STENCIL_EXPORT;
+    }
 
     if info.is_supported(&[Core(4, 3), Es(3, 1)]) { // TODO: extension
         legacy |= LegacyFeatures::INDIRECT_EXECUTION;
@@ -456,6 +474,9 @@ pub fn query_all(gl: &gl::Gl) -> (Info, Features, LegacyFeatures, Limits, Privat
         sync:                               info.is_supported(&[Core(3,2),
                                                                 Es  (3,0),
                                                                 Ext ("GL_ARB_sync")]),
+        memory_barrier:                     info.is_supported(&[Core(4,2),
+                                                                Es  (3,1),
+                                                                Ext ("GL_ARB_shader_image_load_store")]),
         map:                                !info.version.is_embedded, //TODO: OES extension
         sampler_anisotropy_ext:             !info.is_supported(&[Core(4,6),
                                                                 Ext ("GL_ARB_texture_filter_anisotropic")]) &&
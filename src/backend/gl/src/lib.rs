@@ -14,14 +14,16 @@ extern crate spirv_cross;
 #[cfg(feature = "glutin")]
 pub extern crate glutin;
 
-use std::cell::Cell;
+use std::cell::{Cell, RefCell};
 use std::fmt;
 use std::sync::Arc;
 use std::ops::Deref;
 use std::thread::{self, ThreadId};
 
 use hal::{error, image, pso};
+use hal::backend::StatisticsCollector;
 use hal::queue::{Queues, QueueFamilyId};
+use hal::sync::HostTimeline;
 
 pub use self::device::Device;
 pub use self::info::{Info, PlatformName, Version};
@@ -70,12 +72,15 @@ impl hal::Backend for Backend {
     type ComputePipeline = native::ComputePipeline;
     type GraphicsPipeline = native::GraphicsPipeline;
     type PipelineLayout = native::PipelineLayout;
+    type PipelineCache = ();
     type DescriptorSetLayout = native::DescriptorSetLayout;
     type DescriptorPool = native::DescriptorPool;
     type DescriptorSet = native::DescriptorSet;
 
     type Fence = native::Fence;
     type Semaphore = native::Semaphore;
+    type TimelineSemaphore = HostTimeline;
+    type Event = ();
     type QueryPool = ();
 }
 
@@ -114,6 +119,10 @@ struct Share {
     private_caps: info::PrivateCaps,
     // Indicates if there is an active logical device.
     open: Cell<bool>,
+    // Bindings of the context, shared by the device and the queue.
+    bindings: RefCell<state::BindingCache>,
+    // Command statistics, accumulated by the queue on submission.
+    statistics: StatisticsCollector,
 }
 
 impl Share {
@@ -204,6 +213,8 @@ impl PhysicalDevice {
             limits,
             private_caps,
             open: Cell::new(false),
+            bindings: RefCell::new(state::BindingCache::default()),
+            statistics: StatisticsCollector::default(),
         };
         if let Err(err) = share.check() {
             panic!("Error querying info: {:?}", err);
@@ -215,6 +226,7 @@ impl PhysicalDevice {
                 vendor: 0, // TODO
                 device: 0, // TODO
                 software_rendering: false, // not always true ..
+                device_type: hal::adapter::DeviceType::Other,
             },
             physical_device: PhysicalDevice(Starc::new(share)),
             queue_families: vec![QueueFamily],
@@ -247,10 +259,6 @@ impl hal::PhysicalDevice<Backend> for PhysicalDevice {
         }
         unsafe {
             gl.PixelStorei(gl::UNPACK_ALIGNMENT, 1);
-
-            if !self.0.info.version.is_embedded {
-                gl.Enable(gl::PROGRAM_POINT_SIZE);
-            }
         }
 
         // create main VAO and bind it
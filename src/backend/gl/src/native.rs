@@ -113,14 +113,19 @@ pub struct GraphicsPipeline {
     pub(crate) program: Program,
     pub(crate) primitive: gl::types::GLenum,
     pub(crate) patch_size: Option<gl::types::GLint>,
+    pub(crate) program_point_size: bool,
+    pub(crate) framebuffer_srgb: bool,
     pub(crate) blend_targets: Vec<pso::ColorBlendDesc>,
+    pub(crate) depth: pso::DepthTest,
     pub(crate) attributes: Vec<AttributeDesc>,
     pub(crate) vertex_buffers: Vec<Option<pso::VertexBufferDesc>>,
+    pub(crate) push_constants: Vec<PushConstantUniform>,
 }
 
 #[derive(Clone, Debug)]
 pub struct ComputePipeline {
     pub(crate) program: Program,
+    pub(crate) push_constants: Vec<PushConstantUniform>,
 }
 
 #[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
@@ -273,6 +278,37 @@ pub struct AttributeDesc {
     pub(crate) vertex_attrib_fn: VertexAttribFunction,
 }
 
+/// Scalar type of a uniform.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UniformBase {
+    Float,  // glUniform*fv
+    Int,    // glUniform*iv
+    Uint,   // glUniform*uiv
+}
+
+/// Plain uniform backing a member of the push constant block,
+/// which isn't available outside of Vulkan GLSL.
+#[derive(Debug, Clone, Copy)]
+pub struct PushConstantUniform {
+    pub(crate) location: gl::types::GLint,
+    // Offset of the member in the push constant range, in bytes.
+    pub(crate) offset: u32,
+    pub(crate) base: UniformBase,
+    // Vector size for vectors and scalars, number of rows for matrices.
+    pub(crate) rows: u8,
+    // 1 for vectors and scalars.
+    pub(crate) columns: u8,
+    // Number of array elements.
+    pub(crate) count: gl::types::GLsizei,
+}
+
+impl PushConstantUniform {
+    /// Size of the uniform data, in 32-bit words.
+    pub fn word_count(&self) -> u32 {
+        self.rows as u32 * self.columns as u32 * self.count as u32
+    }
+}
+
 #[derive(Debug, Clone, Copy)]
 pub enum VertexAttribFunction {
     Float, // glVertexAttribPointer
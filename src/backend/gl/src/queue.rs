@@ -4,6 +4,7 @@ use Starc;
 
 use hal;
 use hal::error;
+use hal::profiler::FrameStatistics;
 
 use gl;
 use smallvec::SmallVec;
@@ -84,6 +85,7 @@ impl CommandQueue {
         // Flush the state to enforce a reset once a new command buffer
         // is execute because we have no control of the called functions.
         self.state.flush();
+        self.share.bindings.borrow_mut().invalidate();
     }
 
     /*
@@ -376,13 +378,41 @@ impl CommandQueue {
                 let gl = &self.share.context;
                 unsafe { gl.DispatchCompute(count[0], count[1], count[2]) };
             }
+            com::Command::DrawIndirect { primitive, buffer, offset, draw_count, stride } => {
+                let gl = &self.share.context;
+                if !self.share.legacy_features.contains(LegacyFeatures::INDIRECT_EXECUTION) {
+                    error!("Indirect draws are not supported");
+                    return;
+                }
+                unsafe {
+                    gl.BindBuffer(gl::DRAW_INDIRECT_BUFFER, buffer);
+                    for i in 0 .. draw_count as hal::buffer::Offset {
+                        let offset = offset + i * stride as hal::buffer::Offset;
+                        gl.DrawArraysIndirect(primitive, offset as *const gl::types::GLvoid);
+                    }
+                }
+            }
+            com::Command::DrawIndexedIndirect { primitive, index_type, buffer, offset, draw_count, stride } => {
+                let gl = &self.share.context;
+                if !self.share.legacy_features.contains(LegacyFeatures::INDIRECT_EXECUTION) {
+                    error!("Indirect draws are not supported");
+                    return;
+                }
+                unsafe {
+                    gl.BindBuffer(gl::DRAW_INDIRECT_BUFFER, buffer);
+                    for i in 0 .. draw_count as hal::buffer::Offset {
+                        let offset = offset + i * stride as hal::buffer::Offset;
+                        gl.DrawElementsIndirect(primitive, index_type, offset as *const gl::types::GLvoid);
+                    }
+                }
+            }
             com::Command::DispatchIndirect(buffer, offset) => {
                 // Capability support is given by which queue types will be exposed.
                 // If there is no compute support, this pattern should never be reached
                 // because no queue with compute capability can be created.
                 let gl = &self.share.context;
                 unsafe {
-                    gl.BindBuffer(gl::DRAW_INDIRECT_BUFFER, buffer);
+                    gl.BindBuffer(gl::DISPATCH_INDIRECT_BUFFER, buffer);
                     // TODO: possible integer conversion issue
                     gl.DispatchComputeIndirect(offset as _);
                 }
@@ -424,7 +454,10 @@ impl CommandQueue {
                 }
             }
             com::Command::SetBlendColor(color) => {
-                state::set_blend_color(&self.share.context, color);
+                self.share.bindings.borrow_mut().set_blend_color(&self.share.context, color);
+            }
+            com::Command::SetDepthState(ref depth) => {
+                self.share.bindings.borrow_mut().bind_depth(&self.share.context, depth);
             }
             com::Command::ClearBufferColorF(draw_buffer, cv) => unsafe {
                 self.share.context.ClearBufferfv(gl::COLOR, draw_buffer, cv.as_ptr());
@@ -454,8 +487,7 @@ impl CommandQueue {
             }
             com::Command::BindFrameBuffer(point, frame_buffer) => {
                 if self.share.private_caps.framebuffer {
-                    let gl = &self.share.context;
-                    unsafe { gl.BindFramebuffer(point, frame_buffer) };
+                    self.share.bindings.borrow_mut().bind_framebuffer(&self.share.context, point, frame_buffer);
                 } else if frame_buffer != 0 {
                     error!("Tried to bind FBO {} without FBO support!", frame_buffer);
                 }
@@ -469,11 +501,25 @@ impl CommandQueue {
             com::Command::SetPatchSize(num) => unsafe {
                 self.share.context.PatchParameteri(gl::PATCH_VERTICES, num);
             }
-            com::Command::BindProgram(program) => unsafe {
-                self.share.context.UseProgram(program);
+            com::Command::MemoryBarrier(bits) => {
+                if self.share.private_caps.memory_barrier {
+                    unsafe { self.share.context.MemoryBarrier(bits) };
+                }
+            }
+            com::Command::SetProgramPointSize(enable) => {
+                state::set_program_point_size(&self.share.context, enable, self.share.info.version.is_embedded);
+            }
+            com::Command::SetFramebufferSrgb(enable) => {
+                // Without the extension colors are always written as is.
+                if self.share.legacy_features.contains(LegacyFeatures::SRGB_COLOR) {
+                    state::set_framebuffer_srgb(&self.share.context, enable);
+                }
+            }
+            com::Command::BindProgram(program) => {
+                self.share.bindings.borrow_mut().bind_program(&self.share.context, program);
             }
             com::Command::BindBlendSlot(slot, ref blend) => {
-                state::bind_blend_slot(&self.share.context, slot, blend);
+                self.share.bindings.borrow_mut().bind_blend_slot(&self.share.context, slot, blend);
             }
             com::Command::BindAttribute(ref attribute, handle, stride, function_type) => unsafe {
                 use native::VertexAttribFunction::*;
@@ -512,9 +558,8 @@ impl CommandQueue {
                 // TODO: Fix format and active texture
                 assert_eq!(r.image_offset.z, 0);
                 let gl = &self.share.context;
-                gl.ActiveTexture(gl::TEXTURE0);
+                self.share.bindings.borrow_mut().bind_texture(gl, 0, texture);
                 gl.BindBuffer(gl::PIXEL_UNPACK_BUFFER, buffer);
-                gl.BindTexture(gl::TEXTURE_2D, texture);
                 gl.TexSubImage2D(
                     gl::TEXTURE_2D, r.image_layers.level as _,
                     r.image_offset.x, r.image_offset.y,
@@ -531,9 +576,8 @@ impl CommandQueue {
                 // TODO: handle partial copies gracefully
                 assert_eq!(r.image_offset, hal::image::Offset { x: 0, y: 0, z: 0 });
                 let gl = &self.share.context;
-                gl.ActiveTexture(gl::TEXTURE0);
+                self.share.bindings.borrow_mut().bind_texture(gl, 0, texture);
                 gl.BindBuffer(gl::PIXEL_PACK_BUFFER, buffer);
-                gl.BindTexture(gl::TEXTURE_2D, texture);
                 gl.GetTexImage(
                     gl::TEXTURE_2D, r.image_layers.level as _,
                     //r.image_offset.x, r.image_offset.y,
@@ -551,18 +595,47 @@ impl CommandQueue {
             com::Command::CopyImageToSurface(..) => {
                 unimplemented!() //TODO: use FBO
             }
-            com::Command::BindBufferRange(target, index, buffer, offset, size) => unsafe {
-                let gl = &self.share.context;
-                gl.BindBufferRange(target, index, buffer, offset, size);
+            com::Command::BindBufferRange(target, index, buffer, offset, size) => {
+                self.share.bindings.borrow_mut().bind_buffer_range(&self.share.context, target, index, buffer, offset, size);
             }
-            com::Command::BindTexture(index, texture) => unsafe {
-                let gl = &self.share.context;
-                gl.ActiveTexture(gl::TEXTURE0 + index);
-                gl.BindTexture(gl::TEXTURE_2D, texture);
+            com::Command::BindTexture(index, texture) => {
+                self.share.bindings.borrow_mut().bind_texture(&self.share.context, index, texture);
             }
-            com::Command::BindSampler(index, sampler) => unsafe {
+            com::Command::BindSampler(index, sampler) => {
+                self.share.bindings.borrow_mut().bind_sampler(&self.share.context, index, sampler);
+            }
+            com::Command::SetUniform(ref uniform, data_ptr) => {
                 let gl = &self.share.context;
-                gl.BindSampler(index, sampler);
+                let data = Self::get::<u32>(data_buf, data_ptr);
+                let (location, count) = (uniform.location, uniform.count);
+                let floats = data.as_ptr() as *const gl::types::GLfloat;
+                let ints = data.as_ptr() as *const gl::types::GLint;
+                unsafe {
+                    match (uniform.base, uniform.columns, uniform.rows) {
+                        (native::UniformBase::Float, 1, 1) => gl.Uniform1fv(location, count, floats),
+                        (native::UniformBase::Float, 1, 2) => gl.Uniform2fv(location, count, floats),
+                        (native::UniformBase::Float, 1, 3) => gl.Uniform3fv(location, count, floats),
+                        (native::UniformBase::Float, 1, 4) => gl.Uniform4fv(location, count, floats),
+                        (native::UniformBase::Int, 1, 1) => gl.Uniform1iv(location, count, ints),
+                        (native::UniformBase::Int, 1, 2) => gl.Uniform2iv(location, count, ints),
+                        (native::UniformBase::Int, 1, 3) => gl.Uniform3iv(location, count, ints),
+                        (native::UniformBase::Int, 1, 4) => gl.Uniform4iv(location, count, ints),
+                        (native::UniformBase::Uint, 1, 1) => gl.Uniform1uiv(location, count, data.as_ptr()),
+                        (native::UniformBase::Uint, 1, 2) => gl.Uniform2uiv(location, count, data.as_ptr()),
+                        (native::UniformBase::Uint, 1, 3) => gl.Uniform3uiv(location, count, data.as_ptr()),
+                        (native::UniformBase::Uint, 1, 4) => gl.Uniform4uiv(location, count, data.as_ptr()),
+                        (native::UniformBase::Float, 2, 2) => gl.UniformMatrix2fv(location, count, gl::FALSE, floats),
+                        (native::UniformBase::Float, 3, 3) => gl.UniformMatrix3fv(location, count, gl::FALSE, floats),
+                        (native::UniformBase::Float, 4, 4) => gl.UniformMatrix4fv(location, count, gl::FALSE, floats),
+                        (native::UniformBase::Float, 2, 3) => gl.UniformMatrix2x3fv(location, count, gl::FALSE, floats),
+                        (native::UniformBase::Float, 2, 4) => gl.UniformMatrix2x4fv(location, count, gl::FALSE, floats),
+                        (native::UniformBase::Float, 3, 2) => gl.UniformMatrix3x2fv(location, count, gl::FALSE, floats),
+                        (native::UniformBase::Float, 3, 4) => gl.UniformMatrix3x4fv(location, count, gl::FALSE, floats),
+                        (native::UniformBase::Float, 4, 2) => gl.UniformMatrix4x2fv(location, count, gl::FALSE, floats),
+                        (native::UniformBase::Float, 4, 3) => gl.UniformMatrix4x3fv(location, count, gl::FALSE, floats),
+                        _ => error!("Unsupported push constant uniform {:?}", uniform),
+                    }
+                }
             }
             /*
             com::Command::BindConstantBuffer(pso::ConstantBufferParam(buffer, _, slot)) => unsafe {
@@ -693,7 +766,13 @@ impl hal::queue::RawCommandQueue<Backend> for CommandQueue {
         IC::Item: Borrow<com::RawCommandBuffer>,
     {
         use pool::BufferMemory;
+        // Timeline semaphores are emulated on the host, and commands are executed
+        // in order on the context, so only waits for host signals can actually block here.
+        for &(semaphore, value, _) in submit_info.wait_timeline_semaphores {
+            semaphore.wait(value, !0);
+        }
         {
+            let mut statistics = FrameStatistics::default();
             for buf in submit_info.cmd_buffers {
                 let cb = buf.borrow();
                 let memory = cb
@@ -714,13 +793,23 @@ impl hal::queue::RawCommandQueue<Backend> for CommandQueue {
                 for com in commands {
                     self.process(com, &buffer.data);
                 }
+                statistics.add(&cb.statistics);
             }
+            self.share.statistics.add(&statistics);
         }
         fence.map(|fence| self.signal_fence(fence));
+        if !submit_info.signal_timeline_semaphores.is_empty() {
+            self.share.context.Finish();
+            for &(semaphore, value) in submit_info.signal_timeline_semaphores {
+                semaphore.signal(value);
+            }
+        }
     }
 
     #[cfg(feature = "glutin")]
-    fn present<IS, S, IW>(&mut self, swapchains: IS, _wait_semaphores: IW) -> Result<(), ()>
+    fn present<IS, S, IW>(
+        &mut self, swapchains: IS, _wait_semaphores: IW
+    ) -> Result<Option<hal::window::Suboptimal>, hal::window::PresentError>
     where
         IS: IntoIterator<Item = (S, hal::SwapImageIndex)>,
         S: Borrow<window::glutin::Swapchain>,
@@ -729,15 +818,24 @@ impl hal::queue::RawCommandQueue<Backend> for CommandQueue {
     {
         use glutin::GlContext;
 
+        let mut suboptimal = None;
         for swapchain in swapchains {
-            swapchain.0
-                .borrow()
-                .window
-                .swap_buffers()
-                .unwrap();
+            let swapchain = swapchain.0.borrow();
+            match swapchain.window.swap_buffers() {
+                Ok(()) => {}
+                Err(::glutin::ContextError::ContextLost) => {
+                    return Err(hal::window::PresentError::DeviceLost);
+                }
+                Err(err) => panic!("Failed to present frame: {:?}", err),
+            }
+            suboptimal = suboptimal.or(swapchain.suboptimal());
         }
 
-        Ok(())
+        Ok(suboptimal)
+    }
+
+    unsafe fn bind_sparse(&mut self, _info: hal::sparse::SparseBindInfo<Backend>, _fence: Option<&native::Fence>) {
+        unimplemented!()
     }
 
     fn wait_idle(&self) -> Result<(), error::HostExecutionError> {
@@ -1,8 +1,10 @@
 #![allow(dead_code)] //TODO: remove
 
 use hal::{ColorSlot};
+use hal::backend::FastHashMap;
 use hal::pso;
 use gl;
+use gl::types::{GLenum, GLintptr, GLsizeiptr, GLuint};
 use smallvec::SmallVec;
 
 pub fn bind_polygon_mode(gl: &gl::Gl, mode: pso::PolygonMode, bias: Option<pso::DepthBias>) {
@@ -55,6 +57,8 @@ pub fn bind_rasterizer(gl: &gl::Gl, r: &pso::Rasterizer, is_embedded: bool) {
         }
     }
 
+    set_program_point_size(gl, r.program_point_size, is_embedded);
+
     if !is_embedded {
         bind_polygon_mode(gl, r.polygon_mode, r.depth_bias);
         match false { //TODO
@@ -64,6 +68,31 @@ pub fn bind_rasterizer(gl: &gl::Gl, r: &pso::Rasterizer, is_embedded: bool) {
     }
 }
 
+pub fn set_framebuffer_srgb(gl: &gl::Gl, enable: bool) {
+    unsafe {
+        if enable {
+            gl.Enable(gl::FRAMEBUFFER_SRGB);
+        } else {
+            gl.Disable(gl::FRAMEBUFFER_SRGB);
+        }
+    }
+}
+
+pub fn set_program_point_size(gl: &gl::Gl, enable: bool, is_embedded: bool) {
+    // GLES always takes the point size from `gl_PointSize`.
+    if is_embedded {
+        return;
+    }
+    unsafe {
+        if enable {
+            gl.Enable(gl::PROGRAM_POINT_SIZE);
+        } else {
+            gl.Disable(gl::PROGRAM_POINT_SIZE);
+            gl.PointSize(1.0);
+        }
+    }
+}
+
 pub fn bind_draw_color_buffers(gl: &gl::Gl, num: usize) {
     let attachments: SmallVec<[gl::types::GLenum; 16]> =
         (0..num).map(|x| gl::COLOR_ATTACHMENT0 + x as u32).collect();
@@ -243,3 +272,150 @@ pub fn set_blend_color(gl: &gl::Gl, color: pso::ColorValue) {
         gl.BlendColor(color[0], color[1], color[2], color[3])
     };
 }
+
+fn slot_mut<T: Clone>(slots: &mut Vec<Option<T>>, index: usize) -> &mut Option<T> {
+    if slots.len() <= index {
+        slots.resize(index + 1, None);
+    }
+    &mut slots[index]
+}
+
+/// Shadow copy of the bindings of the context, used to skip redundant driver calls.
+///
+/// `None` denotes a binding in an unknown state, e.g. after the user accessed the context.
+#[derive(Debug, Default)]
+pub struct BindingCache {
+    program: Option<GLuint>,
+    active_texture: Option<GLuint>,
+    // 2D textures per texture unit.
+    textures: Vec<Option<GLuint>>,
+    // Samplers per texture unit.
+    samplers: Vec<Option<GLuint>>,
+    // Buffer ranges per indexed target and binding.
+    buffer_ranges: FastHashMap<(GLenum, GLuint), (GLuint, GLintptr, GLsizeiptr)>,
+    draw_framebuffer: Option<GLuint>,
+    read_framebuffer: Option<GLuint>,
+    blend_targets: Vec<Option<pso::ColorBlendDesc>>,
+    blend_color: Option<pso::ColorValue>,
+    depth: Option<pso::DepthTest>,
+}
+
+impl BindingCache {
+    /// Forget all bindings, forcing the next binds to hit the driver.
+    pub fn invalidate(&mut self) {
+        *self = BindingCache::default();
+    }
+
+    /// Forget the bindings of a texture about to be deleted, as GL may reuse its name.
+    pub fn forget_texture(&mut self, texture: GLuint) {
+        for slot in self.textures.iter_mut().filter(|slot| **slot == Some(texture)) {
+            *slot = None;
+        }
+    }
+
+    /// Forget the bindings of a sampler about to be deleted.
+    pub fn forget_sampler(&mut self, sampler: GLuint) {
+        for slot in self.samplers.iter_mut().filter(|slot| **slot == Some(sampler)) {
+            *slot = None;
+        }
+    }
+
+    /// Forget the bindings of a buffer about to be deleted.
+    pub fn forget_buffer(&mut self, buffer: GLuint) {
+        self.buffer_ranges.retain(|_, range| range.0 != buffer);
+    }
+
+    /// Forget the bindings of a framebuffer about to be deleted.
+    pub fn forget_framebuffer(&mut self, framebuffer: GLuint) {
+        if self.draw_framebuffer == Some(framebuffer) {
+            self.draw_framebuffer = None;
+        }
+        if self.read_framebuffer == Some(framebuffer) {
+            self.read_framebuffer = None;
+        }
+    }
+
+    pub fn bind_program(&mut self, gl: &gl::Gl, program: GLuint) {
+        if self.program != Some(program) {
+            self.program = Some(program);
+            unsafe { gl.UseProgram(program) };
+        }
+    }
+
+    /// Bind a 2D texture to a texture unit, which also becomes the active one.
+    pub fn bind_texture(&mut self, gl: &gl::Gl, unit: GLuint, texture: GLuint) {
+        if self.active_texture != Some(unit) {
+            self.active_texture = Some(unit);
+            unsafe { gl.ActiveTexture(gl::TEXTURE0 + unit) };
+        }
+        let slot = slot_mut(&mut self.textures, unit as usize);
+        if *slot != Some(texture) {
+            *slot = Some(texture);
+            unsafe { gl.BindTexture(gl::TEXTURE_2D, texture) };
+        }
+    }
+
+    pub fn bind_sampler(&mut self, gl: &gl::Gl, unit: GLuint, sampler: GLuint) {
+        let slot = slot_mut(&mut self.samplers, unit as usize);
+        if *slot != Some(sampler) {
+            *slot = Some(sampler);
+            unsafe { gl.BindSampler(unit, sampler) };
+        }
+    }
+
+    pub fn bind_buffer_range(
+        &mut self,
+        gl: &gl::Gl,
+        target: GLenum,
+        index: GLuint,
+        buffer: GLuint,
+        offset: GLintptr,
+        size: GLsizeiptr,
+    ) {
+        let range = (buffer, offset, size);
+        if self.buffer_ranges.insert((target, index), range) != Some(range) {
+            unsafe { gl.BindBufferRange(target, index, buffer, offset, size) };
+        }
+    }
+
+    pub fn bind_framebuffer(&mut self, gl: &gl::Gl, point: GLenum, framebuffer: GLuint) {
+        let (draw, read) = match point {
+            gl::DRAW_FRAMEBUFFER => (true, false),
+            gl::READ_FRAMEBUFFER => (false, true),
+            _ => (true, true),
+        };
+        if (draw && self.draw_framebuffer != Some(framebuffer)) ||
+            (read && self.read_framebuffer != Some(framebuffer))
+        {
+            if draw {
+                self.draw_framebuffer = Some(framebuffer);
+            }
+            if read {
+                self.read_framebuffer = Some(framebuffer);
+            }
+            unsafe { gl.BindFramebuffer(point, framebuffer) };
+        }
+    }
+
+    pub fn bind_blend_slot(&mut self, gl: &gl::Gl, slot: ColorSlot, desc: &pso::ColorBlendDesc) {
+        let cached = slot_mut(&mut self.blend_targets, slot as usize);
+        if *cached != Some(*desc) {
+            *cached = Some(*desc);
+            bind_blend_slot(gl, slot, desc);
+        }
+    }
+
+    pub fn set_blend_color(&mut self, gl: &gl::Gl, color: pso::ColorValue) {
+        if self.blend_color != Some(color) {
+            self.blend_color = Some(color);
+            set_blend_color(gl, color);
+        }
+    }
+
+    pub fn bind_depth(&mut self, gl: &gl::Gl, depth: &pso::DepthTest) {
+        if self.depth != Some(*depth) {
+            self.depth = Some(*depth);
+            bind_depth(gl, depth);
+        }
+    }
+}
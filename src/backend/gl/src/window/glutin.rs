@@ -62,12 +62,24 @@ fn get_window_extent(window: &glutin::GlWindow) -> image::Extent {
 pub struct Swapchain {
     // Underlying window, required for presentation
     pub(crate) window: Starc<glutin::GlWindow>,
+    // Extent of the default framebuffer
+    pub(crate) extent: hal::window::Extent2D,
+}
+
+impl Swapchain {
+    // The default framebuffer is stretched onto the window if it got resized.
+    pub(crate) fn suboptimal(&self) -> Option<hal::window::Suboptimal> {
+        let extent = hal::window::Extent2D::from(get_window_extent(&self.window));
+        hal::window::Suboptimal::from_extent(extent, self.extent)
+    }
 }
 
 impl hal::Swapchain<B> for Swapchain {
-    fn acquire_image(&mut self, _sync: hal::FrameSync<B>) -> Result<hal::SwapImageIndex, ()> {
+    fn acquire_image(
+        &mut self, _sync: hal::FrameSync<B>
+    ) -> Result<(hal::SwapImageIndex, Option<hal::window::Suboptimal>), hal::window::AcquireError> {
         // TODO: sync
-        Ok(0)
+        Ok((0, self.suboptimal()))
     }
 }
 
@@ -124,13 +136,12 @@ impl hal::Surface<B> for Surface {
         hal::image::Kind::D2(ex.width, ex.height, 1, samples as _)
     }
 
-    fn compatibility(
-        &self, _: &PhysicalDevice
-    ) -> (hal::SurfaceCapabilities, Option<Vec<f::Format>>, Vec<hal::PresentMode>) {
+    fn capabilities(&self, _: &PhysicalDevice) -> hal::SurfaceCapabilities {
         let ex = get_window_extent(&self.window);
         let extent = hal::window::Extent2D::from(ex);
+        let present_modes = vec![hal::PresentMode::Fifo]; //TODO
 
-        let caps = hal::SurfaceCapabilities {
+        hal::SurfaceCapabilities {
             image_count: if self.window.get_pixel_format().double_buffer { 2..3 } else { 1..2 },
             current_extent: Some(extent),
             extents: extent .. hal::window::Extent2D {
@@ -138,10 +149,11 @@ impl hal::Surface<B> for Surface {
                 height: ex.height + 1,
             },
             max_image_layers: 1,
-        };
-        let present_modes = vec![hal::PresentMode::Fifo]; //TODO
-
-        (caps, Some(self.swapchain_formats()), present_modes)
+            current_transform: hal::SurfaceTransform::IDENTITY,
+            supported_transforms: hal::SurfaceTransform::IDENTITY,
+            formats: Some(self.swapchain_formats()),
+            present_modes,
+        }
     }
 
     fn supports_queue_family(&self, _: &QueueFamily) -> bool { true }
@@ -152,9 +164,16 @@ impl Device {
         &self,
         surface: &mut Surface,
         _config: hal::SwapchainConfig,
+        extent: &hal::window::Extent2D,
     ) -> (Swapchain, hal::Backbuffer<B>) {
+        // Resize the default framebuffer along with the swapchain.
+        surface.window.resize(glutin::dpi::PhysicalSize::new(
+            extent.width as f64,
+            extent.height as f64,
+        ));
         let swapchain = Swapchain {
             window: surface.window.clone(),
+            extent: *extent,
         };
         let backbuffer = hal::Backbuffer::Framebuffer(0);
         (swapchain, backbuffer)
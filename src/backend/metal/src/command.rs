@@ -1,9 +1,10 @@
 use {
     AutoreleasePool, Backend, PrivateDisabilities, Shared, validate_line_width,
-    BufferPtr, TexturePtr, SamplerPtr,
+    BufferPtr, FencePtr, ResourcePtr, TexturePtr, SamplerPtr,
 };
-use {conversions as conv, native, soft, window};
+use {conversions as conv, device, native, soft, window};
 use internal::{BlitVertex, Channel, ClearKey, ClearVertex, ServicePipes};
+use soft::ResourceUsage;
 
 use std::borrow::Borrow;
 use std::cell::RefCell;
@@ -17,7 +18,8 @@ use hal::{DrawCount, SwapImageIndex, VertexCount, VertexOffset, InstanceCount, I
 use hal::backend::FastHashMap;
 use hal::format::{Aspects, Format, FormatDesc};
 use hal::image::{Extent, Filter, Layout, Level, SubresourceRange};
-use hal::pass::{AttachmentLoadOp, AttachmentOps};
+use hal::pass::{self, AttachmentLoadOp, AttachmentOps};
+use hal::profiler::FrameStatistics;
 use hal::query::{Query, QueryControl, QueryId};
 use hal::queue::{RawCommandQueue, RawSubmission};
 use hal::range::RangeArg;
@@ -35,6 +37,9 @@ const WORD_ALIGNMENT: u64 = WORD_SIZE as _;
 /// with clear operations set up to implement our `clear_image`
 /// Note: currently doesn't work, needs a repro case for Apple
 const CLEAR_IMAGE_ARRAY: bool = false;
+/// `MTLRenderStages` values, not exposed by `metal-rs` yet.
+const RENDER_STAGE_VERTEX: NSUInteger = 1 << 0;
+const RENDER_STAGE_FRAGMENT: NSUInteger = 1 << 1;
 
 pub struct QueueInner {
     raw: metal::CommandQueue,
@@ -159,6 +164,10 @@ struct State {
     push_constants: Vec<u32>,
     vertex_buffers: Vec<Option<(BufferPtr, u64)>>,
     framebuffer_inner: native::FramebufferInner,
+    /// Resources referenced by the bound argument buffers, which need to be made resident
+    /// in every render or compute encoder respectively.
+    residency_graphics: FastHashMap<ResourcePtr, ResourceUsage>,
+    residency_compute: FastHashMap<ResourcePtr, ResourceUsage>,
 }
 
 impl State {
@@ -168,6 +177,8 @@ impl State {
         self.resources_cs.clear();
         self.push_constants.clear();
         self.vertex_buffers.clear();
+        self.residency_graphics.clear();
+        self.residency_compute.clear();
     }
 
     fn clamp_scissor(sr: MTLScissorRect, extent: Extent) -> MTLScissorRect {
@@ -258,6 +269,10 @@ impl State {
                     .chain(com_push_constants)
             });
 
+        let com_residency = self.residency_graphics
+            .iter()
+            .map(|(&resource, &usage)| soft::RenderCommand::UseResource { resource, usage });
+
         com_vp
             .into_iter()
             .chain(com_scissor)
@@ -266,6 +281,7 @@ impl State {
             .chain(com_pso)
             //.chain(com_ds) // done outside
             .chain(com_resources)
+            .chain(com_residency)
     }
 
     fn make_compute_commands<'a>(&'a self) -> impl Iterator<Item = soft::ComputeCommand<&'a soft::Own>> {
@@ -312,12 +328,17 @@ impl State {
                 words: self.push_constants.as_slice(),
             });
 
+        let com_residency = self.residency_compute
+            .iter()
+            .map(|(&resource, &usage)| soft::ComputeCommand::UseResource { resource, usage });
+
         com_pso
             .into_iter()
             .chain(com_buffers)
             .chain(com_textures)
             .chain(com_samplers)
             .chain(com_push_constants)
+            .chain(com_residency)
     }
 
     fn set_vertex_buffers(&mut self) -> u64 {
@@ -578,16 +599,50 @@ impl StageResources {
     }
 }
 
+/// Synchronization of encoders across pipeline barriers.
+///
+/// Metal doesn't track hazards of resources allocated from heaps. Every encoder updates
+/// the fence once its work is done, and the first encoder started after a barrier waits
+/// for all the updates encoded before.
+#[derive(Clone, Copy, Debug)]
+struct HazardFence {
+    raw: FencePtr,
+    wait: bool,
+}
+
+impl HazardFence {
+    /// Return the fences to wait for and to update in a new encoder.
+    fn start(fence: &mut Option<Self>) -> (Option<FencePtr>, Option<FencePtr>) {
+        match *fence {
+            Some(ref mut fence) => {
+                let wait = if mem::replace(&mut fence.wait, false) {
+                    Some(fence.raw)
+                } else {
+                    None
+                };
+                (wait, Some(fence.raw))
+            }
+            None => (None, None),
+        }
+    }
+
+    fn is_pending(fence: &Option<Self>) -> bool {
+        fence.map_or(false, |fence| fence.wait)
+    }
+}
+
 #[derive(Debug)]
 enum CommandSink {
     Immediate {
         cmd_buffer: metal::CommandBuffer,
         token: Token,
         encoder_state: EncoderState,
+        fence: Option<HazardFence>,
     },
     Deferred {
         passes: Vec<soft::Pass>,
         is_encoding: bool,
+        fence: Option<HazardFence>,
     },
 }
 
@@ -647,7 +702,7 @@ impl CommandSink {
             CommandSink::Immediate { encoder_state: EncoderState::Render(ref encoder), .. } => {
                 PreRender::Immediate(encoder)
             }
-            CommandSink::Deferred { ref mut passes, is_encoding: true } => {
+            CommandSink::Deferred { ref mut passes, is_encoding: true, .. } => {
                 match passes.last_mut() {
                     Some(&mut soft::Pass::Render { commands: ref mut list, .. }) => PreRender::Deferred(list),
                     _ => PreRender::Void,
@@ -673,7 +728,7 @@ impl CommandSink {
                     _ => panic!("Expected to be in render encoding state!")
                 }
             }
-            CommandSink::Deferred { ref mut passes, is_encoding } => {
+            CommandSink::Deferred { ref mut passes, is_encoding, .. } => {
                 assert!(is_encoding);
                 match passes.last_mut() {
                     Some(&mut soft::Pass::Render { commands: ref mut list, .. }) => {
@@ -692,22 +747,39 @@ impl CommandSink {
         I: Iterator<Item = soft::BlitCommand<&'a soft::Own>>,
     {
         match *self {
-            CommandSink::Immediate { ref cmd_buffer, ref mut encoder_state, .. } => {
+            CommandSink::Immediate { ref cmd_buffer, ref mut encoder_state, ref mut fence, .. } => {
+                let barrier = HazardFence::is_pending(fence);
                 let current = match mem::replace(encoder_state, EncoderState::None) {
                     EncoderState::None => None,
                     EncoderState::Render(enc) => {
                         enc.end_encoding();
                         None
                     },
+                    EncoderState::Blit(ref enc) if barrier => {
+                        enc.end_encoding();
+                        None
+                    },
                     EncoderState::Blit(enc) => Some(enc),
                     EncoderState::Compute(enc) => {
                         enc.end_encoding();
                         None
                     },
                 };
-                let encoder = current.unwrap_or_else(|| {
-                    cmd_buffer.new_blit_command_encoder().to_owned()
-                });
+                let encoder = match current {
+                    Some(encoder) => encoder,
+                    None => {
+                        let encoder = cmd_buffer.new_blit_command_encoder().to_owned();
+                        let (wait, update) = HazardFence::start(fence);
+                        let fence_commands = wait
+                            .map(soft::BlitCommand::WaitForFence)
+                            .into_iter()
+                            .chain(update.map(soft::BlitCommand::UpdateFence));
+                        for command in fence_commands {
+                            exec_blit(&encoder, command);
+                        }
+                        encoder
+                    }
+                };
 
                 for command in commands {
                     exec_blit(&encoder, command);
@@ -715,13 +787,20 @@ impl CommandSink {
 
                 *encoder_state = EncoderState::Blit(encoder);
             }
-            CommandSink::Deferred { ref mut passes, .. } => {
+            CommandSink::Deferred { ref mut passes, ref mut fence, .. } => {
                 let owned_commands = commands.into_iter().map(soft::BlitCommand::own);
-                if let Some(&mut soft::Pass::Blit(ref mut list)) = passes.last_mut() {
-                    list.extend(owned_commands);
-                    return;
+                if !HazardFence::is_pending(fence) {
+                    if let Some(&mut soft::Pass::Blit(ref mut list)) = passes.last_mut() {
+                        list.extend(owned_commands);
+                        return;
+                    }
                 }
-                passes.push(soft::Pass::Blit(owned_commands.collect()));
+                let (wait, update) = HazardFence::start(fence);
+                let fence_commands = wait
+                    .map(soft::BlitCommand::WaitForFence)
+                    .into_iter()
+                    .chain(update.map(soft::BlitCommand::UpdateFence));
+                passes.push(soft::Pass::Blit(fence_commands.chain(owned_commands).collect()));
             }
         }
     }
@@ -733,7 +812,7 @@ impl CommandSink {
             CommandSink::Immediate { encoder_state: EncoderState::Compute(ref encoder), .. } => {
                 PreCompute::Immediate(encoder)
             }
-            CommandSink::Deferred { ref mut passes, is_encoding: true } => {
+            CommandSink::Deferred { ref mut passes, is_encoding: true, .. } => {
                 match passes.last_mut() {
                     Some(&mut soft::Pass::Compute(ref mut list)) => PreCompute::Deferred(list),
                     _ => PreCompute::Void,
@@ -781,6 +860,17 @@ impl CommandSink {
         }
     }
 
+    /// Make the next encoder wait for the work of all the previous ones.
+    fn barrier(&mut self) {
+        match *self {
+            CommandSink::Immediate { fence: Some(ref mut fence), .. } |
+            CommandSink::Deferred { fence: Some(ref mut fence), .. } => {
+                fence.wait = true;
+            }
+            _ => {}
+        }
+    }
+
     fn begin_render_pass<'a, I>(
         &mut self,
         door: PassDoor,
@@ -793,9 +883,14 @@ impl CommandSink {
         self.stop_encoding();
 
         match *self {
-            CommandSink::Immediate { ref cmd_buffer, ref mut encoder_state, .. } => {
+            CommandSink::Immediate { ref cmd_buffer, ref mut encoder_state, ref mut fence, .. } => {
                 let encoder = cmd_buffer.new_render_command_encoder(descriptor);
-                for command in init_commands {
+                let (wait, update) = HazardFence::start(fence);
+                let fence_commands = wait
+                    .map(soft::RenderCommand::WaitForFence)
+                    .into_iter()
+                    .chain(update.map(soft::RenderCommand::UpdateFence));
+                for command in fence_commands.chain(init_commands) {
                     exec_render(encoder, command);
                 }
                 match door {
@@ -808,11 +903,16 @@ impl CommandSink {
                     }
                 }
             }
-            CommandSink::Deferred { ref mut passes, ref mut is_encoding } => {
+            CommandSink::Deferred { ref mut passes, ref mut is_encoding, ref mut fence } => {
                 *is_encoding = match door {
                     PassDoor::Open => true,
                     PassDoor::Closed {..} => false,
                 };
+                let (wait, update) = HazardFence::start(fence);
+                let fence_commands = wait
+                    .map(soft::RenderCommand::WaitForFence)
+                    .into_iter()
+                    .chain(update.map(soft::RenderCommand::UpdateFence));
                 passes.push(soft::Pass::Render {
                     //Note: the original descriptor belongs to the framebuffer,
                     // and will me mutated afterwards.
@@ -821,7 +921,9 @@ impl CommandSink {
                         msg_send![desc.as_ptr(), retain];
                         desc
                     },
-                    commands: init_commands.map(soft::RenderCommand::own).collect(),
+                    commands: fence_commands
+                        .chain(init_commands.map(soft::RenderCommand::own))
+                        .collect(),
                 });
             }
         }
@@ -837,10 +939,15 @@ impl CommandSink {
         self.stop_encoding();
 
         match *self {
-            CommandSink::Immediate { ref cmd_buffer, ref mut encoder_state, .. } => {
+            CommandSink::Immediate { ref cmd_buffer, ref mut encoder_state, ref mut fence, .. } => {
                 let _ap = AutoreleasePool::new();
                 let encoder = cmd_buffer.new_compute_command_encoder();
-                for command in init_commands {
+                let (wait, update) = HazardFence::start(fence);
+                let fence_commands = wait
+                    .map(soft::ComputeCommand::WaitForFence)
+                    .into_iter()
+                    .chain(update.map(soft::ComputeCommand::UpdateFence));
+                for command in fence_commands.chain(init_commands) {
                     exec_compute(encoder, command);
                 }
                 match door {
@@ -853,13 +960,20 @@ impl CommandSink {
                     }
                 }
             }
-            CommandSink::Deferred { ref mut passes, ref mut is_encoding } => {
+            CommandSink::Deferred { ref mut passes, ref mut is_encoding, ref mut fence } => {
                 *is_encoding = match door {
                     PassDoor::Open => true,
                     PassDoor::Closed {..} => false,
                 };
+                let (wait, update) = HazardFence::start(fence);
+                let fence_commands = wait
+                    .map(soft::ComputeCommand::WaitForFence)
+                    .into_iter()
+                    .chain(update.map(soft::ComputeCommand::UpdateFence));
                 passes.push(soft::Pass::Compute(
-                    init_commands.map(soft::ComputeCommand::own).collect(),
+                    fence_commands
+                        .chain(init_commands.map(soft::ComputeCommand::own))
+                        .collect(),
                 ));
             }
         }
@@ -877,6 +991,7 @@ pub struct CommandBufferInner {
     sink: Option<CommandSink>,
     retained_buffers: Vec<metal::Buffer>,
     retained_textures: Vec<metal::Texture>,
+    statistics: FrameStatistics,
 }
 
 impl Drop for CommandBufferInner {
@@ -898,6 +1013,7 @@ impl CommandBufferInner {
         }
         self.retained_buffers.clear();
         self.retained_textures.clear();
+        self.statistics = FrameStatistics::default();
     }
 
     fn sink(&mut self) -> &mut CommandSink {
@@ -1091,6 +1207,15 @@ fn exec_render<'a>(encoder: &metal::RenderCommandEncoderRef, command: soft::Rend
                 offset,
             );
         }
+        Cmd::UseResource { resource, usage } => unsafe {
+            msg_send![encoder, useResource: resource.as_ptr() usage: usage.bits()];
+        }
+        Cmd::UpdateFence(fence) => unsafe {
+            msg_send![encoder, updateFence: fence.as_ptr() afterStages: RENDER_STAGE_FRAGMENT];
+        }
+        Cmd::WaitForFence(fence) => unsafe {
+            msg_send![encoder, waitForFence: fence.as_ptr() beforeStages: RENDER_STAGE_VERTEX];
+        }
     }
 }
 
@@ -1169,6 +1294,12 @@ fn exec_blit<'a>(encoder: &metal::BlitCommandEncoderRef, command: soft::BlitComm
                 );
             }
         }
+        Cmd::UpdateFence(fence) => unsafe {
+            msg_send![encoder, updateFence: fence.as_ptr()];
+        }
+        Cmd::WaitForFence(fence) => unsafe {
+            msg_send![encoder, waitForFence: fence.as_ptr()];
+        }
     }
 }
 
@@ -1199,6 +1330,15 @@ fn exec_compute<'a>(encoder: &metal::ComputeCommandEncoderRef, command: soft::Co
         Cmd::DispatchIndirect { wg_size, buffer, offset } => {
             encoder.dispatch_thread_groups_indirect(buffer.as_native(), offset, wg_size);
         }
+        Cmd::UseResource { resource, usage } => unsafe {
+            msg_send![encoder, useResource: resource.as_ptr() usage: usage.bits()];
+        }
+        Cmd::UpdateFence(fence) => unsafe {
+            msg_send![encoder, updateFence: fence.as_ptr()];
+        }
+        Cmd::WaitForFence(fence) => unsafe {
+            msg_send![encoder, waitForFence: fence.as_ptr()];
+        }
     }
 }
 
@@ -1274,6 +1414,10 @@ impl RawCommandQueue<Backend> for CommandQueue {
         debug!("submitting with fence {:?}", fence);
 
         self.wait(submit.wait_semaphores.iter().map(|&(s, _)| s));
+        // Timeline semaphores are emulated, so their waits block the submitting thread.
+        for &(semaphore, value, _) in submit.wait_timeline_semaphores {
+            semaphore.wait(value, !0);
+        }
 
         let system_semaphores = submit.signal_semaphores
             .into_iter()
@@ -1301,7 +1445,9 @@ impl RawCommandQueue<Backend> for CommandQueue {
                 ref sink,
                 ref mut retained_buffers,
                 ref mut retained_textures,
+                ref statistics,
             } = *inner;
+            self.shared.statistics.add(statistics);
 
             let temp_cmd_buffer;
             let command_buffer: &metal::CommandBufferRef = match *sink {
@@ -1350,9 +1496,27 @@ impl RawCommandQueue<Backend> for CommandQueue {
             msg_send![command_buffer, addCompletedHandler: fence_block.deref() as *const _];
             command_buffer.commit();
         }
+
+        if !submit.signal_timeline_semaphores.is_empty() {
+            let command_buffer = queue.spawn_temp();
+            command_buffer.set_label("timeline");
+            let timelines = submit.signal_timeline_semaphores
+                .iter()
+                .map(|&(semaphore, value)| (Arc::clone(semaphore), value))
+                .collect::<Vec<_>>();
+            let timeline_block = ConcreteBlock::new(move |_cb: *mut ()| -> () {
+                for &(ref timeline, value) in &timelines {
+                    timeline.signal(value);
+                }
+            }).copy();
+            msg_send![command_buffer, addCompletedHandler: timeline_block.deref() as *const _];
+            command_buffer.commit();
+        }
     }
 
-    fn present<IS, S, IW>(&mut self, swapchains: IS, wait_semaphores: IW) -> Result<(), ()>
+    fn present<IS, S, IW>(
+        &mut self, swapchains: IS, wait_semaphores: IW
+    ) -> Result<Option<hal::window::Suboptimal>, hal::window::PresentError>
     where
         IS: IntoIterator<Item = (S, SwapImageIndex)>,
         S: Borrow<window::Swapchain>,
@@ -1365,15 +1529,22 @@ impl RawCommandQueue<Backend> for CommandQueue {
         let command_buffer = queue.raw.new_command_buffer();
         command_buffer.set_label("present");
 
+        let mut suboptimal = None;
         for (swapchain, index) in swapchains {
             debug!("presenting frame {}", index);
-            let drawable = swapchain.borrow().take_drawable(index);
+            let swapchain = swapchain.borrow();
+            let drawable = swapchain.take_drawable(index);
             command_buffer.present_drawable(&drawable);
+            suboptimal = suboptimal.or(swapchain.suboptimal());
         }
 
         command_buffer.commit();
 
-        Ok(())
+        Ok(suboptimal)
+    }
+
+    unsafe fn bind_sparse(&mut self, _info: hal::sparse::SparseBindInfo<Backend>, _fence: Option<&native::Fence>) {
+        unimplemented!()
     }
 
     fn wait_idle(&self) -> Result<(), error::HostExecutionError> {
@@ -1404,6 +1575,7 @@ impl pool::RawCommandPool<Backend> for CommandPool {
                 sink: None,
                 retained_buffers: Vec::new(),
                 retained_textures: Vec::new(),
+                statistics: FrameStatistics::default(),
             })),
             shared: self.shared.clone(),
             state: State {
@@ -1435,7 +1607,9 @@ impl pool::RawCommandPool<Backend> for CommandPool {
                     aspects: Aspects::empty(),
                     colors: SmallVec::new(),
                     depth_stencil: None,
-                }
+                },
+                residency_graphics: FastHashMap::default(),
+                residency_compute: FastHashMap::default(),
             },
             temp: Temp {
                 clear_vertices: Vec::new(),
@@ -1493,20 +1667,37 @@ impl CommandBuffer {
 }
 
 impl com::RawCommandBuffer<Backend> for CommandBuffer {
-    fn begin(&mut self, flags: com::CommandBufferFlags, _info: com::CommandBufferInheritanceInfo<Backend>) {
+    fn begin(&mut self, flags: com::CommandBufferFlags, info: com::CommandBufferInheritanceInfo<Backend>) {
         self.reset(false);
-        //TODO: Implement secondary command buffers
-        let sink = if flags.contains(com::CommandBufferFlags::ONE_TIME_SUBMIT) {
+        let fence = self.shared.hazard_fence.map(|raw| HazardFence { raw, wait: false });
+        let sink = if info.subpass.is_some() {
+            // Secondary command buffers continuing a render pass only record render
+            // commands, which are replayed in the pass of the executing command buffer.
+            if let Some(framebuffer) = info.framebuffer {
+                self.state.framebuffer_inner = framebuffer.inner.clone();
+            }
+            self.state.render_pso_is_compatible = false;
+            CommandSink::Deferred {
+                passes: vec![soft::Pass::Render {
+                    desc: metal::RenderPassDescriptor::new().to_owned(),
+                    commands: Vec::new(),
+                }],
+                is_encoding: true,
+                fence: None,
+            }
+        } else if flags.contains(com::CommandBufferFlags::ONE_TIME_SUBMIT) {
             let (cmd_buffer, token) = self.shared.queue.lock().unwrap().spawn();
             CommandSink::Immediate {
                 cmd_buffer,
                 token,
                 encoder_state: EncoderState::None,
+                fence,
             }
         } else {
             CommandSink::Deferred {
                 passes: Vec::new(),
                 is_encoding: false,
+                fence,
             }
         };
 
@@ -1538,6 +1729,9 @@ impl com::RawCommandBuffer<Backend> for CommandBuffer {
         T::Item: Borrow<memory::Barrier<'a, Backend>>,
     {
         // TODO: MTLRenderCommandEncoder.textureBarrier on macOS?
+        let mut inner = self.inner.borrow_mut();
+        inner.statistics.barriers += 1;
+        inner.sink().barrier();
     }
 
     fn fill_buffer<R>(
@@ -2243,7 +2437,8 @@ impl com::RawCommandBuffer<Backend> for CommandBuffer {
 
     fn bind_index_buffer(&mut self, view: buffer::IndexBufferView<Backend>) {
         let buffer = view.buffer.raw.clone();
-        let offset = view.offset;
+        // The buffer may be a sub-range of a larger Metal buffer.
+        let offset = view.buffer.range.start + view.offset;
         let index_type = conv::map_index_type(view.index_type);
         self.state.index_buffer = Some(IndexBuffer {
             buffer: BufferPtr(buffer.as_ptr()),
@@ -2488,6 +2683,28 @@ impl com::RawCommandBuffer<Backend> for CommandBuffer {
             .begin_render_pass(PassDoor::Open, &*descriptor, init_commands);
     }
 
+    fn begin_render_pass_imageless<T, I>(
+        &mut self,
+        render_pass: &native::RenderPass,
+        framebuffer: &native::Framebuffer,
+        attachments: I,
+        render_area: pso::Rect,
+        clear_values: T,
+        first_subpass: com::SubpassContents,
+    ) where
+        T: IntoIterator,
+        T::Item: Borrow<com::ClearValueRaw>,
+        I: IntoIterator,
+        I::Item: Borrow<native::ImageView>,
+    {
+        let framebuffer = device::create_framebuffer_raw(
+            render_pass,
+            attachments,
+            framebuffer.inner.extent,
+        );
+        self.begin_render_pass(render_pass, &framebuffer, render_area, clear_values, first_subpass);
+    }
+
     fn next_subpass(&mut self, _contents: com::SubpassContents) {
         unimplemented!()
     }
@@ -2499,7 +2716,49 @@ impl com::RawCommandBuffer<Backend> for CommandBuffer {
             .stop_encoding();
     }
 
+    fn begin_rendering(&mut self, info: &com::RenderingInfo<Backend>) {
+        // Metal render passes are described by the attachments themselves,
+        // so a transient framebuffer is built from the views.
+        let attachments = info.colors.iter().chain(info.depth_stencil.iter());
+        let render_pass = native::RenderPass {
+            attachments: attachments
+                .clone()
+                .map(|att| pass::Attachment {
+                    format: Some(att.view.format),
+                    samples: att.view.samples,
+                    ops: att.ops,
+                    stencil_ops: att.stencil_ops,
+                    layouts: att.layout .. att.layout,
+                })
+                .collect(),
+        };
+        let area = info.render_area;
+        let extent = Extent {
+            width: (area.x + area.w) as _,
+            height: (area.y + area.h) as _,
+            depth: 1,
+        };
+        let framebuffer = device::create_framebuffer_raw(
+            &render_pass,
+            attachments.clone().map(|att| att.view),
+            extent,
+        );
+
+        self.begin_render_pass(
+            &render_pass,
+            &framebuffer,
+            area,
+            attachments.map(|att| att.clear_value),
+            com::SubpassContents::Inline,
+        );
+    }
+
+    fn end_rendering(&mut self) {
+        self.end_render_pass();
+    }
+
     fn bind_graphics_pipeline(&mut self, pipeline: &native::GraphicsPipeline) {
+        self.inner.borrow_mut().statistics.pipeline_binds += 1;
         let mut set_stencil_references = false;
         if let pso::StencilTest::On { ref front, ref back } = pipeline.depth_stencil_desc.stencil {
             if let pso::State::Static(value) = front.mask_read {
@@ -2690,7 +2949,7 @@ impl com::RawCommandBuffer<Backend> for CommandBuffer {
                         }
                     }
                 }
-                native::DescriptorSet::ArgumentBuffer { ref raw, offset, stage_flags, .. } => {
+                native::DescriptorSet::ArgumentBuffer { ref raw, offset, stage_flags, resources: ref set_resources, .. } => {
                     if stage_flags.contains(pso::ShaderStageFlags::VERTEX) {
                         let loc = msl::ResourceBindingLocation {
                             stage: spirv::ExecutionModel::Vertex,
@@ -2723,12 +2982,22 @@ impl com::RawCommandBuffer<Backend> for CommandBuffer {
                             });
                         }
                     }
+                    for &(resource, usage) in set_resources.lock().unwrap().values() {
+                        let total = self.state.residency_graphics
+                            .entry(resource)
+                            .or_insert(ResourceUsage::empty());
+                        if !total.contains(usage) {
+                            *total |= usage;
+                            pre.issue(soft::RenderCommand::UseResource { resource, usage: *total });
+                        }
+                    }
                 }
             }
         }
     }
 
     fn bind_compute_pipeline(&mut self, pipeline: &native::ComputePipeline) {
+        self.inner.borrow_mut().statistics.pipeline_binds += 1;
         self.state.compute_pso = Some(pipeline.raw.clone());
         self.state.work_group_size = pipeline.work_group_size;
 
@@ -2841,7 +3110,7 @@ impl com::RawCommandBuffer<Backend> for CommandBuffer {
                         }
                     }
                 }
-                native::DescriptorSet::ArgumentBuffer { ref raw, offset, stage_flags, .. } => {
+                native::DescriptorSet::ArgumentBuffer { ref raw, offset, stage_flags, resources: ref set_resources, .. } => {
                     if stage_flags.contains(pso::ShaderStageFlags::COMPUTE) {
                         let index = res_override.buffer_id as usize;
                         let buffer = BufferPtr(raw.as_ptr());
@@ -2852,6 +3121,15 @@ impl com::RawCommandBuffer<Backend> for CommandBuffer {
                                 offset,
                             });
                         }
+                        for &(resource, usage) in set_resources.lock().unwrap().values() {
+                            let total = self.state.residency_compute
+                                .entry(resource)
+                                .or_insert(ResourceUsage::empty());
+                            if !total.contains(usage) {
+                                *total |= usage;
+                                pre.issue(soft::ComputeCommand::UseResource { resource, usage: *total });
+                            }
+                        }
                     }
                 }
             }
@@ -2871,6 +3149,7 @@ impl com::RawCommandBuffer<Backend> for CommandBuffer {
         };
 
         let mut inner = self.inner.borrow_mut();
+        inner.statistics.dispatches += 1;
         let sink = inner.sink();
         //TODO: re-use compute encoders
         sink.begin_compute_pass(PassDoor::Open, init_commands);
@@ -2878,6 +3157,15 @@ impl com::RawCommandBuffer<Backend> for CommandBuffer {
         sink.stop_encoding();
     }
 
+    fn dispatch_base(&mut self, base: WorkGroupCount, count: WorkGroupCount) {
+        // Metal has no notion of a base threadgroup, the dispatch is skipped.
+        if base != [0; 3] {
+            debug_assert!(false, "Non-zero dispatch base requires `Features::DISPATCH_BASE`");
+            return;
+        }
+        <Self as com::RawCommandBuffer<Backend>>::dispatch(self, count);
+    }
+
     fn dispatch_indirect(&mut self, buffer: &native::Buffer, offset: buffer::Offset) {
         let init_commands = self.state.make_compute_commands();
 
@@ -2888,6 +3176,7 @@ impl com::RawCommandBuffer<Backend> for CommandBuffer {
         };
 
         let mut inner = self.inner.borrow_mut();
+        inner.statistics.dispatches += 1;
         let sink = inner.sink();
         //TODO: re-use compute encoders
         sink.begin_compute_pass(PassDoor::Open, init_commands);
@@ -3072,6 +3361,7 @@ impl com::RawCommandBuffer<Backend> for CommandBuffer {
             return
         }
 
+        self.inner.borrow_mut().statistics.draw_calls += 1;
         let command = soft::RenderCommand::Draw {
             primitive_type: self.state.primitive_type,
             vertices,
@@ -3093,6 +3383,7 @@ impl com::RawCommandBuffer<Backend> for CommandBuffer {
             return
         }
 
+        self.inner.borrow_mut().statistics.draw_calls += 1;
         let command = soft::RenderCommand::DrawIndexed {
             primitive_type: self.state.primitive_type,
             index: self.state.index_buffer.expect("must bind index buffer"),
@@ -3116,6 +3407,7 @@ impl com::RawCommandBuffer<Backend> for CommandBuffer {
         assert_eq!(offset % WORD_ALIGNMENT, 0);
         assert_eq!(stride % WORD_ALIGNMENT as u32, 0);
 
+        self.inner.borrow_mut().statistics.draw_calls += 1;
         let commands = (0 .. count)
             .map(|i| soft::RenderCommand::DrawIndirect {
                 primitive_type: self.state.primitive_type,
@@ -3139,6 +3431,7 @@ impl com::RawCommandBuffer<Backend> for CommandBuffer {
         assert_eq!(offset % WORD_ALIGNMENT, 0);
         assert_eq!(stride % WORD_ALIGNMENT as u32, 0);
 
+        self.inner.borrow_mut().statistics.draw_calls += 1;
         let commands = (0 .. count)
             .map(|i| soft::RenderCommand::DrawIndexedIndirect {
                 primitive_type: self.state.primitive_type,
@@ -3226,11 +3519,33 @@ impl com::RawCommandBuffer<Backend> for CommandBuffer {
 
     fn execute_commands<I>(
         &mut self,
-        _buffers: I,
+        buffers: I,
     ) where
         I: IntoIterator,
         I::Item: Borrow<CommandBuffer>
     {
-        unimplemented!()
+        let mut inner = self.inner.borrow_mut();
+        for cmd_buffer in buffers {
+            let secondary = cmd_buffer.borrow().inner.borrow();
+            match secondary.sink {
+                Some(CommandSink::Deferred { ref passes, .. }) => {
+                    for pass in passes {
+                        match *pass {
+                            soft::Pass::Render { ref commands, .. } => {
+                                inner.sink().render_commands(commands.iter().map(soft::RenderCommand::as_ref));
+                            }
+                            _ => panic!("Only render passes can be continued by secondary command buffers"),
+                        }
+                    }
+                }
+                _ => panic!("Secondary command buffer wasn't begun inside a subpass"),
+            }
+            inner.retained_buffers.extend(secondary.retained_buffers.iter().cloned());
+            inner.retained_textures.extend(secondary.retained_textures.iter().cloned());
+            inner.statistics.add(&secondary.statistics);
+        }
+
+        // The state set by the secondary command buffers isn't tracked.
+        self.state.render_pso_is_compatible = false;
     }
 }
@@ -73,6 +73,7 @@ impl PrivateCapabilities {
             f::Bc7Srgb           => BC7_RGBAUnorm_sRGB,
             f::A2r10g10b10Unorm  => BGR10A2Unorm,
             f::A2b10g10r10Unorm  => RGB10A2Unorm,
+            f::E5b9g9r9Ufloat    => RGB9E5Float,
             //f::EacR11Unorm       => EAC_R11Unorm, // !macOS
             //f::EacR11Inorm       => EAC_R11Snorm, // !macOS
             //f::EacR11g11Unorm    => EAC_RG11Unorm, // !macOS
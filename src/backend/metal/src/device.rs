@@ -1,8 +1,9 @@
 use {
     AutoreleasePool, Backend, PrivateCapabilities, QueueFamily,
-    Shared, Surface, Swapchain, validate_line_width, BufferPtr, SamplerPtr, TexturePtr,
+    Shared, Surface, Swapchain, validate_line_width, BufferPtr, ResourcePtr, SamplerPtr, TexturePtr,
 };
 use {conversions as conv, command, native as n};
+use soft::ResourceUsage;
 use native;
 
 use std::borrow::Borrow;
@@ -10,12 +11,14 @@ use std::collections::hash_map::Entry;
 use std::ops::Range;
 use std::path::Path;
 use std::sync::{Arc, Condvar, Mutex, RwLock};
-use std::{cmp, mem, slice, time};
+use std::{cmp, iter, mem, slice, time};
 
 use hal::{self, error, image, pass, format, mapping, memory, buffer, pso, query, window};
 use hal::device::{BindError, OutOfMemory, FramebufferError, ShaderError};
 use hal::memory::Properties;
 use hal::pool::CommandPoolCreateFlags;
+use hal::sync::HostTimeline;
+use hal::profiler::FrameStatistics;
 use hal::queue::{QueueFamilyId, Queues};
 use hal::range::RangeArg;
 
@@ -68,6 +71,95 @@ fn gen_parse_error(err: SpirvErrorCode) -> ShaderError {
     ShaderError::CompilationFailed(msg)
 }
 
+/// Emit error during shader module creation. Used if we don't expect an error
+/// but might panic due to an exception in SPIRV-Cross.
+fn gen_unexpected_error(err: SpirvErrorCode) -> ShaderError {
+    let msg = match err {
+        SpirvErrorCode::CompilationError(msg) => msg,
+        SpirvErrorCode::Unhandled => "Unexpected error".into(),
+    };
+    ShaderError::CompilationFailed(msg)
+}
+
+/// Emit error during shader module creation. Used if we execute an query command.
+fn gen_query_error(err: SpirvErrorCode) -> ShaderError {
+    let msg = match err {
+        SpirvErrorCode::CompilationError(msg) => msg,
+        SpirvErrorCode::Unhandled => "Unknown query error".into(),
+    };
+    ShaderError::CompilationFailed(msg)
+}
+
+// Name the resources left unnamed by the SPIR-V after their block type, binding or
+// location, so the generated code is debuggable in captures instead of using `_34`
+// identifiers. Names given by the SPIR-V are kept.
+fn name_shader_resources(ast: &mut spirv::Ast<msl::Target>) -> Result<(), ShaderError> {
+    let resources = ast.get_shader_resources().map_err(gen_query_error)?;
+    let is_unnamed = |ast: &mut spirv::Ast<msl::Target>, id| {
+        ast.get_name(id).map(|name| name.is_empty()).map_err(gen_query_error)
+    };
+    let decoration = |ast: &mut spirv::Ast<msl::Target>, id, kind| {
+        ast.get_decoration(id, kind).map_err(gen_query_error)
+    };
+
+    let blocks = resources.uniform_buffers
+        .iter()
+        .chain(&resources.storage_buffers)
+        .map(|resource| (resource, false))
+        .chain(resources.push_constant_buffers.iter().map(|resource| (resource, true)));
+    for (resource, push_constants) in blocks {
+        let binding = if push_constants {
+            "push_constants".to_owned()
+        } else {
+            format!(
+                "set{}_binding{}",
+                decoration(ast, resource.id, spirv::Decoration::DescriptorSet)?,
+                decoration(ast, resource.id, spirv::Decoration::Binding)?,
+            )
+        };
+        let type_name = ast.get_name(resource.base_type_id).map_err(gen_query_error)?;
+        if type_name.is_empty() {
+            ast.set_name(resource.base_type_id, &format!("{}_block", binding))
+                .map_err(gen_unexpected_error)?;
+        }
+        if is_unnamed(ast, resource.id)? {
+            let name = if type_name.is_empty() { binding } else { format!("{}_var", type_name) };
+            ast.set_name(resource.id, &name).map_err(gen_unexpected_error)?;
+        }
+    }
+
+    let bindings = resources.separate_images
+        .iter()
+        .chain(&resources.separate_samplers)
+        .chain(&resources.sampled_images)
+        .chain(&resources.storage_images)
+        .chain(&resources.subpass_inputs);
+    for resource in bindings {
+        if is_unnamed(ast, resource.id)? {
+            let name = format!(
+                "set{}_binding{}",
+                decoration(ast, resource.id, spirv::Decoration::DescriptorSet)?,
+                decoration(ast, resource.id, spirv::Decoration::Binding)?,
+            );
+            ast.set_name(resource.id, &name).map_err(gen_unexpected_error)?;
+        }
+    }
+
+    let interface = resources.stage_inputs
+        .iter()
+        .map(|resource| (resource, "in"))
+        .chain(resources.stage_outputs.iter().map(|resource| (resource, "out")));
+    for (resource, direction) in interface {
+        if is_unnamed(ast, resource.id)? {
+            let location = decoration(ast, resource.id, spirv::Decoration::Location)?;
+            ast.set_name(resource.id, &format!("{}_location{}", direction, location))
+                .map_err(gen_unexpected_error)?;
+        }
+    }
+
+    Ok(())
+}
+
 fn create_function_constants(specialization: &[pso::Specialization]) -> metal::FunctionConstantValues {
     let constants_raw = metal::FunctionConstantValues::new();
     for constant in specialization {
@@ -120,10 +212,79 @@ fn get_final_function(library: &metal::LibraryRef, entry: &str, specialization:
     Ok(mtl_function)
 }
 
+/// Builds a framebuffer for the attachments of `renderpass` from the given views.
+///
+/// Shared with `begin_rendering`, which creates a transient framebuffer per call.
+pub(crate) fn create_framebuffer_raw<I>(
+    renderpass: &n::RenderPass, attachments: I, extent: image::Extent
+) -> n::Framebuffer
+where
+    I: IntoIterator,
+    I::Item: Borrow<n::ImageView>
+{
+    let _ap = AutoreleasePool::new(); // for attachments
+    let descriptor = metal::RenderPassDescriptor::new().to_owned();
+    descriptor.set_render_target_array_length(extent.depth as NSUInteger);
+
+    let mut inner = n::FramebufferInner {
+        extent,
+        aspects: format::Aspects::empty(),
+        colors: SmallVec::new(),
+        depth_stencil: None,
+    };
+
+    for (rat, attachment) in renderpass.attachments.iter().zip(attachments) {
+        let format = match rat.format {
+            Some(format) => format,
+            None => continue,
+        };
+        let aspects = format.surface_desc().aspects;
+        inner.aspects |= aspects;
+
+        let at = attachment.borrow();
+        if aspects.contains(format::Aspects::COLOR) {
+            descriptor
+                .color_attachments()
+                .object_at(inner.colors.len())
+                .expect("too many color attachments")
+                .set_texture(Some(&at.raw));
+            inner.colors.push(native::ColorAttachment {
+                mtl_format: at.mtl_format,
+                channel: format.base_format().1.into(),
+            });
+        }
+        if aspects.contains(format::Aspects::DEPTH) {
+            assert_eq!(inner.depth_stencil, None);
+            inner.depth_stencil = Some(at.mtl_format);
+            descriptor
+                .depth_attachment()
+                .unwrap()
+                .set_texture(Some(&at.raw));
+        }
+        if aspects.contains(format::Aspects::STENCIL) {
+            if let Some(old_format) = inner.depth_stencil {
+                assert_eq!(old_format, at.mtl_format);
+            } else {
+                inner.depth_stencil = Some(at.mtl_format);
+            }
+            descriptor
+                .stencil_attachment()
+                .unwrap()
+                .set_texture(Some(&at.raw));
+        }
+    }
+
+    n::Framebuffer {
+        descriptor: Mutex::new(descriptor),
+        inner,
+    }
+}
+
 //#[derive(Clone)]
 pub struct Device {
     pub(crate) shared: Arc<Shared>,
     pub(crate) private_caps: PrivateCapabilities,
+    features: hal::Features,
     memory_types: [hal::MemoryType; 4],
 }
 unsafe impl Send for Device {}
@@ -193,6 +354,7 @@ impl PhysicalDevice {
                 format_depth32_stencil8: true, //TODO: crashing the Metal validation layer upon copying from buffer
                 format_min_srgb_channels: if Self::is_mac(&*device) {4} else {1},
                 format_b5: !Self::is_mac(device),
+                format_rgb9e5_all: !Self::is_mac(device),
                 max_buffers_per_stage: 31,
                 max_textures_per_stage: if Self::is_mac(device) {128} else {31},
                 max_samplers_per_stage: 16,
@@ -257,6 +419,7 @@ impl hal::PhysicalDevice<Backend> for PhysicalDevice {
         let device = Device {
             shared: self.shared.clone(),
             private_caps: self.private_caps.clone(),
+            features: hal::PhysicalDevice::features(self),
             memory_types: self.memory_types,
         };
 
@@ -268,6 +431,22 @@ impl hal::PhysicalDevice<Backend> for PhysicalDevice {
 
     fn format_properties(&self, format: Option<format::Format>) -> format::Properties {
         match format.and_then(|f| self.private_caps.map_format(f)) {
+            Some(metal::MTLPixelFormat::RGB9E5Float) => {
+                // Shared exponent images can only be sampled on macOS
+                let mut optimal_tiling = format::ImageFeature::SAMPLED |
+                    format::ImageFeature::SAMPLED_LINEAR |
+                    format::ImageFeature::BLIT_SRC;
+                if self.private_caps.format_rgb9e5_all {
+                    optimal_tiling |= format::ImageFeature::COLOR_ATTACHMENT |
+                        format::ImageFeature::COLOR_ATTACHMENT_BLEND |
+                        format::ImageFeature::BLIT_DST;
+                }
+                format::Properties {
+                    linear_tiling: format::ImageFeature::empty(),
+                    optimal_tiling,
+                    buffer_features: format::BufferFeature::empty(),
+                }
+            }
             Some(_) => format::Properties {
                 linear_tiling: format::ImageFeature::empty(),
                 optimal_tiling: format::ImageFeature::all(),
@@ -301,6 +480,15 @@ impl hal::PhysicalDevice<Backend> for PhysicalDevice {
             // MTLRenderPassDescriptor texture must not be MTLTextureType1D
             return None;
         }
+        if format == format::Format::E5b9g9r9Ufloat {
+            let mut unsupported = image::Usage::STORAGE | image::Usage::DEPTH_STENCIL_ATTACHMENT;
+            if !self.private_caps.format_rgb9e5_all {
+                unsupported |= image::Usage::COLOR_ATTACHMENT;
+            }
+            if usage.intersects(unsupported) {
+                return None;
+            }
+        }
         //TODO: actually query this data
         let max_dimension = 4096u32;
         let max_extent = image::Extent {
@@ -333,9 +521,15 @@ impl hal::PhysicalDevice<Backend> for PhysicalDevice {
     }
 
     fn features(&self) -> hal::Features {
+        // No `SUBGROUP_*` features: the translated shaders are compiled as MSL 1.2,
+        // while SIMD-group functions require MSL 2.0.
         hal::Features::ROBUST_BUFFER_ACCESS |
         hal::Features::DRAW_INDIRECT_FIRST_INSTANCE |
-        hal::Features::DEPTH_CLAMP
+        hal::Features::DEPTH_CLAMP |
+        hal::Features::DYNAMIC_RENDERING |
+        hal::Features::IMAGELESS_FRAMEBUFFER |
+        // `half` is supported by every MSL version
+        hal::Features::SHADER_FLOAT16
     }
 
     fn limits(&self) -> hal::Limits {
@@ -367,6 +561,9 @@ impl hal::PhysicalDevice<Backend> for PhysicalDevice {
             // Note: we issue Metal buffer-to-buffer copies on memory flush/invalidate,
             // and those need to operate on sizes being multiples of 4.
             non_coherent_atom_size: 4,
+            // Subgroup operations aren't exposed, see `features`.
+            min_subgroup_size: 0,
+            max_subgroup_size: 0,
         }
     }
 }
@@ -443,6 +640,8 @@ impl Device {
 
         // compile with options
         let mut compiler_options = msl::CompilerOptions::default();
+        // Metal requires `[[point_size]]` to be written when drawing points,
+        // so the builtin is always kept, see `create_graphics_pipeline`.
         compiler_options.enable_point_size_builtin = primitive_class == MTLPrimitiveTopologyClass::Point;
         compiler_options.resolve_specialized_array_lengths = true;
         compiler_options.vertex.invert_y = true;
@@ -470,6 +669,10 @@ impl Device {
                 ShaderError::CompilationFailed(msg)
             })?;
 
+        if cfg!(debug_assertions) {
+            name_shader_resources(&mut ast)?;
+        }
+
         let shader_code = ast.compile()
             .map_err(|err| {
                 let msg = match err {
@@ -518,18 +721,29 @@ impl Device {
         primitive_class: MTLPrimitiveTopologyClass,
     ) -> Result<(metal::Library, metal::Function, metal::MTLSize), pso::CreationError> {
         let entries_owned;
+        let mut specialized_wg_size = None;
         let (lib, entry_point_map) = match *ep.module {
             n::ShaderModule::Compiled {ref library, ref entry_point_map} => {
                 (library.to_owned(), entry_point_map)
             }
             n::ShaderModule::Raw(ref data) => {
+                pso::check_spirv_features(data, self.features)
+                    .map_err(pso::CreationError::Shader)?;
+                // The work group size is only provided on dispatch in Metal,
+                // so specialization constants overriding it are applied here.
+                specialized_wg_size = pso::reflect_spirv(data)
+                    .ok()
+                    .and_then(|info| {
+                        info.entry_point(ep.entry)
+                            .map(|ep_info| ep_info.specialized_workgroup_size(ep.specialization))
+                    });
                 let raw = self.compile_shader_library(data, primitive_class, &layout.res_overrides).unwrap();
                 entries_owned = raw.1;
                 (raw.0, &entries_owned)
             }
         };
 
-        let (name, wg_size) = match entry_point_map.get(ep.entry) {
+        let (name, mut wg_size) = match entry_point_map.get(ep.entry) {
             Some(p) => (p.name.as_str(), metal::MTLSize {
                 width : p.work_group_size.x as _,
                 height: p.work_group_size.y as _,
@@ -538,6 +752,13 @@ impl Device {
             // this can only happen if the shader came directly from the user
             None => (ep.entry, metal::MTLSize { width: 0, height: 0, depth: 0 }),
         };
+        if let Some([width, height, depth]) = specialized_wg_size {
+            wg_size = metal::MTLSize {
+                width: width as _,
+                height: height as _,
+                depth: depth as _,
+            };
+        }
         let mtl_function = get_final_function(&lib, name, ep.specialization)
             .map_err(|_| {
                 error!("Invalid shader entry point");
@@ -780,6 +1001,13 @@ impl hal::Device<Backend> for Device {
                 pipeline_desc.vertex_buffers.len(), pipeline_layout.attribute_buffer_index);
             return Err(pso::CreationError::Shader(ShaderError::InterfaceMismatch(msg)));
         }
+        // The point size can't be forced when the shader writes `[[point_size]]`.
+        if pipeline_desc.input_assembler.primitive == hal::Primitive::PointList &&
+            !pipeline_desc.rasterizer.program_point_size
+        {
+            error!("Points always take their size from `PointSize`");
+            return Err(pso::CreationError::Other);
+        }
         // FIXME: lots missing
 
         let (primitive_class, primitive_type) = match pipeline_desc.input_assembler.primitive {
@@ -858,6 +1086,16 @@ impl hal::Device<Backend> for Device {
         }
 
         // Blending
+        if cfg!(debug_assertions) && pipeline_desc.blender.srgb == pso::SrgbConversion::Encoded {
+            let formats = pass_descriptor.main_pass.attachments
+                .iter()
+                .map(|attachment| attachment.format)
+                .filter(|format| format.map_or(false, |format| format.is_color()));
+            if pipeline_desc.blender.blends_srgb(formats) {
+                warn!("Blending on encoded sRGB values is not supported, sRGB targets are blended \
+                    in linear space, render into a UNORM view of the target instead");
+            }
+        }
         for (i, color_desc) in pipeline_desc.blender.targets.iter().enumerate() {
             let descriptor = pipeline
                 .color_attachments()
@@ -1014,7 +1252,8 @@ impl hal::Device<Backend> for Device {
                 })
             .map_err(|err| {
                 error!("PSO creation failed: {}", err);
-                pso::CreationError::Other
+                // The description of the `NSError` explains the failure.
+                pso::CreationError::Other.with_context(error::ErrorContext { messages: vec![err.to_string()] })
             })
     }
 
@@ -1045,7 +1284,8 @@ impl hal::Device<Backend> for Device {
             })
             .map_err(|err| {
                 error!("PSO creation failed: {}", err);
-                pso::CreationError::Other
+                // The description of the `NSError` explains the failure.
+                pso::CreationError::Other.with_context(error::ErrorContext { messages: vec![err.to_string()] })
             })
     }
 
@@ -1056,65 +1296,24 @@ impl hal::Device<Backend> for Device {
         I: IntoIterator,
         I::Item: Borrow<n::ImageView>
     {
-        let _ap = AutoreleasePool::new(); // for attachments
-        let descriptor = metal::RenderPassDescriptor::new().to_owned();
-        descriptor.set_render_target_array_length(extent.depth as NSUInteger);
-
-        let mut inner = n::FramebufferInner {
-            extent,
-            aspects: format::Aspects::empty(),
-            colors: SmallVec::new(),
-            depth_stencil: None,
-        };
-
-        for (rat, attachment) in renderpass.attachments.iter().zip(attachments) {
-            let format = match rat.format {
-                Some(format) => format,
-                None => continue,
-            };
-            let aspects = format.surface_desc().aspects;
-            inner.aspects |= aspects;
-
-            let at = attachment.borrow();
-            if aspects.contains(format::Aspects::COLOR) {
-                descriptor
-                    .color_attachments()
-                    .object_at(inner.colors.len())
-                    .expect("too many color attachments")
-                    .set_texture(Some(&at.raw));
-                inner.colors.push(native::ColorAttachment {
-                    mtl_format: at.mtl_format,
-                    channel: format.base_format().1.into(),
-                });
-            }
-            if aspects.contains(format::Aspects::DEPTH) {
-                assert_eq!(inner.depth_stencil, None);
-                inner.depth_stencil = Some(at.mtl_format);
-                descriptor
-                    .depth_attachment()
-                    .unwrap()
-                    .set_texture(Some(&at.raw));
-            }
-            if aspects.contains(format::Aspects::STENCIL) {
-                if let Some(old_format) = inner.depth_stencil {
-                    assert_eq!(old_format, at.mtl_format);
-                } else {
-                    inner.depth_stencil = Some(at.mtl_format);
-                }
-                descriptor
-                    .stencil_attachment()
-                    .unwrap()
-                    .set_texture(Some(&at.raw));
-            }
-        }
+        Ok(create_framebuffer_raw(renderpass, attachments, extent))
+    }
 
-        Ok(n::Framebuffer {
-            descriptor: Mutex::new(descriptor),
-            inner,
-        })
+    fn create_imageless_framebuffer<I>(
+        &self, renderpass: &n::RenderPass, _attachments: I, extent: image::Extent
+    ) -> Result<n::Framebuffer, FramebufferError>
+    where
+        I: IntoIterator,
+        I::Item: Borrow<pass::FramebufferAttachment>
+    {
+        // Only the extent is kept, the descriptor is built at `begin_render_pass_imageless`.
+        Ok(create_framebuffer_raw(renderpass, iter::empty::<n::ImageView>(), extent))
     }
 
     fn create_shader_module(&self, raw_data: &[u8]) -> Result<n::ShaderModule, ShaderError> {
+        if cfg!(debug_assertions) {
+            pso::validate_spirv(raw_data)?;
+        }
         //TODO: we can probably at least parse here and save the `Ast`
         let depends_on_pipeline_layout = true; //TODO: !self.private_caps.argument_buffers
         Ok(if depends_on_pipeline_layout {
@@ -1379,7 +1578,9 @@ impl hal::Device<Backend> for Device {
         J::Item: Borrow<pso::Descriptor<'a, Backend>>,
     {
         debug!("write_descriptor_sets");
+        let mut statistics = FrameStatistics::default();
         for write in write_iter {
+            statistics.descriptor_updates += 1;
             match *write.set {
                 n::DescriptorSet::Emulated { ref pool, ref layouts, ref sampler_range, ref texture_range, ref buffer_range } => {
                     let mut array_offset = write.array_offset;
@@ -1434,32 +1635,48 @@ impl hal::Device<Backend> for Device {
                                 pool.textures[texture_index] = Some((TexturePtr(view.raw.as_ptr()), image::Layout::General));
                             }
                             pso::Descriptor::Buffer(buffer, ref range) => {
-                                let buf_length = buffer.raw.length();
+                                // The buffer may be placed at an offset into the memory buffer.
+                                let buf_length = buffer.range.end - buffer.range.start;
                                 let start = range.start.unwrap_or(0);
                                 let end = range.end.unwrap_or(buf_length);
-                                assert!(end <= buf_length);
-                                pool.buffers[buffer_index].base = Some((BufferPtr(buffer.raw.as_ptr()), start));
+                                assert!(start <= end && end <= buf_length);
+                                let offset = buffer.range.start + start;
+                                pool.buffers[buffer_index].base = Some((BufferPtr(buffer.raw.as_ptr()), offset));
                             }
                         }
                     }
                 }
-                n::DescriptorSet::ArgumentBuffer { ref raw, offset, ref encoder, .. } => {
+                n::DescriptorSet::ArgumentBuffer { ref raw, offset, ref encoder, ref resources, .. } => {
                     debug_assert!(self.private_caps.argument_buffers);
 
                     encoder.set_argument_buffer(raw, offset);
                     //TODO: range checks, need to keep some layout metadata around
                     assert_eq!(write.array_offset, 0); //TODO
 
+                    let mut resources = resources.lock().unwrap();
                     for descriptor in write.descriptors {
                         match *descriptor.borrow() {
                             pso::Descriptor::Sampler(sampler) => {
                                 encoder.set_sampler_states(&[&sampler.0], write.binding as _);
+                                resources.remove(&write.binding);
                             }
-                            pso::Descriptor::Image(image, _layout) => {
+                            pso::Descriptor::Image(image, layout) => {
                                 encoder.set_textures(&[&image.raw], write.binding as _);
+                                // Storage images are the only ones used in the `General` layout.
+                                let usage = match layout {
+                                    image::Layout::General => ResourceUsage::READ | ResourceUsage::WRITE,
+                                    _ => ResourceUsage::SAMPLE,
+                                };
+                                let texture = ResourcePtr::from(TexturePtr(image.raw.as_ptr()));
+                                resources.insert(write.binding, (texture, usage));
                             }
                             pso::Descriptor::Buffer(buffer, ref range) => {
-                                encoder.set_buffer(&buffer.raw, range.start.unwrap_or(0), write.binding as _);
+                                let offset = buffer.range.start + range.start.unwrap_or(0);
+                                encoder.set_buffer(&buffer.raw, offset, write.binding as _);
+                                // Uniform and storage buffers are indistinguishable here.
+                                let usage = ResourceUsage::READ | ResourceUsage::WRITE;
+                                let buffer = ResourcePtr::from(BufferPtr(buffer.raw.as_ptr()));
+                                resources.insert(write.binding, (buffer, usage));
                             }
                             pso::Descriptor::CombinedImageSampler(..) |
                             pso::Descriptor::UniformTexelBuffer(..) |
@@ -1469,6 +1686,7 @@ impl hal::Device<Backend> for Device {
                 }
             }
         }
+        self.shared.statistics.add(&statistics);
     }
 
     fn copy_descriptor_sets<'a, I>(&self, copies: I)
@@ -1490,6 +1708,26 @@ impl hal::Device<Backend> for Device {
     fn destroy_pipeline_layout(&self, _pipeline_layout: n::PipelineLayout) {
     }
 
+    fn create_pipeline_cache(&self, _data: Option<&[u8]>) -> Result<(), OutOfMemory> {
+        // Metal caches compiled pipelines internally.
+        Ok(())
+    }
+
+    fn get_pipeline_cache_data(&self, _cache: &()) -> Result<Vec<u8>, OutOfMemory> {
+        Ok(Vec::new())
+    }
+
+    fn merge_pipeline_caches<I>(&self, _target: &(), _sources: I) -> Result<(), OutOfMemory>
+    where
+        I: IntoIterator,
+        I::Item: Borrow<()>,
+    {
+        Ok(())
+    }
+
+    fn destroy_pipeline_cache(&self, _cache: ()) {
+    }
+
     fn destroy_shader_module(&self, _module: n::ShaderModule) {
     }
 
@@ -1898,6 +2136,23 @@ impl hal::Device<Backend> for Device {
     fn destroy_image(&self, _image: n::Image) {
     }
 
+    fn create_sparse_buffer(
+        &self, _size: u64, _usage: buffer::Usage,
+    ) -> Result<(n::Buffer, memory::Requirements), buffer::CreationError> {
+        unimplemented!()
+    }
+
+    fn create_sparse_image(
+        &self,
+        _kind: image::Kind,
+        _mip_levels: image::Level,
+        _format: format::Format,
+        _usage: image::Usage,
+        _storage_flags: image::StorageFlags,
+    ) -> Result<(n::Image, hal::sparse::SparseImageRequirements), image::CreationError> {
+        unimplemented!()
+    }
+
     fn create_image_view(
         &self,
         image: &n::Image,
@@ -1951,7 +2206,12 @@ impl hal::Device<Backend> for Device {
             )
         };
 
-        Ok(n::ImageView { raw: view, mtl_format })
+        Ok(n::ImageView {
+            raw: view,
+            mtl_format,
+            format,
+            samples: image.kind.num_samples(),
+        })
     }
 
     fn destroy_image_view(&self, _view: n::ImageView) {
@@ -2004,6 +2264,39 @@ impl hal::Device<Backend> for Device {
     fn destroy_fence(&self, _fence: n::Fence) {
     }
 
+    fn create_timeline_semaphore(&self, initial_value: u64) -> n::TimelineSemaphore {
+        Arc::new(HostTimeline::new(initial_value))
+    }
+
+    fn get_timeline_semaphore_value(&self, semaphore: &n::TimelineSemaphore) -> u64 {
+        semaphore.value()
+    }
+
+    fn signal_timeline_semaphore(&self, semaphore: &n::TimelineSemaphore, value: u64) {
+        semaphore.signal(value);
+    }
+
+    fn wait_for_timeline_semaphores<'a, I>(&self, semaphores: I, wait: hal::device::WaitFor, timeout_ms: u32) -> bool
+    where
+        I: IntoIterator<Item = (&'a n::TimelineSemaphore, u64)>,
+    {
+        HostTimeline::wait_many(
+            semaphores.into_iter().map(|(semaphore, value)| (&**semaphore, value)),
+            wait,
+            timeout_ms,
+        )
+    }
+
+    fn destroy_timeline_semaphore(&self, _semaphore: n::TimelineSemaphore) {
+    }
+
+    fn create_event(&self) -> () {
+        // Events are waited on with full barriers.
+    }
+
+    fn destroy_event(&self, _: ()) {
+    }
+
     fn create_query_pool(&self, _ty: query::QueryType, _count: u32) -> () {
         unimplemented!()
     }
@@ -2032,6 +2325,10 @@ impl hal::Device<Backend> for Device {
         command::QueueInner::wait_idle(&self.shared.queue);
         Ok(())
     }
+
+    fn frame_statistics(&self) -> Option<FrameStatistics> {
+        Some(self.shared.statistics.take())
+    }
 }
 
 #[test]
@@ -35,12 +35,13 @@ use std::mem;
 use std::sync::{Arc, Mutex};
 use std::os::raw::c_void;
 
+use hal::backend::StatisticsCollector;
 use hal::queue::QueueFamilyId;
 
-use objc::runtime::{Class, Object};
+use objc::runtime::{Class, Object, BOOL, YES};
 use cocoa::foundation::NSAutoreleasePool;
 use core_graphics::geometry::CGRect;
-use foreign_types::ForeignTypeRef;
+use foreign_types::{ForeignType, ForeignTypeRef};
 
 
 const MAX_ACTIVE_COMMAND_BUFFERS: usize = 1 << 14;
@@ -60,6 +61,11 @@ struct Shared {
     service_pipes: Mutex<internal::ServicePipes>,
     push_constants_buffer_id: u32,
     disabilities: PrivateDisabilities,
+    /// Fence synchronizing encoders across pipeline barriers, only needed when
+    /// resources can be allocated from heaps, which Metal doesn't track hazards for.
+    hazard_fence: Option<FencePtr>,
+    /// Command statistics, accumulated by the queue on submission.
+    statistics: StatisticsCollector,
 }
 
 unsafe impl Send for Shared {}
@@ -68,6 +74,15 @@ unsafe impl Sync for Shared {}
 impl Shared {
     fn new(device: metal::Device) -> Self {
         let feature_macos_10_14: metal::MTLFeatureSet = unsafe { mem::transmute(10004u64) };
+        let hazard_fence = unsafe {
+            let supports_heaps: BOOL = msg_send![device.as_ptr(), respondsToSelector: sel!(newHeapWithDescriptor:)];
+            if supports_heaps == YES {
+                let fence: *mut Object = msg_send![device.as_ptr(), newFence];
+                Some(FencePtr(fence))
+            } else {
+                None
+            }
+        };
         Shared {
             queue: Mutex::new(command::QueueInner::new(&device, Some(MAX_ACTIVE_COMMAND_BUFFERS))),
             service_pipes: Mutex::new(internal::ServicePipes::new(&device)),
@@ -76,11 +91,23 @@ impl Shared {
                 broken_viewport_near_depth: device.name().starts_with("Intel") &&
                     !device.supports_feature_set(feature_macos_10_14),
             },
+            hazard_fence,
+            statistics: StatisticsCollector::default(),
             device: Mutex::new(device),
         }
     }
 }
 
+impl Drop for Shared {
+    fn drop(&mut self) {
+        if let Some(fence) = self.hazard_fence {
+            unsafe {
+                msg_send![fence.as_ptr(), release];
+            }
+        }
+    }
+}
+
 
 pub struct Instance {
     shared: Arc<Shared>,
@@ -91,7 +118,15 @@ impl hal::Instance for Instance {
 
     fn enumerate_adapters(&self) -> Vec<hal::Adapter<Backend>> {
         // TODO: enumerate all devices
-        let name = self.shared.device.lock().unwrap().name().into();
+        let (name, device_type) = {
+            let device = self.shared.device.lock().unwrap();
+            let device_type = if device.is_low_power() {
+                hal::adapter::DeviceType::IntegratedGpu
+            } else {
+                hal::adapter::DeviceType::DiscreteGpu
+            };
+            (device.name().into(), device_type)
+        };
 
         vec![
             hal::Adapter {
@@ -100,6 +135,7 @@ impl hal::Instance for Instance {
                     vendor: 0,
                     device: 0,
                     software_rendering: false,
+                    device_type,
                 },
                 physical_device: device::PhysicalDevice::new(self.shared.clone()),
                 queue_families: vec![QueueFamily{}],
@@ -187,12 +223,15 @@ impl hal::Backend for Backend {
     type ComputePipeline = native::ComputePipeline;
     type GraphicsPipeline = native::GraphicsPipeline;
     type PipelineLayout = native::PipelineLayout;
+    type PipelineCache = ();
     type DescriptorSetLayout = native::DescriptorSetLayout;
     type DescriptorPool = native::DescriptorPool;
     type DescriptorSet = native::DescriptorSet;
 
     type Fence = native::Fence;
     type Semaphore = native::Semaphore;
+    type TimelineSemaphore = native::TimelineSemaphore;
+    type Event = ();
     type QueryPool = ();
 }
 
@@ -207,6 +246,7 @@ struct PrivateCapabilities {
     format_depth32_stencil8: bool,
     format_min_srgb_channels: u8,
     format_b5: bool,
+    format_rgb9e5_all: bool,
     max_buffers_per_stage: usize,
     max_textures_per_stage: usize,
     max_samplers_per_stage: usize,
@@ -287,6 +327,39 @@ impl TexturePtr {
     }
 }
 
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub struct ResourcePtr(*mut metal::MTLResource);
+
+impl ResourcePtr {
+    #[inline]
+    pub fn as_ptr(&self) -> *mut metal::MTLResource {
+        self.0
+    }
+}
+
+impl From<BufferPtr> for ResourcePtr {
+    fn from(buffer: BufferPtr) -> Self {
+        ResourcePtr(buffer.0 as *mut _)
+    }
+}
+
+impl From<TexturePtr> for ResourcePtr {
+    fn from(texture: TexturePtr) -> Self {
+        ResourcePtr(texture.0 as *mut _)
+    }
+}
+
+/// Raw `MTLFence`, which isn't exposed by `metal-rs` yet.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct FencePtr(*mut Object);
+
+impl FencePtr {
+    #[inline]
+    pub fn as_ptr(&self) -> *mut Object {
+        self.0
+    }
+}
+
 #[derive(Clone, Copy, Debug, PartialEq)]
 pub struct SamplerPtr(*mut metal::MTLSamplerState);
 
@@ -1,5 +1,6 @@
-use {Backend, BufferPtr, SamplerPtr, TexturePtr};
+use {Backend, BufferPtr, ResourcePtr, SamplerPtr, TexturePtr};
 use internal::Channel;
+use soft::ResourceUsage;
 use window::SwapchainImage;
 
 use std::ops::Range;
@@ -8,6 +9,7 @@ use std::sync::{Arc, Condvar, Mutex, RwLock};
 
 use hal::{self, image, pso};
 use hal::backend::FastHashMap;
+use hal::sync::HostTimeline;
 use hal::format::{Aspects, Format, FormatDesc};
 
 use cocoa::foundation::{NSUInteger};
@@ -182,6 +184,8 @@ unsafe impl Sync for BufferView {}
 pub struct ImageView {
     pub(crate) raw: metal::Texture,
     pub(crate) mtl_format: metal::MTLPixelFormat,
+    pub(crate) format: Format,
+    pub(crate) samples: image::NumSamples,
 }
 
 unsafe impl Send for ImageView {}
@@ -397,6 +401,7 @@ impl hal::DescriptorPool<Backend> for DescriptorPool {
                         offset: range.start,
                         encoder: encoder.clone(),
                         stage_flags,
+                        resources: Arc::new(Mutex::new(FastHashMap::default())),
                     }),
                     Err(_) => Err(pso::AllocationError::OutOfPoolMemory),
                 }
@@ -505,6 +510,9 @@ pub enum DescriptorSet {
         offset: NSUInteger,
         encoder: metal::ArgumentEncoder,
         stage_flags: pso::ShaderStageFlags,
+        /// Resources referenced by the argument buffer, by binding. They need to be made
+        /// resident explicitly in every encoder the set is used in.
+        resources: Arc<Mutex<FastHashMap<pso::DescriptorBinding, (ResourcePtr, ResourceUsage)>>>,
     },
 }
 unsafe impl Send for DescriptorSet {}
@@ -567,6 +575,9 @@ pub struct FenceInner {
 
 pub type Fence = Arc<FenceInner>;
 
+/// Timeline semaphore emulated on the host, signaled by command buffer completion handlers.
+pub type TimelineSemaphore = Arc<HostTimeline>;
+
 extern "C" {
     fn dispatch_semaphore_wait(
         semaphore: *mut c_void,
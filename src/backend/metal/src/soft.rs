@@ -1,7 +1,8 @@
-use {BufferPtr, SamplerPtr, TexturePtr};
+use {BufferPtr, FencePtr, ResourcePtr, SamplerPtr, TexturePtr};
 use command::IndexBuffer;
 use native::RasterizerState;
 
+use cocoa::foundation::NSUInteger;
 use hal;
 use metal;
 
@@ -41,6 +42,16 @@ impl<'a> Resources for &'a Own {
 }
 
 
+bitflags! {
+    /// Mirror of `MTLResourceUsage`, describing how an encoder accesses a resource
+    /// referenced indirectly, e.g. through an argument buffer.
+    pub struct ResourceUsage: NSUInteger {
+        const READ = 1 << 0;
+        const WRITE = 1 << 1;
+        const SAMPLE = 1 << 2;
+    }
+}
+
 #[derive(Clone, Debug)]
 pub enum RenderCommand<R: Resources> {
     SetViewport(metal::MTLViewport),
@@ -74,6 +85,12 @@ pub enum RenderCommand<R: Resources> {
         R::RenderPipeline,
         Option<RasterizerState>,
     ),
+    UseResource {
+        resource: ResourcePtr,
+        usage: ResourceUsage,
+    },
+    UpdateFence(FencePtr),
+    WaitForFence(FencePtr),
     Draw {
         primitive_type: metal::MTLPrimitiveType,
         vertices: Range<hal::VertexCount>,
@@ -131,6 +148,9 @@ impl RenderCommand<Own> {
                 sampler,
             },
             BindPipeline(ref pso, ref state) => BindPipeline(&**pso, state.clone()),
+            UseResource { resource, usage } => UseResource { resource, usage },
+            UpdateFence(fence) => UpdateFence(fence),
+            WaitForFence(fence) => WaitForFence(fence),
             Draw { primitive_type, ref vertices, ref instances } => Draw {
                 primitive_type,
                 vertices: vertices.clone(),
@@ -190,6 +210,9 @@ impl<'a> RenderCommand<&'a Own> {
                 sampler,
             },
             BindPipeline(pso, state) => BindPipeline(pso.to_owned(), state),
+            UseResource { resource, usage } => UseResource { resource, usage },
+            UpdateFence(fence) => UpdateFence(fence),
+            WaitForFence(fence) => WaitForFence(fence),
             Draw { primitive_type, vertices, instances } => Draw {
                 primitive_type,
                 vertices,
@@ -242,6 +265,8 @@ pub enum BlitCommand<R: Resources> {
         dst: R::Buffer,
         region: hal::command::BufferImageCopy,
     },
+    UpdateFence(FencePtr),
+    WaitForFence(FencePtr),
 }
 
 impl BlitCommand<Own> {
@@ -270,6 +295,8 @@ impl BlitCommand<Own> {
                 dst,
                 region: region.clone(),
             },
+            UpdateFence(fence) => UpdateFence(fence),
+            WaitForFence(fence) => WaitForFence(fence),
         }
     }
 }
@@ -300,6 +327,8 @@ impl<'a> BlitCommand<&'a Own> {
                 dst,
                 region,
             },
+            UpdateFence(fence) => UpdateFence(fence),
+            WaitForFence(fence) => WaitForFence(fence),
         }
     }
 }
@@ -334,6 +363,12 @@ pub enum ComputeCommand<R: Resources> {
         buffer: R::Buffer,
         offset: hal::buffer::Offset,
     },
+    UseResource {
+        resource: ResourcePtr,
+        usage: ResourceUsage,
+    },
+    UpdateFence(FencePtr),
+    WaitForFence(FencePtr),
 }
 
 impl ComputeCommand<Own> {
@@ -367,6 +402,9 @@ impl ComputeCommand<Own> {
                 buffer,
                 offset,
             },
+            UseResource { resource, usage } => UseResource { resource, usage },
+            UpdateFence(fence) => UpdateFence(fence),
+            WaitForFence(fence) => WaitForFence(fence),
         }
     }
 }
@@ -402,6 +440,9 @@ impl<'a> ComputeCommand<&'a Own> {
                 buffer,
                 offset,
             },
+            UseResource { resource, usage } => UseResource { resource, usage },
+            UpdateFence(fence) => UpdateFence(fence),
+            WaitForFence(fence) => WaitForFence(fence),
         }
     }
 }
@@ -172,17 +172,7 @@ impl hal::Surface<Backend> for Surface {
         image::Kind::D2(width, height, 1, 1)
     }
 
-    fn compatibility(
-        &self, _: &PhysicalDevice,
-    ) -> (hal::SurfaceCapabilities, Option<Vec<format::Format>>, Vec<hal::PresentMode>) {
-        let caps = hal::SurfaceCapabilities {
-            //Note: this is hardcoded in `CAMetalLayer` documentation
-            image_count: 2 .. 4,
-            current_extent: None,
-            extents: Extent2D { width: 4, height: 4} .. Extent2D { width: 4096, height: 4096 },
-            max_image_layers: 1,
-        };
-
+    fn capabilities(&self, _: &PhysicalDevice) -> hal::SurfaceCapabilities {
         let formats = vec![
             format::Format::Bgra8Unorm,
             format::Format::Bgra8Srgb,
@@ -193,7 +183,17 @@ impl hal::Surface<Backend> for Surface {
             hal::PresentMode::Immediate,
         ];
 
-        (caps, Some(formats), present_modes)
+        hal::SurfaceCapabilities {
+            //Note: this is hardcoded in `CAMetalLayer` documentation
+            image_count: 2 .. 4,
+            current_extent: None,
+            extents: Extent2D { width: 4, height: 4} .. Extent2D { width: 4096, height: 4096 },
+            max_image_layers: 1,
+            current_transform: hal::SurfaceTransform::IDENTITY,
+            supported_transforms: hal::SurfaceTransform::IDENTITY,
+            formats: Some(formats),
+            present_modes,
+        }
     }
 
     fn supports_queue_family(&self, _queue_family: &QueueFamily) -> bool {
@@ -324,14 +324,25 @@ impl Device {
     }
 }
 
+impl Swapchain {
+    /// The layer keeps the drawable size of the swapchain and stretches
+    /// the drawables if the view got resized.
+    pub(crate) fn suboptimal(&self) -> Option<hal::window::Suboptimal> {
+        //TODO: figure out a proper story of HiDPI
+        let (width, height) = self.surface.pixel_dimensions();
+        hal::window::Suboptimal::from_extent(
+            hal::window::Extent2D { width, height },
+            hal::window::Extent2D { width: self.size_pixels.0, height: self.size_pixels.1 },
+        )
+    }
+}
+
 impl hal::Swapchain<Backend> for Swapchain {
-    fn acquire_image(&mut self, sync: hal::FrameSync<Backend>) -> Result<hal::SwapImageIndex, ()> {
+    fn acquire_image(
+        &mut self, sync: hal::FrameSync<Backend>
+    ) -> Result<(hal::SwapImageIndex, Option<hal::window::Suboptimal>), hal::window::AcquireError> {
         self.last_frame += 1;
-
-        //TODO: figure out a proper story of HiDPI
-        if false && self.surface.pixel_dimensions() != self.size_pixels {
-            return Err(())
-        }
+        let suboptimal = self.suboptimal();
 
         let mut oldest_index = 0;
         let mut oldest_frame = self.last_frame;
@@ -345,7 +356,7 @@ impl hal::Swapchain<Backend> for Swapchain {
                 frame.available = false;
                 frame.last_frame = self.last_frame;
                 self.signal_sync(sync);
-                return Ok(index as _);
+                return Ok((index as _, suboptimal));
             }
             if frame.last_frame < oldest_frame {
                 oldest_frame = frame.last_frame;
@@ -384,6 +395,6 @@ impl hal::Swapchain<Backend> for Swapchain {
         frame.last_frame = self.last_frame;
         frame.available = false;
 
-        Ok(index as _)
+        Ok((index as _, suboptimal))
     }
 }
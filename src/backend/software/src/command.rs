@@ -0,0 +1,1269 @@
+use hal::{self, buffer, command as com, format, image, memory, pool, pso, query};
+use hal::format::{ChannelType, SurfaceType};
+use hal::profiler::CommandBufferStatistics;
+use hal::range::RangeArg;
+
+use std::borrow::Borrow;
+use std::ops::Range;
+use std::sync::{Arc, Mutex};
+use std::{cmp, ptr};
+
+use native as n;
+use {Backend, Shared};
+
+const COPY_SRC_LAYOUTS: &[image::Layout] = &[image::Layout::TransferSrcOptimal, image::Layout::General];
+const COPY_DST_LAYOUTS: &[image::Layout] = &[image::Layout::TransferDstOptimal, image::Layout::General];
+
+#[derive(Debug)]
+pub struct CommandPool;
+
+impl pool::RawCommandPool<Backend> for CommandPool {
+    fn reset(&mut self) {
+        // Command buffers drop their recorded commands when they begin recording again.
+    }
+
+    fn allocate(&mut self, num: usize, _: com::RawLevel) -> Vec<CommandBuffer> {
+        (0 .. num).map(|_| CommandBuffer::new()).collect()
+    }
+
+    unsafe fn free(&mut self, _: Vec<CommandBuffer>) {
+    }
+}
+
+#[derive(Clone, Debug)]
+enum QueryValue {
+    /// Nothing is rasterized, so occlusion and pipeline statistics queries count zero.
+    Zero,
+    Timestamp,
+}
+
+#[derive(Clone, Debug)]
+enum Command {
+    Transition {
+        image: n::Image,
+        range: image::SubresourceRange,
+        layouts: Range<image::Layout>,
+    },
+    FillBuffer {
+        dst: n::Buffer,
+        range: Range<u64>,
+        data: u32,
+    },
+    UpdateBuffer {
+        dst: n::Buffer,
+        offset: u64,
+        data: Vec<u8>,
+    },
+    CopyBuffer {
+        src: n::Buffer,
+        dst: n::Buffer,
+        regions: Vec<com::BufferCopy>,
+    },
+    CopyImage {
+        src: n::Image,
+        src_layout: image::Layout,
+        dst: n::Image,
+        dst_layout: image::Layout,
+        regions: Vec<com::ImageCopy>,
+    },
+    BlitImage {
+        src: n::Image,
+        src_layout: image::Layout,
+        dst: n::Image,
+        dst_layout: image::Layout,
+        regions: Vec<com::ImageBlit>,
+    },
+    CopyBufferToImage {
+        src: n::Buffer,
+        dst: n::Image,
+        dst_layout: image::Layout,
+        regions: Vec<com::BufferImageCopy>,
+    },
+    CopyImageToBuffer {
+        src: n::Image,
+        src_layout: image::Layout,
+        dst: n::Buffer,
+        regions: Vec<com::BufferImageCopy>,
+    },
+    ClearImage {
+        image: n::Image,
+        layout: image::Layout,
+        texel: Vec<u8>,
+        ranges: Vec<image::SubresourceRange>,
+    },
+    Dispatch {
+        state: ComputeState,
+        base: hal::WorkGroupCount,
+        count: hal::WorkGroupCount,
+    },
+    DispatchIndirect {
+        state: ComputeState,
+        buffer: n::Buffer,
+        offset: u64,
+    },
+    ResetQueries {
+        results: Arc<Mutex<Vec<Option<u64>>>>,
+        queries: Range<query::QueryId>,
+    },
+    WriteQuery {
+        results: Arc<Mutex<Vec<Option<u64>>>>,
+        id: query::QueryId,
+        value: QueryValue,
+    },
+}
+
+/// Compute bindings captured by a dispatch.
+#[derive(Clone, Debug, Default)]
+struct ComputeState {
+    pipeline: Option<n::ComputePipeline>,
+    sets: Vec<Option<n::DescriptorSet>>,
+    push_constants: Vec<u32>,
+}
+
+/// Command buffer recording the commands for execution on submission.
+#[derive(Clone, Debug)]
+pub struct CommandBuffer {
+    commands: Vec<Command>,
+    compute: ComputeState,
+    graphics_pipeline_bound: bool,
+    /// Attachment views and final layouts of the current render pass instance.
+    render_pass: Option<Vec<(n::ImageView, image::Layout)>>,
+    pub(crate) statistics: CommandBufferStatistics,
+}
+
+impl CommandBuffer {
+    fn new() -> Self {
+        CommandBuffer {
+            commands: Vec::new(),
+            compute: ComputeState::default(),
+            graphics_pipeline_bound: false,
+            render_pass: None,
+            statistics: CommandBufferStatistics::default(),
+        }
+    }
+
+    fn assert_draw(&self) {
+        assert!(self.graphics_pipeline_bound, "Draw call without a bound graphics pipeline");
+        assert!(self.render_pass.is_some(), "Draw call outside of a render pass");
+    }
+
+    fn begin_pass(&mut self, attachments: Vec<(n::ImageView, Range<image::Layout>)>) {
+        assert!(self.render_pass.is_none(), "Render pass begun inside of another one");
+        let mut final_layouts = Vec::with_capacity(attachments.len());
+        for (view, layouts) in attachments {
+            self.commands.push(Command::Transition {
+                image: view.image.clone(),
+                range: view.range.clone(),
+                layouts: layouts.start .. layouts.start,
+            });
+            final_layouts.push((view, layouts.end));
+        }
+        self.render_pass = Some(final_layouts);
+    }
+
+    /// Execute the recorded commands, panicking on invalid usage.
+    pub(crate) fn execute(&self, shared: &Shared) {
+        for command in &self.commands {
+            match *command {
+                Command::Transition { ref image, ref range, ref layouts } => {
+                    image.transition(range, layouts.clone());
+                }
+                Command::FillBuffer { ref dst, ref range, data } => {
+                    assert_eq!((range.end - range.start) % 4, 0, "Fill size has to be a multiple of 4");
+                    let range = dst.range(range.clone());
+                    let bytes: Vec<u8> = (0 .. range.end - range.start)
+                        .map(|i| (data >> (8 * (i % 4))) as u8)
+                        .collect();
+                    dst.memory.write(range.start, &bytes);
+                }
+                Command::UpdateBuffer { ref dst, offset, ref data } => {
+                    let range = dst.range(offset .. offset + data.len() as u64);
+                    dst.memory.write(range.start, data);
+                }
+                Command::CopyBuffer { ref src, ref dst, ref regions } => {
+                    for region in regions {
+                        let src_range = src.range(region.src .. region.src + region.size);
+                        let dst_range = dst.range(region.dst .. region.dst + region.size);
+                        let mut data = vec![0; region.size as usize];
+                        src.memory.read(src_range.start, &mut data);
+                        dst.memory.write(dst_range.start, &data);
+                    }
+                }
+                Command::CopyImage { ref src, src_layout, ref dst, dst_layout, ref regions } => {
+                    for region in regions {
+                        copy_image_region(src, src_layout, dst, dst_layout, region);
+                    }
+                }
+                Command::BlitImage { ref src, src_layout, ref dst, dst_layout, ref regions } => {
+                    for region in regions {
+                        blit_image_region(src, src_layout, dst, dst_layout, region);
+                    }
+                }
+                Command::CopyBufferToImage { ref src, ref dst, dst_layout, ref regions } => {
+                    for region in regions {
+                        copy_buffer_image_region(src, dst, dst_layout, region, true);
+                    }
+                }
+                Command::CopyImageToBuffer { ref src, src_layout, ref dst, ref regions } => {
+                    for region in regions {
+                        copy_buffer_image_region(dst, src, src_layout, region, false);
+                    }
+                }
+                Command::ClearImage { ref image, layout, ref texel, ref ranges } => {
+                    for range in ranges {
+                        for level in range.levels.clone() {
+                            image.validate_layout(level, range.layers.clone(), layout, COPY_DST_LAYOUTS, "clear_image");
+                            for layer in range.layers.clone() {
+                                let slice = image.info.footprint(level, layer).slice;
+                                let data: Vec<u8> = texel
+                                    .iter()
+                                    .cloned()
+                                    .cycle()
+                                    .take((slice.end - slice.start) as usize)
+                                    .collect();
+                                image.memory.write(image.offset + slice.start, &data);
+                            }
+                        }
+                    }
+                }
+                Command::Dispatch { ref state, base, count } => {
+                    dispatch(state, base, count);
+                }
+                Command::DispatchIndirect { ref state, ref buffer, offset } => {
+                    let range = buffer.range(offset .. offset + 12);
+                    let count = unsafe {
+                        ptr::read_unaligned(buffer.memory.ptr(range.start, 12) as *const hal::WorkGroupCount)
+                    };
+                    dispatch(state, [0; 3], count);
+                }
+                Command::ResetQueries { ref results, ref queries } => {
+                    let mut results = results.lock().unwrap();
+                    for result in &mut results[queries.start as usize .. queries.end as usize] {
+                        *result = None;
+                    }
+                }
+                Command::WriteQuery { ref results, id, ref value } => {
+                    let value = match *value {
+                        QueryValue::Zero => 0,
+                        QueryValue::Timestamp => {
+                            let elapsed = shared.timestamp_origin.elapsed();
+                            elapsed.as_secs() * 1_000_000_000 + elapsed.subsec_nanos() as u64
+                        }
+                    };
+                    results.lock().unwrap()[id as usize] = Some(value);
+                }
+            }
+        }
+    }
+}
+
+/// Invoke the kernel of the bound pipeline once per workgroup.
+fn dispatch(state: &ComputeState, base: hal::WorkGroupCount, count: hal::WorkGroupCount) {
+    let pipeline = state.pipeline.as_ref().unwrap();
+    for z in 0 .. count[2] {
+        for y in 0 .. count[1] {
+            for x in 0 .. count[0] {
+                (pipeline.kernel)(&n::KernelContext {
+                    workgroup_id: [base[0] + x, base[1] + y, base[2] + z],
+                    workgroup_count: count,
+                    sets: &state.sets,
+                    push_constants: &state.push_constants,
+                });
+            }
+        }
+    }
+}
+
+fn blocks(texels: u32, block_dim: u8) -> u64 {
+    (texels as u64 + block_dim as u64 - 1) / block_dim as u64
+}
+
+/// Byte offset of the texel block containing `offset` in the memory of an image.
+fn texel_offset(image: &n::Image, level: image::Level, layer: image::Layer, offset: image::Offset) -> u64 {
+    let extent = image.info.kind.level_extent(level);
+    assert!(offset.x >= 0 && offset.y >= 0 && offset.z >= 0 &&
+        (offset.x as u32) < extent.width && (offset.y as u32) < extent.height && (offset.z as u32) < extent.depth,
+        "Texel {:?} is out of bounds of level {} with {:?}", offset, level, extent);
+    let dim = image.info.format.surface_desc().dim;
+    let footprint = image.info.footprint(level, layer);
+    image.offset + footprint.slice.start
+        + offset.z as u64 * footprint.depth_pitch
+        + offset.y as u64 / dim.1 as u64 * footprint.row_pitch
+        + offset.x as u64 / dim.0 as u64 * image.info.texel_size()
+}
+
+/// Check that a region ending at `offset + extent` lies within a level of the image.
+fn validate_region(image: &n::Image, level: image::Level, offset: image::Offset, extent: image::Extent) {
+    let level_extent = image.info.kind.level_extent(level);
+    assert!(offset.x >= 0 && offset.y >= 0 && offset.z >= 0 &&
+        offset.x as u32 + extent.width <= level_extent.width &&
+        offset.y as u32 + extent.height <= level_extent.height &&
+        offset.z as u32 + extent.depth <= level_extent.depth,
+        "Region {:?} of {:?} is out of bounds of level {} with {:?}", offset, extent, level, level_extent);
+}
+
+fn copy_image_region(
+    src: &n::Image, src_layout: image::Layout, dst: &n::Image, dst_layout: image::Layout, region: &com::ImageCopy,
+) {
+    let src_sub = &region.src_subresource;
+    let dst_sub = &region.dst_subresource;
+    assert_eq!(src_sub.layers.end - src_sub.layers.start, dst_sub.layers.end - dst_sub.layers.start,
+        "Copies have to cover the same number of layers");
+    assert_eq!(src.info.texel_size(), dst.info.texel_size(), "Copies have to be between size-compatible formats");
+    src.validate_layout(src_sub.level, src_sub.layers.clone(), src_layout, COPY_SRC_LAYOUTS, "copy_image");
+    dst.validate_layout(dst_sub.level, dst_sub.layers.clone(), dst_layout, COPY_DST_LAYOUTS, "copy_image");
+    validate_region(src, src_sub.level, region.src_offset, region.extent);
+    validate_region(dst, dst_sub.level, region.dst_offset, region.extent);
+
+    let dim = src.info.format.surface_desc().dim;
+    let mut row = vec![0; (blocks(region.extent.width, dim.0) * src.info.texel_size()) as usize];
+    for (src_layer, dst_layer) in src_sub.layers.clone().zip(dst_sub.layers.clone()) {
+        for z in 0 .. region.extent.depth as i32 {
+            for y in 0 .. blocks(region.extent.height, dim.1) as i32 {
+                let y = y * dim.1 as i32;
+                let src_offset = image::Offset { y: region.src_offset.y + y, z: region.src_offset.z + z, .. region.src_offset };
+                let dst_offset = image::Offset { y: region.dst_offset.y + y, z: region.dst_offset.z + z, .. region.dst_offset };
+                src.memory.read(texel_offset(src, src_sub.level, src_layer, src_offset), &mut row);
+                dst.memory.write(texel_offset(dst, dst_sub.level, dst_layer, dst_offset), &row);
+            }
+        }
+    }
+}
+
+/// Map the texel `index` along one axis of the destination bounds to the source bounds.
+fn blit_coordinate(index: i32, dst: Range<i32>, src: Range<i32>) -> i32 {
+    let dst_start = cmp::min(dst.start, dst.end);
+    let t = ((dst_start + index) as f32 + 0.5 - dst.start as f32) / (dst.end - dst.start) as f32;
+    let coordinate = (src.start as f32 + t * (src.end - src.start) as f32).floor() as i32;
+    cmp::max(cmp::min(src.start, src.end), cmp::min(coordinate, cmp::max(src.start, src.end) - 1))
+}
+
+fn blit_image_region(
+    src: &n::Image, src_layout: image::Layout, dst: &n::Image, dst_layout: image::Layout, region: &com::ImageBlit,
+) {
+    let src_sub = &region.src_subresource;
+    let dst_sub = &region.dst_subresource;
+    assert_eq!(src.info.format, dst.info.format, "Blits between different formats are not supported");
+    assert!(!src.info.format.surface_desc().is_compressed(), "Blits of compressed formats are not allowed");
+    src.validate_layout(src_sub.level, src_sub.layers.clone(), src_layout, COPY_SRC_LAYOUTS, "blit_image");
+    dst.validate_layout(dst_sub.level, dst_sub.layers.clone(), dst_layout, COPY_DST_LAYOUTS, "blit_image");
+
+    let (src_bounds, dst_bounds) = (&region.src_bounds, &region.dst_bounds);
+    let mut texel = vec![0; src.info.texel_size() as usize];
+    for (src_layer, dst_layer) in src_sub.layers.clone().zip(dst_sub.layers.clone()) {
+        for z in 0 .. (dst_bounds.end.z - dst_bounds.start.z).abs() {
+            for y in 0 .. (dst_bounds.end.y - dst_bounds.start.y).abs() {
+                for x in 0 .. (dst_bounds.end.x - dst_bounds.start.x).abs() {
+                    let src_offset = image::Offset {
+                        x: blit_coordinate(x, dst_bounds.start.x .. dst_bounds.end.x, src_bounds.start.x .. src_bounds.end.x),
+                        y: blit_coordinate(y, dst_bounds.start.y .. dst_bounds.end.y, src_bounds.start.y .. src_bounds.end.y),
+                        z: blit_coordinate(z, dst_bounds.start.z .. dst_bounds.end.z, src_bounds.start.z .. src_bounds.end.z),
+                    };
+                    let dst_offset = image::Offset {
+                        x: cmp::min(dst_bounds.start.x, dst_bounds.end.x) + x,
+                        y: cmp::min(dst_bounds.start.y, dst_bounds.end.y) + y,
+                        z: cmp::min(dst_bounds.start.z, dst_bounds.end.z) + z,
+                    };
+                    src.memory.read(texel_offset(src, src_sub.level, src_layer, src_offset), &mut texel);
+                    dst.memory.write(texel_offset(dst, dst_sub.level, dst_layer, dst_offset), &texel);
+                }
+            }
+        }
+    }
+}
+
+/// Copy a region between a buffer and an image, in the direction given by `to_image`.
+fn copy_buffer_image_region(
+    buffer: &n::Buffer, image: &n::Image, layout: image::Layout, region: &com::BufferImageCopy, to_image: bool,
+) {
+    let sub = &region.image_layers;
+    if to_image {
+        image.validate_layout(sub.level, sub.layers.clone(), layout, COPY_DST_LAYOUTS, "copy_buffer_to_image");
+    } else {
+        image.validate_layout(sub.level, sub.layers.clone(), layout, COPY_SRC_LAYOUTS, "copy_image_to_buffer");
+    }
+    validate_region(image, sub.level, region.image_offset, region.image_extent);
+
+    let dim = image.info.format.surface_desc().dim;
+    let texel_size = image.info.texel_size();
+    let buffer_width = if region.buffer_width == 0 { region.image_extent.width } else { region.buffer_width };
+    let buffer_height = if region.buffer_height == 0 { region.image_extent.height } else { region.buffer_height };
+    let row_pitch = blocks(buffer_width, dim.0) * texel_size;
+    let slice_pitch = blocks(buffer_height, dim.1) * row_pitch;
+    let layer_pitch = slice_pitch * region.image_extent.depth as u64;
+
+    let mut row = vec![0; (blocks(region.image_extent.width, dim.0) * texel_size) as usize];
+    for (i, layer) in sub.layers.clone().enumerate() {
+        for z in 0 .. region.image_extent.depth {
+            for y in 0 .. blocks(region.image_extent.height, dim.1) {
+                let offset = region.buffer_offset + i as u64 * layer_pitch + z as u64 * slice_pitch + y * row_pitch;
+                let buffer_offset = buffer.range(offset .. offset + row.len() as u64).start;
+                let image_offset = texel_offset(image, sub.level, layer, image::Offset {
+                    y: region.image_offset.y + y as i32 * dim.1 as i32,
+                    z: region.image_offset.z + z as i32,
+                    .. region.image_offset
+                });
+                if to_image {
+                    buffer.memory.read(buffer_offset, &mut row);
+                    image.memory.write(image_offset, &row);
+                } else {
+                    image.memory.read(image_offset, &mut row);
+                    buffer.memory.write(buffer_offset, &row);
+                }
+            }
+        }
+    }
+}
+
+fn linear_to_srgb(value: f32) -> f32 {
+    if value <= 0.0031308 {
+        value * 12.92
+    } else {
+        1.055 * value.powf(1.0 / 2.4) - 0.055
+    }
+}
+
+/// Append the `width` lower bytes of `value` in little endian order.
+fn push_bytes(texel: &mut Vec<u8>, value: u64, width: usize) {
+    texel.extend((0 .. width).map(|i| (value >> (8 * i)) as u8));
+}
+
+/// Encode a clear value into the texel representation of `format`.
+// Texel of a clear value, `None` if clearing the format isn't supported.
+fn clear_texel(format: format::Format, color: com::ClearColorRaw, depth_stencil: com::ClearDepthStencilRaw) -> Option<Vec<u8>> {
+    let base = format.base_format();
+    let mut texel = Vec::new();
+
+    let depth_unorm = |bits: u32| (depth_stencil.depth.max(0.0).min(1.0) * ((1u64 << bits) - 1) as f32).round() as u64;
+    match base.0 {
+        SurfaceType::D16 => push_bytes(&mut texel, depth_unorm(16), 2),
+        SurfaceType::D32 => push_bytes(&mut texel, depth_stencil.depth.to_bits() as u64, 4),
+        SurfaceType::S8 => push_bytes(&mut texel, depth_stencil.stencil as u64, 1),
+        SurfaceType::D16_S8 => {
+            push_bytes(&mut texel, depth_unorm(16), 2);
+            push_bytes(&mut texel, depth_stencil.stencil as u64, 1);
+        }
+        SurfaceType::D24_S8 => {
+            push_bytes(&mut texel, depth_unorm(24), 3);
+            push_bytes(&mut texel, depth_stencil.stencil as u64, 1);
+        }
+        SurfaceType::D32_S8 => {
+            push_bytes(&mut texel, depth_stencil.depth.to_bits() as u64, 4);
+            push_bytes(&mut texel, depth_stencil.stencil as u64, 1);
+        }
+        _ => {
+            let (width, channels): (usize, &[usize]) = match base.0 {
+                SurfaceType::R8 => (1, &[0]),
+                SurfaceType::R8_G8 => (1, &[0, 1]),
+                SurfaceType::R8_G8_B8 => (1, &[0, 1, 2]),
+                SurfaceType::B8_G8_R8 => (1, &[2, 1, 0]),
+                SurfaceType::R8_G8_B8_A8 => (1, &[0, 1, 2, 3]),
+                SurfaceType::B8_G8_R8_A8 => (1, &[2, 1, 0, 3]),
+                SurfaceType::R16 => (2, &[0]),
+                SurfaceType::R16_G16 => (2, &[0, 1]),
+                SurfaceType::R16_G16_B16 => (2, &[0, 1, 2]),
+                SurfaceType::R16_G16_B16_A16 => (2, &[0, 1, 2, 3]),
+                SurfaceType::R32 => (4, &[0]),
+                SurfaceType::R32_G32 => (4, &[0, 1]),
+                SurfaceType::R32_G32_B32 => (4, &[0, 1, 2]),
+                SurfaceType::R32_G32_B32_A32 => (4, &[0, 1, 2, 3]),
+                _ => return None,
+            };
+            let max = ((1u64 << (8 * width)) - 1) as f32;
+            for &c in channels {
+                let (float, int, uint) = unsafe { (color.float32[c], color.int32[c], color.uint32[c]) };
+                let value = match (width, base.1) {
+                    // 32 bit channels are stored as given, regardless of the type
+                    (4, _) => uint as u64,
+                    (_, ChannelType::Unorm) => (float.max(0.0).min(1.0) * max).round() as u64,
+                    // alpha isn't sRGB encoded
+                    (_, ChannelType::Srgb) if c == 3 => (float.max(0.0).min(1.0) * max).round() as u64,
+                    (_, ChannelType::Srgb) => (linear_to_srgb(float.max(0.0).min(1.0)) * max).round() as u64,
+                    (_, ChannelType::Inorm) => (float.max(-1.0).min(1.0) * (max / 2.0).floor()).round() as i64 as u64,
+                    (_, ChannelType::Uint) | (_, ChannelType::Uscaled) => uint as u64,
+                    (_, ChannelType::Int) | (_, ChannelType::Iscaled) => int as i64 as u64,
+                    _ => return None,
+                };
+                push_bytes(&mut texel, value, width);
+            }
+        }
+    }
+    Some(texel)
+}
+
+// Check if images of the format can be cleared with `clear_image`.
+pub(crate) fn supports_clear(format: format::Format) -> bool {
+    let color = com::ClearColorRaw { uint32: [0; 4] };
+    let depth_stencil = com::ClearDepthStencilRaw { depth: 0.0, stencil: 0 };
+    clear_texel(format, color, depth_stencil).is_some()
+}
+
+impl com::RawCommandBuffer<Backend> for CommandBuffer {
+    fn begin(&mut self, _: com::CommandBufferFlags, _: com::CommandBufferInheritanceInfo<Backend>) {
+        self.reset(false);
+    }
+
+    fn finish(&mut self) {
+        assert!(self.render_pass.is_none(), "Command buffer finished inside of a render pass");
+    }
+
+    fn reset(&mut self, _: bool) {
+        *self = CommandBuffer::new();
+    }
+
+    fn pipeline_barrier<'a, T>(
+        &mut self,
+        _: Range<pso::PipelineStage>,
+        _: memory::Dependencies,
+        barriers: T,
+    ) where
+        T: IntoIterator,
+        T::Item: Borrow<memory::Barrier<'a, Backend>>,
+    {
+        self.statistics.commands += 1;
+        self.statistics.frame.barriers += 1;
+        for barrier in barriers {
+            // Execution is serial, so only the image layouts matter.
+            if let memory::Barrier::Image { ref states, target, ref range, .. } = *barrier.borrow() {
+                self.commands.push(Command::Transition {
+                    image: target.clone(),
+                    range: range.clone(),
+                    layouts: states.start.1 .. states.end.1,
+                });
+            }
+        }
+    }
+
+    fn fill_buffer<R>(&mut self, buffer: &n::Buffer, range: R, data: u32)
+    where
+        R: RangeArg<buffer::Offset>,
+    {
+        self.statistics.commands += 1;
+        assert!(buffer.usage.contains(buffer::Usage::TRANSFER_DST), "fill_buffer requires TRANSFER_DST usage");
+        let start = *range.start().unwrap_or(&0);
+        let end = *range.end().unwrap_or(&buffer.size);
+        self.statistics.referenced_memory += end - start;
+        self.commands.push(Command::FillBuffer {
+            dst: buffer.clone(),
+            range: start .. end,
+            data,
+        });
+    }
+
+    fn update_buffer(&mut self, buffer: &n::Buffer, offset: buffer::Offset, data: &[u8]) {
+        self.statistics.commands += 1;
+        assert!(buffer.usage.contains(buffer::Usage::TRANSFER_DST), "update_buffer requires TRANSFER_DST usage");
+        self.statistics.referenced_memory += data.len() as u64;
+        self.commands.push(Command::UpdateBuffer {
+            dst: buffer.clone(),
+            offset,
+            data: data.to_vec(),
+        });
+    }
+
+    fn clear_image<T>(
+        &mut self,
+        image: &n::Image,
+        layout: image::Layout,
+        color: com::ClearColorRaw,
+        depth_stencil: com::ClearDepthStencilRaw,
+        subresource_ranges: T,
+    ) where
+        T: IntoIterator,
+        T::Item: Borrow<image::SubresourceRange>,
+    {
+        self.statistics.commands += 1;
+        assert!(image.info.usage.contains(image::Usage::TRANSFER_DST), "clear_image requires TRANSFER_DST usage");
+        self.commands.push(Command::ClearImage {
+            image: image.clone(),
+            layout,
+            texel: clear_texel(image.info.format, color, depth_stencil).unwrap_or_else(|| {
+                panic!("Clearing images of format {:?} is not supported", image.info.format)
+            }),
+            ranges: subresource_ranges.into_iter().map(|range| range.borrow().clone()).collect(),
+        });
+    }
+
+    fn clear_attachments<T, U>(&mut self, _: T, _: U)
+    where
+        T: IntoIterator,
+        T::Item: Borrow<com::AttachmentClear>,
+        U: IntoIterator,
+        U::Item: Borrow<pso::ClearRect>,
+    {
+        self.statistics.commands += 1;
+        assert!(self.render_pass.is_some(), "clear_attachments outside of a render pass");
+    }
+
+    fn resolve_image<T>(
+        &mut self,
+        src: &n::Image,
+        src_layout: image::Layout,
+        dst: &n::Image,
+        dst_layout: image::Layout,
+        regions: T,
+    ) where
+        T: IntoIterator,
+        T::Item: Borrow<com::ImageResolve>,
+    {
+        self.statistics.commands += 1;
+        // Images are single sampled, resolving is a plain copy.
+        let regions = regions
+            .into_iter()
+            .map(|region| {
+                let region = region.borrow();
+                com::ImageCopy {
+                    src_subresource: region.src_subresource.clone(),
+                    src_offset: region.src_offset,
+                    dst_subresource: region.dst_subresource.clone(),
+                    dst_offset: region.dst_offset,
+                    extent: region.extent,
+                }
+            })
+            .collect();
+        self.commands.push(Command::CopyImage {
+            src: src.clone(),
+            src_layout,
+            dst: dst.clone(),
+            dst_layout,
+            regions,
+        });
+    }
+
+    fn blit_image<T>(
+        &mut self,
+        src: &n::Image,
+        src_layout: image::Layout,
+        dst: &n::Image,
+        dst_layout: image::Layout,
+        filter: image::Filter,
+        regions: T,
+    ) where
+        T: IntoIterator,
+        T::Item: Borrow<com::ImageBlit>,
+    {
+        self.statistics.commands += 1;
+        assert!(src.info.usage.contains(image::Usage::TRANSFER_SRC), "blit_image requires TRANSFER_SRC usage");
+        assert!(dst.info.usage.contains(image::Usage::TRANSFER_DST), "blit_image requires TRANSFER_DST usage");
+        if filter != image::Filter::Nearest {
+            warn!("Blit filter {:?} is not supported, falling back to nearest filtering", filter);
+        }
+        self.commands.push(Command::BlitImage {
+            src: src.clone(),
+            src_layout,
+            dst: dst.clone(),
+            dst_layout,
+            regions: regions.into_iter().map(|region| region.borrow().clone()).collect(),
+        });
+    }
+
+    fn bind_index_buffer(&mut self, view: buffer::IndexBufferView<Backend>) {
+        self.statistics.commands += 1;
+        assert!(view.buffer.usage.contains(buffer::Usage::INDEX), "Index buffers require INDEX usage");
+        assert!(view.offset <= view.buffer.size, "Index buffer offset is out of the buffer bounds");
+    }
+
+    fn bind_vertex_buffers<I, T>(&mut self, _: u32, buffers: I)
+    where
+        I: IntoIterator<Item = (T, buffer::Offset)>,
+        T: Borrow<n::Buffer>,
+    {
+        self.statistics.commands += 1;
+        for (buffer, offset) in buffers {
+            let buffer = buffer.borrow();
+            assert!(buffer.usage.contains(buffer::Usage::VERTEX), "Vertex buffers require VERTEX usage");
+            assert!(offset <= buffer.size, "Vertex buffer offset is out of the buffer bounds");
+        }
+    }
+
+    fn set_viewports<T>(&mut self, _: u32, _: T)
+    where
+        T: IntoIterator,
+        T::Item: Borrow<pso::Viewport>,
+    {
+        self.statistics.commands += 1;
+    }
+
+    fn set_scissors<T>(&mut self, _: u32, _: T)
+    where
+        T: IntoIterator,
+        T::Item: Borrow<pso::Rect>,
+    {
+        self.statistics.commands += 1;
+    }
+
+    fn set_stencil_reference(&mut self, _: pso::Face, _: pso::StencilValue) {
+        self.statistics.commands += 1;
+    }
+
+    fn set_stencil_read_mask(&mut self, _: pso::Face, _: pso::StencilValue) {
+        self.statistics.commands += 1;
+    }
+
+    fn set_stencil_write_mask(&mut self, _: pso::Face, _: pso::StencilValue) {
+        self.statistics.commands += 1;
+    }
+
+    fn set_blend_constants(&mut self, _: pso::ColorValue) {
+        self.statistics.commands += 1;
+    }
+
+    fn set_depth_bounds(&mut self, _: Range<f32>) {
+        self.statistics.commands += 1;
+    }
+
+    fn set_line_width(&mut self, _: f32) {
+        self.statistics.commands += 1;
+    }
+
+    fn set_depth_bias(&mut self, _: pso::DepthBias) {
+        self.statistics.commands += 1;
+    }
+
+    fn begin_render_pass<T>(
+        &mut self,
+        render_pass: &n::RenderPass,
+        framebuffer: &n::Framebuffer,
+        _: pso::Rect,
+        _: T,
+        _: com::SubpassContents,
+    ) where
+        T: IntoIterator,
+        T::Item: Borrow<com::ClearValueRaw>,
+    {
+        self.statistics.commands += 1;
+        let attachments = framebuffer.attachments
+            .iter()
+            .cloned()
+            .zip(render_pass.attachment_layouts.iter().cloned())
+            .collect();
+        self.begin_pass(attachments);
+    }
+
+    fn begin_render_pass_imageless<T, I>(
+        &mut self,
+        render_pass: &n::RenderPass,
+        _: &n::Framebuffer,
+        attachments: I,
+        _: pso::Rect,
+        _: T,
+        _: com::SubpassContents,
+    ) where
+        T: IntoIterator,
+        T::Item: Borrow<com::ClearValueRaw>,
+        I: IntoIterator,
+        I::Item: Borrow<n::ImageView>,
+    {
+        self.statistics.commands += 1;
+        let attachments = attachments
+            .into_iter()
+            .map(|view| view.borrow().clone())
+            .zip(render_pass.attachment_layouts.iter().cloned())
+            .collect();
+        self.begin_pass(attachments);
+    }
+
+    fn next_subpass(&mut self, _: com::SubpassContents) {
+        self.statistics.commands += 1;
+    }
+
+    fn end_render_pass(&mut self) {
+        self.statistics.commands += 1;
+        let attachments = self.render_pass.take().expect("end_render_pass outside of a render pass");
+        for (view, layout) in attachments {
+            self.commands.push(Command::Transition {
+                image: view.image,
+                range: view.range,
+                layouts: image::Layout::Undefined .. layout,
+            });
+        }
+    }
+
+    fn begin_rendering(&mut self, info: &com::RenderingInfo<Backend>) {
+        self.statistics.commands += 1;
+        // The attachments have to be in the given layouts already, which stay unchanged.
+        let attachments = info.colors
+            .iter()
+            .chain(info.depth_stencil.as_ref())
+            .map(|attachment| (attachment.view.clone(), attachment.layout .. attachment.layout))
+            .collect();
+        self.begin_pass(attachments);
+    }
+
+    fn end_rendering(&mut self) {
+        self.statistics.commands += 1;
+        self.render_pass.take().expect("end_rendering outside of a dynamic rendering scope");
+    }
+
+    fn bind_graphics_pipeline(&mut self, _: &n::GraphicsPipeline) {
+        self.statistics.commands += 1;
+        self.statistics.frame.pipeline_binds += 1;
+        self.graphics_pipeline_bound = true;
+    }
+
+    fn bind_graphics_descriptor_sets<I, J>(&mut self, _: &n::PipelineLayout, _: usize, _: I, _: J)
+    where
+        I: IntoIterator,
+        I::Item: Borrow<n::DescriptorSet>,
+        J: IntoIterator,
+        J::Item: Borrow<com::DescriptorSetOffset>,
+    {
+        self.statistics.commands += 1;
+    }
+
+    fn bind_compute_pipeline(&mut self, pipeline: &n::ComputePipeline) {
+        self.statistics.commands += 1;
+        self.statistics.frame.pipeline_binds += 1;
+        self.compute.pipeline = Some(pipeline.clone());
+    }
+
+    fn bind_compute_descriptor_sets<I, J>(&mut self, _: &n::PipelineLayout, first_set: usize, sets: I, offsets: J)
+    where
+        I: IntoIterator,
+        I::Item: Borrow<n::DescriptorSet>,
+        J: IntoIterator,
+        J::Item: Borrow<com::DescriptorSetOffset>,
+    {
+        self.statistics.commands += 1;
+        if offsets.into_iter().next().is_some() {
+            warn!("Dynamic descriptor offsets are not supported and ignored");
+        }
+        for (i, set) in sets.into_iter().enumerate() {
+            let index = first_set + i;
+            if self.compute.sets.len() <= index {
+                self.compute.sets.resize(index + 1, None);
+            }
+            self.compute.sets[index] = Some(set.borrow().clone());
+        }
+    }
+
+    fn dispatch(&mut self, count: hal::WorkGroupCount) {
+        self.dispatch_base([0; 3], count);
+    }
+
+    fn dispatch_base(&mut self, base: hal::WorkGroupCount, count: hal::WorkGroupCount) {
+        self.statistics.commands += 1;
+        assert!(self.compute.pipeline.is_some(), "Dispatch without a bound compute pipeline");
+        self.statistics.frame.dispatches += 1;
+        self.commands.push(Command::Dispatch {
+            state: self.compute.clone(),
+            base,
+            count,
+        });
+    }
+
+    fn dispatch_indirect(&mut self, buffer: &n::Buffer, offset: buffer::Offset) {
+        self.statistics.commands += 1;
+        assert!(self.compute.pipeline.is_some(), "Dispatch without a bound compute pipeline");
+        assert!(buffer.usage.contains(buffer::Usage::INDIRECT), "Indirect dispatches require INDIRECT usage");
+        self.statistics.frame.dispatches += 1;
+        self.commands.push(Command::DispatchIndirect {
+            state: self.compute.clone(),
+            buffer: buffer.clone(),
+            offset,
+        });
+    }
+
+    fn copy_buffer<T>(&mut self, src: &n::Buffer, dst: &n::Buffer, regions: T)
+    where
+        T: IntoIterator,
+        T::Item: Borrow<com::BufferCopy>,
+    {
+        self.statistics.commands += 1;
+        assert!(src.usage.contains(buffer::Usage::TRANSFER_SRC), "copy_buffer requires TRANSFER_SRC usage");
+        assert!(dst.usage.contains(buffer::Usage::TRANSFER_DST), "copy_buffer requires TRANSFER_DST usage");
+        let regions: Vec<_> = regions.into_iter().map(|region| *region.borrow()).collect();
+        // Both the source and the destination range are referenced.
+        self.statistics.referenced_memory += regions.iter().map(|region| 2 * region.size).sum::<u64>();
+        self.commands.push(Command::CopyBuffer {
+            src: src.clone(),
+            dst: dst.clone(),
+            regions,
+        });
+    }
+
+    fn copy_image<T>(
+        &mut self,
+        src: &n::Image,
+        src_layout: image::Layout,
+        dst: &n::Image,
+        dst_layout: image::Layout,
+        regions: T,
+    ) where
+        T: IntoIterator,
+        T::Item: Borrow<com::ImageCopy>,
+    {
+        self.statistics.commands += 1;
+        assert!(src.info.usage.contains(image::Usage::TRANSFER_SRC), "copy_image requires TRANSFER_SRC usage");
+        assert!(dst.info.usage.contains(image::Usage::TRANSFER_DST), "copy_image requires TRANSFER_DST usage");
+        self.commands.push(Command::CopyImage {
+            src: src.clone(),
+            src_layout,
+            dst: dst.clone(),
+            dst_layout,
+            regions: regions.into_iter().map(|region| region.borrow().clone()).collect(),
+        });
+    }
+
+    fn copy_buffer_to_image<T>(
+        &mut self,
+        src: &n::Buffer,
+        dst: &n::Image,
+        dst_layout: image::Layout,
+        regions: T,
+    ) where
+        T: IntoIterator,
+        T::Item: Borrow<com::BufferImageCopy>,
+    {
+        self.statistics.commands += 1;
+        assert!(src.usage.contains(buffer::Usage::TRANSFER_SRC), "copy_buffer_to_image requires TRANSFER_SRC usage");
+        assert!(dst.info.usage.contains(image::Usage::TRANSFER_DST), "copy_buffer_to_image requires TRANSFER_DST usage");
+        self.commands.push(Command::CopyBufferToImage {
+            src: src.clone(),
+            dst: dst.clone(),
+            dst_layout,
+            regions: regions.into_iter().map(|region| region.borrow().clone()).collect(),
+        });
+    }
+
+    fn copy_image_to_buffer<T>(
+        &mut self,
+        src: &n::Image,
+        src_layout: image::Layout,
+        dst: &n::Buffer,
+        regions: T,
+    ) where
+        T: IntoIterator,
+        T::Item: Borrow<com::BufferImageCopy>,
+    {
+        self.statistics.commands += 1;
+        assert!(src.info.usage.contains(image::Usage::TRANSFER_SRC), "copy_image_to_buffer requires TRANSFER_SRC usage");
+        assert!(dst.usage.contains(buffer::Usage::TRANSFER_DST), "copy_image_to_buffer requires TRANSFER_DST usage");
+        self.commands.push(Command::CopyImageToBuffer {
+            src: src.clone(),
+            src_layout,
+            dst: dst.clone(),
+            regions: regions.into_iter().map(|region| region.borrow().clone()).collect(),
+        });
+    }
+
+    fn draw(&mut self, _: Range<hal::VertexCount>, _: Range<hal::InstanceCount>) {
+        self.statistics.commands += 1;
+        self.assert_draw();
+        self.statistics.frame.draw_calls += 1;
+    }
+
+    fn draw_indexed(&mut self, _: Range<hal::IndexCount>, _: hal::VertexOffset, _: Range<hal::InstanceCount>) {
+        self.statistics.commands += 1;
+        self.assert_draw();
+        self.statistics.frame.draw_calls += 1;
+    }
+
+    fn draw_indirect(&mut self, buffer: &n::Buffer, _: buffer::Offset, _: hal::DrawCount, _: u32) {
+        self.statistics.commands += 1;
+        assert!(buffer.usage.contains(buffer::Usage::INDIRECT), "Indirect draws require INDIRECT usage");
+        self.assert_draw();
+        self.statistics.frame.draw_calls += 1;
+    }
+
+    fn draw_indexed_indirect(&mut self, buffer: &n::Buffer, _: buffer::Offset, _: hal::DrawCount, _: u32) {
+        self.statistics.commands += 1;
+        assert!(buffer.usage.contains(buffer::Usage::INDIRECT), "Indirect draws require INDIRECT usage");
+        self.assert_draw();
+        self.statistics.frame.draw_calls += 1;
+    }
+
+    fn begin_query(&mut self, _: query::Query<Backend>, _: query::QueryControl) {
+        self.statistics.commands += 1;
+    }
+
+    fn end_query(&mut self, query: query::Query<Backend>) {
+        self.statistics.commands += 1;
+        self.commands.push(Command::WriteQuery {
+            results: query.pool.results.clone(),
+            id: query.id,
+            value: QueryValue::Zero,
+        });
+    }
+
+    fn reset_query_pool(&mut self, pool: &n::QueryPool, queries: Range<query::QueryId>) {
+        self.statistics.commands += 1;
+        self.commands.push(Command::ResetQueries {
+            results: pool.results.clone(),
+            queries,
+        });
+    }
+
+    fn write_timestamp(&mut self, _: pso::PipelineStage, query: query::Query<Backend>) {
+        self.statistics.commands += 1;
+        self.commands.push(Command::WriteQuery {
+            results: query.pool.results.clone(),
+            id: query.id,
+            value: QueryValue::Timestamp,
+        });
+    }
+
+    fn push_graphics_constants(&mut self, _: &n::PipelineLayout, _: pso::ShaderStageFlags, _: u32, _: &[u32]) {
+        self.statistics.commands += 1;
+    }
+
+    fn push_compute_constants(&mut self, layout: &n::PipelineLayout, offset: u32, constants: &[u32]) {
+        self.statistics.commands += 1;
+        let offset = offset as usize;
+        let len = cmp::max(layout.push_constant_words, offset + constants.len());
+        if self.compute.push_constants.len() < len {
+            self.compute.push_constants.resize(len, 0);
+        }
+        self.compute.push_constants[offset .. offset + constants.len()].copy_from_slice(constants);
+    }
+
+    fn execute_commands<I>(&mut self, buffers: I)
+    where
+        I: IntoIterator,
+        I::Item: Borrow<CommandBuffer>,
+    {
+        for buffer in buffers {
+            let buffer = buffer.borrow();
+            self.commands.extend(buffer.commands.iter().cloned());
+            self.statistics.commands += 1;
+            self.statistics.add_secondary(&buffer.statistics);
+        }
+    }
+
+    fn statistics(&self) -> CommandBufferStatistics {
+        self.statistics
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use hal::Device as HalDevice;
+    use hal::command::RawCommandBuffer;
+    use hal::pso::DescriptorPool;
+    use device::Device;
+    use std::time::Instant;
+
+    fn device() -> Device {
+        Device {
+            shared: Arc::new(Shared {
+                statistics: Default::default(),
+                timestamp_origin: Instant::now(),
+            }),
+        }
+    }
+
+    fn create_buffer(device: &Device, size: u64, usage: buffer::Usage) -> n::Buffer {
+        let buffer = device.create_buffer(size, usage).unwrap();
+        let memory = device.allocate_memory(hal::MemoryTypeId(0), size).unwrap();
+        device.bind_buffer_memory(&memory, 0, buffer).unwrap()
+    }
+
+    fn create_image(device: &Device, width: u32, height: u32, format: format::Format) -> n::Image {
+        let image = device.create_image(
+            image::Kind::D2(width, height, 1, 1),
+            1,
+            format,
+            image::Tiling::Optimal,
+            image::Usage::TRANSFER_SRC | image::Usage::TRANSFER_DST,
+            image::StorageFlags::empty(),
+        ).unwrap();
+        let requirements = device.get_image_requirements(&image);
+        let memory = device.allocate_memory(hal::MemoryTypeId(0), requirements.size).unwrap();
+        device.bind_image_memory(&memory, 0, image).unwrap()
+    }
+
+    fn read_buffer(buffer: &n::Buffer) -> Vec<u8> {
+        let mut data = vec![0; buffer.size as usize];
+        buffer.memory.read(buffer.offset, &mut data);
+        data
+    }
+
+    fn color_range() -> image::SubresourceRange {
+        image::SubresourceRange {
+            aspects: format::Aspects::COLOR,
+            levels: 0 .. 1,
+            layers: 0 .. 1,
+        }
+    }
+
+    fn transition(cmd_buffer: &mut CommandBuffer, image: &n::Image, layouts: Range<image::Layout>) {
+        cmd_buffer.pipeline_barrier(
+            pso::PipelineStage::TOP_OF_PIPE .. pso::PipelineStage::TRANSFER,
+            memory::Dependencies::empty(),
+            Some(memory::Barrier::Image {
+                states: (image::Access::empty(), layouts.start) .. (image::Access::empty(), layouts.end),
+                target: image,
+                range: color_range(),
+                families: None,
+            }),
+        );
+    }
+
+    fn buffer_image_copy(width: u32, height: u32) -> com::BufferImageCopy {
+        com::BufferImageCopy {
+            buffer_offset: 0,
+            buffer_width: 0,
+            buffer_height: 0,
+            image_layers: image::SubresourceLayers {
+                aspects: format::Aspects::COLOR,
+                level: 0,
+                layers: 0 .. 1,
+            },
+            image_offset: image::Offset::ZERO,
+            image_extent: image::Extent { width, height, depth: 1 },
+        }
+    }
+
+    #[test]
+    fn test_copy_buffer() {
+        let device = device();
+        let src = create_buffer(&device, 16, buffer::Usage::TRANSFER_SRC);
+        let dst = create_buffer(&device, 16, buffer::Usage::TRANSFER_DST);
+        src.memory.write(src.offset, &(0 .. 16).collect::<Vec<u8>>());
+
+        let mut cmd_buffer = CommandBuffer::new();
+        cmd_buffer.copy_buffer(&src, &dst, &[
+            com::BufferCopy { src: 0, dst: 8, size: 4 },
+            com::BufferCopy { src: 12, dst: 0, size: 4 },
+        ]);
+        cmd_buffer.execute(&device.shared);
+
+        assert_eq!(read_buffer(&dst), vec![12, 13, 14, 15, 0, 0, 0, 0, 0, 1, 2, 3, 0, 0, 0, 0]);
+    }
+
+    #[test]
+    fn test_copy_buffer_image_round_trip() {
+        let device = device();
+        let usage = buffer::Usage::TRANSFER_SRC | buffer::Usage::TRANSFER_DST;
+        let src = create_buffer(&device, 4 * 4 * 4, usage);
+        let dst = create_buffer(&device, 4 * 4 * 4, usage);
+        let image = create_image(&device, 4, 4, format::Format::Rgba8Unorm);
+        let texels: Vec<u8> = (0 .. 64).collect();
+        src.memory.write(src.offset, &texels);
+
+        let mut cmd_buffer = CommandBuffer::new();
+        transition(&mut cmd_buffer, &image, image::Layout::Undefined .. image::Layout::TransferDstOptimal);
+        cmd_buffer.copy_buffer_to_image(&src, &image, image::Layout::TransferDstOptimal, Some(buffer_image_copy(4, 4)));
+        transition(&mut cmd_buffer, &image, image::Layout::TransferDstOptimal .. image::Layout::TransferSrcOptimal);
+        // Copy the bottom right quadrant back into the start of the buffer.
+        cmd_buffer.copy_image_to_buffer(&image, image::Layout::TransferSrcOptimal, &dst, Some(com::BufferImageCopy {
+            image_offset: image::Offset { x: 2, y: 2, z: 0 },
+            .. buffer_image_copy(2, 2)
+        }));
+        cmd_buffer.execute(&device.shared);
+
+        let data = read_buffer(&dst);
+        assert_eq!(&data[.. 8], &texels[40 .. 48]);
+        assert_eq!(&data[8 .. 16], &texels[56 .. 64]);
+        assert!(data[16 ..].iter().all(|&byte| byte == 0));
+    }
+
+    #[test]
+    fn test_clear_image() {
+        let device = device();
+        let image = create_image(&device, 2, 2, format::Format::Rgba8Unorm);
+        let dst = create_buffer(&device, 2 * 2 * 4, buffer::Usage::TRANSFER_DST);
+
+        let mut cmd_buffer = CommandBuffer::new();
+        transition(&mut cmd_buffer, &image, image::Layout::Undefined .. image::Layout::General);
+        cmd_buffer.clear_image(
+            &image,
+            image::Layout::General,
+            com::ClearColorRaw { float32: [1.0, 0.5, 0.0, 1.0] },
+            com::ClearDepthStencilRaw { depth: 0.0, stencil: 0 },
+            Some(color_range()),
+        );
+        cmd_buffer.copy_image_to_buffer(&image, image::Layout::General, &dst, Some(buffer_image_copy(2, 2)));
+        cmd_buffer.execute(&device.shared);
+
+        assert_eq!(read_buffer(&dst), [255, 128, 0, 255].iter().cloned().cycle().take(16).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_supports_clear() {
+        assert!(supports_clear(format::Format::Rgba8Srgb));
+        assert!(supports_clear(format::Format::D24UnormS8Uint));
+        // Packed formats aren't encoded by `clear_texel`.
+        assert!(!supports_clear(format::Format::R5g6b5Unorm));
+        assert!(!supports_clear(format::Format::A2b10g10r10Unorm));
+        assert!(!supports_clear(format::Format::B10g11r11Ufloat));
+    }
+
+    #[test]
+    #[should_panic(expected = "but it is in Undefined")]
+    fn test_copy_without_transition() {
+        let device = device();
+        let src = create_buffer(&device, 16, buffer::Usage::TRANSFER_SRC);
+        let image = create_image(&device, 2, 2, format::Format::Rgba8Unorm);
+
+        let mut cmd_buffer = CommandBuffer::new();
+        cmd_buffer.copy_buffer_to_image(&src, &image, image::Layout::TransferDstOptimal, Some(buffer_image_copy(2, 2)));
+        cmd_buffer.execute(&device.shared);
+    }
+
+    #[test]
+    #[should_panic(expected = "from layout TransferSrcOptimal, but it is in TransferDstOptimal")]
+    fn test_transition_from_wrong_layout() {
+        let device = device();
+        let image = create_image(&device, 2, 2, format::Format::Rgba8Unorm);
+
+        let mut cmd_buffer = CommandBuffer::new();
+        transition(&mut cmd_buffer, &image, image::Layout::Undefined .. image::Layout::TransferDstOptimal);
+        transition(&mut cmd_buffer, &image, image::Layout::TransferSrcOptimal .. image::Layout::General);
+        cmd_buffer.execute(&device.shared);
+    }
+
+    #[test]
+    fn test_dispatch() {
+        let device = device();
+        let output = create_buffer(&device, 4 * 8, buffer::Usage::STORAGE);
+        let layout = device.create_descriptor_set_layout(
+            &[pso::DescriptorSetLayoutBinding {
+                binding: 0,
+                ty: pso::DescriptorType::StorageBuffer,
+                count: 1,
+                stage_flags: pso::ShaderStageFlags::COMPUTE,
+                immutable_samplers: false,
+            }],
+            &[],
+        );
+        let set = device.create_descriptor_pool(1, &[]).allocate_set(&layout).unwrap();
+        device.write_descriptor_sets(Some(pso::DescriptorSetWrite {
+            set: &set,
+            binding: 0,
+            array_offset: 0,
+            descriptors: Some(pso::Descriptor::Buffer(&output, None .. None)),
+        }));
+        let pipeline_layout = device.create_pipeline_layout(
+            Some(&layout),
+            &[(pso::ShaderStageFlags::COMPUTE, 0 .. 1)],
+        );
+        let module = device.create_kernel_module(|ctx: &n::KernelContext| {
+            let index = ctx.workgroup_id[1] * ctx.workgroup_count[0] + ctx.workgroup_id[0];
+            let output = ctx.buffer(0, 0).unwrap();
+            output.write(index as usize, index * ctx.push_constant(0));
+        });
+        let pipeline = device.create_compute_pipeline(&pso::ComputePipelineDesc::new(
+            pso::EntryPoint { entry: "main", module: &module, specialization: &[] },
+            &pipeline_layout,
+        )).unwrap();
+
+        let mut cmd_buffer = CommandBuffer::new();
+        cmd_buffer.bind_compute_pipeline(&pipeline);
+        cmd_buffer.bind_compute_descriptor_sets(&pipeline_layout, 0, Some(&set), &[]);
+        cmd_buffer.push_compute_constants(&pipeline_layout, 0, &[3]);
+        cmd_buffer.dispatch([4, 2, 1]);
+        // Each command, including the dispatch, is counted once.
+        assert_eq!(cmd_buffer.statistics().commands, 4);
+        assert_eq!(cmd_buffer.statistics().frame.dispatches, 1);
+        cmd_buffer.execute(&device.shared);
+
+        let data = read_buffer(&output);
+        let values: Vec<u32> = data
+            .chunks(4)
+            .map(|bytes| bytes.iter().rev().fold(0, |value, &byte| value << 8 | byte as u32))
+            .collect();
+        assert_eq!(values, (0 .. 8).map(|i| i * 3).collect::<Vec<_>>());
+    }
+}
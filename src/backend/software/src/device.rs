@@ -0,0 +1,544 @@
+use hal::{self, buffer, device as d, error, format, image, mapping, memory, pass, pool, pso, query, queue, window};
+use hal::profiler::FrameStatistics;
+use hal::range::RangeArg;
+use hal::sync::HostTimeline;
+
+use std::borrow::Borrow;
+use std::ops::Range;
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::AtomicBool;
+
+use native as n;
+use command::CommandPool;
+use {Backend as B, Shared, Surface, Swapchain};
+
+/// Alignment of buffers and images in memory, large enough for any element type.
+const RESOURCE_ALIGNMENT: u64 = 16;
+
+pub struct Device {
+    pub(crate) shared: Arc<Shared>,
+}
+
+impl Device {
+    /// Create a shader module executing `kernel` on the CPU for each workgroup of a dispatch.
+    ///
+    /// SPIR-V modules can't be executed by this backend, so compute pipelines have to be
+    /// created from kernel modules. The entry point name and specialization are ignored.
+    pub fn create_kernel_module<F>(&self, kernel: F) -> n::ShaderModule
+    where
+        F: Fn(&n::KernelContext) + Send + Sync + 'static,
+    {
+        n::ShaderModule::Kernel(Arc::new(kernel))
+    }
+}
+
+impl hal::Device<B> for Device {
+    fn allocate_memory(&self, _: hal::MemoryTypeId, size: u64) -> Result<n::Memory, d::OutOfMemory> {
+        Ok(n::Memory {
+            raw: Arc::new(n::RawMemory::new(size as usize)),
+        })
+    }
+
+    fn free_memory(&self, _memory: n::Memory) {
+    }
+
+    fn create_command_pool(&self, _: queue::QueueFamilyId, _: pool::CommandPoolCreateFlags) -> CommandPool {
+        CommandPool
+    }
+
+    fn destroy_command_pool(&self, _: CommandPool) {
+    }
+
+    fn create_render_pass<'a, IA, IS, ID>(&self, attachments: IA, _: IS, _: ID) -> n::RenderPass
+    where
+        IA: IntoIterator,
+        IA::Item: Borrow<pass::Attachment>,
+        IS: IntoIterator,
+        IS::Item: Borrow<pass::SubpassDesc<'a>>,
+        ID: IntoIterator,
+        ID::Item: Borrow<pass::SubpassDependency>,
+    {
+        n::RenderPass {
+            attachment_layouts: attachments
+                .into_iter()
+                .map(|attachment| attachment.borrow().layouts.clone())
+                .collect(),
+        }
+    }
+
+    fn destroy_render_pass(&self, _: n::RenderPass) {
+    }
+
+    fn create_pipeline_layout<IS, IR>(&self, _: IS, push_constant_ranges: IR) -> n::PipelineLayout
+    where
+        IS: IntoIterator,
+        IS::Item: Borrow<n::DescriptorSetLayout>,
+        IR: IntoIterator,
+        IR::Item: Borrow<(pso::ShaderStageFlags, Range<u32>)>,
+    {
+        let push_constant_words = push_constant_ranges
+            .into_iter()
+            .map(|range| range.borrow().1.end as usize)
+            .max()
+            .unwrap_or(0);
+        n::PipelineLayout { push_constant_words }
+    }
+
+    fn destroy_pipeline_layout(&self, _: n::PipelineLayout) {
+    }
+
+    fn create_pipeline_cache(&self, _data: Option<&[u8]>) -> Result<(), d::OutOfMemory> {
+        // Pipelines are not compiled ahead of time.
+        Ok(())
+    }
+
+    fn get_pipeline_cache_data(&self, _cache: &()) -> Result<Vec<u8>, d::OutOfMemory> {
+        Ok(Vec::new())
+    }
+
+    fn merge_pipeline_caches<I>(&self, _target: &(), _sources: I) -> Result<(), d::OutOfMemory>
+    where
+        I: IntoIterator,
+        I::Item: Borrow<()>,
+    {
+        Ok(())
+    }
+
+    fn destroy_pipeline_cache(&self, _cache: ()) {
+    }
+
+    fn create_graphics_pipeline<'a>(
+        &self,
+        _: &pso::GraphicsPipelineDesc<'a, B>,
+    ) -> Result<n::GraphicsPipeline, pso::CreationError> {
+        Ok(n::GraphicsPipeline)
+    }
+
+    fn destroy_graphics_pipeline(&self, _: n::GraphicsPipeline) {
+    }
+
+    fn create_compute_pipeline<'a>(
+        &self,
+        desc: &pso::ComputePipelineDesc<'a, B>,
+    ) -> Result<n::ComputePipeline, pso::CreationError> {
+        match *desc.shader.module {
+            n::ShaderModule::Kernel(ref kernel) => Ok(n::ComputePipeline {
+                kernel: kernel.clone(),
+            }),
+            n::ShaderModule::Spirv(_) => Err(pso::CreationError::Shader(d::ShaderError::CompilationFailed(
+                "SPIR-V can't be executed on the CPU, use `Device::create_kernel_module`".into(),
+            ))),
+        }
+    }
+
+    fn destroy_compute_pipeline(&self, _: n::ComputePipeline) {
+    }
+
+    fn create_framebuffer<I>(
+        &self, _: &n::RenderPass, attachments: I, _: image::Extent,
+    ) -> Result<n::Framebuffer, d::FramebufferError>
+    where
+        I: IntoIterator,
+        I::Item: Borrow<n::ImageView>,
+    {
+        Ok(n::Framebuffer {
+            attachments: attachments.into_iter().map(|view| view.borrow().clone()).collect(),
+        })
+    }
+
+    fn create_imageless_framebuffer<I>(
+        &self, _: &n::RenderPass, _: I, _: image::Extent,
+    ) -> Result<n::Framebuffer, d::FramebufferError>
+    where
+        I: IntoIterator,
+        I::Item: Borrow<pass::FramebufferAttachment>,
+    {
+        Ok(n::Framebuffer {
+            attachments: Vec::new(),
+        })
+    }
+
+    fn destroy_framebuffer(&self, _: n::Framebuffer) {
+    }
+
+    fn create_shader_module(&self, spirv: &[u8]) -> Result<n::ShaderModule, d::ShaderError> {
+        if cfg!(debug_assertions) {
+            pso::validate_spirv(spirv)?;
+        }
+        Ok(n::ShaderModule::Spirv(spirv.to_vec()))
+    }
+
+    fn destroy_shader_module(&self, _: n::ShaderModule) {
+    }
+
+    fn create_buffer(&self, size: u64, usage: buffer::Usage) -> Result<n::UnboundBuffer, buffer::CreationError> {
+        Ok(n::UnboundBuffer { size, usage })
+    }
+
+    fn get_buffer_requirements(&self, buffer: &n::UnboundBuffer) -> memory::Requirements {
+        memory::Requirements {
+            size: buffer.size,
+            alignment: RESOURCE_ALIGNMENT,
+            type_mask: 0x1,
+        }
+    }
+
+    fn bind_buffer_memory(
+        &self, memory: &n::Memory, offset: u64, buffer: n::UnboundBuffer,
+    ) -> Result<n::Buffer, d::BindError> {
+        if offset % RESOURCE_ALIGNMENT != 0 {
+            return Err(d::BindError::WrongMemory);
+        }
+        if offset + buffer.size > memory.raw.size() {
+            return Err(d::BindError::OutOfBounds);
+        }
+        Ok(n::Buffer {
+            memory: memory.raw.clone(),
+            offset,
+            size: buffer.size,
+            usage: buffer.usage,
+        })
+    }
+
+    fn destroy_buffer(&self, _: n::Buffer) {
+    }
+
+    fn create_buffer_view<R: RangeArg<u64>>(
+        &self, buffer: &n::Buffer, _: Option<format::Format>, range: R,
+    ) -> Result<n::BufferView, buffer::ViewCreationError> {
+        let start = *range.start().unwrap_or(&0);
+        let end = *range.end().unwrap_or(&buffer.size);
+        Ok(n::BufferView {
+            buffer: buffer.clone(),
+            range: start .. end,
+        })
+    }
+
+    fn destroy_buffer_view(&self, _: n::BufferView) {
+    }
+
+    fn create_image(
+        &self,
+        kind: image::Kind,
+        levels: image::Level,
+        format: format::Format,
+        _: image::Tiling,
+        usage: image::Usage,
+        _: image::StorageFlags,
+    ) -> Result<n::UnboundImage, image::CreationError> {
+        Ok(n::UnboundImage { kind, levels, format, usage })
+    }
+
+    fn get_image_requirements(&self, image: &n::UnboundImage) -> memory::Requirements {
+        memory::Requirements {
+            size: image.size(),
+            alignment: RESOURCE_ALIGNMENT,
+            type_mask: 0x1,
+        }
+    }
+
+    fn get_image_subresource_footprint(
+        &self, image: &n::Image, subresource: image::Subresource,
+    ) -> image::SubresourceFootprint {
+        image.info.footprint(subresource.level, subresource.layer)
+    }
+
+    fn bind_image_memory(
+        &self, memory: &n::Memory, offset: u64, image: n::UnboundImage,
+    ) -> Result<n::Image, d::BindError> {
+        if offset % RESOURCE_ALIGNMENT != 0 {
+            return Err(d::BindError::WrongMemory);
+        }
+        if offset + image.size() > memory.raw.size() {
+            return Err(d::BindError::OutOfBounds);
+        }
+        let subresources = image.levels as usize * image.kind.num_layers() as usize;
+        Ok(n::Image {
+            info: Arc::new(image),
+            memory: memory.raw.clone(),
+            offset,
+            layouts: Arc::new(Mutex::new(vec![image::Layout::Undefined; subresources])),
+        })
+    }
+
+    fn destroy_image(&self, _: n::Image) {
+    }
+
+    fn create_sparse_buffer(
+        &self, _size: u64, _usage: buffer::Usage,
+    ) -> Result<(n::Buffer, memory::Requirements), buffer::CreationError> {
+        unimplemented!()
+    }
+
+    fn create_sparse_image(
+        &self,
+        _kind: image::Kind,
+        _mip_levels: image::Level,
+        _format: format::Format,
+        _usage: image::Usage,
+        _storage_flags: image::StorageFlags,
+    ) -> Result<(n::Image, hal::sparse::SparseImageRequirements), image::CreationError> {
+        unimplemented!()
+    }
+
+    fn create_image_view(
+        &self,
+        image: &n::Image,
+        _: image::ViewKind,
+        _: format::Format,
+        _: format::Swizzle,
+        range: image::SubresourceRange,
+    ) -> Result<n::ImageView, image::ViewError> {
+        if range.levels.end > image.info.levels || range.layers.end > image.info.kind.num_layers() {
+            return Err(image::ViewError::Level(range.levels.start));
+        }
+        Ok(n::ImageView {
+            image: image.clone(),
+            range,
+        })
+    }
+
+    fn destroy_image_view(&self, _: n::ImageView) {
+    }
+
+    fn create_sampler(&self, _: image::SamplerInfo) -> () {
+    }
+
+    fn destroy_sampler(&self, _: ()) {
+    }
+
+    fn create_descriptor_pool<I>(&self, _: usize, _: I) -> n::DescriptorPool
+    where
+        I: IntoIterator,
+        I::Item: Borrow<pso::DescriptorRangeDesc>,
+    {
+        n::DescriptorPool
+    }
+
+    fn destroy_descriptor_pool(&self, _: n::DescriptorPool) {
+    }
+
+    fn create_descriptor_set_layout<I, J>(&self, bindings: I, _: J) -> n::DescriptorSetLayout
+    where
+        I: IntoIterator,
+        I::Item: Borrow<pso::DescriptorSetLayoutBinding>,
+        J: IntoIterator,
+        J::Item: Borrow<()>,
+    {
+        n::DescriptorSetLayout {
+            bindings: bindings.into_iter().map(|binding| binding.borrow().clone()).collect(),
+        }
+    }
+
+    fn destroy_descriptor_set_layout(&self, _: n::DescriptorSetLayout) {
+    }
+
+    fn write_descriptor_sets<'a, I, J>(&self, write_iter: I)
+    where
+        I: IntoIterator<Item = pso::DescriptorSetWrite<'a, B, J>>,
+        J: IntoIterator,
+        J::Item: Borrow<pso::Descriptor<'a, B>>,
+    {
+        let mut statistics = FrameStatistics::default();
+        for write in write_iter {
+            statistics.descriptor_updates += 1;
+            let mut descriptors = write.set.descriptors.lock().unwrap();
+            let mut binding = write.binding;
+            let mut index = write.array_offset;
+
+            for descriptor in write.descriptors {
+                // spill over the writes onto the next binding
+                while index >= write.set.counts.get(&binding).cloned().unwrap_or(0) {
+                    assert!(write.set.counts.keys().any(|&b| b > binding),
+                        "Descriptor write is out of the set layout bounds");
+                    binding += 1;
+                    index = 0;
+                }
+
+                let descriptor = match *descriptor.borrow() {
+                    pso::Descriptor::Buffer(buffer, ref range) => {
+                        let start = range.start.unwrap_or(0);
+                        let end = range.end.unwrap_or(buffer.size);
+                        n::Descriptor::Buffer(buffer.clone(), start .. end)
+                    }
+                    pso::Descriptor::UniformTexelBuffer(view) |
+                    pso::Descriptor::StorageTexelBuffer(view) => {
+                        n::Descriptor::TexelBuffer(view.buffer.clone(), view.range.clone())
+                    }
+                    pso::Descriptor::Image(..) |
+                    pso::Descriptor::CombinedImageSampler(..) => n::Descriptor::Image,
+                    pso::Descriptor::Sampler(_) => n::Descriptor::Sampler,
+                };
+                descriptors.insert((binding, index), descriptor);
+                index += 1;
+            }
+        }
+        self.shared.statistics.add(&statistics);
+    }
+
+    fn copy_descriptor_sets<'a, I>(&self, copies: I)
+    where
+        I: IntoIterator,
+        I::Item: Borrow<pso::DescriptorSetCopy<'a, B>>,
+    {
+        let mut statistics = FrameStatistics::default();
+        for copy in copies {
+            let copy = copy.borrow();
+            statistics.descriptor_updates += 1;
+            let sources: Vec<_> = {
+                let src = copy.src_set.descriptors.lock().unwrap();
+                (0 .. copy.count)
+                    .map(|i| src.get(&(copy.src_binding, copy.src_array_offset + i)).cloned())
+                    .collect()
+            };
+            let mut dst = copy.dst_set.descriptors.lock().unwrap();
+            for (i, descriptor) in sources.into_iter().enumerate() {
+                let key = (copy.dst_binding, copy.dst_array_offset + i);
+                match descriptor {
+                    Some(descriptor) => { dst.insert(key, descriptor); }
+                    None => { dst.remove(&key); }
+                }
+            }
+        }
+        self.shared.statistics.add(&statistics);
+    }
+
+    fn map_memory<R>(&self, memory: &n::Memory, range: R) -> Result<*mut u8, mapping::Error>
+    where
+        R: RangeArg<u64>,
+    {
+        let start = *range.start().unwrap_or(&0);
+        let end = *range.end().unwrap_or(&memory.raw.size());
+        if start > end || end > memory.raw.size() {
+            return Err(mapping::Error::OutOfBounds);
+        }
+        Ok(memory.raw.ptr(start, end - start))
+    }
+
+    fn unmap_memory(&self, _: &n::Memory) {
+    }
+
+    fn flush_mapped_memory_ranges<'a, I, R>(&self, _: I)
+    where
+        I: IntoIterator,
+        I::Item: Borrow<(&'a n::Memory, R)>,
+        R: RangeArg<u64>,
+    {
+        // All memory is coherent.
+    }
+
+    fn invalidate_mapped_memory_ranges<'a, I, R>(&self, _: I)
+    where
+        I: IntoIterator,
+        I::Item: Borrow<(&'a n::Memory, R)>,
+        R: RangeArg<u64>,
+    {
+        // All memory is coherent.
+    }
+
+    fn create_semaphore(&self) -> () {
+    }
+
+    fn destroy_semaphore(&self, _: ()) {
+    }
+
+    fn create_fence(&self, signaled: bool) -> n::Fence {
+        n::Fence(AtomicBool::new(signaled))
+    }
+
+    fn reset_fence(&self, fence: &n::Fence) {
+        fence.set(false);
+    }
+
+    fn wait_for_fence(&self, fence: &n::Fence, _: u32) -> bool {
+        // Submissions are executed synchronously, waiting wouldn't change the status.
+        fence.is_signaled()
+    }
+
+    fn get_fence_status(&self, fence: &n::Fence) -> bool {
+        fence.is_signaled()
+    }
+
+    fn destroy_fence(&self, _: n::Fence) {
+    }
+
+    fn create_timeline_semaphore(&self, initial_value: u64) -> HostTimeline {
+        HostTimeline::new(initial_value)
+    }
+
+    fn get_timeline_semaphore_value(&self, semaphore: &HostTimeline) -> u64 {
+        semaphore.value()
+    }
+
+    fn signal_timeline_semaphore(&self, semaphore: &HostTimeline, value: u64) {
+        semaphore.signal(value);
+    }
+
+    fn wait_for_timeline_semaphores<'a, I>(&self, semaphores: I, wait: d::WaitFor, timeout_ms: u32) -> bool
+    where
+        I: IntoIterator<Item = (&'a HostTimeline, u64)>,
+    {
+        // Unlike fences, timelines can be signaled from another thread.
+        HostTimeline::wait_many(semaphores, wait, timeout_ms)
+    }
+
+    fn destroy_timeline_semaphore(&self, _: HostTimeline) {
+    }
+
+    fn create_event(&self) -> () {
+        // Events are waited on with full barriers.
+    }
+
+    fn destroy_event(&self, _: ()) {
+    }
+
+    fn create_query_pool(&self, _: query::QueryType, count: u32) -> n::QueryPool {
+        n::QueryPool {
+            results: Arc::new(Mutex::new(vec![None; count as usize])),
+        }
+    }
+
+    fn destroy_query_pool(&self, _: n::QueryPool) {
+    }
+
+    fn get_query_pool_results(
+        &self,
+        pool: &n::QueryPool,
+        queries: Range<query::QueryId>,
+        data: &mut [u64],
+        _wait: bool,
+    ) -> Result<bool, error::HostExecutionError> {
+        let results = pool.results.lock().unwrap();
+        let mut available = true;
+        for (result, out) in results[queries.start as usize .. queries.end as usize].iter().zip(data.iter_mut()) {
+            match *result {
+                Some(value) => *out = value,
+                None => available = false,
+            }
+        }
+        Ok(available)
+    }
+
+    fn create_swapchain(
+        &self,
+        surface: &mut Surface,
+        _: hal::SwapchainConfig,
+        _: Option<Swapchain>,
+        _: &window::Extent2D,
+    ) -> (Swapchain, hal::Backbuffer<B>) {
+        match *surface {}
+    }
+
+    fn destroy_swapchain(&self, swapchain: Swapchain) {
+        match swapchain {}
+    }
+
+    fn wait_idle(&self) -> Result<(), error::HostExecutionError> {
+        // Submissions are executed synchronously.
+        Ok(())
+    }
+
+    fn frame_statistics(&self) -> Option<FrameStatistics> {
+        Some(self.shared.statistics.take())
+    }
+}
@@ -0,0 +1,329 @@
+//! CPU reference implementation, executing submissions synchronously on the host.
+//!
+//! Intended for testing higher level code without a GPU: transfers are byte-exact,
+//! image layout transitions are validated, and compute dispatches run kernels created
+//! with `Device::create_kernel_module`. Draw calls are validated but not rasterized.
+
+#[macro_use]
+extern crate log;
+extern crate gfx_hal as hal;
+
+use std::borrow::Borrow;
+use std::sync::Arc;
+use std::time::Instant;
+
+use hal::{error, format, image, memory, queue};
+use hal::backend::StatisticsCollector;
+use hal::sync::HostTimeline;
+
+pub use command::{CommandBuffer, CommandPool};
+pub use device::Device;
+pub use native::{BufferAccess, Kernel, KernelContext};
+
+mod command;
+mod device;
+mod native;
+
+/// Capacity of the only memory heap, it's allocated lazily from the host.
+const MEMORY_HEAP_SIZE: u64 = 1 << 32;
+/// Largest image extent in any dimension.
+const MAX_IMAGE_DIMENSION: u32 = 1 << 14;
+
+/// CPU backend.
+///
+/// Compute pipelines only run Rust closures, created with `Device::create_kernel_module`.
+/// SPIR-V modules are validated and accepted by graphics pipelines, but creating a
+/// compute pipeline from one fails.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Backend {}
+impl hal::Backend for Backend {
+    type PhysicalDevice = PhysicalDevice;
+    type Device = Device;
+
+    type Surface = Surface;
+    type Swapchain = Swapchain;
+
+    type QueueFamily = QueueFamily;
+    type CommandQueue = CommandQueue;
+    type CommandBuffer = CommandBuffer;
+
+    type Memory = native::Memory;
+    type CommandPool = CommandPool;
+
+    type ShaderModule = native::ShaderModule;
+    type RenderPass = native::RenderPass;
+    type Framebuffer = native::Framebuffer;
+
+    type UnboundBuffer = native::UnboundBuffer;
+    type Buffer = native::Buffer;
+    type BufferView = native::BufferView;
+    type UnboundImage = native::UnboundImage;
+    type Image = native::Image;
+    type ImageView = native::ImageView;
+    type Sampler = ();
+
+    type ComputePipeline = native::ComputePipeline;
+    type GraphicsPipeline = native::GraphicsPipeline;
+    type PipelineLayout = native::PipelineLayout;
+    type PipelineCache = ();
+    type DescriptorSetLayout = native::DescriptorSetLayout;
+    type DescriptorPool = native::DescriptorPool;
+    type DescriptorSet = native::DescriptorSet;
+
+    type Fence = native::Fence;
+    type Semaphore = ();
+    type TimelineSemaphore = HostTimeline;
+    type Event = ();
+    type QueryPool = native::QueryPool;
+}
+
+/// State shared between the device and its queues.
+#[derive(Debug)]
+pub(crate) struct Shared {
+    pub(crate) statistics: StatisticsCollector,
+    /// Reference point of timestamp queries, which are reported in nanoseconds.
+    pub(crate) timestamp_origin: Instant,
+}
+
+pub struct Instance;
+
+impl Instance {
+    pub fn create(_name: &str, _version: u32) -> Self {
+        Instance
+    }
+}
+
+impl hal::Instance for Instance {
+    type Backend = Backend;
+    fn enumerate_adapters(&self) -> Vec<hal::Adapter<Backend>> {
+        vec![hal::Adapter {
+            info: hal::AdapterInfo {
+                name: "Software".to_owned(),
+                vendor: 0,
+                device: 0,
+                software_rendering: true,
+                device_type: hal::adapter::DeviceType::Cpu,
+            },
+            physical_device: PhysicalDevice,
+            queue_families: vec![QueueFamily],
+        }]
+    }
+}
+
+pub struct PhysicalDevice;
+impl hal::PhysicalDevice<Backend> for PhysicalDevice {
+    fn open(
+        &self, families: &[(&QueueFamily, &[hal::QueuePriority])],
+    ) -> Result<hal::Gpu<Backend>, error::DeviceCreationError> {
+        let shared = Arc::new(Shared {
+            statistics: StatisticsCollector::default(),
+            timestamp_origin: Instant::now(),
+        });
+
+        let queues = families
+            .iter()
+            .map(|&(family, priorities)| {
+                let mut group = hal::backend::RawQueueGroup::new(family.clone());
+                for _ in 0 .. priorities.len() {
+                    group.add_queue(CommandQueue {
+                        shared: shared.clone(),
+                    });
+                }
+                group
+            })
+            .collect();
+
+        Ok(hal::Gpu {
+            device: Device { shared },
+            queues: queue::Queues::new(queues),
+        })
+    }
+
+    fn format_properties(&self, format: Option<format::Format>) -> format::Properties {
+        let format = match format {
+            Some(format) => format,
+            None => return format::Properties::default(),
+        };
+        let desc = format.surface_desc();
+
+        // Images are plain memory, so every tiling supports the same features. Views can be
+        // bound as descriptors of any format, but only copies, blits and clears actually
+        // touch the texels. Attachments are limited to the formats which can be cleared.
+        let mut image_features = format::ImageFeature::SAMPLED | format::ImageFeature::STORAGE;
+        let clear = command::supports_clear(format);
+        if format.is_color() && clear {
+            image_features |= format::ImageFeature::COLOR_ATTACHMENT | format::ImageFeature::COLOR_ATTACHMENT_BLEND;
+        }
+        if (format.is_depth() || format.is_stencil()) && clear {
+            image_features |= format::ImageFeature::DEPTH_STENCIL_ATTACHMENT;
+        }
+        if !desc.is_compressed() {
+            image_features |= format::ImageFeature::BLIT_SRC | format::ImageFeature::BLIT_DST;
+        }
+
+        // Kernels read and write texel buffers as raw memory, which works for every format.
+        let buffer_features = format::BufferFeature::UNIFORM_TEXEL
+            | format::BufferFeature::STORAGE_TEXEL
+            | format::BufferFeature::STORAGE_TEXEL_READ
+            | format::BufferFeature::VERTEX;
+
+        format::Properties {
+            linear_tiling: image_features,
+            optimal_tiling: image_features,
+            buffer_features,
+        }
+    }
+
+    fn image_format_properties(
+        &self, _: format::Format, dimensions: u8, _: image::Tiling,
+        _: image::Usage, _: image::StorageFlags,
+    ) -> Option<image::FormatProperties> {
+        Some(image::FormatProperties {
+            max_extent: image::Extent {
+                width: MAX_IMAGE_DIMENSION,
+                height: if dimensions >= 2 { MAX_IMAGE_DIMENSION } else { 1 },
+                depth: if dimensions >= 3 { MAX_IMAGE_DIMENSION } else { 1 },
+            },
+            max_levels: 15,
+            max_layers: 2048,
+            sample_count_mask: 0x1,
+            max_resource_size: MEMORY_HEAP_SIZE as usize,
+        })
+    }
+
+    fn memory_properties(&self) -> hal::MemoryProperties {
+        hal::MemoryProperties {
+            memory_types: vec![hal::MemoryType {
+                properties: memory::Properties::DEVICE_LOCAL
+                    | memory::Properties::CPU_VISIBLE
+                    | memory::Properties::COHERENT
+                    | memory::Properties::CPU_CACHED,
+                heap_index: 0,
+            }],
+            memory_heaps: vec![MEMORY_HEAP_SIZE],
+        }
+    }
+
+    fn features(&self) -> hal::Features {
+        hal::Features::DYNAMIC_RENDERING
+    }
+
+    fn limits(&self) -> hal::Limits {
+        hal::Limits {
+            max_texture_size: MAX_IMAGE_DIMENSION as usize,
+            max_compute_group_count: [65535; 3],
+            // Kernels loop over the invocations of a workgroup themselves, which
+            // isn't bound by any hardware, so the limits match common GPUs.
+            max_compute_group_size: [1024, 1024, 64],
+            min_buffer_copy_offset_alignment: 1,
+            min_buffer_copy_pitch_alignment: 1,
+            min_texel_buffer_offset_alignment: 1,
+            min_uniform_buffer_offset_alignment: 1,
+            min_storage_buffer_offset_alignment: 1,
+            framebuffer_color_samples_count: 1,
+            framebuffer_depth_samples_count: 1,
+            framebuffer_stencil_samples_count: 1,
+            non_coherent_atom_size: 1,
+            .. Default::default()
+        }
+    }
+}
+
+/// The only queue family, supporting all the operations.
+#[derive(Clone, Debug)]
+pub struct QueueFamily;
+impl queue::QueueFamily for QueueFamily {
+    fn queue_type(&self) -> hal::QueueType {
+        hal::QueueType::General
+    }
+    fn max_queues(&self) -> usize {
+        16
+    }
+    fn id(&self) -> queue::QueueFamilyId {
+        queue::QueueFamilyId(0)
+    }
+}
+
+pub struct CommandQueue {
+    shared: Arc<Shared>,
+}
+
+impl queue::RawCommandQueue<Backend> for CommandQueue {
+    unsafe fn submit_raw<IC>(&mut self, submission: queue::RawSubmission<Backend, IC>, fence: Option<&native::Fence>)
+    where
+        IC: IntoIterator,
+        IC::Item: Borrow<CommandBuffer>,
+    {
+        // Everything is executed synchronously, so the waited values have to be signaled
+        // by earlier submissions or the host already. Waiting for a later submission would
+        // block forever.
+        for &(semaphore, value, _) in submission.wait_timeline_semaphores {
+            let current = semaphore.value();
+            assert!(
+                value <= current,
+                "Submission waits for timeline value {}, but the semaphore is at {}. Waits before signals aren't supported by the software backend",
+                value,
+                current
+            );
+        }
+        for cmd_buffer in submission.cmd_buffers {
+            let cmd_buffer = cmd_buffer.borrow();
+            cmd_buffer.execute(&self.shared);
+            self.shared.statistics.add(&cmd_buffer.statistics.frame);
+        }
+        for &(semaphore, value) in submission.signal_timeline_semaphores {
+            semaphore.signal(value);
+        }
+        if let Some(fence) = fence {
+            fence.set(true);
+        }
+    }
+
+    fn present<IS, S, IW>(
+        &mut self, swapchains: IS, _: IW
+    ) -> Result<Option<hal::window::Suboptimal>, hal::window::PresentError>
+    where
+        IS: IntoIterator<Item = (S, hal::SwapImageIndex)>,
+        S: Borrow<Swapchain>,
+        IW: IntoIterator,
+        IW::Item: Borrow<()>,
+    {
+        match swapchains.into_iter().next() {
+            Some((swapchain, _)) => match *swapchain.borrow() {},
+            None => Ok(None),
+        }
+    }
+
+    unsafe fn bind_sparse(&mut self, _info: hal::sparse::SparseBindInfo<Backend>, _fence: Option<&native::Fence>) {
+        unimplemented!()
+    }
+
+    fn wait_idle(&self) -> Result<(), error::HostExecutionError> {
+        Ok(())
+    }
+}
+
+/// Surfaces can't be created, as there is nothing to present to.
+pub enum Surface {}
+impl hal::Surface<Backend> for Surface {
+    fn kind(&self) -> image::Kind {
+        match *self {}
+    }
+
+    fn capabilities(&self, _: &PhysicalDevice) -> hal::SurfaceCapabilities {
+        match *self {}
+    }
+
+    fn supports_queue_family(&self, _: &QueueFamily) -> bool {
+        match *self {}
+    }
+}
+
+pub enum Swapchain {}
+impl hal::Swapchain<Backend> for Swapchain {
+    fn acquire_image(
+        &mut self, _: hal::FrameSync<Backend>
+    ) -> Result<(hal::SwapImageIndex, Option<hal::window::Suboptimal>), hal::window::AcquireError> {
+        match *self {}
+    }
+}
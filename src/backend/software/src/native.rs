@@ -0,0 +1,398 @@
+use hal::{buffer, format, image, pso};
+use hal::backend::FastHashMap;
+use hal::pso::{DescriptorArrayIndex, DescriptorBinding};
+
+use std::cell::UnsafeCell;
+use std::fmt;
+use std::marker::PhantomData;
+use std::ops::Range;
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::{mem, ptr};
+
+/// Host allocation backing a `Memory` object.
+///
+/// Accesses are synchronized externally, as they would be for device memory:
+/// the host may only touch mapped ranges the queue doesn't use concurrently.
+pub struct RawMemory {
+    data: UnsafeCell<Box<[u8]>>,
+}
+
+unsafe impl Send for RawMemory {}
+unsafe impl Sync for RawMemory {}
+
+impl RawMemory {
+    pub(crate) fn new(size: usize) -> Self {
+        RawMemory {
+            data: UnsafeCell::new(vec![0; size].into_boxed_slice()),
+        }
+    }
+
+    pub(crate) fn size(&self) -> u64 {
+        unsafe { (&*self.data.get()).len() as u64 }
+    }
+
+    /// Pointer to the byte at `offset`, checking that `size` bytes are accessible from there.
+    pub(crate) fn ptr(&self, offset: u64, size: u64) -> *mut u8 {
+        assert!(offset + size <= self.size(), "Access of {:?} is out of the memory bounds", offset .. offset + size);
+        unsafe { (&mut *self.data.get()).as_mut_ptr().offset(offset as isize) }
+    }
+
+    pub(crate) fn read(&self, offset: u64, data: &mut [u8]) {
+        let src = self.ptr(offset, data.len() as u64);
+        unsafe { ptr::copy(src, data.as_mut_ptr(), data.len()); }
+    }
+
+    pub(crate) fn write(&self, offset: u64, data: &[u8]) {
+        let dst = self.ptr(offset, data.len() as u64);
+        unsafe { ptr::copy(data.as_ptr(), dst, data.len()); }
+    }
+}
+
+impl fmt::Debug for RawMemory {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "RawMemory({} bytes)", self.size())
+    }
+}
+
+#[derive(Debug)]
+pub struct Memory {
+    pub(crate) raw: Arc<RawMemory>,
+}
+
+#[derive(Debug)]
+pub struct UnboundBuffer {
+    pub(crate) size: u64,
+    pub(crate) usage: buffer::Usage,
+}
+
+#[derive(Clone, Debug)]
+pub struct Buffer {
+    pub(crate) memory: Arc<RawMemory>,
+    pub(crate) offset: u64,
+    pub(crate) size: u64,
+    pub(crate) usage: buffer::Usage,
+}
+
+impl Buffer {
+    /// Resolve a buffer relative range into a memory range.
+    pub(crate) fn range(&self, range: Range<u64>) -> Range<u64> {
+        assert!(range.start <= range.end && range.end <= self.size,
+            "Range {:?} is out of the buffer bounds {}", range, self.size);
+        self.offset + range.start .. self.offset + range.end
+    }
+}
+
+#[derive(Debug)]
+pub struct BufferView {
+    pub(crate) buffer: Buffer,
+    pub(crate) range: Range<u64>,
+}
+
+#[derive(Debug)]
+pub struct UnboundImage {
+    pub(crate) kind: image::Kind,
+    pub(crate) levels: image::Level,
+    pub(crate) format: format::Format,
+    pub(crate) usage: image::Usage,
+}
+
+impl UnboundImage {
+    /// Size of a single texel or texel block in bytes.
+    pub(crate) fn texel_size(&self) -> u64 {
+        self.format.surface_desc().bits as u64 / 8
+    }
+
+    /// Size of a single subresource at `level`, with all the depth slices.
+    pub(crate) fn level_size(&self, level: image::Level) -> u64 {
+        let desc = self.format.surface_desc();
+        let extent = self.kind.level_extent(level);
+        let blocks_x = (extent.width as u64 + desc.dim.0 as u64 - 1) / desc.dim.0 as u64;
+        let blocks_y = (extent.height as u64 + desc.dim.1 as u64 - 1) / desc.dim.1 as u64;
+        blocks_x * blocks_y * extent.depth as u64 * self.texel_size()
+    }
+
+    /// Total size of the image data, stored level by level and layer by layer.
+    pub(crate) fn size(&self) -> u64 {
+        (0 .. self.levels)
+            .map(|level| self.level_size(level) * self.kind.num_layers() as u64)
+            .sum()
+    }
+
+    pub(crate) fn footprint(&self, level: image::Level, layer: image::Layer) -> image::SubresourceFootprint {
+        let desc = self.format.surface_desc();
+        let extent = self.kind.level_extent(level);
+        let level_offset: u64 = (0 .. level)
+            .map(|l| self.level_size(l) * self.kind.num_layers() as u64)
+            .sum();
+        let array_pitch = self.level_size(level);
+        let start = level_offset + layer as u64 * array_pitch;
+        let blocks_x = (extent.width as u64 + desc.dim.0 as u64 - 1) / desc.dim.0 as u64;
+        let blocks_y = (extent.height as u64 + desc.dim.1 as u64 - 1) / desc.dim.1 as u64;
+        let row_pitch = blocks_x * self.texel_size();
+        image::SubresourceFootprint {
+            slice: start .. start + array_pitch,
+            row_pitch,
+            array_pitch,
+            depth_pitch: row_pitch * blocks_y,
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Image {
+    pub(crate) info: Arc<UnboundImage>,
+    pub(crate) memory: Arc<RawMemory>,
+    pub(crate) offset: u64,
+    /// Current layout of each subresource, indexed by `level * layers + layer`.
+    pub(crate) layouts: Arc<Mutex<Vec<image::Layout>>>,
+}
+
+impl Image {
+    pub(crate) fn subresource_index(&self, level: image::Level, layer: image::Layer) -> usize {
+        assert!(level < self.info.levels && layer < self.info.kind.num_layers(),
+            "Subresource (level {}, layer {}) is out of the image bounds", level, layer);
+        level as usize * self.info.kind.num_layers() as usize + layer as usize
+    }
+
+    /// Check that the given layers of `level` are in `layout`, which has to be
+    /// one of the `allowed` layouts for the operation.
+    pub(crate) fn validate_layout(
+        &self,
+        level: image::Level,
+        layers: Range<image::Layer>,
+        layout: image::Layout,
+        allowed: &[image::Layout],
+        operation: &str,
+    ) {
+        assert!(allowed.contains(&layout),
+            "{} doesn't support image layout {:?}, expected one of {:?}", operation, layout, allowed);
+        let layouts = self.layouts.lock().unwrap();
+        for layer in layers {
+            let current = layouts[self.subresource_index(level, layer)];
+            assert_eq!(current, layout,
+                "{} expects the image subresource (level {}, layer {}) in layout {:?}, but it is in {:?}",
+                operation, level, layer, layout, current);
+        }
+    }
+
+    /// Apply a layout transition of a barrier to the subresources in `range`.
+    pub(crate) fn transition(&self, range: &image::SubresourceRange, layouts: Range<image::Layout>) {
+        let mut current = self.layouts.lock().unwrap();
+        for level in range.levels.clone() {
+            for layer in range.layers.clone() {
+                let index = self.subresource_index(level, layer);
+                assert!(layouts.start == image::Layout::Undefined || current[index] == layouts.start,
+                    "Barrier transitions the image subresource (level {}, layer {}) from layout {:?}, but it is in {:?}",
+                    level, layer, layouts.start, current[index]);
+                current[index] = layouts.end;
+            }
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct ImageView {
+    pub(crate) image: Image,
+    pub(crate) range: image::SubresourceRange,
+}
+
+#[derive(Debug)]
+pub struct RenderPass {
+    /// Initial and final layouts of the attachments, applied when the pass begins and ends.
+    pub(crate) attachment_layouts: Vec<Range<image::Layout>>,
+}
+
+#[derive(Debug)]
+pub struct Framebuffer {
+    /// Attached views, empty for imageless framebuffers.
+    pub(crate) attachments: Vec<ImageView>,
+}
+
+/// Kernel executing a workgroup of a compute dispatch.
+pub type Kernel = Fn(&KernelContext) + Send + Sync;
+
+pub enum ShaderModule {
+    /// SPIR-V module, which can't be executed by this backend.
+    Spirv(Vec<u8>),
+    /// CPU kernel, see `Device::create_kernel_module`.
+    Kernel(Arc<Kernel>),
+}
+
+impl fmt::Debug for ShaderModule {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ShaderModule::Spirv(ref code) => write!(f, "ShaderModule::Spirv({} bytes)", code.len()),
+            ShaderModule::Kernel(_) => write!(f, "ShaderModule::Kernel"),
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct ComputePipeline {
+    pub(crate) kernel: Arc<Kernel>,
+}
+
+impl fmt::Debug for ComputePipeline {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "ComputePipeline")
+    }
+}
+
+#[derive(Debug)]
+pub struct GraphicsPipeline;
+
+#[derive(Debug)]
+pub struct PipelineLayout {
+    pub(crate) push_constant_words: usize,
+}
+
+#[derive(Debug)]
+pub struct DescriptorSetLayout {
+    pub(crate) bindings: Vec<pso::DescriptorSetLayoutBinding>,
+}
+
+#[derive(Clone, Debug)]
+pub enum Descriptor {
+    Buffer(Buffer, Range<u64>),
+    TexelBuffer(Buffer, Range<u64>),
+    /// Images can't be accessed by kernels, only the binding is recorded.
+    Image,
+    Sampler,
+}
+
+#[derive(Clone, Debug)]
+pub struct DescriptorSet {
+    /// Number of array elements of each binding, for spilling writes over.
+    pub(crate) counts: Arc<FastHashMap<DescriptorBinding, DescriptorArrayIndex>>,
+    pub(crate) descriptors: Arc<Mutex<FastHashMap<(DescriptorBinding, DescriptorArrayIndex), Descriptor>>>,
+}
+
+#[derive(Debug)]
+pub struct DescriptorPool;
+
+impl pso::DescriptorPool<::Backend> for DescriptorPool {
+    fn allocate_set(&mut self, layout: &DescriptorSetLayout) -> Result<DescriptorSet, pso::AllocationError> {
+        let counts = layout.bindings
+            .iter()
+            .map(|binding| (binding.binding, binding.count))
+            .collect();
+        Ok(DescriptorSet {
+            counts: Arc::new(counts),
+            descriptors: Arc::new(Mutex::new(FastHashMap::default())),
+        })
+    }
+
+    fn free_sets<I>(&mut self, _descriptor_sets: I)
+    where
+        I: IntoIterator<Item = DescriptorSet>,
+    {
+        // Sets are reference counted by the command buffers using them.
+    }
+
+    fn reset(&mut self) {
+    }
+}
+
+#[derive(Debug)]
+pub struct Fence(pub(crate) AtomicBool);
+
+impl Fence {
+    pub(crate) fn is_signaled(&self) -> bool {
+        self.0.load(Ordering::Acquire)
+    }
+
+    pub(crate) fn set(&self, signaled: bool) {
+        self.0.store(signaled, Ordering::Release);
+    }
+}
+
+#[derive(Debug)]
+pub struct QueryPool {
+    /// Results of the queries, `None` until written.
+    pub(crate) results: Arc<Mutex<Vec<Option<u64>>>>,
+}
+
+/// Bound view into a storage or uniform buffer of a dispatch.
+#[derive(Clone, Copy, Debug)]
+pub struct BufferAccess<'a> {
+    ptr: *mut u8,
+    size: usize,
+    marker: PhantomData<&'a RawMemory>,
+}
+
+impl<'a> BufferAccess<'a> {
+    /// Size of the bound range in bytes.
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    /// Read the element of type `T` at `index`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the element is out of the bound range.
+    pub fn read<T: Copy>(&self, index: usize) -> T {
+        let offset = index * mem::size_of::<T>();
+        assert!(offset + mem::size_of::<T>() <= self.size, "Read of element {} is out of bounds", index);
+        unsafe { ptr::read_unaligned(self.ptr.offset(offset as isize) as *const T) }
+    }
+
+    /// Write `value` to the element of type `T` at `index`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the element is out of the bound range.
+    pub fn write<T: Copy>(&self, index: usize, value: T) {
+        let offset = index * mem::size_of::<T>();
+        assert!(offset + mem::size_of::<T>() <= self.size, "Write of element {} is out of bounds", index);
+        unsafe { ptr::write_unaligned(self.ptr.offset(offset as isize) as *mut T, value) }
+    }
+}
+
+/// State of a compute dispatch visible to a kernel.
+pub struct KernelContext<'a> {
+    /// Index of the executed workgroup, including the dispatch base.
+    pub workgroup_id: [u32; 3],
+    /// Number of workgroups dispatched.
+    pub workgroup_count: [u32; 3],
+    pub(crate) sets: &'a [Option<DescriptorSet>],
+    pub(crate) push_constants: &'a [u32],
+}
+
+impl<'a> KernelContext<'a> {
+    /// Access the buffer bound to the `binding` of the descriptor set `set`.
+    ///
+    /// Returns `None` if no (texel) buffer is bound there.
+    pub fn buffer(&self, set: usize, binding: DescriptorBinding) -> Option<BufferAccess<'a>> {
+        self.buffer_element(set, binding, 0)
+    }
+
+    /// Access the buffer bound to the array element `index` of a binding.
+    pub fn buffer_element(
+        &self, set: usize, binding: DescriptorBinding, index: DescriptorArrayIndex,
+    ) -> Option<BufferAccess<'a>> {
+        let set = match self.sets.get(set) {
+            Some(&Some(ref set)) => set,
+            _ => return None,
+        };
+        let descriptors = set.descriptors.lock().unwrap();
+        match descriptors.get(&(binding, index)) {
+            Some(&Descriptor::Buffer(ref buffer, ref range)) |
+            Some(&Descriptor::TexelBuffer(ref buffer, ref range)) => {
+                let range = buffer.range(range.clone());
+                Some(BufferAccess {
+                    ptr: buffer.memory.ptr(range.start, range.end - range.start),
+                    size: (range.end - range.start) as usize,
+                    marker: PhantomData,
+                })
+            }
+            _ => None,
+        }
+    }
+
+    /// Read the push constant word at `index`, zero if it was never set.
+    pub fn push_constant(&self, index: usize) -> u32 {
+        self.push_constants.get(index).cloned().unwrap_or(0)
+    }
+}
@@ -1,25 +1,185 @@
 use std::borrow::Borrow;
-use std::{mem, ptr};
+use std::{cmp, iter, mem, ptr};
 use std::ops::Range;
 use std::sync::Arc;
 use smallvec::SmallVec;
+use ash;
 use ash::vk;
-use ash::version::DeviceV1_0;
+use ash::version::{DeviceV1_0, V1_0};
 
-use hal::{buffer, command as com, memory, pso, query};
+use hal::{buffer, command as com, format, image, memory, pass, pso, query};
+use hal::backend::FastHashMap;
 use hal::{DrawCount, IndexCount, InstanceCount, VertexCount, VertexOffset, WorkGroupCount};
 use hal::format::Aspects;
 use hal::image::{Filter, Layout, SubresourceRange};
+use hal::profiler::CommandBufferStatistics;
+use hal::queue::QueueFamilyId;
 use hal::range::RangeArg;
-use {conv, native as n};
+use {conv, device, native as n};
 use {Backend, RawDevice};
 
 #[derive(Clone)]
 pub struct CommandBuffer {
     pub raw: vk::CommandBuffer,
     pub device: Arc<RawDevice>,
+    pub statistics: CommandBufferStatistics,
 }
 
+// Attachment of a transient render pass created for `begin_rendering`.
+#[derive(Clone, Debug, Hash, PartialEq, Eq)]
+struct RenderingAttachmentKey {
+    format: format::Format,
+    samples: image::NumSamples,
+    layout: Layout,
+    ops: pass::AttachmentOps,
+    stencil_ops: pass::AttachmentOps,
+}
+
+impl<'a, 'b> From<&'a com::RenderingAttachment<'b, Backend>> for RenderingAttachmentKey {
+    fn from(attachment: &'a com::RenderingAttachment<'b, Backend>) -> Self {
+        RenderingAttachmentKey {
+            format: attachment.view.format,
+            samples: attachment.view.samples,
+            layout: attachment.layout,
+            ops: attachment.ops,
+            stencil_ops: attachment.stencil_ops,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Hash, PartialEq, Eq)]
+struct RenderingPassKey {
+    colors: SmallVec<[RenderingAttachmentKey; 4]>,
+    depth_stencil: Option<RenderingAttachmentKey>,
+}
+
+#[derive(Clone, Debug, Hash, PartialEq, Eq)]
+struct RenderingFramebufferKey {
+    render_pass: vk::RenderPass,
+    views: SmallVec<[vk::ImageView; 4]>,
+}
+
+/// Render passes and framebuffers emulating `begin_rendering`, created on first use.
+///
+/// Render passes are shared by the renderings with the same attachment formats, layouts
+/// and operations. Framebuffers cover the whole extent of their views, independently of
+/// the render area, and are destroyed along with the views they reference.
+#[derive(Debug, Default)]
+pub struct RenderingCache {
+    render_passes: FastHashMap<RenderingPassKey, vk::RenderPass>,
+    framebuffers: FastHashMap<RenderingFramebufferKey, vk::Framebuffer>,
+}
+
+impl RenderingCache {
+    fn render_pass(&mut self, device: &RawDevice, info: &com::RenderingInfo<Backend>) -> vk::RenderPass {
+        let key = RenderingPassKey {
+            colors: info.colors.iter().map(RenderingAttachmentKey::from).collect(),
+            depth_stencil: info.depth_stencil.as_ref().map(RenderingAttachmentKey::from),
+        };
+        if let Some(&raw) = self.render_passes.get(&key) {
+            return raw;
+        }
+
+        // The attachments stay in their layouts, no transitions are performed.
+        let attachments = key.colors
+            .iter()
+            .chain(key.depth_stencil.iter())
+            .map(|attachment| pass::Attachment {
+                format: Some(attachment.format),
+                samples: attachment.samples,
+                ops: attachment.ops,
+                stencil_ops: attachment.stencil_ops,
+                layouts: attachment.layout .. attachment.layout,
+            });
+        let colors = key.colors
+            .iter()
+            .enumerate()
+            .map(|(id, attachment)| (id, attachment.layout))
+            .collect::<SmallVec<[_; 4]>>();
+        let depth_stencil = key.depth_stencil
+            .as_ref()
+            .map(|attachment| (colors.len(), attachment.layout));
+        let subpass = pass::SubpassDesc {
+            colors: &colors,
+            depth_stencil: depth_stencil.as_ref(),
+            inputs: &[],
+            resolves: &[],
+            preserves: &[],
+        };
+
+        let raw = device
+            .create_render_pass_raw(attachments, Some(subpass), iter::empty::<pass::SubpassDependency>(), ptr::null())
+            .raw;
+        self.render_passes.insert(key, raw);
+        raw
+    }
+
+    fn framebuffer(
+        &mut self,
+        device: &RawDevice,
+        render_pass: vk::RenderPass,
+        info: &com::RenderingInfo<Backend>,
+    ) -> vk::Framebuffer {
+        let views = info.colors
+            .iter()
+            .chain(info.depth_stencil.iter())
+            .map(|attachment| attachment.view);
+        let key = RenderingFramebufferKey {
+            render_pass,
+            views: views.clone().map(|view| view.view).collect(),
+        };
+        if let Some(&raw) = self.framebuffers.get(&key) {
+            return raw;
+        }
+
+        // The largest extent and layer count shared by all the views.
+        let (width, height, layers) = views.fold((!0, !0, !0), |(width, height, layers), view| (
+            cmp::min(width, view.extent.width),
+            cmp::min(height, view.extent.height),
+            cmp::min(layers, (view.range.layers.end - view.range.layers.start) as u32),
+        ));
+        let framebuffer_info = vk::FramebufferCreateInfo {
+            s_type: vk::StructureType::FramebufferCreateInfo,
+            p_next: ptr::null(),
+            flags: vk::FramebufferCreateFlags::empty(),
+            render_pass,
+            attachment_count: key.views.len() as u32,
+            p_attachments: key.views.as_ptr(),
+            width,
+            height,
+            layers,
+        };
+        let raw = unsafe {
+            device.0.create_framebuffer(&framebuffer_info, None)
+        }.expect("error on framebuffer creation");
+        self.framebuffers.insert(key, raw);
+        raw
+    }
+
+    /// Destroy the framebuffers referencing a view which is about to be destroyed.
+    pub fn evict_view(&mut self, device: &ash::Device<V1_0>, view: vk::ImageView) {
+        self.framebuffers.retain(|key, &mut raw| {
+            let referenced = key.views.contains(&view);
+            if referenced {
+                unsafe { device.destroy_framebuffer(raw, None); }
+            }
+            !referenced
+        });
+    }
+
+    pub fn destroy(&mut self, device: &ash::Device<V1_0>) {
+        for (_, raw) in self.framebuffers.drain() {
+            unsafe { device.destroy_framebuffer(raw, None); }
+        }
+        for (_, raw) in self.render_passes.drain() {
+            unsafe { device.destroy_render_pass(raw, None); }
+        }
+    }
+}
+
+/// `vkCmdDispatchBaseKHR` of `VK_KHR_device_group`, not exposed by `ash`.
+pub(crate) type CmdDispatchBase = extern "system" fn(vk::CommandBuffer, u32, u32, u32, u32, u32, u32);
+
 fn map_subpass_contents(contents: com::SubpassContents) -> vk::SubpassContents {
     match contents {
         com::SubpassContents::Inline => vk::SubpassContents::Inline,
@@ -52,6 +212,83 @@ where
         .collect()
 }
 
+fn map_queue_families(families: &Option<Range<QueueFamilyId>>) -> (u32, u32) {
+    match *families {
+        Some(ref families) => (families.start.0 as u32, families.end.0 as u32),
+        None => (vk::VK_QUEUE_FAMILY_IGNORED, vk::VK_QUEUE_FAMILY_IGNORED),
+    }
+}
+
+fn map_barriers<'a, T>(
+    device: &RawDevice,
+    barriers: T,
+) -> (
+    SmallVec<[vk::MemoryBarrier; 4]>,
+    SmallVec<[vk::BufferMemoryBarrier; 4]>,
+    SmallVec<[vk::ImageMemoryBarrier; 4]>,
+)
+where
+    T: IntoIterator,
+    T::Item: Borrow<memory::Barrier<'a, Backend>>,
+{
+    let mut global_bars: SmallVec<[vk::MemoryBarrier; 4]> = SmallVec::new();
+    let mut buffer_bars: SmallVec<[vk::BufferMemoryBarrier; 4]> = SmallVec::new();
+    let mut image_bars: SmallVec<[vk::ImageMemoryBarrier; 4]> = SmallVec::new();
+
+    for barrier in barriers {
+        match *barrier.borrow() {
+            memory::Barrier::AllBuffers(ref access) => {
+                global_bars.push(vk::MemoryBarrier {
+                    s_type: vk::StructureType::MemoryBarrier,
+                    p_next: ptr::null(),
+                    src_access_mask: conv::map_buffer_access(access.start),
+                    dst_access_mask: conv::map_buffer_access(access.end),
+                });
+            }
+            memory::Barrier::AllImages(ref access) => {
+                global_bars.push(vk::MemoryBarrier {
+                    s_type: vk::StructureType::MemoryBarrier,
+                    p_next: ptr::null(),
+                    src_access_mask: conv::map_image_access(access.start),
+                    dst_access_mask: conv::map_image_access(access.end),
+                });
+            }
+            memory::Barrier::Buffer { ref states, target, ref families } => {
+                let (src_family, dst_family) = map_queue_families(families);
+                buffer_bars.push(vk::BufferMemoryBarrier {
+                    s_type: vk::StructureType::BufferMemoryBarrier,
+                    p_next: ptr::null(),
+                    src_access_mask: conv::map_buffer_access(states.start),
+                    dst_access_mask: conv::map_buffer_access(states.end),
+                    src_queue_family_index: src_family,
+                    dst_queue_family_index: dst_family,
+                    buffer: target.raw,
+                    offset: 0,
+                    size: vk::VK_WHOLE_SIZE,
+                });
+            }
+            memory::Barrier::Image { ref states, target, ref range, ref families } => {
+                let (src_family, dst_family) = map_queue_families(families);
+                let subresource_range = conv::map_subresource_range(range);
+                image_bars.push(vk::ImageMemoryBarrier {
+                    s_type: vk::StructureType::ImageMemoryBarrier,
+                    p_next: ptr::null(),
+                    src_access_mask: conv::map_image_access(states.start.0),
+                    dst_access_mask: conv::map_image_access(states.end.0),
+                    old_layout: device.map_image_layout(states.start.1),
+                    new_layout: device.map_image_layout(states.end.1),
+                    src_queue_family_index: src_family,
+                    dst_queue_family_index: dst_family,
+                    image: target.raw,
+                    subresource_range,
+                });
+            }
+        }
+    }
+
+    (global_bars, buffer_bars, image_bars)
+}
+
 impl CommandBuffer {
     fn bind_descriptor_sets<I, J>(
         &mut self,
@@ -105,6 +342,7 @@ impl com::RawCommandBuffer<Backend> for CommandBuffer {
         assert_eq!(Ok(()),
             unsafe { self.device.0.begin_command_buffer(self.raw, &info) }
         );
+        self.statistics = CommandBufferStatistics::default();
     }
 
     fn finish(&mut self) {
@@ -123,6 +361,7 @@ impl com::RawCommandBuffer<Backend> for CommandBuffer {
         assert_eq!(Ok(()),
             unsafe { self.device.0.reset_command_buffer(self.raw, flags) }
         );
+        self.statistics = CommandBufferStatistics::default();
     }
 
     fn begin_render_pass<T>(
@@ -136,6 +375,7 @@ impl com::RawCommandBuffer<Backend> for CommandBuffer {
         T: IntoIterator,
         T::Item: Borrow<com::ClearValueRaw>,
     {
+        self.statistics.commands += 1;
         let render_area = conv::map_rect(&render_area);
 
         let clear_values: SmallVec<[vk::ClearValue; 16]> =
@@ -167,7 +407,65 @@ impl com::RawCommandBuffer<Backend> for CommandBuffer {
         }
     }
 
+    fn begin_render_pass_imageless<T, I>(
+        &mut self,
+        render_pass: &n::RenderPass,
+        frame_buffer: &n::Framebuffer,
+        attachments: I,
+        render_area: pso::Rect,
+        clear_values: T,
+        first_subpass: com::SubpassContents,
+    ) where
+        T: IntoIterator,
+        T::Item: Borrow<com::ClearValueRaw>,
+        I: IntoIterator,
+        I::Item: Borrow<n::ImageView>,
+    {
+        self.statistics.commands += 1;
+        let render_area = conv::map_rect(&render_area);
+
+        let clear_values: SmallVec<[vk::ClearValue; 16]> =
+            clear_values
+                .into_iter()
+                .map(|clear| unsafe {
+                    // Vulkan and HAL share same memory layout
+                    mem::transmute(*clear.borrow())
+                })
+                .collect();
+        let attachments: SmallVec<[vk::ImageView; 4]> =
+            attachments
+                .into_iter()
+                .map(|attachment| attachment.borrow().view)
+                .collect();
+
+        let attachment_info = device::RenderPassAttachmentBeginInfo {
+            s_type: device::STRUCTURE_TYPE_RENDER_PASS_ATTACHMENT_BEGIN_INFO,
+            p_next: ptr::null(),
+            attachment_count: attachments.len() as u32,
+            p_attachments: attachments.as_ptr(),
+        };
+        let info = vk::RenderPassBeginInfo {
+            s_type: vk::StructureType::RenderPassBeginInfo,
+            p_next: &attachment_info as *const _ as *const _,
+            render_pass: render_pass.raw,
+            framebuffer: frame_buffer.raw,
+            render_area,
+            clear_value_count: clear_values.len() as u32,
+            p_clear_values: clear_values.as_ptr(),
+        };
+
+        let contents = map_subpass_contents(first_subpass);
+        unsafe {
+            self.device.0.cmd_begin_render_pass(
+                self.raw,
+                &info,
+                contents,
+            );
+        }
+    }
+
     fn next_subpass(&mut self, contents: com::SubpassContents) {
+        self.statistics.commands += 1;
         let contents = map_subpass_contents(contents);
         unsafe {
             self.device.0.cmd_next_subpass(self.raw, contents);
@@ -175,6 +473,53 @@ impl com::RawCommandBuffer<Backend> for CommandBuffer {
     }
 
     fn end_render_pass(&mut self) {
+        self.statistics.commands += 1;
+        unsafe {
+            self.device.0.cmd_end_render_pass(self.raw);
+        }
+    }
+
+    fn begin_rendering(&mut self, info: &com::RenderingInfo<Backend>) {
+        self.statistics.commands += 1;
+        // `VK_KHR_dynamic_rendering` isn't exposed by our version of ash, the rendering
+        // is recorded as a single subpass render pass cached by the device instead.
+        let (render_pass, framebuffer) = {
+            let mut cache = self.device.4.lock().unwrap();
+            let render_pass = cache.render_pass(&self.device, info);
+            (render_pass, cache.framebuffer(&self.device, render_pass, info))
+        };
+
+        let clear_values: SmallVec<[vk::ClearValue; 16]> =
+            info.colors
+                .iter()
+                .chain(info.depth_stencil.iter())
+                .map(|attachment| unsafe {
+                    // Vulkan and HAL share same memory layout
+                    mem::transmute(attachment.clear_value)
+                })
+                .collect();
+
+        let begin_info = vk::RenderPassBeginInfo {
+            s_type: vk::StructureType::RenderPassBeginInfo,
+            p_next: ptr::null(),
+            render_pass,
+            framebuffer,
+            render_area: conv::map_rect(&info.render_area),
+            clear_value_count: clear_values.len() as u32,
+            p_clear_values: clear_values.as_ptr(),
+        };
+
+        unsafe {
+            self.device.0.cmd_begin_render_pass(
+                self.raw,
+                &begin_info,
+                vk::SubpassContents::Inline,
+            );
+        }
+    }
+
+    fn end_rendering(&mut self) {
+        self.statistics.commands += 1;
         unsafe {
             self.device.0.cmd_end_render_pass(self.raw);
         }
@@ -189,58 +534,9 @@ impl com::RawCommandBuffer<Backend> for CommandBuffer {
         T: IntoIterator,
         T::Item: Borrow<memory::Barrier<'a, Backend>>,
     {
-        let mut global_bars: SmallVec<[vk::MemoryBarrier; 4]> = SmallVec::new();
-        let mut buffer_bars: SmallVec<[vk::BufferMemoryBarrier; 4]> = SmallVec::new();
-        let mut image_bars: SmallVec<[vk::ImageMemoryBarrier; 4]> = SmallVec::new();
-
-        for barrier in barriers {
-            match *barrier.borrow() {
-                memory::Barrier::AllBuffers(ref access) => {
-                    global_bars.push(vk::MemoryBarrier {
-                        s_type: vk::StructureType::MemoryBarrier,
-                        p_next: ptr::null(),
-                        src_access_mask: conv::map_buffer_access(access.start),
-                        dst_access_mask: conv::map_buffer_access(access.end),
-                    });
-                }
-                memory::Barrier::AllImages(ref access) => {
-                    global_bars.push(vk::MemoryBarrier {
-                        s_type: vk::StructureType::MemoryBarrier,
-                        p_next: ptr::null(),
-                        src_access_mask: conv::map_image_access(access.start),
-                        dst_access_mask: conv::map_image_access(access.end),
-                    });
-                }
-                memory::Barrier::Buffer { ref states, target} => {
-                    buffer_bars.push(vk::BufferMemoryBarrier {
-                        s_type: vk::StructureType::BufferMemoryBarrier,
-                        p_next: ptr::null(),
-                        src_access_mask: conv::map_buffer_access(states.start),
-                        dst_access_mask: conv::map_buffer_access(states.end),
-                        src_queue_family_index: vk::VK_QUEUE_FAMILY_IGNORED, // TODO
-                        dst_queue_family_index: vk::VK_QUEUE_FAMILY_IGNORED, // TODO
-                        buffer: target.raw,
-                        offset: 0,
-                        size: vk::VK_WHOLE_SIZE,
-                    });
-                }
-                memory::Barrier::Image { ref states, target, ref range } => {
-                    let subresource_range = conv::map_subresource_range(range);
-                    image_bars.push(vk::ImageMemoryBarrier {
-                        s_type: vk::StructureType::ImageMemoryBarrier,
-                        p_next: ptr::null(),
-                        src_access_mask: conv::map_image_access(states.start.0),
-                        dst_access_mask: conv::map_image_access(states.end.0),
-                        old_layout: conv::map_image_layout(states.start.1),
-                        new_layout: conv::map_image_layout(states.end.1),
-                        src_queue_family_index: vk::VK_QUEUE_FAMILY_IGNORED, // TODO
-                        dst_queue_family_index: vk::VK_QUEUE_FAMILY_IGNORED, // TODO
-                        image: target.raw,
-                        subresource_range,
-                    });
-                }
-            }
-        }
+        self.statistics.commands += 1;
+        self.statistics.frame.barriers += 1;
+        let (global_bars, buffer_bars, image_bars) = map_barriers(&self.device, barriers);
 
         unsafe {
             self.device.0.cmd_pipeline_barrier(
@@ -255,6 +551,57 @@ impl com::RawCommandBuffer<Backend> for CommandBuffer {
         }
     }
 
+    fn set_event<'a, T>(
+        &mut self,
+        event: &n::Event,
+        stages: Range<pso::PipelineStage>,
+        _barriers: T,
+    ) where
+        T: IntoIterator,
+        T::Item: Borrow<memory::Barrier<'a, Backend>>,
+    {
+        self.statistics.commands += 1;
+        unsafe {
+            self.device.0.cmd_set_event(self.raw, event.0, conv::map_pipeline_stage(stages.start));
+        }
+    }
+
+    fn reset_event(&mut self, event: &n::Event, stages: pso::PipelineStage) {
+        self.statistics.commands += 1;
+        unsafe {
+            self.device.0.cmd_reset_event(self.raw, event.0, conv::map_pipeline_stage(stages));
+        }
+    }
+
+    fn wait_events<'a, I, T>(
+        &mut self,
+        events: I,
+        stages: Range<pso::PipelineStage>,
+        barriers: T,
+    ) where
+        I: IntoIterator,
+        I::Item: Borrow<n::Event>,
+        T: IntoIterator,
+        T::Item: Borrow<memory::Barrier<'a, Backend>>,
+    {
+        self.statistics.commands += 1;
+        self.statistics.frame.barriers += 1;
+        let events: SmallVec<[vk::Event; 4]> = events.into_iter().map(|event| event.borrow().0).collect();
+        let (global_bars, buffer_bars, image_bars) = map_barriers(&self.device, barriers);
+
+        unsafe {
+            self.device.0.cmd_wait_events(
+                self.raw,
+                &events,
+                conv::map_pipeline_stage(stages.start),
+                conv::map_pipeline_stage(stages.end),
+                &global_bars,
+                &buffer_bars,
+                &image_bars,
+            );
+        }
+    }
+
     fn fill_buffer<R>(
         &mut self,
         buffer: &n::Buffer,
@@ -263,7 +610,11 @@ impl com::RawCommandBuffer<Backend> for CommandBuffer {
     ) where
         R: RangeArg<buffer::Offset>,
     {
+        self.statistics.commands += 1;
         let (offset, size) = conv::map_range_arg(&range);
+        if size != vk::VK_WHOLE_SIZE {
+            self.statistics.referenced_memory += size;
+        }
         unsafe {
             self.device.0.cmd_fill_buffer(
                 self.raw,
@@ -281,6 +632,8 @@ impl com::RawCommandBuffer<Backend> for CommandBuffer {
         offset: buffer::Offset,
         data: &[u8],
     ) {
+        self.statistics.commands += 1;
+        self.statistics.referenced_memory += data.len() as u64;
         unsafe {
             self.device.0.cmd_update_buffer(
                 self.raw,
@@ -302,6 +655,7 @@ impl com::RawCommandBuffer<Backend> for CommandBuffer {
         T: IntoIterator,
         T::Item: Borrow<SubresourceRange>,
     {
+        self.statistics.commands += 1;
         let mut color_ranges = Vec::new();
         let mut ds_ranges = Vec::new();
 
@@ -335,7 +689,7 @@ impl com::RawCommandBuffer<Backend> for CommandBuffer {
                 self.device.0.cmd_clear_color_image(
                     self.raw,
                     image.raw,
-                    conv::map_image_layout(layout),
+                    self.device.map_image_layout(layout),
                     &color_value,
                     &color_ranges,
                 )
@@ -344,7 +698,7 @@ impl com::RawCommandBuffer<Backend> for CommandBuffer {
                 self.device.0.cmd_clear_depth_stencil_image(
                     self.raw,
                     image.raw,
-                    conv::map_image_layout(layout),
+                    self.device.map_image_layout(layout),
                     &depth_stencil_value,
                     &ds_ranges,
                 )
@@ -359,6 +713,7 @@ impl com::RawCommandBuffer<Backend> for CommandBuffer {
         U: IntoIterator,
         U::Item: Borrow<pso::ClearRect>,
     {
+        self.statistics.commands += 1;
         let clears: SmallVec<[vk::ClearAttachment; 16]> = clears
             .into_iter()
             .map(|clear| {
@@ -408,6 +763,7 @@ impl com::RawCommandBuffer<Backend> for CommandBuffer {
         T: IntoIterator,
         T::Item: Borrow<com::ImageResolve>,
     {
+        self.statistics.commands += 1;
         let regions = regions
             .into_iter()
             .map(|region| {
@@ -426,9 +782,9 @@ impl com::RawCommandBuffer<Backend> for CommandBuffer {
             self.device.0.cmd_resolve_image(
                 self.raw,
                 src.raw,
-                conv::map_image_layout(src_layout),
+                self.device.map_image_layout(src_layout),
                 dst.raw,
-                conv::map_image_layout(dst_layout),
+                self.device.map_image_layout(dst_layout),
                 &regions,
             );
         }
@@ -446,6 +802,7 @@ impl com::RawCommandBuffer<Backend> for CommandBuffer {
         T: IntoIterator,
         T::Item: Borrow<com::ImageBlit>
     {
+        self.statistics.commands += 1;
         let regions = regions
             .into_iter()
             .map(|region| {
@@ -463,9 +820,9 @@ impl com::RawCommandBuffer<Backend> for CommandBuffer {
             self.device.0.cmd_blit_image(
                 self.raw,
                 src.raw,
-                conv::map_image_layout(src_layout),
+                self.device.map_image_layout(src_layout),
                 dst.raw,
-                conv::map_image_layout(dst_layout),
+                self.device.map_image_layout(dst_layout),
                 &regions,
                 conv::map_filter(filter),
             );
@@ -473,6 +830,7 @@ impl com::RawCommandBuffer<Backend> for CommandBuffer {
     }
 
     fn bind_index_buffer(&mut self, ibv: buffer::IndexBufferView<Backend>) {
+        self.statistics.commands += 1;
         unsafe {
             self.device.0.cmd_bind_index_buffer(
                 self.raw,
@@ -488,6 +846,7 @@ impl com::RawCommandBuffer<Backend> for CommandBuffer {
         I: IntoIterator<Item = (T, buffer::Offset)>,
         T: Borrow<n::Buffer>,
     {
+        self.statistics.commands += 1;
         let (buffers, offsets): (SmallVec<[vk::Buffer; 16]>, SmallVec<[vk::DeviceSize; 16]>) = buffers
             .into_iter()
             .map(|(buffer, offset)| (buffer.borrow().raw, offset))
@@ -508,6 +867,7 @@ impl com::RawCommandBuffer<Backend> for CommandBuffer {
         T: IntoIterator,
         T::Item: Borrow<pso::Viewport>,
     {
+        self.statistics.commands += 1;
         let viewports: SmallVec<[vk::Viewport; 16]> = viewports
             .into_iter()
             .map(|viewport| {
@@ -525,6 +885,7 @@ impl com::RawCommandBuffer<Backend> for CommandBuffer {
         T: IntoIterator,
         T::Item: Borrow<pso::Rect>,
     {
+        self.statistics.commands += 1;
         let scissors: SmallVec<[vk::Rect2D; 16]> = scissors
             .into_iter()
             .map(|scissor| {
@@ -538,6 +899,7 @@ impl com::RawCommandBuffer<Backend> for CommandBuffer {
     }
 
     fn set_stencil_reference(&mut self, faces: pso::Face, value: pso::StencilValue) {
+        self.statistics.commands += 1;
         unsafe {
             // Vulkan and HAL share same faces bit flags
             self.device.0.cmd_set_stencil_reference(self.raw, mem::transmute(faces), value);
@@ -545,6 +907,7 @@ impl com::RawCommandBuffer<Backend> for CommandBuffer {
     }
 
     fn set_stencil_read_mask(&mut self, faces: pso::Face, value: pso::StencilValue) {
+        self.statistics.commands += 1;
         unsafe {
             // Vulkan and HAL share same faces bit flags
             self.device.0.cmd_set_stencil_compare_mask(self.raw, mem::transmute(faces), value);
@@ -552,6 +915,7 @@ impl com::RawCommandBuffer<Backend> for CommandBuffer {
     }
 
     fn set_stencil_write_mask(&mut self, faces: pso::Face, value: pso::StencilValue) {
+        self.statistics.commands += 1;
         unsafe {
             // Vulkan and HAL share same faces bit flags
             self.device.0.cmd_set_stencil_write_mask(self.raw, mem::transmute(faces), value);
@@ -559,24 +923,28 @@ impl com::RawCommandBuffer<Backend> for CommandBuffer {
     }
 
     fn set_blend_constants(&mut self, color: pso::ColorValue) {
+        self.statistics.commands += 1;
         unsafe {
             self.device.0.cmd_set_blend_constants(self.raw, color);
         }
     }
 
     fn set_depth_bounds(&mut self, bounds: Range<f32>) {
+        self.statistics.commands += 1;
         unsafe {
             self.device.0.cmd_set_depth_bounds(self.raw, bounds.start, bounds.end);
         }
     }
 
     fn set_line_width(&mut self, width: f32) {
+        self.statistics.commands += 1;
         unsafe {
             self.device.0.cmd_set_line_width(self.raw, width);
         }
     }
 
     fn set_depth_bias(&mut self, depth_bias: pso::DepthBias) {
+        self.statistics.commands += 1;
         unsafe {
             self.device.0.cmd_set_depth_bias(
                 self.raw,
@@ -588,6 +956,8 @@ impl com::RawCommandBuffer<Backend> for CommandBuffer {
     }
 
     fn bind_graphics_pipeline(&mut self, pipeline: &n::GraphicsPipeline) {
+        self.statistics.commands += 1;
+        self.statistics.frame.pipeline_binds += 1;
         unsafe {
             self.device.0.cmd_bind_pipeline(
                 self.raw,
@@ -609,6 +979,7 @@ impl com::RawCommandBuffer<Backend> for CommandBuffer {
         J: IntoIterator,
         J::Item: Borrow<com::DescriptorSetOffset>,
     {
+        self.statistics.commands += 1;
         self.bind_descriptor_sets(
             vk::PipelineBindPoint::Graphics,
             layout,
@@ -619,6 +990,8 @@ impl com::RawCommandBuffer<Backend> for CommandBuffer {
     }
 
     fn bind_compute_pipeline(&mut self, pipeline: &n::ComputePipeline) {
+        self.statistics.commands += 1;
+        self.statistics.frame.pipeline_binds += 1;
         unsafe {
             self.device.0.cmd_bind_pipeline(
                 self.raw,
@@ -640,6 +1013,7 @@ impl com::RawCommandBuffer<Backend> for CommandBuffer {
         J: IntoIterator,
         J::Item: Borrow<com::DescriptorSetOffset>,
     {
+        self.statistics.commands += 1;
         self.bind_descriptor_sets(
             vk::PipelineBindPoint::Compute,
             layout,
@@ -650,6 +1024,8 @@ impl com::RawCommandBuffer<Backend> for CommandBuffer {
     }
 
     fn dispatch(&mut self, count: WorkGroupCount) {
+        self.statistics.commands += 1;
+        self.statistics.frame.dispatches += 1;
         unsafe {
             self.device.0.cmd_dispatch(
                 self.raw,
@@ -660,7 +1036,32 @@ impl com::RawCommandBuffer<Backend> for CommandBuffer {
         }
     }
 
+    fn dispatch_base(&mut self, base: WorkGroupCount, count: WorkGroupCount) {
+        // Without `vkCmdDispatchBaseKHR`, the dispatch is skipped.
+        if self.device.5.is_none() && base != [0; 3] {
+            debug_assert!(false, "Non-zero dispatch base requires `Features::DISPATCH_BASE`");
+            return;
+        }
+        self.statistics.commands += 1;
+        self.statistics.frame.dispatches += 1;
+        match self.device.5 {
+            Some(cmd_dispatch_base) => {
+                cmd_dispatch_base(self.raw, base[0], base[1], base[2], count[0], count[1], count[2]);
+            }
+            None => unsafe {
+                self.device.0.cmd_dispatch(
+                    self.raw,
+                    count[0],
+                    count[1],
+                    count[2],
+                )
+            }
+        }
+    }
+
     fn dispatch_indirect(&mut self, buffer: &n::Buffer, offset: buffer::Offset) {
+        self.statistics.commands += 1;
+        self.statistics.frame.dispatches += 1;
         unsafe {
             self.device.0.cmd_dispatch_indirect(
                 self.raw,
@@ -675,6 +1076,7 @@ impl com::RawCommandBuffer<Backend> for CommandBuffer {
         T: IntoIterator,
         T::Item: Borrow<com::BufferCopy>,
     {
+        self.statistics.commands += 1;
         let regions: SmallVec<[vk::BufferCopy; 16]> = regions
             .into_iter()
             .map(|region| {
@@ -686,6 +1088,8 @@ impl com::RawCommandBuffer<Backend> for CommandBuffer {
                 }
             })
             .collect();
+        // Both the source and the destination range are referenced.
+        self.statistics.referenced_memory += regions.iter().map(|region| 2 * region.size).sum::<u64>();
 
         unsafe {
             self.device.0.cmd_copy_buffer(
@@ -708,6 +1112,7 @@ impl com::RawCommandBuffer<Backend> for CommandBuffer {
         T: IntoIterator,
         T::Item: Borrow<com::ImageCopy>,
     {
+        self.statistics.commands += 1;
         let regions: SmallVec<[vk::ImageCopy; 16]> = regions
             .into_iter()
             .map(|region| {
@@ -726,9 +1131,9 @@ impl com::RawCommandBuffer<Backend> for CommandBuffer {
             self.device.0.cmd_copy_image(
                 self.raw,
                 src.raw,
-                conv::map_image_layout(src_layout),
+                self.device.map_image_layout(src_layout),
                 dst.raw,
-                conv::map_image_layout(dst_layout),
+                self.device.map_image_layout(dst_layout),
                 &regions,
             );
         }
@@ -744,6 +1149,7 @@ impl com::RawCommandBuffer<Backend> for CommandBuffer {
         T: IntoIterator,
         T::Item: Borrow<com::BufferImageCopy>,
     {
+        self.statistics.commands += 1;
         let regions = map_buffer_image_regions(dst, regions);
 
         unsafe {
@@ -751,7 +1157,7 @@ impl com::RawCommandBuffer<Backend> for CommandBuffer {
                 self.raw,
                 src.raw,
                 dst.raw,
-                conv::map_image_layout(dst_layout),
+                self.device.map_image_layout(dst_layout),
                 &regions,
             );
         }
@@ -767,13 +1173,14 @@ impl com::RawCommandBuffer<Backend> for CommandBuffer {
         T: IntoIterator,
         T::Item: Borrow<com::BufferImageCopy>,
     {
+        self.statistics.commands += 1;
         let regions = map_buffer_image_regions(src, regions);
 
         unsafe {
             self.device.0.cmd_copy_image_to_buffer(
                 self.raw,
                 src.raw,
-                conv::map_image_layout(src_layout),
+                self.device.map_image_layout(src_layout),
                 dst.raw,
                 &regions,
             );
@@ -781,6 +1188,8 @@ impl com::RawCommandBuffer<Backend> for CommandBuffer {
     }
 
     fn draw(&mut self, vertices: Range<VertexCount>, instances: Range<InstanceCount>) {
+        self.statistics.commands += 1;
+        self.statistics.frame.draw_calls += 1;
         unsafe {
             self.device.0.cmd_draw(
                 self.raw,
@@ -798,6 +1207,8 @@ impl com::RawCommandBuffer<Backend> for CommandBuffer {
         base_vertex: VertexOffset,
         instances: Range<InstanceCount>,
     ) {
+        self.statistics.commands += 1;
+        self.statistics.frame.draw_calls += 1;
         unsafe {
             self.device.0.cmd_draw_indexed(
                 self.raw,
@@ -817,6 +1228,8 @@ impl com::RawCommandBuffer<Backend> for CommandBuffer {
         draw_count: DrawCount,
         stride: u32,
     ) {
+        self.statistics.commands += 1;
+        self.statistics.frame.draw_calls += 1;
         unsafe {
             self.device.0.cmd_draw_indirect(
                 self.raw,
@@ -835,6 +1248,8 @@ impl com::RawCommandBuffer<Backend> for CommandBuffer {
         draw_count: DrawCount,
         stride: u32,
     ) {
+        self.statistics.commands += 1;
+        self.statistics.frame.draw_calls += 1;
         unsafe {
             self.device.0.cmd_draw_indexed_indirect(
                 self.raw,
@@ -851,6 +1266,7 @@ impl com::RawCommandBuffer<Backend> for CommandBuffer {
         query: query::Query<Backend>,
         control: query::QueryControl,
     ) {
+        self.statistics.commands += 1;
         let mut flags = vk::QueryControlFlags::empty();
         if control.contains(query::QueryControl::PRECISE) {
             flags |= vk::QUERY_CONTROL_PRECISE_BIT;
@@ -870,6 +1286,7 @@ impl com::RawCommandBuffer<Backend> for CommandBuffer {
         &mut self,
         query: query::Query<Backend>,
     ) {
+        self.statistics.commands += 1;
         unsafe {
             self.device.0.cmd_end_query(
                 self.raw,
@@ -884,6 +1301,7 @@ impl com::RawCommandBuffer<Backend> for CommandBuffer {
         pool: &n::QueryPool,
         queries: Range<query::QueryId>,
     ) {
+        self.statistics.commands += 1;
         unsafe {
             self.device.0.cmd_reset_query_pool(
                 self.raw,
@@ -899,6 +1317,7 @@ impl com::RawCommandBuffer<Backend> for CommandBuffer {
         stage: pso::PipelineStage,
         query: query::Query<Backend>,
     ) {
+        self.statistics.commands += 1;
         unsafe {
             self.device.0.cmd_write_timestamp(
                 self.raw,
@@ -915,6 +1334,7 @@ impl com::RawCommandBuffer<Backend> for CommandBuffer {
         offset: u32,
         constants: &[u32],
     ) {
+        self.statistics.commands += 1;
         unsafe {
             self.device.0.cmd_push_constants(
                 self.raw,
@@ -933,6 +1353,7 @@ impl com::RawCommandBuffer<Backend> for CommandBuffer {
         offset: u32,
         constants: &[u32],
     ) {
+        self.statistics.commands += 1;
         unsafe {
             self.device.0.cmd_push_constants(
                 self.raw,
@@ -951,7 +1372,19 @@ impl com::RawCommandBuffer<Backend> for CommandBuffer {
         I: IntoIterator,
         I::Item: Borrow<CommandBuffer>,
     {
-        let command_buffers = buffers.into_iter().map(|b| b.borrow().raw).collect::<Vec<_>>();
+        self.statistics.commands += 1;
+        let command_buffers = buffers
+            .into_iter()
+            .map(|b| {
+                let b = b.borrow();
+                self.statistics.add_secondary(&b.statistics);
+                b.raw
+            })
+            .collect::<Vec<_>>();
         unsafe { self.device.0.cmd_execute_commands(self.raw, &command_buffers); }
     }
+
+    fn statistics(&self) -> CommandBufferStatistics {
+        self.statistics
+    }
 }
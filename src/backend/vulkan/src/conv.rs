@@ -3,7 +3,7 @@ use byteorder::{NativeEndian, WriteBytesExt};
 use smallvec::SmallVec;
 
 use hal::{buffer, command, format, image, pass, pso, query};
-use hal::{IndexType, Primitive, PresentMode};
+use hal::{IndexType, Primitive, PresentMode, SurfaceTransform};
 use hal::range::RangeArg;
 
 use native as n;
@@ -61,6 +61,10 @@ pub fn map_index_type(index_type: IndexType) -> vk::IndexType {
     }
 }
 
+/// `VK_IMAGE_LAYOUT_*` values of `VK_KHR_maintenance2`, not exposed by `ash`.
+const IMAGE_LAYOUT_DEPTH_READ_ONLY_STENCIL_ATTACHMENT_OPTIMAL: u32 = 1000117000;
+const IMAGE_LAYOUT_DEPTH_ATTACHMENT_STENCIL_READ_ONLY_OPTIMAL: u32 = 1000117001;
+
 pub fn map_image_layout(layout: image::Layout) -> vk::ImageLayout {
     use hal::image::Layout as Il;
     match layout {
@@ -68,6 +72,14 @@ pub fn map_image_layout(layout: image::Layout) -> vk::ImageLayout {
         Il::ColorAttachmentOptimal => vk::ImageLayout::ColorAttachmentOptimal,
         Il::DepthStencilAttachmentOptimal => vk::ImageLayout::DepthStencilAttachmentOptimal,
         Il::DepthStencilReadOnlyOptimal => vk::ImageLayout::DepthStencilReadOnlyOptimal,
+        // The per-aspect layouts of `VK_KHR_maintenance2` aren't exposed by the bindings,
+        // the raw values are passed through to the driver.
+        Il::DepthReadOnlyStencilAttachmentOptimal => unsafe {
+            mem::transmute(IMAGE_LAYOUT_DEPTH_READ_ONLY_STENCIL_ATTACHMENT_OPTIMAL)
+        },
+        Il::DepthAttachmentStencilReadOnlyOptimal => unsafe {
+            mem::transmute(IMAGE_LAYOUT_DEPTH_ATTACHMENT_STENCIL_READ_ONLY_OPTIMAL)
+        },
         Il::ShaderReadOnlyOptimal => vk::ImageLayout::ShaderReadOnlyOptimal,
         Il::TransferSrcOptimal => vk::ImageLayout::TransferSrcOptimal,
         Il::TransferDstOptimal => vk::ImageLayout::TransferDstOptimal,
@@ -400,12 +412,21 @@ pub fn map_query_control_flags(flags: query::QueryControl) -> vk::QueryControlFl
 
 pub fn map_image_features(features: vk::FormatFeatureFlags) -> format::ImageFeature {
     // Safe due to equivalence of HAL values and Vulkan values
-    unsafe { mem::transmute(features) }
+    let mut features: format::ImageFeature = unsafe { mem::transmute(features) };
+    // Vulkan supports typed loads from all storage formats
+    if features.contains(format::ImageFeature::STORAGE) {
+        features |= format::ImageFeature::STORAGE_READ;
+    }
+    features
 }
 
 pub fn map_buffer_features(features: vk::FormatFeatureFlags) -> format::BufferFeature {
     // Safe due to equivalence of HAL values and Vulkan values
-    unsafe { mem::transmute(features) }
+    let mut features: format::BufferFeature = unsafe { mem::transmute(features) };
+    if features.contains(format::BufferFeature::STORAGE_TEXEL) {
+        features |= format::BufferFeature::STORAGE_TEXEL_READ;
+    }
+    features
 }
 
 pub fn map_memory_ranges<'a, I, R>(ranges: I) -> Vec<vk::MappedMemoryRange>
@@ -516,7 +537,29 @@ pub fn map_image_flags(flags: image::StorageFlags) -> vk::ImageCreateFlags {
     unsafe { mem::transmute(flags) }
 }
 
+pub fn map_present_mode(mode: PresentMode) -> vk::PresentModeKHR {
+    match mode {
+        PresentMode::Immediate => vk::PresentModeKHR::Immediate,
+        PresentMode::Mailbox => vk::PresentModeKHR::Mailbox,
+        PresentMode::Fifo => vk::PresentModeKHR::Fifo,
+        PresentMode::Relaxed => vk::PresentModeKHR::FifoRelaxed,
+    }
+}
+
 pub fn map_vk_present_mode(mode: vk::PresentModeKHR) -> PresentMode {
-    // the enum variants have to match Vulkan
-    unsafe { mem::transmute(mode) }
+    match mode {
+        vk::PresentModeKHR::Immediate => PresentMode::Immediate,
+        vk::PresentModeKHR::Mailbox => PresentMode::Mailbox,
+        vk::PresentModeKHR::Fifo => PresentMode::Fifo,
+        vk::PresentModeKHR::FifoRelaxed => PresentMode::Relaxed,
+    }
+}
+
+pub fn map_surface_transform(transform: SurfaceTransform) -> vk::SurfaceTransformFlagsKHR {
+    // the flags have to match Vulkan
+    vk::SurfaceTransformFlagsKHR::from_flags_truncate(transform.bits())
+}
+
+pub fn map_vk_surface_transform(transform: vk::SurfaceTransformFlagsKHR) -> SurfaceTransform {
+    SurfaceTransform::from_bits_truncate(transform.flags())
 }
@@ -1,26 +1,559 @@
 use ash::vk;
 use ash::extensions as ext;
-use ash::version::DeviceV1_0;
+use ash::version::{DeviceV1_0, InstanceV1_0};
 use smallvec::SmallVec;
 
 use hal::{buffer, device as d, format, image, mapping, pass, pso, query, queue, window};
 use hal::{Backbuffer, Features, MemoryTypeId, SwapchainConfig};
-use hal::error::HostExecutionError;
+use hal::error::{ErrorContext, HostExecutionError};
 use hal::memory::Requirements;
 use hal::pool::CommandPoolCreateFlags;
+use hal::profiler::FrameStatistics;
 use hal::range::RangeArg;
+use hal::sparse::SparseImageRequirements;
+use hal::sync::HostTimeline;
 
-use std::{mem, ptr};
+use std::{cmp, mem, ptr};
 use std::borrow::Borrow;
-use std::ffi::CString;
+use std::ffi::{CStr, CString};
 use std::ops::Range;
 use std::sync::Arc;
 
-use {Backend as B, Device};
+use {capture_messages, Backend as B, Device, RawDevice};
 use {conv, native as n, result, window as w};
 use pool::RawCommandPool;
 
 
+/// `VK_STRUCTURE_TYPE_*_KHR` values of `VK_KHR_multiview`, not exposed by `ash`.
+const STRUCTURE_TYPE_RENDER_PASS_MULTIVIEW_CREATE_INFO: u32 = 1000053000;
+pub(crate) const STRUCTURE_TYPE_PHYSICAL_DEVICE_MULTIVIEW_FEATURES: u32 = 1000053001;
+
+/// `VkPhysicalDeviceMultiviewFeaturesKHR`, not exposed by `ash`.
+#[repr(C)]
+pub(crate) struct PhysicalDeviceMultiviewFeatures {
+    pub s_type: u32,
+    pub p_next: *mut vk::types::c_void,
+    pub multiview: u32,
+    pub multiview_geometry_shader: u32,
+    pub multiview_tessellation_shader: u32,
+}
+
+/// `VkRenderPassMultiviewCreateInfoKHR` of `VK_KHR_multiview`, not exposed by `ash`.
+#[repr(C)]
+struct RenderPassMultiviewCreateInfo {
+    s_type: u32,
+    p_next: *const vk::types::c_void,
+    subpass_count: u32,
+    p_view_masks: *const u32,
+    dependency_count: u32,
+    p_view_offsets: *const i32,
+    correlation_mask_count: u32,
+    p_correlation_masks: *const u32,
+}
+
+/// `VK_PIPELINE_CREATE_DISPATCH_BASE_BIT_KHR` of `VK_KHR_device_group`.
+const PIPELINE_CREATE_DISPATCH_BASE: u32 = 0x10;
+
+/// `VK_STRUCTURE_TYPE_IMAGE_FORMAT_LIST_CREATE_INFO_KHR`, not exposed by `ash`.
+const STRUCTURE_TYPE_IMAGE_FORMAT_LIST_CREATE_INFO: u32 = 1000147000;
+
+/// `VkImageFormatListCreateInfoKHR` of `VK_KHR_image_format_list`, not exposed by `ash`.
+#[repr(C)]
+struct ImageFormatListCreateInfo {
+    s_type: u32,
+    p_next: *const vk::types::c_void,
+    view_format_count: u32,
+    p_view_formats: *const vk::Format,
+}
+
+/// `VK_STRUCTURE_TYPE_*_KHR` values of `VK_KHR_imageless_framebuffer`, not exposed by `ash`.
+pub(crate) const STRUCTURE_TYPE_PHYSICAL_DEVICE_IMAGELESS_FRAMEBUFFER_FEATURES: u32 = 1000108000;
+const STRUCTURE_TYPE_FRAMEBUFFER_ATTACHMENTS_CREATE_INFO: u32 = 1000108001;
+const STRUCTURE_TYPE_FRAMEBUFFER_ATTACHMENT_IMAGE_INFO: u32 = 1000108002;
+pub(crate) const STRUCTURE_TYPE_RENDER_PASS_ATTACHMENT_BEGIN_INFO: u32 = 1000108003;
+
+/// `VK_FRAMEBUFFER_CREATE_IMAGELESS_BIT_KHR` of `VK_KHR_imageless_framebuffer`.
+const FRAMEBUFFER_CREATE_IMAGELESS: u32 = 0x1;
+
+/// `VkPhysicalDeviceImagelessFramebufferFeaturesKHR`, not exposed by `ash`.
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub(crate) struct PhysicalDeviceImagelessFramebufferFeatures {
+    pub s_type: u32,
+    pub p_next: *mut vk::types::c_void,
+    pub imageless_framebuffer: u32,
+}
+
+/// `VkFramebufferAttachmentImageInfoKHR`, not exposed by `ash`.
+#[repr(C)]
+struct FramebufferAttachmentImageInfo {
+    s_type: u32,
+    p_next: *const vk::types::c_void,
+    flags: vk::ImageCreateFlags,
+    usage: vk::ImageUsageFlags,
+    width: u32,
+    height: u32,
+    layer_count: u32,
+    view_format_count: u32,
+    p_view_formats: *const vk::Format,
+}
+
+/// `VkFramebufferAttachmentsCreateInfoKHR`, not exposed by `ash`.
+#[repr(C)]
+struct FramebufferAttachmentsCreateInfo {
+    s_type: u32,
+    p_next: *const vk::types::c_void,
+    attachment_image_info_count: u32,
+    p_attachment_image_infos: *const FramebufferAttachmentImageInfo,
+}
+
+/// `VkRenderPassAttachmentBeginInfoKHR`, not exposed by `ash`.
+#[repr(C)]
+pub(crate) struct RenderPassAttachmentBeginInfo {
+    pub s_type: u32,
+    pub p_next: *const vk::types::c_void,
+    pub attachment_count: u32,
+    pub p_attachments: *const vk::ImageView,
+}
+
+/// `VK_STRUCTURE_TYPE_*_KHR` values of `VK_KHR_timeline_semaphore`, not exposed by `ash`.
+pub(crate) const STRUCTURE_TYPE_PHYSICAL_DEVICE_TIMELINE_SEMAPHORE_FEATURES: u32 = 1000207000;
+const STRUCTURE_TYPE_SEMAPHORE_TYPE_CREATE_INFO: u32 = 1000207002;
+pub(crate) const STRUCTURE_TYPE_TIMELINE_SEMAPHORE_SUBMIT_INFO: u32 = 1000207003;
+const STRUCTURE_TYPE_SEMAPHORE_WAIT_INFO: u32 = 1000207004;
+const STRUCTURE_TYPE_SEMAPHORE_SIGNAL_INFO: u32 = 1000207005;
+/// `VK_SEMAPHORE_TYPE_TIMELINE_KHR`.
+const SEMAPHORE_TYPE_TIMELINE: u32 = 1;
+/// `VK_SEMAPHORE_WAIT_ANY_BIT_KHR`.
+const SEMAPHORE_WAIT_ANY: u32 = 0x1;
+
+/// `VkPhysicalDeviceTimelineSemaphoreFeaturesKHR`, not exposed by `ash`.
+#[repr(C)]
+pub(crate) struct PhysicalDeviceTimelineSemaphoreFeatures {
+    pub s_type: u32,
+    pub p_next: *mut vk::types::c_void,
+    pub timeline_semaphore: u32,
+}
+
+/// `VkSemaphoreTypeCreateInfoKHR`, not exposed by `ash`.
+#[repr(C)]
+struct SemaphoreTypeCreateInfo {
+    s_type: u32,
+    p_next: *const vk::types::c_void,
+    semaphore_type: u32,
+    initial_value: u64,
+}
+
+/// `VkTimelineSemaphoreSubmitInfoKHR`, not exposed by `ash`.
+#[repr(C)]
+pub(crate) struct TimelineSemaphoreSubmitInfo {
+    pub s_type: u32,
+    pub p_next: *const vk::types::c_void,
+    pub wait_semaphore_value_count: u32,
+    pub p_wait_semaphore_values: *const u64,
+    pub signal_semaphore_value_count: u32,
+    pub p_signal_semaphore_values: *const u64,
+}
+
+/// `VkSemaphoreWaitInfoKHR`, not exposed by `ash`.
+#[repr(C)]
+struct SemaphoreWaitInfo {
+    s_type: u32,
+    p_next: *const vk::types::c_void,
+    flags: u32,
+    semaphore_count: u32,
+    p_semaphores: *const vk::Semaphore,
+    p_values: *const u64,
+}
+
+/// `VkSemaphoreSignalInfoKHR`, not exposed by `ash`.
+#[repr(C)]
+struct SemaphoreSignalInfo {
+    s_type: u32,
+    p_next: *const vk::types::c_void,
+    semaphore: vk::Semaphore,
+    value: u64,
+}
+
+/// `VK_STRUCTURE_TYPE_*` values of `VK_KHR_get_physical_device_properties2` and
+/// `VK_EXT_descriptor_indexing`, not exposed by `ash`.
+pub(crate) const STRUCTURE_TYPE_PHYSICAL_DEVICE_FEATURES_2: u32 = 1000059000;
+const STRUCTURE_TYPE_DESCRIPTOR_SET_LAYOUT_BINDING_FLAGS_CREATE_INFO: u32 = 1000161000;
+pub(crate) const STRUCTURE_TYPE_PHYSICAL_DEVICE_DESCRIPTOR_INDEXING_FEATURES: u32 = 1000161001;
+pub(crate) const STRUCTURE_TYPE_DESCRIPTOR_SET_VARIABLE_DESCRIPTOR_COUNT_ALLOCATE_INFO: u32 = 1000161003;
+/// `VK_DESCRIPTOR_SET_LAYOUT_CREATE_UPDATE_AFTER_BIND_POOL_BIT_EXT`.
+const DESCRIPTOR_SET_LAYOUT_CREATE_UPDATE_AFTER_BIND_POOL: u32 = 0x2;
+/// `VK_DESCRIPTOR_POOL_CREATE_UPDATE_AFTER_BIND_BIT_EXT`.
+const DESCRIPTOR_POOL_CREATE_UPDATE_AFTER_BIND: u32 = 0x2;
+
+/// `VkPhysicalDeviceFeatures2KHR`, not exposed by `ash`.
+#[repr(C)]
+pub(crate) struct PhysicalDeviceFeatures2 {
+    pub s_type: u32,
+    pub p_next: *mut vk::types::c_void,
+    pub features: vk::PhysicalDeviceFeatures,
+}
+
+/// `vkGetPhysicalDeviceFeatures2KHR`, not exposed by `ash`.
+pub(crate) type GetPhysicalDeviceFeatures2 = extern "system" fn(vk::PhysicalDevice, *mut PhysicalDeviceFeatures2);
+
+/// `VK_STRUCTURE_TYPE_*` values of `VK_KHR_get_physical_device_properties2` and Vulkan 1.1,
+/// not exposed by `ash`.
+pub(crate) const STRUCTURE_TYPE_PHYSICAL_DEVICE_PROPERTIES_2: u32 = 1000059001;
+pub(crate) const STRUCTURE_TYPE_PHYSICAL_DEVICE_SUBGROUP_PROPERTIES: u32 = 1000094000;
+
+/// `VkPhysicalDeviceProperties2KHR`, not exposed by `ash`.
+#[repr(C)]
+pub(crate) struct PhysicalDeviceProperties2 {
+    pub s_type: u32,
+    pub p_next: *mut vk::types::c_void,
+    pub properties: vk::PhysicalDeviceProperties,
+}
+
+/// `vkGetPhysicalDeviceProperties2KHR`, not exposed by `ash`.
+pub(crate) type GetPhysicalDeviceProperties2 = extern "system" fn(vk::PhysicalDevice, *mut PhysicalDeviceProperties2);
+
+/// `VkPhysicalDeviceSubgroupProperties` of Vulkan 1.1, not exposed by `ash`.
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub(crate) struct PhysicalDeviceSubgroupProperties {
+    pub s_type: u32,
+    pub p_next: *mut vk::types::c_void,
+    pub subgroup_size: u32,
+    pub supported_stages: u32,
+    pub supported_operations: u32,
+    pub quad_operations_in_all_stages: u32,
+}
+
+/// `VK_SUBGROUP_FEATURE_*_BIT` values of Vulkan 1.1.
+const SUBGROUP_FEATURE_BASIC: u32 = 0x1;
+const SUBGROUP_FEATURE_VOTE: u32 = 0x2;
+const SUBGROUP_FEATURE_ARITHMETIC: u32 = 0x4;
+const SUBGROUP_FEATURE_BALLOT: u32 = 0x8;
+const SUBGROUP_FEATURE_SHUFFLE: u32 = 0x10;
+const SUBGROUP_FEATURE_SHUFFLE_RELATIVE: u32 = 0x20;
+const SUBGROUP_FEATURE_QUAD: u32 = 0x80;
+
+impl PhysicalDeviceSubgroupProperties {
+    /// Subgroup operations supported by compute shaders, the only stage
+    /// required to support them.
+    pub(crate) fn features(&self) -> Features {
+        let mut features = Features::empty();
+        if self.supported_stages & vk::SHADER_STAGE_COMPUTE_BIT.flags() == 0 {
+            return features;
+        }
+        let operations = self.supported_operations;
+        if operations & SUBGROUP_FEATURE_BASIC != 0 {
+            features |= Features::SUBGROUP_BASIC;
+        }
+        if operations & SUBGROUP_FEATURE_VOTE != 0 {
+            features |= Features::SUBGROUP_VOTE;
+        }
+        if operations & SUBGROUP_FEATURE_ARITHMETIC != 0 {
+            features |= Features::SUBGROUP_ARITHMETIC;
+        }
+        if operations & SUBGROUP_FEATURE_BALLOT != 0 {
+            features |= Features::SUBGROUP_BALLOT;
+        }
+        let shuffle = SUBGROUP_FEATURE_SHUFFLE | SUBGROUP_FEATURE_SHUFFLE_RELATIVE;
+        if operations & shuffle == shuffle {
+            features |= Features::SUBGROUP_SHUFFLE;
+        }
+        if operations & SUBGROUP_FEATURE_QUAD != 0 {
+            features |= Features::SUBGROUP_QUAD;
+        }
+        features
+    }
+}
+
+/// `VkPhysicalDeviceDescriptorIndexingFeaturesEXT`, not exposed by `ash`.
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub(crate) struct PhysicalDeviceDescriptorIndexingFeatures {
+    pub s_type: u32,
+    pub p_next: *mut vk::types::c_void,
+    pub shader_input_attachment_array_dynamic_indexing: u32,
+    pub shader_uniform_texel_buffer_array_dynamic_indexing: u32,
+    pub shader_storage_texel_buffer_array_dynamic_indexing: u32,
+    pub shader_uniform_buffer_array_non_uniform_indexing: u32,
+    pub shader_sampled_image_array_non_uniform_indexing: u32,
+    pub shader_storage_buffer_array_non_uniform_indexing: u32,
+    pub shader_storage_image_array_non_uniform_indexing: u32,
+    pub shader_input_attachment_array_non_uniform_indexing: u32,
+    pub shader_uniform_texel_buffer_array_non_uniform_indexing: u32,
+    pub shader_storage_texel_buffer_array_non_uniform_indexing: u32,
+    pub descriptor_binding_uniform_buffer_update_after_bind: u32,
+    pub descriptor_binding_sampled_image_update_after_bind: u32,
+    pub descriptor_binding_storage_image_update_after_bind: u32,
+    pub descriptor_binding_storage_buffer_update_after_bind: u32,
+    pub descriptor_binding_uniform_texel_buffer_update_after_bind: u32,
+    pub descriptor_binding_storage_texel_buffer_update_after_bind: u32,
+    pub descriptor_binding_update_unused_while_pending: u32,
+    pub descriptor_binding_partially_bound: u32,
+    pub descriptor_binding_variable_descriptor_count: u32,
+    pub runtime_descriptor_array: u32,
+}
+
+impl PhysicalDeviceDescriptorIndexingFeatures {
+    /// Check if the features making up `Features::DESCRIPTOR_INDEXING` are supported.
+    pub(crate) fn is_supported(&self) -> bool {
+        self.shader_sampled_image_array_non_uniform_indexing != 0 &&
+            self.descriptor_binding_sampled_image_update_after_bind != 0 &&
+            self.descriptor_binding_update_unused_while_pending != 0 &&
+            self.descriptor_binding_partially_bound != 0 &&
+            self.descriptor_binding_variable_descriptor_count != 0 &&
+            self.runtime_descriptor_array != 0
+    }
+}
+
+/// `VK_STRUCTURE_TYPE_PHYSICAL_DEVICE_SHADER_CLOCK_FEATURES_KHR`, not exposed by `ash`.
+pub(crate) const STRUCTURE_TYPE_PHYSICAL_DEVICE_SHADER_CLOCK_FEATURES: u32 = 1000181000;
+
+/// `VkPhysicalDeviceShaderClockFeaturesKHR`, not exposed by `ash`.
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub(crate) struct PhysicalDeviceShaderClockFeatures {
+    pub s_type: u32,
+    pub p_next: *mut vk::types::c_void,
+    pub shader_subgroup_clock: u32,
+    pub shader_device_clock: u32,
+}
+
+/// `VK_STRUCTURE_TYPE_PHYSICAL_DEVICE_FRAGMENT_SHADER_BARYCENTRIC_FEATURES_KHR`, not exposed by `ash`.
+pub(crate) const STRUCTURE_TYPE_PHYSICAL_DEVICE_FRAGMENT_SHADER_BARYCENTRIC_FEATURES: u32 = 1000203000;
+
+/// `VkPhysicalDeviceFragmentShaderBarycentricFeaturesKHR`, not exposed by `ash`.
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub(crate) struct PhysicalDeviceFragmentShaderBarycentricFeatures {
+    pub s_type: u32,
+    pub p_next: *mut vk::types::c_void,
+    pub fragment_shader_barycentric: u32,
+}
+
+/// `VK_STRUCTURE_TYPE_PHYSICAL_DEVICE_*_FEATURES` values of `VK_KHR_shader_atomic_int64`,
+/// `VK_EXT_shader_image_atomic_int64` and `VK_EXT_shader_atomic_float`, not exposed by `ash`.
+pub(crate) const STRUCTURE_TYPE_PHYSICAL_DEVICE_SHADER_ATOMIC_INT64_FEATURES: u32 = 1000180000;
+pub(crate) const STRUCTURE_TYPE_PHYSICAL_DEVICE_SHADER_IMAGE_ATOMIC_INT64_FEATURES: u32 = 1000234000;
+pub(crate) const STRUCTURE_TYPE_PHYSICAL_DEVICE_SHADER_ATOMIC_FLOAT_FEATURES: u32 = 1000260000;
+
+/// `VkPhysicalDeviceShaderAtomicInt64FeaturesKHR`, not exposed by `ash`.
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub(crate) struct PhysicalDeviceShaderAtomicInt64Features {
+    pub s_type: u32,
+    pub p_next: *mut vk::types::c_void,
+    pub shader_buffer_int64_atomics: u32,
+    pub shader_shared_int64_atomics: u32,
+}
+
+impl PhysicalDeviceShaderAtomicInt64Features {
+    pub(crate) fn features(&self) -> Features {
+        let mut features = Features::empty();
+        if self.shader_buffer_int64_atomics != 0 {
+            features |= Features::SHADER_BUFFER_INT64_ATOMICS;
+        }
+        if self.shader_shared_int64_atomics != 0 {
+            features |= Features::SHADER_SHARED_INT64_ATOMICS;
+        }
+        features
+    }
+}
+
+/// `VkPhysicalDeviceShaderImageAtomicInt64FeaturesEXT`, not exposed by `ash`.
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub(crate) struct PhysicalDeviceShaderImageAtomicInt64Features {
+    pub s_type: u32,
+    pub p_next: *mut vk::types::c_void,
+    pub shader_image_int64_atomics: u32,
+    pub sparse_image_int64_atomics: u32,
+}
+
+impl PhysicalDeviceShaderImageAtomicInt64Features {
+    pub(crate) fn features(&self) -> Features {
+        if self.shader_image_int64_atomics != 0 {
+            Features::SHADER_IMAGE_INT64_ATOMICS
+        } else {
+            Features::empty()
+        }
+    }
+}
+
+/// `VK_STRUCTURE_TYPE_PHYSICAL_DEVICE_*_FEATURES` values of `VK_KHR_shader_float16_int8`,
+/// `VK_KHR_16bit_storage` and `VK_KHR_8bit_storage`, not exposed by `ash`.
+pub(crate) const STRUCTURE_TYPE_PHYSICAL_DEVICE_SHADER_FLOAT16_INT8_FEATURES: u32 = 1000082000;
+pub(crate) const STRUCTURE_TYPE_PHYSICAL_DEVICE_16BIT_STORAGE_FEATURES: u32 = 1000083000;
+pub(crate) const STRUCTURE_TYPE_PHYSICAL_DEVICE_8BIT_STORAGE_FEATURES: u32 = 1000177000;
+
+/// `VkPhysicalDeviceShaderFloat16Int8FeaturesKHR`, not exposed by `ash`.
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub(crate) struct PhysicalDeviceShaderFloat16Int8Features {
+    pub s_type: u32,
+    pub p_next: *mut vk::types::c_void,
+    pub shader_float16: u32,
+    pub shader_int8: u32,
+}
+
+impl PhysicalDeviceShaderFloat16Int8Features {
+    pub(crate) fn features(&self) -> Features {
+        let mut features = Features::empty();
+        if self.shader_float16 != 0 {
+            features |= Features::SHADER_FLOAT16;
+        }
+        if self.shader_int8 != 0 {
+            features |= Features::SHADER_INT8;
+        }
+        features
+    }
+}
+
+/// `VkPhysicalDevice16BitStorageFeaturesKHR`, not exposed by `ash`.
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub(crate) struct PhysicalDevice16BitStorageFeatures {
+    pub s_type: u32,
+    pub p_next: *mut vk::types::c_void,
+    pub storage_buffer16_bit_access: u32,
+    pub uniform_and_storage_buffer16_bit_access: u32,
+    pub storage_push_constant16: u32,
+    pub storage_input_output16: u32,
+}
+
+impl PhysicalDevice16BitStorageFeatures {
+    /// `Features::STORAGE_16BIT` covers the buffer and push constant storage classes at once.
+    pub(crate) fn features(&self) -> Features {
+        let mut features = Features::empty();
+        if self.storage_buffer16_bit_access != 0 &&
+            self.uniform_and_storage_buffer16_bit_access != 0 &&
+            self.storage_push_constant16 != 0
+        {
+            features |= Features::STORAGE_16BIT;
+        }
+        if self.storage_input_output16 != 0 {
+            features |= Features::STORAGE_INPUT_OUTPUT_16BIT;
+        }
+        features
+    }
+}
+
+/// `VkPhysicalDevice8BitStorageFeaturesKHR`, not exposed by `ash`.
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub(crate) struct PhysicalDevice8BitStorageFeatures {
+    pub s_type: u32,
+    pub p_next: *mut vk::types::c_void,
+    pub storage_buffer8_bit_access: u32,
+    pub uniform_and_storage_buffer8_bit_access: u32,
+    pub storage_push_constant8: u32,
+}
+
+impl PhysicalDevice8BitStorageFeatures {
+    /// `Features::STORAGE_8BIT` covers all the storage classes at once.
+    pub(crate) fn features(&self) -> Features {
+        if self.storage_buffer8_bit_access != 0 &&
+            self.uniform_and_storage_buffer8_bit_access != 0 &&
+            self.storage_push_constant8 != 0
+        {
+            Features::STORAGE_8BIT
+        } else {
+            Features::empty()
+        }
+    }
+}
+
+/// `VkPhysicalDeviceShaderAtomicFloatFeaturesEXT`, not exposed by `ash`.
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub(crate) struct PhysicalDeviceShaderAtomicFloatFeatures {
+    pub s_type: u32,
+    pub p_next: *mut vk::types::c_void,
+    pub shader_buffer_float32_atomics: u32,
+    pub shader_buffer_float32_atomic_add: u32,
+    pub shader_buffer_float64_atomics: u32,
+    pub shader_buffer_float64_atomic_add: u32,
+    pub shader_shared_float32_atomics: u32,
+    pub shader_shared_float32_atomic_add: u32,
+    pub shader_shared_float64_atomics: u32,
+    pub shader_shared_float64_atomic_add: u32,
+    pub shader_image_float32_atomics: u32,
+    pub shader_image_float32_atomic_add: u32,
+    pub sparse_image_float32_atomics: u32,
+    pub sparse_image_float32_atomic_add: u32,
+}
+
+impl PhysicalDeviceShaderAtomicFloatFeatures {
+    /// Float atomics are only exposed along with `OpAtomicFAddEXT`, since shaders
+    /// are checked against the type of the atomic operands and not the operations.
+    pub(crate) fn features(&self) -> Features {
+        let mut features = Features::empty();
+        if self.shader_buffer_float32_atomics != 0 && self.shader_buffer_float32_atomic_add != 0 {
+            features |= Features::SHADER_BUFFER_FLOAT32_ATOMICS;
+        }
+        if self.shader_buffer_float64_atomics != 0 && self.shader_buffer_float64_atomic_add != 0 {
+            features |= Features::SHADER_BUFFER_FLOAT64_ATOMICS;
+        }
+        if self.shader_shared_float32_atomics != 0 && self.shader_shared_float32_atomic_add != 0 {
+            features |= Features::SHADER_SHARED_FLOAT32_ATOMICS;
+        }
+        if self.shader_shared_float64_atomics != 0 && self.shader_shared_float64_atomic_add != 0 {
+            features |= Features::SHADER_SHARED_FLOAT64_ATOMICS;
+        }
+        if self.shader_image_float32_atomics != 0 && self.shader_image_float32_atomic_add != 0 {
+            features |= Features::SHADER_IMAGE_FLOAT32_ATOMICS;
+        }
+        features
+    }
+}
+
+/// `VkDescriptorSetLayoutBindingFlagsCreateInfoEXT`, not exposed by `ash`.
+#[repr(C)]
+struct DescriptorSetLayoutBindingFlagsCreateInfo {
+    s_type: u32,
+    p_next: *const vk::types::c_void,
+    binding_count: u32,
+    p_binding_flags: *const u32,
+}
+
+/// `VkDescriptorSetVariableDescriptorCountAllocateInfoEXT`, not exposed by `ash`.
+#[repr(C)]
+pub(crate) struct DescriptorSetVariableDescriptorCountAllocateInfo {
+    pub s_type: u32,
+    pub p_next: *const vk::types::c_void,
+    pub descriptor_set_count: u32,
+    pub p_descriptor_counts: *const u32,
+}
+
+/// Device functions of `VK_KHR_timeline_semaphore`, not exposed by `ash`.
+#[derive(Clone, Copy)]
+pub(crate) struct TimelineSemaphoreFn {
+    get_semaphore_counter_value: extern "system" fn(vk::Device, vk::Semaphore, *mut u64) -> vk::Result,
+    wait_semaphores: extern "system" fn(vk::Device, *const SemaphoreWaitInfo, u64) -> vk::Result,
+    signal_semaphore: extern "system" fn(vk::Device, *const SemaphoreSignalInfo) -> vk::Result,
+}
+
+impl TimelineSemaphoreFn {
+    /// Load the functions with `get_proc_addr`, returns `None` if any of them is missing.
+    pub(crate) fn load<F>(mut get_proc_addr: F) -> Option<Self>
+    where
+        F: FnMut(&CStr) -> *const vk::types::c_void,
+    {
+        let mut load = |name: &[u8]| {
+            let name = CStr::from_bytes_with_nul(name).unwrap();
+            let function = get_proc_addr(name);
+            if function.is_null() { None } else { Some(function) }
+        };
+        unsafe {
+            Some(TimelineSemaphoreFn {
+                get_semaphore_counter_value: mem::transmute(load(b"vkGetSemaphoreCounterValueKHR\0")?),
+                wait_semaphores: mem::transmute(load(b"vkWaitSemaphoresKHR\0")?),
+                signal_semaphore: mem::transmute(load(b"vkSignalSemaphoreKHR\0")?),
+            })
+        }
+    }
+}
+
 #[derive(Debug)]
 pub struct UnboundBuffer(n::Buffer);
 
@@ -28,90 +561,104 @@ pub struct UnboundBuffer(n::Buffer);
 pub struct UnboundImage(n::Image);
 
 impl Device {
-    #[cfg(feature = "glsl-to-spirv")]
-    pub fn create_shader_module_from_glsl(
+    /// Get the native Vulkan device.
+    ///
+    /// Required for FFI with libraries like OpenXR.
+    pub fn as_raw(&self) -> vk::Device {
+        self.raw.0.handle()
+    }
+
+    /// Wrap an existing image into an `Image`, e.g. a swapchain image handed over by OpenXR.
+    ///
+    /// `kind` and `storage_flags` must match the creation parameters of the image. The image
+    /// doesn't take ownership of the raw image and must not be destroyed with `destroy_image`.
+    pub unsafe fn image_from_raw(
         &self,
-        code: &str,
-        stage: pso::Stage,
-    ) -> Result<n::ShaderModule, d::ShaderError> {
-        use self::d::Device;
-        use std::io::Read;
-        use glsl_to_spirv::{compile, ShaderType};
-
-        let ty = match stage {
-            pso::Stage::Vertex => ShaderType::Vertex,
-            pso::Stage::Fragment => ShaderType::Fragment,
-            pso::Stage::Geometry => ShaderType::Geometry,
-            pso::Stage::Hull => ShaderType::TessellationControl,
-            pso::Stage::Domain => ShaderType::TessellationEvaluation,
-            pso::Stage::Compute => ShaderType::Compute,
-        };
-
-        match compile(code, ty) {
-            Ok(mut file) => {
-                let mut data = Vec::new();
-                file.read_to_end(&mut data).unwrap();
-                self.create_shader_module(&data)
+        raw: vk::Image,
+        kind: image::Kind,
+        storage_flags: image::StorageFlags,
+    ) -> n::Image {
+        n::Image {
+            raw,
+            ty: match kind {
+                image::Kind::D1(..) => vk::ImageType::Type1d,
+                image::Kind::D2(..) => vk::ImageType::Type2d,
+                image::Kind::D3(..) => vk::ImageType::Type3d,
             },
-            Err(string) => Err(d::ShaderError::CompilationFailed(string)),
+            flags: conv::map_image_flags(storage_flags),
+            extent: conv::map_extent(kind.extent()),
+            samples: kind.num_samples(),
         }
     }
-}
-
-impl d::Device<B> for Device {
-    fn allocate_memory(&self, mem_type: MemoryTypeId, size: u64) -> Result<n::Memory, d::OutOfMemory> {
-        let info = vk::MemoryAllocateInfo {
-            s_type: vk::StructureType::MemoryAllocateInfo,
-            p_next: ptr::null(),
-            allocation_size: size,
-            memory_type_index: mem_type.0 as _,
-        };
-
-        let memory = unsafe {
-            self.raw.0.allocate_memory(&info, None)
-        }.expect("Error on memory allocation"); // TODO: error handling
 
-        Ok(n::Memory { raw: memory })
+    fn check_shader_features(&self, source: &pso::EntryPoint<B>) -> Result<(), pso::CreationError> {
+        let missing = source.module.required_features - self.raw.1;
+        if missing.is_empty() {
+            Ok(())
+        } else {
+            Err(pso::CreationError::Shader(d::ShaderError::MissingFeatures(missing)))
+        }
     }
 
-    fn create_command_pool(
-        &self, family: queue::QueueFamilyId, create_flags: CommandPoolCreateFlags
-    ) -> RawCommandPool {
-        let mut flags = vk::CommandPoolCreateFlags::empty();
-        if create_flags.contains(CommandPoolCreateFlags::TRANSIENT) {
-            flags |= vk::COMMAND_POOL_CREATE_TRANSIENT_BIT;
-        }
-        if create_flags.contains(CommandPoolCreateFlags::RESET_INDIVIDUAL) {
-            flags |= vk::COMMAND_POOL_CREATE_RESET_COMMAND_BUFFER_BIT;
-        }
+    fn create_image_raw(
+        &self,
+        kind: image::Kind,
+        mip_levels: image::Level,
+        format: format::Format,
+        tiling: image::Tiling,
+        usage: image::Usage,
+        storage_flags: image::StorageFlags,
+        p_next: *const vk::types::c_void,
+    ) -> Result<UnboundImage, image::CreationError> {
+        let flags = conv::map_image_flags(storage_flags);
+        let extent = conv::map_extent(kind.extent());
+        let array_layers = kind.num_layers();
+        let samples = kind.num_samples();
+        let image_type = match kind {
+            image::Kind::D1(..) => vk::ImageType::Type1d,
+            image::Kind::D2(..) => vk::ImageType::Type2d,
+            image::Kind::D3(..) => vk::ImageType::Type3d,
+        };
 
-        let info = vk::CommandPoolCreateInfo {
-            s_type: vk::StructureType::CommandPoolCreateInfo,
-            p_next: ptr::null(),
+        let info = vk::ImageCreateInfo {
+            s_type: vk::StructureType::ImageCreateInfo,
+            p_next,
             flags,
-            queue_family_index: family.0 as _,
+            image_type,
+            format: conv::map_format(format),
+            extent: extent.clone(),
+            mip_levels: mip_levels as u32,
+            array_layers: array_layers as u32,
+            samples: vk::SampleCountFlags::from_flags_truncate(samples as u32),
+            tiling: conv::map_tiling(tiling),
+            usage: conv::map_image_usage(usage),
+            sharing_mode: vk::SharingMode::Exclusive, // TODO:
+            queue_family_index_count: 0,
+            p_queue_family_indices: ptr::null(),
+            initial_layout: vk::ImageLayout::Undefined,
         };
 
-        let command_pool_raw = unsafe {
-            self.raw.0
-                .create_command_pool(&info, None)
-        }.expect("Error on command pool creation"); // TODO: better error handling
+        let raw = unsafe {
+            self.raw.0.create_image(&info, None)
+        }.expect("Error on image creation"); // TODO: error handling
 
-        RawCommandPool {
-            raw: command_pool_raw,
-            device: self.raw.clone(),
-        }
+        Ok(UnboundImage(n::Image{ raw, ty: image_type, flags, extent, samples }))
     }
+}
 
-    fn destroy_command_pool(&self, pool: RawCommandPool) {
-        unsafe {
-            self.raw.0
-                .destroy_command_pool(pool.raw, None)
-        };
+impl RawDevice {
+    /// Map the layout, the per-aspect read-only depth-stencil layouts fall back
+    /// to `General` without `VK_KHR_maintenance2`.
+    pub(crate) fn map_image_layout(&self, layout: image::Layout) -> vk::ImageLayout {
+        match layout {
+            image::Layout::DepthReadOnlyStencilAttachmentOptimal |
+            image::Layout::DepthAttachmentStencilReadOnlyOptimal if !self.6 => vk::ImageLayout::General,
+            _ => conv::map_image_layout(layout),
+        }
     }
 
-    fn create_render_pass<'a, IA, IS, ID>(
-        &self, attachments: IA, subpasses: IS, dependencies: ID
+    pub(crate) fn create_render_pass_raw<'a, IA, IS, ID>(
+        &self, attachments: IA, subpasses: IS, dependencies: ID, p_next: *const vk::types::c_void,
     ) -> n::RenderPass
     where
         IA: IntoIterator,
@@ -133,13 +680,13 @@ impl d::Device<B> for Device {
             vk::AttachmentDescription {
                 flags: vk::AttachmentDescriptionFlags::empty(), // TODO: may even alias!
                 format: attachment.format.map_or(vk::Format::Undefined, conv::map_format),
-                samples: vk::SAMPLE_COUNT_1_BIT, // TODO: multisampling
+                samples: vk::SampleCountFlags::from_flags_truncate(attachment.samples as u32),
                 load_op: conv::map_attachment_load_op(attachment.ops.load),
                 store_op: conv::map_attachment_store_op(attachment.ops.store),
                 stencil_load_op: conv::map_attachment_load_op(attachment.stencil_ops.load),
                 stencil_store_op: conv::map_attachment_store_op(attachment.stencil_ops.store),
-                initial_layout: conv::map_image_layout(attachment.layouts.start),
-                final_layout: conv::map_image_layout(attachment.layouts.end),
+                initial_layout: self.map_image_layout(attachment.layouts.start),
+                final_layout: self.map_image_layout(attachment.layouts.end),
             }
         }).collect::<Vec<_>>();
 
@@ -148,12 +695,12 @@ impl d::Device<B> for Device {
         let subpasses = subpasses.into_iter().map(|subpass| {
             let subpass = subpass.borrow();
             {
-                fn make_ref(&(id, layout): &pass::AttachmentRef) -> vk::AttachmentReference {
+                let make_ref = |&(id, layout): &pass::AttachmentRef| {
                     vk::AttachmentReference {
                         attachment: id as _,
-                        layout: conv::map_image_layout(layout),
+                        layout: self.map_image_layout(layout),
                     }
-                }
+                };
                 let colors = subpass.colors.iter()
                     .map(make_ref)
                     .collect::<Vec<_>>();
@@ -162,15 +709,20 @@ impl d::Device<B> for Device {
                 let inputs = subpass.inputs.iter()
                     .map(make_ref)
                     .collect::<Vec<_>>();
+                let resolves = subpass.resolves.iter()
+                    .map(make_ref)
+                    .collect::<Vec<_>>();
                 let preserves = subpass.preserves.iter()
                     .map(|&id| id as u32)
                     .collect::<Vec<_>>();
 
-                attachment_refs.push((colors, depth_stencil, inputs, preserves));
+                attachment_refs.push((colors, depth_stencil, inputs, resolves, preserves));
             }
 
-            let &(ref color_attachments, ref depth_stencil, ref input_attachments, ref preserve_attachments) =
+            let &(ref color_attachments, ref depth_stencil, ref input_attachments, ref resolve_attachments, ref preserve_attachments) =
                 attachment_refs.last().unwrap();
+            // Resolve attachments are either absent or match the color attachments one to one.
+            assert!(resolve_attachments.is_empty() || resolve_attachments.len() == color_attachments.len());
 
             vk::SubpassDescription {
                 flags: vk::SubpassDescriptionFlags::empty(),
@@ -179,7 +731,11 @@ impl d::Device<B> for Device {
                 p_input_attachments: input_attachments.as_ptr(),
                 color_attachment_count: color_attachments.len() as u32,
                 p_color_attachments: color_attachments.as_ptr(),
-                p_resolve_attachments: ptr::null(), // TODO
+                p_resolve_attachments: if resolve_attachments.is_empty() {
+                    ptr::null()
+                } else {
+                    resolve_attachments.as_ptr()
+                },
                 p_depth_stencil_attachment: match *depth_stencil {
                     Some(ref aref) => aref as *const _,
                     None => ptr::null(),
@@ -205,7 +761,7 @@ impl d::Device<B> for Device {
 
         let info = vk::RenderPassCreateInfo {
             s_type: vk::StructureType::RenderPassCreateInfo,
-            p_next: ptr::null(),
+            p_next,
             flags: vk::RenderPassCreateFlags::empty(),
             attachment_count: attachments.len() as u32,
             p_attachments: attachments.as_ptr(),
@@ -216,12 +772,116 @@ impl d::Device<B> for Device {
         };
 
         let renderpass = unsafe {
-            self.raw.0.create_render_pass(&info, None)
+            self.0.create_render_pass(&info, None)
                 .expect("Error on render pass creation") // TODO: handle this better
         };
 
         n::RenderPass { raw: renderpass }
     }
+}
+
+impl d::Device<B> for Device {
+    fn allocate_memory(&self, mem_type: MemoryTypeId, size: u64) -> Result<n::Memory, d::OutOfMemory> {
+        let info = vk::MemoryAllocateInfo {
+            s_type: vk::StructureType::MemoryAllocateInfo,
+            p_next: ptr::null(),
+            allocation_size: size,
+            memory_type_index: mem_type.0 as _,
+        };
+
+        let memory = unsafe {
+            self.raw.0.allocate_memory(&info, None)
+        }.expect("Error on memory allocation"); // TODO: error handling
+
+        Ok(n::Memory { raw: memory })
+    }
+
+    fn create_command_pool(
+        &self, family: queue::QueueFamilyId, create_flags: CommandPoolCreateFlags
+    ) -> RawCommandPool {
+        let mut flags = vk::CommandPoolCreateFlags::empty();
+        if create_flags.contains(CommandPoolCreateFlags::TRANSIENT) {
+            flags |= vk::COMMAND_POOL_CREATE_TRANSIENT_BIT;
+        }
+        if create_flags.contains(CommandPoolCreateFlags::RESET_INDIVIDUAL) {
+            flags |= vk::COMMAND_POOL_CREATE_RESET_COMMAND_BUFFER_BIT;
+        }
+
+        let info = vk::CommandPoolCreateInfo {
+            s_type: vk::StructureType::CommandPoolCreateInfo,
+            p_next: ptr::null(),
+            flags,
+            queue_family_index: family.0 as _,
+        };
+
+        let command_pool_raw = unsafe {
+            self.raw.0
+                .create_command_pool(&info, None)
+        }.expect("Error on command pool creation"); // TODO: better error handling
+
+        RawCommandPool {
+            raw: command_pool_raw,
+            device: self.raw.clone(),
+        }
+    }
+
+    fn destroy_command_pool(&self, pool: RawCommandPool) {
+        unsafe {
+            self.raw.0
+                .destroy_command_pool(pool.raw, None)
+        };
+    }
+
+    fn create_render_pass<'a, IA, IS, ID>(
+        &self, attachments: IA, subpasses: IS, dependencies: ID
+    ) -> n::RenderPass
+    where
+        IA: IntoIterator,
+        IA::Item: Borrow<pass::Attachment>,
+        IS: IntoIterator,
+        IS::Item: Borrow<pass::SubpassDesc<'a>>,
+        ID: IntoIterator,
+        ID::Item: Borrow<pass::SubpassDependency>,
+    {
+        self.raw.create_render_pass_raw(attachments, subpasses, dependencies, ptr::null())
+    }
+
+    fn create_render_pass_multiview<'a, IA, IS, ID>(
+        &self,
+        attachments: IA,
+        subpasses: IS,
+        dependencies: ID,
+        view_masks: &[pass::ViewMask],
+        correlation_masks: &[pass::ViewMask],
+    ) -> Result<n::RenderPass, d::MultiviewError>
+    where
+        IA: IntoIterator,
+        IA::Item: Borrow<pass::Attachment>,
+        IS: IntoIterator,
+        IS::Item: Borrow<pass::SubpassDesc<'a>>,
+        ID: IntoIterator,
+        ID::Item: Borrow<pass::SubpassDependency>,
+    {
+        if !self.raw.1.contains(Features::MULTIVIEW) {
+            return Err(d::MultiviewError);
+        }
+        let multiview = RenderPassMultiviewCreateInfo {
+            s_type: STRUCTURE_TYPE_RENDER_PASS_MULTIVIEW_CREATE_INFO,
+            p_next: ptr::null(),
+            subpass_count: view_masks.len() as u32,
+            p_view_masks: view_masks.as_ptr(),
+            dependency_count: 0,
+            p_view_offsets: ptr::null(),
+            correlation_mask_count: correlation_masks.len() as u32,
+            p_correlation_masks: correlation_masks.as_ptr(),
+        };
+        Ok(self.raw.create_render_pass_raw(
+            attachments,
+            subpasses,
+            dependencies,
+            &multiview as *const _ as *const _,
+        ))
+    }
 
     fn create_pipeline_layout<IS, IR>(&self, sets: IS, push_constant_ranges: IR) -> n::PipelineLayout
     where
@@ -270,6 +930,16 @@ impl d::Device<B> for Device {
     fn create_graphics_pipelines<'a, T>(
         &self, descs: T
     ) -> Vec<Result<n::GraphicsPipeline, pso::CreationError>>
+    where
+        T: IntoIterator,
+        T::Item: Borrow<pso::GraphicsPipelineDesc<'a, B>>,
+    {
+        d::Device::create_graphics_pipelines_with_cache(self, descs, None)
+    }
+
+    fn create_graphics_pipelines_with_cache<'a, T>(
+        &self, descs: T, cache: Option<&n::PipelineCache>,
+    ) -> Vec<Result<n::GraphicsPipeline, pso::CreationError>>
     where
         T: IntoIterator,
         T::Item: Borrow<pso::GraphicsPipelineDesc<'a, B>>,
@@ -335,6 +1005,22 @@ impl d::Device<B> for Device {
 
         let infos = descs.iter().map(|desc| {
             let desc = desc.borrow();
+            {
+                let shaders = &desc.shaders;
+                let entries = [Some(&shaders.vertex), shaders.hull.as_ref(), shaders.domain.as_ref(),
+                    shaders.geometry.as_ref(), shaders.fragment.as_ref()];
+                for entry in entries.iter().filter_map(|entry| *entry) {
+                    if let Err(e) = self.check_shader_features(entry) {
+                        return Err(e);
+                    }
+                }
+            }
+            // Points always take their size from `PointSize`.
+            if desc.input_assembler.primitive == hal::Primitive::PointList && !desc.rasterizer.program_point_size {
+                error!("Points always take their size from `PointSize`");
+                return Err(pso::CreationError::Other);
+            }
+
             let mut stages = Vec::new();
             // Vertex stage
             if true { //vertex shader is required
@@ -542,6 +1228,10 @@ impl d::Device<B> for Device {
             });
 
             // Build blend states for color attachments
+            if cfg!(debug_assertions) && desc.blender.srgb == pso::SrgbConversion::Encoded {
+                warn!("Blending on encoded sRGB values is not supported, sRGB targets are blended \
+                    in linear space, render into a UNORM view of the target instead");
+            }
             let blend_states = desc.blender.targets
                 .iter()
                 .map(|&pso::ColorBlendDesc(mask, ref blend)| {
@@ -639,16 +1329,16 @@ impl d::Device<B> for Device {
         }).collect::<Vec<_>>();
 
         let valid_infos = infos.iter().filter_map(|info| info.clone().ok()).collect::<Vec<_>>();
-        let result = if valid_infos.is_empty() {
-            Ok(Vec::new())
+        let (result, context) = if valid_infos.is_empty() {
+            (Ok(Vec::new()), ErrorContext::default())
         } else {
-            unsafe {
+            capture_messages(|| unsafe {
                 self.raw.0.create_graphics_pipelines(
-                    vk::PipelineCache::null(),
+                    cache.map_or(vk::PipelineCache::null(), |cache| cache.raw),
                     &valid_infos,
                     None,
                 )
-            }
+            })
         };
 
         match result {
@@ -660,7 +1350,7 @@ impl d::Device<B> for Device {
                     .map(|result| result.and_then(|_| {
                         let pso = psos.next().unwrap();
                         if pso == vk::Pipeline::null() {
-                            Err(pso::CreationError::Other)
+                            Err(pso::CreationError::Other.with_context(context.clone()))
                         } else {
                             Ok(n::GraphicsPipeline(pso))
                         }
@@ -673,6 +1363,16 @@ impl d::Device<B> for Device {
     fn create_compute_pipelines<'a, T>(
         &self, descs: T
     ) -> Vec<Result<n::ComputePipeline, pso::CreationError>>
+    where
+        T: IntoIterator,
+        T::Item: Borrow<pso::ComputePipelineDesc<'a, B>>,
+    {
+        d::Device::create_compute_pipelines_with_cache(self, descs, None)
+    }
+
+    fn create_compute_pipelines_with_cache<'a, T>(
+        &self, descs: T, cache: Option<&n::PipelineCache>,
+    ) -> Vec<Result<n::ComputePipeline, pso::CreationError>>
     where
         T: IntoIterator,
         T::Item: Borrow<pso::ComputePipelineDesc<'a, B>>,
@@ -684,6 +1384,10 @@ impl d::Device<B> for Device {
 
         let infos = descs.iter().map(|desc| {
             let desc = desc.borrow();
+            if let Err(e) = self.check_shader_features(&desc.shader) {
+                return Err(e);
+            }
+
             let string = CString::new(desc.shader.entry).unwrap();
             let p_name = string.as_ptr();
             c_strings.push(string);
@@ -732,6 +1436,10 @@ impl d::Device<B> for Device {
             if desc.flags.contains(pso::PipelineCreationFlags::ALLOW_DERIVATIVES) {
                 flags |= vk::PIPELINE_CREATE_ALLOW_DERIVATIVES_BIT;
             }
+            // Required by `vkCmdDispatchBaseKHR` with a non-zero base.
+            if self.raw.1.contains(Features::DISPATCH_BASE) {
+                flags = unsafe { mem::transmute(flags.flags() | PIPELINE_CREATE_DISPATCH_BASE) };
+            }
 
             Ok(vk::ComputePipelineCreateInfo {
                 s_type: vk::StructureType::ComputePipelineCreateInfo,
@@ -745,16 +1453,16 @@ impl d::Device<B> for Device {
         }).collect::<Vec<_>>();
 
         let valid_infos = infos.iter().filter_map(|info| info.clone().ok()).collect::<Vec<_>>();
-        let result = if valid_infos.is_empty() {
-            Ok(Vec::new())
+        let (result, context) = if valid_infos.is_empty() {
+            (Ok(Vec::new()), ErrorContext::default())
         } else {
-            unsafe {
+            capture_messages(|| unsafe {
                 self.raw.0.create_compute_pipelines(
-                    vk::PipelineCache::null(),
+                    cache.map_or(vk::PipelineCache::null(), |cache| cache.raw),
                     &valid_infos,
                     None,
                 )
-            }
+            })
         };
 
         match result {
@@ -766,7 +1474,7 @@ impl d::Device<B> for Device {
                     .map(|result| result.and_then(|_| {
                         let pso = psos.next().unwrap();
                         if pso == vk::Pipeline::null() {
-                            Err(pso::CreationError::Other)
+                            Err(pso::CreationError::Other.with_context(context.clone()))
                         } else {
                             Ok(n::ComputePipeline(pso))
                         }
@@ -810,9 +1518,77 @@ impl d::Device<B> for Device {
         Ok(n::Framebuffer { raw: framebuffer })
     }
 
+    fn create_imageless_framebuffer<T>(
+        &self,
+        renderpass: &n::RenderPass,
+        attachments: T,
+        extent: image::Extent,
+    ) -> Result<n::Framebuffer, d::FramebufferError>
+    where
+        T: IntoIterator,
+        T::Item: Borrow<pass::FramebufferAttachment>,
+    {
+        if !self.raw.1.contains(Features::IMAGELESS_FRAMEBUFFER) {
+            error!("Imageless framebuffers require `Features::IMAGELESS_FRAMEBUFFER`");
+            return Err(d::FramebufferError);
+        }
+
+        let attachments = attachments
+            .into_iter()
+            .map(|attachment| attachment.borrow().clone())
+            .collect::<SmallVec<[_; 4]>>();
+        let formats = attachments
+            .iter()
+            .map(|attachment| conv::map_format(attachment.format))
+            .collect::<SmallVec<[_; 4]>>();
+        let image_infos = attachments
+            .iter()
+            .zip(formats.iter())
+            .map(|(attachment, format)| FramebufferAttachmentImageInfo {
+                s_type: STRUCTURE_TYPE_FRAMEBUFFER_ATTACHMENT_IMAGE_INFO,
+                p_next: ptr::null(),
+                flags: conv::map_image_flags(attachment.flags),
+                usage: conv::map_image_usage(attachment.usage),
+                width: extent.width,
+                height: extent.height,
+                layer_count: extent.depth,
+                view_format_count: 1,
+                p_view_formats: format,
+            })
+            .collect::<SmallVec<[_; 4]>>();
+
+        let attachments_info = FramebufferAttachmentsCreateInfo {
+            s_type: STRUCTURE_TYPE_FRAMEBUFFER_ATTACHMENTS_CREATE_INFO,
+            p_next: ptr::null(),
+            attachment_image_info_count: image_infos.len() as u32,
+            p_attachment_image_infos: image_infos.as_ptr(),
+        };
+        let info = vk::FramebufferCreateInfo {
+            s_type: vk::StructureType::FramebufferCreateInfo,
+            p_next: &attachments_info as *const _ as *const _,
+            flags: unsafe { mem::transmute(FRAMEBUFFER_CREATE_IMAGELESS) },
+            render_pass: renderpass.raw,
+            attachment_count: image_infos.len() as u32,
+            p_attachments: ptr::null(),
+            width: extent.width,
+            height: extent.height,
+            layers: extent.depth,
+        };
+
+        let framebuffer = unsafe {
+            self.raw.0.create_framebuffer(&info, None)
+        }.expect("error on framebuffer creation");
+
+        Ok(n::Framebuffer { raw: framebuffer })
+    }
+
     fn create_shader_module(&self, spirv_data: &[u8]) -> Result<n::ShaderModule, d::ShaderError> {
+        if cfg!(debug_assertions) {
+            pso::validate_spirv(spirv_data)?;
+        }
         // spec requires "codeSize must be a multiple of 4"
         assert_eq!(spirv_data.len() & 3, 0);
+        let required_features = pso::reflect_spirv(spirv_data)?.required_features;
 
         let info = vk::ShaderModuleCreateInfo {
             s_type: vk::StructureType::ShaderModuleCreateInfo,
@@ -827,7 +1603,7 @@ impl d::Device<B> for Device {
         };
 
         match module {
-            Ok(raw) => Ok(n::ShaderModule { raw }),
+            Ok(raw) => Ok(n::ShaderModule { raw, required_features }),
             Err(e) => {
                 error!("Shader module error {:?}", e);
                 Err(d::ShaderError::CompilationFailed(String::new())) // TODO
@@ -838,6 +1614,12 @@ impl d::Device<B> for Device {
     fn create_sampler(&self, sampler_info: image::SamplerInfo) -> n::Sampler {
         use hal::pso::Comparison;
 
+        let mut samplers = self.samplers.lock().unwrap();
+        if let Some(&mut (sampler, ref mut count)) = samplers.get_mut(&sampler_info) {
+            *count += 1;
+            return n::Sampler(sampler);
+        }
+
         let (anisotropy_enable, max_anisotropy) = match sampler_info.anisotropic {
             image::Anisotropic::Off => (vk::VK_FALSE, 1.0),
             image::Anisotropic::On(aniso) => {
@@ -881,6 +1663,7 @@ impl d::Device<B> for Device {
                         .expect("error on sampler creation")
         };
 
+        samplers.insert(sampler_info, (sampler, 1));
         n::Sampler(sampler)
     }
 
@@ -957,39 +1740,49 @@ impl d::Device<B> for Device {
         usage: image::Usage,
         storage_flags: image::StorageFlags,
     ) -> Result<UnboundImage, image::CreationError> {
-        let flags = conv::map_image_flags(storage_flags);
-        let extent = conv::map_extent(kind.extent());
-        let array_layers = kind.num_layers();
-        let samples = kind.num_samples() as u32;
-        let image_type = match kind {
-            image::Kind::D1(..) => vk::ImageType::Type1d,
-            image::Kind::D2(..) => vk::ImageType::Type2d,
-            image::Kind::D3(..) => vk::ImageType::Type3d,
-        };
+        self.create_image_raw(kind, mip_levels, format, tiling, usage, storage_flags, ptr::null())
+    }
 
-        let info = vk::ImageCreateInfo {
-            s_type: vk::StructureType::ImageCreateInfo,
+    fn create_image_with_view_formats(
+        &self,
+        kind: image::Kind,
+        mip_levels: image::Level,
+        format: format::Format,
+        tiling: image::Tiling,
+        usage: image::Usage,
+        storage_flags: image::StorageFlags,
+        view_formats: &[format::Format],
+    ) -> Result<UnboundImage, image::CreationError> {
+        if let Some(&view_format) = view_formats.iter().find(|&&f| !format.is_view_compatible(f)) {
+            return Err(image::CreationError::Format(view_format));
+        }
+        if view_formats.iter().all(|&f| f == format) {
+            return self.create_image(kind, mip_levels, format, tiling, usage, storage_flags);
+        }
+
+        // The list includes the format of the image itself.
+        let mut raw_formats = SmallVec::<[_; 4]>::new();
+        raw_formats.push(conv::map_format(format));
+        raw_formats.extend(view_formats.iter().filter(|&&f| f != format).map(|&f| conv::map_format(f)));
+        let format_list = ImageFormatListCreateInfo {
+            s_type: STRUCTURE_TYPE_IMAGE_FORMAT_LIST_CREATE_INFO,
             p_next: ptr::null(),
-            flags,
-            image_type,
-            format: conv::map_format(format),
-            extent: extent.clone(),
-            mip_levels: mip_levels as u32,
-            array_layers: array_layers as u32,
-            samples: vk::SampleCountFlags::from_flags_truncate(samples),
-            tiling: conv::map_tiling(tiling),
-            usage: conv::map_image_usage(usage),
-            sharing_mode: vk::SharingMode::Exclusive, // TODO:
-            queue_family_index_count: 0,
-            p_queue_family_indices: ptr::null(),
-            initial_layout: vk::ImageLayout::Undefined,
+            view_format_count: raw_formats.len() as _,
+            p_view_formats: raw_formats.as_ptr(),
         };
-
-        let raw = unsafe {
-            self.raw.0.create_image(&info, None)
-        }.expect("Error on image creation"); // TODO: error handling
-
-        Ok(UnboundImage(n::Image{ raw, ty: image_type, flags, extent }))
+        self.create_image_raw(
+            kind,
+            mip_levels,
+            format,
+            tiling,
+            usage,
+            storage_flags | image::StorageFlags::MUTABLE_FORMAT,
+            if self.image_format_list {
+                &format_list as *const _ as *const _
+            } else {
+                ptr::null()
+            },
+        )
     }
 
     fn get_image_requirements(&self, image: &UnboundImage) -> Requirements {
@@ -1053,14 +1846,37 @@ impl d::Device<B> for Device {
             self.raw.0.create_image_view(&info, None)
         }.expect("Error on image view creation"); // TODO
 
+        let level = range.levels.start as u32;
+        let extent = vk::Extent3D {
+            width: cmp::max(1, image.extent.width >> level),
+            height: cmp::max(1, image.extent.height >> level),
+            depth: cmp::max(1, image.extent.depth >> level),
+        };
+
         Ok(n::ImageView {
             image: image.raw,
             view,
             range,
+            format,
+            samples: image.samples,
+            extent,
         })
     }
 
     fn create_descriptor_pool<T>(&self, max_sets: usize, descriptor_pools: T) -> n::DescriptorPool
+    where
+        T: IntoIterator,
+        T::Item: Borrow<pso::DescriptorRangeDesc>,
+    {
+        self.create_descriptor_pool_with_flags(max_sets, descriptor_pools, pso::DescriptorPoolCreateFlags::empty())
+    }
+
+    fn create_descriptor_pool_with_flags<T>(
+        &self,
+        max_sets: usize,
+        descriptor_pools: T,
+        flags: pso::DescriptorPoolCreateFlags,
+    ) -> n::DescriptorPool
     where
         T: IntoIterator,
         T::Item: Borrow<pso::DescriptorRangeDesc>,
@@ -1073,10 +1889,17 @@ impl d::Device<B> for Device {
             }
         }).collect::<Vec<_>>();
 
+        // disallow individual freeing
+        let mut raw_flags = 0;
+        if flags.contains(pso::DescriptorPoolCreateFlags::UPDATE_AFTER_BIND) {
+            assert!(self.raw.1.contains(Features::DESCRIPTOR_INDEXING));
+            raw_flags |= DESCRIPTOR_POOL_CREATE_UPDATE_AFTER_BIND;
+        }
+
         let info = vk::DescriptorPoolCreateInfo {
             s_type: vk::StructureType::DescriptorPoolCreateInfo,
             p_next: ptr::null(),
-            flags: vk::DescriptorPoolCreateFlags::empty(), // disallow individual freeing
+            flags: unsafe { mem::transmute(raw_flags) },
             max_sets: max_sets as u32,
             pool_size_count: pools.len() as u32,
             p_pool_sizes: pools.as_ptr(),
@@ -1097,6 +1920,21 @@ impl d::Device<B> for Device {
     fn create_descriptor_set_layout<I, J>(
         &self, binding_iter: I, immutable_sampler_iter: J
     ) -> n::DescriptorSetLayout
+    where
+        I: IntoIterator,
+        I::Item: Borrow<pso::DescriptorSetLayoutBinding>,
+        J: IntoIterator,
+        J::Item: Borrow<n::Sampler>,
+    {
+        self.create_descriptor_set_layout_with_flags(binding_iter, &[], immutable_sampler_iter)
+    }
+
+    fn create_descriptor_set_layout_with_flags<I, J>(
+        &self,
+        binding_iter: I,
+        binding_flags: &[pso::DescriptorBindingFlags],
+        immutable_sampler_iter: J,
+    ) -> n::DescriptorSetLayout
     where
         I: IntoIterator,
         I::Item: Borrow<pso::DescriptorSetLayoutBinding>,
@@ -1133,10 +1971,35 @@ impl d::Device<B> for Device {
 
         debug!("create_descriptor_set_layout {:?}", raw_bindings);
 
+        // The flags of `VK_EXT_descriptor_indexing` match `pso::DescriptorBindingFlags`.
+        let raw_binding_flags = binding_flags
+            .iter()
+            .map(|flags| flags.bits())
+            .collect::<Vec<_>>();
+        let has_flags = raw_binding_flags.iter().any(|&flags| flags != 0);
+        let flags_info = DescriptorSetLayoutBindingFlagsCreateInfo {
+            s_type: STRUCTURE_TYPE_DESCRIPTOR_SET_LAYOUT_BINDING_FLAGS_CREATE_INFO,
+            p_next: ptr::null(),
+            binding_count: raw_binding_flags.len() as _,
+            p_binding_flags: raw_binding_flags.as_ptr(),
+        };
+        let mut raw_flags = 0;
+        if has_flags {
+            assert!(self.raw.1.contains(Features::DESCRIPTOR_INDEXING));
+            assert_eq!(raw_binding_flags.len(), raw_bindings.len());
+            if binding_flags.iter().any(|flags| flags.contains(pso::DescriptorBindingFlags::UPDATE_AFTER_BIND)) {
+                raw_flags |= DESCRIPTOR_SET_LAYOUT_CREATE_UPDATE_AFTER_BIND_POOL;
+            }
+        }
+
         let info = vk::DescriptorSetLayoutCreateInfo {
             s_type: vk::StructureType::DescriptorSetLayoutCreateInfo,
-            p_next: ptr::null(),
-            flags: vk::DescriptorSetLayoutCreateFlags::empty(),
+            p_next: if has_flags {
+                &flags_info as *const _ as *const _
+            } else {
+                ptr::null()
+            },
+            flags: unsafe { mem::transmute(raw_flags) },
             binding_count: raw_bindings.len() as _,
             p_bindings: raw_bindings.as_ptr(),
         };
@@ -1194,14 +2057,14 @@ impl d::Device<B> for Device {
                         image_infos.push(vk::DescriptorImageInfo {
                             sampler: vk::Sampler::null(),
                             image_view: view.view,
-                            image_layout: conv::map_image_layout(layout),
+                            image_layout: self.raw.map_image_layout(layout),
                         });
                     }
                     pso::Descriptor::CombinedImageSampler(view, layout, sampler) => {
                         image_infos.push(vk::DescriptorImageInfo {
                             sampler: sampler.0,
                             image_view: view.view,
-                            image_layout: conv::map_image_layout(layout),
+                            image_layout: self.raw.map_image_layout(layout),
                         });
                     }
                     pso::Descriptor::Buffer(buffer, ref range) => {
@@ -1264,6 +2127,10 @@ impl d::Device<B> for Device {
         unsafe {
             self.raw.0.update_descriptor_sets(&raw_writes, &[]);
         }
+        self.raw.2.add(&FrameStatistics {
+            descriptor_updates: raw_writes.len() as u64,
+            .. FrameStatistics::default()
+        });
     }
 
     fn copy_descriptor_sets<'a, I>(&self, copies: I)
@@ -1289,6 +2156,10 @@ impl d::Device<B> for Device {
         unsafe {
             self.raw.0.update_descriptor_sets(&[], &copies);
         }
+        self.raw.2.add(&FrameStatistics {
+            descriptor_updates: copies.len() as u64,
+            .. FrameStatistics::default()
+        });
     }
 
     fn map_memory<R>(&self, memory: &n::Memory, range: R) -> Result<*mut u8, mapping::Error>
@@ -1416,6 +2287,129 @@ impl d::Device<B> for Device {
         }
     }
 
+    fn create_timeline_semaphore(&self, initial_value: u64) -> n::TimelineSemaphore {
+        if self.raw.3.is_none() {
+            return n::TimelineSemaphore::Emulated(HostTimeline::new(initial_value));
+        }
+
+        let type_info = SemaphoreTypeCreateInfo {
+            s_type: STRUCTURE_TYPE_SEMAPHORE_TYPE_CREATE_INFO,
+            p_next: ptr::null(),
+            semaphore_type: SEMAPHORE_TYPE_TIMELINE,
+            initial_value,
+        };
+        let info = vk::SemaphoreCreateInfo {
+            s_type: vk::StructureType::SemaphoreCreateInfo,
+            p_next: &type_info as *const _ as *const _,
+            flags: vk::SemaphoreCreateFlags::empty(),
+        };
+
+        let semaphore = unsafe {
+            self.raw.0.create_semaphore(&info, None)
+                        .expect("Error on semaphore creation") // TODO: error handling
+        };
+
+        n::TimelineSemaphore::Native(semaphore)
+    }
+
+    fn get_timeline_semaphore_value(&self, semaphore: &n::TimelineSemaphore) -> u64 {
+        match *semaphore {
+            n::TimelineSemaphore::Native(raw) => {
+                let functions = self.raw.3.as_ref().unwrap();
+                let mut value = 0;
+                let result = (functions.get_semaphore_counter_value)(self.raw.0.handle(), raw, &mut value);
+                assert_eq!(vk::Result::Success, result);
+                value
+            }
+            n::TimelineSemaphore::Emulated(ref timeline) => timeline.value(),
+        }
+    }
+
+    fn signal_timeline_semaphore(&self, semaphore: &n::TimelineSemaphore, value: u64) {
+        match *semaphore {
+            n::TimelineSemaphore::Native(raw) => {
+                let functions = self.raw.3.as_ref().unwrap();
+                let info = SemaphoreSignalInfo {
+                    s_type: STRUCTURE_TYPE_SEMAPHORE_SIGNAL_INFO,
+                    p_next: ptr::null(),
+                    semaphore: raw,
+                    value,
+                };
+                let result = (functions.signal_semaphore)(self.raw.0.handle(), &info);
+                assert_eq!(vk::Result::Success, result);
+            }
+            n::TimelineSemaphore::Emulated(ref timeline) => timeline.signal(value),
+        }
+    }
+
+    fn wait_for_timeline_semaphores<'a, I>(&self, semaphores: I, wait: d::WaitFor, timeout_ms: u32) -> bool
+    where
+        I: IntoIterator<Item = (&'a n::TimelineSemaphore, u64)>,
+    {
+        let functions = match self.raw.3 {
+            Some(ref functions) => functions,
+            None => {
+                let timelines = semaphores
+                    .into_iter()
+                    .map(|(semaphore, value)| match *semaphore {
+                        n::TimelineSemaphore::Emulated(ref timeline) => (timeline, value),
+                        n::TimelineSemaphore::Native(_) => unreachable!(),
+                    });
+                return HostTimeline::wait_many(timelines, wait, timeout_ms);
+            }
+        };
+
+        let (raws, values): (Vec<_>, Vec<_>) = semaphores
+            .into_iter()
+            .map(|(semaphore, value)| match *semaphore {
+                n::TimelineSemaphore::Native(raw) => (raw, value),
+                n::TimelineSemaphore::Emulated(_) => unreachable!(),
+            })
+            .unzip();
+        let info = SemaphoreWaitInfo {
+            s_type: STRUCTURE_TYPE_SEMAPHORE_WAIT_INFO,
+            p_next: ptr::null(),
+            flags: match wait {
+                d::WaitFor::Any => SEMAPHORE_WAIT_ANY,
+                d::WaitFor::All => 0,
+            },
+            semaphore_count: raws.len() as u32,
+            p_semaphores: raws.as_ptr(),
+            p_values: values.as_ptr(),
+        };
+        let timeout_ns = if timeout_ms == !0 { !0 } else { timeout_ms as u64 * 1_000_000 };
+        match (functions.wait_semaphores)(self.raw.0.handle(), &info, timeout_ns) {
+            vk::Result::Success => true,
+            vk::Result::Timeout => false,
+            result => panic!("Unexpected wait result {:?}", result),
+        }
+    }
+
+    fn destroy_timeline_semaphore(&self, semaphore: n::TimelineSemaphore) {
+        if let n::TimelineSemaphore::Native(raw) = semaphore {
+            unsafe { self.raw.0.destroy_semaphore(raw, None); }
+        }
+    }
+
+    fn create_event(&self) -> n::Event {
+        let info = vk::EventCreateInfo {
+            s_type: vk::StructureType::EventCreateInfo,
+            p_next: ptr::null(),
+            flags: vk::EventCreateFlags::empty(),
+        };
+
+        let event = unsafe {
+            self.raw.0.create_event(&info, None)
+                        .expect("Error on event creation") // TODO: error handling
+        };
+
+        n::Event(event)
+    }
+
+    fn destroy_event(&self, event: n::Event) {
+        unsafe { self.raw.0.destroy_event(event.0, None); }
+    }
+
     fn free_memory(&self, memory: n::Memory) {
         unsafe { self.raw.0.free_memory(memory.raw, None); }
     }
@@ -1461,7 +2455,7 @@ impl d::Device<B> for Device {
         let format = config.color_format;
 
         let old_swapchain = match provided_old_swapchain {
-            Some(osc) => osc.raw,
+            Some(ref osc) => osc.raw,
             None => vk::SwapchainKHR::null(),
         };
 
@@ -1485,9 +2479,9 @@ impl d::Device<B> for Device {
             image_sharing_mode: vk::SharingMode::Exclusive,
             queue_family_index_count: 0,
             p_queue_family_indices: ptr::null(),
-            pre_transform: vk::SURFACE_TRANSFORM_IDENTITY_BIT_KHR,
+            pre_transform: conv::map_surface_transform(config.pre_transform),
             composite_alpha: vk::COMPOSITE_ALPHA_OPAQUE_BIT_KHR,
-            present_mode: unsafe { mem::transmute(config.present_mode) },
+            present_mode: conv::map_present_mode(config.present_mode),
             clipped: 1,
             old_swapchain,
         };
@@ -1495,13 +2489,35 @@ impl d::Device<B> for Device {
         let swapchain_raw = unsafe { functor.create_swapchain_khr(&info, None) }
             .expect("Unable to create a swapchain");
 
+        // The retired swapchain still has to be destroyed, its images are released already.
+        if let Some(osc) = provided_old_swapchain {
+            osc.destroy();
+        }
+
+        let swapchain_fn = vk::SwapchainFn::load(|name| unsafe {
+            mem::transmute(
+                surface.raw.instance.0
+                    .get_device_proc_addr(
+                        self.raw.0.handle(),
+                        name.as_ptr(),
+                    )
+            )
+        }).expect("Unable to load swapchain functions");
+
         let backbuffer_images = functor.get_swapchain_images_khr(swapchain_raw)
             .expect("Unable to get swapchain images");
 
-        let swapchain = w::Swapchain {
-            raw: swapchain_raw,
+        let swapchain = w::Swapchain::new(
+            swapchain_raw,
             functor,
-        };
+            swapchain_fn,
+            self.raw.clone(),
+            surface.raw.clone(),
+            backbuffer_images.clone(),
+            self.physical_device,
+            *extent,
+            config.pre_transform,
+        );
 
         let images = backbuffer_images
             .into_iter()
@@ -1515,6 +2531,7 @@ impl d::Device<B> for Device {
                         height: surface.height,
                         depth: 1,
                     },
+                    samples: 1,
                 }
             })
             .collect();
@@ -1523,13 +2540,43 @@ impl d::Device<B> for Device {
     }
 
     fn destroy_swapchain(&self, swapchain: w::Swapchain) {
-        unsafe { swapchain.functor.destroy_swapchain_khr(swapchain.raw, None); }
+        swapchain.destroy();
     }
 
     fn destroy_query_pool(&self, pool: n::QueryPool) {
         unsafe { self.raw.0.destroy_query_pool(pool.0, None); }
     }
 
+    fn get_query_pool_results(
+        &self,
+        pool: &n::QueryPool,
+        queries: Range<query::QueryId>,
+        data: &mut [u64],
+        wait: bool,
+    ) -> Result<bool, HostExecutionError> {
+        let count = queries.end - queries.start;
+        assert!(data.len() >= count as usize);
+        let mut flags = vk::QUERY_RESULT_64_BIT;
+        if wait {
+            flags |= vk::QUERY_RESULT_WAIT_BIT;
+        }
+
+        let result = unsafe {
+            self.raw.0.get_query_pool_results(
+                pool.0,
+                queries.start,
+                count,
+                &mut data[.. count as usize],
+                flags,
+            )
+        };
+        match result {
+            Ok(()) | Err(vk::Result::Success) => Ok(true),
+            Err(vk::Result::NotReady) => Ok(false),
+            Err(err) => Err(From::<result::Error>::from(From::from(err))),
+        }
+    }
+
     fn destroy_shader_module(&self, module: n::ShaderModule) {
         unsafe { self.raw.0.destroy_shader_module(module.raw, None); }
     }
@@ -1542,6 +2589,77 @@ impl d::Device<B> for Device {
         unsafe { self.raw.0.destroy_pipeline_layout(pl.raw, None); }
     }
 
+    fn create_pipeline_cache(&self, data: Option<&[u8]>) -> Result<n::PipelineCache, d::OutOfMemory> {
+        let data = data.unwrap_or(&[]);
+        let info = vk::PipelineCacheCreateInfo {
+            s_type: vk::StructureType::PipelineCacheCreateInfo,
+            p_next: ptr::null(),
+            flags: vk::PipelineCacheCreateFlags::empty(),
+            initial_data_size: data.len(),
+            p_initial_data: data.as_ptr() as *const _,
+        };
+
+        // Incompatible initial data is ignored by the driver.
+        let raw = unsafe { self.raw.0.create_pipeline_cache(&info, None) }
+            .map_err(|_| d::OutOfMemory)?;
+
+        Ok(n::PipelineCache { raw })
+    }
+
+    fn get_pipeline_cache_data(&self, cache: &n::PipelineCache) -> Result<Vec<u8>, d::OutOfMemory> {
+        unsafe {
+            let fp = self.raw.0.fp_v1_0();
+            let device = self.raw.0.handle();
+            let mut size = 0;
+            let result = fp.get_pipeline_cache_data(device, cache.raw, &mut size, ptr::null_mut());
+            if result != vk::Result::Success {
+                return Err(d::OutOfMemory);
+            }
+
+            let mut data = Vec::<u8>::with_capacity(size);
+            let result = fp.get_pipeline_cache_data(device, cache.raw, &mut size, data.as_mut_ptr() as *mut _);
+            match result {
+                // The cache may have shrunk in between, `size` holds the number of bytes written.
+                vk::Result::Success | vk::Result::Incomplete => {
+                    data.set_len(size);
+                    Ok(data)
+                }
+                _ => Err(d::OutOfMemory),
+            }
+        }
+    }
+
+    fn merge_pipeline_caches<I>(&self, target: &n::PipelineCache, sources: I) -> Result<(), d::OutOfMemory>
+    where
+        I: IntoIterator,
+        I::Item: Borrow<n::PipelineCache>,
+    {
+        let sources = sources
+            .into_iter()
+            .map(|cache| cache.borrow().raw)
+            .collect::<Vec<_>>();
+        if sources.is_empty() {
+            return Ok(());
+        }
+
+        let result = unsafe {
+            self.raw.0.fp_v1_0().merge_pipeline_caches(
+                self.raw.0.handle(),
+                target.raw,
+                sources.len() as _,
+                sources.as_ptr(),
+            )
+        };
+        match result {
+            vk::Result::Success => Ok(()),
+            _ => Err(d::OutOfMemory),
+        }
+    }
+
+    fn destroy_pipeline_cache(&self, cache: n::PipelineCache) {
+        unsafe { self.raw.0.destroy_pipeline_cache(cache.raw, None); }
+    }
+
     fn destroy_graphics_pipeline(&self, pipeline: n::GraphicsPipeline) {
         unsafe { self.raw.0.destroy_pipeline(pipeline.0, None); }
     }
@@ -1566,12 +2684,119 @@ impl d::Device<B> for Device {
         unsafe { self.raw.0.destroy_image(image.raw, None); }
     }
 
+    fn create_sparse_buffer(
+        &self, size: u64, usage: buffer::Usage,
+    ) -> Result<(n::Buffer, Requirements), buffer::CreationError> {
+        let mut flags = vk::BUFFER_CREATE_SPARSE_BINDING_BIT;
+        if self.raw.1.contains(Features::SPARSE_RESIDENCY_BUFFER) {
+            flags |= vk::BUFFER_CREATE_SPARSE_RESIDENCY_BIT;
+        }
+        let info = vk::BufferCreateInfo {
+            s_type: vk::StructureType::BufferCreateInfo,
+            p_next: ptr::null(),
+            flags,
+            size,
+            usage: conv::map_buffer_usage(usage),
+            sharing_mode: vk::SharingMode::Exclusive,
+            queue_family_index_count: 0,
+            p_queue_family_indices: ptr::null(),
+        };
+
+        let buffer = unsafe {
+            self.raw.0.create_buffer(&info, None)
+                .expect("Error on sparse buffer creation") // TODO: error handling
+        };
+        let req = self.raw.0.get_buffer_memory_requirements(buffer);
+        let requirements = Requirements {
+            size: req.size,
+            alignment: req.alignment,
+            type_mask: req.memory_type_bits as _,
+        };
+
+        Ok((n::Buffer { raw: buffer }, requirements))
+    }
+
+    fn create_sparse_image(
+        &self,
+        kind: image::Kind,
+        mip_levels: image::Level,
+        format: format::Format,
+        usage: image::Usage,
+        storage_flags: image::StorageFlags,
+    ) -> Result<(n::Image, SparseImageRequirements), image::CreationError> {
+        let image = d::Device::create_image(
+            self,
+            kind,
+            mip_levels,
+            format,
+            image::Tiling::Optimal,
+            usage,
+            storage_flags | image::StorageFlags::SPARSE_BINDING,
+        )?;
+        let req = self.raw.0.get_image_memory_requirements(image.0.raw);
+        let memory = Requirements {
+            size: req.size,
+            alignment: req.alignment,
+            type_mask: req.memory_type_bits as _,
+        };
+
+        let sparse = unsafe {
+            let fp = self.raw.0.fp_v1_0();
+            let device = self.raw.0.handle();
+            let mut count = 0;
+            fp.get_image_sparse_memory_requirements(device, image.0.raw, &mut count, ptr::null_mut());
+            let mut requirements = Vec::with_capacity(count as usize);
+            fp.get_image_sparse_memory_requirements(device, image.0.raw, &mut count, requirements.as_mut_ptr());
+            requirements.set_len(count as usize);
+            requirements
+        };
+
+        // Only the first aspect is reported, depth-stencil sparse images are not supported.
+        let sparse = match sparse.first() {
+            Some(req) => req,
+            None => {
+                unsafe { self.raw.0.destroy_image(image.0.raw, None); }
+                return Err(image::CreationError::Format(format));
+            }
+        };
+        let granularity = sparse.format_properties.image_granularity;
+
+        let requirements = SparseImageRequirements {
+            memory,
+            tile_extent: image::Extent {
+                width: granularity.width,
+                height: granularity.height,
+                depth: granularity.depth,
+            },
+            mip_tail_first_level: sparse.image_mip_tail_first_lod as _,
+            mip_tail_size: sparse.image_mip_tail_size,
+            mip_tail_offset: sparse.image_mip_tail_offset,
+        };
+
+        Ok((image.0, requirements))
+    }
+
     fn destroy_image_view(&self, view: n::ImageView) {
+        self.raw.4.lock().unwrap().evict_view(&self.raw.0, view.view);
         unsafe { self.raw.0.destroy_image_view(view.view, None); }
     }
 
     fn destroy_sampler(&self, sampler: n::Sampler) {
-        unsafe { self.raw.0.destroy_sampler(sampler.0, None); }
+        let mut samplers = self.samplers.lock().unwrap();
+        let info = samplers
+            .iter()
+            .find(|&(_, &(raw, _))| raw == sampler.0)
+            .map(|(info, _)| info.clone())
+            .expect("Unknown sampler");
+        let count = {
+            let entry = samplers.get_mut(&info).unwrap();
+            entry.1 -= 1;
+            entry.1
+        };
+        if count == 0 {
+            samplers.remove(&info);
+            unsafe { self.raw.0.destroy_sampler(sampler.0, None); }
+        }
     }
 
     fn destroy_descriptor_pool(&self, pool: n::DescriptorPool) {
@@ -1597,6 +2822,10 @@ impl d::Device<B> for Device {
             .map_err(From::from)
             .map_err(From::<result::Error>::from)
     }
+
+    fn frame_statistics(&self) -> Option<FrameStatistics> {
+        Some(self.raw.2.take())
+    }
 }
 
 #[test]
@@ -17,8 +17,6 @@ extern crate x11;
 #[cfg(all(unix, not(target_os = "android")))]
 extern crate xcb;
 
-#[cfg(feature = "glsl-to-spirv")]
-extern crate glsl_to_spirv;
 
 use ash::{Entry, LoadingError};
 use ash::extensions as ext;
@@ -26,13 +24,18 @@ use ash::version::{EntryV1_0, DeviceV1_0, InstanceV1_0, V1_0};
 use ash::vk;
 
 use hal::{format, image, memory, queue};
+use hal::backend::{FastHashMap, StatisticsCollector};
 use hal::{Features, SwapImageIndex, Limits, PatchSize, QueueType};
-use hal::error::{DeviceCreationError, HostExecutionError};
+use hal::window::{PresentError, Suboptimal};
+use hal::error::{DeviceCreationError, ErrorContext, HostExecutionError};
+use hal::profiler::FrameStatistics;
+use hal::pso::PipelineStage;
 
-use std::{fmt, mem, ptr};
+use std::{cmp, fmt, mem, ptr};
 use std::borrow::Borrow;
+use std::cell::RefCell;
 use std::ffi::{CStr, CString};
-use std::sync::Arc;
+use std::sync::{Arc, Mutex};
 
 mod command;
 mod conv;
@@ -44,16 +47,78 @@ mod result;
 mod window;
 
 const LAYERS: &'static [&'static str] = &[
-    #[cfg(debug_assertions)]
+    #[cfg(all(debug_assertions, not(feature = "shader-printf")))]
     "VK_LAYER_LUNARG_standard_validation",
+    // Shader printf is only implemented by the unified validation layer.
+    #[cfg(all(debug_assertions, feature = "shader-printf"))]
+    "VK_LAYER_KHRONOS_validation",
 ];
 const EXTENSIONS: &'static [&'static str] = &[
     #[cfg(debug_assertions)]
     "VK_EXT_debug_report",
+    #[cfg(all(debug_assertions, feature = "shader-printf"))]
+    VALIDATION_FEATURES_EXTENSION,
+    PHYSICAL_DEVICE_PROPERTIES2_EXTENSION,
+    DEVICE_GROUP_CREATION_EXTENSION,
 ];
+/// Instance extension of the validation layer, used to enable shader printf.
+const VALIDATION_FEATURES_EXTENSION: &'static str = "VK_EXT_validation_features";
+/// Instance extension required by `VK_KHR_multiview`, `VK_KHR_timeline_semaphore`,
+/// `VK_EXT_descriptor_indexing`, `VK_KHR_shader_clock`, `VK_KHR_fragment_shader_barycentric`
+/// and the shader atomics, 16-bit and 8-bit type and imageless framebuffer extensions.
+const PHYSICAL_DEVICE_PROPERTIES2_EXTENSION: &'static str = "VK_KHR_get_physical_device_properties2";
+/// Instance extension required by `VK_KHR_device_group`.
+const DEVICE_GROUP_CREATION_EXTENSION: &'static str = "VK_KHR_device_group_creation";
 const DEVICE_EXTENSIONS: &'static [&'static str] = &[
     vk::VK_KHR_SWAPCHAIN_EXTENSION_NAME,
 ];
+const STENCIL_EXPORT_EXTENSION: &'static str = "VK_EXT_shader_stencil_export";
+const MULTIVIEW_EXTENSION: &'static str = "VK_KHR_multiview";
+const TIMELINE_SEMAPHORE_EXTENSION: &'static str = "VK_KHR_timeline_semaphore";
+/// Device extension required by `VK_EXT_descriptor_indexing`.
+const MAINTENANCE3_EXTENSION: &'static str = "VK_KHR_maintenance3";
+const DESCRIPTOR_INDEXING_EXTENSION: &'static str = "VK_EXT_descriptor_indexing";
+/// Device extension required by shaders using `debugPrintfEXT`.
+const SHADER_NON_SEMANTIC_INFO_EXTENSION: &'static str = "VK_KHR_shader_non_semantic_info";
+const SHADER_CLOCK_EXTENSION: &'static str = "VK_KHR_shader_clock";
+const FRAGMENT_SHADER_BARYCENTRIC_EXTENSION: &'static str = "VK_KHR_fragment_shader_barycentric";
+const SHADER_ATOMIC_INT64_EXTENSION: &'static str = "VK_KHR_shader_atomic_int64";
+const SHADER_IMAGE_ATOMIC_INT64_EXTENSION: &'static str = "VK_EXT_shader_image_atomic_int64";
+const SHADER_ATOMIC_FLOAT_EXTENSION: &'static str = "VK_EXT_shader_atomic_float";
+const SHADER_FLOAT16_INT8_EXTENSION: &'static str = "VK_KHR_shader_float16_int8";
+/// Device extension required by `VK_KHR_16bit_storage` and `VK_KHR_8bit_storage`.
+const STORAGE_BUFFER_STORAGE_CLASS_EXTENSION: &'static str = "VK_KHR_storage_buffer_storage_class";
+const STORAGE_16BIT_EXTENSION: &'static str = "VK_KHR_16bit_storage";
+const STORAGE_8BIT_EXTENSION: &'static str = "VK_KHR_8bit_storage";
+/// Device extension passing the view formats of mutable-format images to the driver.
+const IMAGE_FORMAT_LIST_EXTENSION: &'static str = "VK_KHR_image_format_list";
+/// Device extension required by `VK_KHR_imageless_framebuffer`.
+const MAINTENANCE2_EXTENSION: &'static str = "VK_KHR_maintenance2";
+const IMAGELESS_FRAMEBUFFER_EXTENSION: &'static str = "VK_KHR_imageless_framebuffer";
+/// Device extension providing `vkCmdDispatchBaseKHR`.
+const DEVICE_GROUP_EXTENSION: &'static str = "VK_KHR_device_group";
+/// Device extensions enabled only when available, each exposing some of the `Features`.
+const OPTIONAL_DEVICE_EXTENSIONS: &'static [&'static str] = &[
+    STENCIL_EXPORT_EXTENSION,
+    MULTIVIEW_EXTENSION,
+    TIMELINE_SEMAPHORE_EXTENSION,
+    MAINTENANCE3_EXTENSION,
+    DESCRIPTOR_INDEXING_EXTENSION,
+    SHADER_NON_SEMANTIC_INFO_EXTENSION,
+    SHADER_CLOCK_EXTENSION,
+    FRAGMENT_SHADER_BARYCENTRIC_EXTENSION,
+    SHADER_ATOMIC_INT64_EXTENSION,
+    SHADER_IMAGE_ATOMIC_INT64_EXTENSION,
+    SHADER_ATOMIC_FLOAT_EXTENSION,
+    SHADER_FLOAT16_INT8_EXTENSION,
+    STORAGE_BUFFER_STORAGE_CLASS_EXTENSION,
+    STORAGE_16BIT_EXTENSION,
+    STORAGE_8BIT_EXTENSION,
+    DEVICE_GROUP_EXTENSION,
+    IMAGE_FORMAT_LIST_EXTENSION,
+    MAINTENANCE2_EXTENSION,
+    IMAGELESS_FRAMEBUFFER_EXTENSION,
+];
 const SURFACE_EXTENSIONS: &'static [&'static str] = &[
     vk::VK_KHR_SURFACE_EXTENSION_NAME,
 
@@ -92,6 +157,30 @@ pub struct Instance {
 
     /// Supported extensions of this instance.
     pub extensions: Vec<&'static str>,
+    /// Vulkan version requested by the instance.
+    api_version: u32,
+}
+
+/// `vkEnumerateInstanceVersion` of Vulkan 1.1, not exposed by `ash`.
+type EnumerateInstanceVersion = extern "system" fn(*mut u32) -> vk::Result;
+
+/// Query the instance version supported by the loader, `None` for Vulkan 1.0 loaders.
+fn instance_version(entry: &Entry<V1_0>) -> Option<u32> {
+    unsafe {
+        let name = CStr::from_bytes_with_nul(b"vkEnumerateInstanceVersion\0").unwrap();
+        let function: *const vk::types::c_void = mem::transmute(
+            entry.static_fn().get_instance_proc_addr(mem::zeroed(), name.as_ptr())
+        );
+        if function.is_null() {
+            return None;
+        }
+        let enumerate_instance_version: EnumerateInstanceVersion = mem::transmute(function);
+        let mut version = 0;
+        match enumerate_instance_version(&mut version) {
+            vk::Result::Success => Some(version),
+            _ => None,
+        }
+    }
 }
 
 fn map_queue_type(flags: vk::QueueFlags) -> QueueType {
@@ -109,6 +198,20 @@ fn map_queue_type(flags: vk::QueueFlags) -> QueueType {
     }
 }
 
+/// `VkValidationFeaturesEXT`, which isn't exposed by `ash`.
+#[repr(C)]
+struct ValidationFeaturesEXT {
+    s_type: u32,
+    p_next: *const vk::types::c_void,
+    enabled_validation_feature_count: u32,
+    p_enabled_validation_features: *const u32,
+    disabled_validation_feature_count: u32,
+    p_disabled_validation_features: *const u32,
+}
+
+const STRUCTURE_TYPE_VALIDATION_FEATURES_EXT: u32 = 1000247000;
+const VALIDATION_FEATURE_ENABLE_DEBUG_PRINTF_EXT: u32 = 3;
+
 extern "system" fn callback(
     type_: vk::DebugReportFlagsEXT,
     _: vk::DebugReportObjectTypeEXT,
@@ -123,20 +226,62 @@ extern "system" fn callback(
         let level = match type_ {
             vk::DEBUG_REPORT_ERROR_BIT_EXT => log::Level::Error,
             vk::DEBUG_REPORT_DEBUG_BIT_EXT => log::Level::Debug,
+            // Shader printf messages.
+            vk::DEBUG_REPORT_INFORMATION_BIT_EXT => log::Level::Info,
             _ => log::Level::Warn,
         };
         let layer_prefix = CStr::from_ptr(layer_prefix).to_str().unwrap();
         let description = CStr::from_ptr(description).to_str().unwrap();
         log!(level, "[{}] {}", layer_prefix, description);
+        CAPTURED_MESSAGES.with(|messages| {
+            if let Some(ref mut messages) = *messages.borrow_mut() {
+                messages.push(format!("[{}] {}", layer_prefix, description));
+            }
+        });
         vk::VK_FALSE
     }
 }
 
+thread_local! {
+    // Validation messages reported on this thread during `capture_messages`.
+    static CAPTURED_MESSAGES: RefCell<Option<Vec<String>>> = RefCell::new(None);
+}
+
+/// Run `fun`, collecting the validation messages reported while it executes.
+///
+/// Layers report synchronously on the calling thread, so the messages can be attached
+/// to the error of a failing call.
+pub(crate) fn capture_messages<R, F: FnOnce() -> R>(fun: F) -> (R, ErrorContext) {
+    if !cfg!(debug_assertions) {
+        return (fun(), ErrorContext::default());
+    }
+
+    let outer = CAPTURED_MESSAGES.with(|messages| mem::replace(&mut *messages.borrow_mut(), Some(Vec::new())));
+    let result = fun();
+    let captured = CAPTURED_MESSAGES.with(|messages| mem::replace(&mut *messages.borrow_mut(), outer));
+    let messages = captured.unwrap_or_default();
+
+    // Nested captures also report the messages to the enclosing one.
+    CAPTURED_MESSAGES.with(|outer| {
+        if let Some(ref mut outer) = *outer.borrow_mut() {
+            outer.extend(messages.iter().cloned());
+        }
+    });
+    (result, ErrorContext { messages })
+}
+
 impl Instance {
     pub fn create(name: &str, version: u32) -> Self {
         // TODO: return errors instead of panic
         let entry = VK_ENTRY.as_ref().expect("Unable to load Vulkan entry points");
 
+        // Vulkan 1.1 is required by subgroup operations, and only requested
+        // if the loader supports it.
+        let api_version = match instance_version(entry) {
+            Some(version) if version >= vk_make_version!(1, 1, 0) => vk_make_version!(1, 1, 0),
+            _ => vk_make_version!(1, 0, 0),
+        };
+
         let app_name = CString::new(name).unwrap();
         let app_info = vk::ApplicationInfo {
             s_type: vk::StructureType::ApplicationInfo,
@@ -145,7 +290,7 @@ impl Instance {
             application_version: version,
             p_engine_name: b"gfx-rs\0".as_ptr() as *const _,
             engine_version: 1,
-            api_version: vk_make_version!(1, 0, 0),
+            api_version,
         };
 
         let instance_extensions = entry
@@ -203,9 +348,23 @@ impl Instance {
                 .map(|s| s.as_ptr())
                 .collect::<Vec<_>>();
 
+            let printf_features = [VALIDATION_FEATURE_ENABLE_DEBUG_PRINTF_EXT];
+            let validation_features = ValidationFeaturesEXT {
+                s_type: STRUCTURE_TYPE_VALIDATION_FEATURES_EXT,
+                p_next: ptr::null(),
+                enabled_validation_feature_count: printf_features.len() as _,
+                p_enabled_validation_features: printf_features.as_ptr(),
+                disabled_validation_feature_count: 0,
+                p_disabled_validation_features: ptr::null(),
+            };
+
             let create_info = vk::InstanceCreateInfo {
                 s_type: vk::StructureType::InstanceCreateInfo,
-                p_next: ptr::null(),
+                p_next: if extensions.contains(&VALIDATION_FEATURES_EXTENSION) {
+                    &validation_features as *const _ as *const _
+                } else {
+                    ptr::null()
+                },
                 flags: vk::InstanceCreateFlags::empty(),
                 p_application_info: &app_info,
                 enabled_layer_count: layers.len() as _,
@@ -222,12 +381,17 @@ impl Instance {
         #[cfg(debug_assertions)]
         let debug_report = {
             let ext = ext::DebugReport::new(entry, &instance).unwrap();
+            let mut flags = vk::DEBUG_REPORT_WARNING_BIT_EXT |
+                vk::DEBUG_REPORT_PERFORMANCE_WARNING_BIT_EXT |
+                vk::DEBUG_REPORT_ERROR_BIT_EXT;
+            // Shader printf messages are reported as information.
+            if extensions.contains(&VALIDATION_FEATURES_EXTENSION) {
+                flags |= vk::DEBUG_REPORT_INFORMATION_BIT_EXT;
+            }
             let info = vk::DebugReportCallbackCreateInfoEXT {
                 s_type: vk::StructureType::DebugReportCallbackCreateInfoExt,
                 p_next: ptr::null(),
-                flags: vk::DEBUG_REPORT_WARNING_BIT_EXT |
-                       vk::DEBUG_REPORT_PERFORMANCE_WARNING_BIT_EXT |
-                       vk::DEBUG_REPORT_ERROR_BIT_EXT,
+                flags,
                 pfn_callback: callback,
                 p_user_data: ptr::null_mut(),
             };
@@ -242,8 +406,19 @@ impl Instance {
         Instance {
             raw: Arc::new(RawInstance(instance, debug_report)),
             extensions,
+            api_version,
         }
     }
+
+    /// Get the adapter of a raw physical device.
+    ///
+    /// Required for OpenXR, which requests the physical device to be used by the runtime
+    /// with `xrGetVulkanGraphicsDeviceKHR`.
+    pub fn adapter_from_raw(&self, physical_device: vk::PhysicalDevice) -> Option<hal::Adapter<Backend>> {
+        hal::Instance::enumerate_adapters(self)
+            .into_iter()
+            .find(|adapter| adapter.physical_device.handle == physical_device)
+    }
 }
 
 impl hal::Instance for Instance {
@@ -265,11 +440,67 @@ impl hal::Instance for Instance {
                     vendor: properties.vendor_id as usize,
                     device: properties.device_id as usize,
                     software_rendering: properties.device_type == vk::PhysicalDeviceType::Cpu,
+                    device_type: match properties.device_type {
+                        vk::PhysicalDeviceType::IntegratedGpu => hal::adapter::DeviceType::IntegratedGpu,
+                        vk::PhysicalDeviceType::DiscreteGpu => hal::adapter::DeviceType::DiscreteGpu,
+                        vk::PhysicalDeviceType::VirtualGpu => hal::adapter::DeviceType::VirtualGpu,
+                        vk::PhysicalDeviceType::Cpu => hal::adapter::DeviceType::Cpu,
+                        _ => hal::adapter::DeviceType::Other,
+                    },
+                };
+                let device_extensions = self.raw.0
+                    .enumerate_device_extension_properties(device)
+                    .unwrap_or_else(|_| Vec::new());
+                let is_supported = |ext: &str| {
+                    device_extensions.iter().any(|dev_ext| unsafe {
+                        CStr::from_ptr(dev_ext.extension_name.as_ptr()).to_bytes() == ext.as_bytes()
+                    })
                 };
+                let extensions = OPTIONAL_DEVICE_EXTENSIONS
+                    .iter()
+                    .cloned()
+                    .filter(|ext| is_supported(ext))
+                    .filter(|&ext| {
+                        (ext != MULTIVIEW_EXTENSION &&
+                            ext != TIMELINE_SEMAPHORE_EXTENSION &&
+                            ext != DESCRIPTOR_INDEXING_EXTENSION &&
+                            ext != SHADER_CLOCK_EXTENSION &&
+                            ext != FRAGMENT_SHADER_BARYCENTRIC_EXTENSION &&
+                            ext != SHADER_ATOMIC_INT64_EXTENSION &&
+                            ext != SHADER_IMAGE_ATOMIC_INT64_EXTENSION &&
+                            ext != SHADER_ATOMIC_FLOAT_EXTENSION &&
+                            ext != SHADER_FLOAT16_INT8_EXTENSION &&
+                            ext != STORAGE_16BIT_EXTENSION &&
+                            ext != STORAGE_8BIT_EXTENSION &&
+                            ext != IMAGELESS_FRAMEBUFFER_EXTENSION) ||
+                            self.extensions.contains(&PHYSICAL_DEVICE_PROPERTIES2_EXTENSION)
+                    })
+                    .filter(|&ext| ext != DESCRIPTOR_INDEXING_EXTENSION || is_supported(MAINTENANCE3_EXTENSION))
+                    .filter(|&ext| {
+                        (ext != STORAGE_16BIT_EXTENSION && ext != STORAGE_8BIT_EXTENSION) ||
+                            is_supported(STORAGE_BUFFER_STORAGE_CLASS_EXTENSION)
+                    })
+                    .filter(|&ext| {
+                        ext != IMAGELESS_FRAMEBUFFER_EXTENSION ||
+                            (is_supported(MAINTENANCE2_EXTENSION) && is_supported(IMAGE_FORMAT_LIST_EXTENSION))
+                    })
+                    .filter(|&ext| {
+                        ext != DEVICE_GROUP_EXTENSION ||
+                            self.extensions.contains(&DEVICE_GROUP_CREATION_EXTENSION)
+                    })
+                    // Shader printf needs the validation layer to be configured for it.
+                    .filter(|&ext| {
+                        ext != SHADER_NON_SEMANTIC_INFO_EXTENSION ||
+                            self.extensions.contains(&VALIDATION_FEATURES_EXTENSION)
+                    })
+                    .collect();
                 let physical_device = PhysicalDevice {
                     instance: self.raw.clone(),
                     handle: device,
+                    api_version: cmp::min(self.api_version, properties.api_version),
                     properties,
+                    extensions,
+                    instance_extensions: self.extensions.clone(),
                 };
                 let queue_families = self.raw.0
                     .get_physical_device_queue_family_properties(device)
@@ -306,6 +537,9 @@ impl hal::queue::QueueFamily for QueueFamily {
     fn max_queues(&self) -> usize {
         self.properties.queue_count as _
     }
+    fn supports_sparse_binding(&self) -> bool {
+        self.properties.queue_flags.subset(vk::QUEUE_SPARSE_BINDING_BIT)
+    }
     fn id(&self) -> queue::QueueFamilyId {
         queue::QueueFamilyId(self.index as _)
     }
@@ -316,31 +550,392 @@ pub struct PhysicalDevice {
     instance: Arc<RawInstance>,
     handle: vk::PhysicalDevice,
     properties: vk::PhysicalDeviceProperties,
+    /// Vulkan version usable with the device, limited by the version of the instance.
+    api_version: u32,
+    /// Optional device extensions supported by the device.
+    extensions: Vec<&'static str>,
+    /// Extensions enabled on the instance.
+    instance_extensions: Vec<&'static str>,
+}
+
+impl PhysicalDevice {
+    /// Load `vkGetPhysicalDeviceFeatures2KHR` of `VK_KHR_get_physical_device_properties2`.
+    fn get_features2(&self) -> Option<device::GetPhysicalDeviceFeatures2> {
+        let entry = VK_ENTRY.as_ref().ok()?;
+        unsafe {
+            let name = CStr::from_bytes_with_nul(b"vkGetPhysicalDeviceFeatures2KHR\0").unwrap();
+            let function: *const vk::types::c_void = mem::transmute(
+                entry.static_fn().get_instance_proc_addr(self.instance.0.handle(), name.as_ptr())
+            );
+            if function.is_null() {
+                return None;
+            }
+            Some(mem::transmute(function))
+        }
+    }
+
+    /// Query the subgroup properties of Vulkan 1.1, `None` if the device doesn't support it.
+    fn subgroup_properties(&self) -> Option<device::PhysicalDeviceSubgroupProperties> {
+        if self.api_version < vk_make_version!(1, 1, 0) ||
+            !self.instance_extensions.contains(&PHYSICAL_DEVICE_PROPERTIES2_EXTENSION)
+        {
+            return None;
+        }
+        let entry = VK_ENTRY.as_ref().ok()?;
+        let get_properties2: device::GetPhysicalDeviceProperties2 = unsafe {
+            let name = CStr::from_bytes_with_nul(b"vkGetPhysicalDeviceProperties2KHR\0").unwrap();
+            let function: *const vk::types::c_void = mem::transmute(
+                entry.static_fn().get_instance_proc_addr(self.instance.0.handle(), name.as_ptr())
+            );
+            if function.is_null() {
+                return None;
+            }
+            mem::transmute(function)
+        };
+
+        let mut subgroup_properties: device::PhysicalDeviceSubgroupProperties = unsafe { mem::zeroed() };
+        subgroup_properties.s_type = device::STRUCTURE_TYPE_PHYSICAL_DEVICE_SUBGROUP_PROPERTIES;
+        let mut properties2 = device::PhysicalDeviceProperties2 {
+            s_type: device::STRUCTURE_TYPE_PHYSICAL_DEVICE_PROPERTIES_2,
+            p_next: &mut subgroup_properties as *mut _ as *mut _,
+            properties: unsafe { mem::zeroed() },
+        };
+        get_properties2(self.handle, &mut properties2);
+        subgroup_properties.p_next = ptr::null_mut();
+        Some(subgroup_properties)
+    }
+
+    /// Query the features of `VK_EXT_descriptor_indexing`, `None` if the extension isn't available.
+    fn descriptor_indexing_features(&self) -> Option<device::PhysicalDeviceDescriptorIndexingFeatures> {
+        if !self.extensions.contains(&DESCRIPTOR_INDEXING_EXTENSION) {
+            return None;
+        }
+        let get_features2 = self.get_features2()?;
+
+        let mut indexing_features: device::PhysicalDeviceDescriptorIndexingFeatures = unsafe { mem::zeroed() };
+        indexing_features.s_type = device::STRUCTURE_TYPE_PHYSICAL_DEVICE_DESCRIPTOR_INDEXING_FEATURES;
+        let mut features2 = device::PhysicalDeviceFeatures2 {
+            s_type: device::STRUCTURE_TYPE_PHYSICAL_DEVICE_FEATURES_2,
+            p_next: &mut indexing_features as *mut _ as *mut _,
+            features: unsafe { mem::zeroed() },
+        };
+        get_features2(self.handle, &mut features2);
+        indexing_features.p_next = ptr::null_mut();
+        Some(indexing_features)
+    }
+
+    /// Query the feature structure of an extension, `None` if the extension isn't available.
+    ///
+    /// `features` has to be a zeroed feature structure with only its type set.
+    fn extension_features<T>(&self, extension: &'static str, mut features: T) -> Option<T> {
+        if !self.extensions.contains(&extension) {
+            return None;
+        }
+        let get_features2 = self.get_features2()?;
+
+        let mut features2 = device::PhysicalDeviceFeatures2 {
+            s_type: device::STRUCTURE_TYPE_PHYSICAL_DEVICE_FEATURES_2,
+            p_next: &mut features as *mut _ as *mut _,
+            features: unsafe { mem::zeroed() },
+        };
+        get_features2(self.handle, &mut features2);
+        Some(features)
+    }
+
+    /// Query the features of `VK_KHR_shader_clock`, `None` if the extension isn't available.
+    fn shader_clock_features(&self) -> Option<device::PhysicalDeviceShaderClockFeatures> {
+        let mut clock_features: device::PhysicalDeviceShaderClockFeatures = unsafe { mem::zeroed() };
+        clock_features.s_type = device::STRUCTURE_TYPE_PHYSICAL_DEVICE_SHADER_CLOCK_FEATURES;
+        self.extension_features(SHADER_CLOCK_EXTENSION, clock_features)
+    }
+
+    /// Query the features of `VK_KHR_fragment_shader_barycentric`, `None` if the extension
+    /// isn't available.
+    fn barycentric_features(&self) -> Option<device::PhysicalDeviceFragmentShaderBarycentricFeatures> {
+        let mut barycentric_features: device::PhysicalDeviceFragmentShaderBarycentricFeatures = unsafe { mem::zeroed() };
+        barycentric_features.s_type = device::STRUCTURE_TYPE_PHYSICAL_DEVICE_FRAGMENT_SHADER_BARYCENTRIC_FEATURES;
+        self.extension_features(FRAGMENT_SHADER_BARYCENTRIC_EXTENSION, barycentric_features)
+    }
+
+    /// Query the features of `VK_KHR_shader_atomic_int64`, `None` if the extension isn't available.
+    fn atomic_int64_features(&self) -> Option<device::PhysicalDeviceShaderAtomicInt64Features> {
+        let mut atomic_features: device::PhysicalDeviceShaderAtomicInt64Features = unsafe { mem::zeroed() };
+        atomic_features.s_type = device::STRUCTURE_TYPE_PHYSICAL_DEVICE_SHADER_ATOMIC_INT64_FEATURES;
+        self.extension_features(SHADER_ATOMIC_INT64_EXTENSION, atomic_features)
+    }
+
+    /// Query the features of `VK_EXT_shader_image_atomic_int64`, `None` if the extension
+    /// isn't available.
+    fn image_atomic_int64_features(&self) -> Option<device::PhysicalDeviceShaderImageAtomicInt64Features> {
+        let mut atomic_features: device::PhysicalDeviceShaderImageAtomicInt64Features = unsafe { mem::zeroed() };
+        atomic_features.s_type = device::STRUCTURE_TYPE_PHYSICAL_DEVICE_SHADER_IMAGE_ATOMIC_INT64_FEATURES;
+        self.extension_features(SHADER_IMAGE_ATOMIC_INT64_EXTENSION, atomic_features)
+    }
+
+    /// Query the features of `VK_KHR_shader_float16_int8`, `None` if the extension isn't available.
+    fn float16_int8_features(&self) -> Option<device::PhysicalDeviceShaderFloat16Int8Features> {
+        let mut float16_int8_features: device::PhysicalDeviceShaderFloat16Int8Features = unsafe { mem::zeroed() };
+        float16_int8_features.s_type = device::STRUCTURE_TYPE_PHYSICAL_DEVICE_SHADER_FLOAT16_INT8_FEATURES;
+        self.extension_features(SHADER_FLOAT16_INT8_EXTENSION, float16_int8_features)
+    }
+
+    /// Query the features of `VK_KHR_16bit_storage`, `None` if the extension isn't available.
+    fn storage_16bit_features(&self) -> Option<device::PhysicalDevice16BitStorageFeatures> {
+        let mut storage_features: device::PhysicalDevice16BitStorageFeatures = unsafe { mem::zeroed() };
+        storage_features.s_type = device::STRUCTURE_TYPE_PHYSICAL_DEVICE_16BIT_STORAGE_FEATURES;
+        self.extension_features(STORAGE_16BIT_EXTENSION, storage_features)
+    }
+
+    /// Query the features of `VK_KHR_8bit_storage`, `None` if the extension isn't available.
+    fn storage_8bit_features(&self) -> Option<device::PhysicalDevice8BitStorageFeatures> {
+        let mut storage_features: device::PhysicalDevice8BitStorageFeatures = unsafe { mem::zeroed() };
+        storage_features.s_type = device::STRUCTURE_TYPE_PHYSICAL_DEVICE_8BIT_STORAGE_FEATURES;
+        self.extension_features(STORAGE_8BIT_EXTENSION, storage_features)
+    }
+
+    /// Query the features of `VK_KHR_imageless_framebuffer`, `None` if the extension isn't available.
+    fn imageless_framebuffer_features(&self) -> Option<device::PhysicalDeviceImagelessFramebufferFeatures> {
+        let mut imageless_features: device::PhysicalDeviceImagelessFramebufferFeatures = unsafe { mem::zeroed() };
+        imageless_features.s_type = device::STRUCTURE_TYPE_PHYSICAL_DEVICE_IMAGELESS_FRAMEBUFFER_FEATURES;
+        self.extension_features(IMAGELESS_FRAMEBUFFER_EXTENSION, imageless_features)
+    }
+
+    /// Query the features of `VK_EXT_shader_atomic_float`, `None` if the extension isn't available.
+    fn atomic_float_features(&self) -> Option<device::PhysicalDeviceShaderAtomicFloatFeatures> {
+        let mut atomic_features: device::PhysicalDeviceShaderAtomicFloatFeatures = unsafe { mem::zeroed() };
+        atomic_features.s_type = device::STRUCTURE_TYPE_PHYSICAL_DEVICE_SHADER_ATOMIC_FLOAT_FEATURES;
+        self.extension_features(SHADER_ATOMIC_FLOAT_EXTENSION, atomic_features)
+    }
 }
 
 impl hal::PhysicalDevice<Backend> for PhysicalDevice {
     fn open(
         &self, families: &[(&QueueFamily, &[hal::QueuePriority])]
     ) -> Result<hal::Gpu<Backend>, DeviceCreationError> {
+        // One queue is created in each family not opened by the user, for presenting
+        // to surfaces which can't be presented to from the opened families.
+        let present_priorities = [1.0];
+        let present_families = (0 .. self.instance.0
+            .get_physical_device_queue_family_properties(self.handle)
+            .len() as u32)
+            .filter(|&index| families.iter().all(|&(family, _)| family.index != index))
+            .collect::<Vec<_>>();
+
         let family_infos = families
             .iter()
-            .map(|&(family, priorities)| vk::DeviceQueueCreateInfo {
+            .map(|&(family, priorities)| (family.index, priorities))
+            .chain(present_families.iter().map(|&index| (index, &present_priorities[..])))
+            .map(|(index, priorities)| vk::DeviceQueueCreateInfo {
                 s_type: vk::StructureType::DeviceQueueCreateInfo,
                 p_next: ptr::null(),
                 flags: vk::DeviceQueueCreateFlags::empty(),
-                queue_family_index: family.index,
+                queue_family_index: index,
                 queue_count: priorities.len() as _,
                 p_queue_priorities: priorities.as_ptr(),
             })
             .collect::<Vec<_>>();
 
         // enabled features mask
-        let features = Features::empty();
+        let mut features = Features::DYNAMIC_RENDERING;
+        if self.extensions.contains(&STENCIL_EXPORT_EXTENSION) {
+            features |= Features::SHADER_STENCIL_EXPORT;
+        }
+        if self.extensions.contains(&MULTIVIEW_EXTENSION) {
+            features |= Features::MULTIVIEW;
+        }
+        if self.extensions.contains(&SHADER_NON_SEMANTIC_INFO_EXTENSION) {
+            features |= Features::SHADER_PRINTF;
+        }
+        if self.extensions.contains(&DEVICE_GROUP_EXTENSION) {
+            features |= Features::DISPATCH_BASE;
+        }
+        // Subgroup operations don't need to be enabled.
+        if let Some(subgroup_properties) = self.subgroup_properties() {
+            features |= subgroup_properties.features();
+        }
+        // The `timelineSemaphore` feature is required by `VK_KHR_timeline_semaphore`,
+        // but still needs to be enabled.
+        // All supported features of `VK_EXT_descriptor_indexing` are enabled,
+        // as they don't affect descriptor sets not using them.
+        let mut indexing_features = self
+            .descriptor_indexing_features()
+            .filter(|indexing_features| indexing_features.is_supported());
+        let indexing_next: *mut vk::types::c_void = match indexing_features {
+            Some(ref mut indexing_features) => {
+                features |= Features::DESCRIPTOR_INDEXING;
+                indexing_features as *mut _ as *mut _
+            }
+            None => ptr::null_mut(),
+        };
+        // The supported shader clock scopes are enabled, only the subgroup scope is
+        // required for `Features::SHADER_CLOCK`.
+        let mut clock_features = self
+            .shader_clock_features()
+            .filter(|clock_features| clock_features.shader_subgroup_clock != 0);
+        let clock_next: *mut vk::types::c_void = match clock_features {
+            Some(ref mut clock_features) => {
+                features |= Features::SHADER_CLOCK;
+                clock_features.p_next = indexing_next;
+                clock_features as *mut _ as *mut _
+            }
+            None => indexing_next,
+        };
+        let mut barycentric_features = self
+            .barycentric_features()
+            .filter(|barycentric_features| barycentric_features.fragment_shader_barycentric != 0);
+        let barycentric_next: *mut vk::types::c_void = match barycentric_features {
+            Some(ref mut barycentric_features) => {
+                features |= Features::FRAGMENT_BARYCENTRIC;
+                barycentric_features.p_next = clock_next;
+                barycentric_features as *mut _ as *mut _
+            }
+            None => clock_next,
+        };
+        // All supported atomic operations are enabled, they don't affect shaders not using them.
+        let mut atomic_int64_features = self
+            .atomic_int64_features()
+            .filter(|atomic_features| !atomic_features.features().is_empty());
+        let atomic_int64_next: *mut vk::types::c_void = match atomic_int64_features {
+            Some(ref mut atomic_features) => {
+                features |= atomic_features.features();
+                atomic_features.p_next = barycentric_next;
+                atomic_features as *mut _ as *mut _
+            }
+            None => barycentric_next,
+        };
+        let mut image_atomic_int64_features = self
+            .image_atomic_int64_features()
+            .filter(|atomic_features| !atomic_features.features().is_empty());
+        let image_atomic_int64_next: *mut vk::types::c_void = match image_atomic_int64_features {
+            Some(ref mut atomic_features) => {
+                features |= atomic_features.features();
+                atomic_features.p_next = atomic_int64_next;
+                atomic_features as *mut _ as *mut _
+            }
+            None => atomic_int64_next,
+        };
+        let mut atomic_float_features = self
+            .atomic_float_features()
+            .filter(|atomic_features| !atomic_features.features().is_empty());
+        let atomic_float_next: *mut vk::types::c_void = match atomic_float_features {
+            Some(ref mut atomic_features) => {
+                features |= atomic_features.features();
+                atomic_features.p_next = image_atomic_int64_next;
+                atomic_features as *mut _ as *mut _
+            }
+            None => image_atomic_int64_next,
+        };
+        // Supported 16-bit and 8-bit types are enabled the same way.
+        let mut float16_int8_features = self
+            .float16_int8_features()
+            .filter(|float16_int8_features| !float16_int8_features.features().is_empty());
+        let float16_int8_next: *mut vk::types::c_void = match float16_int8_features {
+            Some(ref mut float16_int8_features) => {
+                features |= float16_int8_features.features();
+                float16_int8_features.p_next = atomic_float_next;
+                float16_int8_features as *mut _ as *mut _
+            }
+            None => atomic_float_next,
+        };
+        let mut storage_16bit_features = self
+            .storage_16bit_features()
+            .filter(|storage_features| !storage_features.features().is_empty());
+        let storage_16bit_next: *mut vk::types::c_void = match storage_16bit_features {
+            Some(ref mut storage_features) => {
+                features |= storage_features.features();
+                storage_features.p_next = float16_int8_next;
+                storage_features as *mut _ as *mut _
+            }
+            None => float16_int8_next,
+        };
+        let mut storage_8bit_features = self
+            .storage_8bit_features()
+            .filter(|storage_features| !storage_features.features().is_empty());
+        let storage_8bit_next: *mut vk::types::c_void = match storage_8bit_features {
+            Some(ref mut storage_features) => {
+                features |= storage_features.features();
+                storage_features.p_next = storage_16bit_next;
+                storage_features as *mut _ as *mut _
+            }
+            None => storage_16bit_next,
+        };
+        let mut imageless_features = self
+            .imageless_framebuffer_features()
+            .filter(|imageless_features| imageless_features.imageless_framebuffer != 0);
+        let imageless_next: *mut vk::types::c_void = match imageless_features {
+            Some(ref mut imageless_features) => {
+                features |= Features::IMAGELESS_FRAMEBUFFER;
+                imageless_features.p_next = storage_8bit_next;
+                imageless_features as *mut _ as *mut _
+            }
+            None => storage_8bit_next,
+        };
+        // The `multiview` feature is required by `VK_KHR_multiview`, but still needs to be enabled.
+        let mut multiview_features = device::PhysicalDeviceMultiviewFeatures {
+            s_type: device::STRUCTURE_TYPE_PHYSICAL_DEVICE_MULTIVIEW_FEATURES,
+            p_next: imageless_next,
+            multiview: vk::VK_TRUE,
+            multiview_geometry_shader: vk::VK_FALSE,
+            multiview_tessellation_shader: vk::VK_FALSE,
+        };
+        let multiview_next: *mut vk::types::c_void = if self.extensions.contains(&MULTIVIEW_EXTENSION) {
+            &mut multiview_features as *mut _ as *mut _
+        } else {
+            imageless_next
+        };
+        let timeline_features = device::PhysicalDeviceTimelineSemaphoreFeatures {
+            s_type: device::STRUCTURE_TYPE_PHYSICAL_DEVICE_TIMELINE_SEMAPHORE_FEATURES,
+            p_next: multiview_next,
+            timeline_semaphore: vk::VK_TRUE,
+        };
+        let timeline_supported = self.extensions.contains(&TIMELINE_SEMAPHORE_EXTENSION);
+        if timeline_supported {
+            features |= Features::TIMELINE_SEMAPHORE;
+        }
+
+        // Block-compressed formats need to be enabled explicitly at device creation.
+        let supported_features = self.instance.0.get_physical_device_features(self.handle);
+        let mut enabled_features: vk::PhysicalDeviceFeatures = unsafe { mem::zeroed() };
+        if supported_features.texture_compression_etc2 != 0 {
+            enabled_features.texture_compression_etc2 = vk::VK_TRUE;
+            features |= Features::FORMAT_ETC2;
+        }
+        if supported_features.texture_compression_astc_ldr != 0 {
+            enabled_features.texture_compression_astc_ldr = vk::VK_TRUE;
+            features |= Features::FORMAT_ASTC_LDR;
+        }
+        if supported_features.texture_compression_bc != 0 {
+            enabled_features.texture_compression_bc = vk::VK_TRUE;
+            features |= Features::FORMAT_BC;
+        }
+        // Sparse binding and residency are enabled whenever available,
+        // since they have no cost for resources not using them.
+        if supported_features.sparse_binding != 0 {
+            enabled_features.sparse_binding = vk::VK_TRUE;
+            features |= Features::SPARSE_BINDING;
+            if supported_features.sparse_residency_buffer != 0 {
+                enabled_features.sparse_residency_buffer = vk::VK_TRUE;
+                features |= Features::SPARSE_RESIDENCY_BUFFER;
+            }
+            if supported_features.sparse_residency_image2_d != 0 {
+                enabled_features.sparse_residency_image2_d = vk::VK_TRUE;
+                features |= Features::SHADER_RESIDENCY_IMAGE_2D;
+            }
+            if supported_features.sparse_residency_image3_d != 0 {
+                enabled_features.sparse_residency_image3_d = vk::VK_TRUE;
+                features |= Features::SHADER_RESIDENSY_IMAGE_3D;
+            }
+            if supported_features.sparse_residency_aliased != 0 {
+                enabled_features.sparse_residency_aliased = vk::VK_TRUE;
+                features |= Features::SPARSE_RESIDENCY_ALIASED;
+            }
+        }
 
         // Create device
         let device_raw = {
             let cstrings = DEVICE_EXTENSIONS
                 .iter()
+                .chain(self.extensions.iter())
                 .map(|&s| CString::new(s).unwrap())
                 .collect::<Vec<_>>();
 
@@ -349,11 +944,13 @@ impl hal::PhysicalDevice<Backend> for PhysicalDevice {
                 .map(|s| s.as_ptr())
                 .collect::<Vec<_>>();
 
-            // TODO: derive from `features`
-            let enabled_features = unsafe { mem::zeroed() };
             let info = vk::DeviceCreateInfo {
                 s_type: vk::StructureType::DeviceCreateInfo,
-                p_next: ptr::null(),
+                p_next: if timeline_supported {
+                    &timeline_features as *const _ as *const _
+                } else {
+                    multiview_next as *const _
+                },
                 flags: vk::DeviceCreateFlags::empty(),
                 queue_create_info_count: family_infos.len() as u32,
                 p_queue_create_infos: family_infos.as_ptr(),
@@ -388,11 +985,61 @@ impl hal::PhysicalDevice<Backend> for PhysicalDevice {
             )
         }).unwrap();
 
+        let timeline_fn = if timeline_supported {
+            device::TimelineSemaphoreFn::load(|name| unsafe {
+                mem::transmute(
+                    self.instance.0
+                        .get_device_proc_addr(
+                            device_raw.handle(),
+                            name.as_ptr(),
+                        )
+                )
+            })
+        } else {
+            None
+        };
+
+        let dispatch_base_fn = if features.contains(Features::DISPATCH_BASE) {
+            let name = CStr::from_bytes_with_nul(b"vkCmdDispatchBaseKHR\0").unwrap();
+            let function: *const vk::types::c_void = unsafe {
+                mem::transmute(self.instance.0.get_device_proc_addr(device_raw.handle(), name.as_ptr()))
+            };
+            if function.is_null() {
+                features.remove(Features::DISPATCH_BASE);
+                None
+            } else {
+                Some(unsafe { mem::transmute::<_, command::CmdDispatchBase>(function) })
+            }
+        } else {
+            None
+        };
+
         let device = Device {
-            raw: Arc::new(RawDevice(device_raw, features)),
+            raw: Arc::new(RawDevice(
+                device_raw,
+                features,
+                StatisticsCollector::default(),
+                timeline_fn,
+                Mutex::new(command::RenderingCache::default()),
+                dispatch_base_fn,
+                self.extensions.contains(&MAINTENANCE2_EXTENSION),
+            )),
+            physical_device: self.handle,
+            samplers: Mutex::new(FastHashMap::default()),
+            image_format_list: self.extensions.contains(&IMAGE_FORMAT_LIST_EXTENSION),
         };
 
         let device_arc = device.raw.clone();
+        let present_queues = Arc::new(window::PresentQueues {
+            physical_device: self.handle,
+            queues: present_families
+                .into_iter()
+                .map(|family| window::PresentQueue {
+                    family,
+                    raw: Mutex::new(unsafe { device_arc.0.get_device_queue(family, 0) }),
+                })
+                .collect(),
+        });
         let queues = families
             .into_iter()
             .map(|&(family, ref priorities)| {
@@ -404,8 +1051,10 @@ impl hal::PhysicalDevice<Backend> for PhysicalDevice {
                     };
                     family_raw.add_queue(CommandQueue {
                         raw: Arc::new(queue_raw),
+                        family: family_index,
                         device: device_arc.clone(),
                         swapchain_fn: swapchain_fn.clone(),
+                        present_queues: present_queues.clone(),
                     });
                 }
                 family_raw
@@ -598,15 +1247,91 @@ impl hal::PhysicalDevice<Backend> for PhysicalDevice {
         if features.fragment_stores_and_atomics != 0 {
             bits |= Features::FRAGMENT_STORES_AND_ATOMICS;
         }
+        if features.shader_int16 != 0 {
+            bits |= Features::SHADER_INT16;
+        }
+        if features.sparse_binding != 0 {
+            bits |= Features::SPARSE_BINDING;
+        }
+        if features.sparse_residency_buffer != 0 {
+            bits |= Features::SPARSE_RESIDENCY_BUFFER;
+        }
+        if features.sparse_residency_image2_d != 0 {
+            bits |= Features::SHADER_RESIDENCY_IMAGE_2D;
+        }
+        if features.sparse_residency_image3_d != 0 {
+            bits |= Features::SHADER_RESIDENSY_IMAGE_3D;
+        }
+        if features.sparse_residency_aliased != 0 {
+            bits |= Features::SPARSE_RESIDENCY_ALIASED;
+        }
+        if self.extensions.contains(&STENCIL_EXPORT_EXTENSION) {
+            bits |= Features::SHADER_STENCIL_EXPORT;
+        }
+        // The `multiview` feature is required by `VK_KHR_multiview`.
+        if self.extensions.contains(&MULTIVIEW_EXTENSION) {
+            bits |= Features::MULTIVIEW;
+        }
+        if self.extensions.contains(&TIMELINE_SEMAPHORE_EXTENSION) {
+            bits |= Features::TIMELINE_SEMAPHORE;
+        }
+        if self.extensions.contains(&SHADER_NON_SEMANTIC_INFO_EXTENSION) {
+            bits |= Features::SHADER_PRINTF;
+        }
+        if self.extensions.contains(&DEVICE_GROUP_EXTENSION) {
+            bits |= Features::DISPATCH_BASE;
+        }
+        if let Some(subgroup_properties) = self.subgroup_properties() {
+            bits |= subgroup_properties.features();
+        }
+        if self.descriptor_indexing_features().map_or(false, |features| features.is_supported()) {
+            bits |= Features::DESCRIPTOR_INDEXING;
+        }
+        if self.shader_clock_features().map_or(false, |features| features.shader_subgroup_clock != 0) {
+            bits |= Features::SHADER_CLOCK;
+        }
+        if self.barycentric_features().map_or(false, |features| features.fragment_shader_barycentric != 0) {
+            bits |= Features::FRAGMENT_BARYCENTRIC;
+        }
+        if let Some(atomic_features) = self.atomic_int64_features() {
+            bits |= atomic_features.features();
+        }
+        if let Some(atomic_features) = self.image_atomic_int64_features() {
+            bits |= atomic_features.features();
+        }
+        if let Some(atomic_features) = self.atomic_float_features() {
+            bits |= atomic_features.features();
+        }
+        if let Some(float16_int8_features) = self.float16_int8_features() {
+            bits |= float16_int8_features.features();
+        }
+        if let Some(storage_features) = self.storage_16bit_features() {
+            bits |= storage_features.features();
+        }
+        if let Some(storage_features) = self.storage_8bit_features() {
+            bits |= storage_features.features();
+        }
+        if self.imageless_framebuffer_features().map_or(false, |features| features.imageless_framebuffer != 0) {
+            bits |= Features::IMAGELESS_FRAMEBUFFER;
+        }
+        // Emulated with transient render passes and framebuffers, `VK_KHR_dynamic_rendering`
+        // isn't exposed by our version of ash.
+        bits |= Features::DYNAMIC_RENDERING;
         //TODO: cover more features
 
         bits
     }
 
+    fn timestamp_period(&self) -> f32 {
+        self.properties.limits.timestamp_period
+    }
+
     fn limits(&self) -> Limits {
         let limits = &self.properties.limits;
         let max_group_count = limits.max_compute_work_group_count;
         let max_group_size = limits.max_compute_work_group_size;
+        // Without `VK_EXT_subgroup_size_control` the subgroup size is fixed.
+        let subgroup_size = self.subgroup_properties().map_or(0, |properties| properties.subgroup_size);
 
         Limits {
             max_texture_size: limits.max_image_dimension3d as _,
@@ -629,12 +1354,27 @@ impl hal::PhysicalDevice<Backend> for PhysicalDevice {
             framebuffer_stencil_samples_count: limits.framebuffer_stencil_sample_counts.flags() as _,
             max_color_attachments: limits.max_color_attachments as _,
             non_coherent_atom_size: limits.non_coherent_atom_size as _,
+            min_subgroup_size: subgroup_size,
+            max_subgroup_size: subgroup_size,
         }
     }
 }
 
 #[doc(hidden)]
-pub struct RawDevice(pub ash::Device<V1_0>, Features);
+pub struct RawDevice(
+    pub ash::Device<V1_0>,
+    Features,
+    StatisticsCollector,
+    /// Present when timeline semaphores are native, they are emulated on the host otherwise.
+    Option<device::TimelineSemaphoreFn>,
+    /// Render passes and framebuffers emulating `begin_rendering`.
+    Mutex<command::RenderingCache>,
+    /// `vkCmdDispatchBaseKHR`, present with `Features::DISPATCH_BASE`.
+    Option<command::CmdDispatchBase>,
+    /// Whether `VK_KHR_maintenance2` is enabled, providing the per-aspect read-only
+    /// depth-stencil layouts.
+    bool,
+);
 impl fmt::Debug for RawDevice {
     fn fmt(&self, _formatter: &mut fmt::Formatter) -> fmt::Result {
         unimplemented!()
@@ -642,6 +1382,7 @@ impl fmt::Debug for RawDevice {
 }
 impl Drop for RawDevice {
     fn drop(&mut self) {
+        self.4.get_mut().unwrap().destroy(&self.0);
         unsafe { self.0.destroy_device(None); }
     }
 }
@@ -651,8 +1392,126 @@ pub type RawCommandQueue = Arc<vk::Queue>;
 
 pub struct CommandQueue {
     raw: RawCommandQueue,
+    family: u32,
     device: Arc<RawDevice>,
     swapchain_fn: vk::SwapchainFn,
+    present_queues: Arc<window::PresentQueues>,
+}
+
+impl CommandQueue {
+    fn present_raw(
+        &self,
+        queue: vk::Queue,
+        swapchains: &[vk::SwapchainKHR],
+        frames: &[SwapImageIndex],
+        semaphores: &[vk::Semaphore],
+    ) -> Result<Option<Suboptimal>, PresentError> {
+        let info = vk::PresentInfoKHR {
+            s_type: vk::StructureType::PresentInfoKhr,
+            p_next: ptr::null(),
+            wait_semaphore_count: semaphores.len() as _,
+            p_wait_semaphores: semaphores.as_ptr(),
+            swapchain_count: swapchains.len() as _,
+            p_swapchains: swapchains.as_ptr(),
+            p_image_indices: frames.as_ptr(),
+            p_results: ptr::null_mut(),
+        };
+
+        match unsafe {
+            self.swapchain_fn
+                .queue_present_khr(queue, &info)
+        } {
+            vk::Result::Success => Ok(None),
+            vk::Result::SuboptimalKhr => Ok(Some(Suboptimal::UNKNOWN)),
+            vk::Result::ErrorOutOfDateKhr => Err(PresentError::OutOfDate),
+            vk::Result::ErrorSurfaceLostKhr => Err(PresentError::SurfaceLost),
+            vk::Result::ErrorDeviceLost => Err(PresentError::DeviceLost),
+            result => panic!("Failed to present frame: {:?}", result),
+        }
+    }
+
+    fn present_swapchains<S>(
+        &self,
+        swapchains: &[(S, SwapImageIndex)],
+        semaphores: &[vk::Semaphore],
+    ) -> Result<Option<Suboptimal>, PresentError>
+    where
+        S: Borrow<window::Swapchain>,
+    {
+        let mut frames = Vec::new();
+        let mut vk_swapchains = Vec::new();
+        let mut transfers = Vec::new();
+        for &(ref swapchain, index) in swapchains {
+            let swapchain = swapchain.borrow();
+            match swapchain.present_transfer(self.family, index, &self.present_queues) {
+                Some(transfer) => transfers.push((swapchain.raw, index, transfer)),
+                None => {
+                    vk_swapchains.push(swapchain.raw);
+                    frames.push(index);
+                }
+            }
+        }
+
+        if transfers.is_empty() {
+            return self.present_raw(*self.raw, &vk_swapchains, &frames, semaphores);
+        }
+        assert!(
+            vk_swapchains.is_empty(),
+            "Swapchains presentable from this queue can't be presented together with ones which aren't"
+        );
+
+        // Release the images to the present queues after the wait semaphores, then
+        // acquire and present them from there.
+        let releases = transfers.iter().map(|&(_, _, ref t)| t.release).collect::<Vec<_>>();
+        let released = transfers.iter().map(|&(_, _, ref t)| t.released).collect::<Vec<_>>();
+        self.submit_transfer(*self.raw, semaphores, &releases, &released);
+
+        let mut result = Ok(None);
+        for (i, present_queue) in self.present_queues.queues.iter().enumerate() {
+            let transfers = transfers
+                .iter()
+                .filter(|&&(_, _, ref t)| t.present_queue == i)
+                .collect::<Vec<_>>();
+            if transfers.is_empty() {
+                continue;
+            }
+
+            let released = transfers.iter().map(|&&(_, _, ref t)| t.released).collect::<Vec<_>>();
+            let acquires = transfers.iter().map(|&&(_, _, ref t)| t.acquire).collect::<Vec<_>>();
+            let acquired = transfers.iter().map(|&&(_, _, ref t)| t.acquired).collect::<Vec<_>>();
+            let vk_swapchains = transfers.iter().map(|&&(raw, _, _)| raw).collect::<Vec<_>>();
+            let frames = transfers.iter().map(|&&(_, index, _)| index).collect::<Vec<_>>();
+
+            let queue = present_queue.raw.lock().unwrap();
+            self.submit_transfer(*queue, &released, &acquires, &acquired);
+            let presented = self.present_raw(*queue, &vk_swapchains, &frames, &acquired);
+            result = result.and_then(|suboptimal| presented.map(|s| suboptimal.or(s)));
+        }
+        result
+    }
+
+    fn submit_transfer(
+        &self,
+        queue: vk::Queue,
+        waits: &[vk::Semaphore],
+        buffers: &[vk::CommandBuffer],
+        signals: &[vk::Semaphore],
+    ) {
+        let stages = vec![conv::map_pipeline_stage(PipelineStage::TOP_OF_PIPE); waits.len()];
+        let info = vk::SubmitInfo {
+            s_type: vk::StructureType::SubmitInfo,
+            p_next: ptr::null(),
+            wait_semaphore_count: waits.len() as u32,
+            p_wait_semaphores: waits.as_ptr(),
+            p_wait_dst_stage_mask: if stages.is_empty() { ptr::null() } else { stages.as_ptr() },
+            command_buffer_count: buffers.len() as u32,
+            p_command_buffers: buffers.as_ptr(),
+            signal_semaphore_count: signals.len() as u32,
+            p_signal_semaphores: signals.as_ptr(),
+        };
+        let result = unsafe { self.device.0.queue_submit(queue, &[info], vk::Fence::null()) };
+        assert_eq!(Ok(()), result);
+    }
 }
 
 impl hal::queue::RawCommandQueue<Backend> for CommandQueue {
@@ -664,26 +1523,76 @@ impl hal::queue::RawCommandQueue<Backend> for CommandQueue {
         IC: IntoIterator,
         IC::Item: Borrow<command::CommandBuffer>,
     {
+        let mut statistics = FrameStatistics::default();
         let buffers = submission.cmd_buffers
             .into_iter()
-            .map(|cmd| cmd.borrow().raw)
+            .map(|cmd| {
+                let cmd = cmd.borrow();
+                statistics.add(&cmd.statistics.frame);
+                cmd.raw
+            })
             .collect::<Vec<_>>();
-        let waits = submission.wait_semaphores
+        self.device.2.add(&statistics);
+
+        // Emulated timeline semaphores are waited on by the host before submitting.
+        let mut emulated_signals = Vec::new();
+        let mut waits = submission.wait_semaphores
             .iter()
             .map(|&(ref semaphore, _)| semaphore.0)
             .collect::<Vec<_>>();
-        let stages = submission.wait_semaphores
+        let mut stages = submission.wait_semaphores
             .iter()
             .map(|&(_, stage)| conv::map_pipeline_stage(stage))
             .collect::<Vec<_>>();
-        let signals = submission.signal_semaphores
+        let mut wait_values = vec![0; waits.len()];
+        for &(semaphore, value, stage) in submission.wait_timeline_semaphores {
+            match *semaphore {
+                native::TimelineSemaphore::Native(raw) => {
+                    waits.push(raw);
+                    stages.push(conv::map_pipeline_stage(stage));
+                    wait_values.push(value);
+                }
+                native::TimelineSemaphore::Emulated(ref timeline) => {
+                    timeline.wait(value, !0);
+                }
+            }
+        }
+        let mut signals = submission.signal_semaphores
             .iter()
             .map(|semaphore| semaphore.0)
             .collect::<Vec<_>>();
+        let mut signal_values = vec![0; signals.len()];
+        for &(semaphore, value) in submission.signal_timeline_semaphores {
+            match *semaphore {
+                native::TimelineSemaphore::Native(raw) => {
+                    signals.push(raw);
+                    signal_values.push(value);
+                }
+                native::TimelineSemaphore::Emulated(ref timeline) => {
+                    emulated_signals.push((timeline, value));
+                }
+            }
+        }
+
+        // Binary semaphores ignore their values, which are only chained when needed.
+        let timeline_info = device::TimelineSemaphoreSubmitInfo {
+            s_type: device::STRUCTURE_TYPE_TIMELINE_SEMAPHORE_SUBMIT_INFO,
+            p_next: ptr::null(),
+            wait_semaphore_value_count: wait_values.len() as u32,
+            p_wait_semaphore_values: wait_values.as_ptr(),
+            signal_semaphore_value_count: signal_values.len() as u32,
+            p_signal_semaphore_values: signal_values.as_ptr(),
+        };
+        let has_timeline_values = waits.len() > submission.wait_semaphores.len() ||
+            signals.len() > submission.signal_semaphores.len();
 
         let info = vk::SubmitInfo {
             s_type: vk::StructureType::SubmitInfo,
-            p_next: ptr::null(),
+            p_next: if has_timeline_values {
+                &timeline_info as *const _ as *const _
+            } else {
+                ptr::null()
+            },
             wait_semaphore_count: waits.len() as u32,
             p_wait_semaphores: waits.as_ptr(),
             // If count is zero, AMD driver crashes if nullptr is not set for stage masks
@@ -700,9 +1609,128 @@ impl hal::queue::RawCommandQueue<Backend> for CommandQueue {
 
         let result = self.device.0.queue_submit(*self.raw, &[info], fence_raw);
         assert_eq!(Ok(()), result);
+
+        if !emulated_signals.is_empty() {
+            let result = self.device.0.queue_wait_idle(*self.raw);
+            assert_eq!(Ok(()), result);
+            for (timeline, value) in emulated_signals {
+                timeline.signal(value);
+            }
+        }
+    }
+
+    unsafe fn bind_sparse(
+        &mut self,
+        info: hal::sparse::SparseBindInfo<Backend>,
+        fence: Option<&native::Fence>,
+    ) {
+        fn map_memory(memory: Option<(&native::Memory, u64)>) -> (vk::DeviceMemory, u64) {
+            memory.map_or((vk::DeviceMemory::null(), 0), |(memory, offset)| (memory.raw, offset))
+        }
+        fn map_bind(bind: &hal::sparse::SparseBind<Backend>) -> vk::SparseMemoryBind {
+            let (memory, memory_offset) = map_memory(bind.memory);
+            vk::SparseMemoryBind {
+                resource_offset: bind.resource_offset,
+                size: bind.size,
+                memory,
+                memory_offset,
+                flags: vk::SparseMemoryBindFlags::empty(),
+            }
+        }
+
+        let waits = info.wait_semaphores
+            .iter()
+            .map(|&(semaphore, _)| semaphore.0)
+            .collect::<Vec<_>>();
+        let signals = info.signal_semaphores
+            .iter()
+            .map(|semaphore| semaphore.0)
+            .collect::<Vec<_>>();
+
+        // The bind arrays have to outlive the info structures pointing into them.
+        let buffer_binds = info.buffer_binds
+            .iter()
+            .map(|&(_, binds)| binds.iter().map(map_bind).collect::<Vec<_>>())
+            .collect::<Vec<_>>();
+        let buffer_infos = info.buffer_binds
+            .iter()
+            .zip(&buffer_binds)
+            .map(|(&(buffer, _), binds)| vk::SparseBufferMemoryBindInfo {
+                buffer: buffer.raw,
+                bind_count: binds.len() as u32,
+                p_binds: binds.as_ptr(),
+            })
+            .collect::<Vec<_>>();
+
+        let image_opaque_binds = info.image_opaque_binds
+            .iter()
+            .map(|&(_, binds)| binds.iter().map(map_bind).collect::<Vec<_>>())
+            .collect::<Vec<_>>();
+        let image_opaque_infos = info.image_opaque_binds
+            .iter()
+            .zip(&image_opaque_binds)
+            .map(|(&(image, _), binds)| vk::SparseImageOpaqueMemoryBindInfo {
+                image: image.raw,
+                bind_count: binds.len() as u32,
+                p_binds: binds.as_ptr(),
+            })
+            .collect::<Vec<_>>();
+
+        let image_binds = info.image_binds
+            .iter()
+            .map(|&(_, binds)| {
+                binds
+                    .iter()
+                    .map(|bind| {
+                        let (memory, memory_offset) = map_memory(bind.memory);
+                        vk::SparseImageMemoryBind {
+                            subresource: conv::map_subresource(&bind.subresource),
+                            offset: conv::map_offset(bind.offset),
+                            extent: conv::map_extent(bind.extent),
+                            memory,
+                            memory_offset,
+                            flags: vk::SparseMemoryBindFlags::empty(),
+                        }
+                    })
+                    .collect::<Vec<_>>()
+            })
+            .collect::<Vec<_>>();
+        let image_infos = info.image_binds
+            .iter()
+            .zip(&image_binds)
+            .map(|(&(image, _), binds)| vk::SparseImageMemoryBindInfo {
+                image: image.raw,
+                bind_count: binds.len() as u32,
+                p_binds: binds.as_ptr(),
+            })
+            .collect::<Vec<_>>();
+
+        let bind_info = vk::BindSparseInfo {
+            s_type: vk::StructureType::BindSparseInfo,
+            p_next: ptr::null(),
+            wait_semaphore_count: waits.len() as u32,
+            p_wait_semaphores: waits.as_ptr(),
+            buffer_bind_count: buffer_infos.len() as u32,
+            p_buffer_binds: buffer_infos.as_ptr(),
+            image_opaque_bind_count: image_opaque_infos.len() as u32,
+            p_image_opaque_binds: image_opaque_infos.as_ptr(),
+            image_bind_count: image_infos.len() as u32,
+            p_image_binds: image_infos.as_ptr(),
+            signal_semaphore_count: signals.len() as u32,
+            p_signal_semaphores: signals.as_ptr(),
+        };
+
+        let fence_raw = fence
+            .map(|fence| fence.0)
+            .unwrap_or(vk::Fence::null());
+
+        let result = self.device.0.fp_v1_0().queue_bind_sparse(*self.raw, 1, &bind_info, fence_raw);
+        assert_eq!(vk::Result::Success, result);
     }
 
-    fn present<IS, S, IW>(&mut self, swapchains: IS, wait_semaphores: IW) -> Result<(), ()>
+    fn present<IS, S, IW>(
+        &mut self, swapchains: IS, wait_semaphores: IW
+    ) -> Result<Option<Suboptimal>, PresentError>
     where
         IS: IntoIterator<Item = (S, SwapImageIndex)>,
         S: Borrow<window::Swapchain>,
@@ -714,32 +1742,16 @@ impl hal::queue::RawCommandQueue<Backend> for CommandQueue {
             .map(|sem| sem.borrow().0)
             .collect::<Vec<_>>();
 
-        let mut frames = Vec::new();
-        let mut vk_swapchains = Vec::new();
-        for (swapchain, index) in swapchains {
-            vk_swapchains.push(swapchain.borrow().raw);
-            frames.push(index);
-        }
-
-        let info = vk::PresentInfoKHR {
-            s_type: vk::StructureType::PresentInfoKhr,
-            p_next: ptr::null(),
-            wait_semaphore_count: semaphores.len() as _,
-            p_wait_semaphores: semaphores.as_ptr(),
-            swapchain_count: vk_swapchains.len() as _,
-            p_swapchains: vk_swapchains.as_ptr(),
-            p_image_indices: frames.as_ptr(),
-            p_results: ptr::null_mut(),
-        };
-
-        match unsafe {
-            self.swapchain_fn
-                .queue_present_khr(*self.raw, &info)
-        } {
-            vk::Result::Success => Ok(()),
-            vk::Result::SuboptimalKhr | vk::Result::ErrorOutOfDateKhr => Err(()),
-            _ => panic!("Failed to present frame"),
-        }
+        let swapchains = swapchains.into_iter().collect::<Vec<_>>();
+        let result = self.present_swapchains(&swapchains, &semaphores);
+        // Presentation doesn't tell which of the swapchains got suboptimal.
+        result.map(|suboptimal| suboptimal.map(|suboptimal| {
+            swapchains
+                .iter()
+                .filter_map(|&(ref swapchain, _)| swapchain.borrow().suboptimal())
+                .next()
+                .unwrap_or(suboptimal)
+        }))
     }
 
     fn wait_idle(&self) -> Result<(), HostExecutionError> {
@@ -755,6 +1767,12 @@ impl hal::queue::RawCommandQueue<Backend> for CommandQueue {
 
 pub struct Device {
     raw: Arc<RawDevice>,
+    // Physical device of the swapchains, for querying the surface state when they're suboptimal.
+    physical_device: vk::PhysicalDevice,
+    // Samplers shared between identical sampler descriptions, with their reference counts.
+    samplers: Mutex<FastHashMap<image::SamplerInfo, (vk::Sampler, usize)>>,
+    // Whether `VK_KHR_image_format_list` is enabled, for the view formats of mutable images.
+    image_format_list: bool,
 }
 
 #[derive(Copy, Clone, Debug, Eq, Hash, PartialEq)]
@@ -788,11 +1806,14 @@ impl hal::Backend for Backend {
     type ComputePipeline = native::ComputePipeline;
     type GraphicsPipeline = native::GraphicsPipeline;
     type PipelineLayout = native::PipelineLayout;
+    type PipelineCache = native::PipelineCache;
     type DescriptorSetLayout = native::DescriptorSetLayout;
     type DescriptorPool = native::DescriptorPool;
     type DescriptorSet = native::DescriptorSet;
 
     type Fence = native::Fence;
     type Semaphore = native::Semaphore;
+    type TimelineSemaphore = native::TimelineSemaphore;
+    type Event = native::Event;
     type QueryPool = native::QueryPool;
 }
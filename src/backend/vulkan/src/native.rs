@@ -1,9 +1,14 @@
 use ash::vk;
 use ash::version::DeviceV1_0;
-use hal::pso;
+use hal::{format, image, pso, Features};
 use hal::image::SubresourceRange;
+use hal::sync::HostTimeline;
 use std::borrow::Borrow;
 use std::sync::Arc;
+use device::{
+    DescriptorSetVariableDescriptorCountAllocateInfo,
+    STRUCTURE_TYPE_DESCRIPTOR_SET_VARIABLE_DESCRIPTOR_COUNT_ALLOCATE_INFO,
+};
 use {Backend, RawDevice};
 
 #[derive(Debug, Hash)]
@@ -12,6 +17,17 @@ pub struct Semaphore(pub vk::Semaphore);
 #[derive(Debug, Hash, PartialEq, Eq)]
 pub struct Fence(pub vk::Fence);
 
+#[derive(Debug, Hash, PartialEq, Eq)]
+pub struct Event(pub vk::Event);
+
+#[derive(Debug)]
+pub enum TimelineSemaphore {
+    /// Semaphore of `VK_SEMAPHORE_TYPE_TIMELINE_KHR`.
+    Native(vk::Semaphore),
+    /// Emulated on the host when `VK_KHR_timeline_semaphore` isn't supported.
+    Emulated(HostTimeline),
+}
+
 #[derive(Debug, Hash)]
 pub struct GraphicsPipeline(pub vk::Pipeline);
 
@@ -42,6 +58,7 @@ pub struct Image {
     pub(crate) ty: vk::ImageType,
     pub(crate) flags: vk::ImageCreateFlags,
     pub(crate) extent: vk::Extent3D,
+    pub(crate) samples: image::NumSamples,
 }
 
 #[derive(Debug, Hash, PartialEq, Eq)]
@@ -49,6 +66,11 @@ pub struct ImageView {
     pub(crate) image: vk::Image,
     pub(crate) view: vk::ImageView,
     pub(crate) range: SubresourceRange,
+    // Required to build the transient render passes and framebuffers of `begin_rendering`.
+    pub(crate) format: format::Format,
+    pub(crate) samples: image::NumSamples,
+    /// Extent of the first mip level of the view.
+    pub(crate) extent: vk::Extent3D,
 }
 
 #[derive(Debug, Hash)]
@@ -81,9 +103,16 @@ pub struct PipelineLayout {
     pub(crate) raw: vk::PipelineLayout,
 }
 
+#[derive(Debug, Hash)]
+pub struct PipelineCache {
+    pub(crate) raw: vk::PipelineCache,
+}
+
 #[derive(Debug, Eq, Hash, PartialEq)]
 pub struct ShaderModule {
     pub(crate) raw: vk::ShaderModule,
+    // Features required by the module, validated on pipeline creation.
+    pub(crate) required_features: Features,
 }
 
 #[derive(Debug)]
@@ -94,8 +123,12 @@ pub struct DescriptorPool {
     pub(crate) set_free_vec: Vec<vk::DescriptorSet>,
 }
 
-impl pso::DescriptorPool<Backend> for DescriptorPool {
-    fn allocate_sets<I>(&mut self, layout_iter: I) -> Vec<Result<DescriptorSet, pso::AllocationError>>
+impl DescriptorPool {
+    fn allocate<I>(
+        &mut self,
+        layout_iter: I,
+        variable_counts: Option<&[u32]>,
+    ) -> Vec<Result<DescriptorSet, pso::AllocationError>>
     where
         I: IntoIterator,
         I::Item: Borrow<DescriptorSetLayout>,
@@ -109,9 +142,22 @@ impl pso::DescriptorPool<Backend> for DescriptorPool {
             layout_bindinds.push(layout.borrow().bindings.clone());
         }
 
+        let count_info = variable_counts.map(|counts| {
+            assert_eq!(counts.len(), raw_layouts.len());
+            DescriptorSetVariableDescriptorCountAllocateInfo {
+                s_type: STRUCTURE_TYPE_DESCRIPTOR_SET_VARIABLE_DESCRIPTOR_COUNT_ALLOCATE_INFO,
+                p_next: ptr::null(),
+                descriptor_set_count: counts.len() as u32,
+                p_descriptor_counts: counts.as_ptr(),
+            }
+        });
+
         let info = vk::DescriptorSetAllocateInfo {
             s_type: vk::StructureType::DescriptorSetAllocateInfo,
-            p_next: ptr::null(),
+            p_next: match count_info {
+                Some(ref count_info) => count_info as *const _ as *const _,
+                None => ptr::null(),
+            },
             descriptor_pool: self.raw,
             descriptor_set_count: raw_layouts.len() as u32,
             p_set_layouts: raw_layouts.as_ptr(),
@@ -139,6 +185,24 @@ impl pso::DescriptorPool<Backend> for DescriptorPool {
             })]
         }
     }
+}
+
+impl pso::DescriptorPool<Backend> for DescriptorPool {
+    fn allocate_sets<I>(&mut self, layout_iter: I) -> Vec<Result<DescriptorSet, pso::AllocationError>>
+    where
+        I: IntoIterator,
+        I::Item: Borrow<DescriptorSetLayout>,
+    {
+        self.allocate(layout_iter, None)
+    }
+
+    fn allocate_set_with_count(
+        &mut self,
+        layout: &DescriptorSetLayout,
+        count: pso::DescriptorArrayIndex,
+    ) -> Result<DescriptorSet, pso::AllocationError> {
+        self.allocate(Some(layout), Some(&[count as u32])).remove(0)
+    }
 
     fn free_sets<I>(&mut self, descriptor_sets: I)
     where
@@ -7,6 +7,7 @@ use smallvec::SmallVec;
 use command::CommandBuffer;
 use conv;
 use hal::{pool, command};
+use hal::profiler::CommandBufferStatistics;
 use {Backend, RawDevice};
 
 
@@ -45,6 +46,7 @@ impl pool::RawCommandPool<Backend> for RawCommandPool {
                 CommandBuffer {
                     raw: buffer,
                     device: device.clone(),
+                    statistics: CommandBufferStatistics::default(),
                 }
             }).collect()
     }
@@ -1,19 +1,23 @@
 use std::ptr;
-use std::sync::Arc;
+use std::sync::{Arc, Mutex};
 use std::os::raw::c_void;
 
 use ash::vk;
 use ash::extensions as ext;
+use ash::version::DeviceV1_0;
 
 use hal;
-use hal::image::{NumSamples, Size};
-use hal::format::Format;
+use hal::backend::FastHashMap;
+use hal::command::CommandBufferFlags;
+use hal::image::{Layout, NumSamples, Size, SubresourceRange};
+use hal::format::Aspects;
+use hal::pso::PipelineStage;
 
 #[cfg(feature = "winit")]
 use winit;
 
 use conv;
-use {VK_ENTRY, Backend, Instance, PhysicalDevice, QueueFamily, RawInstance};
+use {VK_ENTRY, Backend, Instance, PhysicalDevice, QueueFamily, RawDevice, RawInstance};
 
 
 pub struct Surface {
@@ -294,9 +298,7 @@ impl hal::Surface<Backend> for Surface {
         hal::image::Kind::D2(self.width, self.height, 1, self.samples)
     }
 
-    fn compatibility(
-        &self, physical_device: &PhysicalDevice
-    ) -> (hal::SurfaceCapabilities, Option<Vec<Format>>, Vec<hal::PresentMode>) {
+    fn capabilities(&self, physical_device: &PhysicalDevice) -> hal::SurfaceCapabilities {
         // Capabilities
         let caps =
             self.raw.functor.get_physical_device_surface_capabilities_khr(
@@ -329,13 +331,6 @@ impl hal::Surface<Backend> for Surface {
             height: caps.max_image_extent.height,
         };
 
-        let capabilities = hal::SurfaceCapabilities {
-            image_count: caps.min_image_count..max_images,
-            current_extent,
-            extents: min_extent..max_extent,
-            max_image_layers: caps.max_image_array_layers as _,
-        };
-
         // Swapchain formats
         let formats =
             self.raw.functor.get_physical_device_surface_formats_khr(
@@ -367,40 +362,346 @@ impl hal::Surface<Backend> for Surface {
             .map(conv::map_vk_present_mode)
             .collect();
 
-        (capabilities, formats, present_modes)
+        hal::SurfaceCapabilities {
+            image_count: caps.min_image_count..max_images,
+            current_extent,
+            extents: min_extent..max_extent,
+            max_image_layers: caps.max_image_array_layers as _,
+            current_transform: conv::map_vk_surface_transform(caps.current_transform),
+            supported_transforms: conv::map_vk_surface_transform(caps.supported_transforms),
+            formats,
+            present_modes,
+        }
     }
 
+    /// Graphics queue families are also reported as supported when another family
+    /// of the device can present, in which case presenting from them goes through the
+    /// backend's own queue of that family. The present-capable family mustn't be opened
+    /// by the user then.
     fn supports_queue_family(&self, queue_family: &QueueFamily) -> bool {
-        self.raw.functor.get_physical_device_surface_support_khr(
-            queue_family.device,
-            queue_family.index,
-            self.raw.handle,
-        )
+        let supports = |index| {
+            self.raw.functor.get_physical_device_surface_support_khr(
+                queue_family.device,
+                index,
+                self.raw.handle,
+            )
+        };
+        if supports(queue_family.index) {
+            return true;
+        }
+        if !queue_family.properties.queue_flags.subset(vk::QUEUE_GRAPHICS_BIT) {
+            return false;
+        }
+
+        let family_count = self.raw.instance.0
+            .get_physical_device_queue_family_properties(queue_family.device)
+            .len() as u32;
+        (0 .. family_count).any(supports)
+    }
+}
+
+/// Queue created by the backend in each queue family the user didn't open, for
+/// presenting to surfaces which the queue families of the submissions can't present to.
+pub(crate) struct PresentQueue {
+    pub(crate) family: u32,
+    pub(crate) raw: Mutex<vk::Queue>,
+}
+
+pub(crate) struct PresentQueues {
+    pub(crate) physical_device: vk::PhysicalDevice,
+    pub(crate) queues: Vec<PresentQueue>,
+}
+
+/// Commands and semaphores moving a swapchain image from the submitting
+/// queue family to a present queue.
+#[derive(Clone, Copy)]
+pub(crate) struct PresentTransfer {
+    /// Index into the present queues.
+    pub(crate) present_queue: usize,
+    pub(crate) release: vk::CommandBuffer,
+    pub(crate) released: vk::Semaphore,
+    pub(crate) acquire: vk::CommandBuffer,
+    pub(crate) acquired: vk::Semaphore,
+}
+
+/// Queue family ownership transfer of all the images of a swapchain,
+/// recorded once per submitting queue family.
+struct OwnershipTransfer {
+    present_queue: usize,
+    // Command pools of the submitting and the presenting queue families.
+    pools: [vk::CommandPool; 2],
+    release: Vec<vk::CommandBuffer>,
+    acquire: Vec<vk::CommandBuffer>,
+    released: Vec<vk::Semaphore>,
+    acquired: Vec<vk::Semaphore>,
+}
+
+impl OwnershipTransfer {
+    fn new(device: &RawDevice, images: &[vk::Image], src: u32, dst: u32, present_queue: usize) -> Self {
+        let release_pool = create_transient_pool(device, src);
+        let acquire_pool = create_transient_pool(device, dst);
+        let create_semaphores = || {
+            images
+                .iter()
+                .map(|_| {
+                    let info = vk::SemaphoreCreateInfo {
+                        s_type: vk::StructureType::SemaphoreCreateInfo,
+                        p_next: ptr::null(),
+                        flags: vk::SemaphoreCreateFlags::empty(),
+                    };
+                    unsafe { device.0.create_semaphore(&info, None) }
+                        .expect("Error on semaphore creation")
+                })
+                .collect()
+        };
+
+        OwnershipTransfer {
+            present_queue,
+            pools: [release_pool, acquire_pool],
+            release: record_ownership_transfer(device, release_pool, images, src, dst),
+            acquire: record_ownership_transfer(device, acquire_pool, images, src, dst),
+            released: create_semaphores(),
+            acquired: create_semaphores(),
+        }
+    }
+
+    fn destroy(self, device: &RawDevice) {
+        unsafe {
+            for pool in &self.pools {
+                device.0.destroy_command_pool(*pool, None);
+            }
+            for &semaphore in self.released.iter().chain(&self.acquired) {
+                device.0.destroy_semaphore(semaphore, None);
+            }
+        }
     }
 }
 
+fn create_transient_pool(device: &RawDevice, family: u32) -> vk::CommandPool {
+    let info = vk::CommandPoolCreateInfo {
+        s_type: vk::StructureType::CommandPoolCreateInfo,
+        p_next: ptr::null(),
+        flags: vk::CommandPoolCreateFlags::empty(),
+        queue_family_index: family,
+    };
+    unsafe { device.0.create_command_pool(&info, None) }
+        .expect("Error on command pool creation")
+}
+
+/// Record the release or the acquire (depending on the family of `pool`) of the
+/// images by the `dst` queue family, one command buffer per image.
+fn record_ownership_transfer(
+    device: &RawDevice,
+    pool: vk::CommandPool,
+    images: &[vk::Image],
+    src: u32,
+    dst: u32,
+) -> Vec<vk::CommandBuffer> {
+    let info = vk::CommandBufferAllocateInfo {
+        s_type: vk::StructureType::CommandBufferAllocateInfo,
+        p_next: ptr::null(),
+        command_pool: pool,
+        level: vk::CommandBufferLevel::Primary,
+        command_buffer_count: images.len() as u32,
+    };
+    let buffers = unsafe { device.0.allocate_command_buffers(&info) }
+        .expect("Error on command buffer allocation");
+
+    let range = SubresourceRange {
+        aspects: Aspects::COLOR,
+        levels: 0 .. 1,
+        layers: 0 .. 1,
+    };
+    for (&buffer, &image) in buffers.iter().zip(images) {
+        let begin_info = vk::CommandBufferBeginInfo {
+            s_type: vk::StructureType::CommandBufferBeginInfo,
+            p_next: ptr::null(),
+            flags: conv::map_command_buffer_flags(CommandBufferFlags::SIMULTANEOUS_USE),
+            p_inheritance_info: ptr::null(),
+        };
+        // The layout is kept, the submissions already transitioned the image for presentation.
+        let barrier = vk::ImageMemoryBarrier {
+            s_type: vk::StructureType::ImageMemoryBarrier,
+            p_next: ptr::null(),
+            src_access_mask: vk::AccessFlags::empty(),
+            dst_access_mask: vk::AccessFlags::empty(),
+            old_layout: conv::map_image_layout(Layout::Present),
+            new_layout: conv::map_image_layout(Layout::Present),
+            src_queue_family_index: src,
+            dst_queue_family_index: dst,
+            image,
+            subresource_range: conv::map_subresource_range(&range),
+        };
+        unsafe {
+            assert_eq!(Ok(()), device.0.begin_command_buffer(buffer, &begin_info));
+            device.0.cmd_pipeline_barrier(
+                buffer,
+                conv::map_pipeline_stage(PipelineStage::TOP_OF_PIPE),
+                conv::map_pipeline_stage(PipelineStage::BOTTOM_OF_PIPE),
+                vk::DependencyFlags::empty(),
+                &[],
+                &[],
+                &[barrier],
+            );
+            assert_eq!(Ok(()), device.0.end_command_buffer(buffer));
+        }
+    }
+
+    buffers
+}
+
 pub struct Swapchain {
     pub(crate) raw: vk::SwapchainKHR,
     pub(crate) functor: ext::Swapchain,
+    // Raw entry points, `functor` drops the image index of suboptimal acquisitions.
+    pub(crate) swapchain_fn: vk::SwapchainFn,
+    pub(crate) device: Arc<RawDevice>,
+    pub(crate) surface: Arc<RawSurface>,
+    pub(crate) images: Vec<vk::Image>,
+    physical_device: vk::PhysicalDevice,
+    extent: hal::window::Extent2D,
+    pre_transform: hal::window::SurfaceTransform,
+    // Ownership transfers keyed by submitting queue family,
+    // `None` for the families which can present directly.
+    transfers: Mutex<FastHashMap<u32, Option<OwnershipTransfer>>>,
+}
+
+impl Swapchain {
+    pub(crate) fn new(
+        raw: vk::SwapchainKHR,
+        functor: ext::Swapchain,
+        swapchain_fn: vk::SwapchainFn,
+        device: Arc<RawDevice>,
+        surface: Arc<RawSurface>,
+        images: Vec<vk::Image>,
+        physical_device: vk::PhysicalDevice,
+        extent: hal::window::Extent2D,
+        pre_transform: hal::window::SurfaceTransform,
+    ) -> Self {
+        Swapchain {
+            raw,
+            functor,
+            swapchain_fn,
+            device,
+            surface,
+            images,
+            physical_device,
+            extent,
+            pre_transform,
+            transfers: Mutex::new(FastHashMap::default()),
+        }
+    }
+
+    /// Query the surface properties which don't match the swapchain anymore, `None` if
+    /// the surface state can't be queried or matches.
+    pub(crate) fn suboptimal(&self) -> Option<hal::window::Suboptimal> {
+        let caps = match self.surface.functor
+            .get_physical_device_surface_capabilities_khr(self.physical_device, self.surface.handle)
+        {
+            Ok(caps) => caps,
+            Err(_) => return None,
+        };
+
+        let extent = hal::window::Extent2D {
+            width: caps.current_extent.width,
+            height: caps.current_extent.height,
+        };
+        // `0xFFFFFFFF` indicates that the extent depends on the swapchain.
+        let extent = if extent.width != 0xFFFFFFFF && extent != self.extent { Some(extent) } else { None };
+        let transform = conv::map_vk_surface_transform(caps.current_transform);
+        let transform = if transform != self.pre_transform { Some(transform) } else { None };
+
+        if extent.is_some() || transform.is_some() {
+            Some(hal::window::Suboptimal { extent, transform })
+        } else {
+            None
+        }
+    }
+
+    /// Return the ownership transfer of image `index` needed to present it after
+    /// submissions to the queue `family`, if that family can't present to the surface.
+    pub(crate) fn present_transfer(
+        &self,
+        family: u32,
+        index: hal::SwapImageIndex,
+        present_queues: &PresentQueues,
+    ) -> Option<PresentTransfer> {
+        let mut transfers = self.transfers.lock().unwrap();
+        let transfer = transfers.entry(family).or_insert_with(|| {
+            let supports = |family| {
+                self.surface.functor.get_physical_device_surface_support_khr(
+                    present_queues.physical_device,
+                    family,
+                    self.surface.handle,
+                )
+            };
+            if supports(family) {
+                return None;
+            }
+
+            let present_queue = present_queues.queues
+                .iter()
+                .position(|queue| supports(queue.family))
+                .expect("No queue family not opened by the user can present to the surface");
+            let dst = present_queues.queues[present_queue].family;
+            Some(OwnershipTransfer::new(&self.device, &self.images, family, dst, present_queue))
+        });
+
+        transfer.as_ref().map(|transfer| {
+            let i = index as usize;
+            PresentTransfer {
+                present_queue: transfer.present_queue,
+                release: transfer.release[i],
+                released: transfer.released[i],
+                acquire: transfer.acquire[i],
+                acquired: transfer.acquired[i],
+            }
+        })
+    }
+
+    pub(crate) fn destroy(self) {
+        for (_, transfer) in self.transfers.into_inner().unwrap() {
+            if let Some(transfer) = transfer {
+                transfer.destroy(&self.device);
+            }
+        }
+        unsafe { self.functor.destroy_swapchain_khr(self.raw, None); }
+    }
 }
 
 
 impl hal::Swapchain<Backend> for Swapchain {
-    fn acquire_image(&mut self, sync: hal::FrameSync<Backend>) -> Result<hal::SwapImageIndex, ()> {
+    fn acquire_image(
+        &mut self, sync: hal::FrameSync<Backend>
+    ) -> Result<(hal::SwapImageIndex, Option<hal::window::Suboptimal>), hal::window::AcquireError> {
         let (semaphore, fence) = match sync {
             hal::FrameSync::Semaphore(semaphore) => (semaphore.0, vk::Fence::null()),
             hal::FrameSync::Fence(fence) => (vk::Semaphore::null(), fence.0),
         };
 
-        let index = unsafe {
+        let mut index = 0;
+        let result = unsafe {
             // will block if no image is available
-            self.functor.acquire_next_image_khr(self.raw, !0, semaphore, fence)
+            self.swapchain_fn.acquire_next_image_khr(
+                self.device.0.handle(),
+                self.raw,
+                !0,
+                semaphore,
+                fence,
+                &mut index,
+            )
         };
 
-        match index {
-            Ok(i) => Ok(i),
-            Err(vk::Result::SuboptimalKhr) | Err(vk::Result::ErrorOutOfDateKhr) => Err(()),
-            _ => panic!("Failed to acquire image."),
+        match result {
+            vk::Result::Success => Ok((index, None)),
+            vk::Result::SuboptimalKhr => {
+                let suboptimal = self.suboptimal().unwrap_or(hal::window::Suboptimal::UNKNOWN);
+                Ok((index, Some(suboptimal)))
+            }
+            vk::Result::ErrorOutOfDateKhr => Err(hal::window::AcquireError::OutOfDate),
+            vk::Result::ErrorSurfaceLostKhr => Err(hal::window::AcquireError::SurfaceLost),
+            vk::Result::ErrorDeviceLost => Err(hal::window::AcquireError::DeviceLost),
+            _ => panic!("Failed to acquire image: {:?}", result),
         }
     }
 }
@@ -10,7 +10,8 @@ use std::any::Any;
 
 use {format, image, memory, Backend, Gpu, Features, Limits};
 use error::DeviceCreationError;
-use queue::{Capability, QueueGroup};
+use queue::{Capability, Compute, General, QueueGroup, QueueType, Transfer};
+use window::PresentMode;
 
 /// Scheduling hint for devices about the priority of a queue.  Values range from `0.0` (low) to
 /// `1.0` (high).
@@ -49,6 +50,66 @@ pub struct MemoryProperties {
     pub memory_heaps: Vec<u64>,
 }
 
+impl MemoryProperties {
+    /// Find a memory type out of `type_mask`, which is device local and visible to the host
+    /// across the whole device local heap, as exposed by systems with resizable BAR or
+    /// unified memory (UMA).
+    ///
+    /// Resources in this fast-upload memory can be written by the host and read by the device
+    /// at full speed, so uploads can skip the staging copy. Small host visible windows into
+    /// device memory, like the 256MB BAR of discrete GPUs without resizable BAR, are not
+    /// considered as they are a scarce resource. Coherent memory types are preferred.
+    pub fn fast_upload_type(&self, type_mask: u64) -> Option<MemoryTypeId> {
+        use memory::Properties;
+
+        let largest_local_heap = self.memory_types
+            .iter()
+            .filter(|ty| ty.properties.contains(Properties::DEVICE_LOCAL))
+            .map(|ty| self.memory_heaps[ty.heap_index])
+            .max()?;
+        let candidates = self.memory_types
+            .iter()
+            .enumerate()
+            .filter(|&(id, ty)| {
+                type_mask & (1 << id) != 0 &&
+                ty.properties.contains(Properties::DEVICE_LOCAL | Properties::CPU_VISIBLE) &&
+                self.memory_heaps[ty.heap_index] >= largest_local_heap
+            });
+
+        candidates
+            .clone()
+            .find(|&(_, ty)| ty.properties.contains(Properties::COHERENT))
+            .or_else(|| candidates.clone().next())
+            .map(|(id, _)| MemoryTypeId(id))
+    }
+
+    /// Check if the device has fast-upload memory, see `fast_upload_type`.
+    pub fn has_fast_upload(&self) -> bool {
+        self.fast_upload_type(!0).is_some()
+    }
+
+    /// Find a memory type out of `type_mask` for resources written by the host every frame,
+    /// like staging and dynamic vertex or uniform buffers.
+    ///
+    /// Fast-upload memory is preferred, see `fast_upload_type`, otherwise any host visible
+    /// memory type is returned, coherent ones first.
+    pub fn upload_type(&self, type_mask: u64) -> Option<MemoryTypeId> {
+        use memory::Properties;
+
+        self.fast_upload_type(type_mask).or_else(|| {
+            let visible = |properties| {
+                self.memory_types
+                    .iter()
+                    .enumerate()
+                    .position(|(id, ty)| type_mask & (1 << id) != 0 && ty.properties.contains(properties))
+            };
+            visible(Properties::CPU_VISIBLE | Properties::COHERENT)
+                .or_else(|| visible(Properties::CPU_VISIBLE))
+                .map(MemoryTypeId)
+        })
+    }
+}
+
 /// Represents a physical device (such as a GPU) capable of supporting the given backend.
 pub trait PhysicalDevice<B: Backend>: Any + Send + Sync {
     /// Create a new logical device.
@@ -94,6 +155,27 @@ pub trait PhysicalDevice<B: Backend>: Any + Send + Sync {
 
     /// Returns the resource limits of this `Device`.
     fn limits(&self) -> Limits;
+
+    /// Returns the number of nanoseconds per tick of timestamp queries.
+    fn timestamp_period(&self) -> f32 {
+        1.0
+    }
+}
+
+/// Kind of the device behind an adapter.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum DeviceType {
+    /// Unknown or other kind of device.
+    Other,
+    /// GPU sharing the memory of the host, usually part of the CPU package.
+    IntegratedGpu,
+    /// Separate GPU with its own memory.
+    DiscreteGpu,
+    /// Virtual GPU, e.g. in a virtualized environment.
+    VirtualGpu,
+    /// Software rasterizer running on the CPU.
+    Cpu,
 }
 
 /// Metadata about a backend adapter.
@@ -108,6 +190,81 @@ pub struct AdapterInfo {
     pub device: usize,
     /// Whether or not the device is based on a software rasterizer
     pub software_rendering: bool,
+    /// Kind of the device, `Other` if the backend can't tell.
+    pub device_type: DeviceType,
+}
+
+/// Power consumption hint for selecting and opening adapters.
+///
+/// `LowPower` targets tool-style applications which mostly idle, e.g. editors embedding a
+/// viewport: it prefers integrated adapters, opens the queues at low priority and presents
+/// in sync with the display. The hint doesn't change the behavior of an opened device.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum PowerPreference {
+    /// No preference, adapters are kept in the order of enumeration.
+    Default,
+    /// Save power, e.g. for running on battery.
+    LowPower,
+    /// Prefer the fastest adapter and lowest latency.
+    HighPerformance,
+}
+
+impl Default for PowerPreference {
+    fn default() -> Self {
+        PowerPreference::Default
+    }
+}
+
+impl PowerPreference {
+    // Rank of a device type for this preference, lower is better.
+    fn rank(&self, device_type: DeviceType) -> usize {
+        let order: &[DeviceType] = match *self {
+            PowerPreference::Default => return 0,
+            PowerPreference::LowPower => &[
+                DeviceType::IntegratedGpu, DeviceType::DiscreteGpu, DeviceType::VirtualGpu, DeviceType::Other,
+            ],
+            PowerPreference::HighPerformance => &[
+                DeviceType::DiscreteGpu, DeviceType::IntegratedGpu, DeviceType::VirtualGpu, DeviceType::Other,
+            ],
+        };
+        order.iter().position(|&ty| ty == device_type).unwrap_or(order.len())
+    }
+
+    /// Priority of the queues opened with this preference.
+    pub fn queue_priority(&self) -> QueuePriority {
+        match *self {
+            PowerPreference::LowPower => 0.0,
+            PowerPreference::Default | PowerPreference::HighPerformance => 1.0,
+        }
+    }
+
+    /// Present modes favored by this preference, in order, to pass to
+    /// `SurfaceCapabilities::select_present_mode`.
+    ///
+    /// `LowPower` only presents in sync with the display, never rendering frames which
+    /// get discarded.
+    pub fn present_modes(&self) -> &'static [PresentMode] {
+        match *self {
+            PowerPreference::LowPower => &[PresentMode::Fifo],
+            PowerPreference::Default => &[PresentMode::Mailbox, PresentMode::Fifo],
+            PowerPreference::HighPerformance => &[PresentMode::Mailbox, PresentMode::Immediate, PresentMode::Fifo],
+        }
+    }
+}
+
+/// Queue groups opened by `Adapter::open_with_queues`.
+///
+/// The compute and transfer groups are only opened from families dedicated to them,
+/// e.g. asynchronous compute and DMA queues. If the adapter has no such family, the
+/// work has to go to the general queues instead.
+pub struct QueueGroups<B: Backend> {
+    /// Queues supporting all operations.
+    pub general: QueueGroup<B, General>,
+    /// Queues of a compute only family.
+    pub compute: Option<QueueGroup<B, Compute>>,
+    /// Queues of a transfer only family.
+    pub transfer: Option<QueueGroup<B, Transfer>>,
 }
 
 /// The list of `Adapter` instances is obtained by calling `Instance::enumerate_adapters()`.
@@ -125,6 +282,25 @@ pub struct Adapter<B: Backend> {
 }
 
 impl<B: Backend> Adapter<B> {
+    /// Pick the adapter best matching the power preference, e.g. from the adapters
+    /// returned by `Instance::enumerate_adapters`.
+    ///
+    /// Adapters of the same kind are kept in the order of enumeration, software
+    /// rasterizers are only picked if there is no other adapter.
+    pub fn select(adapters: Vec<Self>, power: PowerPreference) -> Option<Self> {
+        let rank = |adapter: &Self| {
+            let software = adapter.info.software_rendering || adapter.info.device_type == DeviceType::Cpu;
+            (software, power.rank(adapter.info.device_type))
+        };
+        let mut best: Option<Self> = None;
+        for adapter in adapters {
+            if best.as_ref().map_or(true, |best| rank(&adapter) < rank(best)) {
+                best = Some(adapter);
+            }
+        }
+        best
+    }
+
     /// Open the physical device with `count` queues from some active queue family. The family is
     /// the first that both provides the capability `C`, supports at least `count' queues, and for
     /// which `selector` returns true.
@@ -149,6 +325,18 @@ impl<B: Backend> Adapter<B> {
     pub fn open_with<F, C>(
         &mut self, count: usize, selector: F
     ) -> Result<(B::Device, QueueGroup<B, C>), DeviceCreationError>
+    where
+        F: Fn(&B::QueueFamily) -> bool,
+        C: Capability,
+    {
+        self.open_with_preference(count, PowerPreference::Default, selector)
+    }
+
+    /// Open the physical device like `open_with`, with the queue priority of the
+    /// power preference.
+    pub fn open_with_preference<F, C>(
+        &mut self, count: usize, power: PowerPreference, selector: F
+    ) -> Result<(B::Device, QueueGroup<B, C>), DeviceCreationError>
     where
         F: Fn(&B::QueueFamily) -> bool,
         C: Capability,
@@ -164,7 +352,7 @@ impl<B: Backend> Adapter<B> {
             })
             .next();
 
-        let priorities = vec![1.0; count];
+        let priorities = vec![power.queue_priority(); count];
         let (id, families) = match requested_family {
             Some(ref family) => (family.id(), [(family, priorities.as_slice())]),
             _ => return Err(DeviceCreationError::InitializationFailed),
@@ -173,4 +361,61 @@ impl<B: Backend> Adapter<B> {
         let Gpu { device, mut queues } = self.physical_device.open(&families)?;
         Ok((device, queues.take(id).unwrap()))
     }
+
+    /// Open the physical device with `general` queues from a general family, and up to
+    /// `compute` and `transfer` queues from families dedicated to these operations.
+    ///
+    /// The dedicated families are skipped when their count is zero. Resources shared
+    /// between the families need ownership transfers, see `memory::Barrier`.
+    ///
+    /// # Return
+    ///
+    /// Returns the same errors as `open` and `InitializationFailed` if there is no
+    /// general family with enough queues.
+    pub fn open_with_queues(
+        &mut self, general: usize, compute: usize, transfer: usize
+    ) -> Result<(B::Device, QueueGroups<B>), DeviceCreationError> {
+        use queue::QueueFamily;
+
+        let find = |families: &[B::QueueFamily], ty: QueueType, count: usize| {
+            families
+                .iter()
+                .position(|family| family.queue_type() == ty && family.max_queues() > 0)
+                .map(|index| (index, count.min(families[index].max_queues())))
+        };
+        let general_family = self.queue_families
+            .iter()
+            .position(|family| family.queue_type() == QueueType::General && general <= family.max_queues());
+        let general_family = match general_family {
+            Some(index) if general > 0 => (index, general),
+            _ => return Err(DeviceCreationError::InitializationFailed),
+        };
+        let compute_family = if compute > 0 { find(&self.queue_families, QueueType::Compute, compute) } else { None };
+        let transfer_family = if transfer > 0 { find(&self.queue_families, QueueType::Transfer, transfer) } else { None };
+
+        let priorities = vec![1.0; general.max(compute).max(transfer)];
+        let requested = Some(general_family)
+            .into_iter()
+            .chain(compute_family)
+            .chain(transfer_family)
+            .collect::<Vec<_>>();
+        let ids = requested
+            .iter()
+            .map(|&(index, _)| self.queue_families[index].id())
+            .collect::<Vec<_>>();
+        let Gpu { device, mut queues } = {
+            let families = requested
+                .iter()
+                .map(|&(index, count)| (&self.queue_families[index], &priorities[.. count]))
+                .collect::<Vec<_>>();
+            self.physical_device.open(&families)?
+        };
+        self.queue_families.clear();
+
+        let mut ids = ids.into_iter();
+        let general = queues.take(ids.next().unwrap()).unwrap();
+        let compute = compute_family.map(|_| queues.take(ids.next().unwrap()).unwrap());
+        let transfer = transfer_family.map(|_| queues.take(ids.next().unwrap()).unwrap());
+        Ok((device, QueueGroups { general, compute, transfer }))
+    }
 }
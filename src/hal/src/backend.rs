@@ -1,10 +1,12 @@
 //! Functionality only required for backend implementations.
 
 use Backend;
+use profiler::FrameStatistics;
 use queue::{QueueFamily, Queues};
 
 use std::collections::HashMap;
 use std::hash::BuildHasherDefault;
+use std::sync::Mutex;
 
 use fxhash::FxHasher;
 
@@ -48,3 +50,23 @@ impl<B: Backend> Queues<B> {
 
 /// Fast hash map used internally.
 pub type FastHashMap<K, V> = HashMap<K, V, BuildHasherDefault<FxHasher>>;
+
+/// Device-wide command statistics, accumulated by the queues on submission
+/// and retrieved by `Device::frame_statistics`.
+#[derive(Debug, Default)]
+pub struct StatisticsCollector(Mutex<FrameStatistics>);
+
+impl StatisticsCollector {
+    /// Accumulate the statistics of a submitted command buffer or a device operation.
+    pub fn add(&self, statistics: &FrameStatistics) {
+        self.0.lock().unwrap().add(statistics);
+    }
+
+    /// Return the statistics accumulated so far and reset them.
+    pub fn take(&self) -> FrameStatistics {
+        let mut statistics = self.0.lock().unwrap();
+        let current = *statistics;
+        *statistics = FrameStatistics::default();
+        current
+    }
+}
@@ -7,6 +7,18 @@ use buffer::Offset;
 use queue::capability::{Compute, Supports};
 use super::{CommandBuffer, DescriptorSetOffset, RawCommandBuffer, Shot, Level};
 
+/// Layout of the arguments read by `dispatch_indirect` from the indirect buffer.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default, Hash, PartialEq, Eq)]
+pub struct DispatchIndirectCommand {
+    /// Number of work groups in the X dimension.
+    pub x: u32,
+    /// Number of work groups in the Y dimension.
+    pub y: u32,
+    /// Number of work groups in the Z dimension.
+    pub z: u32,
+}
+
 impl<'a, B: Backend, C: Supports<Compute>, S: Shot, L: Level> CommandBuffer<'a, B, C, S, L> {
     /// Identical to the `RawCommandBuffer` method of the same name.
     pub fn bind_compute_pipeline(&mut self, pipeline: &B::ComputePipeline) {
@@ -34,6 +46,27 @@ impl<'a, B: Backend, C: Supports<Compute>, S: Shot, L: Level> CommandBuffer<'a,
         self.raw.dispatch(count)
     }
 
+    /// Identical to the `RawCommandBuffer` method of the same name.
+    pub fn dispatch_base(&mut self, base: WorkGroupCount, count: WorkGroupCount) {
+        self.raw.dispatch_base(base, count)
+    }
+
+    /// Emulation of `dispatch_base` for devices without `Features::DISPATCH_BASE`.
+    ///
+    /// Writes `base` as three consecutive `u32` compute push constants at `offset`
+    /// before dispatching `count` workgroups. The shader is expected to add these
+    /// to its workgroup id.
+    pub fn dispatch_with_base_constants(
+        &mut self,
+        layout: &B::PipelineLayout,
+        offset: u32,
+        base: WorkGroupCount,
+        count: WorkGroupCount,
+    ) {
+        self.raw.push_compute_constants(layout, offset, &base);
+        self.raw.dispatch(count)
+    }
+
     /// Identical to the `RawCommandBuffer` method of the same name.
     pub fn dispatch_indirect(&mut self, buffer: &B::Buffer, offset: Offset) {
         self.raw.dispatch_indirect(buffer, offset)
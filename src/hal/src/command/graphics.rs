@@ -8,7 +8,7 @@ use query::{Query, QueryControl, QueryId};
 use queue::capability::{Graphics, GraphicsOrCompute, Supports};
 use super::{
     CommandBuffer, RawCommandBuffer,
-    RenderPassInlineEncoder, RenderPassSecondaryEncoder,
+    RenderPassInlineEncoder, RenderPassSecondaryEncoder, RenderingEncoder, RenderingInfo,
     Shot, Level, Primary,
     ClearColorRaw, ClearDepthStencilRaw, ClearValueRaw, DescriptorSetOffset,
 };
@@ -320,6 +320,24 @@ impl<'a, B: Backend, C: Supports<Graphics>, S: Shot> CommandBuffer<'a, B, C, S,
         RenderPassInlineEncoder::new(self, render_pass, frame_buffer, render_area, clear_values)
     }
 
+    /// Identical to the `RawCommandBuffer` method of the same name.
+    pub fn begin_render_pass_inline_imageless<T, I>(
+        &mut self,
+        render_pass: &B::RenderPass,
+        frame_buffer: &B::Framebuffer,
+        attachments: I,
+        render_area: pso::Rect,
+        clear_values: T,
+    ) -> RenderPassInlineEncoder<B, Primary>
+    where
+        T: IntoIterator,
+        T::Item: Borrow<ClearValue>,
+        I: IntoIterator,
+        I::Item: Borrow<B::ImageView>,
+    {
+        RenderPassInlineEncoder::new_imageless(self, render_pass, frame_buffer, attachments, render_area, clear_values)
+    }
+
     /// Creates a new secondary render pass.
     pub fn begin_render_pass_secondary<T>(
         &mut self,
@@ -334,6 +352,11 @@ impl<'a, B: Backend, C: Supports<Graphics>, S: Shot> CommandBuffer<'a, B, C, S,
     {
         RenderPassSecondaryEncoder::new(self, render_pass, frame_buffer, render_area, clear_values)
     }
+
+    /// Identical to the `RawCommandBuffer` method of the same name.
+    pub fn begin_rendering(&mut self, info: &RenderingInfo<B>) -> RenderingEncoder<B, Primary> {
+        RenderingEncoder::new(self, info)
+    }
 }
 
 impl<'a, B: Backend, C: Supports<GraphicsOrCompute>, S: Shot, L: Level> CommandBuffer<'a, B, C, S, L> {
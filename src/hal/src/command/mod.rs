@@ -15,6 +15,7 @@
 
 use Backend;
 use queue::capability::Supports;
+use profiler::CommandBufferStatistics;
 use std::marker::PhantomData;
 
 mod compute;
@@ -23,6 +24,7 @@ mod raw;
 mod render_pass;
 mod transfer;
 
+pub use self::compute::*;
 pub use self::graphics::*;
 pub use self::raw::{
     ClearValueRaw, ClearColorRaw, ClearDepthStencilRaw, DescriptorSetOffset,
@@ -119,6 +121,11 @@ impl<'a, B: Backend, C, S: Shot, L: Level> CommandBuffer<'a, B, C, S, L> {
         self.raw
     }
 
+    /// Statistics of the commands recorded so far.
+    pub fn statistics(&self) -> CommandBufferStatistics {
+        self.raw.statistics()
+    }
+
     /// Finish recording commands to the command buffers.
     ///
     /// The command buffer will be consumed and can't be modified further.
@@ -6,11 +6,12 @@ use {buffer, pass, pso};
 use {Backend, DrawCount, IndexCount, InstanceCount, VertexCount, VertexOffset, WorkGroupCount};
 use image::{Filter, Layout, SubresourceRange};
 use memory::{Barrier, Dependencies};
+use profiler::CommandBufferStatistics;
 use query::{PipelineStatistic, Query, QueryControl, QueryId};
 use range::RangeArg;
 use super::{
     AttachmentClear, BufferCopy, BufferImageCopy,
-    ImageBlit, ImageCopy, ImageResolve, SubpassContents,
+    ImageBlit, ImageCopy, ImageResolve, RenderingInfo, SubpassContents,
 };
 
 /// Unsafe variant of `ClearColor`.
@@ -132,6 +133,80 @@ pub trait RawCommandBuffer<B: Backend>: Clone + Any + Send + Sync {
         T: IntoIterator,
         T::Item: Borrow<Barrier<'a, B>>;
 
+    /// Begins a split barrier, allowing the transitions described by `barriers`
+    /// to overlap with the commands recorded until the matching `end_split_barrier`.
+    ///
+    /// The same `stages` and `barriers` have to be passed to `end_split_barrier`,
+    /// and the affected resources must not be accessed in between.
+    /// Backends without native split barriers perform the whole barrier on
+    /// `end_split_barrier` instead.
+    fn begin_split_barrier<'a, T>(
+        &mut self,
+        stages: Range<pso::PipelineStage>,
+        barriers: T,
+    ) where
+        T: IntoIterator,
+        T::Item: Borrow<Barrier<'a, B>>,
+    {
+        let _ = (stages, barriers);
+    }
+
+    /// Ends a split barrier previously started with `begin_split_barrier`.
+    fn end_split_barrier<'a, T>(
+        &mut self,
+        stages: Range<pso::PipelineStage>,
+        dependencies: Dependencies,
+        barriers: T,
+    ) where
+        T: IntoIterator,
+        T::Item: Borrow<Barrier<'a, B>>,
+    {
+        self.pipeline_barrier(stages, dependencies, barriers)
+    }
+
+    /// Signals an event once the commands recorded before reach `stages.start`.
+    ///
+    /// The same `stages` and `barriers` have to be passed to `wait_events`, the
+    /// commands recorded in between may overlap with the transitions. Events are
+    /// mapped onto split barriers by backends without native events, the wait has
+    /// to be recorded into the same command buffer then.
+    fn set_event<'a, T>(
+        &mut self,
+        event: &B::Event,
+        stages: Range<pso::PipelineStage>,
+        barriers: T,
+    ) where
+        T: IntoIterator,
+        T::Item: Borrow<Barrier<'a, B>>,
+    {
+        let _ = event;
+        self.begin_split_barrier(stages, barriers)
+    }
+
+    /// Unsignals an event once the commands recorded before reach `stages`.
+    fn reset_event(&mut self, event: &B::Event, stages: pso::PipelineStage) {
+        let _ = (event, stages);
+    }
+
+    /// Waits for events to be signaled before executing `stages.end` of the
+    /// commands recorded after, and performs the barriers.
+    ///
+    /// The barriers have to include all the barriers passed to `set_event` of the events.
+    fn wait_events<'a, I, T>(
+        &mut self,
+        events: I,
+        stages: Range<pso::PipelineStage>,
+        barriers: T,
+    ) where
+        I: IntoIterator,
+        I::Item: Borrow<B::Event>,
+        T: IntoIterator,
+        T::Item: Borrow<Barrier<'a, B>>,
+    {
+        let _ = events;
+        self.end_split_barrier(stages, Dependencies::empty(), barriers)
+    }
+
     /// Fill a buffer with the given `u32` value.
     fn fill_buffer<R>(
         &mut self,
@@ -172,6 +247,13 @@ pub trait RawCommandBuffer<B: Backend>: Clone + Any + Send + Sync {
 
     /// "Resolves" a multisampled image, converting it into a non-multisampled
     /// image. Takes an iterator of regions to apply the resolution to.
+    ///
+    /// The source is accessed with `image::Access::TRANSFER_READ` in `TransferSrcOptimal`
+    /// (or `General`) layout, the destination with `image::Access::TRANSFER_WRITE` in
+    /// `TransferDstOptimal` (or `General`) layout, and both in the `TRANSFER` pipeline stage.
+    /// Resolves at the end of a subpass are described with `SubpassDesc::resolves` instead.
+    ///
+    /// D3D11 only supports resolving whole subresources, offsets and extents are ignored.
     fn resolve_image<T>(
         &mut self,
         src: &B::Image,
@@ -199,13 +281,17 @@ pub trait RawCommandBuffer<B: Backend>: Clone + Any + Send + Sync {
 
     /// Bind the index buffer view, making it the "current" one that draw commands
     /// will operate on.
+    ///
+    /// Indices are read starting at the byte `offset` of the view, which allows
+    /// index data of multiple meshes to share one buffer.
     fn bind_index_buffer(&mut self, buffer::IndexBufferView<B>);
 
     /// Bind the vertex buffer set, making it the "current" one that draw commands
     /// will operate on.
     ///
     /// Each buffer passed corresponds to the vertex input binding with the same index,
-    /// starting from an offset index `first_binding`.
+    /// starting from an offset index `first_binding`. The vertex data of a binding
+    /// starts at the byte offset given along with its buffer.
     fn bind_vertex_buffers<I, T>(&mut self, first_binding: u32, buffers: I)
     where
         I: IntoIterator<Item = (T, buffer::Offset)>,
@@ -296,12 +382,48 @@ pub trait RawCommandBuffer<B: Backend>: Clone + Any + Send + Sync {
         T: IntoIterator,
         T::Item: Borrow<ClearValueRaw>;
 
+    /// Begins recording commands for a render pass on an imageless framebuffer,
+    /// created with `create_imageless_framebuffer`.
+    ///
+    /// `attachments` provides the image views, in the order of the render pass
+    /// attachments. They must match the descriptions the framebuffer was
+    /// created with. Otherwise identical to `begin_render_pass`.
+    fn begin_render_pass_imageless<T, I>(
+        &mut self,
+        render_pass: &B::RenderPass,
+        framebuffer: &B::Framebuffer,
+        attachments: I,
+        render_area: pso::Rect,
+        clear_values: T,
+        first_subpass: SubpassContents,
+    ) where
+        T: IntoIterator,
+        T::Item: Borrow<ClearValueRaw>,
+        I: IntoIterator,
+        I::Item: Borrow<B::ImageView>;
+
     /// Steps to the next subpass in the current render pass.
     fn next_subpass(&mut self, contents: SubpassContents);
 
     /// Finishes recording commands for the current a render pass.
     fn end_render_pass(&mut self);
 
+    /// Begins a single-subpass render pass targeting the attachments in `info`
+    /// directly, without a `RenderPass` or `Framebuffer` object.
+    ///
+    /// Requires `Features::DYNAMIC_RENDERING`. No layout transitions are
+    /// performed: the attachments must already be in the layouts given in
+    /// `info`. Pipelines bound inside are still created against a render pass
+    /// with compatible attachment formats.
+    ///
+    /// Backends without native support emulate it with a single-subpass render
+    /// pass and framebuffer built from the attachments, which some of them create
+    /// again on every call. Backends not reporting the feature panic.
+    fn begin_rendering(&mut self, info: &RenderingInfo<B>);
+
+    /// Finishes recording commands for the rendering started by `begin_rendering`.
+    fn end_rendering(&mut self);
+
     /// Bind a graphics pipeline.
     ///
     /// # Errors
@@ -370,8 +492,17 @@ pub trait RawCommandBuffer<B: Backend>: Clone + Any + Send + Sync {
     /// TODO:
     fn dispatch(&mut self, count: WorkGroupCount);
 
+    /// Works similarly to `dispatch()` but offsets the workgroup ids
+    /// of the dispatched workgroups by `base`.
+    ///
+    /// A non-zero `base` requires `Features::DISPATCH_BASE`. Without it, the dispatch
+    /// is skipped, panicking in debug builds, and the offset can be passed through
+    /// push constants instead, see `CommandBuffer::dispatch_with_base_constants`.
+    fn dispatch_base(&mut self, base: WorkGroupCount, count: WorkGroupCount);
+
     /// Works similarly to `dispatch()` but reads parameters from the given
-    /// buffer during execution.
+    /// buffer during execution. The parameters at `offset` are laid out
+    /// as a `DispatchIndirectCommand`.
     fn dispatch_indirect(&mut self, buffer: &B::Buffer, offset: buffer::Offset);
 
     /// Adds a command to copy regions from the source to destination buffer.
@@ -450,10 +581,10 @@ pub trait RawCommandBuffer<B: Backend>: Clone + Any + Send + Sync {
     /// bytes with each successive draw.  Performs `draw_count` draws total.
     /// `draw_count` may be zero.
     ///
-    /// Each draw command in the buffer is a series of 4 `u32` values specifying,
-    /// in order, the number of vertices to draw, the number of instances to draw,
-    /// the index of the first vertex to draw, and the instance ID of the first
-    /// instance to draw.
+    /// Each draw command in the buffer is laid out as a `DrawIndirectCommand`,
+    /// a series of 4 `u32` values specifying, in order, the number of vertices
+    /// to draw, the number of instances to draw, the index of the first vertex
+    /// to draw, and the instance ID of the first instance to draw.
     fn draw_indirect(
         &mut self,
         buffer: &B::Buffer,
@@ -465,10 +596,10 @@ pub trait RawCommandBuffer<B: Backend>: Clone + Any + Send + Sync {
     /// Like `draw_indirect()`, this does indexed drawing a la `draw_indexed()` but
     /// reads the draw parameters out of the given buffer.
     ///
-    /// Each draw command in the buffer is a series of 5 values specifying,
-    /// in order, the number of indices, the number of instances, the first index,
-    /// the vertex offset, and the first instance.  All are `u32`'s except
-    /// the vertex offset, which is an `i32`.
+    /// Each draw command in the buffer is laid out as a `DrawIndexedIndirectCommand`,
+    /// a series of 5 values specifying, in order, the number of indices, the number
+    /// of instances, the first index, the vertex offset, and the first instance.
+    /// All are `u32`'s except the vertex offset, which is an `i32`.
     fn draw_indexed_indirect(
         &mut self,
         buffer: &B::Buffer,
@@ -519,4 +650,12 @@ pub trait RawCommandBuffer<B: Backend>: Clone + Any + Send + Sync {
     ) where
         I: IntoIterator,
         I::Item: Borrow<B::CommandBuffer>;
+
+    /// Statistics of the commands recorded since the command buffer was last begun or reset.
+    ///
+    /// Only collected by the Vulkan and software backends, the default implementation
+    /// used by the other backends returns `CommandBufferStatistics::default()`.
+    fn statistics(&self) -> CommandBufferStatistics {
+        CommandBufferStatistics::default()
+    }
 }
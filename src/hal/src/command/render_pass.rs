@@ -2,7 +2,8 @@ use std::borrow::Borrow;
 use std::ops::{Range, Deref, DerefMut};
 use std::marker::PhantomData;
 
-use {buffer, pso};
+use {buffer, image, pass, pso};
+use query::{Query, QueryControl};
 use {Backend, DrawCount, IndexCount, InstanceCount, VertexCount, VertexOffset};
 use queue::{Supports, Graphics};
 use super::{
@@ -21,6 +22,36 @@ pub enum SubpassContents {
     SecondaryBuffers,
 }
 
+/// Layout of the arguments read by `draw_indirect` from the indirect buffer.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default, Hash, PartialEq, Eq)]
+pub struct DrawIndirectCommand {
+    /// Number of vertices to draw.
+    pub vertex_count: VertexCount,
+    /// Number of instances to draw.
+    pub instance_count: InstanceCount,
+    /// Index of the first vertex.
+    pub first_vertex: VertexCount,
+    /// Instance ID of the first instance.
+    pub first_instance: InstanceCount,
+}
+
+/// Layout of the arguments read by `draw_indexed_indirect` from the indirect buffer.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default, Hash, PartialEq, Eq)]
+pub struct DrawIndexedIndirectCommand {
+    /// Number of indices to draw.
+    pub index_count: IndexCount,
+    /// Number of instances to draw.
+    pub instance_count: InstanceCount,
+    /// Index of the first index in the bound index buffer.
+    pub first_index: IndexCount,
+    /// Value added to each index before fetching the vertex.
+    pub vertex_offset: VertexOffset,
+    /// Instance ID of the first instance.
+    pub first_instance: InstanceCount,
+}
+
 /// This struct contains all methods for all commands submittable during a subpass.
 /// It is used to implement the identical portions of RenderPassInlineEncoder and SubpassCommandBuffer.
 ///
@@ -152,8 +183,22 @@ impl<'a, B: Backend> RenderSubpassCommon<'a, B> {
         self.0.set_depth_bias(depth_bias);
     }
 
+    ///
+    pub fn begin_query(&mut self, query: Query<B>, flags: QueryControl) {
+        self.0.begin_query(query, flags)
+    }
+
+    ///
+    pub fn end_query(&mut self, query: Query<B>) {
+        self.0.end_query(query)
+    }
+
+    ///
+    pub fn write_timestamp(&mut self, stage: pso::PipelineStage, query: Query<B>) {
+        self.0.write_timestamp(stage, query)
+    }
+
     // TODO: pipeline barrier (postponed)
-    // TODO: begin/end query
 }
 
 /// An object that records commands into a command buffer inline, that is,
@@ -194,6 +239,42 @@ impl<'a, B: Backend, L: Level> RenderPassInlineEncoder<'a, B, L> {
         )
     }
 
+    /// Creates a new `RenderPassInlineEncoder`, starting a new render
+    /// pass on an imageless framebuffer with the given attachments.
+    pub fn new_imageless<C, T, I, S: Shot>(
+        cmd_buffer: &'a mut CommandBuffer<B, C, S, L>,
+        render_pass: &B::RenderPass,
+        frame_buffer: &B::Framebuffer,
+        attachments: I,
+        render_area: pso::Rect,
+        clear_values: T,
+    ) -> Self
+    where
+        C: Supports<Graphics>,
+        T: IntoIterator,
+        T::Item: Borrow<ClearValue>,
+        I: IntoIterator,
+        I::Item: Borrow<B::ImageView>,
+    {
+        let clear_values = clear_values
+            .into_iter()
+            .map(|cv| ClearValueRaw::from(*cv.borrow()));
+
+        cmd_buffer.raw.begin_render_pass_imageless(
+            render_pass,
+            frame_buffer,
+            attachments,
+            render_area,
+            clear_values,
+            SubpassContents::Inline,
+        );
+
+        RenderPassInlineEncoder(
+            Some(RenderSubpassCommon(cmd_buffer.raw)),
+            PhantomData,
+        )
+    }
+
     /// Start the next subpass.
     pub fn next_subpass_inline(mut self) -> Self {
         self.0.as_mut().unwrap().0.next_subpass(SubpassContents::Inline);
@@ -232,6 +313,70 @@ impl<'a, B: Backend, L: Level> Drop for RenderPassInlineEncoder<'a, B, L> {
     }
 }
 
+/// An attachment bound by `begin_rendering`.
+pub struct RenderingAttachment<'a, B: Backend> {
+    /// View of the attached image.
+    pub view: &'a B::ImageView,
+    /// Layout of the image for the duration of the rendering.
+    pub layout: image::Layout,
+    /// Load and store operations of the attachment.
+    pub ops: pass::AttachmentOps,
+    /// Load and store operations of the stencil aspect, if any.
+    pub stencil_ops: pass::AttachmentOps,
+    /// Clear value, used if either load operation is `Clear`.
+    pub clear_value: ClearValueRaw,
+}
+
+/// Describes the attachments of a framebuffer-less render pass,
+/// started with `begin_rendering`.
+pub struct RenderingInfo<'a, B: Backend> {
+    /// Area of the attachments affected by the rendering.
+    pub render_area: pso::Rect,
+    /// Color attachments, in the order of the fragment shader outputs.
+    pub colors: &'a [RenderingAttachment<'a, B>],
+    /// Depth-stencil attachment, if any.
+    pub depth_stencil: Option<RenderingAttachment<'a, B>>,
+}
+
+/// An object that records commands inline into a command buffer between
+/// `begin_rendering` and `end_rendering`.
+pub struct RenderingEncoder<'a, B: Backend, L: Level>(pub(crate) RenderSubpassCommon<'a, B>, PhantomData<L>)
+where B::CommandBuffer: 'a;
+
+impl<'a, B: Backend, L: Level> RenderingEncoder<'a, B, L> {
+    /// Creates a new `RenderingEncoder`, starting rendering to the attachments
+    /// of `info` in the given `CommandBuffer`.
+    pub fn new<C, S: Shot>(
+        cmd_buffer: &'a mut CommandBuffer<B, C, S, L>,
+        info: &RenderingInfo<B>,
+    ) -> Self
+    where
+        C: Supports<Graphics>,
+    {
+        cmd_buffer.raw.begin_rendering(info);
+        RenderingEncoder(RenderSubpassCommon(cmd_buffer.raw), PhantomData)
+    }
+}
+
+impl<'a, B: Backend, L: Level> Deref for RenderingEncoder<'a, B, L> {
+    type Target = RenderSubpassCommon<'a, B>;
+    fn deref(&self) -> &RenderSubpassCommon<'a, B> {
+        &self.0
+    }
+}
+
+impl<'a, B: Backend, L: Level> DerefMut for RenderingEncoder<'a, B, L> {
+    fn deref_mut(&mut self) -> &mut RenderSubpassCommon<'a, B> {
+        &mut self.0
+    }
+}
+
+impl<'a, B: Backend, L: Level> Drop for RenderingEncoder<'a, B, L> {
+    fn drop(&mut self) {
+        (self.0).0.end_rendering();
+    }
+}
+
 /// An object that records commands into a command buffer where each command must
 /// be a call to execute a secondary command buffer.
 pub struct RenderPassSecondaryEncoder<'a, B: Backend>(pub(crate) Option<&'a mut B::CommandBuffer>)
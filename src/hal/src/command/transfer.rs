@@ -74,6 +74,64 @@ impl<'a, B: Backend, C: Supports<Transfer>, S: Shot, L: Level> CommandBuffer<'a,
         self.raw.pipeline_barrier(stages, dependencies, barriers)
     }
 
+    /// Identical to the `RawCommandBuffer` method of the same name.
+    pub fn begin_split_barrier<'i, T>(
+        &mut self,
+        stages: Range<PipelineStage>,
+        barriers: T,
+    ) where
+        T: IntoIterator,
+        T::Item: Borrow<Barrier<'i, B>>,
+    {
+        self.raw.begin_split_barrier(stages, barriers)
+    }
+
+    /// Identical to the `RawCommandBuffer` method of the same name.
+    pub fn end_split_barrier<'i, T>(
+        &mut self,
+        stages: Range<PipelineStage>,
+        dependencies: Dependencies,
+        barriers: T,
+    ) where
+        T: IntoIterator,
+        T::Item: Borrow<Barrier<'i, B>>,
+    {
+        self.raw.end_split_barrier(stages, dependencies, barriers)
+    }
+
+    /// Identical to the `RawCommandBuffer` method of the same name.
+    pub fn set_event<'i, T>(
+        &mut self,
+        event: &B::Event,
+        stages: Range<PipelineStage>,
+        barriers: T,
+    ) where
+        T: IntoIterator,
+        T::Item: Borrow<Barrier<'i, B>>,
+    {
+        self.raw.set_event(event, stages, barriers)
+    }
+
+    /// Identical to the `RawCommandBuffer` method of the same name.
+    pub fn reset_event(&mut self, event: &B::Event, stages: PipelineStage) {
+        self.raw.reset_event(event, stages)
+    }
+
+    /// Identical to the `RawCommandBuffer` method of the same name.
+    pub fn wait_events<'i, I, T>(
+        &mut self,
+        events: I,
+        stages: Range<PipelineStage>,
+        barriers: T,
+    ) where
+        I: IntoIterator,
+        I::Item: Borrow<B::Event>,
+        T: IntoIterator,
+        T::Item: Borrow<Barrier<'i, B>>,
+    {
+        self.raw.wait_events(events, stages, barriers)
+    }
+
 
     /// Identical to the `RawCommandBuffer` method of the same name.
     pub fn fill_buffer<R>(
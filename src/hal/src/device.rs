@@ -10,6 +10,28 @@
 //! (or CPU) that implements a backend, a `Device` is a
 //! handle to that physical device that has the requested capabilities
 //! and is used to actually do things.
+//!
+//! # Synchronization
+//!
+//! All `Device` methods take `&self` and are internally synchronized: resources can
+//! be created and destroyed from multiple threads at once, for example to load assets
+//! in parallel, without an application-side lock around the device. All backend
+//! resource types are `Send + Sync`.
+//!
+//! Objects which are modified by their operations are externally synchronized,
+//! which is expressed by taking them as `&mut`: command pools and the command buffers
+//! allocated from them, descriptor pools, queues and swapchains. A resource must not
+//! be destroyed while another thread is using it.
+//!
+//! Backend specifics:
+//!
+//! - Vulkan and D3D12 devices are free-threaded.
+//! - D3D11 devices are free-threaded, the immediate context shared by the device
+//!   and its queues is locked internally.
+//! - Metal serializes access to the device and the queue with internal locks.
+//! - OpenGL contexts are bound to a single thread. The objects are `Send + Sync` to
+//!   satisfy the trait bounds, but using the device from another thread than the
+//!   one it was created on panics.
 
 use std::{fmt, mem, slice};
 use std::any::Any;
@@ -18,13 +40,15 @@ use std::error::Error;
 use std::ops::Range;
 
 use {buffer, format, image, mapping, pass, pso, query, window};
-use {Backend, MemoryTypeId};
+use {Backend, Features, MemoryTypeId};
 
 use error::HostExecutionError;
 use memory::Requirements;
 use pool::{CommandPool, CommandPoolCreateFlags};
+use profiler::FrameStatistics;
 use queue::{QueueFamilyId, QueueGroup};
 use range::RangeArg;
+use sparse::SparseImageRequirements;
 use window::{Backbuffer, SwapchainConfig};
 
 
@@ -91,6 +115,25 @@ pub enum ShaderError {
     InterfaceMismatch(String),
     /// Shader stage is not supported.
     UnsupportedStage(pso::Stage),
+    /// The shader requires device features which are not supported.
+    MissingFeatures(Features),
+}
+
+/// Error creating a multiview render pass with views the device can't render,
+/// because it doesn't support `Features::MULTIVIEW`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct MultiviewError;
+
+impl fmt::Display for MultiviewError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.description())
+    }
+}
+
+impl Error for MultiviewError {
+    fn description(&self) -> &str {
+        "Multiview render passes are not supported by the device"
+    }
 }
 
 /// An error from creating a framebuffer.
@@ -180,6 +223,39 @@ pub trait Device<B: Backend>: Any + Send + Sync {
         ID: IntoIterator,
         ID::Item: Borrow<pass::SubpassDependency>;
 
+    /// Creates a multiview render pass, rendering each subpass into multiple layers
+    /// of its attachments at once, e.g. both eyes of a stereo renderer.
+    ///
+    /// Requires `Features::MULTIVIEW`. `view_masks` contains the non-zero view mask of each subpass.
+    /// `correlation_masks` are sets of views which are likely to be rendered with similar
+    /// contents, and may be used by the implementation to render them more efficiently.
+    /// Framebuffers of the render pass must have a single layer.
+    ///
+    /// The default implementation, used by backends without multiview support, only
+    /// accepts view masks rendering the first view, creating a regular render pass.
+    /// Other masks fail with `MultiviewError` instead of dropping views.
+    fn create_render_pass_multiview<'a, IA, IS, ID>(
+        &self,
+        attachments: IA,
+        subpasses: IS,
+        dependencies: ID,
+        view_masks: &[pass::ViewMask],
+        _correlation_masks: &[pass::ViewMask],
+    ) -> Result<B::RenderPass, MultiviewError>
+    where
+        IA: IntoIterator,
+        IA::Item: Borrow<pass::Attachment>,
+        IS: IntoIterator,
+        IS::Item: Borrow<pass::SubpassDesc<'a>>,
+        ID: IntoIterator,
+        ID::Item: Borrow<pass::SubpassDependency>,
+    {
+        if view_masks.iter().any(|&mask| mask != 1) {
+            return Err(MultiviewError);
+        }
+        Ok(self.create_render_pass(attachments, subpasses, dependencies))
+    }
+
     /// Destroys a `RenderPass`.
     fn destroy_render_pass(&self, rp: B::RenderPass);
 
@@ -205,6 +281,28 @@ pub trait Device<B: Backend>: Any + Send + Sync {
     ///
     fn destroy_pipeline_layout(&self, layout: B::PipelineLayout);
 
+    /// Create a pipeline cache, optionally seeded with data previously returned by
+    /// `get_pipeline_cache_data`.
+    ///
+    /// Data written by a different device or driver version is ignored, resulting in
+    /// an empty cache. Use `pso::deserialize_pipeline_cache` for loading data from disk.
+    fn create_pipeline_cache(&self, data: Option<&[u8]>) -> Result<B::PipelineCache, OutOfMemory>;
+
+    /// Retrieve the contents of a pipeline cache, for seeding a new cache
+    /// with `create_pipeline_cache` in a later run.
+    ///
+    /// Use `pso::serialize_pipeline_cache` for storing the data on disk.
+    fn get_pipeline_cache_data(&self, cache: &B::PipelineCache) -> Result<Vec<u8>, OutOfMemory>;
+
+    /// Merge the contents of the `sources` caches into `target`.
+    fn merge_pipeline_caches<I>(&self, target: &B::PipelineCache, sources: I) -> Result<(), OutOfMemory>
+    where
+        I: IntoIterator,
+        I::Item: Borrow<B::PipelineCache>;
+
+    /// Destroys a pipeline cache.
+    fn destroy_pipeline_cache(&self, cache: B::PipelineCache);
+
     /// Create a graphics pipeline.
     fn create_graphics_pipeline<'a>(
         &self,
@@ -227,6 +325,53 @@ pub trait Device<B: Backend>: Any + Send + Sync {
             .collect()
     }
 
+    /// Create a graphics pipeline, looking up and storing the compiled pipeline in `cache`.
+    fn create_graphics_pipeline_with_cache<'a>(
+        &self,
+        desc: &pso::GraphicsPipelineDesc<'a, B>,
+        cache: Option<&B::PipelineCache>,
+    ) -> Result<B::GraphicsPipeline, pso::CreationError> {
+        self.create_graphics_pipelines_with_cache(Some(desc), cache).remove(0)
+    }
+
+    /// Create graphics pipelines, looking up and storing the compiled pipelines in `cache`.
+    ///
+    /// The default implementation ignores the cache.
+    fn create_graphics_pipelines_with_cache<'a, I>(
+        &self,
+        descs: I,
+        _cache: Option<&B::PipelineCache>,
+    ) -> Vec<Result<B::GraphicsPipeline, pso::CreationError>>
+    where
+        I: IntoIterator,
+        I::Item: Borrow<pso::GraphicsPipelineDesc<'a, B>>,
+    {
+        self.create_graphics_pipelines(descs)
+    }
+
+    /// Create a graphics pipeline from a binary previously returned by
+    /// `get_graphics_pipeline_binary`, skipping the backend compilation where possible.
+    ///
+    /// If the binary is rejected, for example after a driver update, the pipeline is
+    /// compiled from `desc` instead. The default implementation ignores the binary.
+    fn create_graphics_pipeline_with_binary<'a>(
+        &self,
+        desc: &pso::GraphicsPipelineDesc<'a, B>,
+        _binary: &[u8],
+    ) -> Result<B::GraphicsPipeline, pso::CreationError> {
+        self.create_graphics_pipeline(desc)
+    }
+
+    /// Retrieve the backend binary of a compiled graphics pipeline, for importing with
+    /// `create_graphics_pipeline_with_binary` later on.
+    ///
+    /// Returns `None` if the backend or the driver doesn't expose pipeline binaries,
+    /// see `Features::PIPELINE_BINARY`. The binary is only valid on the same device and
+    /// driver version.
+    fn get_graphics_pipeline_binary(&self, _pipeline: &B::GraphicsPipeline) -> Option<Vec<u8>> {
+        None
+    }
+
     /// Destroys a graphics pipeline.
     ///
     /// The graphics pipeline shouldn't be destroyed before any submitted command buffer,
@@ -234,6 +379,11 @@ pub trait Device<B: Backend>: Any + Send + Sync {
     fn destroy_graphics_pipeline(&self, pipeline: B::GraphicsPipeline);
 
     /// Create a compute pipeline.
+    ///
+    /// Specialization constants overriding the workgroup size are applied by all backends.
+    /// Vulkan, DX11, DX12 and GL specialize the shader code itself, Metal, which takes
+    /// the workgroup size on dispatch, reflects it with
+    /// `pso::EntryPointInfo::specialized_workgroup_size`.
     fn create_compute_pipeline<'a>(
         &self,
         desc: &pso::ComputePipelineDesc<'a, B>
@@ -255,6 +405,50 @@ pub trait Device<B: Backend>: Any + Send + Sync {
             .collect()
     }
 
+    /// Create a compute pipeline, looking up and storing the compiled pipeline in `cache`.
+    fn create_compute_pipeline_with_cache<'a>(
+        &self,
+        desc: &pso::ComputePipelineDesc<'a, B>,
+        cache: Option<&B::PipelineCache>,
+    ) -> Result<B::ComputePipeline, pso::CreationError> {
+        self.create_compute_pipelines_with_cache(Some(desc), cache).remove(0)
+    }
+
+    /// Create compute pipelines, looking up and storing the compiled pipelines in `cache`.
+    ///
+    /// The default implementation ignores the cache.
+    fn create_compute_pipelines_with_cache<'a, I>(
+        &self,
+        descs: I,
+        _cache: Option<&B::PipelineCache>,
+    ) -> Vec<Result<B::ComputePipeline, pso::CreationError>>
+    where
+        I: IntoIterator,
+        I::Item: Borrow<pso::ComputePipelineDesc<'a, B>>,
+    {
+        self.create_compute_pipelines(descs)
+    }
+
+    /// Create a compute pipeline from a binary previously returned by
+    /// `get_compute_pipeline_binary`, skipping the backend compilation where possible.
+    ///
+    /// If the binary is rejected, the pipeline is compiled from `desc` instead.
+    /// The default implementation ignores the binary.
+    fn create_compute_pipeline_with_binary<'a>(
+        &self,
+        desc: &pso::ComputePipelineDesc<'a, B>,
+        _binary: &[u8],
+    ) -> Result<B::ComputePipeline, pso::CreationError> {
+        self.create_compute_pipeline(desc)
+    }
+
+    /// Retrieve the backend binary of a compiled compute pipeline.
+    ///
+    /// Returns `None` if the backend or the driver doesn't expose pipeline binaries.
+    fn get_compute_pipeline_binary(&self, _pipeline: &B::ComputePipeline) -> Option<Vec<u8>> {
+        None
+    }
+
     /// Destroys a compute pipeline.
     ///
     /// The compute pipeline shouldn't be destroyed before any submitted command buffer,
@@ -272,6 +466,21 @@ pub trait Device<B: Backend>: Any + Send + Sync {
         I: IntoIterator,
         I::Item: Borrow<B::ImageView>;
 
+    /// Create a framebuffer without image views, which are instead provided
+    /// each time a render pass is begun with `begin_render_pass_imageless`.
+    ///
+    /// Requires `Features::IMAGELESS_FRAMEBUFFER`. `attachments` describes the
+    /// views that will be bound, in the order of the render pass attachments.
+    fn create_imageless_framebuffer<I>(
+        &self,
+        pass: &B::RenderPass,
+        attachments: I,
+        extent: image::Extent,
+    ) -> Result<B::Framebuffer, FramebufferError>
+    where
+        I: IntoIterator,
+        I::Item: Borrow<pass::FramebufferAttachment>;
+
     /// Destroys a framebuffer.
     ///
     /// The framebuffer shouldn't be destroy before any submitted command buffer,
@@ -283,6 +492,27 @@ pub trait Device<B: Backend>: Any + Send + Sync {
         &self, spirv_data: &[u8]
     ) -> Result<B::ShaderModule, ShaderError>;
 
+    /// Compile GLSL source code and create a shader module from it.
+    ///
+    /// Requires the `glsl-to-spirv` feature.
+    #[cfg(feature = "glsl-to-spirv")]
+    fn create_shader_module_from_glsl(
+        &self, stage: pso::Stage, source: &str
+    ) -> Result<B::ShaderModule, ShaderError> {
+        let spirv = pso::compile_glsl(stage, source)?;
+        self.create_shader_module(&spirv)
+    }
+
+    /// Create a shader module along with the reflection of its SPIR-V code, e.g. for
+    /// generating the pipeline layout from the descriptor bindings and push constants.
+    fn create_shader_module_with_reflection(
+        &self, spirv_data: &[u8]
+    ) -> Result<(B::ShaderModule, pso::ShaderInfo), ShaderError> {
+        let info = pso::reflect_spirv(spirv_data)?;
+        let module = self.create_shader_module(spirv_data)?;
+        Ok((module, info))
+    }
+
     ///
     fn destroy_shader_module(&self, shader: B::ShaderModule);
 
@@ -311,7 +541,10 @@ pub trait Device<B: Backend>: Any + Send + Sync {
     /// which references the images, has finished execution.
     fn destroy_buffer(&self, B::Buffer);
 
+    /// Create a view of the buffer `range`, interpreting its elements with the given format.
     ///
+    /// Buffers themselves are typeless, so multiple views with different formats
+    /// may alias the same range.
     fn create_buffer_view<R: RangeArg<u64>>(
         &self, buf: &B::Buffer, fmt: Option<format::Format>, range: R
     ) -> Result<B::BufferView, buffer::ViewCreationError>;
@@ -325,6 +558,33 @@ pub trait Device<B: Backend>: Any + Send + Sync {
         tiling: image::Tiling, usage: image::Usage, storage_flags: image::StorageFlags,
     ) -> Result<B::UnboundImage, image::CreationError>;
 
+    /// Create a new image, which can also be viewed with any of the `view_formats`.
+    ///
+    /// All view formats have to be compatible with `format` (see `Format::is_view_compatible`),
+    /// otherwise `CreationError::Format` is returned for the first incompatible one.
+    /// The image is created with `StorageFlags::MUTABLE_FORMAT` if any of them differs from `format`.
+    ///
+    /// Beyond this validation the list is a hint: Vulkan passes it to the driver when
+    /// `VK_KHR_image_format_list` is available, to keep optimizations like compression
+    /// enabled, D3D11 and D3D12 create mutable images as typeless resources and the
+    /// other backends ignore it.
+    fn create_image_with_view_formats(
+        &self, kind: image::Kind, mip_levels: image::Level, format: format::Format,
+        tiling: image::Tiling, usage: image::Usage, storage_flags: image::StorageFlags,
+        view_formats: &[format::Format],
+    ) -> Result<B::UnboundImage, image::CreationError> {
+        if let Some(&view_format) = view_formats.iter().find(|&&f| !format.is_view_compatible(f)) {
+            return Err(image::CreationError::Format(view_format));
+        }
+
+        let storage_flags = if view_formats.iter().any(|&f| f != format) {
+            storage_flags | image::StorageFlags::MUTABLE_FORMAT
+        } else {
+            storage_flags
+        };
+        self.create_image(kind, mip_levels, format, tiling, usage, storage_flags)
+    }
+
     ///
     fn get_image_requirements(&self, image: &B::UnboundImage) -> Requirements;
 
@@ -344,6 +604,28 @@ pub trait Device<B: Backend>: Any + Send + Sync {
     /// which references the images, has finished execution.
     fn destroy_image(&self, image: B::Image);
 
+    /// Create a sparse buffer, which has no memory bound to it.
+    ///
+    /// Memory is bound to page-sized regions of the buffer with `RawCommandQueue::bind_sparse`.
+    /// The returned requirements have `alignment` equal to the page size.
+    /// Requires `Features::SPARSE_BINDING`, and `Features::SPARSE_RESIDENCY_BUFFER`
+    /// for buffers which aren't fully bound when used.
+    /// The buffer is destroyed with `destroy_buffer`.
+    fn create_sparse_buffer(
+        &self, size: u64, usage: buffer::Usage,
+    ) -> Result<(B::Buffer, Requirements), buffer::CreationError>;
+
+    /// Create a sparse image with optimal tiling, which has no memory bound to it.
+    ///
+    /// Memory is bound to tiles of the image with `RawCommandQueue::bind_sparse`.
+    /// `StorageFlags::SPARSE_BINDING` is added to `storage_flags` implicitly,
+    /// partially resident images have to request `StorageFlags::SPARSE_RESIDENCY`.
+    /// The image is destroyed with `destroy_image`.
+    fn create_sparse_image(
+        &self, kind: image::Kind, mip_levels: image::Level, format: format::Format,
+        usage: image::Usage, storage_flags: image::StorageFlags,
+    ) -> Result<(B::Image, SparseImageRequirements), image::CreationError>;
+
     ///
     fn create_image_view(
         &self,
@@ -372,6 +654,23 @@ pub trait Device<B: Backend>: Any + Send + Sync {
         I: IntoIterator,
         I::Item: Borrow<pso::DescriptorRangeDesc>;
 
+    /// Create a descriptor pool with the given flags.
+    ///
+    /// Backends without `Features::DESCRIPTOR_INDEXING` only support empty flags.
+    fn create_descriptor_pool_with_flags<I>(
+        &self,
+        max_sets: usize,
+        descriptor_ranges: I,
+        flags: pso::DescriptorPoolCreateFlags,
+    ) -> B::DescriptorPool
+    where
+        I: IntoIterator,
+        I::Item: Borrow<pso::DescriptorRangeDesc>,
+    {
+        assert!(flags.is_empty(), "Descriptor indexing is not supported");
+        self.create_descriptor_pool(max_sets, descriptor_ranges)
+    }
+
     ///
     fn destroy_descriptor_pool(&self, pool: B::DescriptorPool);
 
@@ -385,6 +684,30 @@ pub trait Device<B: Backend>: Any + Send + Sync {
         J: IntoIterator,
         J::Item: Borrow<B::Sampler>;
 
+    /// Create a descriptor set layout with flags for each binding, e.g. for bindless
+    /// descriptor arrays which are partially bound or updated after binding.
+    ///
+    /// `binding_flags` is either empty or holds the flags of each binding, in the order of
+    /// `bindings`. Backends without `Features::DESCRIPTOR_INDEXING` only support empty flags.
+    fn create_descriptor_set_layout_with_flags<I, J>(
+        &self,
+        bindings: I,
+        binding_flags: &[pso::DescriptorBindingFlags],
+        immutable_samplers: J,
+    ) -> B::DescriptorSetLayout
+    where
+        I: IntoIterator,
+        I::Item: Borrow<pso::DescriptorSetLayoutBinding>,
+        J: IntoIterator,
+        J::Item: Borrow<B::Sampler>,
+    {
+        assert!(
+            binding_flags.iter().all(|flags| flags.is_empty()),
+            "Descriptor indexing is not supported"
+        );
+        self.create_descriptor_set_layout(bindings, immutable_samplers)
+    }
+
     ///
     fn destroy_descriptor_set_layout(&self, layout: B::DescriptorSetLayout);
 
@@ -480,6 +803,67 @@ pub trait Device<B: Backend>: Any + Send + Sync {
         self.unmap_memory(writer.memory);
     }
 
+    /// Acquire a persistent mapping, which stays mapped until released.
+    ///
+    /// The accessible slice will correspond to the specified range (in bytes), which should
+    /// start at a multiple of `non_coherent_atom_size` (see `Limits`). The atom size is used to
+    /// align the ranges flushed or invalidated through the mapping.
+    fn acquire_persistent_mapping<'a, T>(
+        &self,
+        memory: &'a B::Memory,
+        range: Range<u64>,
+        non_coherent_atom_size: u64,
+    ) -> Result<mapping::Persistent<'a, B, T>, mapping::Error>
+    where
+        T: Copy,
+    {
+        let count = (range.end - range.start) as usize / mem::size_of::<T>();
+        self.map_memory(memory, range.clone())
+            .map(|ptr| unsafe {
+                mapping::Persistent {
+                    slice: slice::from_raw_parts_mut(ptr as *mut _, count),
+                    memory,
+                    range,
+                    atom_size: non_coherent_atom_size.max(1),
+                    released: false,
+                }
+            })
+    }
+
+    /// Make host writes to the given element ranges of a persistent mapping visible to the device.
+    fn flush_mapped_ranges<'a, T, I>(&self, mapping: &mapping::Persistent<'a, B, T>, ranges: I)
+    where
+        I: IntoIterator<Item = Range<usize>>,
+    {
+        let memory = mapping.memory;
+        self.flush_mapped_memory_ranges(
+            ranges
+                .into_iter()
+                .map(|elements| (memory, mapping.memory_range(elements))),
+        );
+    }
+
+    /// Make device writes to the given element ranges of a persistent mapping visible to the host.
+    fn invalidate_mapped_ranges<'a, T, I>(&self, mapping: &mapping::Persistent<'a, B, T>, ranges: I)
+    where
+        I: IntoIterator<Item = Range<usize>>,
+    {
+        let memory = mapping.memory;
+        self.invalidate_mapped_memory_ranges(
+            ranges
+                .into_iter()
+                .map(|elements| (memory, mapping.memory_range(elements))),
+        );
+    }
+
+    /// Release a persistent mapping.
+    ///
+    /// Nothing is flushed, pending writes must be flushed with `flush_mapped_ranges` first.
+    fn release_persistent_mapping<'a, T>(&self, mut mapping: mapping::Persistent<'a, B, T>) {
+        mapping.released = true;
+        self.unmap_memory(mapping.memory);
+    }
+
     ///
     fn create_semaphore(&self) -> B::Semaphore;
 
@@ -562,12 +946,110 @@ pub trait Device<B: Backend>: Any + Send + Sync {
     ///
     fn destroy_fence(&self, fence: B::Fence);
 
+    /// Create a timeline semaphore, see the `sync` module.
+    fn create_timeline_semaphore(&self, initial_value: u64) -> B::TimelineSemaphore;
+
+    /// Return the current value of a timeline semaphore.
+    fn get_timeline_semaphore_value(&self, semaphore: &B::TimelineSemaphore) -> u64;
+
+    /// Signal a timeline semaphore to `value` from the host.
+    ///
+    /// `value` must be greater than the current value and than any value
+    /// a pending submission is going to signal.
+    fn signal_timeline_semaphore(&self, semaphore: &B::TimelineSemaphore, value: u64);
+
+    /// Blocks until the timeline semaphore reaches `value`.
+    /// Returns true if the value was reached before the timeout.
+    fn wait_for_timeline_semaphore(
+        &self, semaphore: &B::TimelineSemaphore, value: u64, timeout_ms: u32,
+    ) -> bool {
+        self.wait_for_timeline_semaphores(Some((semaphore, value)), WaitFor::All, timeout_ms)
+    }
+
+    /// Blocks until all or one of the given timeline semaphores reach their values.
+    /// Returns true if the values were reached before the timeout.
+    fn wait_for_timeline_semaphores<'a, I>(&self, semaphores: I, wait: WaitFor, timeout_ms: u32) -> bool
+    where
+        I: IntoIterator<Item = (&'a B::TimelineSemaphore, u64)>,
+    {
+        use std::{time, thread};
+        fn to_ms(duration: time::Duration) -> u32 {
+            duration.as_secs() as u32 * 1000 + duration.subsec_nanos() / 1_000_000
+        }
+
+        let start = time::Instant::now();
+        match wait {
+            WaitFor::All => {
+                for (semaphore, value) in semaphores {
+                    if !self.wait_for_timeline_semaphore(semaphore, value, 0) {
+                        let elapsed_ms = to_ms(start.elapsed());
+                        if elapsed_ms > timeout_ms {
+                            return false;
+                        }
+                        if !self.wait_for_timeline_semaphore(semaphore, value, timeout_ms - elapsed_ms) {
+                            return false;
+                        }
+                    }
+                }
+                true
+            }
+            WaitFor::Any => {
+                let semaphores: Vec<_> = semaphores.into_iter().collect();
+                loop {
+                    for &(semaphore, value) in &semaphores {
+                        if self.get_timeline_semaphore_value(semaphore) >= value {
+                            return true;
+                        }
+                    }
+                    if to_ms(start.elapsed()) >= timeout_ms {
+                        return false;
+                    }
+                    thread::sleep(time::Duration::from_millis(1));
+                }
+            }
+        }
+    }
+
+    ///
+    fn destroy_timeline_semaphore(&self, semaphore: B::TimelineSemaphore);
+
+    /// Create an event, for synchronizing commands within a queue, see
+    /// `RawCommandBuffer::set_event`.
+    fn create_event(&self) -> B::Event;
+
+    /// Destroy an event.
+    ///
+    /// The event shouldn't be destroyed before any submitted command buffer using it
+    /// has finished execution.
+    fn destroy_event(&self, event: B::Event);
+
     ///
     fn create_query_pool(&self, ty: query::QueryType, count: u32) -> B::QueryPool;
 
     ///
     fn destroy_query_pool(&self, pool: B::QueryPool);
 
+    /// Copy the results of the `queries` into `data`, one value per query.
+    /// Pipeline statistics queries write one value per enabled statistic,
+    /// in the order of the `PipelineStatistic` bits.
+    ///
+    /// Timestamps are returned in ticks, see `PhysicalDevice::timestamp_period`.
+    /// If `wait` is set, blocks until all the results are available.
+    /// Returns `Ok(false)` if some results were not available yet, in which case
+    /// the contents of `data` are undefined.
+    ///
+    /// The default implementation, used by backends without query support,
+    /// always returns `Ok(false)`, even if `wait` is set.
+    fn get_query_pool_results(
+        &self,
+        _pool: &B::QueryPool,
+        _queries: Range<query::QueryId>,
+        _data: &mut [u64],
+        _wait: bool,
+    ) -> Result<bool, HostExecutionError> {
+        Ok(false)
+    }
+
     /// Create a new swapchain from a surface and a queue family, optionally providing the old
     /// swapchain to aid in resource reuse and rendering continuity.
     ///
@@ -614,4 +1096,12 @@ pub trait Device<B: Backend>: Any + Send + Sync {
     ///
     /// Host access to all queues needs to be **externally** sycnhronized!
     fn wait_idle(&self) -> Result<(), HostExecutionError>;
+
+    /// Retrieve the numbers of commands submitted and descriptor updates performed since the
+    /// last call, resetting the counters. Calling this once per frame yields per-frame statistics.
+    ///
+    /// Returns `None` if the backend doesn't collect statistics.
+    fn frame_statistics(&self) -> Option<FrameStatistics> {
+        None
+    }
 }
@@ -1,5 +1,7 @@
 //! Return values from function calls.
 
+use std::fmt;
+
 /// Device creation errors during `open`.
 #[derive(Fail, Debug, Clone, PartialEq, Eq)]
 pub enum DeviceCreationError {
@@ -59,3 +61,30 @@ pub enum HostExecutionError {
     #[fail(display = "Physical or logical device lost.")]
     DeviceLost,
 }
+
+/// Diagnostic output of the backend collected while executing a failed call,
+/// such as validation layer reports, D3D info queue entries or driver error descriptions.
+///
+/// Validation messages are only collected in debug builds, where backends enable
+/// their debug layers.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ErrorContext {
+    /// Messages in the order they were reported.
+    pub messages: Vec<String>,
+}
+
+impl ErrorContext {
+    /// Whether no messages were collected.
+    pub fn is_empty(&self) -> bool {
+        self.messages.is_empty()
+    }
+}
+
+impl fmt::Display for ErrorContext {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for message in &self.messages {
+            writeln!(f, "{}", message)?;
+        }
+        Ok(())
+    }
+}
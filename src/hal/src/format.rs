@@ -9,6 +9,8 @@
 //! for instance `R32_G32_B32_A32`.  The `ChannelType` specifies how the
 //! components are interpreted, for instance `Float` or `Int`.
 
+use Features;
+
 bitflags!(
     /// Bitflags which describe what properties of an image
     /// a format specifies or does not specify.  For example,
@@ -164,6 +166,12 @@ bitflags!(
         /// with linear sampling.
         /// Requires `SAMPLED` or `BLIT_SRC` flag.
         const SAMPLED_LINEAR = 0x1000;
+        /// Storage image view of this format supports typed loads in shaders,
+        /// which are required for read-modify-write access.
+        /// Stores are always supported by `STORAGE` views, while loads are
+        /// only available for some formats on D3D.
+        /// Requires `STORAGE` flag.
+        const STORAGE_READ = 0x8000_0000;
     }
 );
 
@@ -180,6 +188,9 @@ bitflags!(
         const STORAGE_TEXEL_ATOMIC = 0x20;
         /// Image view can be used as vertex buffer.
         const VERTEX = 0x40;
+        /// Storage texel buffer view of this format supports typed loads in shaders.
+        /// Requires `STORAGE_TEXEL` flag.
+        const STORAGE_TEXEL_READ = 0x8000_0000;
     }
 );
 
@@ -545,7 +556,7 @@ formats! {
     Etc2R8g8b8a8Unorm = (ETC2_R8_G8_B8_A8, Unorm),
     Etc2R8g8b8a8Srgb = (ETC2_R8_G8_B8_A8, Srgb),
     EacR11Unorm = (EAC_R11, Unorm),
-    EacR11Inorm = (EAC_R11, Unorm),
+    EacR11Inorm = (EAC_R11, Inorm),
     EacR11g11Unorm = (EAC_R11_G11, Unorm),
     EacR11g11Inorm = (EAC_R11_G11, Inorm),
     Astc4x4Unorm = (ASTC_4x4, Unorm),
@@ -606,6 +617,108 @@ impl Format {
     pub fn is_stencil(self) -> bool {
         self.surface_desc().aspects.contains(Aspects::STENCIL)
     }
+    /// Returns the format which differs from this one only in sRGB encoding,
+    /// i.e. the `Srgb` format for an `Unorm` one and vice versa.
+    ///
+    /// Returns `None` if the format has no such counterpart.
+    pub fn srgb_counterpart(self) -> Option<Format> {
+        use self::Format::*;
+        match self {
+            R8Unorm => Some(R8Srgb),
+            R8Srgb => Some(R8Unorm),
+            Rg8Unorm => Some(Rg8Srgb),
+            Rg8Srgb => Some(Rg8Unorm),
+            Rgb8Unorm => Some(Rgb8Srgb),
+            Rgb8Srgb => Some(Rgb8Unorm),
+            Bgr8Unorm => Some(Bgr8Srgb),
+            Bgr8Srgb => Some(Bgr8Unorm),
+            Rgba8Unorm => Some(Rgba8Srgb),
+            Rgba8Srgb => Some(Rgba8Unorm),
+            Bgra8Unorm => Some(Bgra8Srgb),
+            Bgra8Srgb => Some(Bgra8Unorm),
+            Abgr8Unorm => Some(Abgr8Srgb),
+            Abgr8Srgb => Some(Abgr8Unorm),
+            Bc1RgbUnorm => Some(Bc1RgbSrgb),
+            Bc1RgbSrgb => Some(Bc1RgbUnorm),
+            Bc1RgbaUnorm => Some(Bc1RgbaSrgb),
+            Bc1RgbaSrgb => Some(Bc1RgbaUnorm),
+            Bc2Unorm => Some(Bc2Srgb),
+            Bc2Srgb => Some(Bc2Unorm),
+            Bc3Unorm => Some(Bc3Srgb),
+            Bc3Srgb => Some(Bc3Unorm),
+            Bc7Unorm => Some(Bc7Srgb),
+            Bc7Srgb => Some(Bc7Unorm),
+            Etc2R8g8b8Unorm => Some(Etc2R8g8b8Srgb),
+            Etc2R8g8b8Srgb => Some(Etc2R8g8b8Unorm),
+            Etc2R8g8b8a1Unorm => Some(Etc2R8g8b8a1Srgb),
+            Etc2R8g8b8a1Srgb => Some(Etc2R8g8b8a1Unorm),
+            Etc2R8g8b8a8Unorm => Some(Etc2R8g8b8a8Srgb),
+            Etc2R8g8b8a8Srgb => Some(Etc2R8g8b8a8Unorm),
+            Astc4x4Unorm => Some(Astc4x4Srgb),
+            Astc4x4Srgb => Some(Astc4x4Unorm),
+            Astc5x4Unorm => Some(Astc5x4Srgb),
+            Astc5x4Srgb => Some(Astc5x4Unorm),
+            Astc5x5Unorm => Some(Astc5x5Srgb),
+            Astc5x5Srgb => Some(Astc5x5Unorm),
+            Astc6x5Unorm => Some(Astc6x5Srgb),
+            Astc6x5Srgb => Some(Astc6x5Unorm),
+            Astc6x6Unorm => Some(Astc6x6Srgb),
+            Astc6x6Srgb => Some(Astc6x6Unorm),
+            Astc8x5Unorm => Some(Astc8x5Srgb),
+            Astc8x5Srgb => Some(Astc8x5Unorm),
+            Astc8x6Unorm => Some(Astc8x6Srgb),
+            Astc8x6Srgb => Some(Astc8x6Unorm),
+            Astc8x8Unorm => Some(Astc8x8Srgb),
+            Astc8x8Srgb => Some(Astc8x8Unorm),
+            Astc10x5Unorm => Some(Astc10x5Srgb),
+            Astc10x5Srgb => Some(Astc10x5Unorm),
+            Astc10x6Unorm => Some(Astc10x6Srgb),
+            Astc10x6Srgb => Some(Astc10x6Unorm),
+            Astc10x8Unorm => Some(Astc10x8Srgb),
+            Astc10x8Srgb => Some(Astc10x8Unorm),
+            Astc10x10Unorm => Some(Astc10x10Srgb),
+            Astc10x10Srgb => Some(Astc10x10Unorm),
+            Astc12x10Unorm => Some(Astc12x10Srgb),
+            Astc12x10Srgb => Some(Astc12x10Unorm),
+            Astc12x12Unorm => Some(Astc12x12Srgb),
+            Astc12x12Srgb => Some(Astc12x12Unorm),
+            _ => None,
+        }
+    }
+
+    /// Returns if an image of this format can be viewed with the `other` format,
+    /// provided it was created with `StorageFlags::MUTABLE_FORMAT`.
+    ///
+    /// Compatible formats share the same surface type and only differ in how the
+    /// channels are interpreted, e.g. `Rgba8Unorm`, `Rgba8Srgb` and `Rgba8Uint`.
+    pub fn is_view_compatible(self, other: Format) -> bool {
+        self == other || (
+            self.is_color() &&
+            self.base_format().0 == other.base_format().0
+        )
+    }
+
+    /// Returns if the format uses sRGB encoding for its color channels.
+    pub fn is_srgb(self) -> bool {
+        self.base_format().1 == ChannelType::Srgb
+    }
+
+    /// Returns the device features required to use this format.
+    ///
+    /// Block-compressed formats are optional and need to be checked against
+    /// the features of the physical device.
+    pub fn required_features(self) -> Features {
+        use self::SurfaceType::*;
+        match self.base_format().0 {
+            BC1_RGB | BC1_RGBA | BC2 | BC3 | BC4 | BC5 | BC6 | BC7 => Features::FORMAT_BC,
+            ETC2_R8_G8_B8 | ETC2_R8_G8_B8_A1 | ETC2_R8_G8_B8_A8 |
+            EAC_R11 | EAC_R11_G11 => Features::FORMAT_ETC2,
+            ASTC_4x4 | ASTC_5x4 | ASTC_5x5 | ASTC_6x5 | ASTC_6x6 |
+            ASTC_8x5 | ASTC_8x6 | ASTC_8x8 | ASTC_10x5 | ASTC_10x6 |
+            ASTC_10x8 | ASTC_10x10 | ASTC_12x10 | ASTC_12x12 => Features::FORMAT_ASTC_LDR,
+            _ => Features::empty(),
+        }
+    }
 }
 
 // Common vertex attribute formats
@@ -381,6 +381,18 @@ bitflags!(
     /// Image storage flags
     #[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
     pub struct StorageFlags: u32 {
+        /// Image is backed by sparse memory bindings.
+        /// Set implicitly for images created with `Device::create_sparse_image`.
+        const SPARSE_BINDING = 0b0000001;
+        /// Sparse image may be partially resident.
+        /// Requires `Features::SHADER_RESIDENCY_IMAGE_2D` (or the 3D variant).
+        const SPARSE_RESIDENCY = 0b0000010;
+        /// Sparse image may alias memory with other sparse resources.
+        /// Requires `Features::SPARSE_RESIDENCY_ALIASED`.
+        const SPARSE_ALIASED = 0b0000100;
+        /// Support creation of views with a format different from the image,
+        /// such as a linear view of an sRGB image. See `Format::is_view_compatible`.
+        const MUTABLE_FORMAT = 0b0001000;
         /// Support creation of `Cube` and `CubeArray` views.
         const CUBE_VIEW = 0b0010000;
     }
@@ -604,6 +616,12 @@ pub enum Layout {
     /// Must only be used as a depth attachment in a framebuffer,
     /// or as a read-only depth or stencil buffer in a shader.
     DepthStencilReadOnlyOptimal,
+    /// Like `DepthStencilReadOnlyOptimal` for the depth aspect, which can be read
+    /// in a shader, while the stencil aspect is written as an attachment.
+    DepthReadOnlyStencilAttachmentOptimal,
+    /// Like `DepthStencilReadOnlyOptimal` for the stencil aspect, which can be read
+    /// in a shader, while the depth aspect is written as an attachment.
+    DepthAttachmentStencilReadOnlyOptimal,
     /// Must only be used as a read-only image in a shader.
     ShaderReadOnlyOptimal,
     /// Must only be used as the source for a transfer command.
@@ -641,13 +659,13 @@ bitflags!(
         const DEPTH_STENCIL_ATTACHMENT_READ = 0x200;
         /// Write access to a depth/stencil attachment in a depth or stencil operation.
         const DEPTH_STENCIL_ATTACHMENT_WRITE = 0x400;
-        /// Read access to the buffer in a copy operation.
+        /// Read access to the image in a copy, blit or resolve operation.
         const TRANSFER_READ = 0x800;
-        /// Write access to the buffer in a copy operation.
+        /// Write access to the image in a copy, blit, resolve or clear operation.
         const TRANSFER_WRITE = 0x1000;
-        /// Read access for raw memory to be accessed by the host system (ie, CPU).
+        /// Read access to linear image memory by the host system (ie, CPU).
         const HOST_READ = 0x2000;
-        /// Write access for raw memory to be accessed by the host system.
+        /// Write access to linear image memory by the host system.
         const HOST_WRITE = 0x4000;
         /// Read access for memory to be accessed by a non-specific entity.  This may
         /// be the host system, or it may be something undefined or specified by an
@@ -10,6 +10,8 @@ extern crate failure;
 extern crate smallvec;
 extern crate fxhash;
 
+#[cfg(feature = "glsl-to-spirv")]
+extern crate glsl_to_spirv;
 #[cfg(feature = "mint")]
 extern crate mint;
 
@@ -25,8 +27,8 @@ use std::hash::Hash;
 //TODO: reconsider what is publicly exported
 
 pub use self::adapter::{
-    Adapter, AdapterInfo, MemoryProperties, MemoryType, MemoryTypeId,
-    PhysicalDevice, QueuePriority,
+    Adapter, AdapterInfo, DeviceType, MemoryProperties, MemoryType, MemoryTypeId,
+    PhysicalDevice, PowerPreference, QueueGroups, QueuePriority,
 };
 pub use self::device::Device;
 pub use self::pool::CommandPool;
@@ -36,8 +38,9 @@ pub use self::queue::{
     Capability, Supports, General, Graphics, Compute, Transfer,
 };
 pub use self::window::{
-    Backbuffer, SwapImageIndex, FrameSync, PresentMode,
-    Surface, SurfaceCapabilities, Swapchain, SwapchainConfig,
+    AcquireError, Backbuffer, SwapImageIndex, FrameSync, PresentError, PresentMode, Resize,
+    ResizePolicy, Suboptimal, Surface, SurfaceCapabilities, SurfaceTransform, Swapchain,
+    SwapchainConfig,
 };
 
 pub mod adapter;
@@ -51,10 +54,14 @@ pub mod mapping;
 pub mod memory;
 pub mod pass;
 pub mod pool;
+pub mod profiler;
 pub mod pso;
 pub mod query;
 pub mod queue;
 pub mod range;
+pub mod sparse;
+pub mod sync;
+pub mod validation;
 pub mod window;
 
 #[doc(hidden)]
@@ -90,13 +97,16 @@ pub type SamplerSlot = u8;
 
 bitflags! {
     /// Features that the device supports.
-    /// These only include features of the core interface and not API extensions.
+    /// These include features of the core interface as well as features
+    /// exposed through API extensions, if the backend is able to provide them.
     #[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
-    pub struct Features: u64 {
+    pub struct Features: u128 {
         /// Bit mask of Vulkan Core features.
         const CORE_MASK   = 0x0FFF_FFFF_FFFF_FFFF;
         /// Bit mask of Vulkan Portability features.
         const PORTABILITY_MASK  = 0xF000_0000_0000_0000;
+        /// Bit mask of features exposed through API extensions.
+        const EXTENSIONS_MASK = 0xFFFF_FFFF_FFFF_FFFF_0000_0000_0000_0000;
 
         /// Support for robust buffer access.
         /// Buffer access by SPIR-V shaders is checked against the buffer/image boundaries.
@@ -222,6 +232,80 @@ bitflags! {
         const SEPARATE_STENCIL_REF_VALUES = 0x2000_0000_0000_0000;
         /// Support manually specified vertex attribute rates (divisors).
         const INSTANCE_RATE = 0x8000_0000_0000_0000;
+
+        /// Support 64-bit integer atomic operations on storage images
+        /// of `R64Uint` and `R64Int` formats.
+        const SHADER_IMAGE_INT64_ATOMICS = 0x0001 << 64;
+        /// Support 64-bit integer atomic operations on storage buffers.
+        const SHADER_BUFFER_INT64_ATOMICS = 0x0002 << 64;
+        /// Support 64-bit integer atomic operations on workgroup shared memory.
+        const SHADER_SHARED_INT64_ATOMICS = 0x0004 << 64;
+        /// Support 32-bit float atomic operations on storage buffers.
+        const SHADER_BUFFER_FLOAT32_ATOMICS = 0x0008 << 64;
+        /// Support 32-bit float atomic operations on workgroup shared memory.
+        const SHADER_SHARED_FLOAT32_ATOMICS = 0x0010 << 64;
+        /// Support 32-bit float atomic operations on storage images.
+        const SHADER_IMAGE_FLOAT32_ATOMICS = 0x0020 << 64;
+        /// Support 64-bit float atomic operations on storage buffers.
+        const SHADER_BUFFER_FLOAT64_ATOMICS = 0x0040 << 64;
+        /// Support 64-bit float atomic operations on workgroup shared memory.
+        const SHADER_SHARED_FLOAT64_ATOMICS = 0x0080 << 64;
+        /// Support dispatching compute workgroups with a non-zero base workgroup.
+        const DISPATCH_BASE = 0x0100 << 64;
+        /// Support writing the stencil reference value from fragment shaders
+        /// (`StencilExportEXT` capability, `SV_StencilRef` in HLSL).
+        const SHADER_STENCIL_EXPORT = 0x0200 << 64;
+        /// Support basic subgroup operations: electing an invocation and subgroup barriers.
+        const SUBGROUP_BASIC = 0x0400 << 64;
+        /// Support subgroup vote operations (`all`, `any`, `all_equal`).
+        const SUBGROUP_VOTE = 0x0800 << 64;
+        /// Support subgroup arithmetic operations (reductions and prefix scans).
+        const SUBGROUP_ARITHMETIC = 0x1000 << 64;
+        /// Support subgroup ballot and broadcast operations.
+        const SUBGROUP_BALLOT = 0x2000 << 64;
+        /// Support exchanging values between invocations of a subgroup,
+        /// both by absolute and relative invocation index.
+        const SUBGROUP_SHUFFLE = 0x4000 << 64;
+        /// Support exchanging values between the invocations of a quad.
+        const SUBGROUP_QUAD = 0x8000 << 64;
+        /// Support 16-bit floating point arithmetic in shaders.
+        const SHADER_FLOAT16 = 0x1_0000 << 64;
+        /// Support 8-bit integer arithmetic in shaders.
+        const SHADER_INT8 = 0x2_0000 << 64;
+        /// Support 16-bit types in uniform and storage buffers and push constants.
+        const STORAGE_16BIT = 0x4_0000 << 64;
+        /// Support 8-bit types in uniform and storage buffers and push constants.
+        const STORAGE_8BIT = 0x8_0000 << 64;
+        /// Support recording render passes with `begin_rendering`, without
+        /// creating `RenderPass` and `Framebuffer` objects up front.
+        const DYNAMIC_RENDERING = 0x10_0000 << 64;
+        /// Support framebuffers created without image views, which are
+        /// provided when beginning the render pass instead.
+        const IMAGELESS_FRAMEBUFFER = 0x20_0000 << 64;
+        /// Support exporting compiled pipelines as backend binaries and
+        /// creating pipelines from them, see `Device::get_graphics_pipeline_binary`.
+        const PIPELINE_BINARY = 0x40_0000 << 64;
+        /// Support multiview render passes, rendering each subpass into several
+        /// layers of its attachments, see `Device::create_render_pass_multiview`.
+        const MULTIVIEW = 0x80_0000 << 64;
+        /// Timeline semaphores are backed by native objects. Otherwise they are
+        /// emulated on the host, see `sync::HostTimeline`.
+        const TIMELINE_SEMAPHORE = 0x100_0000 << 64;
+        /// Support non-uniform indexing into descriptor arrays, partially bound and
+        /// variable sized arrays, and updating descriptors after binding them,
+        /// see `pso::DescriptorBindingFlags`.
+        const DESCRIPTOR_INDEXING = 0x200_0000 << 64;
+        /// Support `debugPrintfEXT` (`GL_EXT_debug_printf`) in shaders, for debugging.
+        /// The messages are reported through the debug callback of the backend.
+        const SHADER_PRINTF = 0x400_0000 << 64;
+        /// Support reading the subgroup scope shader clock (`GL_ARB_shader_clock`), for
+        /// profiling parts of a shader. The clock values aren't related to timestamps.
+        const SHADER_CLOCK = 0x800_0000 << 64;
+        /// Support the barycentric coordinates of the fragment within its primitive
+        /// (`GL_EXT_fragment_shader_barycentric`), e.g. for wireframe overlays.
+        const FRAGMENT_BARYCENTRIC = 0x1000_0000 << 64;
+        /// Support 16-bit types in shader stage inputs and outputs.
+        const STORAGE_INPUT_OUTPUT_16BIT = 0x2000_0000 << 64;
     }
 }
 
@@ -272,6 +356,12 @@ pub struct Limits {
     pub max_color_attachments: usize,
     /// Size and alignment in bytes that bounds concurrent access to host-mapped device memory.
     pub non_coherent_atom_size: usize,
+    /// Minimum number of invocations in a subgroup, zero if unknown.
+    pub min_subgroup_size: u32,
+    /// Maximum number of invocations in a subgroup, zero if unknown.
+    ///
+    /// Subgroup operations are exposed by the `Features::SUBGROUP_*` flags.
+    pub max_subgroup_size: u32,
 }
 
 /// Describes the type of geometric primitives,
@@ -372,12 +462,15 @@ pub trait Backend: 'static + Sized + Eq + Clone + Hash + fmt::Debug + Any + Send
     type ComputePipeline:     fmt::Debug + Any + Send + Sync;
     type GraphicsPipeline:    fmt::Debug + Any + Send + Sync;
     type PipelineLayout:      fmt::Debug + Any + Send + Sync;
+    type PipelineCache:       fmt::Debug + Any + Send + Sync;
     type DescriptorPool:      pso::DescriptorPool<Self>;
     type DescriptorSet:       fmt::Debug + Any + Send + Sync;
     type DescriptorSetLayout: fmt::Debug + Any + Send + Sync;
 
     type Fence:               fmt::Debug + Any + Send + Sync;
     type Semaphore:           fmt::Debug + Any + Send + Sync;
+    type TimelineSemaphore:   fmt::Debug + Any + Send + Sync;
+    type Event:               fmt::Debug + Any + Send + Sync;
     type QueryPool:           fmt::Debug + Any + Send + Sync;
 }
 
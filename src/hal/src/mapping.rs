@@ -3,7 +3,8 @@
 //! Memory mapping
 use std::error::Error as StdError;
 use std::fmt;
-use std::ops::{self, Range};
+use std::{mem, ops};
+use std::ops::Range;
 use Backend;
 
 // TODO
@@ -77,3 +78,54 @@ impl<'a, B: Backend, T: 'a> ops::Deref for Writer<'a, B, T> {
 impl<'a, B: Backend, T: 'a> ops::DerefMut for Writer<'a, B, T> {
     fn deref_mut(&mut self) -> &mut [T] { self.slice }
 }
+
+/// Persistent typed mapping, for memory written or read by the host every frame.
+///
+/// Unlike `Writer` the mapping isn't flushed as a whole on release, only the element
+/// ranges passed to `Device::flush_mapped_ranges` are made visible to the device, and
+/// `Device::invalidate_mapped_ranges` makes device writes visible to the host. Both are
+/// only required for non-coherent memory.
+pub struct Persistent<'a, B: Backend, T: 'a> {
+    pub(crate) slice: &'a mut [T],
+    pub(crate) memory: &'a B::Memory,
+    pub(crate) range: Range<u64>,
+    pub(crate) atom_size: u64,
+    pub(crate) released: bool,
+}
+
+impl<'a, B: Backend, T: 'a> Persistent<'a, B, T> {
+    /// Range of the memory covering the given elements, expanded to the non-coherent
+    /// atom size and clamped to the mapped range.
+    pub(crate) fn memory_range(&self, elements: Range<usize>) -> Range<u64> {
+        let size = mem::size_of::<T>() as u64;
+        let start = self.range.start + elements.start as u64 * size;
+        let end = self.range.start + elements.end as u64 * size;
+        let start = start / self.atom_size * self.atom_size;
+        let end = (end + self.atom_size - 1) / self.atom_size * self.atom_size;
+        start.max(self.range.start) .. end.min(self.range.end)
+    }
+}
+
+impl<'a, B: Backend, T: 'a> fmt::Debug for Persistent<'a, B, T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Persistent")
+            .field("range", &self.range)
+            .field("len", &self.slice.len())
+            .finish()
+    }
+}
+
+impl<'a, B: Backend, T: 'a> Drop for Persistent<'a, B, T> {
+    fn drop(&mut self) {
+        assert!(self.released, "a persistent mapping was not released");
+    }
+}
+
+impl<'a, B: Backend, T: 'a> ops::Deref for Persistent<'a, B, T> {
+    type Target = [T];
+    fn deref(&self) -> &[T] { self.slice }
+}
+
+impl<'a, B: Backend, T: 'a> ops::DerefMut for Persistent<'a, B, T> {
+    fn deref_mut(&mut self) -> &mut [T] { self.slice }
+}
@@ -3,6 +3,7 @@
 use std::mem;
 use std::ops::Range;
 use {buffer, image};
+use queue::QueueFamilyId;
 use Backend;
 
 /// A trait for plain-old-data types.
@@ -81,9 +82,13 @@ bitflags!(
 #[allow(missing_docs)] 
 #[derive(Clone, Debug)]
 pub enum Barrier<'a, B: Backend> {
-    /// Applies the given access flags to all buffers in the range.
+    /// A global memory barrier, making all buffer accesses of the source
+    /// kind visible to the destination accesses, e.g. all compute shader
+    /// writes to all subsequent reads. This is usually cheaper than issuing
+    /// a barrier per resource.
     AllBuffers(Range<buffer::Access>),
-    /// Applies the given access flags to all images in the range.
+    /// A global memory barrier for the accesses of all images.
+    /// Image layouts are not changed by this barrier.
     AllImages(Range<image::Access>),
     /// A memory barrier that defines access to a buffer.
     Buffer {
@@ -91,6 +96,12 @@ pub enum Barrier<'a, B: Backend> {
         states: Range<buffer::State>,
         /// The buffer the barrier controls.
         target: &'a B::Buffer,
+        /// Source and destination queue families of a queue family ownership transfer.
+        ///
+        /// The transfer is done by recording the same barrier on a queue of both families,
+        /// the release on the source queue has to complete before the acquire on the
+        /// destination queue, e.g. through a semaphore. `None` if the ownership doesn't change.
+        families: Option<Range<QueueFamilyId>>,
     },
     /// A memory barrier that defines access to (a subset of) an image.
     Image {
@@ -99,7 +110,13 @@ pub enum Barrier<'a, B: Backend> {
         /// The image the barrier controls.
         target: &'a B::Image,
         /// A `SubresourceRange` that defines which section of an image the barrier applies to.
+        ///
+        /// Only the selected aspects, mip levels and array layers are transitioned,
+        /// the rest of the image keeps its current state.
         range: image::SubresourceRange,
+        /// Source and destination queue families of a queue family ownership transfer,
+        /// see `Barrier::Buffer`.
+        families: Option<Range<QueueFamilyId>>,
     },
 }
 
@@ -1,9 +1,10 @@
 //! RenderPass handling.
 
+use device::{Device, MultiviewError};
 use format::Format;
 use image;
 use pso::PipelineStage;
-use Backend;
+use {Backend, Features};
 use std::ops::Range;
 
 /// Specifies the operation which will be applied at the beginning of a subpass.
@@ -64,6 +65,20 @@ impl AttachmentOps {
     }
 }
 
+/// Description of the image view bound to an attachment of an imageless framebuffer.
+///
+/// The view itself is only provided when beginning the render pass.
+#[derive(Clone, Debug, Hash, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct FramebufferAttachment {
+    /// Usage of the image the view is created from.
+    pub usage: image::Usage,
+    /// Storage flags of the image the view is created from.
+    pub flags: image::StorageFlags,
+    /// Format of the view.
+    pub format: Format,
+}
+
 /// An `Attachment` is a description of a resource provided to a render subpass.
 /// It includes things such as render targets, images that were produced from
 /// previous subpasses, etc.
@@ -134,6 +149,8 @@ pub struct SubpassDesc<'a> {
     /// The number of resolve attachments may be zero or equal to the number of color attachments.
     /// At the end of a subpass the color attachment will be resolved to the corresponding
     /// resolve attachment. The resolve attachment must not be multisampled.
+    /// Resolves are written with `image::Access::COLOR_ATTACHMENT_WRITE` in the
+    /// `COLOR_ATTACHMENT_OUTPUT` pipeline stage.
     pub resolves: &'a [AttachmentRef],
     /// Attachments that are not used by the subpass but must be preserved to be
     /// passed on to subsequent passes.
@@ -143,6 +160,12 @@ pub struct SubpassDesc<'a> {
 /// Index of a subpass.
 pub type SubpassId = usize;
 
+/// Mask of the views a multiview subpass is rendered into.
+///
+/// Bit `i` selects layer `i` of the attachments, the shaders of the subpass
+/// are invoked once per view with `ViewIndex` (`gl_ViewIndex`) set to `i`.
+pub type ViewMask = u32;
+
 /// A sub-pass borrow of a pass.
 #[derive(Debug)]
 pub struct Subpass<'a, B: Backend> {
@@ -170,3 +193,184 @@ impl<'a, B: Backend> PartialEq for Subpass<'a, B> {
 
 impl<'a, B: Backend> Copy for Subpass<'a, B> {}
 impl<'a, B: Backend> Eq for Subpass<'a, B> {}
+
+/// Single subpass render pass for a common kind of pass, with the attachment layouts
+/// and external dependencies set up for the passes usually surrounding it.
+///
+/// The fields can be adjusted before creating the render pass, e.g. to keep the
+/// contents of an attachment instead of clearing it.
+///
+/// ```ignore
+/// let prepass = PassTemplate::depth_prepass(Format::D32Float, 1).create::<B, _>(&device)?;
+/// // Main pass loading the depth with `DepthStencilAttachmentOptimal` as initial layout,
+/// // drawn with `Comparison::Equal` and depth writes disabled.
+/// ```
+#[derive(Clone, Debug)]
+pub struct PassTemplate {
+    /// Attachments of the render pass.
+    pub attachments: Vec<Attachment>,
+    /// Color attachments of the subpass.
+    pub colors: Vec<AttachmentRef>,
+    /// Depth stencil attachment of the subpass.
+    pub depth_stencil: Option<AttachmentRef>,
+    /// Dependencies on the commands before and after the render pass.
+    pub dependencies: Vec<SubpassDependency>,
+    /// View mask of the subpass, the render pass is created with multiview if set.
+    pub view_mask: Option<ViewMask>,
+}
+
+impl PassTemplate {
+    /// Depth only pass filling the depth buffer before the main pass.
+    ///
+    /// The depth is cleared and left in `DepthStencilAttachmentOptimal`, for the
+    /// main pass to load and test against.
+    pub fn depth_prepass(format: Format, samples: image::NumSamples) -> Self {
+        let depth_stages = PipelineStage::EARLY_FRAGMENT_TESTS | PipelineStage::LATE_FRAGMENT_TESTS;
+        let depth_accesses = image::Access::DEPTH_STENCIL_ATTACHMENT_READ | image::Access::DEPTH_STENCIL_ATTACHMENT_WRITE;
+        PassTemplate {
+            attachments: vec![Attachment {
+                format: Some(format),
+                samples,
+                ops: AttachmentOps::new(AttachmentLoadOp::Clear, AttachmentStoreOp::Store),
+                stencil_ops: AttachmentOps::DONT_CARE,
+                layouts: image::Layout::Undefined .. image::Layout::DepthStencilAttachmentOptimal,
+            }],
+            colors: Vec::new(),
+            depth_stencil: Some((0, image::Layout::DepthStencilAttachmentOptimal)),
+            dependencies: vec![
+                // Depth tests of the previous frame's main pass.
+                SubpassDependency {
+                    passes: SubpassRef::External .. SubpassRef::Pass(0),
+                    stages: depth_stages .. depth_stages,
+                    accesses: depth_accesses .. depth_accesses,
+                },
+                // Depth tests of the main pass.
+                SubpassDependency {
+                    passes: SubpassRef::Pass(0) .. SubpassRef::External,
+                    stages: depth_stages .. depth_stages,
+                    accesses: image::Access::DEPTH_STENCIL_ATTACHMENT_WRITE .. depth_accesses,
+                },
+            ],
+            view_mask: None,
+        }
+    }
+
+    /// Depth only pass rendering shadow cascades, sampled afterwards by fragment shaders.
+    ///
+    /// With more than one cascade the pass is rendered with multiview, one view per
+    /// layer of the depth attachment, which requires `Features::MULTIVIEW`. `create`
+    /// fails without it, check `required_features` to fall back to a pass per cascade.
+    /// The depth is left in `ShaderReadOnlyOptimal`.
+    pub fn shadow_cascades(format: Format, cascades: u32) -> Self {
+        assert!(cascades > 0 && cascades <= 32);
+        let depth_stages = PipelineStage::EARLY_FRAGMENT_TESTS | PipelineStage::LATE_FRAGMENT_TESTS;
+        PassTemplate {
+            attachments: vec![Attachment {
+                format: Some(format),
+                samples: 1,
+                ops: AttachmentOps::new(AttachmentLoadOp::Clear, AttachmentStoreOp::Store),
+                stencil_ops: AttachmentOps::DONT_CARE,
+                layouts: image::Layout::Undefined .. image::Layout::ShaderReadOnlyOptimal,
+            }],
+            colors: Vec::new(),
+            depth_stencil: Some((0, image::Layout::DepthStencilAttachmentOptimal)),
+            dependencies: vec![
+                // Shadow lookups of the previous frame.
+                SubpassDependency {
+                    passes: SubpassRef::External .. SubpassRef::Pass(0),
+                    stages: PipelineStage::FRAGMENT_SHADER .. depth_stages,
+                    accesses: image::Access::SHADER_READ .. image::Access::DEPTH_STENCIL_ATTACHMENT_WRITE,
+                },
+                SubpassDependency {
+                    passes: SubpassRef::Pass(0) .. SubpassRef::External,
+                    stages: PipelineStage::LATE_FRAGMENT_TESTS .. PipelineStage::FRAGMENT_SHADER,
+                    accesses: image::Access::DEPTH_STENCIL_ATTACHMENT_WRITE .. image::Access::SHADER_READ,
+                },
+            ],
+            view_mask: if cascades > 1 { Some((!0u32) >> (32 - cascades)) } else { None },
+        }
+    }
+
+    /// Color only pass drawing a fullscreen primitive, sampling the output of the
+    /// previous pass.
+    ///
+    /// The previous contents of the target are discarded. The target is left in
+    /// `final_layout`, e.g. `Present` for a swapchain image.
+    pub fn fullscreen_post(format: Format, final_layout: image::Layout) -> Self {
+        PassTemplate {
+            attachments: vec![Attachment {
+                format: Some(format),
+                samples: 1,
+                ops: AttachmentOps::new(AttachmentLoadOp::DontCare, AttachmentStoreOp::Store),
+                stencil_ops: AttachmentOps::DONT_CARE,
+                layouts: image::Layout::Undefined .. final_layout,
+            }],
+            colors: vec![(0, image::Layout::ColorAttachmentOptimal)],
+            depth_stencil: None,
+            dependencies: vec![
+                // Color writes of the previous pass, either sampled or to the same target.
+                SubpassDependency {
+                    passes: SubpassRef::External .. SubpassRef::Pass(0),
+                    stages: PipelineStage::COLOR_ATTACHMENT_OUTPUT ..
+                        PipelineStage::FRAGMENT_SHADER | PipelineStage::COLOR_ATTACHMENT_OUTPUT,
+                    accesses: image::Access::COLOR_ATTACHMENT_WRITE ..
+                        image::Access::SHADER_READ | image::Access::COLOR_ATTACHMENT_WRITE,
+                },
+            ],
+            view_mask: None,
+        }
+    }
+
+    /// Description of the subpass.
+    pub fn subpass_desc(&self) -> SubpassDesc {
+        SubpassDesc {
+            colors: &self.colors,
+            depth_stencil: self.depth_stencil.as_ref(),
+            inputs: &[],
+            resolves: &[],
+            preserves: &[],
+        }
+    }
+
+    /// Device features required for creating the render pass, `Features::MULTIVIEW`
+    /// if the subpass renders more than the first view.
+    pub fn required_features(&self) -> Features {
+        match self.view_mask {
+            Some(mask) if mask != 1 => Features::MULTIVIEW,
+            _ => Features::empty(),
+        }
+    }
+
+    /// Create the render pass.
+    ///
+    /// Fails if the device doesn't support the `required_features`, instead of
+    /// rendering only the first view.
+    pub fn create<B: Backend, D: Device<B>>(&self, device: &D) -> Result<B::RenderPass, MultiviewError> {
+        let subpass = self.subpass_desc();
+        match self.view_mask {
+            Some(mask) => device.create_render_pass_multiview(
+                &self.attachments,
+                Some(subpass),
+                &self.dependencies,
+                &[mask],
+                &[mask],
+            ),
+            None => Ok(device.create_render_pass(&self.attachments, Some(subpass), &self.dependencies)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_required_features() {
+        let format = Format::D32Float;
+        assert_eq!(PassTemplate::depth_prepass(format, 1).required_features(), Features::empty());
+        assert_eq!(PassTemplate::shadow_cascades(format, 1).required_features(), Features::empty());
+        let cascades = PassTemplate::shadow_cascades(format, 4);
+        assert_eq!(cascades.view_mask, Some(0b1111));
+        assert_eq!(cascades.required_features(), Features::MULTIVIEW);
+    }
+}
@@ -139,6 +139,9 @@ impl<B: Backend, C> CommandPool<B, C> {
         if allow_pending_resubmit {
             flags |= CommandBufferFlags::SIMULTANEOUS_USE;
         }
+        if subpass.is_some() {
+            flags |= CommandBufferFlags::RENDER_PASS_CONTINUE;
+        }
         let inheritance_info = CommandBufferInheritanceInfo {
             subpass,
             framebuffer,
@@ -176,7 +179,7 @@ impl<B: Backend, C: Supports<Graphics>> CommandPool<B, C> {
         self.reserve_secondary(1);
 
         let buffer = &mut self.secondary_buffers[self.next_secondary_buffer];
-        let mut flags = S::FLAGS;
+        let mut flags = S::FLAGS | CommandBufferFlags::RENDER_PASS_CONTINUE;
         if allow_pending_resubmit {
             flags |= CommandBufferFlags::SIMULTANEOUS_USE;
         }
@@ -0,0 +1,63 @@
+//! Statistics of the recorded and submitted commands.
+//!
+//! Backends count the commands of submitted command buffers, which
+//! `Device::frame_statistics` returns as `FrameStatistics`. The recording of a single
+//! command buffer is described by `CommandBufferStatistics`, which helps splitting the
+//! work evenly across recording threads and catching oversized buffers before submission.
+
+/// Numbers of commands submitted since the statistics were last retrieved.
+#[derive(Clone, Copy, Debug, Default, Eq, Hash, PartialEq)]
+pub struct FrameStatistics {
+    /// Draw calls, including indirect ones.
+    pub draw_calls: u64,
+    /// Compute dispatches, including indirect ones.
+    pub dispatches: u64,
+    /// Pipeline barriers.
+    pub barriers: u64,
+    /// Descriptor set writes and copies performed by the device.
+    pub descriptor_updates: u64,
+    /// Graphics and compute pipeline binds.
+    pub pipeline_binds: u64,
+}
+
+impl FrameStatistics {
+    /// Accumulate the counters of `other` into these.
+    pub fn add(&mut self, other: &FrameStatistics) {
+        self.draw_calls += other.draw_calls;
+        self.dispatches += other.dispatches;
+        self.barriers += other.barriers;
+        self.descriptor_updates += other.descriptor_updates;
+        self.pipeline_binds += other.pipeline_binds;
+    }
+}
+
+/// Size of the recording of a single command buffer, since it was last begun or reset.
+///
+/// Returned by `RawCommandBuffer::statistics`. Backends which don't track their
+/// recording return empty statistics.
+#[derive(Clone, Copy, Debug, Default, Eq, Hash, PartialEq)]
+pub struct CommandBufferStatistics {
+    /// Commands recorded, including state changes and the commands of executed
+    /// secondary command buffers.
+    pub commands: u64,
+    /// Secondary command buffers executed, including nested ones.
+    pub secondary_buffers: u64,
+    /// Estimated device memory referenced by the commands in bytes.
+    ///
+    /// Only the buffer ranges read or written by copies, fills and updates of buffers
+    /// are accounted. Resources accessed through descriptors and the texels of images
+    /// aren't known to every backend while recording.
+    pub referenced_memory: u64,
+    /// Counters of the individual kinds of commands.
+    pub frame: FrameStatistics,
+}
+
+impl CommandBufferStatistics {
+    /// Accumulate the statistics of an executed secondary command buffer.
+    pub fn add_secondary(&mut self, secondary: &CommandBufferStatistics) {
+        self.commands += secondary.commands;
+        self.secondary_buffers += 1 + secondary.secondary_buffers;
+        self.referenced_memory += secondary.referenced_memory;
+        self.frame.add(&secondary.frame);
+    }
+}
@@ -0,0 +1,135 @@
+//! Portable on-disk container for pipeline caches.
+//!
+//! The data returned by `Device::get_pipeline_cache_data` is opaque and only valid on the
+//! device it was retrieved from. Wrapping it with `serialize_pipeline_cache` tags the data
+//! with the adapter it belongs to and a checksum, so stale or corrupted files left behind
+//! by a previous run are detected by `deserialize_pipeline_cache` instead of being passed
+//! on to the driver.
+//!
+//! Layout, all integers little endian:
+//!
+//! | Field          | Size |
+//! |----------------|------|
+//! | magic `GFXC`   | 4    |
+//! | version        | 4    |
+//! | vendor id      | 8    |
+//! | device id      | 8    |
+//! | adapter name   | 8    |
+//! | payload length | 8    |
+//! | payload hash   | 8    |
+//! | payload        | *    |
+//!
+//! Adapter name and payload are stored as FNV-1a hashes. The payload itself is validated by
+//! the backend on `Device::create_pipeline_cache`, which ignores data written by another
+//! driver version.
+
+use std::error::Error;
+use std::fmt;
+
+use adapter::AdapterInfo;
+
+const MAGIC: [u8; 4] = *b"GFXC";
+const HEADER_SIZE: usize = 48;
+
+/// Version of the container layout, bumped on incompatible changes.
+pub const PIPELINE_CACHE_VERSION: u32 = 1;
+
+/// Error reading a serialized pipeline cache.
+#[derive(Clone, Debug, PartialEq)]
+pub enum PipelineCacheError {
+    /// The data is too short or doesn't start with the container magic.
+    InvalidHeader,
+    /// The data was written by an incompatible container version.
+    Version(u32),
+    /// The data was written on a different adapter.
+    AdapterMismatch,
+    /// The payload is truncated or corrupted.
+    Corrupted,
+}
+
+impl fmt::Display for PipelineCacheError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            PipelineCacheError::Version(version) => write!(f, "{}: {}", self.description(), version),
+            _ => write!(f, "{}", self.description()),
+        }
+    }
+}
+
+impl Error for PipelineCacheError {
+    fn description(&self) -> &str {
+        match *self {
+            PipelineCacheError::InvalidHeader => "Invalid pipeline cache header.",
+            PipelineCacheError::Version(_) => "Unsupported pipeline cache version.",
+            PipelineCacheError::AdapterMismatch => "Pipeline cache was created on a different adapter.",
+            PipelineCacheError::Corrupted => "Pipeline cache data is corrupted.",
+        }
+    }
+}
+
+/// FNV-1a hash of `data`, as stored in the pipeline cache container.
+pub fn fnv1a(data: &[u8]) -> u64 {
+    data.iter().fold(0xcbf2_9ce4_8422_2325, |hash, &byte| {
+        (hash ^ byte as u64).wrapping_mul(0x0100_0000_01b3)
+    })
+}
+
+fn write_u64(out: &mut Vec<u8>, value: u64) {
+    for i in 0 .. 8 {
+        out.push((value >> (i * 8)) as u8);
+    }
+}
+
+fn read_le(data: &[u8]) -> u64 {
+    data.iter()
+        .enumerate()
+        .fold(0, |value, (i, &byte)| value | (byte as u64) << (i * 8))
+}
+
+/// Wrap pipeline cache data of a device into the portable container format.
+pub fn serialize_pipeline_cache(adapter: &AdapterInfo, data: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(HEADER_SIZE + data.len());
+    out.extend_from_slice(&MAGIC);
+    for i in 0 .. 4 {
+        out.push((PIPELINE_CACHE_VERSION >> (i * 8)) as u8);
+    }
+    write_u64(&mut out, adapter.vendor as u64);
+    write_u64(&mut out, adapter.device as u64);
+    write_u64(&mut out, fnv1a(adapter.name.as_bytes()));
+    write_u64(&mut out, data.len() as u64);
+    write_u64(&mut out, fnv1a(data));
+    out.extend_from_slice(data);
+    out
+}
+
+/// Extract the pipeline cache data from the portable container format, for
+/// passing on to `Device::create_pipeline_cache`.
+///
+/// Fails if the data was written on a different adapter or is corrupted.
+pub fn deserialize_pipeline_cache<'a>(
+    adapter: &AdapterInfo,
+    bytes: &'a [u8],
+) -> Result<&'a [u8], PipelineCacheError> {
+    if bytes.len() < HEADER_SIZE || bytes[.. 4] != MAGIC {
+        return Err(PipelineCacheError::InvalidHeader);
+    }
+
+    let version = read_le(&bytes[4 .. 8]) as u32;
+    if version != PIPELINE_CACHE_VERSION {
+        return Err(PipelineCacheError::Version(version));
+    }
+
+    if read_le(&bytes[8 .. 16]) != adapter.vendor as u64
+        || read_le(&bytes[16 .. 24]) != adapter.device as u64
+        || read_le(&bytes[24 .. 32]) != fnv1a(adapter.name.as_bytes())
+    {
+        return Err(PipelineCacheError::AdapterMismatch);
+    }
+
+    let payload = &bytes[HEADER_SIZE ..];
+    if read_le(&bytes[32 .. 40]) != payload.len() as u64 || read_le(&bytes[40 .. 48]) != fnv1a(payload) {
+        return Err(PipelineCacheError::Corrupted);
+    }
+
+    Ok(payload)
+}
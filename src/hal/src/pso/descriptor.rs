@@ -10,6 +10,7 @@ use std::fmt;
 use std::ops::Range;
 
 use {Backend};
+use device::Device;
 use buffer::Offset;
 use image::Layout;
 use pso::ShaderStageFlags;
@@ -78,6 +79,39 @@ pub struct DescriptorSetLayoutBinding {
     pub immutable_samplers: bool,
 }
 
+bitflags!(
+    /// Flags modifying the behavior of a binding of a descriptor set layout.
+    ///
+    /// Require `Features::DESCRIPTOR_INDEXING`, see `Device::create_descriptor_set_layout_with_flags`.
+    #[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+    pub struct DescriptorBindingFlags: u32 {
+        /// Descriptors may be written after the set was bound in a command buffer, as long as
+        /// the command buffer wasn't submitted yet. The set has to be allocated from a pool
+        /// created with `DescriptorPoolCreateFlags::UPDATE_AFTER_BIND`.
+        const UPDATE_AFTER_BIND = 0x1;
+        /// Descriptors not used by pending command buffers may be written while these execute.
+        const UPDATE_UNUSED_WHILE_PENDING = 0x2;
+        /// Descriptors not accessed dynamically by the shaders don't need to be valid.
+        const PARTIALLY_BOUND = 0x4;
+        /// The number of descriptors is chosen on allocation with
+        /// `DescriptorPool::allocate_set_with_count`, `count` being the upper bound.
+        ///
+        /// Only valid for the binding with the highest binding number.
+        const VARIABLE_DESCRIPTOR_COUNT = 0x8;
+    }
+);
+
+bitflags!(
+    /// Flags of a descriptor pool.
+    #[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+    pub struct DescriptorPoolCreateFlags: u32 {
+        /// Allow allocating sets with `DescriptorBindingFlags::UPDATE_AFTER_BIND` bindings.
+        ///
+        /// Requires `Features::DESCRIPTOR_INDEXING`.
+        const UPDATE_AFTER_BIND = 0x1;
+    }
+);
+
 /// Set of descriptors of a specific type.
 #[derive(Clone, Copy, Debug)]
 #[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
@@ -122,6 +156,18 @@ pub trait DescriptorPool<B: Backend>: Send + Sync + fmt::Debug {
         self.allocate_sets(Some(layout)).remove(0)
     }
 
+    /// Allocate a descriptor set whose `DescriptorBindingFlags::VARIABLE_DESCRIPTOR_COUNT` binding
+    /// holds `count` descriptors, at most the count specified in the layout.
+    ///
+    /// Backends without native support allocate the full count of the layout.
+    fn allocate_set_with_count(
+        &mut self,
+        layout: &B::DescriptorSetLayout,
+        _count: DescriptorArrayIndex,
+    ) -> Result<B::DescriptorSet, AllocationError> {
+        self.allocate_set(layout)
+    }
+
     /// Allocate one or multiple descriptor sets from the pool.
     ///
     /// Each descriptor set will be allocated from the pool according to the corresponding set layout.
@@ -165,16 +211,34 @@ pub struct DescriptorSetWrite<'a, B: Backend, W> {
 
 /// DOC TODO
 #[allow(missing_docs)]
-#[derive(Clone)]
 pub enum Descriptor<'a, B: Backend> {
     Sampler(&'a B::Sampler),
     Image(&'a B::ImageView, Layout),
     CombinedImageSampler(&'a B::ImageView, Layout, &'a B::Sampler),
+    /// Byte range of a uniform or storage buffer, so suballocated ranges of a single
+    /// buffer can be bound to different descriptors. `None` bounds default to the start
+    /// and end of the buffer. The start has to be a multiple of
+    /// `Limits::min_uniform_buffer_offset_alignment` or
+    /// `Limits::min_storage_buffer_offset_alignment` respectively.
     Buffer(&'a B::Buffer, Range<Option<Offset>>),
     UniformTexelBuffer(&'a B::BufferView),
     StorageTexelBuffer(&'a B::BufferView),
 }
 
+// Not derived, which would require `B: Clone`.
+impl<'a, B: Backend> Clone for Descriptor<'a, B> {
+    fn clone(&self) -> Self {
+        match *self {
+            Descriptor::Sampler(sampler) => Descriptor::Sampler(sampler),
+            Descriptor::Image(view, layout) => Descriptor::Image(view, layout),
+            Descriptor::CombinedImageSampler(view, layout, sampler) => Descriptor::CombinedImageSampler(view, layout, sampler),
+            Descriptor::Buffer(buffer, ref range) => Descriptor::Buffer(buffer, range.clone()),
+            Descriptor::UniformTexelBuffer(view) => Descriptor::UniformTexelBuffer(view),
+            Descriptor::StorageTexelBuffer(view) => Descriptor::StorageTexelBuffer(view),
+        }
+    }
+}
+
 
 /// DOC TODO
 #[allow(missing_docs)]
@@ -188,3 +252,88 @@ pub struct DescriptorSetCopy<'a, B: Backend> {
     pub dst_array_offset: DescriptorArrayIndex,
     pub count: usize,
 }
+
+/// Default descriptors of a set layout, from which many descriptor sets are written
+/// with only a few descriptors overridden, e.g. the instances of a material.
+///
+/// All sets are written by a single `Device::write_descriptor_sets` call, which the
+/// backends turn into one bulk update.
+///
+/// ```ignore
+/// let template = DescriptorSetTemplate::new()
+///     .binding(0, Some(Descriptor::Buffer(&material_params, None .. None)))
+///     .binding(1, Some(Descriptor::CombinedImageSampler(&white, Layout::ShaderReadOnlyOptimal, &sampler)));
+/// let sets = pool.allocate_sets(iter::repeat(&layout).take(instances.len()));
+/// template.write(&device, sets.iter().zip(&instances).map(|(set, instance)| {
+///     (set.as_ref().unwrap(), Some((1, 0, Descriptor::CombinedImageSampler(&instance.albedo, Layout::ShaderReadOnlyOptimal, &sampler))))
+/// }));
+/// ```
+pub struct DescriptorSetTemplate<'a, B: Backend> {
+    // Default descriptors of every array element of the bindings.
+    bindings: Vec<(DescriptorBinding, Vec<Descriptor<'a, B>>)>,
+}
+
+impl<'a, B: Backend> fmt::Debug for DescriptorSetTemplate<'a, B> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("DescriptorSetTemplate")
+            .field("bindings", &self.bindings.iter().map(|&(binding, ref descriptors)| (binding, descriptors.len())).collect::<Vec<_>>())
+            .finish()
+    }
+}
+
+impl<'a, B: Backend> DescriptorSetTemplate<'a, B> {
+    /// Create a template without bindings.
+    pub fn new() -> Self {
+        DescriptorSetTemplate {
+            bindings: Vec::new(),
+        }
+    }
+
+    /// Set the default descriptors of a binding, one per array element.
+    pub fn binding<I>(mut self, binding: DescriptorBinding, descriptors: I) -> Self
+    where
+        I: IntoIterator,
+        I::Item: Borrow<Descriptor<'a, B>>,
+    {
+        let descriptors = descriptors.into_iter().map(|d| d.borrow().clone()).collect();
+        match self.bindings.iter().position(|&(b, _)| b == binding) {
+            Some(index) => self.bindings[index].1 = descriptors,
+            None => self.bindings.push((binding, descriptors)),
+        }
+        self
+    }
+
+    /// Write the template into descriptor sets, each with its overrides of the defaults.
+    ///
+    /// An override `(binding, array_index, descriptor)` replaces the default descriptor
+    /// of an array element of one of the template bindings.
+    pub fn write<D, I, J>(&self, device: &D, instances: I)
+    where
+        D: Device<B>,
+        I: IntoIterator<Item = (&'a B::DescriptorSet, J)>,
+        J: IntoIterator,
+        J::Item: Borrow<(DescriptorBinding, DescriptorArrayIndex, Descriptor<'a, B>)>,
+    {
+        let mut writes = Vec::new();
+        for (set, overrides) in instances {
+            let first = writes.len();
+            for &(binding, ref descriptors) in &self.bindings {
+                writes.push(DescriptorSetWrite {
+                    set,
+                    binding,
+                    array_offset: 0,
+                    descriptors: descriptors.clone(),
+                });
+            }
+            for descriptor in overrides {
+                let (binding, index, ref descriptor) = *descriptor.borrow();
+                let write = writes[first ..]
+                    .iter_mut()
+                    .find(|write| write.binding == binding)
+                    .expect("Override of a binding missing in the template");
+                write.descriptors[index] = descriptor.clone();
+            }
+        }
+        device.write_descriptor_sets(writes);
+    }
+}
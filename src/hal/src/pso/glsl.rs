@@ -0,0 +1,26 @@
+//! Runtime GLSL compilation, enabled by the `glsl-to-spirv` feature.
+
+use std::io::Read;
+
+use glsl_to_spirv::{self, ShaderType};
+
+use device::ShaderError;
+use pso::Stage;
+
+/// Compile GLSL source code of a shader stage to SPIR-V.
+pub fn compile_glsl(stage: Stage, source: &str) -> Result<Vec<u8>, ShaderError> {
+    let ty = match stage {
+        Stage::Vertex => ShaderType::Vertex,
+        Stage::Hull => ShaderType::TessellationControl,
+        Stage::Domain => ShaderType::TessellationEvaluation,
+        Stage::Geometry => ShaderType::Geometry,
+        Stage::Fragment => ShaderType::Fragment,
+        Stage::Compute => ShaderType::Compute,
+    };
+
+    let mut file = glsl_to_spirv::compile(source, ty).map_err(ShaderError::CompilationFailed)?;
+    let mut spirv = Vec::new();
+    file.read_to_end(&mut spirv)
+        .map_err(|err| ShaderError::CompilationFailed(format!("Unable to read the SPIR-V output: {}", err)))?;
+    Ok(spirv)
+}
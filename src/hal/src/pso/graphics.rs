@@ -1,9 +1,9 @@
 //! Graphics pipeline descriptor.
 
-use {image, pass, Backend, Primitive};
+use {format, image, pass, Backend, Primitive};
 use super::{BasePipeline, EntryPoint, PipelineCreationFlags};
 use super::input_assembler::{AttributeDesc, InputAssemblerDesc, VertexBufferDesc};
-use super::output_merger::{ColorBlendDesc, DepthStencilDesc, Face};
+use super::output_merger::{BlendState, ColorBlendDesc, DepthStencilDesc, Face};
 
 use std::ops::Range;
 
@@ -216,6 +216,14 @@ pub struct Rasterizer {
     pub depth_bias: Option<DepthBias>,
     /// Controls how triangles will be rasterized depending on their overlap with pixels.
     pub conservative: bool,
+    /// Whether point primitives take their size from the `PointSize` output
+    /// of the last vertex processing stage, enabled by default. Points are
+    /// rasterized with a size of one pixel otherwise.
+    ///
+    /// Only GL supports both. Vulkan and Metal reject point list pipelines disabling it,
+    /// D3D11 and D3D12 reject the ones enabling it.
+    #[cfg_attr(feature = "serde", serde(default = "default_program_point_size"))]
+    pub program_point_size: bool,
 }
 
 impl Rasterizer {
@@ -227,9 +235,15 @@ impl Rasterizer {
         depth_clamping: false,
         depth_bias: None,
         conservative: false,
+        program_point_size: true,
     };
 }
 
+#[cfg(feature = "serde")]
+fn default_program_point_size() -> bool {
+    true
+}
+
 /// A description of an equation for how to blend transparent, overlapping fragments.
 #[derive(Clone, Debug, Default, PartialEq)]
 #[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
@@ -238,6 +252,48 @@ pub struct BlendDesc {
     pub logic_op: Option<LogicOp>,
     /// Which color targets to apply the blending operation to.
     pub targets: Vec<ColorBlendDesc>,
+    /// Conversion of the colors written to sRGB targets.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub srgb: SrgbConversion,
+}
+
+impl BlendDesc {
+    /// Returns true if one of the targets blending is enabled for has an sRGB format.
+    ///
+    /// `formats` are the formats of the color attachments of the subpass, in order.
+    pub fn blends_srgb<I>(&self, formats: I) -> bool
+    where
+        I: IntoIterator<Item = Option<format::Format>>,
+    {
+        self.targets
+            .iter()
+            .zip(formats)
+            .any(|(target, format)| {
+                target.1 != BlendState::Off && format.map_or(false, |format| format.is_srgb())
+            })
+    }
+}
+
+/// Conversion of the colors written to and blended with sRGB color targets.
+///
+/// Backends differ in how blending into sRGB targets behaves by default, e.g. GL only
+/// converts with `GL_FRAMEBUFFER_SRGB` enabled. The conversion is chosen explicitly
+/// per pipeline so the output matches across backends.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum SrgbConversion {
+    /// Colors are encoded to sRGB when written, and blending happens in linear space
+    /// on the decoded destination. This is the behavior of Vulkan, D3D and Metal.
+    Linear,
+    /// Colors are written as is, and blending happens on the encoded values. Only
+    /// supported by GL, other backends have to render into a UNORM view of the target.
+    Encoded,
+}
+
+impl Default for SrgbConversion {
+    fn default() -> Self {
+        SrgbConversion::Linear
+    }
 }
 
 /// Logic operations used for specifying blend equations.
@@ -268,6 +324,7 @@ pub type SampleMask = u64;
 
 ///
 #[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct Multisampling {
     ///
     pub rasterization_samples: image::NumSamples,
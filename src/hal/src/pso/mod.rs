@@ -4,21 +4,29 @@
 //! will want to use the typed and safe `PipelineState`. See the `pso` module inside the `gfx`
 //! crate.
 
-use {device, pass};
+use {device, error, pass};
 use std::error::Error;
 use std::fmt;
 
+mod cache;
 mod compute;
 mod descriptor;
+#[cfg(feature = "glsl-to-spirv")]
+mod glsl;
 mod graphics;
 mod input_assembler;
 mod output_merger;
+mod reflect;
 
+pub use self::cache::*;
 pub use self::compute::*;
 pub use self::descriptor::*;
+#[cfg(feature = "glsl-to-spirv")]
+pub use self::glsl::*;
 pub use self::graphics::*;
 pub use self::input_assembler::*;
 pub use self::output_merger::*;
+pub use self::reflect::*;
 
 use Backend;
 
@@ -31,6 +39,20 @@ pub enum CreationError {
     InvalidSubpass(pass::SubpassId),
     /// Shader compilation error.
     Shader(device::ShaderError),
+    /// Backend error, with the diagnostic output of the backend explaining the failure.
+    Backend(error::ErrorContext),
+}
+
+impl CreationError {
+    /// Attach the diagnostic output of the backend to an unspecific `Other` error.
+    ///
+    /// Other errors already describe the failure and are returned unchanged.
+    pub fn with_context(self, context: error::ErrorContext) -> Self {
+        match self {
+            CreationError::Other if !context.is_empty() => CreationError::Backend(context),
+            error => error,
+        }
+    }
 }
 
 impl fmt::Display for CreationError {
@@ -38,6 +60,7 @@ impl fmt::Display for CreationError {
         match *self {
             CreationError::InvalidSubpass(id) => write!(f, "{}: {:?}", self.description(), id),
             CreationError::Shader(ref err) => write!(f, "{}: {:?}", self.description(), err),
+            CreationError::Backend(ref context) => write!(f, "{}:\n{}", self.description(), context),
             _ => write!(f, "{}", self.description()),
         }
     }
@@ -49,6 +72,7 @@ impl Error for CreationError {
             CreationError::Other => "Unknown other error.",
             CreationError::InvalidSubpass(_) => "Invalid subpass index.",
             CreationError::Shader(_) => "Shader compilation error.",
+            CreationError::Backend(_) => "Backend error.",
         }
     }
 }
@@ -209,7 +233,8 @@ pub enum BasePipeline<'a, P: 'a> {
 /// but they share most of the internal stuff and are fast to produce. 
 /// More importantly, they are fast to execute, since the driver 
 /// can optimize out the branch on that other PSO creation.
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct Specialization {
     /// Constant identifier in shader source.
     pub id: u32,
@@ -219,7 +244,8 @@ pub struct Specialization {
 
 /// Scalar specialization constant with value for overriding.
 #[allow(missing_docs)]
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub enum Constant {
     Bool(bool),
     U32(u32),
@@ -230,6 +256,23 @@ pub enum Constant {
     F64(f64),
 }
 
+impl Constant {
+    /// Raw bits of the value, as passed to the shader translators.
+    ///
+    /// Values narrower than 64 bits are zero-extended.
+    pub fn to_bits(&self) -> u64 {
+        match *self {
+            Constant::Bool(v) => v as u64,
+            Constant::U32(v) => v as u64,
+            Constant::U64(v) => v,
+            Constant::I32(v) => v as u32 as u64,
+            Constant::I64(v) => v as u64,
+            Constant::F32(v) => v.to_bits() as u64,
+            Constant::F64(v) => v.to_bits(),
+        }
+    }
+}
+
 /// Pipeline state which may be static or dynamic.
 #[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
 #[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
@@ -0,0 +1,1065 @@
+//! Lightweight SPIR-V reflection.
+//!
+//! Backends translate SPIR-V through their own toolchains, but a few properties of a shader
+//! module need to be known up-front in order to validate pipeline creation consistently.
+
+use std::collections::{HashMap, HashSet};
+
+use std::ops::Range;
+
+use device::ShaderError;
+use format::Format;
+use pso::{
+    Constant, DescriptorArrayIndex, DescriptorBinding, DescriptorSetLayoutBinding,
+    DescriptorType, ShaderStageFlags, Specialization, Stage,
+};
+use Features;
+
+const MAGIC_NUMBER: u32 = 0x0723_0203;
+const HEADER_LENGTH: usize = 5;
+
+const OP_NAME: u16 = 5;
+const OP_STRING: u16 = 7;
+const OP_EXT_INST_IMPORT: u16 = 11;
+const OP_MEMORY_MODEL: u16 = 14;
+const OP_ENTRY_POINT: u16 = 15;
+const OP_EXECUTION_MODE: u16 = 16;
+const OP_CAPABILITY: u16 = 17;
+const OP_TYPE_VOID: u16 = 19;
+const OP_TYPE_INT: u16 = 21;
+const OP_TYPE_FLOAT: u16 = 22;
+const OP_TYPE_VECTOR: u16 = 23;
+const OP_TYPE_MATRIX: u16 = 24;
+const OP_TYPE_IMAGE: u16 = 25;
+const OP_TYPE_SAMPLER: u16 = 26;
+const OP_TYPE_SAMPLED_IMAGE: u16 = 27;
+const OP_TYPE_ARRAY: u16 = 28;
+const OP_TYPE_RUNTIME_ARRAY: u16 = 29;
+const OP_TYPE_STRUCT: u16 = 30;
+const OP_TYPE_POINTER: u16 = 32;
+const OP_TYPE_PIPE: u16 = 38;
+const OP_CONSTANT_TRUE: u16 = 41;
+const OP_CONSTANT: u16 = 43;
+const OP_CONSTANT_COMPOSITE: u16 = 44;
+const OP_SPEC_CONSTANT: u16 = 50;
+const OP_SPEC_CONSTANT_COMPOSITE: u16 = 51;
+const OP_SPEC_CONSTANT_OP: u16 = 52;
+const OP_FUNCTION: u16 = 54;
+const OP_FUNCTION_PARAMETER: u16 = 55;
+const OP_FUNCTION_END: u16 = 56;
+const OP_VARIABLE: u16 = 59;
+const OP_IMAGE_TEXEL_POINTER: u16 = 60;
+const OP_ACCESS_CHAIN: u16 = 65;
+const OP_IN_BOUNDS_ACCESS_CHAIN: u16 = 66;
+const OP_PTR_ACCESS_CHAIN: u16 = 67;
+const OP_DECORATE: u16 = 71;
+const OP_MEMBER_DECORATE: u16 = 72;
+const OP_COPY_OBJECT: u16 = 83;
+const OP_ATOMIC_LOAD: u16 = 227;
+const OP_ATOMIC_STORE: u16 = 228;
+const OP_ATOMIC_XOR: u16 = 242;
+const OP_LABEL: u16 = 248;
+const OP_BRANCH: u16 = 249;
+const OP_UNREACHABLE: u16 = 255;
+const OP_EXECUTION_MODE_ID: u16 = 331;
+const OP_TERMINATE_INVOCATION: u16 = 4416;
+const OP_IGNORE_INTERSECTION_KHR: u16 = 4448;
+const OP_TERMINATE_RAY_KHR: u16 = 4449;
+const OP_EMIT_MESH_TASKS_EXT: u16 = 5294;
+const OP_ATOMIC_FMIN_EXT: u16 = 5614;
+const OP_ATOMIC_FMAX_EXT: u16 = 5615;
+const OP_ATOMIC_FADD_EXT: u16 = 6035;
+
+const CAPABILITY_LINKAGE: u32 = 5;
+const CAPABILITY_FLOAT16: u32 = 9;
+const CAPABILITY_INT16: u32 = 22;
+const CAPABILITY_INT8: u32 = 39;
+const CAPABILITY_GROUP_NON_UNIFORM: u32 = 61;
+const CAPABILITY_GROUP_NON_UNIFORM_VOTE: u32 = 62;
+const CAPABILITY_GROUP_NON_UNIFORM_ARITHMETIC: u32 = 63;
+const CAPABILITY_GROUP_NON_UNIFORM_BALLOT: u32 = 64;
+const CAPABILITY_GROUP_NON_UNIFORM_SHUFFLE: u32 = 65;
+const CAPABILITY_GROUP_NON_UNIFORM_SHUFFLE_RELATIVE: u32 = 66;
+const CAPABILITY_GROUP_NON_UNIFORM_QUAD: u32 = 68;
+const CAPABILITY_STORAGE_BUFFER_16BIT_ACCESS: u32 = 4433;
+const CAPABILITY_STORAGE_PUSH_CONSTANT_16: u32 = 4435;
+const CAPABILITY_STORAGE_INPUT_OUTPUT_16: u32 = 4436;
+const CAPABILITY_MULTI_VIEW: u32 = 4439;
+const CAPABILITY_STORAGE_BUFFER_8BIT_ACCESS: u32 = 4448;
+const CAPABILITY_STORAGE_PUSH_CONSTANT_8: u32 = 4450;
+const CAPABILITY_STENCIL_EXPORT_EXT: u32 = 5013;
+const CAPABILITY_SHADER_CLOCK_KHR: u32 = 5055;
+const CAPABILITY_FRAGMENT_BARYCENTRIC_KHR: u32 = 5284;
+
+const STORAGE_CLASS_UNIFORM_CONSTANT: u32 = 0;
+const STORAGE_CLASS_INPUT: u32 = 1;
+const STORAGE_CLASS_UNIFORM: u32 = 2;
+const STORAGE_CLASS_OUTPUT: u32 = 3;
+const STORAGE_CLASS_WORKGROUP: u32 = 4;
+const STORAGE_CLASS_PUSH_CONSTANT: u32 = 9;
+const STORAGE_CLASS_IMAGE: u32 = 11;
+const STORAGE_CLASS_STORAGE_BUFFER: u32 = 12;
+
+const DIM_BUFFER: u32 = 5;
+const DIM_SUBPASS_DATA: u32 = 6;
+
+const EXECUTION_MODE_LOCAL_SIZE: u32 = 17;
+const EXECUTION_MODE_LOCAL_SIZE_ID: u32 = 38;
+
+const DECORATION_SPEC_ID: u32 = 1;
+const DECORATION_BLOCK: u32 = 2;
+const DECORATION_BUFFER_BLOCK: u32 = 3;
+const DECORATION_ARRAY_STRIDE: u32 = 6;
+const DECORATION_MATRIX_STRIDE: u32 = 7;
+const DECORATION_BUILT_IN: u32 = 11;
+const DECORATION_LOCATION: u32 = 30;
+const DECORATION_BINDING: u32 = 33;
+const DECORATION_DESCRIPTOR_SET: u32 = 34;
+const DECORATION_OFFSET: u32 = 35;
+const BUILT_IN_WORKGROUP_SIZE: u32 = 25;
+
+const EXT_INST_SET_DEBUG_PRINTF: &str = "NonSemantic.DebugPrintf";
+
+/// Properties of a shader module gathered from its SPIR-V code.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ShaderInfo {
+    /// Device features required by the module.
+    pub required_features: Features,
+    /// Entry points declared by the module.
+    pub entry_points: Vec<EntryPointInfo>,
+    /// Descriptor bindings declared by the module.
+    pub descriptor_bindings: Vec<DescriptorBindingInfo>,
+    /// Byte range of the push constant block, if the module declares one.
+    pub push_constants: Option<Range<u32>>,
+}
+
+impl ShaderInfo {
+    /// Find an entry point by name.
+    pub fn entry_point(&self, name: &str) -> Option<&EntryPointInfo> {
+        self.entry_points.iter().find(|ep| ep.name == name)
+    }
+
+    /// Stages of the entry points.
+    pub fn stages(&self) -> ShaderStageFlags {
+        self.entry_points
+            .iter()
+            .filter_map(|ep| ep.stage)
+            .fold(ShaderStageFlags::empty(), |stages, stage| stages | stage.into())
+    }
+
+    /// Bindings of the descriptor set layout at index `set`, visible to the stages of
+    /// the module.
+    ///
+    /// The bindings of the modules of a pipeline have to be merged for its layout.
+    pub fn descriptor_set_layout_bindings(&self, set: u32) -> Vec<DescriptorSetLayoutBinding> {
+        let stage_flags = self.stages();
+        self.descriptor_bindings
+            .iter()
+            .filter(|binding| binding.set == set)
+            .map(|binding| DescriptorSetLayoutBinding {
+                binding: binding.binding,
+                ty: binding.ty,
+                count: binding.count,
+                stage_flags,
+                immutable_samplers: false,
+            })
+            .collect()
+    }
+
+    /// Push constant range for `Device::create_pipeline_layout`, in 32-bit words.
+    pub fn push_constant_range(&self) -> Option<(ShaderStageFlags, Range<u32>)> {
+        self.push_constants
+            .as_ref()
+            .map(|range| (self.stages(), range.start / 4 .. (range.end + 3) / 4))
+    }
+}
+
+/// Descriptor binding declared by a shader module.
+#[derive(Clone, Debug, PartialEq)]
+pub struct DescriptorBindingInfo {
+    /// Descriptor set index.
+    pub set: u32,
+    /// Binding within the set.
+    pub binding: DescriptorBinding,
+    /// Type of the descriptors.
+    pub ty: DescriptorType,
+    /// Number of descriptors, 0 for a runtime sized array.
+    pub count: DescriptorArrayIndex,
+    /// Name of the variable, if the module has debug names.
+    pub name: Option<String>,
+}
+
+/// Input or output variable of an entry point.
+#[derive(Clone, Debug, PartialEq)]
+pub struct InterfaceVariableInfo {
+    /// Location of the variable.
+    pub location: u32,
+    /// Format of the variable, if it's a 32-bit scalar or vector.
+    pub format: Option<Format>,
+    /// Name of the variable, if the module has debug names.
+    pub name: Option<String>,
+}
+
+/// Properties of a single entry point of a shader module.
+#[derive(Clone, Debug, PartialEq)]
+pub struct EntryPointInfo {
+    /// Entry point name.
+    pub name: String,
+    /// Shader stage of the entry point, if it maps onto one of the HAL stages.
+    pub stage: Option<Stage>,
+    /// Workgroup size declared by a compute entry point, before specialization.
+    /// All dimensions are 0 for other stages.
+    pub workgroup_size: [u32; 3],
+    /// Specialization constant ids overriding each dimension of the workgroup size.
+    pub workgroup_size_spec_ids: [Option<u32>; 3],
+    /// Input variables with a location, built-ins excluded.
+    pub inputs: Vec<InterfaceVariableInfo>,
+    /// Output variables with a location, built-ins excluded.
+    pub outputs: Vec<InterfaceVariableInfo>,
+}
+
+impl EntryPointInfo {
+    /// Workgroup size of the entry point after applying the given specialization constants.
+    ///
+    /// Only needed where the workgroup size isn't part of the compiled shader, like on Metal.
+    pub fn specialized_workgroup_size(&self, specialization: &[Specialization]) -> [u32; 3] {
+        let mut size = self.workgroup_size;
+        for (dim, spec_id) in size.iter_mut().zip(self.workgroup_size_spec_ids.iter()) {
+            let value = spec_id.and_then(|id| specialization.iter().find(|s| s.id == id));
+            *dim = match value.map(|s| &s.value) {
+                Some(&Constant::U32(v)) => v,
+                Some(&Constant::I32(v)) => v as u32,
+                Some(&Constant::U64(v)) => v as u32,
+                Some(&Constant::I64(v)) => v as u32,
+                _ => *dim,
+            };
+        }
+        size
+    }
+}
+
+/// Single instruction of a SPIR-V module.
+struct Instruction<'a> {
+    opcode: u16,
+    operands: &'a [u32],
+}
+
+/// Scalar types an atomic operation may act on.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Scalar {
+    Int(u32),
+    Float(u32),
+}
+
+/// Types relevant to the layout of the resources and interface variables.
+#[derive(Clone, Debug, PartialEq)]
+enum Type {
+    Int { width: u32, signed: bool },
+    Float { width: u32 },
+    Vector { component: u32, count: u32 },
+    Matrix { column: u32, count: u32 },
+    Image { dim: u32, sampled: u32 },
+    Sampler,
+    SampledImage,
+    /// Element type and id of the length constant.
+    Array { element: u32, length: u32 },
+    RuntimeArray { element: u32 },
+    Struct { members: Vec<u32> },
+}
+
+/// Types and decorations of a module, for the reflection of its resources.
+#[derive(Default)]
+struct Layouts {
+    types: HashMap<u32, Type>,
+    // (Id, decoration) -> first literal operand
+    decorations: HashMap<(u32, u32), u32>,
+    // (Struct id, member, decoration) -> first literal operand
+    member_decorations: HashMap<(u32, u32, u32), u32>,
+    names: HashMap<u32, String>,
+}
+
+impl Layouts {
+    fn decoration(&self, id: u32, decoration: u32) -> Option<u32> {
+        self.decorations.get(&(id, decoration)).cloned()
+    }
+
+    // Size in bytes of a type in an explicitly laid out block.
+    fn size_of(&self, ty: u32, constants: &HashMap<u32, u32>) -> u32 {
+        match self.types.get(&ty) {
+            Some(&Type::Int { width, .. }) |
+            Some(&Type::Float { width }) => width / 8,
+            Some(&Type::Vector { component, count }) => self.size_of(component, constants) * count,
+            // Matrices are only laid out by the decorations of the block members.
+            Some(&Type::Matrix { column, count }) => self.size_of(column, constants) * count,
+            Some(&Type::Array { element, length }) => {
+                let stride = self
+                    .decoration(ty, DECORATION_ARRAY_STRIDE)
+                    .unwrap_or_else(|| self.size_of(element, constants));
+                stride * constants.get(&length).cloned().unwrap_or(0)
+            }
+            Some(&Type::Struct { ref members }) => members
+                .iter()
+                .enumerate()
+                .map(|(i, &member)| self.member_end(ty, i as u32, member, constants))
+                .max()
+                .unwrap_or(0),
+            _ => 0,
+        }
+    }
+
+    // End offset of a struct member.
+    fn member_end(&self, ty: u32, index: u32, member: u32, constants: &HashMap<u32, u32>) -> u32 {
+        let offset = self.member_decorations.get(&(ty, index, DECORATION_OFFSET)).cloned().unwrap_or(0);
+        let size = match (self.types.get(&member), self.member_decorations.get(&(ty, index, DECORATION_MATRIX_STRIDE))) {
+            (Some(&Type::Matrix { count, .. }), Some(&stride)) => stride * count,
+            _ => self.size_of(member, constants),
+        };
+        offset + size
+    }
+
+    // Descriptor type and count of a resource variable type.
+    fn descriptor(&self, storage_class: u32, ty: u32, constants: &HashMap<u32, u32>) -> Option<(DescriptorType, DescriptorArrayIndex)> {
+        let (ty, count) = match self.types.get(&ty) {
+            Some(&Type::Array { element, length }) => {
+                (element, constants.get(&length).cloned().unwrap_or(1) as DescriptorArrayIndex)
+            }
+            Some(&Type::RuntimeArray { element }) => (element, 0),
+            _ => (ty, 1),
+        };
+        let descriptor_type = match (storage_class, self.types.get(&ty)) {
+            (STORAGE_CLASS_UNIFORM_CONSTANT, Some(&Type::Sampler)) => DescriptorType::Sampler,
+            (STORAGE_CLASS_UNIFORM_CONSTANT, Some(&Type::SampledImage)) => DescriptorType::CombinedImageSampler,
+            (STORAGE_CLASS_UNIFORM_CONSTANT, Some(&Type::Image { dim, sampled })) => match (dim, sampled) {
+                (DIM_BUFFER, 2) => DescriptorType::StorageTexelBuffer,
+                (DIM_BUFFER, _) => DescriptorType::UniformTexelBuffer,
+                (DIM_SUBPASS_DATA, _) => DescriptorType::InputAttachment,
+                (_, 2) => DescriptorType::StorageImage,
+                _ => DescriptorType::SampledImage,
+            },
+            (STORAGE_CLASS_UNIFORM, _) if self.decoration(ty, DECORATION_BUFFER_BLOCK).is_some() => {
+                DescriptorType::StorageBuffer
+            }
+            (STORAGE_CLASS_UNIFORM, _) if self.decoration(ty, DECORATION_BLOCK).is_some() => {
+                DescriptorType::UniformBuffer
+            }
+            (STORAGE_CLASS_STORAGE_BUFFER, _) => DescriptorType::StorageBuffer,
+            _ => return None,
+        };
+        Some((descriptor_type, count))
+    }
+
+    // Format of a 32-bit scalar or vector type.
+    fn format(&self, ty: u32) -> Option<Format> {
+        let (component, count) = match self.types.get(&ty) {
+            Some(&Type::Vector { component, count }) => (component, count),
+            _ => (ty, 1),
+        };
+        let formats = match self.types.get(&component) {
+            Some(&Type::Float { width: 32 }) => {
+                [Format::R32Float, Format::Rg32Float, Format::Rgb32Float, Format::Rgba32Float]
+            }
+            Some(&Type::Int { width: 32, signed: true }) => {
+                [Format::R32Int, Format::Rg32Int, Format::Rgb32Int, Format::Rgba32Int]
+            }
+            Some(&Type::Int { width: 32, signed: false }) => {
+                [Format::R32Uint, Format::Rg32Uint, Format::Rgb32Uint, Format::Rgba32Uint]
+            }
+            _ => return None,
+        };
+        formats.get(count as usize - 1).cloned()
+    }
+}
+
+fn parse_string(operands: &[u32]) -> (String, &[u32]) {
+    let mut bytes = Vec::new();
+    for (i, &word) in operands.iter().enumerate() {
+        for shift in &[0, 8, 16, 24] {
+            let byte = (word >> shift) as u8;
+            if byte == 0 {
+                return (String::from_utf8_lossy(&bytes).into_owned(), &operands[i + 1 ..]);
+            }
+            bytes.push(byte);
+        }
+    }
+    (String::from_utf8_lossy(&bytes).into_owned(), &[])
+}
+
+fn map_execution_model(model: u32) -> Option<Stage> {
+    match model {
+        0 => Some(Stage::Vertex),
+        1 => Some(Stage::Hull),
+        2 => Some(Stage::Domain),
+        3 => Some(Stage::Geometry),
+        4 => Some(Stage::Fragment),
+        5 => Some(Stage::Compute),
+        _ => None,
+    }
+}
+
+fn parse_words(spirv: &[u8]) -> Result<Vec<u32>, ShaderError> {
+    if spirv.len() % 4 != 0 || spirv.len() < HEADER_LENGTH * 4 {
+        return Err(ShaderError::CompilationFailed("Invalid SPIR-V module size".into()));
+    }
+
+    let mut words = spirv
+        .chunks(4)
+        .map(|c| c[0] as u32 | (c[1] as u32) << 8 | (c[2] as u32) << 16 | (c[3] as u32) << 24)
+        .collect::<Vec<_>>();
+
+    if words[0] == MAGIC_NUMBER.swap_bytes() {
+        for word in &mut words {
+            *word = word.swap_bytes();
+        }
+    } else if words[0] != MAGIC_NUMBER {
+        return Err(ShaderError::CompilationFailed("Invalid SPIR-V magic number".into()));
+    }
+
+    Ok(words)
+}
+
+fn parse_instructions(words: &[u32]) -> Result<Vec<Instruction>, ShaderError> {
+    let mut instructions = Vec::new();
+    let mut rest = &words[HEADER_LENGTH ..];
+
+    while !rest.is_empty() {
+        let count = (rest[0] >> 16) as usize;
+        if count == 0 || count > rest.len() {
+            return Err(ShaderError::CompilationFailed("Invalid SPIR-V instruction length".into()));
+        }
+        instructions.push(Instruction {
+            opcode: rest[0] as u16,
+            operands: &rest[1 .. count],
+        });
+        rest = &rest[count ..];
+    }
+
+    Ok(instructions)
+}
+
+/// Gather the properties of a SPIR-V shader module.
+pub fn reflect_spirv(spirv: &[u8]) -> Result<ShaderInfo, ShaderError> {
+    let words = parse_words(spirv)?;
+    let instructions = parse_instructions(&words)?;
+
+    let mut scalars = HashMap::new();
+    // Pointer type id -> (storage class, pointee type id)
+    let mut pointer_types = HashMap::new();
+    // Pointer id -> pointer type id
+    let mut pointers = HashMap::new();
+    let mut atomic_pointers = Vec::new();
+
+    // Function id -> entry point
+    let mut entry_points = Vec::new();
+    // Function id -> workgroup size, either literal values or constant ids
+    let mut local_sizes = HashMap::new();
+    let mut local_size_ids = HashMap::new();
+    // Constant id -> value
+    let mut constants = HashMap::new();
+    // Composite constant id -> constituent ids
+    let mut composites = HashMap::new();
+    let mut spec_ids = HashMap::new();
+    let mut workgroup_size_builtin = None;
+    let mut required_features = Features::empty();
+    let mut layouts = Layouts::default();
+    // (Variable id, pointer type id, storage class)
+    let mut variables = Vec::new();
+
+    for inst in &instructions {
+        let operands = inst.operands;
+        match inst.opcode {
+            OP_ENTRY_POINT if operands.len() >= 3 => {
+                let (name, interface) = parse_string(&operands[2 ..]);
+                entry_points.push((operands[1], map_execution_model(operands[0]), name, interface));
+            }
+            OP_NAME if operands.len() >= 2 => {
+                let (name, _) = parse_string(&operands[1 ..]);
+                layouts.names.insert(operands[0], name);
+            }
+            OP_EXT_INST_IMPORT if operands.len() >= 2 => {
+                let (name, _) = parse_string(&operands[1 ..]);
+                if name == EXT_INST_SET_DEBUG_PRINTF {
+                    required_features |= Features::SHADER_PRINTF;
+                }
+            }
+            OP_CAPABILITY if !operands.is_empty() => {
+                required_features |= match operands[0] {
+                    CAPABILITY_FLOAT16 => Features::SHADER_FLOAT16,
+                    CAPABILITY_INT16 => Features::SHADER_INT16,
+                    CAPABILITY_INT8 => Features::SHADER_INT8,
+                    CAPABILITY_STORAGE_BUFFER_16BIT_ACCESS ..= CAPABILITY_STORAGE_PUSH_CONSTANT_16 => {
+                        Features::STORAGE_16BIT
+                    }
+                    CAPABILITY_STORAGE_INPUT_OUTPUT_16 => Features::STORAGE_INPUT_OUTPUT_16BIT,
+                    CAPABILITY_STORAGE_BUFFER_8BIT_ACCESS ..= CAPABILITY_STORAGE_PUSH_CONSTANT_8 => {
+                        Features::STORAGE_8BIT
+                    }
+                    CAPABILITY_GROUP_NON_UNIFORM => Features::SUBGROUP_BASIC,
+                    CAPABILITY_GROUP_NON_UNIFORM_VOTE => Features::SUBGROUP_VOTE,
+                    CAPABILITY_GROUP_NON_UNIFORM_ARITHMETIC => Features::SUBGROUP_ARITHMETIC,
+                    CAPABILITY_GROUP_NON_UNIFORM_BALLOT => Features::SUBGROUP_BALLOT,
+                    CAPABILITY_GROUP_NON_UNIFORM_SHUFFLE |
+                    CAPABILITY_GROUP_NON_UNIFORM_SHUFFLE_RELATIVE => Features::SUBGROUP_SHUFFLE,
+                    CAPABILITY_GROUP_NON_UNIFORM_QUAD => Features::SUBGROUP_QUAD,
+                    CAPABILITY_STENCIL_EXPORT_EXT => Features::SHADER_STENCIL_EXPORT,
+                    CAPABILITY_MULTI_VIEW => Features::MULTIVIEW,
+                    CAPABILITY_SHADER_CLOCK_KHR => Features::SHADER_CLOCK,
+                    CAPABILITY_FRAGMENT_BARYCENTRIC_KHR => Features::FRAGMENT_BARYCENTRIC,
+                    _ => Features::empty(),
+                };
+            }
+            OP_EXECUTION_MODE if operands.len() >= 5 && operands[1] == EXECUTION_MODE_LOCAL_SIZE => {
+                local_sizes.insert(operands[0], [operands[2], operands[3], operands[4]]);
+            }
+            OP_EXECUTION_MODE_ID if operands.len() >= 5 && operands[1] == EXECUTION_MODE_LOCAL_SIZE_ID => {
+                local_size_ids.insert(operands[0], [operands[2], operands[3], operands[4]]);
+            }
+            OP_DECORATE if operands.len() >= 2 => {
+                layouts.decorations.insert((operands[0], operands[1]), operands.get(2).cloned().unwrap_or(0));
+                match operands[1] {
+                    DECORATION_SPEC_ID if operands.len() >= 3 => {
+                        spec_ids.insert(operands[0], operands[2]);
+                    }
+                    DECORATION_BUILT_IN if operands.len() >= 3 && operands[2] == BUILT_IN_WORKGROUP_SIZE => {
+                        workgroup_size_builtin = Some(operands[0]);
+                    }
+                    _ => {}
+                }
+            }
+            OP_MEMBER_DECORATE if operands.len() >= 3 => {
+                layouts.member_decorations.insert(
+                    (operands[0], operands[1], operands[2]),
+                    operands.get(3).cloned().unwrap_or(0),
+                );
+            }
+            OP_CONSTANT |
+            OP_SPEC_CONSTANT if operands.len() >= 3 => {
+                constants.insert(operands[1], operands[2]);
+            }
+            OP_CONSTANT_COMPOSITE |
+            OP_SPEC_CONSTANT_COMPOSITE if operands.len() >= 5 => {
+                composites.insert(operands[1], [operands[2], operands[3], operands[4]]);
+            }
+            OP_TYPE_INT if operands.len() >= 3 => {
+                scalars.insert(operands[0], Scalar::Int(operands[1]));
+                layouts.types.insert(operands[0], Type::Int { width: operands[1], signed: operands[2] != 0 });
+            }
+            OP_TYPE_FLOAT if operands.len() >= 2 => {
+                scalars.insert(operands[0], Scalar::Float(operands[1]));
+                layouts.types.insert(operands[0], Type::Float { width: operands[1] });
+            }
+            OP_TYPE_VECTOR if operands.len() >= 3 => {
+                layouts.types.insert(operands[0], Type::Vector { component: operands[1], count: operands[2] });
+            }
+            OP_TYPE_MATRIX if operands.len() >= 3 => {
+                layouts.types.insert(operands[0], Type::Matrix { column: operands[1], count: operands[2] });
+            }
+            OP_TYPE_IMAGE if operands.len() >= 7 => {
+                layouts.types.insert(operands[0], Type::Image { dim: operands[2], sampled: operands[6] });
+            }
+            OP_TYPE_SAMPLER if !operands.is_empty() => {
+                layouts.types.insert(operands[0], Type::Sampler);
+            }
+            OP_TYPE_SAMPLED_IMAGE if !operands.is_empty() => {
+                layouts.types.insert(operands[0], Type::SampledImage);
+            }
+            OP_TYPE_ARRAY if operands.len() >= 3 => {
+                layouts.types.insert(operands[0], Type::Array { element: operands[1], length: operands[2] });
+            }
+            OP_TYPE_RUNTIME_ARRAY if operands.len() >= 2 => {
+                layouts.types.insert(operands[0], Type::RuntimeArray { element: operands[1] });
+            }
+            OP_TYPE_STRUCT if !operands.is_empty() => {
+                layouts.types.insert(operands[0], Type::Struct { members: operands[1 ..].to_vec() });
+            }
+            OP_TYPE_POINTER if operands.len() >= 3 => {
+                pointer_types.insert(operands[0], (operands[1], operands[2]));
+            }
+            OP_VARIABLE |
+            OP_IMAGE_TEXEL_POINTER |
+            OP_ACCESS_CHAIN |
+            OP_IN_BOUNDS_ACCESS_CHAIN |
+            OP_PTR_ACCESS_CHAIN |
+            OP_COPY_OBJECT if operands.len() >= 2 => {
+                pointers.insert(operands[1], operands[0]);
+                if inst.opcode == OP_VARIABLE && operands.len() >= 3 {
+                    variables.push((operands[1], operands[0], operands[2]));
+                }
+            }
+            OP_ATOMIC_STORE if !operands.is_empty() => {
+                atomic_pointers.push(operands[0]);
+            }
+            OP_ATOMIC_LOAD ..= OP_ATOMIC_XOR |
+            OP_ATOMIC_FMIN_EXT |
+            OP_ATOMIC_FMAX_EXT |
+            OP_ATOMIC_FADD_EXT if operands.len() >= 3 => {
+                atomic_pointers.push(operands[2]);
+            }
+            _ => {}
+        }
+    }
+
+    for pointer in atomic_pointers {
+        let (storage_class, pointee) = match pointers
+            .get(&pointer)
+            .and_then(|ty| pointer_types.get(ty))
+        {
+            Some(&pointer_type) => pointer_type,
+            None => continue,
+        };
+
+        let (int64, float32, float64) = match storage_class {
+            STORAGE_CLASS_WORKGROUP => (
+                Features::SHADER_SHARED_INT64_ATOMICS,
+                Features::SHADER_SHARED_FLOAT32_ATOMICS,
+                Features::SHADER_SHARED_FLOAT64_ATOMICS,
+            ),
+            // There are no 64-bit float image formats.
+            STORAGE_CLASS_IMAGE => (
+                Features::SHADER_IMAGE_INT64_ATOMICS,
+                Features::SHADER_IMAGE_FLOAT32_ATOMICS,
+                Features::empty(),
+            ),
+            _ => (
+                Features::SHADER_BUFFER_INT64_ATOMICS,
+                Features::SHADER_BUFFER_FLOAT32_ATOMICS,
+                Features::SHADER_BUFFER_FLOAT64_ATOMICS,
+            ),
+        };
+
+        required_features |= match scalars.get(&pointee) {
+            Some(&Scalar::Int(64)) => int64,
+            Some(&Scalar::Float(32)) => float32,
+            Some(&Scalar::Float(64)) => float64,
+            _ => Features::empty(),
+        };
+    }
+
+    // A constant decorated with the `WorkgroupSize` built-in takes precedence
+    // over the execution modes of all entry points.
+    let mut descriptor_bindings = Vec::new();
+    let mut push_constants = None;
+    for &(variable, pointer_type, storage_class) in &variables {
+        let pointee = match pointer_types.get(&pointer_type) {
+            Some(&(_, pointee)) => pointee,
+            None => continue,
+        };
+        if storage_class == STORAGE_CLASS_PUSH_CONSTANT {
+            let start = match layouts.types.get(&pointee) {
+                Some(&Type::Struct { ref members }) => (0 .. members.len() as u32)
+                    .filter_map(|i| layouts.member_decorations.get(&(pointee, i, DECORATION_OFFSET)).cloned())
+                    .min()
+                    .unwrap_or(0),
+                _ => 0,
+            };
+            push_constants = Some(start .. layouts.size_of(pointee, &constants));
+            continue;
+        }
+        let (set, binding) = match (
+            layouts.decoration(variable, DECORATION_DESCRIPTOR_SET),
+            layouts.decoration(variable, DECORATION_BINDING),
+        ) {
+            (Some(set), Some(binding)) => (set, binding),
+            _ => continue,
+        };
+        if let Some((ty, count)) = layouts.descriptor(storage_class, pointee, &constants) {
+            descriptor_bindings.push(DescriptorBindingInfo {
+                set,
+                binding,
+                ty,
+                count,
+                name: layouts.names.get(&variable).cloned(),
+            });
+        }
+    }
+    descriptor_bindings.sort_by_key(|binding| (binding.set, binding.binding));
+
+    // Input and output variables of an entry point interface.
+    let interface_variables = |interface: &[u32], class: u32| {
+        let mut infos = variables
+            .iter()
+            .filter(|&&(variable, _, storage_class)| storage_class == class && interface.contains(&variable))
+            .filter(|&&(variable, _, _)| layouts.decoration(variable, DECORATION_BUILT_IN).is_none())
+            .filter_map(|&(variable, pointer_type, _)| {
+                let location = layouts.decoration(variable, DECORATION_LOCATION)?;
+                let format = pointer_types
+                    .get(&pointer_type)
+                    .and_then(|&(_, pointee)| layouts.format(pointee));
+                Some(InterfaceVariableInfo {
+                    location,
+                    format,
+                    name: layouts.names.get(&variable).cloned(),
+                })
+            })
+            .collect::<Vec<_>>();
+        infos.sort_by_key(|info| info.location);
+        infos
+    };
+
+    let builtin_ids = workgroup_size_builtin.and_then(|id| composites.get(&id).cloned());
+    let entry_points = entry_points
+        .into_iter()
+        .map(|(function, stage, name, interface)| {
+            let mut workgroup_size = [0; 3];
+            let mut workgroup_size_spec_ids = [None; 3];
+            if stage == Some(Stage::Compute) {
+                if let Some(ids) = builtin_ids.or_else(|| local_size_ids.get(&function).cloned()) {
+                    for i in 0 .. 3 {
+                        workgroup_size[i] = constants.get(&ids[i]).cloned().unwrap_or(0);
+                        workgroup_size_spec_ids[i] = spec_ids.get(&ids[i]).cloned();
+                    }
+                } else if let Some(&size) = local_sizes.get(&function) {
+                    workgroup_size = size;
+                }
+            }
+
+            EntryPointInfo {
+                name,
+                stage,
+                workgroup_size,
+                workgroup_size_spec_ids,
+                inputs: interface_variables(interface, STORAGE_CLASS_INPUT),
+                outputs: interface_variables(interface, STORAGE_CLASS_OUTPUT),
+            }
+        })
+        .collect();
+
+    Ok(ShaderInfo {
+        required_features,
+        entry_points,
+        descriptor_bindings,
+        push_constants,
+    })
+}
+
+/// Check that all features required by a SPIR-V module are supported by the device.
+pub fn check_spirv_features(spirv: &[u8], features: Features) -> Result<(), ShaderError> {
+    let info = reflect_spirv(spirv)?;
+    let missing = info.required_features - features;
+    if missing.is_empty() {
+        Ok(())
+    } else {
+        Err(ShaderError::MissingFeatures(missing))
+    }
+}
+
+fn invalid<T>(message: String) -> Result<T, ShaderError> {
+    Err(ShaderError::CompilationFailed(format!("Invalid SPIR-V: {}", message)))
+}
+
+/// Check the structure of a SPIR-V module: the header, the instruction stream,
+/// the bounds and uniqueness of result ids, the nesting of functions and blocks,
+/// and the entry points.
+///
+/// This is not a full validator. It catches malformed modules which would otherwise
+/// be handed to the driver or the shader translators, where they may crash.
+pub fn validate_spirv(spirv: &[u8]) -> Result<(), ShaderError> {
+    let words = parse_words(spirv)?;
+    let instructions = parse_instructions(&words)?;
+
+    let (major, minor) = ((words[1] >> 16) & 0xFF, (words[1] >> 8) & 0xFF);
+    if major != 1 || minor > 6 {
+        return invalid(format!("unsupported version {}.{}", major, minor));
+    }
+    if words[4] != 0 {
+        return invalid("reserved header word is not 0".into());
+    }
+    let bound = words[3];
+
+    let mut defined = HashSet::new();
+    let mut functions = HashSet::new();
+    let mut entry_functions = Vec::new();
+    let mut memory_models = 0;
+    let mut linkage = false;
+    // Id of the function being defined, and whether we are inside one of its blocks.
+    let mut function = None;
+    let mut in_block = false;
+
+    for inst in &instructions {
+        let operands = inst.operands;
+        let result_index = match inst.opcode {
+            OP_STRING |
+            OP_EXT_INST_IMPORT |
+            OP_TYPE_VOID ..= OP_TYPE_PIPE |
+            OP_LABEL => Some(0),
+            OP_CONSTANT_TRUE ..= OP_SPEC_CONSTANT_OP |
+            OP_FUNCTION |
+            OP_FUNCTION_PARAMETER |
+            OP_VARIABLE => Some(1),
+            _ => None,
+        };
+        if let Some(index) = result_index {
+            let id = match operands.get(index) {
+                Some(&id) => id,
+                None => return invalid(format!("opcode {} is missing its result id", inst.opcode)),
+            };
+            if id == 0 || id >= bound {
+                return invalid(format!("result id {} is out of bounds ({})", id, bound));
+            }
+            if !defined.insert(id) {
+                return invalid(format!("result id {} is defined more than once", id));
+            }
+        }
+
+        match inst.opcode {
+            OP_CAPABILITY => {
+                linkage |= operands.first() == Some(&CAPABILITY_LINKAGE);
+            }
+            OP_MEMORY_MODEL => {
+                memory_models += 1;
+            }
+            OP_ENTRY_POINT => {
+                if operands.len() < 3 {
+                    return invalid("truncated entry point".into());
+                }
+                entry_functions.push(operands[1]);
+            }
+            OP_FUNCTION => {
+                if function.is_some() {
+                    return invalid(format!("function {} is nested in another function", operands[1]));
+                }
+                function = Some(operands[1]);
+                functions.insert(operands[1]);
+            }
+            OP_FUNCTION_END => {
+                if in_block {
+                    return invalid("last block of a function is not terminated".into());
+                }
+                if function.take().is_none() {
+                    return invalid("function end outside of a function".into());
+                }
+            }
+            OP_LABEL => {
+                if function.is_none() {
+                    return invalid(format!("label {} outside of a function", operands[0]));
+                }
+                if in_block {
+                    return invalid(format!("block preceding label {} is not terminated", operands[0]));
+                }
+                in_block = true;
+            }
+            OP_BRANCH ..= OP_UNREACHABLE |
+            OP_TERMINATE_INVOCATION |
+            OP_IGNORE_INTERSECTION_KHR |
+            OP_TERMINATE_RAY_KHR |
+            OP_EMIT_MESH_TASKS_EXT => {
+                if !in_block {
+                    return invalid(format!("block terminator {} outside of a block", inst.opcode));
+                }
+                in_block = false;
+            }
+            _ => {}
+        }
+    }
+
+    if function.is_some() {
+        return invalid("last function is not ended".into());
+    }
+    if memory_models != 1 {
+        return invalid(format!("expected a single memory model, found {}", memory_models));
+    }
+    if entry_functions.is_empty() && !linkage {
+        return invalid("no entry points".into());
+    }
+    if let Some(id) = entry_functions.iter().find(|id| !functions.contains(id)) {
+        return invalid(format!("entry point function {} is not defined", id));
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CAPABILITY_SHADER: u32 = 1;
+    const OP_TYPE_FUNCTION: u16 = 33;
+    const OP_RETURN: u16 = 253;
+
+    fn inst(opcode: u16, operands: &[u32]) -> Vec<u32> {
+        let mut words = vec![(operands.len() as u32 + 1) << 16 | opcode as u32];
+        words.extend_from_slice(operands);
+        words
+    }
+
+    fn to_bytes(words: &[u32]) -> Vec<u8> {
+        words
+            .iter()
+            .flat_map(|word| (0 .. 4).map(move |i| (word >> (i * 8)) as u8))
+            .collect()
+    }
+
+    // Compute shader with an empty `main`: %1 main, %2 void, %3 function type, %4 label.
+    fn compute_module(capabilities: &[u32]) -> Vec<u32> {
+        let mut words = vec![MAGIC_NUMBER, 0x0001_0000, 0, 5, 0];
+        words.extend(inst(OP_CAPABILITY, &[CAPABILITY_SHADER]));
+        for &capability in capabilities {
+            words.extend(inst(OP_CAPABILITY, &[capability]));
+        }
+        words.extend(inst(OP_MEMORY_MODEL, &[0, 1]));
+        words.extend(inst(OP_ENTRY_POINT, &[5, 1, 0x6E69_616D, 0]));
+        words.extend(inst(OP_EXECUTION_MODE, &[1, EXECUTION_MODE_LOCAL_SIZE, 8, 8, 1]));
+        words.extend(inst(OP_TYPE_VOID, &[2]));
+        words.extend(inst(OP_TYPE_FUNCTION, &[3, 2]));
+        words.extend(inst(OP_FUNCTION, &[2, 1, 0, 3]));
+        words.extend(inst(OP_LABEL, &[4]));
+        words.extend(inst(OP_RETURN, &[]));
+        words.extend(inst(OP_FUNCTION_END, &[]));
+        words
+    }
+
+    fn assert_invalid(words: &[u32]) {
+        match validate_spirv(&to_bytes(words)) {
+            Err(ShaderError::CompilationFailed(_)) => {}
+            other => panic!("expected a compilation failure, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_valid_module() {
+        let words = compute_module(&[]);
+        validate_spirv(&to_bytes(&words)).unwrap();
+
+        let info = reflect_spirv(&to_bytes(&words)).unwrap();
+        assert_eq!(info.required_features, Features::empty());
+        assert_eq!(info.entry_points.len(), 1);
+
+        // Big-endian modules are accepted as well.
+        let swapped = words.iter().map(|word| word.swap_bytes()).collect::<Vec<_>>();
+        validate_spirv(&to_bytes(&swapped)).unwrap();
+    }
+
+    #[test]
+    fn test_bad_magic() {
+        let mut words = compute_module(&[]);
+        words[0] = 0xDEAD_BEEF;
+        assert_invalid(&words);
+    }
+
+    #[test]
+    fn test_bad_header() {
+        let mut words = compute_module(&[]);
+        words[1] = 0x0002_0000;
+        assert_invalid(&words);
+
+        let mut words = compute_module(&[]);
+        words[4] = 1;
+        assert_invalid(&words);
+
+        // shorter than the header
+        assert_invalid(&[MAGIC_NUMBER, 0x0001_0000, 0, 5]);
+    }
+
+    #[test]
+    fn test_truncated() {
+        let words = compute_module(&[]);
+        // not a whole number of words
+        let mut bytes = to_bytes(&words);
+        bytes.pop();
+        assert!(validate_spirv(&bytes).is_err());
+
+        // last instruction extends past the end of the module
+        let mut words = compute_module(&[]);
+        let last = words.len() - 1;
+        words[last] = 2 << 16 | OP_FUNCTION_END as u32;
+        assert_invalid(&words);
+
+        // zero word count
+        let mut words = compute_module(&[]);
+        words.insert(HEADER_LENGTH, OP_CAPABILITY as u32);
+        assert_invalid(&words);
+    }
+
+    #[test]
+    fn test_result_ids() {
+        // out of bounds
+        let mut words = compute_module(&[]);
+        words[3] = 4;
+        assert_invalid(&words);
+
+        // defined twice, by opcodes from different ranges
+        let mut words = compute_module(&[]);
+        let function_end = words.len() - 2;
+        words.truncate(function_end - 4);
+        words.extend(inst(OP_CONSTANT, &[2, 3, 0]));
+        words.extend(inst(OP_FUNCTION, &[2, 1, 0, 3]));
+        words.extend(inst(OP_LABEL, &[4]));
+        words.extend(inst(OP_RETURN, &[]));
+        words.extend(inst(OP_FUNCTION_END, &[]));
+        assert_invalid(&words);
+
+        // missing result id
+        let mut words = compute_module(&[]);
+        words.extend(inst(OP_TYPE_VOID, &[]));
+        assert_invalid(&words);
+    }
+
+    #[test]
+    fn test_blocks() {
+        // terminator outside of a block
+        let mut words = compute_module(&[]);
+        let function_end = words.len() - 1;
+        words.insert(function_end, inst(OP_RETURN, &[])[0]);
+        assert_invalid(&words);
+
+        // unterminated block
+        let mut words = compute_module(&[]);
+        let return_index = words.len() - 2;
+        words.remove(return_index);
+        assert_invalid(&words);
+
+        // unterminated function
+        let mut words = compute_module(&[]);
+        words.pop();
+        assert_invalid(&words);
+    }
+
+    #[test]
+    fn test_entry_points() {
+        // no entry point
+        let words = compute_module(&[]);
+        let entry_point = words
+            .iter()
+            .position(|&word| word == (5 << 16 | OP_ENTRY_POINT as u32))
+            .unwrap();
+        let mut without_entry = words.clone();
+        without_entry.drain(entry_point .. entry_point + 5);
+        assert_invalid(&without_entry);
+
+        // linkage modules don't need one
+        let mut library = without_entry.clone();
+        library.splice(HEADER_LENGTH .. HEADER_LENGTH, inst(OP_CAPABILITY, &[CAPABILITY_LINKAGE]));
+        validate_spirv(&to_bytes(&library)).unwrap();
+
+        // entry point function not defined
+        let mut words = words;
+        words[entry_point + 2] = 3;
+        assert_invalid(&words);
+    }
+
+    #[test]
+    fn test_capability_features() {
+        let capabilities = [
+            (CAPABILITY_FLOAT16, Features::SHADER_FLOAT16),
+            (CAPABILITY_INT16, Features::SHADER_INT16),
+            (CAPABILITY_INT8, Features::SHADER_INT8),
+            (CAPABILITY_STORAGE_BUFFER_16BIT_ACCESS, Features::STORAGE_16BIT),
+            (CAPABILITY_STORAGE_PUSH_CONSTANT_16, Features::STORAGE_16BIT),
+            (CAPABILITY_STORAGE_INPUT_OUTPUT_16, Features::STORAGE_INPUT_OUTPUT_16BIT),
+            (CAPABILITY_STORAGE_BUFFER_8BIT_ACCESS, Features::STORAGE_8BIT),
+            (CAPABILITY_STORAGE_PUSH_CONSTANT_8, Features::STORAGE_8BIT),
+            (CAPABILITY_GROUP_NON_UNIFORM, Features::SUBGROUP_BASIC),
+            (CAPABILITY_GROUP_NON_UNIFORM_SHUFFLE_RELATIVE, Features::SUBGROUP_SHUFFLE),
+            (CAPABILITY_GROUP_NON_UNIFORM_QUAD, Features::SUBGROUP_QUAD),
+        ];
+        for &(capability, feature) in &capabilities {
+            let spirv = to_bytes(&compute_module(&[capability]));
+            validate_spirv(&spirv).unwrap();
+            assert_eq!(reflect_spirv(&spirv).unwrap().required_features, feature);
+            check_spirv_features(&spirv, feature).unwrap();
+            match check_spirv_features(&spirv, Features::empty()) {
+                Err(ShaderError::MissingFeatures(missing)) => assert_eq!(missing, feature),
+                other => panic!("expected missing features, got {:?}", other),
+            }
+        }
+    }
+}
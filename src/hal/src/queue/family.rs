@@ -3,7 +3,7 @@
 use Backend;
 use backend::RawQueueGroup;
 use queue::{CommandQueue, QueueType};
-use queue::capability::{Capability, Graphics, Compute};
+use queue::capability::{Capability, Graphics, Compute, Transfer};
 
 use std::any::Any;
 use std::fmt::Debug;
@@ -24,6 +24,14 @@ pub trait QueueFamily: Debug + Any + Send + Sync {
     fn supports_compute(&self) -> bool {
         Compute::supported_by(self.queue_type())
     }
+    /// Returns true if the queue supports transfer operations, which all queues do.
+    fn supports_transfer(&self) -> bool {
+        Transfer::supported_by(self.queue_type())
+    }
+    /// Returns true if the queue supports sparse binding operations.
+    fn supports_sparse_binding(&self) -> bool {
+        false
+    }
     /// Returns the queue family ID.
     fn id(&self) -> QueueFamilyId;
 }
@@ -15,7 +15,8 @@ use std::borrow::Borrow;
 use std::marker::PhantomData;
 
 use error::HostExecutionError;
-use window::SwapImageIndex;
+use sparse::SparseBindInfo;
+use window::{PresentError, Suboptimal, SwapImageIndex};
 use Backend;
 
 pub use self::capability::{
@@ -61,8 +62,12 @@ pub trait RawCommandQueue<B: Backend>: Any + Send + Sync {
     /// semaphores given in `wait_semaphores`. A given swapchain must not appear in this
     /// list more than once.
     ///
+    /// Returns `Suboptimal` if any of the swapchains should be recreated.
+    ///
     /// Unsafe for the same reasons as `submit_raw()`.
-    fn present<IS, S, IW>(&mut self, swapchains: IS, wait_semaphores: IW) -> Result<(), ()>
+    fn present<IS, S, IW>(
+        &mut self, swapchains: IS, wait_semaphores: IW
+    ) -> Result<Option<Suboptimal>, PresentError>
     where
         Self: Sized,
         IS: IntoIterator<Item = (S, SwapImageIndex)>,
@@ -70,6 +75,13 @@ pub trait RawCommandQueue<B: Backend>: Any + Send + Sync {
         IW: IntoIterator,
         IW::Item: Borrow<B::Semaphore>;
 
+    /// Bind memory to regions of sparse buffers and images.
+    /// `fence` will be signalled after the binds have been executed and _must_ be unsignalled.
+    ///
+    /// Unsafe because the queue family has to support sparse binding
+    /// (see `QueueFamily::supports_sparse_binding`) and the bound regions aren't validated.
+    unsafe fn bind_sparse(&mut self, info: SparseBindInfo<B>, fence: Option<&B::Fence>);
+
     /// Wait for the queue to idle.
     fn wait_idle(&self) -> Result<(), HostExecutionError>;
 }
@@ -119,7 +131,11 @@ impl<B: Backend, C> CommandQueue<B, C> {
     /// Presents the result of the queue to the given swapchains, after waiting on all the
     /// semaphores given in `wait_semaphores`. A given swapchain must not appear in this
     /// list more than once.
-    pub fn present<IS, S, IW>(&mut self, swapchains: IS, wait_semaphores: IW) -> Result<(), ()>
+    ///
+    /// Returns `Suboptimal` if any of the swapchains should be recreated.
+    pub fn present<IS, S, IW>(
+        &mut self, swapchains: IS, wait_semaphores: IW
+    ) -> Result<Option<Suboptimal>, PresentError>
     where
         IS: IntoIterator<Item = (S, SwapImageIndex)>,
         S: Borrow<B::Swapchain>,
@@ -129,6 +145,15 @@ impl<B: Backend, C> CommandQueue<B, C> {
         self.0.present(swapchains, wait_semaphores)
     }
 
+    /// Bind memory to regions of sparse buffers and images.
+    /// `fence` will be signalled after the binds have been executed and _must_ be unsignalled.
+    ///
+    /// Unsafe because the queue family has to support sparse binding
+    /// (see `QueueFamily::supports_sparse_binding`).
+    pub unsafe fn bind_sparse(&mut self, info: SparseBindInfo<B>, fence: Option<&B::Fence>) {
+        self.0.bind_sparse(info, fence)
+    }
+
     /// Wait for the queue to idle.
     pub fn wait_idle(&self) -> Result<(), HostExecutionError> {
         self.0.wait_idle()
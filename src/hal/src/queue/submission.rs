@@ -21,6 +21,10 @@ where
     pub wait_semaphores: &'a [(&'a B::Semaphore, pso::PipelineStage)],
     /// Semaphores which get signalled after submission.
     pub signal_semaphores: &'a [&'a B::Semaphore],
+    /// Timeline semaphores to wait reaching a value before submission.
+    pub wait_timeline_semaphores: &'a [(&'a B::TimelineSemaphore, u64, pso::PipelineStage)],
+    /// Timeline semaphores which get signalled to a value after submission.
+    pub signal_timeline_semaphores: &'a [(&'a B::TimelineSemaphore, u64)],
 }
 
 /// Submission information for a command queue, generic over a particular
@@ -29,6 +33,8 @@ pub struct Submission<'a, B: Backend, C> {
     cmd_buffers: SmallVec<[Cow<'a, B::CommandBuffer>; 16]>,
     wait_semaphores: SmallVec<[(&'a B::Semaphore, pso::PipelineStage); 16]>,
     signal_semaphores: SmallVec<[&'a B::Semaphore; 16]>,
+    wait_timeline_semaphores: SmallVec<[(&'a B::TimelineSemaphore, u64, pso::PipelineStage); 4]>,
+    signal_timeline_semaphores: SmallVec<[(&'a B::TimelineSemaphore, u64); 4]>,
     marker: PhantomData<C>,
 }
 
@@ -41,6 +47,8 @@ impl<'a, B: Backend> Submission<'a, B, Transfer> {
             cmd_buffers: SmallVec::new(),
             wait_semaphores: SmallVec::new(),
             signal_semaphores: SmallVec::new(),
+            wait_timeline_semaphores: SmallVec::new(),
+            signal_timeline_semaphores: SmallVec::new(),
             marker: PhantomData,
         }
     }
@@ -70,12 +78,34 @@ where
         self
     }
 
+    /// Add to timeline semaphores which will be waited on to reach a value before the submission will be executed.
+    pub fn wait_on_timeline<I>(mut self, semaphores: I) -> Self
+    where
+        I: IntoIterator,
+        I::Item: Borrow<(&'a B::TimelineSemaphore, u64, pso::PipelineStage)>,
+    {
+        self.wait_timeline_semaphores.extend(semaphores.into_iter().map(|semaphore| *semaphore.borrow()));
+        self
+    }
+
+    /// Add to timeline semaphores which will be signalled to a value once this submission has finished executing.
+    pub fn signal_timeline<I>(mut self, semaphores: I) -> Self
+    where
+        I: IntoIterator,
+        I::Item: Borrow<(&'a B::TimelineSemaphore, u64)>,
+    {
+        self.signal_timeline_semaphores.extend(semaphores.into_iter().map(|semaphore| *semaphore.borrow()));
+        self
+    }
+
     /// Convert strong-typed submission object into untyped equivalent.
     pub(super) fn to_raw(&self) -> RawSubmission<B, Vec<&B::CommandBuffer>> {
         RawSubmission {
             cmd_buffers: self.cmd_buffers.iter().map(|b| b.deref().borrow()).collect::<Vec<_>>(),
             wait_semaphores: &self.wait_semaphores,
             signal_semaphores: &self.signal_semaphores,
+            wait_timeline_semaphores: &self.wait_timeline_semaphores,
+            signal_timeline_semaphores: &self.signal_timeline_semaphores,
         }
     }
 
@@ -97,6 +127,8 @@ where
             cmd_buffers: self.cmd_buffers,
             wait_semaphores: self.wait_semaphores,
             signal_semaphores: self.signal_semaphores,
+            wait_timeline_semaphores: self.wait_timeline_semaphores,
+            signal_timeline_semaphores: self.signal_timeline_semaphores,
             marker: PhantomData,
         }
     }
@@ -114,6 +146,8 @@ where
             cmd_buffers: self.cmd_buffers,
             wait_semaphores: self.wait_semaphores,
             signal_semaphores: self.signal_semaphores,
+            wait_timeline_semaphores: self.wait_timeline_semaphores,
+            signal_timeline_semaphores: self.signal_timeline_semaphores,
             marker: PhantomData,
         }
     }
@@ -0,0 +1,75 @@
+//! Sparse (tiled) resources.
+//!
+//! Sparse buffers and images are created without any backing memory. Memory is
+//! attached to, and detached from, regions of the resource at page granularity
+//! with `RawCommandQueue::bind_sparse`, which makes them the building block for
+//! virtual texturing and streaming of very large resources.
+//!
+//! Sparse resources are only available if the device exposes `Features::SPARSE_BINDING`
+//! and the queue family reports `QueueFamily::supports_sparse_binding`.
+//! Partially resident resources additionally require `Features::SPARSE_RESIDENCY_BUFFER`
+//! or `Features::SHADER_RESIDENCY_IMAGE_2D` respectively.
+
+use {image, memory, pso, Backend};
+
+/// Memory layout of a sparse image.
+#[derive(Clone, Copy, Debug)]
+pub struct SparseImageRequirements {
+    /// Memory requirements of the whole image.
+    /// `alignment` is the size of a single page (tile).
+    pub memory: memory::Requirements,
+    /// Dimensions of a single tile in texels.
+    pub tile_extent: image::Extent,
+    /// First mip level of the mip tail. Levels starting from this one are smaller
+    /// than a tile and have to be bound as a whole through opaque binds.
+    pub mip_tail_first_level: image::Level,
+    /// Size of the mip tail in bytes.
+    pub mip_tail_size: u64,
+    /// Offset of the mip tail in the opaque resource address space.
+    pub mip_tail_offset: u64,
+}
+
+/// Binding of a memory range to a linear region of a sparse resource.
+///
+/// Used for buffers and for the opaque (mip tail) part of images.
+#[derive(Debug)]
+pub struct SparseBind<'a, B: Backend> {
+    /// Offset into the resource in bytes, must be a multiple of the page size.
+    pub resource_offset: u64,
+    /// Size of the region in bytes, must be a multiple of the page size.
+    pub size: u64,
+    /// Memory and offset into it to bind the region to,
+    /// `None` unbinds the region.
+    pub memory: Option<(&'a B::Memory, u64)>,
+}
+
+/// Binding of a memory range to a block of tiles of a sparse image.
+#[derive(Debug)]
+pub struct SparseImageBind<'a, B: Backend> {
+    /// Subresource containing the block, must be below the mip tail.
+    pub subresource: image::Subresource,
+    /// Offset of the block in texels, must be a multiple of the tile extent.
+    pub offset: image::Offset,
+    /// Extent of the block in texels, must be a multiple of the tile extent
+    /// unless it reaches the edge of the subresource.
+    pub extent: image::Extent,
+    /// Memory and offset into it to bind the block to,
+    /// `None` unbinds the block.
+    pub memory: Option<(&'a B::Memory, u64)>,
+}
+
+/// Sparse binding operation executed by a queue.
+///
+/// Binds are executed in order after all `wait_semaphores` have been signalled.
+pub struct SparseBindInfo<'a, B: Backend + 'a> {
+    /// Semaphores to wait being signalled before binding.
+    pub wait_semaphores: &'a [(&'a B::Semaphore, pso::PipelineStage)],
+    /// Semaphores which get signalled after binding.
+    pub signal_semaphores: &'a [&'a B::Semaphore],
+    /// Binds for sparse buffers.
+    pub buffer_binds: &'a [(&'a B::Buffer, &'a [SparseBind<'a, B>])],
+    /// Opaque binds for sparse images, addressing the image as a linear range of pages.
+    pub image_opaque_binds: &'a [(&'a B::Image, &'a [SparseBind<'a, B>])],
+    /// Tile binds for partially resident sparse images.
+    pub image_binds: &'a [(&'a B::Image, &'a [SparseImageBind<'a, B>])],
+}
@@ -0,0 +1,115 @@
+//! Timeline synchronization.
+//!
+//! A timeline semaphore carries a monotonically increasing 64-bit value instead of
+//! a binary signaled state. Submissions signal it to a given value once they finish
+//! executing and wait for it to reach a given value before starting, while the host
+//! can query, signal and wait on it through the `Device`. One timeline semaphore per
+//! queue is enough to express the dependencies of a frame graph spanning several
+//! queues, without recycling pools of binary semaphores and fences.
+//!
+//! Backends without native support emulate timeline semaphores with a `HostTimeline`,
+//! which is signaled once the submission has completed on the device. Waits of a
+//! submission block the submitting thread, so the signaling submission must have been
+//! submitted before, or be submitted from another thread.
+//! `Features::TIMELINE_SEMAPHORE` is only exposed when the support is native.
+
+use device::WaitFor;
+
+use std::sync::{Condvar, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Timeout value denoting an unbounded wait.
+const INFINITE_TIMEOUT_MS: u32 = !0;
+
+/// Timeline value, signaled and waited on by the host.
+#[derive(Debug, Default)]
+pub struct HostTimeline {
+    value: Mutex<u64>,
+    condvar: Condvar,
+}
+
+impl HostTimeline {
+    /// Create a new timeline starting at `initial_value`.
+    pub fn new(initial_value: u64) -> Self {
+        HostTimeline {
+            value: Mutex::new(initial_value),
+            condvar: Condvar::new(),
+        }
+    }
+
+    /// Return the current value of the timeline.
+    pub fn value(&self) -> u64 {
+        *self.value.lock().unwrap()
+    }
+
+    /// Advance the timeline to `value` and wake up the waiting threads.
+    ///
+    /// Signaling a value lower than the current one has no effect.
+    pub fn signal(&self, value: u64) {
+        let mut current = self.value.lock().unwrap();
+        debug_assert!(value >= *current, "Timeline values must increase");
+        if value > *current {
+            *current = value;
+            self.condvar.notify_all();
+        }
+    }
+
+    /// Block until the timeline reaches `value`.
+    /// Returns true if the value was reached before the timeout.
+    pub fn wait(&self, value: u64, timeout_ms: u32) -> bool {
+        let mut current = self.value.lock().unwrap();
+        if timeout_ms == INFINITE_TIMEOUT_MS {
+            while *current < value {
+                current = self.condvar.wait(current).unwrap();
+            }
+            return true;
+        }
+
+        let deadline = Instant::now() + Duration::from_millis(timeout_ms as u64);
+        while *current < value {
+            let now = Instant::now();
+            if now >= deadline {
+                return false;
+            }
+            current = self.condvar.wait_timeout(current, deadline - now).unwrap().0;
+        }
+        true
+    }
+
+    /// Block until all or one of the given timelines reach their values.
+    /// Returns true if the values were reached before the timeout.
+    pub fn wait_many<'a, I>(timelines: I, wait: WaitFor, timeout_ms: u32) -> bool
+    where
+        I: IntoIterator<Item = (&'a HostTimeline, u64)>,
+    {
+        let start = Instant::now();
+        let remaining_ms = || {
+            if timeout_ms == INFINITE_TIMEOUT_MS {
+                return timeout_ms;
+            }
+            let elapsed = start.elapsed();
+            let elapsed_ms = elapsed.as_secs() as u32 * 1000 + elapsed.subsec_nanos() / 1_000_000;
+            timeout_ms.saturating_sub(elapsed_ms)
+        };
+
+        match wait {
+            WaitFor::All => timelines
+                .into_iter()
+                .all(|(timeline, value)| timeline.wait(value, remaining_ms())),
+            WaitFor::Any => {
+                // Timelines don't share a condition variable, so they are polled.
+                let timelines = timelines.into_iter().collect::<Vec<_>>();
+                loop {
+                    if timelines.iter().any(|&(timeline, value)| timeline.value() >= value) {
+                        return true;
+                    }
+                    if remaining_ms() == 0 {
+                        return false;
+                    }
+                    thread::sleep(Duration::from_millis(1));
+                }
+            }
+        }
+    }
+}
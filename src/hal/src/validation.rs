@@ -0,0 +1,806 @@
+//! Opt-in validation of the API usage, independent of the backend validation layers.
+//!
+//! `ValidationDevice` wraps a device and hands out `Tracked` resources, tagged with an ID
+//! the validation follows through recording, submission and destruction.
+//! `ValidationCommandBuffer` wraps a raw command buffer, checking the commands recorded
+//! through it. The misuse detected is reported through a callback, the calls are still
+//! forwarded to the backend:
+//!
+//! - accesses to a resource written earlier in the command buffer without a barrier
+//!   in between,
+//! - submissions using destroyed resources, and resources destroyed while a submission
+//!   using them may still be executing,
+//! - submissions of command buffers which aren't fully recorded,
+//! - framebuffers created with or used with render passes of incompatible formats.
+//!
+//! ```ignore
+//! let device = ValidationDevice::new(device, |error| panic!("{}", error));
+//! let buffer = device.bind_buffer_memory(&memory, 0, unbound)?;
+//! let mut cmd_buffer = device.wrap_command_buffer(pool.allocate(1, RawLevel::Primary).remove(0));
+//! cmd_buffer.begin(CommandBufferFlags::ONE_TIME_SUBMIT, Default::default());
+//! cmd_buffer.copy_buffer(&staging, &buffer, regions);
+//! // missing barrier, reported
+//! cmd_buffer.bind_vertex_buffers(0, Some((&buffer, 0)));
+//! ```
+//!
+//! This is not a wrapper backend: only the resources created and the commands recorded
+//! through the wrappers above are validated, everything else goes to the backend directly.
+//! In particular, the following isn't validated:
+//!
+//! - draws and dispatches, along with the pipelines and descriptor sets they use,
+//!   so resources accessed through descriptors aren't tracked,
+//! - commands recorded through `ValidationCommandBuffer::raw_mut`,
+//! - barriers across command buffers, they're only tracked within a command buffer.
+//!
+//! Resources are boxed for their addresses to stay stable, which is how the targets of
+//! barriers are recognized. Queues are recognized by the address of the raw queue, which
+//! must not move while submissions to it are in flight.
+
+use std::borrow::Borrow;
+use std::collections::{HashMap, HashSet};
+use std::ops::{Deref, Range};
+use std::sync::{Arc, Mutex};
+use std::{fmt, mem};
+
+use command::{
+    BufferCopy, BufferImageCopy, ClearValueRaw, CommandBufferFlags, CommandBufferInheritanceInfo,
+    ImageCopy, RawCommandBuffer, SubpassContents,
+};
+use device::{BindError, Device, FramebufferError};
+use error::HostExecutionError;
+use format::{Format, Swizzle};
+use memory::{Barrier, Dependencies};
+use queue::{CommandQueue, RawCommandQueue, RawSubmission};
+use range::RangeArg;
+use {buffer, image, pass, pso, Backend, IndexType};
+
+/// Callback receiving the errors detected by a `ValidationDevice`.
+pub type Callback = Box<Fn(&ValidationError) + Send + Sync>;
+
+/// Identifier of a resource tracked by a `ValidationDevice`, never reused.
+#[derive(Clone, Copy, Debug, Hash, Eq, Ord, PartialEq, PartialOrd)]
+pub struct ResourceId(u64);
+
+/// Kind of a tracked resource.
+#[derive(Clone, Copy, Debug, Hash, Eq, Ord, PartialEq, PartialOrd)]
+pub enum ResourceKind {
+    /// Buffer.
+    Buffer,
+    /// Image.
+    Image,
+    /// Image view.
+    ImageView,
+    /// Render pass.
+    RenderPass,
+    /// Framebuffer.
+    Framebuffer,
+    /// Fence.
+    Fence,
+}
+
+/// Recording state of a `ValidationCommandBuffer`.
+#[derive(Clone, Copy, Debug, Hash, Eq, PartialEq)]
+pub enum RecordingState {
+    /// Not recorded since its allocation or last reset.
+    Initial,
+    /// Between `begin` and `finish`.
+    Recording,
+    /// Finished, ready for submission.
+    Executable,
+}
+
+/// API misuse detected by a `ValidationDevice`.
+#[derive(Fail, Debug, Clone, PartialEq)]
+pub enum ValidationError {
+    /// A resource is accessed after a write without a barrier in between.
+    #[fail(display = "{:?} {:?} is accessed after a write without a barrier in between.", kind, resource)]
+    MissingBarrier {
+        /// Kind of the resource.
+        kind: ResourceKind,
+        /// Accessed resource.
+        resource: ResourceId,
+    },
+    /// A command buffer using a destroyed resource is submitted.
+    #[fail(display = "{:?} {:?} is used by a submission after being destroyed.", kind, resource)]
+    UseAfterFree {
+        /// Kind of the resource.
+        kind: ResourceKind,
+        /// Destroyed resource.
+        resource: ResourceId,
+    },
+    /// A resource is destroyed while a submission using it may still be executing.
+    #[fail(display = "{:?} {:?} is destroyed while a submission using it is in flight.", kind, resource)]
+    DestroyedInFlight {
+        /// Kind of the resource.
+        kind: ResourceKind,
+        /// Destroyed resource.
+        resource: ResourceId,
+    },
+    /// A command buffer is submitted without being fully recorded.
+    #[fail(display = "Command buffer {} is submitted in the {:?} state.", command_buffer, state)]
+    UnrecordedSubmission {
+        /// Index of the command buffer in the submission.
+        command_buffer: usize,
+        /// State of the command buffer.
+        state: RecordingState,
+    },
+    /// The attachments of a framebuffer don't match the render pass.
+    #[fail(display = "Framebuffer attachment {} is {:?}, the render pass expects {:?}.", attachment, found, expected)]
+    IncompatibleFramebuffer {
+        /// Index of the mismatching attachment.
+        attachment: usize,
+        /// Format of the render pass attachment, `None` if missing.
+        expected: Option<Format>,
+        /// Format of the framebuffer attachment, `None` if missing.
+        found: Option<Format>,
+    },
+}
+
+/// Resource tracked by a `ValidationDevice`.
+///
+/// Dereferences to the backend resource, for passing it on to the unvalidated API.
+pub struct Tracked<T> {
+    raw: Box<T>,
+    id: ResourceId,
+}
+
+impl<T> Tracked<T> {
+    /// ID of the resource.
+    pub fn id(&self) -> ResourceId {
+        self.id
+    }
+}
+
+impl<T> Deref for Tracked<T> {
+    type Target = T;
+    fn deref(&self) -> &T {
+        &self.raw
+    }
+}
+
+impl<T: fmt::Debug> fmt::Debug for Tracked<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Tracked")
+            .field("raw", &self.raw)
+            .field("id", &self.id)
+            .finish()
+    }
+}
+
+// Address of a raw queue, identifying it.
+type QueueKey = usize;
+
+struct Resource {
+    kind: ResourceKind,
+    // Index of the last submission using the resource, on each queue.
+    last_submissions: HashMap<QueueKey, u64>,
+    // Format of image views, attachment formats of render passes and framebuffers.
+    formats: Vec<Option<Format>>,
+    address: usize,
+}
+
+#[derive(Default)]
+struct State {
+    next_id: u64,
+    resources: HashMap<ResourceId, Resource>,
+    // Addresses of the boxed resources, for recognizing barrier targets.
+    addresses: HashMap<usize, ResourceId>,
+    submission_count: u64,
+    // Index of the last submission to each queue.
+    submitted: HashMap<QueueKey, u64>,
+    // All submissions to each queue up to this index completed. Submissions to a queue
+    // complete in order, but queues progress independently of each other.
+    completed: HashMap<QueueKey, u64>,
+    // Queue and index of the last submission signaling each fence.
+    fences: HashMap<ResourceId, (QueueKey, u64)>,
+}
+
+impl State {
+    fn in_flight(&self, resource: &Resource) -> bool {
+        resource.last_submissions
+            .iter()
+            .any(|(queue, &submission)| submission > self.completed.get(queue).cloned().unwrap_or(0))
+    }
+}
+
+struct Shared {
+    state: Mutex<State>,
+    callback: Callback,
+}
+
+impl Shared {
+    fn report(&self, error: ValidationError) {
+        (self.callback)(&error);
+    }
+
+    fn track<T>(&self, raw: T, kind: ResourceKind, formats: Vec<Option<Format>>) -> Tracked<T> {
+        let raw = Box::new(raw);
+        let address = &*raw as *const T as usize;
+        let mut state = self.state.lock().unwrap();
+        state.next_id += 1;
+        let id = ResourceId(state.next_id);
+        state.resources.insert(id, Resource {
+            kind,
+            last_submissions: HashMap::new(),
+            formats,
+            address,
+        });
+        // Boxes of zero-sized resources share their address.
+        if mem::size_of::<T>() != 0 {
+            state.addresses.insert(address, id);
+        }
+        Tracked { raw, id }
+    }
+
+    fn untrack<T>(&self, resource: Tracked<T>) -> T {
+        let mut state = self.state.lock().unwrap();
+        if let Some(tracked) = state.resources.remove(&resource.id) {
+            if state.addresses.get(&tracked.address) == Some(&resource.id) {
+                state.addresses.remove(&tracked.address);
+            }
+            if state.in_flight(&tracked) {
+                drop(state);
+                self.report(ValidationError::DestroyedInFlight {
+                    kind: tracked.kind,
+                    resource: resource.id,
+                });
+            }
+        }
+        *resource.raw
+    }
+
+    fn formats(&self, id: ResourceId) -> Vec<Option<Format>> {
+        let state = self.state.lock().unwrap();
+        state.resources.get(&id).map_or(Vec::new(), |resource| resource.formats.clone())
+    }
+}
+
+/// Device wrapper validating the usage of the resources it creates.
+///
+/// Dereferences to the wrapped device for the operations which aren't validated.
+pub struct ValidationDevice<B: Backend> {
+    raw: B::Device,
+    shared: Arc<Shared>,
+}
+
+impl<B: Backend> fmt::Debug for ValidationDevice<B> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let state = self.shared.state.lock().unwrap();
+        f.debug_struct("ValidationDevice")
+            .field("resources", &state.resources.len())
+            .field("submissions", &state.submission_count)
+            .finish()
+    }
+}
+
+impl<B: Backend> Deref for ValidationDevice<B> {
+    type Target = B::Device;
+    fn deref(&self) -> &B::Device {
+        &self.raw
+    }
+}
+
+impl<B: Backend> ValidationDevice<B> {
+    /// Wrap a device, reporting the errors detected to `callback`.
+    pub fn new<F>(device: B::Device, callback: F) -> Self
+    where
+        F: Fn(&ValidationError) + Send + Sync + 'static,
+    {
+        ValidationDevice {
+            raw: device,
+            shared: Arc::new(Shared {
+                state: Mutex::new(State::default()),
+                callback: Box::new(callback),
+            }),
+        }
+    }
+
+    /// Number of live tracked resources of each kind, e.g. for the crash dumps of `gfx-helpers`.
+    pub fn live_resources(&self) -> Vec<(ResourceKind, usize)> {
+        let state = self.shared.state.lock().unwrap();
+        let mut counts = HashMap::new();
+        for resource in state.resources.values() {
+            *counts.entry(resource.kind).or_insert(0) += 1;
+        }
+        let mut counts = counts.into_iter().collect::<Vec<_>>();
+        counts.sort();
+        counts
+    }
+
+    /// Unwrap the device, the tracked resources have to be destroyed before.
+    pub fn into_inner(self) -> B::Device {
+        self.raw
+    }
+
+    /// Bind memory to a buffer, tracking it.
+    pub fn bind_buffer_memory(
+        &self, memory: &B::Memory, offset: u64, buffer: B::UnboundBuffer
+    ) -> Result<Tracked<B::Buffer>, BindError> {
+        let buffer = self.raw.bind_buffer_memory(memory, offset, buffer)?;
+        Ok(self.shared.track(buffer, ResourceKind::Buffer, Vec::new()))
+    }
+
+    /// Destroy a buffer, reporting if a pending submission uses it.
+    pub fn destroy_buffer(&self, buffer: Tracked<B::Buffer>) {
+        self.raw.destroy_buffer(self.shared.untrack(buffer));
+    }
+
+    /// Bind memory to an image, tracking it.
+    pub fn bind_image_memory(
+        &self, memory: &B::Memory, offset: u64, image: B::UnboundImage
+    ) -> Result<Tracked<B::Image>, BindError> {
+        let image = self.raw.bind_image_memory(memory, offset, image)?;
+        Ok(self.shared.track(image, ResourceKind::Image, Vec::new()))
+    }
+
+    /// Destroy an image, reporting if a pending submission uses it.
+    pub fn destroy_image(&self, image: Tracked<B::Image>) {
+        self.raw.destroy_image(self.shared.untrack(image));
+    }
+
+    /// Create an image view, remembering its format for framebuffer validation.
+    pub fn create_image_view(
+        &self,
+        image: &Tracked<B::Image>,
+        view_kind: image::ViewKind,
+        format: Format,
+        swizzle: Swizzle,
+        range: image::SubresourceRange,
+    ) -> Result<Tracked<B::ImageView>, image::ViewError> {
+        let view = self.raw.create_image_view(image, view_kind, format, swizzle, range)?;
+        Ok(self.shared.track(view, ResourceKind::ImageView, vec![Some(format)]))
+    }
+
+    /// Destroy an image view.
+    pub fn destroy_image_view(&self, view: Tracked<B::ImageView>) {
+        self.raw.destroy_image_view(self.shared.untrack(view));
+    }
+
+    /// Create a render pass, remembering its attachment formats.
+    pub fn create_render_pass<'a, IA, IS, ID>(
+        &self,
+        attachments: IA,
+        subpasses: IS,
+        dependencies: ID,
+    ) -> Tracked<B::RenderPass>
+    where
+        IA: IntoIterator,
+        IA::Item: Borrow<pass::Attachment>,
+        IS: IntoIterator,
+        IS::Item: Borrow<pass::SubpassDesc<'a>>,
+        ID: IntoIterator,
+        ID::Item: Borrow<pass::SubpassDependency>,
+    {
+        let attachments = attachments
+            .into_iter()
+            .map(|attachment| attachment.borrow().clone())
+            .collect::<Vec<_>>();
+        let formats = attachments.iter().map(|attachment| attachment.format).collect();
+        let render_pass = self.raw.create_render_pass(attachments, subpasses, dependencies);
+        self.shared.track(render_pass, ResourceKind::RenderPass, formats)
+    }
+
+    /// Destroy a render pass.
+    pub fn destroy_render_pass(&self, render_pass: Tracked<B::RenderPass>) {
+        self.raw.destroy_render_pass(self.shared.untrack(render_pass));
+    }
+
+    /// Create a framebuffer, checking the formats of the attachments against the
+    /// render pass.
+    pub fn create_framebuffer<'b, I>(
+        &self,
+        render_pass: &Tracked<B::RenderPass>,
+        attachments: I,
+        extent: image::Extent,
+    ) -> Result<Tracked<B::Framebuffer>, FramebufferError>
+    where
+        I: IntoIterator<Item = &'b Tracked<B::ImageView>>,
+        B::ImageView: 'b,
+    {
+        let attachments = attachments.into_iter().collect::<Vec<_>>();
+        let formats = attachments
+            .iter()
+            .map(|view| self.shared.formats(view.id).into_iter().next().and_then(|format| format))
+            .collect::<Vec<_>>();
+        check_formats(&self.shared, &self.shared.formats(render_pass.id), &formats);
+
+        let framebuffer = self.raw.create_framebuffer(
+            render_pass,
+            attachments.iter().map(|view| &***view),
+            extent,
+        )?;
+        Ok(self.shared.track(framebuffer, ResourceKind::Framebuffer, formats))
+    }
+
+    /// Destroy a framebuffer.
+    pub fn destroy_framebuffer(&self, framebuffer: Tracked<B::Framebuffer>) {
+        self.raw.destroy_framebuffer(self.shared.untrack(framebuffer));
+    }
+
+    /// Create a fence, which marks the submissions as completed once signaled.
+    pub fn create_fence(&self, signaled: bool) -> Tracked<B::Fence> {
+        let fence = self.raw.create_fence(signaled);
+        self.shared.track(fence, ResourceKind::Fence, Vec::new())
+    }
+
+    /// Check if a fence is signaled.
+    pub fn get_fence_status(&self, fence: &Tracked<B::Fence>) -> bool {
+        let signaled = self.raw.get_fence_status(fence);
+        if signaled {
+            self.complete(fence);
+        }
+        signaled
+    }
+
+    /// Wait for a fence to be signaled.
+    pub fn wait_for_fence(&self, fence: &Tracked<B::Fence>, timeout_ms: u32) -> bool {
+        let signaled = self.raw.wait_for_fence(fence, timeout_ms);
+        if signaled {
+            self.complete(fence);
+        }
+        signaled
+    }
+
+    /// Destroy a fence, reporting if a pending submission signals it.
+    pub fn destroy_fence(&self, fence: Tracked<B::Fence>) {
+        {
+            let mut state = self.shared.state.lock().unwrap();
+            if let Some((queue, submission)) = state.fences.remove(&fence.id) {
+                if let Some(resource) = state.resources.get_mut(&fence.id) {
+                    resource.last_submissions.insert(queue, submission);
+                }
+            }
+        }
+        self.raw.destroy_fence(self.shared.untrack(fence));
+    }
+
+    /// Wait for the device to be idle, completing all submissions.
+    pub fn wait_idle(&self) -> Result<(), HostExecutionError> {
+        self.raw.wait_idle()?;
+        let mut state = self.shared.state.lock().unwrap();
+        state.completed = state.submitted.clone();
+        Ok(())
+    }
+
+    /// Wait for a queue to be idle, completing the submissions to it.
+    ///
+    /// Waiting through `CommandQueue::wait_idle` directly isn't seen by the validation.
+    pub fn wait_queue_idle<C>(&self, queue: &CommandQueue<B, C>) -> Result<(), HostExecutionError> {
+        queue.wait_idle()?;
+        let key = queue.as_raw() as *const _ as QueueKey;
+        let mut state = self.shared.state.lock().unwrap();
+        if let Some(submission) = state.submitted.get(&key).cloned() {
+            state.completed.insert(key, submission);
+        }
+        Ok(())
+    }
+
+    // Submissions to a queue complete in order, a signaled fence completes the earlier
+    // submissions to the same queue only.
+    fn complete(&self, fence: &Tracked<B::Fence>) {
+        let mut state = self.shared.state.lock().unwrap();
+        if let Some((queue, submission)) = state.fences.get(&fence.id).cloned() {
+            let completed = state.completed.entry(queue).or_insert(0);
+            *completed = (*completed).max(submission);
+        }
+    }
+
+    /// Wrap a raw command buffer for validating the commands recorded into it.
+    pub fn wrap_command_buffer(&self, raw: B::CommandBuffer) -> ValidationCommandBuffer<B> {
+        ValidationCommandBuffer {
+            raw,
+            shared: self.shared.clone(),
+            state: RecordingState::Initial,
+            used: HashSet::new(),
+            pending_writes: HashMap::new(),
+        }
+    }
+
+    /// Submit command buffers, checking that they're recorded and that the resources
+    /// they use are alive.
+    ///
+    /// # Safety
+    ///
+    /// Same as `RawCommandQueue::submit_raw`, the queue must support the recorded commands.
+    pub unsafe fn submit<C>(
+        &self,
+        queue: &mut CommandQueue<B, C>,
+        command_buffers: &[&ValidationCommandBuffer<B>],
+        wait_semaphores: &[(&B::Semaphore, pso::PipelineStage)],
+        signal_semaphores: &[&B::Semaphore],
+        fence: Option<&Tracked<B::Fence>>,
+    ) {
+        let mut errors = Vec::new();
+        {
+            let queue_key = queue.as_raw() as *const _ as QueueKey;
+            let mut state = self.shared.state.lock().unwrap();
+            state.submission_count += 1;
+            let submission = state.submission_count;
+            state.submitted.insert(queue_key, submission);
+            for (index, cmd_buffer) in command_buffers.iter().enumerate() {
+                if cmd_buffer.state != RecordingState::Executable {
+                    errors.push(ValidationError::UnrecordedSubmission {
+                        command_buffer: index,
+                        state: cmd_buffer.state,
+                    });
+                }
+                for &(kind, id) in &cmd_buffer.used {
+                    match state.resources.get_mut(&id) {
+                        Some(resource) => {
+                            resource.last_submissions.insert(queue_key, submission);
+                        }
+                        None => errors.push(ValidationError::UseAfterFree { kind, resource: id }),
+                    }
+                }
+            }
+            if let Some(fence) = fence {
+                state.fences.insert(fence.id, (queue_key, submission));
+            }
+        }
+        for error in errors {
+            self.shared.report(error);
+        }
+
+        queue.as_raw_mut().submit_raw(
+            RawSubmission {
+                cmd_buffers: command_buffers.iter().map(|cmd_buffer| &cmd_buffer.raw),
+                wait_semaphores,
+                signal_semaphores,
+                wait_timeline_semaphores: &[],
+                signal_timeline_semaphores: &[],
+            },
+            fence.map(|fence| &**fence),
+        );
+    }
+}
+
+fn check_formats(shared: &Shared, expected: &[Option<Format>], found: &[Option<Format>]) {
+    for attachment in 0 .. expected.len().max(found.len()) {
+        let expected = expected.get(attachment).cloned();
+        let found = found.get(attachment).cloned();
+        // Dummy render pass attachments without a format are compatible with any view.
+        let compatible = match (expected, found) {
+            (Some(None), Some(_)) => true,
+            (Some(expected), Some(found)) => expected == found,
+            _ => false,
+        };
+        if !compatible {
+            shared.report(ValidationError::IncompatibleFramebuffer {
+                attachment,
+                expected: expected.and_then(|format| format),
+                found: found.and_then(|format| format),
+            });
+        }
+    }
+}
+
+/// Command buffer wrapper validating the recorded commands, created by
+/// `ValidationDevice::wrap_command_buffer`.
+pub struct ValidationCommandBuffer<B: Backend> {
+    raw: B::CommandBuffer,
+    shared: Arc<Shared>,
+    state: RecordingState,
+    used: HashSet<(ResourceKind, ResourceId)>,
+    // Resources written since their last barrier.
+    pending_writes: HashMap<ResourceId, ResourceKind>,
+}
+
+impl<B: Backend> fmt::Debug for ValidationCommandBuffer<B> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("ValidationCommandBuffer")
+            .field("state", &self.state)
+            .field("used", &self.used.len())
+            .field("pending_writes", &self.pending_writes.len())
+            .finish()
+    }
+}
+
+impl<B: Backend> ValidationCommandBuffer<B> {
+    /// Recording state of the command buffer.
+    pub fn state(&self) -> RecordingState {
+        self.state
+    }
+
+    /// Get the raw command buffer, for recording commands which aren't validated.
+    pub fn raw_mut(&mut self) -> &mut B::CommandBuffer {
+        &mut self.raw
+    }
+
+    /// Unwrap the raw command buffer.
+    pub fn into_raw(self) -> B::CommandBuffer {
+        self.raw
+    }
+
+    fn access(&mut self, kind: ResourceKind, id: ResourceId, write: bool) {
+        self.used.insert((kind, id));
+        if self.pending_writes.remove(&id).is_some() {
+            self.shared.report(ValidationError::MissingBarrier { kind, resource: id });
+        }
+        if write {
+            self.pending_writes.insert(id, kind);
+        }
+    }
+
+    /// Begin recording, discarding the previous recording.
+    pub fn begin(&mut self, flags: CommandBufferFlags, inheritance_info: CommandBufferInheritanceInfo<B>) {
+        self.raw.begin(flags, inheritance_info);
+        self.state = RecordingState::Recording;
+        self.used.clear();
+        self.pending_writes.clear();
+    }
+
+    /// Finish recording.
+    pub fn finish(&mut self) {
+        self.raw.finish();
+        self.state = RecordingState::Executable;
+    }
+
+    /// Reset the command buffer to the initial state.
+    pub fn reset(&mut self, release_resources: bool) {
+        self.raw.reset(release_resources);
+        self.state = RecordingState::Initial;
+        self.used.clear();
+        self.pending_writes.clear();
+    }
+
+    /// Record a pipeline barrier, making the writes to its targets visible.
+    pub fn pipeline_barrier<'a, T>(
+        &mut self,
+        stages: Range<pso::PipelineStage>,
+        dependencies: Dependencies,
+        barriers: T,
+    ) where
+        T: IntoIterator,
+        T::Item: Borrow<Barrier<'a, B>>,
+    {
+        let barriers = barriers.into_iter().collect::<Vec<_>>();
+        {
+            let state = self.shared.state.lock().unwrap();
+            for barrier in &barriers {
+                match *barrier.borrow() {
+                    Barrier::AllBuffers(_) => self.pending_writes.retain(|_, kind| *kind != ResourceKind::Buffer),
+                    Barrier::AllImages(_) => self.pending_writes.retain(|_, kind| *kind != ResourceKind::Image),
+                    Barrier::Buffer { target, .. } => {
+                        if let Some(id) = state.addresses.get(&(target as *const _ as usize)) {
+                            self.pending_writes.remove(id);
+                        }
+                    }
+                    Barrier::Image { target, .. } => {
+                        if let Some(id) = state.addresses.get(&(target as *const _ as usize)) {
+                            self.pending_writes.remove(id);
+                        }
+                    }
+                }
+            }
+        }
+        self.raw.pipeline_barrier(stages, dependencies, barriers);
+    }
+
+    /// Fill a buffer with a value.
+    pub fn fill_buffer<R>(&mut self, buffer: &Tracked<B::Buffer>, range: R, data: u32)
+    where
+        R: RangeArg<buffer::Offset>,
+    {
+        self.access(ResourceKind::Buffer, buffer.id, true);
+        self.raw.fill_buffer(buffer, range, data);
+    }
+
+    /// Update a buffer with inline data.
+    pub fn update_buffer(&mut self, buffer: &Tracked<B::Buffer>, offset: buffer::Offset, data: &[u8]) {
+        self.access(ResourceKind::Buffer, buffer.id, true);
+        self.raw.update_buffer(buffer, offset, data);
+    }
+
+    /// Copy regions between buffers.
+    pub fn copy_buffer<T>(&mut self, src: &Tracked<B::Buffer>, dst: &Tracked<B::Buffer>, regions: T)
+    where
+        T: IntoIterator,
+        T::Item: Borrow<BufferCopy>,
+    {
+        self.access(ResourceKind::Buffer, src.id, false);
+        self.access(ResourceKind::Buffer, dst.id, true);
+        self.raw.copy_buffer(src, dst, regions);
+    }
+
+    /// Copy regions between images.
+    pub fn copy_image<T>(
+        &mut self,
+        src: &Tracked<B::Image>,
+        src_layout: image::Layout,
+        dst: &Tracked<B::Image>,
+        dst_layout: image::Layout,
+        regions: T,
+    ) where
+        T: IntoIterator,
+        T::Item: Borrow<ImageCopy>,
+    {
+        self.access(ResourceKind::Image, src.id, false);
+        self.access(ResourceKind::Image, dst.id, true);
+        self.raw.copy_image(src, src_layout, dst, dst_layout, regions);
+    }
+
+    /// Copy regions of a buffer into an image.
+    pub fn copy_buffer_to_image<T>(
+        &mut self,
+        src: &Tracked<B::Buffer>,
+        dst: &Tracked<B::Image>,
+        dst_layout: image::Layout,
+        regions: T,
+    ) where
+        T: IntoIterator,
+        T::Item: Borrow<BufferImageCopy>,
+    {
+        self.access(ResourceKind::Buffer, src.id, false);
+        self.access(ResourceKind::Image, dst.id, true);
+        self.raw.copy_buffer_to_image(src, dst, dst_layout, regions);
+    }
+
+    /// Copy regions of an image into a buffer.
+    pub fn copy_image_to_buffer<T>(
+        &mut self,
+        src: &Tracked<B::Image>,
+        src_layout: image::Layout,
+        dst: &Tracked<B::Buffer>,
+        regions: T,
+    ) where
+        T: IntoIterator,
+        T::Item: Borrow<BufferImageCopy>,
+    {
+        self.access(ResourceKind::Image, src.id, false);
+        self.access(ResourceKind::Buffer, dst.id, true);
+        self.raw.copy_image_to_buffer(src, src_layout, dst, regions);
+    }
+
+    /// Bind vertex buffers.
+    pub fn bind_vertex_buffers<'b, I>(&mut self, first_binding: u32, buffers: I)
+    where
+        I: IntoIterator<Item = (&'b Tracked<B::Buffer>, buffer::Offset)>,
+        B::Buffer: 'b,
+    {
+        let buffers = buffers.into_iter().collect::<Vec<_>>();
+        for &(buffer, _) in &buffers {
+            self.access(ResourceKind::Buffer, buffer.id, false);
+        }
+        self.raw.bind_vertex_buffers(
+            first_binding,
+            buffers.into_iter().map(|(buffer, offset)| (&**buffer, offset)),
+        );
+    }
+
+    /// Bind an index buffer.
+    pub fn bind_index_buffer(&mut self, buffer: &Tracked<B::Buffer>, offset: buffer::Offset, index_type: IndexType) {
+        self.access(ResourceKind::Buffer, buffer.id, false);
+        self.raw.bind_index_buffer(buffer::IndexBufferView {
+            buffer,
+            offset,
+            index_type,
+        });
+    }
+
+    /// Begin a render pass, checking that the framebuffer is compatible with it.
+    pub fn begin_render_pass<T>(
+        &mut self,
+        render_pass: &Tracked<B::RenderPass>,
+        framebuffer: &Tracked<B::Framebuffer>,
+        render_area: pso::Rect,
+        clear_values: T,
+        first_subpass: SubpassContents,
+    ) where
+        T: IntoIterator,
+        T::Item: Borrow<ClearValueRaw>,
+    {
+        check_formats(
+            &self.shared,
+            &self.shared.formats(render_pass.id),
+            &self.shared.formats(framebuffer.id),
+        );
+        self.used.insert((ResourceKind::RenderPass, render_pass.id));
+        self.used.insert((ResourceKind::Framebuffer, framebuffer.id));
+        self.raw.begin_render_pass(render_pass, framebuffer, render_area, clear_values, first_subpass);
+    }
+
+    /// End the current render pass.
+    pub fn end_render_pass(&mut self) {
+        self.raw.end_render_pass();
+    }
+}
@@ -34,10 +34,12 @@
 //! let acquisition_semaphore = device.create_semaphore();
 //! let render_semaphore = device.create_semaphore();
 //!
-//! let frame = swapchain.acquire_image(FrameSync::Semaphore(&acquisition_semaphore));
+//! let (frame, _suboptimal) = swapchain
+//!     .acquire_image(FrameSync::Semaphore(&acquisition_semaphore))
+//!     .unwrap();
 //! // render the scene..
 //! // `render_semaphore` will be signalled once rendering has been finished
-//! swapchain.present(&mut present_queue, 0, &[render_semaphore]);
+//! swapchain.present(&mut present_queue, frame, &[render_semaphore]).unwrap();
 //! # }
 //! ```
 //!
@@ -47,21 +49,34 @@
 //!
 //! ### Recreation
 //!
-//! DOC TODO
+//! A swapchain has to be recreated once it doesn't match the surface anymore, e.g. after
+//! resizing the window. Acquisition and presentation report this either as
+//! `AcquireError::OutOfDate`/`PresentError::OutOfDate`, in which case the swapchain can't be
+//! used anymore, or with a `Suboptimal` flag, in which case presentation still succeeds but
+//! the swapchain should be recreated at the next opportunity. `Suboptimal` carries the
+//! current surface extent and transform where the backend can query them, and
+//! `ResizePolicy::resolve` turns it into either a recreation or a viewport to keep
+//! presenting with, stretched or letterboxed by the presentation engine.
+//!
+//! Query the new surface extent from `Surface::capabilities` and pass it to
+//! `Swapchain::recreate`, which keeps the surface and device alive. All images of the old
+//! swapchain must be released by the device before.
 
 use Backend;
 use image;
 use format::Format;
+use pso::Rect;
 use queue::CommandQueue;
 
 use std::any::Any;
 use std::borrow::Borrow;
+use std::cmp;
 use std::ops::Range;
 
 /// An extent describes the size of a rectangle, such as
 /// a window or texture. It is not used for referring to a
 /// sub-rectangle; for that see `command::Rect`.
-#[derive(Clone, Copy, Debug, PartialEq, PartialOrd)]
+#[derive(Clone, Copy, Debug, Hash, Eq, PartialEq, PartialOrd)]
 #[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct Extent2D {
     /// Width
@@ -80,7 +95,7 @@ impl From<image::Extent> for Extent2D {
 }
 
 /// Describes information about what a `Surface`'s properties are.
-/// Fetch this with `surface.capabilities_and_formats(device)`.
+/// Fetch this with `surface.capabilities(physical_device)`.
 #[derive(Debug, Clone)]
 #[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct SurfaceCapabilities {
@@ -105,6 +120,129 @@ pub struct SurfaceCapabilities {
     ///
     /// Must be at least 1.
     pub max_image_layers: image::Layer,
+
+    /// Current transform of the surface relative to the presentation engine's
+    /// natural orientation, such as the rotation of the display on mobile devices.
+    pub current_transform: SurfaceTransform,
+
+    /// Transforms supported as `SwapchainConfig::pre_transform`.
+    ///
+    /// Always contains `SurfaceTransform::IDENTITY`.
+    pub supported_transforms: SurfaceTransform,
+
+    /// Formats supported for presentable images.
+    ///
+    /// `None` if the surface has no preferred format and the application
+    /// may use any desired format.
+    pub formats: Option<Vec<Format>>,
+
+    /// Supported presentation modes.
+    ///
+    /// Always contains `PresentMode::Fifo`.
+    pub present_modes: Vec<PresentMode>,
+}
+
+impl SurfaceCapabilities {
+    /// Clamp the desired extent of a swapchain into the range of supported extents.
+    ///
+    /// Returns the current extent of the surface if it has an explicit size.
+    pub fn clamp_extent(&self, desired: Extent2D) -> Extent2D {
+        match self.current_extent {
+            Some(extent) => extent,
+            None => Extent2D {
+                width: cmp::max(self.extents.start.width, cmp::min(desired.width, self.extents.end.width)),
+                height: cmp::max(self.extents.start.height, cmp::min(desired.height, self.extents.end.height)),
+            },
+        }
+    }
+
+    /// Select the first of the preferred present modes supported by the surface,
+    /// falling back to `PresentMode::Fifo`.
+    pub fn select_present_mode(&self, preferred: &[PresentMode]) -> PresentMode {
+        preferred
+            .iter()
+            .find(|mode| self.present_modes.contains(mode))
+            .cloned()
+            .unwrap_or(PresentMode::Fifo)
+    }
+
+    /// Clamp the desired number of swapchain images into the supported range.
+    pub fn clamp_image_count(&self, desired: SwapImageIndex) -> SwapImageIndex {
+        cmp::max(self.image_count.start, cmp::min(desired, self.image_count.end))
+    }
+}
+
+bitflags!(
+    /// Transforms applied to the presentable images relative to the
+    /// natural orientation of the presentation engine.
+    ///
+    /// Presenting with a pre-transform matching the current transform of the
+    /// surface avoids the compositor rotating the images on every frame,
+    /// at the cost of rendering them pre-rotated, see `pre_rotation`.
+    #[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+    pub struct SurfaceTransform: u32 {
+        /// No transform.
+        const IDENTITY = 0x1;
+        /// Rotated by 90 degrees clockwise.
+        const ROTATE_90 = 0x2;
+        /// Rotated by 180 degrees clockwise.
+        const ROTATE_180 = 0x4;
+        /// Rotated by 270 degrees clockwise.
+        const ROTATE_270 = 0x8;
+        /// Mirrored horizontally.
+        const HORIZONTAL_MIRROR = 0x10;
+        /// Mirrored horizontally, then rotated by 90 degrees clockwise.
+        const HORIZONTAL_MIRROR_ROTATE_90 = 0x20;
+        /// Mirrored horizontally, then rotated by 180 degrees clockwise.
+        const HORIZONTAL_MIRROR_ROTATE_180 = 0x40;
+        /// Mirrored horizontally, then rotated by 270 degrees clockwise.
+        const HORIZONTAL_MIRROR_ROTATE_270 = 0x80;
+        /// Transform specified by the platform, outside of the API.
+        const INHERIT = 0x100;
+    }
+);
+
+impl SurfaceTransform {
+    /// Check if the transform swaps the width and height of the images,
+    /// in which case the swapchain extent stays in the natural orientation
+    /// while the viewport of the displayed content is swapped.
+    pub fn swaps_extent(&self) -> bool {
+        self.intersects(
+            SurfaceTransform::ROTATE_90 | SurfaceTransform::ROTATE_270 |
+            SurfaceTransform::HORIZONTAL_MIRROR_ROTATE_90 |
+            SurfaceTransform::HORIZONTAL_MIRROR_ROTATE_270
+        )
+    }
+
+    /// Column-major matrix to apply in clip space, after the projection,
+    /// for rendering the images pre-transformed by this single transform.
+    ///
+    /// `INHERIT` and unknown transforms return the identity.
+    pub fn pre_rotation(&self) -> [[f32; 4]; 4] {
+        let (quarter_turns, mirror) = match *self {
+            SurfaceTransform::ROTATE_90 => (1, false),
+            SurfaceTransform::ROTATE_180 => (2, false),
+            SurfaceTransform::ROTATE_270 => (3, false),
+            SurfaceTransform::HORIZONTAL_MIRROR => (0, true),
+            SurfaceTransform::HORIZONTAL_MIRROR_ROTATE_90 => (1, true),
+            SurfaceTransform::HORIZONTAL_MIRROR_ROTATE_180 => (2, true),
+            SurfaceTransform::HORIZONTAL_MIRROR_ROTATE_270 => (3, true),
+            _ => (0, false),
+        };
+        let (cos, sin) = match quarter_turns {
+            0 => (1.0, 0.0),
+            1 => (0.0, 1.0),
+            2 => (-1.0, 0.0),
+            _ => (0.0, -1.0),
+        };
+        let x_scale = if mirror { -1.0 } else { 1.0 };
+        [
+            [cos * x_scale, sin * x_scale, 0.0, 0.0],
+            [-sin, cos, 0.0, 0.0],
+            [0.0, 0.0, 1.0, 0.0],
+            [0.0, 0.0, 0.0, 1.0],
+        ]
+    }
 }
 
 /// A `Surface` abstracts the surface of a native window, which will be presented
@@ -124,14 +262,24 @@ pub trait Surface<B: Backend>: Any + Send + Sync {
 
     /// Query surface capabilities, formats, and present modes for this physical device.
     ///
-    /// Use this function for configuring swapchain creation.
+    /// Use this function for configuring swapchain creation and recreation.
+    /// The capabilities reflect the current state of the surface, so the current extent
+    /// changes as the window gets resized.
+    fn capabilities(&self, physical_device: &B::PhysicalDevice) -> SurfaceCapabilities;
+
+    /// Query surface capabilities, formats, and present modes for this physical device.
     ///
-    /// Returns a tuple of surface capabilities and formats.
+    /// Returns a tuple of surface capabilities, formats and present modes.
     /// If formats is `None` than the surface has no preferred format and the
     /// application may use any desired format.
     fn compatibility(
         &self, physical_device: &B::PhysicalDevice
-    ) -> (SurfaceCapabilities, Option<Vec<Format>>, Vec<PresentMode>);
+    ) -> (SurfaceCapabilities, Option<Vec<Format>>, Vec<PresentMode>) {
+        let caps = self.capabilities(physical_device);
+        let formats = caps.formats.clone();
+        let present_modes = caps.present_modes.clone();
+        (caps, formats, present_modes)
+    }
 }
 
 /// Index of an image in the swapchain.
@@ -157,17 +305,158 @@ pub enum FrameSync<'a, B: Backend> {
     Fence(&'a B::Fence),
 }
 
+/// Returned by a successful acquisition or presentation, if the swapchain
+/// doesn't match the surface properties exactly anymore.
+///
+/// The swapchain can still be used, but should be recreated. The mismatching
+/// properties are reported where the backend can query them, use a `ResizePolicy`
+/// for deciding how to handle them.
+#[derive(Clone, Copy, Debug, Hash, Eq, PartialEq)]
+pub struct Suboptimal {
+    /// Current extent of the surface, if it differs from the swapchain extent.
+    pub extent: Option<Extent2D>,
+    /// Current transform of the surface, if it differs from the swapchain pre-transform.
+    pub transform: Option<SurfaceTransform>,
+}
+
+impl Suboptimal {
+    /// Suboptimal swapchain for reasons the backend can't tell.
+    pub const UNKNOWN: Suboptimal = Suboptimal {
+        extent: None,
+        transform: None,
+    };
+
+    /// Create a suboptimal flag for an extent mismatch, `None` if the extents match.
+    pub fn from_extent(current: Extent2D, swapchain: Extent2D) -> Option<Self> {
+        if current == swapchain {
+            None
+        } else {
+            Some(Suboptimal {
+                extent: Some(current),
+                transform: None,
+            })
+        }
+    }
+}
+
+/// Resize behavior on suboptimal swapchains, applied by `ResizePolicy::resolve`.
+///
+/// Out of date swapchains can't be presented anymore and always have to be recreated.
+#[derive(Clone, Copy, Debug, Hash, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum ResizePolicy {
+    /// Recreate the swapchain as soon as it's suboptimal.
+    Recreate,
+    /// Keep the swapchain, the presentation engine stretches the images to the surface.
+    Stretch,
+    /// Keep the swapchain, rendering into a viewport which keeps the aspect ratio of
+    /// the swapchain once stretched to the surface, with bars on the sides.
+    Letterbox,
+}
+
+/// Action to take for a suboptimal swapchain, returned by `ResizePolicy::resolve`.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Resize {
+    /// Recreate the swapchain with the given extent, or the extent queried
+    /// from `Surface::capabilities` if unknown.
+    Recreate(Option<Extent2D>),
+    /// Keep presenting, rendering into the given viewport of the swapchain images.
+    Present(Rect),
+}
+
+impl ResizePolicy {
+    /// Decide how to handle the result of an acquisition or presentation on a
+    /// swapchain of the given extent.
+    ///
+    /// Transform mismatches are left to the compositor unless the policy is `Recreate`.
+    /// Both flip-model DXGI swapchains and Vulkan swapchains scale the images to the
+    /// surface, so stretching and letterboxing behave the same across backends.
+    pub fn resolve(&self, swapchain: Extent2D, suboptimal: Option<&Suboptimal>) -> Resize {
+        let full = Rect {
+            x: 0,
+            y: 0,
+            w: swapchain.width as i16,
+            h: swapchain.height as i16,
+        };
+        let suboptimal = match suboptimal {
+            Some(suboptimal) => suboptimal,
+            None => return Resize::Present(full),
+        };
+        match (*self, suboptimal.extent) {
+            (ResizePolicy::Recreate, extent) => Resize::Recreate(extent),
+            (ResizePolicy::Stretch, _) |
+            (ResizePolicy::Letterbox, None) => Resize::Present(full),
+            (ResizePolicy::Letterbox, Some(surface)) => Resize::Present(letterbox(swapchain, surface)),
+        }
+    }
+}
+
+// Viewport of the swapchain images which keeps their aspect ratio once stretched
+// to the surface extent.
+fn letterbox(swapchain: Extent2D, surface: Extent2D) -> Rect {
+    let empty = |extent: Extent2D| extent.width == 0 || extent.height == 0;
+    if empty(surface) || empty(swapchain) {
+        return Rect { x: 0, y: 0, w: swapchain.width as i16, h: swapchain.height as i16 };
+    }
+    // Relative size of the content on the surface, the larger side fills it.
+    let sx = swapchain.width as f32 / surface.width as f32;
+    let sy = swapchain.height as f32 / surface.height as f32;
+    let scale = sx.max(sy);
+    let w = (swapchain.width as f32 * sx / scale).round() as i16;
+    let h = (swapchain.height as f32 * sy / scale).round() as i16;
+    Rect {
+        x: (swapchain.width as i16 - w) / 2,
+        y: (swapchain.height as i16 - h) / 2,
+        w,
+        h,
+    }
+}
+
+/// Error on acquiring the next image of a swapchain.
+#[derive(Fail, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AcquireError {
+    /// The swapchain doesn't match the surface anymore and needs to be recreated.
+    #[fail(display = "Swapchain is out of date.")]
+    OutOfDate,
+    /// The surface is no longer available.
+    #[fail(display = "Surface lost.")]
+    SurfaceLost,
+    /// The logical or physical device are lost.
+    #[fail(display = "Device lost.")]
+    DeviceLost,
+}
+
+/// Error on presenting images of swapchains.
+#[derive(Fail, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PresentError {
+    /// The swapchain doesn't match the surface anymore and needs to be recreated.
+    #[fail(display = "Swapchain is out of date.")]
+    OutOfDate,
+    /// The surface is no longer available.
+    #[fail(display = "Surface lost.")]
+    SurfaceLost,
+    /// The logical or physical device are lost.
+    #[fail(display = "Device lost.")]
+    DeviceLost,
+}
+
 /// Specifies the mode regulating how a swapchain presents frames.
+///
+/// Only `Fifo` is guaranteed to be supported, check `SurfaceCapabilities::present_modes`
+/// or use `SurfaceCapabilities::select_present_mode` for the other ones.
 #[repr(C)]
 #[derive(Copy, Clone, Debug, Hash, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub enum PresentMode {
-    /// Don't ever wait for v-sync.
+    /// Don't ever wait for v-sync, presented frames may tear.
     Immediate = 0,
     /// Wait for v-sync, overwrite the last rendered frame.
+    ///
+    /// Doesn't tear nor block the application, at the cost of discarding frames.
     Mailbox = 1,
-    /// Present frames in the same order they are rendered.
+    /// Present frames in the same order they are rendered, waiting for v-sync (vsync on).
     Fifo = 2,
-    /// Don't wait for the next v-sync if we just missed it.
+    /// Don't wait for the next v-sync if we just missed it, presented frames may tear then.
     Relaxed = 3,
 }
 
@@ -202,6 +491,11 @@ pub struct SwapchainConfig {
     pub image_count: SwapImageIndex,
     /// Image usage of the backbuffer images.
     pub image_usage: image::Usage,
+    /// Transform applied by the presentation engine to the backbuffer images.
+    ///
+    /// Must be one of `SurfaceCapabilities::supported_transforms`. Use the current
+    /// transform of the surface to skip the rotation by the compositor on mobile.
+    pub pre_transform: SurfaceTransform,
 }
 
 impl SwapchainConfig {
@@ -219,6 +513,7 @@ impl SwapchainConfig {
             depth_stencil_format: None,
             image_count: 2,
             image_usage: image::Usage::empty(),
+            pre_transform: SurfaceTransform::IDENTITY,
         }
     }
 
@@ -286,6 +581,21 @@ impl SwapchainConfig {
         self
     }
 
+    /// Specify the transform applied to the backbuffer images on presentation.
+    ///
+    /// The rendering has to be pre-transformed accordingly,
+    /// see `SurfaceTransform::pre_rotation`.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    ///
+    /// ```
+    pub fn with_pre_transform(mut self, transform: SurfaceTransform) -> Self {
+        self.pre_transform = transform;
+        self
+    }
+
     // TODO: depth-only, stencil-only, swapchain size, present modes, etc.
 }
 
@@ -303,7 +613,9 @@ pub enum Backbuffer<B: Backend> {
 pub trait Swapchain<B: Backend>: Any + Send + Sync {
     /// Acquire a new swapchain image for rendering. This needs to be called before presenting.
     ///
-    /// Will fail if the swapchain needs recreation.
+    /// Fails with `AcquireError::OutOfDate` if the swapchain needs recreation.
+    /// On success, `Suboptimal` is returned alongside the image index if the swapchain
+    /// should be recreated but can still be presented to.
     ///
     /// # Synchronization
     ///
@@ -317,7 +629,9 @@ pub trait Swapchain<B: Backend>: Any + Send + Sync {
     /// ```no_run
     ///
     /// ```
-    fn acquire_image(&mut self, sync: FrameSync<B>) -> Result<SwapImageIndex, ()>;
+    fn acquire_image(
+        &mut self, sync: FrameSync<B>
+    ) -> Result<(SwapImageIndex, Option<Suboptimal>), AcquireError>;
 
     /// Present one acquired image.
     ///
@@ -336,7 +650,7 @@ pub trait Swapchain<B: Backend>: Any + Send + Sync {
         present_queue: &mut CommandQueue<B, C>,
         image_index: SwapImageIndex,
         wait_semaphores: IW,
-    ) -> Result<(), ()>
+    ) -> Result<Option<Suboptimal>, PresentError>
     where
         &'a Self: Borrow<B::Swapchain>,
         Self: Sized + 'a,
@@ -345,4 +659,82 @@ pub trait Swapchain<B: Backend>: Any + Send + Sync {
     {
         present_queue.present(Some((self, image_index)), wait_semaphores)
     }
+
+    /// Recreate the swapchain for a new configuration or surface extent, e.g. after
+    /// acquisition or presentation reported it as out of date or suboptimal.
+    ///
+    /// The old swapchain is retired and passed on to the backend, which may reuse
+    /// its resources. Its images and backbuffer _must_ not be in use by the device anymore.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    ///
+    /// ```
+    fn recreate(
+        self,
+        device: &B::Device,
+        surface: &mut B::Surface,
+        config: SwapchainConfig,
+        extent: &Extent2D,
+    ) -> (B::Swapchain, Backbuffer<B>)
+    where
+        Self: Sized,
+        B: Backend<Swapchain = Self>,
+    {
+        use Device;
+        device.create_swapchain(surface, config, Some(self), extent)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn extent(width: u32, height: u32) -> Extent2D {
+        Extent2D { width, height }
+    }
+
+    fn rect(x: i16, y: i16, w: i16, h: i16) -> Rect {
+        Rect { x, y, w, h }
+    }
+
+    #[test]
+    fn test_resolve() {
+        let swapchain = extent(800, 600);
+        let full = Resize::Present(rect(0, 0, 800, 600));
+        let resized = Suboptimal::from_extent(extent(1600, 600), swapchain).unwrap();
+
+        for policy in &[ResizePolicy::Recreate, ResizePolicy::Stretch, ResizePolicy::Letterbox] {
+            assert_eq!(policy.resolve(swapchain, None), full);
+        }
+        assert_eq!(ResizePolicy::Recreate.resolve(swapchain, Some(&resized)), Resize::Recreate(Some(extent(1600, 600))));
+        assert_eq!(ResizePolicy::Recreate.resolve(swapchain, Some(&Suboptimal::UNKNOWN)), Resize::Recreate(None));
+        assert_eq!(ResizePolicy::Stretch.resolve(swapchain, Some(&resized)), full);
+        assert_eq!(ResizePolicy::Letterbox.resolve(swapchain, Some(&Suboptimal::UNKNOWN)), full);
+        assert_eq!(ResizePolicy::Letterbox.resolve(swapchain, Some(&resized)), Resize::Present(rect(200, 0, 400, 600)));
+    }
+
+    #[test]
+    fn test_letterbox() {
+        let swapchain = extent(800, 600);
+        // Matching aspect ratios fill the swapchain, whatever the size.
+        assert_eq!(letterbox(swapchain, extent(400, 300)), rect(0, 0, 800, 600));
+        // Wider surface, bars on the left and right.
+        assert_eq!(letterbox(swapchain, extent(1600, 600)), rect(200, 0, 400, 600));
+        // Taller surface, bars on the top and bottom.
+        assert_eq!(letterbox(swapchain, extent(800, 1200)), rect(0, 150, 800, 300));
+        // Two thirds of 100 pixels are rounded, the remainder is split evenly.
+        assert_eq!(letterbox(extent(100, 100), extent(300, 200)), rect(16, 0, 67, 100));
+    }
+
+    #[test]
+    fn test_letterbox_zero_extent() {
+        let swapchain = extent(800, 600);
+        // Minimized windows report a zero extent, the viewport is kept.
+        assert_eq!(letterbox(swapchain, extent(0, 0)), rect(0, 0, 800, 600));
+        assert_eq!(letterbox(swapchain, extent(0, 600)), rect(0, 0, 800, 600));
+        assert_eq!(letterbox(swapchain, extent(800, 0)), rect(0, 0, 800, 600));
+        assert_eq!(letterbox(extent(0, 0), extent(800, 600)), rect(0, 0, 0, 0));
+    }
 }
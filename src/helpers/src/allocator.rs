@@ -0,0 +1,844 @@
+//! Sub-allocation of device memory.
+//!
+//! Drivers limit the number of live memory objects, Vulkan only guarantees 4096 of them,
+//! and allocating memory is slow. `Allocator` places resources into large blocks of memory
+//! instead, with one of the following strategies:
+//!
+//! - `Strategy::Linear` bumps an offset through a block, for short lived data such as
+//!   per-frame uniforms. A block is reused once all of its allocations have been freed.
+//! - `Strategy::Chunked` keeps a free list per block, for long lived resources.
+//! - `Strategy::Dedicated` allocates a memory object per resource, for render targets and
+//!   other large resources. Allocations exceeding `AllocatorConfig::dedicated_threshold`
+//!   are always dedicated.
+//!
+//! The memory allocated from each heap is tracked against a budget. Buffers created with
+//! `Allocator::create_buffer` are owned by the allocator and may be moved by `defragment`,
+//! which copies them out of sparsely used blocks so the blocks can be freed.
+//!
+//! ```ignore
+//! let mut allocator = Allocator::new(physical_device.memory_properties(), AllocatorConfig::default());
+//! let unbound = device.create_buffer(size, buffer::Usage::VERTEX)?;
+//! let (buffer, allocation) = allocator.bind_buffer(&device, unbound, Properties::DEVICE_LOCAL, Strategy::Chunked)?;
+//! // use the buffer
+//! device.destroy_buffer(buffer);
+//! allocator.free(&device, allocation);
+//! ```
+
+use std::{cmp, fmt, mem};
+use std::ops::Range;
+
+use hal::adapter::{MemoryProperties, MemoryTypeId};
+use hal::command::{BufferCopy, CommandBuffer, Level, Shot};
+use hal::device::{BindError, Device};
+use hal::memory::{Properties, Requirements};
+use hal::queue::capability::{Supports, Transfer};
+use hal::{buffer, Backend};
+
+/// Placement strategy of an allocation.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum Strategy {
+    /// Bump allocation, the block is reused once all of its allocations are freed.
+    Linear,
+    /// First fit allocation from a free list.
+    Chunked,
+    /// A memory object for the allocation alone.
+    Dedicated,
+}
+
+/// Sizes of the blocks allocated by an `Allocator`.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct AllocatorConfig {
+    /// Size of the blocks of `Strategy::Chunked` allocations.
+    pub chunk_size: u64,
+    /// Minimum size of the blocks of `Strategy::Linear` allocations.
+    pub linear_size: u64,
+    /// Allocations larger than this get a dedicated memory object, whatever their strategy.
+    pub dedicated_threshold: u64,
+}
+
+impl Default for AllocatorConfig {
+    fn default() -> Self {
+        AllocatorConfig {
+            chunk_size: 64 << 20,
+            linear_size: 16 << 20,
+            dedicated_threshold: 32 << 20,
+        }
+    }
+}
+
+/// Error allocating memory or creating a resource in it.
+#[derive(Fail, Debug, Clone, PartialEq)]
+pub enum AllocationError {
+    /// None of the memory types supported by the resource has the requested properties.
+    #[fail(display = "No memory type has the requested properties.")]
+    NoSuitableMemory,
+    /// Memory allocation on the device side failed.
+    #[fail(display = "Device memory allocation failed.")]
+    OutOfMemory,
+    /// Allocating a block would exceed the budget of the heap.
+    #[fail(display = "Allocation exceeds the budget of heap {}.", heap)]
+    OverBudget {
+        /// Index of the heap.
+        heap: usize,
+    },
+    /// Buffer creation failed.
+    #[fail(display = "{}", _0)]
+    Buffer(buffer::CreationError),
+    /// Binding the memory to the resource failed.
+    #[fail(display = "{}", _0)]
+    Bind(BindError),
+}
+
+/// Memory usage of a heap.
+#[derive(Clone, Copy, Debug, Default, Eq, Hash, PartialEq)]
+pub struct HeapBudget {
+    /// Size of the heap in bytes.
+    pub size: u64,
+    /// Limit of the memory allocated from the heap, initially its size.
+    pub budget: u64,
+    /// Memory allocated from the heap, including the unused parts of blocks.
+    pub allocated: u64,
+    /// Memory of the live allocations.
+    pub used: u64,
+    /// Number of memory objects allocated from the heap.
+    pub blocks: usize,
+}
+
+/// Range of a memory block, which has to be returned with `Allocator::free`.
+#[derive(Debug, Eq, Hash, PartialEq)]
+pub struct Allocation {
+    block: usize,
+    offset: u64,
+    size: u64,
+}
+
+impl Allocation {
+    /// Offset of the allocation in its memory object.
+    pub fn offset(&self) -> u64 {
+        self.offset
+    }
+
+    /// Size of the allocation in bytes.
+    pub fn size(&self) -> u64 {
+        self.size
+    }
+}
+
+/// Identifier of a buffer owned by an `Allocator`.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub struct BufferId(usize);
+
+struct Block<M> {
+    memory: M,
+    memory_type: MemoryTypeId,
+    size: u64,
+    strategy: Strategy,
+    // Free ranges of chunked blocks, sorted and coalesced.
+    free: Vec<Range<u64>>,
+    // Next offset of linear blocks.
+    cursor: u64,
+    allocations: usize,
+    used: u64,
+}
+
+impl<M> Block<M> {
+    fn new(memory: M, memory_type: MemoryTypeId, size: u64, strategy: Strategy) -> Self {
+        Block {
+            memory,
+            memory_type,
+            size,
+            strategy,
+            free: vec![0 .. size],
+            cursor: 0,
+            allocations: 0,
+            used: 0,
+        }
+    }
+
+    fn place(&mut self, size: u64, alignment: u64) -> Option<u64> {
+        let alignment = cmp::max(alignment, 1);
+        let align = |offset: u64| (offset + alignment - 1) / alignment * alignment;
+        let offset = match self.strategy {
+            Strategy::Linear => {
+                let offset = align(self.cursor);
+                if offset + size > self.size {
+                    return None;
+                }
+                self.cursor = offset + size;
+                offset
+            }
+            Strategy::Chunked => {
+                let (index, offset) = self.free
+                    .iter()
+                    .enumerate()
+                    .map(|(index, range)| (index, align(range.start)))
+                    .find(|&(index, offset)| offset + size <= self.free[index].end)?;
+                let range = self.free.remove(index);
+                if offset + size < range.end {
+                    self.free.insert(index, offset + size .. range.end);
+                }
+                if range.start < offset {
+                    self.free.insert(index, range.start .. offset);
+                }
+                offset
+            }
+            Strategy::Dedicated => {
+                if self.allocations != 0 || size > self.size {
+                    return None;
+                }
+                0
+            }
+        };
+        self.allocations += 1;
+        self.used += size;
+        Some(offset)
+    }
+
+    fn release(&mut self, offset: u64, size: u64) {
+        self.allocations -= 1;
+        self.used -= size;
+        match self.strategy {
+            Strategy::Linear => {
+                if self.allocations == 0 {
+                    self.cursor = 0;
+                }
+            }
+            Strategy::Chunked => {
+                let index = self.free
+                    .iter()
+                    .position(|range| range.start > offset)
+                    .unwrap_or(self.free.len());
+                self.free.insert(index, offset .. offset + size);
+                if index + 1 < self.free.len() && self.free[index].end == self.free[index + 1].start {
+                    let next = self.free.remove(index + 1);
+                    self.free[index].end = next.end;
+                }
+                if index > 0 && self.free[index - 1].end == self.free[index].start {
+                    let range = self.free.remove(index);
+                    self.free[index - 1].end = range.end;
+                }
+            }
+            Strategy::Dedicated => {}
+        }
+    }
+}
+
+struct OwnedBuffer<B: Backend> {
+    buffer: B::Buffer,
+    allocation: Allocation,
+    requirements: Requirements,
+    size: u64,
+    usage: buffer::Usage,
+}
+
+/// Sub-allocates resources from large blocks of device memory.
+pub struct Allocator<B: Backend> {
+    memory_properties: MemoryProperties,
+    config: AllocatorConfig,
+    blocks: Vec<Option<Block<B::Memory>>>,
+    free_blocks: Vec<usize>,
+    heaps: Vec<HeapBudget>,
+    buffers: Vec<Option<OwnedBuffer<B>>>,
+    free_buffers: Vec<usize>,
+    // Buffers replaced by `defragment`, destroyed by `cleanup`.
+    retired: Vec<(B::Buffer, Allocation)>,
+}
+
+impl<B: Backend> fmt::Debug for Allocator<B> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Allocator")
+            .field("config", &self.config)
+            .field("heaps", &self.heaps)
+            .field("buffers", &(self.buffers.len() - self.free_buffers.len()))
+            .field("retired", &self.retired.len())
+            .finish()
+    }
+}
+
+impl<B: Backend> Allocator<B> {
+    /// Create an allocator for the memory types of a physical device.
+    pub fn new(memory_properties: MemoryProperties, config: AllocatorConfig) -> Self {
+        let heaps = memory_properties.memory_heaps
+            .iter()
+            .map(|&size| HeapBudget {
+                size,
+                budget: size,
+                .. HeapBudget::default()
+            })
+            .collect();
+
+        Allocator {
+            memory_properties,
+            config,
+            blocks: Vec::new(),
+            free_blocks: Vec::new(),
+            heaps,
+            buffers: Vec::new(),
+            free_buffers: Vec::new(),
+            retired: Vec::new(),
+        }
+    }
+
+    /// Returns the memory usage of a heap.
+    pub fn heap_budget(&self, heap: usize) -> HeapBudget {
+        self.heaps[heap]
+    }
+
+    /// Limit the memory allocated from a heap, e.g. to leave room for other applications.
+    ///
+    /// Blocks allocated already are kept, the limit applies to new blocks.
+    pub fn set_heap_budget(&mut self, heap: usize, budget: u64) {
+        self.heaps[heap].budget = budget;
+    }
+
+    /// Allocate memory for the given requirements from a memory type with `properties`.
+    ///
+    /// Memory types are tried in their order, falling back to the next one if a type
+    /// has no space left within the budget of its heap. Linear allocations of host visible
+    /// memory try fast-upload memory first, see `MemoryProperties::fast_upload_type`.
+    pub fn allocate(
+        &mut self,
+        device: &B::Device,
+        requirements: Requirements,
+        properties: Properties,
+        strategy: Strategy,
+    ) -> Result<Allocation, AllocationError> {
+        let strategy = if requirements.size > self.config.dedicated_threshold {
+            Strategy::Dedicated
+        } else {
+            strategy
+        };
+        let mut memory_types = self.memory_properties.memory_types
+            .iter()
+            .enumerate()
+            .filter(|&(id, ty)| requirements.type_mask & (1 << id) != 0 && ty.properties.contains(properties))
+            .map(|(id, _)| MemoryTypeId(id))
+            .collect::<Vec<_>>();
+        // Host written linear allocations, like dynamic uniforms, are read by the device
+        // at full speed from fast-upload memory.
+        if strategy == Strategy::Linear && properties.contains(Properties::CPU_VISIBLE) {
+            if let Some(fast) = self.memory_properties.fast_upload_type(requirements.type_mask) {
+                if let Some(index) = memory_types.iter().position(|&ty| ty == fast) {
+                    let fast = memory_types.remove(index);
+                    memory_types.insert(0, fast);
+                }
+            }
+        }
+
+        let mut error = AllocationError::NoSuitableMemory;
+        for memory_type in memory_types {
+            if let Some(allocation) = self.suballocate(memory_type, requirements, strategy, &[]) {
+                return Ok(allocation);
+            }
+            let block_size = match strategy {
+                Strategy::Linear => cmp::max(self.config.linear_size, requirements.size),
+                Strategy::Chunked => cmp::max(self.config.chunk_size, requirements.size),
+                Strategy::Dedicated => requirements.size,
+            };
+            match self.allocate_block(device, memory_type, block_size, strategy) {
+                Ok(block) => {
+                    let offset = self.blocks[block]
+                        .as_mut()
+                        .unwrap()
+                        .place(requirements.size, requirements.alignment)
+                        .unwrap();
+                    return Ok(self.allocation(block, offset, requirements.size));
+                }
+                Err(e) => error = e,
+            }
+        }
+        Err(error)
+    }
+
+    /// Return an allocation, the resources bound to it have to be destroyed already.
+    ///
+    /// Empty chunked and dedicated blocks are freed, empty linear blocks are kept
+    /// for reuse until `trim`.
+    pub fn free(&mut self, device: &B::Device, allocation: Allocation) {
+        let empty = {
+            let block = self.blocks[allocation.block].as_mut().unwrap();
+            block.release(allocation.offset, allocation.size);
+            self.heaps[self.memory_properties.memory_types[block.memory_type.0].heap_index].used -= allocation.size;
+            block.allocations == 0 && block.strategy != Strategy::Linear
+        };
+        if empty {
+            self.free_block(device, allocation.block);
+        }
+    }
+
+    /// Free the empty linear blocks.
+    pub fn trim(&mut self, device: &B::Device) {
+        let empty = self.blocks
+            .iter()
+            .enumerate()
+            .filter(|&(_, block)| block.as_ref().map_or(false, |block| block.allocations == 0))
+            .map(|(index, _)| index)
+            .collect::<Vec<_>>();
+        for index in empty {
+            self.free_block(device, index);
+        }
+    }
+
+    /// Returns the memory object of an allocation.
+    pub fn memory(&self, allocation: &Allocation) -> &B::Memory {
+        &self.blocks[allocation.block].as_ref().unwrap().memory
+    }
+
+    /// Returns the memory type of an allocation.
+    pub fn memory_type(&self, allocation: &Allocation) -> MemoryTypeId {
+        self.blocks[allocation.block].as_ref().unwrap().memory_type
+    }
+
+    /// Allocate memory for a buffer and bind it.
+    pub fn bind_buffer(
+        &mut self,
+        device: &B::Device,
+        buffer: B::UnboundBuffer,
+        properties: Properties,
+        strategy: Strategy,
+    ) -> Result<(B::Buffer, Allocation), AllocationError> {
+        let requirements = device.get_buffer_requirements(&buffer);
+        let allocation = self.allocate(device, requirements, properties, strategy)?;
+        match device.bind_buffer_memory(self.memory(&allocation), allocation.offset, buffer) {
+            Ok(buffer) => Ok((buffer, allocation)),
+            Err(e) => {
+                self.free(device, allocation);
+                Err(AllocationError::Bind(e))
+            }
+        }
+    }
+
+    /// Allocate memory for an image and bind it.
+    pub fn bind_image(
+        &mut self,
+        device: &B::Device,
+        image: B::UnboundImage,
+        properties: Properties,
+        strategy: Strategy,
+    ) -> Result<(B::Image, Allocation), AllocationError> {
+        let requirements = device.get_image_requirements(&image);
+        let allocation = self.allocate(device, requirements, properties, strategy)?;
+        match device.bind_image_memory(self.memory(&allocation), allocation.offset, image) {
+            Ok(image) => Ok((image, allocation)),
+            Err(e) => {
+                self.free(device, allocation);
+                Err(AllocationError::Bind(e))
+            }
+        }
+    }
+
+    /// Create a buffer owned by the allocator.
+    ///
+    /// Chunked buffers with `TRANSFER_SRC` and `TRANSFER_DST` usage may be moved by `defragment`.
+    pub fn create_buffer(
+        &mut self,
+        device: &B::Device,
+        size: u64,
+        usage: buffer::Usage,
+        properties: Properties,
+        strategy: Strategy,
+    ) -> Result<BufferId, AllocationError> {
+        let unbound = device.create_buffer(size, usage).map_err(AllocationError::Buffer)?;
+        let requirements = device.get_buffer_requirements(&unbound);
+        let (buffer, allocation) = self.bind_buffer(device, unbound, properties, strategy)?;
+        let owned = OwnedBuffer {
+            buffer,
+            allocation,
+            requirements,
+            size,
+            usage,
+        };
+
+        Ok(match self.free_buffers.pop() {
+            Some(index) => {
+                self.buffers[index] = Some(owned);
+                BufferId(index)
+            }
+            None => {
+                self.buffers.push(Some(owned));
+                BufferId(self.buffers.len() - 1)
+            }
+        })
+    }
+
+    /// Returns a buffer owned by the allocator.
+    pub fn buffer(&self, id: BufferId) -> &B::Buffer {
+        &self.buffers[id.0].as_ref().unwrap().buffer
+    }
+
+    /// Returns the allocation of a buffer owned by the allocator, e.g. for mapping it.
+    pub fn buffer_allocation(&self, id: BufferId) -> &Allocation {
+        &self.buffers[id.0].as_ref().unwrap().allocation
+    }
+
+    /// Destroy a buffer owned by the allocator and free its memory.
+    pub fn destroy_buffer(&mut self, device: &B::Device, id: BufferId) {
+        let owned = self.buffers[id.0].take().unwrap();
+        self.free_buffers.push(id.0);
+        device.destroy_buffer(owned.buffer);
+        self.free(device, owned.allocation);
+    }
+
+    /// Move the movable buffers out of chunked blocks which are at most half used,
+    /// so these blocks are freed once the previous buffers are destroyed by `cleanup`.
+    ///
+    /// A block is only emptied if all of its allocations are movable buffers, which fit
+    /// into the remaining blocks of their memory type. The copies are recorded into
+    /// `cmd_buffer` without synchronization: the moved buffers must not be accessed by the
+    /// device while it executes, and later accesses need a barrier against the transfer
+    /// writes. Returns the moved buffers, whose descriptors and other references to the
+    /// previous buffers have to be rewritten.
+    pub fn defragment<C, S, L>(
+        &mut self,
+        device: &B::Device,
+        cmd_buffer: &mut CommandBuffer<B, C, S, L>,
+    ) -> Vec<BufferId>
+    where
+        C: Supports<Transfer>,
+        S: Shot,
+        L: Level,
+    {
+        let movable = |owned: &OwnedBuffer<B>| owned.usage.contains(buffer::Usage::TRANSFER_SRC | buffer::Usage::TRANSFER_DST);
+        let mut candidates = self.blocks
+            .iter()
+            .enumerate()
+            .filter_map(|(index, block)| {
+                let block = block.as_ref()?;
+                if block.strategy != Strategy::Chunked || block.used * 2 > block.size {
+                    return None;
+                }
+                let buffers = self.buffers
+                    .iter()
+                    .filter_map(|owned| owned.as_ref())
+                    .filter(|owned| owned.allocation.block == index && movable(owned))
+                    .count();
+                if buffers == block.allocations {
+                    Some((block.used, index))
+                } else {
+                    None
+                }
+            })
+            .collect::<Vec<_>>();
+        // Sparsest blocks first. Buffers aren't moved into emptied blocks, and blocks
+        // which received buffers aren't emptied anymore.
+        candidates.sort();
+        let mut excluded = Vec::new();
+        let mut filled = Vec::new();
+
+        let mut moved = Vec::new();
+        for (_, block) in candidates {
+            if filled.contains(&block) {
+                continue;
+            }
+            excluded.push(block);
+            let memory_type = self.blocks[block].as_ref().unwrap().memory_type;
+            let ids = self.buffers
+                .iter()
+                .enumerate()
+                .filter(|&(_, owned)| owned.as_ref().map_or(false, |owned| owned.allocation.block == block))
+                .map(|(index, _)| index)
+                .collect::<Vec<_>>();
+
+            // Reserve the space first, the block is only worth moving if it's emptied.
+            let mut reserved = Vec::with_capacity(ids.len());
+            for &id in &ids {
+                let requirements = self.buffers[id].as_ref().unwrap().requirements;
+                match self.suballocate(memory_type, requirements, Strategy::Chunked, &excluded) {
+                    Some(allocation) => reserved.push(allocation),
+                    None => break,
+                }
+            }
+            if reserved.len() < ids.len() {
+                for allocation in reserved {
+                    self.free(device, allocation);
+                }
+                excluded.pop();
+                continue;
+            }
+            filled.extend(reserved.iter().map(|allocation| allocation.block));
+
+            for (id, allocation) in ids.into_iter().zip(reserved) {
+                let (size, usage) = {
+                    let owned = self.buffers[id].as_ref().unwrap();
+                    (owned.size, owned.usage)
+                };
+                let buffer = device
+                    .create_buffer(size, usage)
+                    .and_then(|unbound| {
+                        device
+                            .bind_buffer_memory(self.memory(&allocation), allocation.offset, unbound)
+                            .map_err(|_| buffer::CreationError::OutOfDeviceMemory)
+                    });
+                let buffer = match buffer {
+                    Ok(buffer) => buffer,
+                    // The buffer stays in place, the block is freed once it's destroyed.
+                    Err(_) => {
+                        self.free(device, allocation);
+                        continue;
+                    }
+                };
+
+                let owned = self.buffers[id].as_mut().unwrap();
+                cmd_buffer.copy_buffer(&owned.buffer, &buffer, &[BufferCopy { src: 0, dst: 0, size }]);
+                let previous = (
+                    mem::replace(&mut owned.buffer, buffer),
+                    mem::replace(&mut owned.allocation, allocation),
+                );
+                self.retired.push(previous);
+                moved.push(BufferId(id));
+            }
+        }
+        moved
+    }
+
+    /// Destroy the buffers replaced by `defragment` and free their memory.
+    ///
+    /// The command buffer with the copies has to be completed.
+    pub fn cleanup(&mut self, device: &B::Device) {
+        for (buffer, allocation) in mem::replace(&mut self.retired, Vec::new()) {
+            device.destroy_buffer(buffer);
+            self.free(device, allocation);
+        }
+    }
+
+    /// Destroy the owned and retired buffers and free all memory.
+    ///
+    /// Allocations which haven't been returned are invalidated.
+    pub fn destroy(mut self, device: &B::Device) {
+        for (buffer, _) in self.retired {
+            device.destroy_buffer(buffer);
+        }
+        for owned in self.buffers.into_iter().filter_map(|b| b) {
+            device.destroy_buffer(owned.buffer);
+        }
+        for block in self.blocks.drain(..).filter_map(|b| b) {
+            device.free_memory(block.memory);
+        }
+    }
+
+    fn allocation(&mut self, block: usize, offset: u64, size: u64) -> Allocation {
+        let memory_type = self.blocks[block].as_ref().unwrap().memory_type;
+        self.heaps[self.memory_properties.memory_types[memory_type.0].heap_index].used += size;
+        Allocation { block, offset, size }
+    }
+
+    // Place an allocation into an existing block of the memory type.
+    fn suballocate(
+        &mut self,
+        memory_type: MemoryTypeId,
+        requirements: Requirements,
+        strategy: Strategy,
+        excluded: &[usize],
+    ) -> Option<Allocation> {
+        if strategy == Strategy::Dedicated {
+            return None;
+        }
+        let placed = self.blocks
+            .iter_mut()
+            .enumerate()
+            .filter(|&(index, _)| !excluded.contains(&index))
+            .filter_map(|(index, block)| {
+                let block = block.as_mut()?;
+                if block.memory_type != memory_type || block.strategy != strategy {
+                    return None;
+                }
+                block
+                    .place(requirements.size, requirements.alignment)
+                    .map(|offset| (index, offset))
+            })
+            .next();
+        placed.map(|(block, offset)| self.allocation(block, offset, requirements.size))
+    }
+
+    fn allocate_block(
+        &mut self,
+        device: &B::Device,
+        memory_type: MemoryTypeId,
+        size: u64,
+        strategy: Strategy,
+    ) -> Result<usize, AllocationError> {
+        let heap = self.memory_properties.memory_types[memory_type.0].heap_index;
+        if self.heaps[heap].allocated + size > self.heaps[heap].budget {
+            return Err(AllocationError::OverBudget { heap });
+        }
+        let memory = device
+            .allocate_memory(memory_type, size)
+            .map_err(|_| AllocationError::OutOfMemory)?;
+        self.heaps[heap].allocated += size;
+        self.heaps[heap].blocks += 1;
+
+        let block = Block::new(memory, memory_type, size, strategy);
+        Ok(match self.free_blocks.pop() {
+            Some(index) => {
+                self.blocks[index] = Some(block);
+                index
+            }
+            None => {
+                self.blocks.push(Some(block));
+                self.blocks.len() - 1
+            }
+        })
+    }
+
+    fn free_block(&mut self, device: &B::Device, index: usize) {
+        let block = self.blocks[index].take().unwrap();
+        let heap = self.memory_properties.memory_types[block.memory_type.0].heap_index;
+        self.heaps[heap].allocated -= block.size;
+        self.heaps[heap].blocks -= 1;
+        self.free_blocks.push(index);
+        device.free_memory(block.memory);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use hal::command::OneShot;
+    use hal::pool::CommandPoolCreateFlags;
+    use hal::queue::{General, QueueGroup, Submission};
+    use hal::{Instance, PhysicalDevice};
+    use software;
+
+    fn block(strategy: Strategy) -> Block<()> {
+        Block::new((), MemoryTypeId(0), 256, strategy)
+    }
+
+    #[test]
+    fn test_chunked_coalescing() {
+        let mut block = block(Strategy::Chunked);
+        let offsets = (0 .. 4)
+            .map(|_| block.place(64, 1).unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(offsets, vec![0, 64, 128, 192]);
+        assert_eq!(block.place(1, 1), None);
+        assert!(block.free.is_empty());
+
+        // Neither neighbour is free.
+        block.release(64, 64);
+        assert_eq!(block.free, vec![64 .. 128]);
+        block.release(192, 64);
+        assert_eq!(block.free, vec![64 .. 128, 192 .. 256]);
+        // Merges with the free range after it.
+        block.release(0, 64);
+        assert_eq!(block.free, vec![0 .. 128, 192 .. 256]);
+        // Merges with the free ranges on both sides.
+        block.release(128, 64);
+        assert_eq!(block.free, vec![0 .. 256]);
+        assert_eq!((block.allocations, block.used), (0, 0));
+    }
+
+    #[test]
+    fn test_chunked_first_fit() {
+        let mut block = block(Strategy::Chunked);
+        for _ in 0 .. 4 {
+            block.place(64, 1).unwrap();
+        }
+        block.release(0, 64);
+        block.release(128, 64);
+
+        // Too large for either hole.
+        assert_eq!(block.place(96, 1), None);
+        assert_eq!(block.place(32, 1), Some(0));
+        assert_eq!(block.place(64, 1), Some(128));
+        assert_eq!(block.free, vec![32 .. 64]);
+    }
+
+    #[test]
+    fn test_chunked_alignment() {
+        let mut block = block(Strategy::Chunked);
+        assert_eq!(block.place(24, 1), Some(0));
+        // The padding before the aligned offset stays free.
+        assert_eq!(block.place(16, 64), Some(64));
+        assert_eq!(block.free, vec![24 .. 64, 80 .. 256]);
+        // Fits into the padding.
+        assert_eq!(block.place(8, 8), Some(24));
+        assert_eq!(block.free, vec![32 .. 64, 80 .. 256]);
+        // An alignment of 0 is treated as 1.
+        assert_eq!(block.place(4, 0), Some(32));
+
+        block.release(64, 16);
+        assert_eq!(block.free, vec![36 .. 256]);
+        // Aligned past the end of the block.
+        assert_eq!(block.place(16, 512), None);
+    }
+
+    #[test]
+    fn test_linear() {
+        let mut block = block(Strategy::Linear);
+        assert_eq!(block.place(100, 1), Some(0));
+        assert_eq!(block.place(100, 64), Some(128));
+        assert_eq!(block.place(32, 1), None);
+        assert_eq!(block.place(28, 1), Some(228));
+
+        // Freed space is only reused once the block is empty.
+        block.release(0, 100);
+        assert_eq!(block.place(1, 1), None);
+        block.release(128, 100);
+        block.release(228, 28);
+        assert_eq!(block.place(256, 1), Some(0));
+    }
+
+    #[test]
+    fn test_dedicated() {
+        let mut block = block(Strategy::Dedicated);
+        assert_eq!(block.place(512, 1), None);
+        assert_eq!(block.place(256, 1), Some(0));
+        assert_eq!(block.place(1, 1), None);
+        block.release(0, 256);
+        assert_eq!(block.place(128, 1), Some(0));
+    }
+
+    fn open() -> (software::Device, QueueGroup<software::Backend, General>, MemoryProperties) {
+        let mut adapter = software::Instance::create("allocator", 1).enumerate_adapters().remove(0);
+        let memory_properties = adapter.physical_device.memory_properties();
+        let (device, queue_group) = adapter.open_with(1, |_| true).unwrap();
+        (device, queue_group, memory_properties)
+    }
+
+    #[test]
+    fn test_defragment() {
+        let (device, mut queue_group, memory_properties) = open();
+        let mut allocator = Allocator::<software::Backend>::new(memory_properties, AllocatorConfig {
+            chunk_size: 256,
+            .. AllocatorConfig::default()
+        });
+        let usage = buffer::Usage::TRANSFER_SRC | buffer::Usage::TRANSFER_DST;
+        let create = |allocator: &mut Allocator<software::Backend>| allocator
+            .create_buffer(&device, 64, usage, Properties::DEVICE_LOCAL, Strategy::Chunked)
+            .unwrap();
+
+        // Fill the first block and put one buffer into a second block.
+        let ids = (0 .. 4).map(|_| create(&mut allocator)).collect::<Vec<_>>();
+        let sparse = create(&mut allocator);
+        assert_eq!(allocator.heap_budget(0).blocks, 2);
+        allocator.destroy_buffer(&device, ids[0]);
+        allocator.destroy_buffer(&device, ids[2]);
+
+        let data = (0 .. 64).collect::<Vec<u8>>();
+        let access = |allocator: &Allocator<software::Backend>, id: BufferId| {
+            let allocation = allocator.buffer_allocation(id);
+            let range = allocation.offset() .. allocation.offset() + allocation.size();
+            let ptr = device.map_memory(allocator.memory(allocation), range).unwrap();
+            unsafe { ::std::slice::from_raw_parts_mut(ptr, allocation.size() as usize) }
+        };
+        access(&allocator, sparse).copy_from_slice(&data);
+
+        // Both blocks are half used, the sparser one is emptied into the other.
+        let mut pool = device.create_command_pool_typed(&queue_group, CommandPoolCreateFlags::empty(), 1);
+        let submit = {
+            let mut cmd_buffer = pool.acquire_command_buffer::<OneShot>(false);
+            let moved = allocator.defragment(&device, &mut cmd_buffer);
+            assert_eq!(moved, vec![sparse]);
+            cmd_buffer.finish()
+        };
+        assert_eq!(allocator.buffer_allocation(sparse).offset(), 0);
+        queue_group.queues[0].submit(Submission::new().submit(Some(submit)), None);
+        assert_eq!(access(&allocator, sparse), &data[..]);
+
+        allocator.cleanup(&device);
+        let budget = allocator.heap_budget(0);
+        assert_eq!((budget.blocks, budget.allocated, budget.used), (1, 256, 192));
+        allocator.destroy(&device);
+        device.destroy_command_pool(pool.into_raw());
+    }
+}
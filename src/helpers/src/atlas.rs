@@ -0,0 +1,469 @@
+//! Partial updates of texture atlases.
+//!
+//! `AtlasUploader` owns a set of 2D atlas images, e.g. the glyph caches of a text
+//! renderer, and uploads small rectangles into them. The texel data is written into
+//! persistently mapped staging chunks, which are recycled once the frame which used
+//! them has completed. All the copies of a frame are recorded into the application's
+//! command buffer at once, with a single barrier before and after the copies.
+//!
+//! ```ignore
+//! let atlas = uploader.add_atlas(&device, 1024, 1024, Format::R8Unorm);
+//! // write `uploader.view(atlas)` into a descriptor set
+//! loop {
+//!     // wait for the fence of the frame submitted `frames_in_flight` frames ago
+//!     uploader.begin_frame(&device);
+//!     for glyph in newly_rasterized {
+//!         uploader.upload(&device, atlas, glyph.rect, &glyph.coverage);
+//!     }
+//!     uploader.record(&device, &mut cmd_buffer);
+//!     // render the frame sampling the atlas in fragment shaders
+//! }
+//! ```
+
+use std::collections::VecDeque;
+use std::{cmp, fmt, ptr};
+
+use hal::adapter::{MemoryProperties, PhysicalDevice};
+use hal::command::{BufferImageCopy, CommandBuffer, Level, Shot};
+use hal::device::Device;
+use hal::format::{Aspects, Format, Swizzle};
+use hal::memory::{Barrier, Dependencies, Properties};
+use hal::pso::{PipelineStage, Rect};
+use hal::queue::{Supports, Transfer};
+use hal::{buffer, image, Backend};
+use streaming::staging_layout;
+
+/// Identifier of an atlas owned by an `AtlasUploader`.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub struct AtlasId(usize);
+
+struct Atlas<B: Backend> {
+    image: B::Image,
+    memory: B::Memory,
+    view: B::ImageView,
+    width: u32,
+    height: u32,
+    format: Format,
+    // Whether the image was transitioned out of the undefined layout already.
+    initialized: bool,
+}
+
+impl<B: Backend> Atlas<B> {
+    fn destroy(self, device: &B::Device) {
+        device.destroy_image_view(self.view);
+        device.destroy_image(self.image);
+        device.free_memory(self.memory);
+    }
+}
+
+/// Persistently mapped staging buffer.
+struct Chunk<B: Backend> {
+    buffer: B::Buffer,
+    memory: B::Memory,
+    mapping: *mut u8,
+    coherent: bool,
+    size: u64,
+}
+
+impl<B: Backend> Chunk<B> {
+    fn destroy(self, device: &B::Device) {
+        device.unmap_memory(&self.memory);
+        device.destroy_buffer(self.buffer);
+        device.free_memory(self.memory);
+    }
+}
+
+/// Copy of a staged region, recorded by the next `record`.
+struct StagedCopy {
+    atlas: AtlasId,
+    // Index into the chunks of the current frame.
+    chunk: usize,
+    region: BufferImageCopy,
+}
+
+/// Uploads rectangles of texels into atlas images, batched per frame.
+pub struct AtlasUploader<B: Backend> {
+    memory_properties: MemoryProperties,
+    copy_offset_alignment: u64,
+    copy_pitch_alignment: u64,
+    atom_size: u64,
+    chunk_size: u64,
+    atlases: Vec<Option<Atlas<B>>>,
+    free_ids: Vec<usize>,
+    // Atlases removed on the given frame, destroyed after `frames_in_flight` frames.
+    retired: VecDeque<(u64, Atlas<B>)>,
+    free_chunks: Vec<Chunk<B>>,
+    // Chunks written by each frame in flight, the last one of the current frame is
+    // filled up to `offset`.
+    frames: Vec<Vec<Chunk<B>>>,
+    offset: u64,
+    copies: Vec<StagedCopy>,
+    frame: u64,
+}
+
+impl<B: Backend> fmt::Debug for AtlasUploader<B> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("AtlasUploader")
+            .field("atlases", &(self.atlases.len() - self.free_ids.len()))
+            .field("chunk_size", &self.chunk_size)
+            .field("staging_size", &self.staging_size())
+            .field("copies", &self.copies.len())
+            .field("frame", &self.frame)
+            .finish()
+    }
+}
+
+impl<B: Backend> AtlasUploader<B> {
+    /// Create an uploader staging the texels in chunks of at least `chunk_size` bytes.
+    ///
+    /// The chunks written in a frame are only reused `frames_in_flight` frames later, the
+    /// application has to wait for the command buffers of that frame to complete first.
+    pub fn new(
+        physical_device: &B::PhysicalDevice,
+        frames_in_flight: usize,
+        chunk_size: u64,
+    ) -> Self {
+        assert!(frames_in_flight > 0 && chunk_size > 0);
+        let limits = physical_device.limits();
+
+        AtlasUploader {
+            memory_properties: physical_device.memory_properties(),
+            copy_offset_alignment: limits.min_buffer_copy_offset_alignment.max(1),
+            copy_pitch_alignment: limits.min_buffer_copy_pitch_alignment.max(1),
+            atom_size: (limits.non_coherent_atom_size as u64).max(1),
+            chunk_size,
+            atlases: Vec::new(),
+            free_ids: Vec::new(),
+            retired: VecDeque::new(),
+            free_chunks: Vec::new(),
+            frames: (0 .. frames_in_flight).map(|_| Vec::new()).collect(),
+            offset: 0,
+            copies: Vec::new(),
+            frame: 0,
+        }
+    }
+
+    /// Returns the total size of the staging chunks in bytes, free or in use.
+    pub fn staging_size(&self) -> u64 {
+        self.free_chunks
+            .iter()
+            .chain(self.frames.iter().flatten())
+            .map(|chunk| chunk.size)
+            .sum()
+    }
+
+    fn current_chunks(&mut self) -> &mut Vec<Chunk<B>> {
+        let index = (self.frame % self.frames.len() as u64) as usize;
+        &mut self.frames[index]
+    }
+
+    /// Create an atlas image of the given size, sampled by shaders.
+    ///
+    /// The content of the atlas is undefined until uploaded, the atlas must not be
+    /// sampled before the commands of the first `record` uploading into it.
+    pub fn add_atlas(&mut self, device: &B::Device, width: u32, height: u32, format: Format) -> AtlasId {
+        let range = image::SubresourceRange {
+            aspects: Aspects::COLOR,
+            levels: 0 .. 1,
+            layers: 0 .. 1,
+        };
+        let unbound = device
+            .create_image(
+                image::Kind::D2(width, height, 1, 1),
+                1,
+                format,
+                image::Tiling::Optimal,
+                image::Usage::TRANSFER_DST | image::Usage::SAMPLED,
+                image::StorageFlags::empty(),
+            )
+            .expect("Unable to create an atlas image");
+        let requirements = device.get_image_requirements(&unbound);
+        let memory_type = self.memory_properties.memory_types
+            .iter()
+            .enumerate()
+            .position(|(id, ty)| {
+                requirements.type_mask & (1 << id) != 0 && ty.properties.contains(Properties::DEVICE_LOCAL)
+            })
+            .expect("No suitable memory type");
+        let memory = device
+            .allocate_memory(memory_type.into(), requirements.size)
+            .expect("Unable to allocate an atlas image");
+        let image = device.bind_image_memory(&memory, 0, unbound).unwrap();
+        let view = device
+            .create_image_view(&image, image::ViewKind::D2, format, Swizzle::NO, range)
+            .unwrap();
+
+        let atlas = Atlas {
+            image,
+            memory,
+            view,
+            width,
+            height,
+            format,
+            initialized: false,
+        };
+        match self.free_ids.pop() {
+            Some(index) => {
+                self.atlases[index] = Some(atlas);
+                AtlasId(index)
+            }
+            None => {
+                self.atlases.push(Some(atlas));
+                AtlasId(self.atlases.len() - 1)
+            }
+        }
+    }
+
+    /// Remove an atlas. Its image is destroyed once no frame uses it anymore.
+    ///
+    /// Uploads into the atlas which weren't recorded yet are dropped.
+    pub fn remove_atlas(&mut self, id: AtlasId) {
+        let atlas = self.atlases[id.0].take().expect("Atlas was already removed");
+        self.retired.push_back((self.frame, atlas));
+        self.copies.retain(|copy| copy.atlas != id);
+        self.free_ids.push(id.0);
+    }
+
+    /// Returns the image of an atlas.
+    pub fn image(&self, id: AtlasId) -> &B::Image {
+        &self.atlases[id.0].as_ref().expect("Atlas was removed").image
+    }
+
+    /// Returns the view of an atlas, for writing it into descriptor sets.
+    pub fn view(&self, id: AtlasId) -> &B::ImageView {
+        &self.atlases[id.0].as_ref().expect("Atlas was removed").view
+    }
+
+    /// Start staging the uploads of the next frame, recycling the chunks written
+    /// `frames_in_flight` frames ago and destroying the atlases removed back then.
+    pub fn begin_frame(&mut self, device: &B::Device) {
+        assert!(self.copies.is_empty(), "Uploads of the previous frame weren't recorded");
+        self.frame += 1;
+        self.offset = 0;
+        let chunks = self.current_chunks().drain(..).collect::<Vec<_>>();
+        self.free_chunks.extend(chunks);
+
+        let frame = self.frame;
+        let frames_in_flight = self.frames.len() as u64;
+        while self.retired.front().map_or(false, |&(retired, _)| retired + frames_in_flight <= frame) {
+            self.retired.pop_front().unwrap().1.destroy(device);
+        }
+    }
+
+    // Reserve `size` bytes in the chunks of the current frame, returns the chunk
+    // index and the offset.
+    fn allocate(&mut self, device: &B::Device, size: u64) -> (usize, u64) {
+        let alignment = self.copy_offset_alignment;
+        let offset = (self.offset + alignment - 1) / alignment * alignment;
+        let fits = self.current_chunks().last().map_or(false, |chunk| offset + size <= chunk.size);
+        if fits {
+            self.offset = offset + size;
+            return (self.current_chunks().len() - 1, offset);
+        }
+
+        let chunk = match self.free_chunks.iter().position(|chunk| chunk.size >= size) {
+            Some(index) => self.free_chunks.swap_remove(index),
+            None => self.create_chunk(device, cmp::max(size, self.chunk_size)),
+        };
+        let chunks = self.current_chunks();
+        chunks.push(chunk);
+        let index = chunks.len() - 1;
+        self.offset = size;
+        (index, 0)
+    }
+
+    fn create_chunk(&self, device: &B::Device, size: u64) -> Chunk<B> {
+        let buffer = device
+            .create_buffer(size, buffer::Usage::TRANSFER_SRC)
+            .expect("Unable to create a staging chunk");
+        let requirements = device.get_buffer_requirements(&buffer);
+        let memory_type = self.memory_properties
+            .upload_type(requirements.type_mask)
+            .expect("No host visible memory type");
+        let coherent = self.memory_properties.memory_types[memory_type.0]
+            .properties
+            .contains(Properties::COHERENT);
+        let memory = device
+            .allocate_memory(memory_type, requirements.size)
+            .expect("Unable to allocate a staging chunk");
+        let buffer = device.bind_buffer_memory(&memory, 0, buffer).unwrap();
+        let mapping = device
+            .map_memory(&memory, 0 .. size)
+            .expect("Unable to map a staging chunk");
+
+        Chunk {
+            buffer,
+            memory,
+            mapping,
+            coherent,
+            size,
+        }
+    }
+
+    /// Stage the texels of a rectangle of an atlas, rows tightly packed.
+    ///
+    /// The copy into the atlas is recorded by the next `record`.
+    pub fn upload(&mut self, device: &B::Device, id: AtlasId, rect: Rect, data: &[u8]) {
+        assert!(rect.x >= 0 && rect.y >= 0 && rect.w > 0 && rect.h > 0);
+        let (width, height) = (rect.w as u32, rect.h as u32);
+        let format = {
+            let atlas = self.atlases[id.0].as_ref().expect("Atlas was removed");
+            assert!(rect.x as u32 + width <= atlas.width && rect.y as u32 + height <= atlas.height);
+            atlas.format
+        };
+
+        let (pitch, buffer_width, rows) = staging_layout(format, width, height, self.copy_pitch_alignment);
+        let row_size = data.len() / rows as usize;
+        assert!(
+            row_size > 0 && row_size as u64 <= pitch && row_size * rows as usize == data.len(),
+            "Texel data doesn't match the rectangle"
+        );
+        let (chunk, offset) = self.allocate(device, pitch * rows as u64);
+
+        let mapping = self.current_chunks()[chunk].mapping;
+        for (row, texels) in data.chunks(row_size).enumerate() {
+            unsafe {
+                ptr::copy_nonoverlapping(
+                    texels.as_ptr(),
+                    mapping.offset((offset + row as u64 * pitch) as isize),
+                    row_size,
+                );
+            }
+        }
+
+        self.copies.push(StagedCopy {
+            atlas: id,
+            chunk,
+            region: BufferImageCopy {
+                buffer_offset: offset,
+                buffer_width,
+                buffer_height: height,
+                image_layers: image::SubresourceLayers {
+                    aspects: Aspects::COLOR,
+                    level: 0,
+                    layers: 0 .. 1,
+                },
+                image_offset: image::Offset { x: rect.x as i32, y: rect.y as i32, z: 0 },
+                image_extent: image::Extent { width, height, depth: 1 },
+            },
+        });
+    }
+
+    /// Record the copies of the uploads staged since `begin_frame` into a command buffer.
+    ///
+    /// The atlases are transitioned to `ShaderReadOnlyOptimal`, the copies complete
+    /// before the following fragment shaders sample them.
+    pub fn record<C, S, L>(&mut self, device: &B::Device, cmd_buffer: &mut CommandBuffer<B, C, S, L>)
+    where
+        C: Supports<Transfer>,
+        S: Shot,
+        L: Level,
+    {
+        if self.copies.is_empty() {
+            return;
+        }
+        let mut copies = self.copies.drain(..).collect::<Vec<_>>();
+        copies.sort_by_key(|copy| (copy.atlas.0, copy.chunk));
+
+        let index = (self.frame % self.frames.len() as u64) as usize;
+        let chunks = &self.frames[index];
+        let last = chunks.len() - 1;
+        let atom_size = self.atom_size;
+        let ranges = chunks
+            .iter()
+            .enumerate()
+            .filter(|&(_, chunk)| !chunk.coherent)
+            .map(|(i, chunk)| {
+                let end = if i == last { self.offset } else { chunk.size };
+                (&chunk.memory, 0 .. cmp::min((end + atom_size - 1) / atom_size * atom_size, chunk.size))
+            })
+            .collect::<Vec<_>>();
+        if !ranges.is_empty() {
+            device.flush_mapped_memory_ranges(ranges);
+        }
+
+        let range = image::SubresourceRange {
+            aspects: Aspects::COLOR,
+            levels: 0 .. 1,
+            layers: 0 .. 1,
+        };
+        let mut atlas_ids = copies.iter().map(|copy| copy.atlas.0).collect::<Vec<_>>();
+        atlas_ids.dedup();
+
+        {
+            let atlases = &self.atlases;
+            let buffer_barriers = chunks.iter().map(|chunk| Barrier::Buffer {
+                states: buffer::Access::HOST_WRITE .. buffer::Access::TRANSFER_READ,
+                target: &chunk.buffer,
+                families: None,
+            });
+            let image_barriers = atlas_ids.iter().map(|&id| {
+                let atlas = atlases[id].as_ref().unwrap();
+                let old = if atlas.initialized {
+                    (image::Access::SHADER_READ, image::Layout::ShaderReadOnlyOptimal)
+                } else {
+                    (image::Access::empty(), image::Layout::Undefined)
+                };
+                Barrier::Image {
+                    states: old .. (image::Access::TRANSFER_WRITE, image::Layout::TransferDstOptimal),
+                    target: &atlas.image,
+                    range: range.clone(),
+                    families: None,
+                }
+            });
+            cmd_buffer.pipeline_barrier(
+                PipelineStage::HOST | PipelineStage::FRAGMENT_SHADER .. PipelineStage::TRANSFER,
+                Dependencies::empty(),
+                buffer_barriers.chain(image_barriers),
+            );
+
+            // Copies from the same chunk into the same atlas are issued together.
+            let mut start = 0;
+            while start < copies.len() {
+                let (atlas, chunk) = (copies[start].atlas, copies[start].chunk);
+                let count = copies[start ..]
+                    .iter()
+                    .take_while(|copy| copy.atlas == atlas && copy.chunk == chunk)
+                    .count();
+                cmd_buffer.copy_buffer_to_image(
+                    &chunks[chunk].buffer,
+                    &atlases[atlas.0].as_ref().unwrap().image,
+                    image::Layout::TransferDstOptimal,
+                    copies[start .. start + count].iter().map(|copy| &copy.region),
+                );
+                start += count;
+            }
+
+            cmd_buffer.pipeline_barrier(
+                PipelineStage::TRANSFER .. PipelineStage::FRAGMENT_SHADER,
+                Dependencies::empty(),
+                atlas_ids.iter().map(|&id| Barrier::Image {
+                    states: (image::Access::TRANSFER_WRITE, image::Layout::TransferDstOptimal) ..
+                        (image::Access::SHADER_READ, image::Layout::ShaderReadOnlyOptimal),
+                    target: &atlases[id].as_ref().unwrap().image,
+                    range: range.clone(),
+                    families: None,
+                }),
+            );
+        }
+
+        for id in atlas_ids {
+            self.atlases[id].as_mut().unwrap().initialized = true;
+        }
+    }
+
+    /// Destroy the atlases and staging chunks.
+    ///
+    /// The application has to wait for all the frames using them to complete first.
+    pub fn destroy(self, device: &B::Device) {
+        for (_, atlas) in self.retired {
+            atlas.destroy(device);
+        }
+        for atlas in self.atlases.into_iter().flatten() {
+            atlas.destroy(device);
+        }
+        for chunk in self.free_chunks.into_iter().chain(self.frames.into_iter().flatten()) {
+            chunk.destroy(device);
+        }
+    }
+}
@@ -0,0 +1,303 @@
+//! Draw coalescing for workloads with many small, scissored draws.
+
+use std::fmt;
+use std::ops::Range;
+
+use hal::command::{DescriptorSetOffset, RenderSubpassCommon};
+use hal::pso::Rect;
+use hal::{Backend, IndexCount, InstanceCount, Primitive, VertexCount, VertexOffset};
+
+/// Draw recorded by a `DrawCoalescer` but not issued yet.
+#[derive(Clone, Debug, PartialEq)]
+enum PendingDraw {
+    Vertices {
+        vertices: Range<VertexCount>,
+        instances: Range<InstanceCount>,
+    },
+    Indexed {
+        indices: Range<IndexCount>,
+        base_vertex: VertexOffset,
+        instances: Range<InstanceCount>,
+    },
+}
+
+// Number of vertices of each primitive of a list topology, `None` for strips, whose
+// primitives share vertices with the previous ones.
+fn list_primitive_size(primitive: Primitive) -> Option<u32> {
+    match primitive {
+        Primitive::PointList => Some(1),
+        Primitive::LineList => Some(2),
+        Primitive::TriangleList => Some(3),
+        Primitive::LineListAdjacency => Some(4),
+        Primitive::TriangleListAdjacency => Some(6),
+        Primitive::PatchList(size) if size > 0 => Some(size as u32),
+        Primitive::PatchList(_) |
+        Primitive::LineStrip |
+        Primitive::TriangleStrip |
+        Primitive::LineStripAdjacency |
+        Primitive::TriangleStripAdjacency => None,
+    }
+}
+
+impl PendingDraw {
+    // Extend the draw by `next` if it continues the vertex or index range. Instanced
+    // draws aren't merged, it would change the order of the primitives.
+    //
+    // Only draws of list topologies with `primitive_size` vertices per primitive are
+    // merged, and only if the draw doesn't end with an incomplete primitive, which
+    // would otherwise take the first vertices of `next`.
+    fn merge(&mut self, next: &PendingDraw, primitive_size: u32) -> bool {
+        match (self, next) {
+            (
+                &mut PendingDraw::Vertices { ref mut vertices, ref instances },
+                &PendingDraw::Vertices { vertices: ref next_vertices, instances: ref next_instances },
+            ) => {
+                if vertices.end != next_vertices.start || (vertices.end - vertices.start) % primitive_size != 0 ||
+                    instances.end - instances.start != 1 || instances != next_instances
+                {
+                    return false;
+                }
+                vertices.end = next_vertices.end;
+                true
+            }
+            (
+                &mut PendingDraw::Indexed { ref mut indices, base_vertex, ref instances },
+                &PendingDraw::Indexed { indices: ref next_indices, base_vertex: next_base_vertex, instances: ref next_instances },
+            ) => {
+                if indices.end != next_indices.start || (indices.end - indices.start) % primitive_size != 0 ||
+                    base_vertex != next_base_vertex || instances.end - instances.start != 1 || instances != next_instances
+                {
+                    return false;
+                }
+                indices.end = next_indices.end;
+                true
+            }
+            _ => false,
+        }
+    }
+}
+
+/// Statistics of a `DrawCoalescer`, for measuring its benefit on a workload.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct CoalescingStatistics {
+    /// Number of draws recorded.
+    pub draws: u32,
+    /// Number of draws issued to the command buffer.
+    pub issued_draws: u32,
+    /// Number of pipeline, descriptor set and scissor changes skipped because
+    /// they matched the current state.
+    pub skipped_state_changes: u32,
+}
+
+/// Fast path for recording many draws with few distinct states, e.g. UI rendering
+/// with thousands of scissored widgets.
+///
+/// State changes which match the current state are dropped, and consecutive draws
+/// with the same state and contiguous vertex or index ranges are issued as a single
+/// draw. Draws are only merged for pipelines with a list topology, as strips would
+/// connect the primitives of both draws, and primitive restart only applies to strips.
+/// Pipelines and descriptor sets are compared by address, so they must not be
+/// destroyed and recreated while recording.
+///
+/// Only the state set through the coalescer is tracked, the encoder must not be used
+/// directly until the coalescer is finished.
+pub struct DrawCoalescer<'e, 'a: 'e, B: Backend> {
+    encoder: &'e mut RenderSubpassCommon<'a, B>,
+    pipeline: Option<*const B::GraphicsPipeline>,
+    // Vertices per primitive of the bound pipeline, `None` if draws can't be merged.
+    primitive_size: Option<u32>,
+    // Bound descriptor set and dynamic offsets of each set index.
+    descriptor_sets: Vec<Option<(*const B::DescriptorSet, Vec<DescriptorSetOffset>)>>,
+    scissor: Option<Rect>,
+    pending: Option<PendingDraw>,
+    statistics: CoalescingStatistics,
+}
+
+impl<'e, 'a: 'e, B: Backend> fmt::Debug for DrawCoalescer<'e, 'a, B> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("DrawCoalescer")
+            .field("scissor", &self.scissor)
+            .field("pending", &self.pending)
+            .field("statistics", &self.statistics)
+            .finish()
+    }
+}
+
+impl<'e, 'a: 'e, B: Backend> DrawCoalescer<'e, 'a, B> {
+    /// Start coalescing the draws recorded into an encoder, with an unknown current state.
+    pub fn new(encoder: &'e mut RenderSubpassCommon<'a, B>) -> Self {
+        DrawCoalescer {
+            encoder,
+            pipeline: None,
+            primitive_size: None,
+            descriptor_sets: Vec::new(),
+            scissor: None,
+            pending: None,
+            statistics: CoalescingStatistics::default(),
+        }
+    }
+
+    fn flush(&mut self) {
+        match self.pending.take() {
+            Some(PendingDraw::Vertices { vertices, instances }) => {
+                self.encoder.draw(vertices, instances);
+            }
+            Some(PendingDraw::Indexed { indices, base_vertex, instances }) => {
+                self.encoder.draw_indexed(indices, base_vertex, instances);
+            }
+            None => return,
+        }
+        self.statistics.issued_draws += 1;
+    }
+
+    /// Bind a graphics pipeline created with the `primitive` topology, unless it's bound already.
+    ///
+    /// Binding a different pipeline resets the tracked descriptor sets, as their
+    /// compatibility depends on the pipeline layout.
+    pub fn bind_graphics_pipeline(&mut self, pipeline: &B::GraphicsPipeline, primitive: Primitive) {
+        if self.pipeline == Some(pipeline as *const _) {
+            self.statistics.skipped_state_changes += 1;
+            return;
+        }
+        self.flush();
+        self.encoder.bind_graphics_pipeline(pipeline);
+        self.pipeline = Some(pipeline as *const _);
+        self.primitive_size = list_primitive_size(primitive);
+        self.descriptor_sets.clear();
+    }
+
+    /// Bind a descriptor set at index `set` of the layout, unless it's bound already
+    /// with the same dynamic offsets.
+    pub fn bind_graphics_descriptor_set(
+        &mut self,
+        layout: &B::PipelineLayout,
+        set: usize,
+        descriptor_set: &B::DescriptorSet,
+        offsets: &[DescriptorSetOffset],
+    ) {
+        let bound = self.descriptor_sets.get(set).map_or(false, |bound| match *bound {
+            Some((ptr, ref bound_offsets)) => ptr == descriptor_set as *const _ && &bound_offsets[..] == offsets,
+            None => false,
+        });
+        if bound {
+            self.statistics.skipped_state_changes += 1;
+            return;
+        }
+        self.flush();
+        self.encoder.bind_graphics_descriptor_sets(layout, set, Some(descriptor_set), offsets);
+        if self.descriptor_sets.len() <= set {
+            self.descriptor_sets.resize(set + 1, None);
+        }
+        self.descriptor_sets[set] = Some((descriptor_set as *const _, offsets.to_vec()));
+    }
+
+    /// Set the scissor of the first viewport, unless it's set already.
+    pub fn set_scissor(&mut self, rect: Rect) {
+        if self.scissor == Some(rect) {
+            self.statistics.skipped_state_changes += 1;
+            return;
+        }
+        self.flush();
+        self.encoder.set_scissors(0, &[rect]);
+        self.scissor = Some(rect);
+    }
+
+    fn record(&mut self, draw: PendingDraw) {
+        self.statistics.draws += 1;
+        let merged = match (self.pending.as_mut(), self.primitive_size) {
+            (Some(pending), Some(primitive_size)) => pending.merge(&draw, primitive_size),
+            _ => false,
+        };
+        if !merged {
+            self.flush();
+            self.pending = Some(draw);
+        }
+    }
+
+    /// Record a draw, merged with the previous one if it continues its vertex range
+    /// with the same single instance and the pipeline has a list topology.
+    pub fn draw(&mut self, vertices: Range<VertexCount>, instances: Range<InstanceCount>) {
+        self.record(PendingDraw::Vertices { vertices, instances });
+    }
+
+    /// Record an indexed draw, merged with the previous one if it continues its index range
+    /// with the same base vertex and single instance and the pipeline has a list topology.
+    pub fn draw_indexed(&mut self, indices: Range<IndexCount>, base_vertex: VertexOffset, instances: Range<InstanceCount>) {
+        self.record(PendingDraw::Indexed { indices, base_vertex, instances });
+    }
+
+    /// Access the encoder for commands the coalescer doesn't track, e.g. push constants.
+    ///
+    /// The pending draw is issued first. Pipelines, descriptor sets and scissors must
+    /// not be changed through the encoder.
+    pub fn encoder(&mut self) -> &mut RenderSubpassCommon<'a, B> {
+        self.flush();
+        self.encoder
+    }
+
+    /// Statistics of the draws recorded so far.
+    pub fn statistics(&self) -> CoalescingStatistics {
+        self.statistics
+    }
+
+    /// Issue the pending draw and return the statistics.
+    pub fn finish(mut self) -> CoalescingStatistics {
+        self.flush();
+        self.statistics
+    }
+}
+
+impl<'e, 'a: 'e, B: Backend> Drop for DrawCoalescer<'e, 'a, B> {
+    fn drop(&mut self) {
+        self.flush();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn vertices(vertices: Range<VertexCount>, instances: Range<InstanceCount>) -> PendingDraw {
+        PendingDraw::Vertices { vertices, instances }
+    }
+
+    fn indexed(indices: Range<IndexCount>, base_vertex: VertexOffset) -> PendingDraw {
+        PendingDraw::Indexed { indices, base_vertex, instances: 0 .. 1 }
+    }
+
+    #[test]
+    fn test_merge_contiguous() {
+        let mut draw = vertices(0 .. 6, 0 .. 1);
+        assert!(draw.merge(&vertices(6 .. 9, 0 .. 1), 3));
+        assert_eq!(draw, vertices(0 .. 9, 0 .. 1));
+
+        let mut draw = indexed(0 .. 6, 4);
+        assert!(draw.merge(&indexed(6 .. 12, 4), 3));
+        assert_eq!(draw, indexed(0 .. 12, 4));
+    }
+
+    #[test]
+    fn test_merge_rejected() {
+        // gap in the vertex range
+        assert!(!vertices(0 .. 6, 0 .. 1).merge(&vertices(9 .. 12, 0 .. 1), 3));
+        // instanced
+        assert!(!vertices(0 .. 6, 0 .. 2).merge(&vertices(6 .. 12, 0 .. 2), 3));
+        // different instance
+        assert!(!vertices(0 .. 6, 0 .. 1).merge(&vertices(6 .. 12, 1 .. 2), 3));
+        // different base vertex
+        assert!(!indexed(0 .. 6, 0).merge(&indexed(6 .. 12, 4), 3));
+        // indexed and non-indexed
+        assert!(!indexed(0 .. 6, 0).merge(&vertices(6 .. 12, 0 .. 1), 3));
+        // incomplete last triangle
+        assert!(!vertices(0 .. 4, 0 .. 1).merge(&vertices(4 .. 7, 0 .. 1), 3));
+        assert!(!indexed(0 .. 4, 0).merge(&indexed(4 .. 7, 0), 3));
+    }
+
+    #[test]
+    fn test_list_primitive_size() {
+        assert_eq!(list_primitive_size(Primitive::TriangleList), Some(3));
+        assert_eq!(list_primitive_size(Primitive::PatchList(4)), Some(4));
+        assert_eq!(list_primitive_size(Primitive::TriangleStrip), None);
+        assert_eq!(list_primitive_size(Primitive::LineStripAdjacency), None);
+    }
+}
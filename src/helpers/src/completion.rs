@@ -0,0 +1,228 @@
+//! Callbacks fired when submitted work has completed on the device.
+//!
+//! A `CompletionQueue` associates every submission made through it with an internal
+//! fence, and runs the attached callbacks once that fence signals. This is handy for
+//! deferred destruction and readbacks, where resources can only be released or read
+//! after the GPU is done with them. A channel `Sender` can be moved into the callback
+//! to forward completions to another thread.
+//!
+//! Completion is detected by polling, so `poll` needs to be called regularly,
+//! e.g. once per frame. Threads dedicated to completions block in `wait_any` instead.
+
+use std::collections::VecDeque;
+use std::fmt;
+
+use hal::device::{Device, WaitFor};
+use hal::queue::{CommandQueue, Submission, Supports};
+use hal::Backend;
+
+/// A callback invoked once, after the associated submission has completed.
+type Callback = Box<FnMut() + Send>;
+
+struct Pending<B: Backend> {
+    fence: B::Fence,
+    callback: Callback,
+}
+
+/// Tracks submissions and runs callbacks on their completion.
+pub struct CompletionQueue<B: Backend> {
+    pending: VecDeque<Pending<B>>,
+    free_fences: Vec<B::Fence>,
+}
+
+impl<B: Backend> fmt::Debug for CompletionQueue<B> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("CompletionQueue")
+            .field("pending", &self.pending.len())
+            .field("free_fences", &self.free_fences.len())
+            .finish()
+    }
+}
+
+impl<B: Backend> CompletionQueue<B> {
+    /// Create an empty completion queue.
+    pub fn new() -> Self {
+        CompletionQueue {
+            pending: VecDeque::new(),
+            free_fences: Vec::new(),
+        }
+    }
+
+    /// Submit to the `queue` and run `callback` once the submission has completed.
+    pub fn submit<C, D, F>(
+        &mut self,
+        device: &B::Device,
+        queue: &mut CommandQueue<B, C>,
+        submission: Submission<B, D>,
+        callback: F,
+    ) where
+        C: Supports<D>,
+        F: FnOnce() + Send + 'static,
+    {
+        let fence = match self.free_fences.pop() {
+            Some(fence) => fence,
+            None => device.create_fence(false),
+        };
+        queue.submit(submission, Some(&fence));
+
+        let mut callback = Some(callback);
+        self.pending.push_back(Pending {
+            fence,
+            callback: Box::new(move || {
+                if let Some(callback) = callback.take() {
+                    callback();
+                }
+            }),
+        });
+    }
+
+    /// Run the callbacks of all completed submissions.
+    ///
+    /// Returns the number of callbacks invoked.
+    pub fn poll(&mut self, device: &B::Device) -> usize {
+        let mut count = 0;
+        let mut i = 0;
+        // Submissions to different queues may complete out of order.
+        while i < self.pending.len() {
+            if device.get_fence_status(&self.pending[i].fence) {
+                let mut pending = self.pending.remove(i).unwrap();
+                (pending.callback)();
+                device.reset_fence(&pending.fence);
+                self.free_fences.push(pending.fence);
+                count += 1;
+            } else {
+                i += 1;
+            }
+        }
+        count
+    }
+
+    /// Block until all tracked submissions have completed, or the timeout expires,
+    /// and run the callbacks of the completed ones.
+    ///
+    /// Returns true if no submission is pending anymore.
+    pub fn wait(&mut self, device: &B::Device, timeout_ms: u32) -> bool {
+        if !self.pending.is_empty() {
+            device.wait_for_fences(
+                self.pending.iter().map(|pending| &pending.fence),
+                WaitFor::All,
+                timeout_ms,
+            );
+            self.poll(device);
+        }
+        self.pending.is_empty()
+    }
+
+    /// Block until at least one tracked submission has completed, or the timeout
+    /// expires, and run the callbacks of the completed ones.
+    ///
+    /// Returns the number of callbacks invoked, 0 if nothing is pending.
+    pub fn wait_any(&mut self, device: &B::Device, timeout_ms: u32) -> usize {
+        if self.pending.is_empty() {
+            return 0;
+        }
+        device.wait_for_fences(
+            self.pending.iter().map(|pending| &pending.fence),
+            WaitFor::Any,
+            timeout_ms,
+        );
+        self.poll(device)
+    }
+
+    /// Returns the number of submissions which haven't completed yet.
+    pub fn len(&self) -> usize {
+        self.pending.len()
+    }
+
+    /// Returns true if there are no pending submissions.
+    pub fn is_empty(&self) -> bool {
+        self.pending.is_empty()
+    }
+
+    /// Destroy the completion queue, waiting for the pending submissions
+    /// and running their callbacks first.
+    pub fn destroy(mut self, device: &B::Device) {
+        while !self.wait(device, !0) {}
+        for fence in self.free_fences.drain(..) {
+            device.destroy_fence(fence);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use hal::queue::{General, QueueGroup};
+    use hal::Instance;
+    use software;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::{mpsc, Arc};
+
+    fn open() -> (software::Device, QueueGroup<software::Backend, General>) {
+        let mut adapter = software::Instance::create("completion", 1).enumerate_adapters().remove(0);
+        adapter.open_with(1, |_| true).unwrap()
+    }
+
+    #[test]
+    fn test_poll() {
+        let (device, mut queue_group) = open();
+        let mut completions = CompletionQueue::new();
+        let counter = Arc::new(AtomicUsize::new(0));
+        for _ in 0 .. 3 {
+            let counter = Arc::clone(&counter);
+            completions.submit(&device, &mut queue_group.queues[0], Submission::new(), move || {
+                counter.fetch_add(1, Ordering::SeqCst);
+            });
+        }
+
+        // Callbacks only run when polling.
+        assert_eq!(completions.len(), 3);
+        assert_eq!(counter.load(Ordering::SeqCst), 0);
+        assert_eq!(completions.poll(&device), 3);
+        assert_eq!(counter.load(Ordering::SeqCst), 3);
+        assert!(completions.is_empty());
+        assert_eq!(completions.poll(&device), 0);
+
+        // Fences of completed submissions are reused.
+        completions.submit(&device, &mut queue_group.queues[0], Submission::new(), || {});
+        assert_eq!(completions.free_fences.len(), 2);
+        completions.destroy(&device);
+    }
+
+    #[test]
+    fn test_wait() {
+        let (device, mut queue_group) = open();
+        let mut completions = CompletionQueue::new();
+        assert!(completions.wait(&device, 0));
+        assert_eq!(completions.wait_any(&device, 0), 0);
+
+        let (sender, receiver) = mpsc::channel();
+        for i in 0 .. 2 {
+            let sender = sender.clone();
+            completions.submit(&device, &mut queue_group.queues[0], Submission::new(), move || {
+                sender.send(i).unwrap();
+            });
+        }
+        assert_eq!(completions.wait_any(&device, !0), 2);
+        assert_eq!(receiver.try_iter().collect::<Vec<_>>(), vec![0, 1]);
+
+        completions.submit(&device, &mut queue_group.queues[0], Submission::new(), move || {
+            sender.send(2).unwrap();
+        });
+        assert!(completions.wait(&device, !0));
+        assert_eq!(receiver.try_recv(), Ok(2));
+    }
+
+    #[test]
+    fn test_destroy_runs_callbacks() {
+        let (device, mut queue_group) = open();
+        let mut completions = CompletionQueue::new();
+        let counter = Arc::new(AtomicUsize::new(0));
+        let callback_counter = Arc::clone(&counter);
+        completions.submit(&device, &mut queue_group.queues[0], Submission::new(), move || {
+            callback_counter.fetch_add(1, Ordering::SeqCst);
+        });
+        completions.destroy(&device);
+        assert_eq!(counter.load(Ordering::SeqCst), 1);
+    }
+}
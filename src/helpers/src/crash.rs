@@ -0,0 +1,308 @@
+//! Crash dumps of the GPU work in flight, captured when the device is lost.
+//!
+//! `CrashRecorder` keeps what's needed to understand a device loss after the fact:
+//! breadcrumbs written by the GPU as it executes the command buffers and the debug
+//! labels of the last submissions. On device loss, `capture` reads back the breadcrumbs
+//! into a `CrashDump` along with the error reported by the backend, which can be
+//! written to a file and attached to bug reports.
+//!
+//! ```ignore
+//! let mut recorder = CrashRecorder::new(&device, &memory_properties, 1024, 8);
+//! recorder.breadcrumb(&mut cmd_buffer, "shadow pass");
+//! // record the shadow pass
+//! recorder.breadcrumb(&mut cmd_buffer, "main pass");
+//! // record the main pass
+//! queue.submit(submission, Some(&fence));
+//! recorder.submitted(vec![format!("frame {}", frame)]);
+//!
+//! if let Err(error @ HostExecutionError::DeviceLost) = device.wait_idle() {
+//!     let mut dump = recorder.capture(&device, error, &ErrorContext::default());
+//!     dump.live_resources = validation_device.live_resources();
+//!     dump.write("crash.txt")?;
+//! }
+//! ```
+//!
+//! Each breadcrumb waits for the commands recorded before it to complete, which
+//! serializes the GPU work: breadcrumbs are meant to be recorded while investigating
+//! a crash rather than permanently. They can't be recorded inside render passes.
+
+use std::borrow::Cow;
+use std::collections::VecDeque;
+use std::fs::File;
+use std::io::{self, Write};
+use std::path::Path;
+use std::{fmt, iter, ptr};
+
+use hal::adapter::MemoryProperties;
+use hal::command::{CommandBuffer, Level, Shot};
+use hal::device::Device;
+use hal::error::ErrorContext;
+use hal::memory::{Barrier, Dependencies, Properties};
+use hal::pso::PipelineStage;
+use hal::queue::{Supports, Transfer};
+use hal::validation::ResourceKind;
+use hal::{buffer, Backend};
+
+/// Breadcrumb of a `CrashDump`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Breadcrumb {
+    /// Label given when recording the breadcrumb.
+    pub label: Cow<'static, str>,
+    /// Index of the submission the breadcrumb was recorded for, `None` if not submitted.
+    pub submission: Option<u64>,
+    /// Whether the GPU reached the breadcrumb, after completing the commands recorded before.
+    pub reached: bool,
+}
+
+/// Submission of a `CrashDump`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SubmissionRecord {
+    /// Index of the submission, counted from the creation of the recorder.
+    pub index: u64,
+    /// Debug labels of the submission.
+    pub labels: Vec<String>,
+}
+
+/// State of the GPU work captured on device loss.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct CrashDump {
+    /// Error which revealed the device loss.
+    pub error: String,
+    /// Diagnostic output of the backend collected with the error.
+    pub context: ErrorContext,
+    /// Last submissions, oldest first.
+    pub submissions: Vec<SubmissionRecord>,
+    /// Breadcrumb trail, oldest first.
+    pub breadcrumbs: Vec<Breadcrumb>,
+    /// Number of live resources of each kind, e.g. from `ValidationDevice::live_resources`.
+    pub live_resources: Vec<(ResourceKind, usize)>,
+}
+
+impl CrashDump {
+    /// Last breadcrumb reached by the GPU, the faulting commands are likely recorded after it.
+    pub fn last_reached(&self) -> Option<&Breadcrumb> {
+        self.breadcrumbs.iter().rev().find(|breadcrumb| breadcrumb.reached)
+    }
+
+    /// Write the dump as text into a file, replacing its contents.
+    pub fn write<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        let mut file = File::create(path)?;
+        write!(file, "{}", self)?;
+        file.sync_all()
+    }
+}
+
+impl fmt::Display for CrashDump {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "Device lost: {}", self.error)?;
+
+        if !self.context.is_empty() {
+            writeln!(f, "\nBackend messages:")?;
+            for message in &self.context.messages {
+                writeln!(f, "  {}", message)?;
+            }
+        }
+
+        writeln!(f, "\nSubmissions (oldest first):")?;
+        for submission in &self.submissions {
+            writeln!(f, "  #{}: {}", submission.index, submission.labels.join(", "))?;
+        }
+
+        let last_reached = self.breadcrumbs.iter().rposition(|breadcrumb| breadcrumb.reached);
+        writeln!(f, "\nBreadcrumbs (oldest first):")?;
+        for (i, breadcrumb) in self.breadcrumbs.iter().enumerate() {
+            let submission = breadcrumb.submission.map_or("-".to_string(), |index| format!("#{}", index));
+            writeln!(
+                f,
+                "  [{}] {} {}{}",
+                if breadcrumb.reached { "reached" } else { "pending" },
+                submission,
+                breadcrumb.label,
+                if Some(i) == last_reached { " <- last reached" } else { "" },
+            )?;
+        }
+
+        if !self.live_resources.is_empty() {
+            writeln!(f, "\nLive resources:")?;
+            for &(kind, count) in &self.live_resources {
+                writeln!(f, "  {:?}: {}", kind, count)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Breadcrumb recorded into a command buffer.
+struct Mark {
+    id: u32,
+    label: Cow<'static, str>,
+    submission: Option<u64>,
+}
+
+/// Records breadcrumbs and submissions, for capturing a `CrashDump` on device loss.
+pub struct CrashRecorder<B: Backend> {
+    buffer: B::Buffer,
+    memory: B::Memory,
+    // Persistent mapping of the breadcrumb slots, one `u32` per breadcrumb.
+    mapping: *const u32,
+    coherent: bool,
+    size: u64,
+    capacity: u32,
+    next_id: u32,
+    breadcrumbs: VecDeque<Mark>,
+    max_submissions: usize,
+    submission_count: u64,
+    submissions: VecDeque<SubmissionRecord>,
+}
+
+impl<B: Backend> fmt::Debug for CrashRecorder<B> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("CrashRecorder")
+            .field("capacity", &self.capacity)
+            .field("breadcrumbs", &self.breadcrumbs.len())
+            .field("submissions", &self.submission_count)
+            .finish()
+    }
+}
+
+impl<B: Backend> CrashRecorder<B> {
+    /// Create a recorder keeping the last `max_breadcrumbs` breadcrumbs and the debug
+    /// labels of the last `max_submissions` submissions.
+    pub fn new(
+        device: &B::Device,
+        memory_properties: &MemoryProperties,
+        max_breadcrumbs: u32,
+        max_submissions: usize,
+    ) -> Self {
+        assert!(max_breadcrumbs > 0);
+        let buffer = device
+            .create_buffer(max_breadcrumbs as u64 * 4, buffer::Usage::TRANSFER_DST)
+            .expect("Unable to create the breadcrumb buffer");
+        let requirements = device.get_buffer_requirements(&buffer);
+        let memory_type = memory_properties
+            .upload_type(requirements.type_mask)
+            .expect("No host visible memory type");
+        let coherent = memory_properties.memory_types[memory_type.0]
+            .properties
+            .contains(Properties::COHERENT);
+        let memory = device
+            .allocate_memory(memory_type, requirements.size)
+            .expect("Unable to allocate the breadcrumb buffer");
+        let buffer = device.bind_buffer_memory(&memory, 0, buffer).unwrap();
+        let mapping = device
+            .map_memory(&memory, 0 .. requirements.size)
+            .expect("Unable to map the breadcrumb buffer") as *mut u32;
+
+        // Breadcrumb IDs start at one, the slots are initially unreached.
+        unsafe {
+            ptr::write_bytes(mapping, 0, max_breadcrumbs as usize);
+        }
+        if !coherent {
+            device.flush_mapped_memory_ranges(Some((&memory, 0 .. requirements.size)));
+        }
+
+        CrashRecorder {
+            buffer,
+            memory,
+            mapping,
+            coherent,
+            size: requirements.size,
+            capacity: max_breadcrumbs,
+            next_id: 0,
+            breadcrumbs: VecDeque::new(),
+            max_submissions,
+            submission_count: 0,
+            submissions: VecDeque::new(),
+        }
+    }
+
+    /// Record a breadcrumb, written by the GPU once the commands recorded before completed.
+    pub fn breadcrumb<C, S, L, N>(&mut self, cmd_buffer: &mut CommandBuffer<B, C, S, L>, label: N)
+    where
+        C: Supports<Transfer>,
+        S: Shot,
+        L: Level,
+        N: Into<Cow<'static, str>>,
+    {
+        self.next_id = self.next_id.wrapping_add(1).max(1);
+        let id = self.next_id;
+        let offset = (id % self.capacity) as u64 * 4;
+        cmd_buffer.pipeline_barrier(
+            PipelineStage::BOTTOM_OF_PIPE .. PipelineStage::TRANSFER,
+            Dependencies::empty(),
+            iter::empty::<Barrier<B>>(),
+        );
+        cmd_buffer.fill_buffer(&self.buffer, offset .. offset + 4, id);
+
+        if self.breadcrumbs.len() == self.capacity as usize {
+            self.breadcrumbs.pop_front();
+        }
+        self.breadcrumbs.push_back(Mark {
+            id,
+            label: label.into(),
+            submission: None,
+        });
+    }
+
+    /// Record the submission of the command buffers containing the breadcrumbs recorded
+    /// since the last call, along with its debug labels.
+    pub fn submitted<I>(&mut self, labels: I)
+    where
+        I: IntoIterator,
+        I::Item: Into<String>,
+    {
+        let index = self.submission_count;
+        self.submission_count += 1;
+        for mark in self.breadcrumbs.iter_mut().rev().take_while(|mark| mark.submission.is_none()) {
+            mark.submission = Some(index);
+        }
+
+        if self.max_submissions == 0 {
+            return;
+        }
+        if self.submissions.len() == self.max_submissions {
+            self.submissions.pop_front();
+        }
+        self.submissions.push_back(SubmissionRecord {
+            index,
+            labels: labels.into_iter().map(Into::into).collect(),
+        });
+    }
+
+    /// Capture the breadcrumbs reached by the GPU after a device loss.
+    ///
+    /// `error` is the error revealing the loss, `context` the diagnostic output of the
+    /// backend collected with it. The live resources are left to be filled in by the caller.
+    pub fn capture<E: fmt::Display>(&self, device: &B::Device, error: E, context: &ErrorContext) -> CrashDump {
+        // Mapped memory stays readable after the device loss.
+        if !self.coherent {
+            device.invalidate_mapped_memory_ranges(Some((&self.memory, 0 .. self.size)));
+        }
+        let breadcrumbs = self.breadcrumbs
+            .iter()
+            .map(|mark| {
+                let value = unsafe { ptr::read_volatile(self.mapping.offset((mark.id % self.capacity) as isize)) };
+                Breadcrumb {
+                    label: mark.label.clone(),
+                    submission: mark.submission,
+                    reached: value == mark.id,
+                }
+            })
+            .collect();
+
+        CrashDump {
+            error: error.to_string(),
+            context: context.clone(),
+            submissions: self.submissions.iter().cloned().collect(),
+            breadcrumbs,
+            live_resources: Vec::new(),
+        }
+    }
+
+    /// Destroy the recorder, the command buffers containing its breadcrumbs must have completed.
+    pub fn destroy(self, device: &B::Device) {
+        device.unmap_memory(&self.memory);
+        device.destroy_buffer(self.buffer);
+        device.free_memory(self.memory);
+    }
+}
@@ -0,0 +1,343 @@
+//! Effects grouping shader permutations into lazily compiled pipelines.
+//!
+//! An `Effect` bundles one or more named techniques, each a graphics pipeline
+//! description, with a set of named permutation flags. Every flag toggles a boolean
+//! specialization constant in all shader stages, so the shaders of a technique are
+//! compiled into a separate pipeline for each combination of flags in use.
+//! Pipelines are only created the first time a permutation is bound, optionally
+//! going through a `PipelineCache`. `Effect::warm_up` creates the permutations known
+//! in advance in a single batch instead, e.g. during a loading screen.
+//!
+//! `Material` selects a technique and permutation of an effect together with the
+//! descriptor sets to bind, for tool-style code which draws objects one by one:
+//!
+//! ```ignore
+//! let mut effect = Effect::new("lit")
+//!     .with_flag("NORMAL_MAP", 0)
+//!     .with_flag("SKINNED", 1)
+//!     .with_technique("forward", forward_desc)
+//!     .with_technique("shadow", shadow_desc);
+//!
+//! let material = Material {
+//!     technique: effect.technique("forward").unwrap(),
+//!     permutation: effect.permutation(&["NORMAL_MAP"]).unwrap(),
+//!     descriptor_sets: vec![material_set],
+//! };
+//!
+//! effect.bind(&device, Some(&cache), &mut encoder, &material)?;
+//! encoder.draw(0 .. vertex_count, 0 .. 1);
+//! ```
+
+use std::fmt;
+use std::ops::BitOr;
+
+use fxhash::FxHashMap;
+use hal::command::RenderSubpassCommon;
+use hal::device::Device;
+use hal::pso::{
+    BasePipeline, Constant, CreationError, EntryPoint, GraphicsPipelineDesc,
+    GraphicsShaderSet, Specialization,
+};
+use hal::Backend;
+
+/// Maximum number of permutation flags of an effect.
+pub const MAX_FLAGS: usize = 64;
+
+/// Set of enabled permutation flags, one bit per flag in the order they were
+/// added to the effect.
+#[derive(Clone, Copy, Debug, Default, Eq, Hash, Ord, PartialEq, PartialOrd)]
+#[cfg_attr(feature = "serialize", derive(Serialize, Deserialize))]
+pub struct Permutation(pub u64);
+
+impl Permutation {
+    /// Permutation with all flags disabled.
+    pub const EMPTY: Permutation = Permutation(0);
+
+    /// Check if the flag with the given index is enabled.
+    pub fn contains(&self, flag: usize) -> bool {
+        self.0 & (1 << flag) != 0
+    }
+}
+
+impl BitOr for Permutation {
+    type Output = Permutation;
+    fn bitor(self, other: Permutation) -> Permutation {
+        Permutation(self.0 | other.0)
+    }
+}
+
+/// Index of a technique in an `Effect`.
+#[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub struct TechniqueId(usize);
+
+struct Flag {
+    name: String,
+    constant_id: u32,
+}
+
+struct Technique<'a, B: Backend> {
+    name: String,
+    desc: GraphicsPipelineDesc<'a, B>,
+    pipelines: FxHashMap<Permutation, B::GraphicsPipeline>,
+}
+
+/// Named techniques sharing a set of permutation flags.
+///
+/// Borrows the shader modules, pipeline layouts and render passes of its techniques.
+pub struct Effect<'a, B: Backend> {
+    name: String,
+    flags: Vec<Flag>,
+    techniques: Vec<Technique<'a, B>>,
+}
+
+impl<'a, B: Backend> fmt::Debug for Effect<'a, B> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Effect")
+            .field("name", &self.name)
+            .field("flags", &self.flags.iter().map(|flag| &flag.name).collect::<Vec<_>>())
+            .field("techniques", &self.techniques.iter().map(|t| &t.name).collect::<Vec<_>>())
+            .field("pipelines", &self.pipeline_count())
+            .finish()
+    }
+}
+
+impl<'a, B: Backend> Effect<'a, B> {
+    /// Create a new effect without techniques and flags.
+    pub fn new<S: Into<String>>(name: S) -> Self {
+        Effect {
+            name: name.into(),
+            flags: Vec::new(),
+            techniques: Vec::new(),
+        }
+    }
+
+    /// Add a permutation flag, toggling the boolean specialization
+    /// constant `constant_id` in all shader stages.
+    ///
+    /// Panics if the effect has `MAX_FLAGS` flags already.
+    pub fn with_flag<S: Into<String>>(mut self, name: S, constant_id: u32) -> Self {
+        assert!(self.flags.len() < MAX_FLAGS, "Too many permutation flags");
+        self.flags.push(Flag {
+            name: name.into(),
+            constant_id,
+        });
+        self
+    }
+
+    /// Add a technique, compiled from `desc` with the specialization constants of
+    /// the permutation flags appended to the ones of each shader stage.
+    ///
+    /// The parent pipeline of `desc` is ignored.
+    pub fn with_technique<S: Into<String>>(mut self, name: S, desc: GraphicsPipelineDesc<'a, B>) -> Self {
+        self.techniques.push(Technique {
+            name: name.into(),
+            desc,
+            pipelines: FxHashMap::default(),
+        });
+        self
+    }
+
+    /// Name of the effect.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Look up a technique by name.
+    pub fn technique(&self, name: &str) -> Option<TechniqueId> {
+        self.techniques
+            .iter()
+            .position(|t| t.name == name)
+            .map(TechniqueId)
+    }
+
+    /// Look up a single flag by name.
+    pub fn flag(&self, name: &str) -> Option<Permutation> {
+        self.flags
+            .iter()
+            .position(|flag| flag.name == name)
+            .map(|index| Permutation(1 << index))
+    }
+
+    /// Permutation with the given flags enabled, `None` if any of the names is unknown.
+    pub fn permutation(&self, names: &[&str]) -> Option<Permutation> {
+        names
+            .iter()
+            .fold(Some(Permutation::EMPTY), |perm, name| {
+                perm.and_then(|perm| self.flag(name).map(|flag| perm | flag))
+            })
+    }
+
+    /// Pipeline layout of a technique, for binding descriptor sets and push constants.
+    pub fn layout(&self, technique: TechniqueId) -> &'a B::PipelineLayout {
+        self.techniques[technique.0].desc.layout
+    }
+
+    /// Total number of pipelines created so far.
+    pub fn pipeline_count(&self) -> usize {
+        self.techniques.iter().map(|t| t.pipelines.len()).sum()
+    }
+
+    fn specialization(&self, constants: &[Specialization], permutation: Permutation) -> Vec<Specialization> {
+        assert_eq!(permutation.0.checked_shr(self.flags.len() as u32).unwrap_or(0), 0, "Unknown permutation flags");
+        let mut specialization = constants.to_vec();
+        for (index, flag) in self.flags.iter().enumerate() {
+            specialization.push(Specialization {
+                id: flag.constant_id,
+                value: Constant::Bool(permutation.contains(index)),
+            });
+        }
+        specialization
+    }
+
+    /// Get the pipeline of a technique for the given permutation, creating it first if needed.
+    pub fn pipeline(
+        &mut self,
+        device: &B::Device,
+        cache: Option<&B::PipelineCache>,
+        technique: TechniqueId,
+        permutation: Permutation,
+    ) -> Result<&B::GraphicsPipeline, CreationError> {
+        if !self.techniques[technique.0].pipelines.contains_key(&permutation) {
+            self.warm_up(device, cache, technique, Some(permutation))
+                .into_iter()
+                .next()
+                .unwrap()?;
+        }
+        Ok(&self.techniques[technique.0].pipelines[&permutation])
+    }
+
+    /// Create the pipelines of a technique for all the given permutations missing
+    /// so far, in a single batch.
+    ///
+    /// Returns a result for each permutation created, skipping the ones which existed already.
+    pub fn warm_up<I>(
+        &mut self,
+        device: &B::Device,
+        cache: Option<&B::PipelineCache>,
+        technique: TechniqueId,
+        permutations: I,
+    ) -> Vec<Result<(), CreationError>>
+    where
+        I: IntoIterator<Item = Permutation>,
+    {
+        let mut missing = Vec::new();
+        for permutation in permutations {
+            if !self.techniques[technique.0].pipelines.contains_key(&permutation) &&
+                !missing.contains(&permutation)
+            {
+                missing.push(permutation);
+            }
+        }
+        if missing.is_empty() {
+            return Vec::new();
+        }
+
+        let results = {
+            let desc = &self.techniques[technique.0].desc;
+            let shaders = &desc.shaders;
+            let stages = [
+                Some(&shaders.vertex),
+                shaders.hull.as_ref(),
+                shaders.domain.as_ref(),
+                shaders.geometry.as_ref(),
+                shaders.fragment.as_ref(),
+            ];
+            // Specialization constants of each stage and permutation, referenced by the descriptions.
+            let constants = missing
+                .iter()
+                .map(|&permutation| {
+                    stages
+                        .iter()
+                        .map(|stage| stage.map_or(Vec::new(), |ep| self.specialization(ep.specialization, permutation)))
+                        .collect::<Vec<_>>()
+                })
+                .collect::<Vec<_>>();
+            let descs = constants
+                .iter()
+                .map(|constants| {
+                    let entry = |stage: Option<&EntryPoint<'a, B>>, index: usize| {
+                        stage.map(|ep| EntryPoint {
+                            entry: ep.entry,
+                            module: ep.module,
+                            specialization: &constants[index],
+                        })
+                    };
+                    GraphicsPipelineDesc {
+                        shaders: GraphicsShaderSet {
+                            vertex: entry(stages[0], 0).unwrap(),
+                            hull: entry(stages[1], 1),
+                            domain: entry(stages[2], 2),
+                            geometry: entry(stages[3], 3),
+                            fragment: entry(stages[4], 4),
+                        },
+                        rasterizer: desc.rasterizer.clone(),
+                        vertex_buffers: desc.vertex_buffers.clone(),
+                        attributes: desc.attributes.clone(),
+                        input_assembler: desc.input_assembler.clone(),
+                        blender: desc.blender.clone(),
+                        depth_stencil: desc.depth_stencil.clone(),
+                        multisampling: desc.multisampling.clone(),
+                        baked_states: desc.baked_states.clone(),
+                        layout: desc.layout,
+                        subpass: desc.subpass.clone(),
+                        flags: desc.flags,
+                        parent: BasePipeline::None,
+                    }
+                })
+                .collect::<Vec<_>>();
+            device.create_graphics_pipelines_with_cache(&descs, cache)
+        };
+
+        let pipelines = &mut self.techniques[technique.0].pipelines;
+        missing
+            .into_iter()
+            .zip(results)
+            .map(|(permutation, result)| {
+                result.map(|pipeline| {
+                    pipelines.insert(permutation, pipeline);
+                })
+            })
+            .collect()
+    }
+
+    /// Bind the pipeline and descriptor sets of a material, creating the pipeline first if needed.
+    pub fn bind(
+        &mut self,
+        device: &B::Device,
+        cache: Option<&B::PipelineCache>,
+        encoder: &mut RenderSubpassCommon<B>,
+        material: &Material<B>,
+    ) -> Result<(), CreationError> {
+        let layout = self.layout(material.technique);
+        encoder.bind_graphics_pipeline(self.pipeline(device, cache, material.technique, material.permutation)?);
+        if !material.descriptor_sets.is_empty() {
+            encoder.bind_graphics_descriptor_sets(layout, 0, &material.descriptor_sets, &[]);
+        }
+        Ok(())
+    }
+
+    /// Destroy the pipelines of all permutations created so far, e.g. after reloading shaders.
+    pub fn clear(&mut self, device: &B::Device) {
+        for technique in &mut self.techniques {
+            for (_, pipeline) in technique.pipelines.drain() {
+                device.destroy_graphics_pipeline(pipeline);
+            }
+        }
+    }
+
+    /// Destroy the pipelines, releasing the borrowed objects.
+    pub fn destroy(mut self, device: &B::Device) {
+        self.clear(device);
+    }
+}
+
+/// Technique and permutation of an effect, with the descriptor sets bound along with them.
+#[derive(Debug)]
+pub struct Material<B: Backend> {
+    /// Technique to draw with.
+    pub technique: TechniqueId,
+    /// Enabled permutation flags.
+    pub permutation: Permutation,
+    /// Descriptor sets bound starting at set 0 of the pipeline layout of the technique.
+    pub descriptor_sets: Vec<B::DescriptorSet>,
+}
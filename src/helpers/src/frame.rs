@@ -0,0 +1,226 @@
+//! Per-frame resource management.
+//!
+//! Rendering with several frames in flight requires keeping the resources used by
+//! a frame alive until the GPU is done with it. `FrameContext` bundles the usual
+//! machinery for this: a fence, a command pool and a deletion queue per frame, and
+//! optionally an `UploadBelt` staging the uploads recorded into the frame.
+//!
+//! ```ignore
+//! let frame = frames.begin_frame(&device);
+//! let submit = {
+//!     let mut cmd_buffer = frame.command_pool().acquire_command_buffer::<OneShot>(false);
+//!     // record commands
+//!     cmd_buffer.finish()
+//! };
+//! frame.defer(move |device| device.destroy_buffer(staging_buffer));
+//! frames.end_frame(&mut queue, Submission::new().submit(Some(submit)));
+//! ```
+
+use std::fmt;
+
+use hal::device::{Device, WaitFor};
+use hal::pool::{CommandPool, CommandPoolCreateFlags};
+use hal::queue::{CommandQueue, QueueGroup, Submission, Supports};
+use hal::Backend;
+use upload::UploadBelt;
+
+/// A deferred operation, run once after the frame has completed.
+type Deferred<B> = Box<FnMut(&<B as Backend>::Device) + Send>;
+
+/// Resources owned by a single frame in flight.
+pub struct Frame<B: Backend, C> {
+    fence: B::Fence,
+    command_pool: CommandPool<B, C>,
+    upload_belt: Option<UploadBelt<B>>,
+    deferred: Vec<Deferred<B>>,
+    submitted: bool,
+}
+
+impl<B: Backend, C> fmt::Debug for Frame<B, C> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Frame")
+            .field("fence", &self.fence)
+            .field("upload_belt", &self.upload_belt)
+            .field("deferred", &self.deferred.len())
+            .field("submitted", &self.submitted)
+            .finish()
+    }
+}
+
+impl<B: Backend, C> Frame<B, C> {
+    /// Get the command pool of the frame, which is reset when the frame begins.
+    pub fn command_pool(&mut self) -> &mut CommandPool<B, C> {
+        &mut self.command_pool
+    }
+
+    /// Get the upload belt of the frame, if the context was created with
+    /// `FrameContext::with_upload_belts`.
+    ///
+    /// Uploads are written into the command buffers of the frame, the staging chunks
+    /// are recycled when the frame begins again. `UploadBelt::finish` must not be called,
+    /// the frame fence signals the completion of the uploads instead.
+    pub fn upload_belt(&mut self) -> Option<&mut UploadBelt<B>> {
+        self.upload_belt.as_mut()
+    }
+
+    /// Get the fence which is signalled once the frame submission completes.
+    pub fn fence(&self) -> &B::Fence {
+        &self.fence
+    }
+
+    /// Run `fun` once the GPU has finished executing this frame,
+    /// e.g. to destroy a resource which is still in use.
+    pub fn defer<F>(&mut self, fun: F)
+    where
+        F: FnOnce(&B::Device) + Send + 'static,
+    {
+        let mut fun = Some(fun);
+        self.deferred.push(Box::new(move |device| {
+            if let Some(fun) = fun.take() {
+                fun(device);
+            }
+        }));
+    }
+
+    fn cleanup(&mut self, device: &B::Device) {
+        if self.submitted {
+            device.reset_fence(&self.fence);
+            self.submitted = false;
+        }
+        for mut fun in self.deferred.drain(..) {
+            fun(device);
+        }
+        if let Some(ref mut upload_belt) = self.upload_belt {
+            upload_belt.reset(device);
+        }
+        self.command_pool.reset();
+    }
+}
+
+/// A ring of frames, allowing the CPU to record a frame while the GPU
+/// still executes the previous ones.
+#[derive(Debug)]
+pub struct FrameContext<B: Backend, C> {
+    frames: Vec<Frame<B, C>>,
+    current: usize,
+    frame_count: u64,
+}
+
+impl<B: Backend, C> FrameContext<B, C> {
+    /// Create a frame context with `frames_in_flight` frames, allocating
+    /// the command pools from the given queue group.
+    pub fn new(device: &B::Device, group: &QueueGroup<B, C>, frames_in_flight: usize) -> Self {
+        Self::create(device, group, frames_in_flight, || None)
+    }
+
+    /// Create a frame context with an `UploadBelt` per frame, staging the uploads
+    /// in chunks of at least `chunk_size` bytes.
+    pub fn with_upload_belts(
+        device: &B::Device,
+        physical_device: &B::PhysicalDevice,
+        group: &QueueGroup<B, C>,
+        frames_in_flight: usize,
+        chunk_size: u64,
+    ) -> Self {
+        Self::create(device, group, frames_in_flight, || Some(UploadBelt::new(physical_device, chunk_size)))
+    }
+
+    fn create<F>(device: &B::Device, group: &QueueGroup<B, C>, frames_in_flight: usize, upload_belt: F) -> Self
+    where
+        F: Fn() -> Option<UploadBelt<B>>,
+    {
+        assert!(frames_in_flight > 0);
+        let frames = (0 .. frames_in_flight)
+            .map(|_| Frame {
+                fence: device.create_fence(false),
+                command_pool: device.create_command_pool_typed(group, CommandPoolCreateFlags::TRANSIENT, 1),
+                upload_belt: upload_belt(),
+                deferred: Vec::new(),
+                submitted: false,
+            })
+            .collect::<Vec<_>>();
+
+        FrameContext {
+            current: frames.len() - 1,
+            frames,
+            frame_count: 0,
+        }
+    }
+
+    /// Begin the next frame.
+    ///
+    /// Blocks until the GPU has finished the frame previously recorded into
+    /// the same slot, then runs its deferred operations and resets its command pool.
+    pub fn begin_frame(&mut self, device: &B::Device) -> &mut Frame<B, C> {
+        self.current = (self.current + 1) % self.frames.len();
+        self.frame_count += 1;
+
+        let frame = &mut self.frames[self.current];
+        if frame.submitted {
+            device.wait_for_fence(&frame.fence, !0);
+        }
+        frame.cleanup(device);
+        frame
+    }
+
+    /// Get the frame which is currently recorded.
+    pub fn current_frame(&mut self) -> &mut Frame<B, C> {
+        &mut self.frames[self.current]
+    }
+
+    /// End the current frame by submitting its work, signalling the frame fence.
+    pub fn end_frame<Q, D>(&mut self, queue: &mut CommandQueue<B, Q>, submission: Submission<B, D>)
+    where
+        Q: Supports<D>,
+    {
+        let frame = &mut self.frames[self.current];
+        assert!(!frame.submitted, "The current frame has already been submitted");
+        queue.submit(submission, Some(&frame.fence));
+        frame.submitted = true;
+    }
+
+    /// Returns the index of the current frame slot.
+    pub fn frame_index(&self) -> usize {
+        self.current
+    }
+
+    /// Returns the number of frames begun so far.
+    pub fn frame_count(&self) -> u64 {
+        self.frame_count
+    }
+
+    /// Returns the number of frames which may be in flight at the same time.
+    pub fn frames_in_flight(&self) -> usize {
+        self.frames.len()
+    }
+
+    /// Block until all submitted frames have completed and run their deferred operations.
+    pub fn wait_idle(&mut self, device: &B::Device) {
+        {
+            let fences = self.frames
+                .iter()
+                .filter(|frame| frame.submitted)
+                .map(|frame| &frame.fence)
+                .collect::<Vec<_>>();
+            // Waiting for an empty set of fences is invalid.
+            if !fences.is_empty() {
+                device.wait_for_fences(fences, WaitFor::All, !0);
+            }
+        }
+        for frame in &mut self.frames {
+            frame.cleanup(device);
+        }
+    }
+
+    /// Wait for all frames to complete and destroy the frame resources.
+    pub fn destroy(mut self, device: &B::Device) {
+        self.wait_idle(device);
+        for frame in self.frames.drain(..) {
+            device.destroy_fence(frame.fence);
+            device.destroy_command_pool(frame.command_pool.into_raw());
+            if let Some(upload_belt) = frame.upload_belt {
+                upload_belt.destroy(device);
+            }
+        }
+    }
+}
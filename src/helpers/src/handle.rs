@@ -0,0 +1,442 @@
+//! Handle-based resource storage.
+//!
+//! The backend objects are owned values, destroyed explicitly through the `Device`.
+//! Applications storing them in an ECS, or serializing references to them, often
+//! need plain `Copy` identifiers instead. `Storage` keeps the objects of one type and
+//! hands out `Handle`s, 32-bit indices tagged with a generation, so that a handle
+//! outliving its object is detected instead of silently referencing a new one.
+//!
+//! `Resources` groups a storage for each kind of device object:
+//!
+//! ```ignore
+//! let mut resources = Resources::<B>::new();
+//! let buffer = resources.buffers.insert(device.bind_buffer_memory(&memory, 0, unbound)?);
+//! // `buffer` is `Copy`, and can be stored anywhere
+//! cmd_buffer.bind_vertex_buffers(0, Some((&resources.buffers[buffer], 0)));
+//! // ...
+//! resources.destroy_buffer(&device, buffer);
+//! resources.clear(&device);
+//! ```
+
+use std::{fmt, ops};
+use std::hash::{Hash, Hasher};
+use std::marker::PhantomData;
+
+use hal::device::Device;
+use hal::Backend;
+
+const INDEX_BITS: u32 = 24;
+const INDEX_MASK: u32 = (1 << INDEX_BITS) - 1;
+const GENERATION_MASK: u32 = !0 >> INDEX_BITS;
+
+/// Maximum number of objects alive at once in a `Storage`.
+pub const MAX_OBJECTS: usize = 1 << INDEX_BITS;
+
+/// Generational handle to an object of type `T` in a `Storage`.
+///
+/// The lower 24 bits hold the index of the object, the upper 8 bits the
+/// generation of its slot. Generations wrap around, so a stale handle is only
+/// guaranteed to be detected until its slot has been reused 256 times.
+#[cfg_attr(feature = "serialize", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serialize", serde(bound = ""))]
+pub struct Handle<T> {
+    raw: u32,
+    marker: PhantomData<fn() -> T>,
+}
+
+impl<T> Handle<T> {
+    fn new(index: u32, generation: u32) -> Self {
+        debug_assert!(index <= INDEX_MASK);
+        Handle {
+            raw: (generation & GENERATION_MASK) << INDEX_BITS | index,
+            marker: PhantomData,
+        }
+    }
+
+    /// Create a handle from its raw value, as returned by `into_raw`.
+    pub fn from_raw(raw: u32) -> Self {
+        Handle {
+            raw,
+            marker: PhantomData,
+        }
+    }
+
+    /// Return the raw value of the handle.
+    pub fn into_raw(self) -> u32 {
+        self.raw
+    }
+
+    /// Index of the object in its storage.
+    pub fn index(&self) -> usize {
+        (self.raw & INDEX_MASK) as usize
+    }
+
+    fn generation(&self) -> u32 {
+        self.raw >> INDEX_BITS
+    }
+}
+
+// Implemented by hand, deriving would require `T` to implement the traits.
+impl<T> Clone for Handle<T> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<T> Copy for Handle<T> {}
+
+impl<T> PartialEq for Handle<T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.raw == other.raw
+    }
+}
+
+impl<T> Eq for Handle<T> {}
+
+impl<T> Hash for Handle<T> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.raw.hash(state)
+    }
+}
+
+impl<T> fmt::Debug for Handle<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Handle({}v{})", self.index(), self.generation())
+    }
+}
+
+#[derive(Debug)]
+enum Slot<T> {
+    Occupied { generation: u32, value: T },
+    Vacant { generation: u32 },
+}
+
+/// Objects of type `T`, referenced by handles.
+#[derive(Debug)]
+pub struct Storage<T> {
+    slots: Vec<Slot<T>>,
+    free: Vec<u32>,
+}
+
+impl<T> Default for Storage<T> {
+    fn default() -> Self {
+        Storage {
+            slots: Vec::new(),
+            free: Vec::new(),
+        }
+    }
+}
+
+impl<T> Storage<T> {
+    /// Create an empty storage.
+    pub fn new() -> Self {
+        Storage::default()
+    }
+
+    /// Store `value`, returning a handle referencing it.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the storage already holds `MAX_OBJECTS` objects.
+    pub fn insert(&mut self, value: T) -> Handle<T> {
+        if let Some(index) = self.free.pop() {
+            let slot = &mut self.slots[index as usize];
+            let generation = match *slot {
+                Slot::Vacant { generation } => (generation + 1) & GENERATION_MASK,
+                Slot::Occupied { .. } => unreachable!(),
+            };
+            *slot = Slot::Occupied { generation, value };
+            return Handle::new(index, generation);
+        }
+
+        assert!(self.slots.len() < MAX_OBJECTS, "Too many objects in the storage");
+        let index = self.slots.len() as u32;
+        self.slots.push(Slot::Occupied { generation: 0, value });
+        Handle::new(index, 0)
+    }
+
+    /// Remove the object referenced by `handle` from the storage, returning it.
+    ///
+    /// Returns `None` if the handle is stale.
+    pub fn remove(&mut self, handle: Handle<T>) -> Option<T> {
+        match self.slots.get(handle.index()) {
+            Some(&Slot::Occupied { generation, .. }) if generation == handle.generation() => {}
+            _ => return None,
+        }
+
+        let vacant = Slot::Vacant { generation: handle.generation() };
+        self.free.push(handle.index() as u32);
+        match ::std::mem::replace(&mut self.slots[handle.index()], vacant) {
+            Slot::Occupied { value, .. } => Some(value),
+            Slot::Vacant { .. } => unreachable!(),
+        }
+    }
+
+    /// Get the object referenced by `handle`, `None` if the handle is stale.
+    pub fn get(&self, handle: Handle<T>) -> Option<&T> {
+        match self.slots.get(handle.index()) {
+            Some(&Slot::Occupied { generation, ref value }) if generation == handle.generation() => Some(value),
+            _ => None,
+        }
+    }
+
+    /// Get the object referenced by `handle` mutably, `None` if the handle is stale.
+    pub fn get_mut(&mut self, handle: Handle<T>) -> Option<&mut T> {
+        match self.slots.get_mut(handle.index()) {
+            Some(&mut Slot::Occupied { generation, ref mut value }) if generation == handle.generation() => Some(value),
+            _ => None,
+        }
+    }
+
+    /// Check if `handle` references an object of the storage.
+    pub fn contains(&self, handle: Handle<T>) -> bool {
+        self.get(handle).is_some()
+    }
+
+    /// Number of objects in the storage.
+    pub fn len(&self) -> usize {
+        self.slots.len() - self.free.len()
+    }
+
+    /// Check if the storage holds no object.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Iterate over the objects of the storage along with their handles.
+    pub fn iter<'a>(&'a self) -> impl Iterator<Item = (Handle<T>, &'a T)> {
+        self.slots
+            .iter()
+            .enumerate()
+            .filter_map(|(index, slot)| match *slot {
+                Slot::Occupied { generation, ref value } => Some((Handle::new(index as u32, generation), value)),
+                Slot::Vacant { .. } => None,
+            })
+    }
+
+    /// Remove all the objects of the storage, returning them.
+    ///
+    /// Handles to the removed objects become stale.
+    pub fn drain(&mut self) -> Vec<T> {
+        let mut values = Vec::with_capacity(self.len());
+        for (index, slot) in self.slots.iter_mut().enumerate() {
+            let generation = match *slot {
+                Slot::Occupied { generation, .. } => generation,
+                Slot::Vacant { .. } => continue,
+            };
+            if let Slot::Occupied { value, .. } = ::std::mem::replace(slot, Slot::Vacant { generation }) {
+                values.push(value);
+            }
+            self.free.push(index as u32);
+        }
+        values
+    }
+}
+
+impl<T> ops::Index<Handle<T>> for Storage<T> {
+    type Output = T;
+    fn index(&self, handle: Handle<T>) -> &T {
+        self.get(handle).expect("Stale handle")
+    }
+}
+
+impl<T> ops::IndexMut<Handle<T>> for Storage<T> {
+    fn index_mut(&mut self, handle: Handle<T>) -> &mut T {
+        self.get_mut(handle).expect("Stale handle")
+    }
+}
+
+macro_rules! define_resources {
+    ($( $field:ident: $ty:ident, $destroy:ident, $destroy_method:ident; )*) => {
+        /// Storages for the objects created by a `Device`.
+        ///
+        /// The objects aren't destroyed when dropping the storages,
+        /// `clear` has to be called beforehand.
+        pub struct Resources<B: Backend> {
+            $(
+                #[allow(missing_docs)]
+                pub $field: Storage<B::$ty>,
+            )*
+        }
+
+        impl<B: Backend> Default for Resources<B> {
+            fn default() -> Self {
+                Resources {
+                    $( $field: Storage::new(), )*
+                }
+            }
+        }
+
+        impl<B: Backend> fmt::Debug for Resources<B> {
+            fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                f.debug_struct("Resources")
+                    $( .field(stringify!($field), &self.$field.len()) )*
+                    .finish()
+            }
+        }
+
+        impl<B: Backend> Resources<B> {
+            $(
+                /// Remove the object referenced by `handle` and destroy it.
+                /// Stale handles are ignored.
+                pub fn $destroy(&mut self, device: &B::Device, handle: Handle<B::$ty>) {
+                    if let Some(object) = self.$field.remove(handle) {
+                        device.$destroy_method(object);
+                    }
+                }
+            )*
+
+            /// Destroy all the objects of the storages.
+            ///
+            /// Objects referencing others are destroyed first, e.g. image views before images.
+            pub fn clear(&mut self, device: &B::Device) {
+                $(
+                    for object in self.$field.drain() {
+                        device.$destroy_method(object);
+                    }
+                )*
+            }
+        }
+    };
+}
+
+// Ordered so that objects are destroyed before the ones they depend on.
+define_resources! {
+    command_pools: CommandPool, destroy_command_pool, destroy_command_pool;
+    query_pools: QueryPool, destroy_query_pool, destroy_query_pool;
+    framebuffers: Framebuffer, destroy_framebuffer, destroy_framebuffer;
+    graphics_pipelines: GraphicsPipeline, destroy_graphics_pipeline, destroy_graphics_pipeline;
+    compute_pipelines: ComputePipeline, destroy_compute_pipeline, destroy_compute_pipeline;
+    pipeline_layouts: PipelineLayout, destroy_pipeline_layout, destroy_pipeline_layout;
+    descriptor_pools: DescriptorPool, destroy_descriptor_pool, destroy_descriptor_pool;
+    descriptor_set_layouts: DescriptorSetLayout, destroy_descriptor_set_layout, destroy_descriptor_set_layout;
+    render_passes: RenderPass, destroy_render_pass, destroy_render_pass;
+    shader_modules: ShaderModule, destroy_shader_module, destroy_shader_module;
+    image_views: ImageView, destroy_image_view, destroy_image_view;
+    buffer_views: BufferView, destroy_buffer_view, destroy_buffer_view;
+    samplers: Sampler, destroy_sampler, destroy_sampler;
+    images: Image, destroy_image, destroy_image;
+    buffers: Buffer, destroy_buffer, destroy_buffer;
+    memory: Memory, free_memory, free_memory;
+    fences: Fence, destroy_fence, destroy_fence;
+    semaphores: Semaphore, destroy_semaphore, destroy_semaphore;
+}
+
+impl<B: Backend> Resources<B> {
+    /// Create empty storages.
+    pub fn new() -> Self {
+        Resources::default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use hal::pool::CommandPoolCreateFlags;
+    use hal::pso::DescriptorRangeDesc;
+    use hal::query::QueryType;
+    use hal::queue::General;
+    use hal::Instance;
+    use software;
+
+    #[test]
+    fn test_free_list_reuse() {
+        let mut storage = Storage::new();
+        let a = storage.insert('a');
+        let b = storage.insert('b');
+        assert_eq!(storage.remove(a), Some('a'));
+        assert_eq!(storage.len(), 1);
+
+        // The vacant slot is reused with the next generation.
+        let c = storage.insert('c');
+        assert_eq!(c.index(), a.index());
+        assert_ne!(c, a);
+        assert_eq!(storage[c], 'c');
+        assert_eq!(storage[b], 'b');
+
+        let d = storage.insert('d');
+        assert_eq!(d.index(), 2);
+        assert_eq!(storage.len(), 3);
+    }
+
+    #[test]
+    fn test_stale_handles() {
+        let mut storage = Storage::new();
+        let a = storage.insert(1);
+        assert_eq!(storage.remove(a), Some(1));
+        assert_eq!(storage.remove(a), None);
+        assert_eq!(storage.get(a), None);
+        assert!(!storage.contains(a));
+
+        // A handle stays stale once its slot has been reused.
+        let b = storage.insert(2);
+        assert_eq!(storage.get(a), None);
+        assert_eq!(storage.get_mut(a), None);
+        assert_eq!(storage.remove(a), None);
+        assert_eq!(storage[b], 2);
+
+        let c = storage.insert(3);
+        let mut values = storage.drain();
+        values.sort();
+        assert_eq!(values, vec![2, 3]);
+        assert!(storage.is_empty());
+        assert!(!storage.contains(b));
+        assert!(!storage.contains(c));
+        assert_eq!(storage.iter().count(), 0);
+
+        // Drained slots are reused.
+        let d = storage.insert(4);
+        assert!(d.index() < 2);
+        assert_eq!(storage.get(b).or(storage.get(c)), None);
+    }
+
+    #[test]
+    fn test_generation_wrap_around() {
+        let mut storage = Storage::new();
+        let first = storage.insert(0);
+        let mut handle = first;
+        for i in 1 ..= GENERATION_MASK {
+            storage.remove(handle);
+            handle = storage.insert(i);
+            assert_eq!(handle.index(), first.index());
+            assert_eq!(handle.generation(), i);
+            assert!(!storage.contains(first));
+        }
+
+        // After 256 reuses the generation wraps, aliasing the first handle.
+        storage.remove(handle);
+        let wrapped = storage.insert(256);
+        assert_eq!(wrapped.generation(), 0);
+        assert_eq!(wrapped, first);
+        assert_eq!(storage[first], 256);
+    }
+
+    #[test]
+    fn test_raw_round_trip() {
+        let mut storage = Storage::new();
+        storage.insert(());
+        let handle = storage.insert(());
+        assert_eq!(Handle::from_raw(handle.into_raw()), handle);
+    }
+
+    #[test]
+    fn test_clear() {
+        let mut adapter = software::Instance::create("handle", 1).enumerate_adapters().remove(0);
+        let (device, queue_group) = adapter.open_with::<_, General>(1, |_| true).unwrap();
+
+        let mut resources = Resources::<software::Backend>::new();
+        resources.command_pools.insert(device.create_command_pool(queue_group.family(), CommandPoolCreateFlags::empty()));
+        resources.query_pools.insert(device.create_query_pool(QueryType::Occlusion, 4));
+        resources.descriptor_pools.insert(device.create_descriptor_pool(1, Vec::<DescriptorRangeDesc>::new()));
+        let fence = resources.fences.insert(device.create_fence(false));
+        resources.fences.insert(device.create_fence(true));
+
+        resources.destroy_fence(&device, fence);
+        assert_eq!(resources.fences.len(), 1);
+        resources.clear(&device);
+        assert!(resources.command_pools.is_empty());
+        assert!(resources.query_pools.is_empty());
+        assert!(resources.descriptor_pools.is_empty());
+        assert!(resources.fences.is_empty());
+    }
+}
@@ -0,0 +1,538 @@
+//! Renderer for the draw data of Dear ImGui.
+//!
+//! `ImguiRenderer` draws the vertex and index lists produced by ImGui each frame. The
+//! lists are written into a host visible buffer per frame in flight, grown on demand,
+//! and every draw command becomes an indexed draw with its clip rectangle as scissor.
+//! The font atlas is uploaded into a device local image, other images are registered
+//! with `add_texture` and referenced by the returned `TextureId`.
+//!
+//! The types of this module mirror the ones of ImGui (`ImDrawVert`, `ImDrawCmd`,
+//! `ImDrawList` and `ImDrawData`) instead of depending on a particular version of the
+//! bindings; `DrawVert` has the same layout as `ImDrawVert`. Draw commands with user
+//! callbacks aren't supported. The shaders of the `sprite` module match this renderer.
+//!
+//! ```ignore
+//! let mut renderer = ImguiRenderer::new(&device, &physical_device, frames_in_flight, 16);
+//! renderer.create_pipeline(&device, &vs_module, &fs_module, subpass)?;
+//! let font = renderer.set_font_atlas(&device, &mut queue_group, width, height, &pixels);
+//! imgui.fonts().set_texture_id(font.0);
+//!
+//! loop {
+//!     let lists = /* convert the lists of `ui.render()` */;
+//!     renderer.render(&device, &mut encoder, &DrawData {
+//!         lists: &lists,
+//!         display_pos: [0.0, 0.0],
+//!         display_size: [width as f32, height as f32],
+//!         framebuffer_scale: [1.0, 1.0],
+//!     });
+//! }
+//! ```
+
+use std::{fmt, mem, ptr};
+
+use hal::adapter::{MemoryProperties, PhysicalDevice};
+use hal::buffer::{self, IndexBufferView};
+use hal::command::{BufferImageCopy, OneShot, RenderSubpassCommon};
+use hal::device::Device;
+use hal::format::{Aspects, Format, Swizzle};
+use hal::memory::{Barrier, Dependencies, Properties};
+use hal::pass::Subpass;
+use hal::pool::{CommandPool, CommandPoolCreateFlags};
+use hal::pso::{CreationError, Descriptor, DescriptorPool, DescriptorRangeDesc, DescriptorSetWrite, DescriptorType,
+    PipelineStage, Rect, ShaderStageFlags, Viewport};
+use hal::queue::{QueueGroup, Submission, Supports, Transfer};
+use sprite::{self, SpriteVertex};
+use hal::{image, Backend, IndexType};
+
+/// Vertex of a draw list, laid out like `ImDrawVert`.
+pub type DrawVert = SpriteVertex;
+
+/// Index of a draw list, ImGui uses 16-bit indices by default.
+pub type DrawIdx = u16;
+
+/// Identifier of a texture registered to an `ImguiRenderer`, used as `ImTextureID`.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub struct TextureId(pub usize);
+
+/// Command drawing a range of indices of a draw list, like `ImDrawCmd`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct DrawCmd {
+    /// Number of indices, following the ones of the previous commands of the list.
+    pub elem_count: u32,
+    /// Clip rectangle as `[left, top, right, bottom]`, in display coordinates.
+    pub clip_rect: [f32; 4],
+    /// Texture to sample.
+    pub texture_id: TextureId,
+}
+
+/// Vertices, indices and commands of a draw list, like `ImDrawList`.
+#[derive(Clone, Copy, Debug)]
+pub struct DrawList<'a> {
+    ///
+    pub vertices: &'a [DrawVert],
+    ///
+    pub indices: &'a [DrawIdx],
+    ///
+    pub commands: &'a [DrawCmd],
+}
+
+/// Draw lists of a frame, like `ImDrawData`.
+#[derive(Clone, Copy, Debug)]
+pub struct DrawData<'a> {
+    /// Draw lists, rendered back to front.
+    pub lists: &'a [DrawList<'a>],
+    /// Top left corner of the display, in display coordinates.
+    pub display_pos: [f32; 2],
+    /// Size of the display, in display coordinates.
+    pub display_size: [f32; 2],
+    /// Number of framebuffer pixels per display coordinate, e.g. `[2.0, 2.0]` on high DPI displays.
+    pub framebuffer_scale: [f32; 2],
+}
+
+/// Vertices followed by indices of a frame in flight.
+struct FrameBuffer<B: Backend> {
+    buffer: B::Buffer,
+    memory: B::Memory,
+    mapping: *mut u8,
+    coherent: bool,
+    size: u64,
+}
+
+impl<B: Backend> FrameBuffer<B> {
+    fn destroy(self, device: &B::Device) {
+        device.unmap_memory(&self.memory);
+        device.destroy_buffer(self.buffer);
+        device.free_memory(self.memory);
+    }
+}
+
+struct FontAtlas<B: Backend> {
+    image: B::Image,
+    memory: B::Memory,
+    view: B::ImageView,
+    texture: TextureId,
+}
+
+/// Renders the draw lists of Dear ImGui.
+pub struct ImguiRenderer<B: Backend> {
+    memory_properties: MemoryProperties,
+    atom_size: u64,
+    copy_pitch_alignment: u64,
+    frames: Vec<Option<FrameBuffer<B>>>,
+    frame: usize,
+    set_layout: B::DescriptorSetLayout,
+    pipeline_layout: B::PipelineLayout,
+    pipeline: Option<B::GraphicsPipeline>,
+    descriptor_pool: B::DescriptorPool,
+    sampler: B::Sampler,
+    textures: Vec<Option<B::DescriptorSet>>,
+    font: Option<FontAtlas<B>>,
+}
+
+impl<B: Backend> fmt::Debug for ImguiRenderer<B> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("ImguiRenderer")
+            .field("frames_in_flight", &self.frames.len())
+            .field("textures", &self.textures.iter().filter(|t| t.is_some()).count())
+            .field("font", &self.font.as_ref().map(|font| font.texture))
+            .finish()
+    }
+}
+
+impl<B: Backend> ImguiRenderer<B> {
+    /// Create a renderer for `frames_in_flight` frames, able to register up to `max_textures`
+    /// textures including the font atlas.
+    ///
+    /// The buffers of a frame are only overwritten `frames_in_flight` frames later, the
+    /// application has to wait for the command buffers of that frame to complete first.
+    pub fn new(
+        device: &B::Device,
+        physical_device: &B::PhysicalDevice,
+        frames_in_flight: usize,
+        max_textures: usize,
+    ) -> Self {
+        assert!(frames_in_flight > 0 && max_textures > 0);
+        let limits = physical_device.limits();
+        let (set_layout, pipeline_layout) = sprite::create_layouts::<B>(device);
+        let descriptor_pool = device.create_descriptor_pool(
+            max_textures,
+            &[
+                DescriptorRangeDesc { ty: DescriptorType::SampledImage, count: max_textures },
+                DescriptorRangeDesc { ty: DescriptorType::Sampler, count: max_textures },
+            ],
+        );
+
+        ImguiRenderer {
+            memory_properties: physical_device.memory_properties(),
+            atom_size: (limits.non_coherent_atom_size as u64).max(4),
+            copy_pitch_alignment: limits.min_buffer_copy_pitch_alignment.max(1),
+            frames: (0 .. frames_in_flight).map(|_| None).collect(),
+            frame: 0,
+            set_layout,
+            pipeline_layout,
+            pipeline: None,
+            descriptor_pool,
+            sampler: device.create_sampler(image::SamplerInfo::new(image::Filter::Linear, image::WrapMode::Clamp)),
+            textures: Vec::new(),
+            font: None,
+        }
+    }
+
+    /// Create the pipeline for the given subpass, replacing the previous one.
+    ///
+    /// The shaders have to match `sprite::SPRITE_VERTEX_SHADER` and `sprite::SPRITE_FRAGMENT_SHADER`.
+    pub fn create_pipeline(
+        &mut self,
+        device: &B::Device,
+        vertex: &B::ShaderModule,
+        fragment: &B::ShaderModule,
+        subpass: Subpass<B>,
+    ) -> Result<(), CreationError> {
+        let pipeline = sprite::create_pipeline::<B>(device, &self.pipeline_layout, vertex, fragment, subpass)?;
+        if let Some(old) = mem::replace(&mut self.pipeline, Some(pipeline)) {
+            device.destroy_graphics_pipeline(old);
+        }
+        Ok(())
+    }
+
+    /// Register an image view in `ShaderReadOnlyOptimal` layout, sampled with linear filtering.
+    ///
+    /// Panics if `max_textures` textures are registered already.
+    pub fn add_texture(&mut self, device: &B::Device, view: &B::ImageView) -> TextureId {
+        let set = self.descriptor_pool
+            .allocate_set(&self.set_layout)
+            .expect("Too many ImGui textures");
+        device.write_descriptor_sets(vec![
+            DescriptorSetWrite {
+                set: &set,
+                binding: 0,
+                array_offset: 0,
+                descriptors: Some(Descriptor::Image(view, image::Layout::ShaderReadOnlyOptimal)),
+            },
+            DescriptorSetWrite {
+                set: &set,
+                binding: 1,
+                array_offset: 0,
+                descriptors: Some(Descriptor::Sampler(&self.sampler)),
+            },
+        ]);
+
+        match self.textures.iter().position(|texture| texture.is_none()) {
+            Some(index) => {
+                self.textures[index] = Some(set);
+                TextureId(index)
+            }
+            None => {
+                self.textures.push(Some(set));
+                TextureId(self.textures.len() - 1)
+            }
+        }
+    }
+
+    /// Unregister a texture. It may still be used by the frames in flight.
+    pub fn remove_texture(&mut self, texture: TextureId) {
+        let set = self.textures[texture.0].take().expect("Unknown ImGui texture");
+        self.descriptor_pool.free_sets(Some(set));
+    }
+
+    /// Upload the RGBA8 font atlas of ImGui and register it as texture, replacing the previous atlas.
+    ///
+    /// Blocks until the upload completed. The previous atlas is destroyed right away, so the queues
+    /// of `group` have to be the ones rendering the user interface.
+    pub fn set_font_atlas<C: Supports<Transfer>>(
+        &mut self,
+        device: &B::Device,
+        group: &mut QueueGroup<B, C>,
+        width: u32,
+        height: u32,
+        pixels: &[u8],
+    ) -> TextureId {
+        assert_eq!(pixels.len(), (width * height * 4) as usize);
+        let format = Format::Rgba8Unorm;
+        let row_size = width as u64 * 4;
+        let pitch = (row_size + self.copy_pitch_alignment - 1) / self.copy_pitch_alignment * self.copy_pitch_alignment;
+        let staging_size = pitch * height as u64;
+
+        let staging = device
+            .create_buffer(staging_size, buffer::Usage::TRANSFER_SRC)
+            .expect("Unable to create the font staging buffer");
+        let requirements = device.get_buffer_requirements(&staging);
+        let memory_type = self.memory_properties
+            .upload_type(requirements.type_mask)
+            .expect("No host visible memory type");
+        let staging_memory = device
+            .allocate_memory(memory_type, requirements.size)
+            .expect("Unable to allocate the font staging buffer");
+        let staging = device.bind_buffer_memory(&staging_memory, 0, staging).unwrap();
+        {
+            let mut writer = device
+                .acquire_mapping_writer::<u8>(&staging_memory, 0 .. staging_size)
+                .expect("Unable to map the font staging buffer");
+            for (row, data) in pixels.chunks(row_size as usize).enumerate() {
+                let start = row * pitch as usize;
+                writer[start .. start + row_size as usize].copy_from_slice(data);
+            }
+            device.release_mapping_writer(writer);
+        }
+
+        let range = image::SubresourceRange {
+            aspects: Aspects::COLOR,
+            levels: 0 .. 1,
+            layers: 0 .. 1,
+        };
+        let unbound = device
+            .create_image(
+                image::Kind::D2(width, height, 1, 1),
+                1,
+                format,
+                image::Tiling::Optimal,
+                image::Usage::TRANSFER_DST | image::Usage::SAMPLED,
+                image::StorageFlags::empty(),
+            )
+            .expect("Unable to create the font image");
+        let requirements = device.get_image_requirements(&unbound);
+        let memory_type = self.memory_properties.memory_types
+            .iter()
+            .enumerate()
+            .position(|(id, ty)| {
+                requirements.type_mask & (1 << id) != 0 && ty.properties.contains(Properties::DEVICE_LOCAL)
+            })
+            .expect("No suitable memory type");
+        let memory = device
+            .allocate_memory(memory_type.into(), requirements.size)
+            .expect("Unable to allocate the font image");
+        let font_image = device.bind_image_memory(&memory, 0, unbound).unwrap();
+        let view = device
+            .create_image_view(&font_image, image::ViewKind::D2, format, Swizzle::NO, range.clone())
+            .unwrap();
+
+        let mut command_pool = unsafe {
+            // Every queue family supports transfer operations.
+            CommandPool::<B, Transfer>::new(device.create_command_pool(group.family(), CommandPoolCreateFlags::TRANSIENT))
+        };
+        let submit = {
+            let mut cmd_buffer = command_pool.acquire_command_buffer::<OneShot>(false);
+            cmd_buffer.pipeline_barrier(
+                PipelineStage::HOST .. PipelineStage::TRANSFER,
+                Dependencies::empty(),
+                &[
+                    Barrier::Buffer {
+                        states: buffer::Access::HOST_WRITE .. buffer::Access::TRANSFER_READ,
+                        target: &staging,
+                        families: None,
+                    },
+                    Barrier::Image {
+                        states: (image::Access::empty(), image::Layout::Undefined) ..
+                            (image::Access::TRANSFER_WRITE, image::Layout::TransferDstOptimal),
+                        target: &font_image,
+                        range: range.clone(),
+                        families: None,
+                    },
+                ],
+            );
+            cmd_buffer.copy_buffer_to_image(
+                &staging,
+                &font_image,
+                image::Layout::TransferDstOptimal,
+                &[BufferImageCopy {
+                    buffer_offset: 0,
+                    buffer_width: (pitch / 4) as u32,
+                    buffer_height: height,
+                    image_layers: image::SubresourceLayers {
+                        aspects: Aspects::COLOR,
+                        level: 0,
+                        layers: 0 .. 1,
+                    },
+                    image_offset: image::Offset { x: 0, y: 0, z: 0 },
+                    image_extent: image::Extent { width, height, depth: 1 },
+                }],
+            );
+            cmd_buffer.pipeline_barrier(
+                PipelineStage::TRANSFER .. PipelineStage::BOTTOM_OF_PIPE,
+                Dependencies::empty(),
+                &[Barrier::Image {
+                    states: (image::Access::TRANSFER_WRITE, image::Layout::TransferDstOptimal) ..
+                        (image::Access::empty(), image::Layout::ShaderReadOnlyOptimal),
+                    target: &font_image,
+                    range,
+                    families: None,
+                }],
+            );
+            cmd_buffer.finish()
+        };
+        let queue = &mut group.queues[0];
+        queue.submit(Submission::new().submit(Some(submit)), None);
+        queue.wait_idle().expect("Unable to upload the font atlas");
+
+        device.destroy_command_pool(command_pool.into_raw());
+        device.destroy_buffer(staging);
+        device.free_memory(staging_memory);
+
+        if let Some(old) = self.font.take() {
+            self.remove_texture(old.texture);
+            device.destroy_image_view(old.view);
+            device.destroy_image(old.image);
+            device.free_memory(old.memory);
+        }
+        let texture = self.add_texture(device, &view);
+        self.font = Some(FontAtlas {
+            image: font_image,
+            memory,
+            view,
+            texture,
+        });
+        texture
+    }
+
+    /// Upload the draw lists and record their draw calls.
+    ///
+    /// Sets the viewport to cover the display as well as the scissor rectangles.
+    /// Panics if `create_pipeline` wasn't called.
+    pub fn render(&mut self, device: &B::Device, encoder: &mut RenderSubpassCommon<B>, data: &DrawData) {
+        let vertex_count = data.lists.iter().map(|list| list.vertices.len()).sum::<usize>();
+        let index_count = data.lists.iter().map(|list| list.indices.len()).sum::<usize>();
+        if index_count == 0 {
+            return;
+        }
+        let pipeline = self.pipeline.as_ref().expect("ImGui pipeline wasn't created");
+
+        // Indices follow the vertices, aligned for the index buffer offset.
+        let vertex_size = (vertex_count * mem::size_of::<DrawVert>()) as u64;
+        let index_offset = (vertex_size + 3) & !3;
+        let size = index_offset + (index_count * mem::size_of::<DrawIdx>()) as u64;
+
+        // The buffer of this frame was last used `frames_in_flight` frames ago,
+        // so it can be replaced right away if it's too small.
+        let index = self.frame;
+        self.frame = (index + 1) % self.frames.len();
+        let slot = &mut self.frames[index];
+        if slot.as_ref().map_or(true, |frame| frame.size < size) {
+            if let Some(old) = slot.take() {
+                old.destroy(device);
+            }
+            let capacity = size.next_power_of_two();
+            let buffer = device
+                .create_buffer(capacity, buffer::Usage::VERTEX | buffer::Usage::INDEX)
+                .expect("Unable to create the ImGui buffer");
+            let requirements = device.get_buffer_requirements(&buffer);
+            let memory_type = self.memory_properties
+                .upload_type(requirements.type_mask)
+                .expect("No host visible memory type");
+            let memory = device
+                .allocate_memory(memory_type, requirements.size)
+                .expect("Unable to allocate the ImGui buffer");
+            let buffer = device.bind_buffer_memory(&memory, 0, buffer).unwrap();
+            let mapping = device
+                .map_memory(&memory, 0 .. capacity)
+                .expect("Unable to map the ImGui buffer");
+            *slot = Some(FrameBuffer {
+                buffer,
+                memory,
+                mapping,
+                coherent: self.memory_properties.memory_types[memory_type.0].properties.contains(Properties::COHERENT),
+                size: capacity,
+            });
+        }
+        let frame = slot.as_ref().unwrap();
+
+        let (mut vertex_offset, mut index_offset_bytes) = (0, index_offset as usize);
+        for list in data.lists {
+            let vertices = list.vertices.len() * mem::size_of::<DrawVert>();
+            let indices = list.indices.len() * mem::size_of::<DrawIdx>();
+            unsafe {
+                ptr::copy_nonoverlapping(
+                    list.vertices.as_ptr() as *const u8,
+                    frame.mapping.offset(vertex_offset as isize),
+                    vertices,
+                );
+                ptr::copy_nonoverlapping(
+                    list.indices.as_ptr() as *const u8,
+                    frame.mapping.offset(index_offset_bytes as isize),
+                    indices,
+                );
+            }
+            vertex_offset += vertices;
+            index_offset_bytes += indices;
+        }
+        if !frame.coherent {
+            let end = ((size + self.atom_size - 1) / self.atom_size * self.atom_size).min(frame.size);
+            device.flush_mapped_memory_ranges(Some((&frame.memory, 0 .. end)));
+        }
+
+        let scale = data.framebuffer_scale;
+        let framebuffer_size = [data.display_size[0] * scale[0], data.display_size[1] * scale[1]];
+        encoder.bind_graphics_pipeline(pipeline);
+        encoder.set_viewports(0, &[Viewport {
+            rect: Rect {
+                x: 0,
+                y: 0,
+                w: framebuffer_size[0] as i16,
+                h: framebuffer_size[1] as i16,
+            },
+            depth: 0.0 .. 1.0,
+        }]);
+        encoder.push_graphics_constants(
+            &self.pipeline_layout,
+            ShaderStageFlags::VERTEX,
+            0,
+            &sprite::transform(data.display_pos, data.display_size),
+        );
+        encoder.bind_vertex_buffers(0, Some((&frame.buffer, 0)));
+        encoder.bind_index_buffer(IndexBufferView {
+            buffer: &frame.buffer,
+            offset: index_offset,
+            index_type: IndexType::U16,
+        });
+
+        let mut bound = None;
+        let (mut base_vertex, mut first_index) = (0, 0);
+        for list in data.lists {
+            for cmd in list.commands {
+                let indices = first_index .. first_index + cmd.elem_count;
+                first_index += cmd.elem_count;
+
+                // Clip rectangles are in display coordinates, scissors in framebuffer pixels.
+                let left = ((cmd.clip_rect[0] - data.display_pos[0]) * scale[0]).max(0.0);
+                let top = ((cmd.clip_rect[1] - data.display_pos[1]) * scale[1]).max(0.0);
+                let right = ((cmd.clip_rect[2] - data.display_pos[0]) * scale[0]).min(framebuffer_size[0]);
+                let bottom = ((cmd.clip_rect[3] - data.display_pos[1]) * scale[1]).min(framebuffer_size[1]);
+                if right <= left || bottom <= top {
+                    continue;
+                }
+
+                if bound != Some(cmd.texture_id) {
+                    let set = self.textures[cmd.texture_id.0].as_ref().expect("Unknown ImGui texture");
+                    encoder.bind_graphics_descriptor_sets(&self.pipeline_layout, 0, Some(set), &[]);
+                    bound = Some(cmd.texture_id);
+                }
+                encoder.set_scissors(0, &[Rect {
+                    x: left as i16,
+                    y: top as i16,
+                    w: (right - left).ceil() as i16,
+                    h: (bottom - top).ceil() as i16,
+                }]);
+                encoder.draw_indexed(indices, base_vertex, 0 .. 1);
+            }
+            base_vertex += list.vertices.len() as i32;
+        }
+    }
+
+    /// Destroy the buffers, the font atlas and the pipeline, freeing the registered textures.
+    pub fn destroy(mut self, device: &B::Device) {
+        for frame in self.frames.drain(..).filter_map(|frame| frame) {
+            frame.destroy(device);
+        }
+        if let Some(font) = self.font.take() {
+            device.destroy_image_view(font.view);
+            device.destroy_image(font.image);
+            device.free_memory(font.memory);
+        }
+        if let Some(pipeline) = self.pipeline {
+            device.destroy_graphics_pipeline(pipeline);
+        }
+        device.destroy_descriptor_pool(self.descriptor_pool);
+        device.destroy_sampler(self.sampler);
+        device.destroy_pipeline_layout(self.pipeline_layout);
+        device.destroy_descriptor_set_layout(self.set_layout);
+    }
+}
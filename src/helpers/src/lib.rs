@@ -0,0 +1,37 @@
+#![deny(missing_docs)]
+
+//! Application-level helpers built on top of `gfx-hal`.
+//!
+//! These utilities only use the public API of the hardware abstraction layer and
+//! work with any backend.
+
+#[macro_use]
+extern crate failure;
+extern crate fxhash;
+extern crate gfx_hal as hal;
+extern crate smallvec;
+
+#[cfg(test)]
+extern crate gfx_backend_software as software;
+
+#[cfg(feature = "serialize")]
+#[macro_use]
+extern crate serde;
+
+pub mod allocator;
+pub mod atlas;
+pub mod coalesce;
+pub mod completion;
+pub mod crash;
+pub mod effect;
+pub mod frame;
+pub mod handle;
+pub mod imgui;
+pub mod profiler;
+pub mod scheduler;
+pub mod sort;
+pub mod sprite;
+pub mod streaming;
+pub mod upload;
+pub mod video;
+pub mod warmup;
@@ -0,0 +1,456 @@
+//! Frame pacing measurements.
+//!
+//! `FrameProfiler` combines CPU timings of a frame with GPU timestamps written into
+//! its command buffers, producing a `FrameReport` per frame. The report tells whether
+//! the application is limited by the CPU, the GPU or the presentation engine.
+//!
+//! ```ignore
+//! if let Some(report) = profiler.begin_frame(&device) {
+//!     println!("{:?}", report);
+//! }
+//! profiler.write_begin(&mut cmd_buffer);
+//! // record commands
+//! profiler.write_end(&mut cmd_buffer);
+//! queue.submit(submission, Some(&fence));
+//! profiler.submitted();
+//! swapchain.present(&mut queue, frame, &[]);
+//! profiler.presented();
+//! ```
+//!
+//! `ScopeProfiler` times nested sections of the command buffers instead, resolving
+//! the timestamps of a frame into a `ScopeReport` tree once the frame completed:
+//!
+//! ```ignore
+//! if let Some(report) = scopes.begin_frame(&device) {
+//!     println!("{:#?}", report.scopes);
+//! }
+//! scopes.begin_scope(&mut cmd_buffer, "shadows");
+//! // record commands
+//! scopes.end_scope(&mut cmd_buffer);
+//! ```
+//!
+//! The command counts collected by the backends are returned by
+//! `Device::frame_statistics` instead, see `gfx_hal::profiler`.
+
+use std::borrow::Cow;
+use std::time::{Duration, Instant};
+
+use hal::command::{CommandBuffer, Level, Shot};
+use hal::device::Device;
+use hal::pso::PipelineStage;
+use hal::query::{Query, QueryId, QueryType};
+use hal::queue::capability::{GraphicsOrCompute, Supports};
+use hal::Backend;
+
+/// The part of the frame limiting the frame rate.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum Bottleneck {
+    /// Recording and submitting the frame takes the most time.
+    Cpu,
+    /// Executing the frame on the device takes the most time.
+    Gpu,
+    /// The presentation engine blocks the most time, e.g. waiting for vertical sync.
+    Present,
+}
+
+/// Timings of a single completed frame.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct FrameReport {
+    /// Number of the frame, counting from 1.
+    pub frame: u64,
+    /// CPU time from the beginning of the frame until its submission.
+    pub cpu_submit_time: Duration,
+    /// GPU timestamp at the start of the frame work, in nanoseconds.
+    pub gpu_start: u64,
+    /// GPU timestamp at the end of the frame work, in nanoseconds.
+    pub gpu_end: u64,
+    /// CPU time spent presenting the frame.
+    pub present_time: Duration,
+    /// CPU time from the beginning of the frame until it has been presented.
+    pub frame_time: Duration,
+    /// The part of the frame which took the most time.
+    pub bottleneck: Bottleneck,
+}
+
+impl FrameReport {
+    /// GPU execution time of the frame.
+    pub fn gpu_time(&self) -> Duration {
+        nanos_between(self.gpu_start, self.gpu_end)
+    }
+}
+
+fn nanos_between(start: u64, end: u64) -> Duration {
+    let nanos = end.saturating_sub(start);
+    Duration::new(nanos / 1_000_000_000, (nanos % 1_000_000_000) as u32)
+}
+
+#[derive(Debug)]
+struct FrameTimes {
+    frame: u64,
+    begin: Instant,
+    submit: Option<Instant>,
+    present: Option<Instant>,
+    timestamps: bool,
+}
+
+/// Collects per-frame timings with a ring of `frames_in_flight` slots.
+#[derive(Debug)]
+pub struct FrameProfiler<B: Backend> {
+    query_pool: B::QueryPool,
+    frames: Vec<Option<FrameTimes>>,
+    current: usize,
+    frame_count: u64,
+    timestamp_period: f32,
+}
+
+impl<B: Backend> FrameProfiler<B> {
+    /// Create a new profiler.
+    ///
+    /// `timestamp_period` is the number of nanoseconds per timestamp tick,
+    /// as returned by `PhysicalDevice::timestamp_period`.
+    pub fn new(device: &B::Device, frames_in_flight: usize, timestamp_period: f32) -> Self {
+        assert!(frames_in_flight > 0);
+        FrameProfiler {
+            query_pool: device.create_query_pool(QueryType::Timestamp, 2 * frames_in_flight as u32),
+            frames: (0 .. frames_in_flight).map(|_| None).collect(),
+            current: frames_in_flight - 1,
+            frame_count: 0,
+            timestamp_period,
+        }
+    }
+
+    fn queries(&self) -> (QueryId, QueryId) {
+        let base = 2 * self.current as QueryId;
+        (base, base + 1)
+    }
+
+    /// Begin timing a new frame.
+    ///
+    /// Returns the report of the frame previously profiled in the same slot,
+    /// blocking until its timestamps are available.
+    pub fn begin_frame(&mut self, device: &B::Device) -> Option<FrameReport> {
+        self.current = (self.current + 1) % self.frames.len();
+        self.frame_count += 1;
+
+        let report = match self.frames[self.current].take() {
+            Some(times) => self.resolve(device, times),
+            None => None,
+        };
+        self.frames[self.current] = Some(FrameTimes {
+            frame: self.frame_count,
+            begin: Instant::now(),
+            submit: None,
+            present: None,
+            timestamps: false,
+        });
+        report
+    }
+
+    /// Record the GPU start timestamp of the current frame.
+    ///
+    /// Needs to be recorded outside of a render pass, before the frame work.
+    pub fn write_begin<C, S, L>(&mut self, cmd_buffer: &mut CommandBuffer<B, C, S, L>)
+    where
+        C: Supports<GraphicsOrCompute>,
+        S: Shot,
+        L: Level,
+    {
+        let (start, end) = self.queries();
+        cmd_buffer.reset_query_pool(&self.query_pool, start .. end + 1);
+        cmd_buffer.write_timestamp(PipelineStage::TOP_OF_PIPE, Query { pool: &self.query_pool, id: start });
+        self.current_times().timestamps = true;
+    }
+
+    /// Record the GPU end timestamp of the current frame, after the frame work.
+    pub fn write_end<C, S, L>(&mut self, cmd_buffer: &mut CommandBuffer<B, C, S, L>)
+    where
+        C: Supports<GraphicsOrCompute>,
+        S: Shot,
+        L: Level,
+    {
+        let (_, end) = self.queries();
+        cmd_buffer.write_timestamp(PipelineStage::BOTTOM_OF_PIPE, Query { pool: &self.query_pool, id: end });
+    }
+
+    /// Mark the current frame as submitted.
+    pub fn submitted(&mut self) {
+        self.current_times().submit = Some(Instant::now());
+    }
+
+    /// Mark the current frame as presented.
+    pub fn presented(&mut self) {
+        self.current_times().present = Some(Instant::now());
+    }
+
+    /// Returns the number of frames begun so far.
+    pub fn frame_count(&self) -> u64 {
+        self.frame_count
+    }
+
+    /// Destroy the profiler. The device must not use the timestamp queries anymore.
+    pub fn destroy(self, device: &B::Device) {
+        device.destroy_query_pool(self.query_pool);
+    }
+
+    fn current_times(&mut self) -> &mut FrameTimes {
+        self.frames[self.current]
+            .as_mut()
+            .expect("`begin_frame` has not been called")
+    }
+
+    fn resolve(&self, device: &B::Device, times: FrameTimes) -> Option<FrameReport> {
+        let (gpu_start, gpu_end) = if times.timestamps {
+            let (start, end) = self.queries();
+            let mut data = [0u64; 2];
+            match device.get_query_pool_results(&self.query_pool, start .. end + 1, &mut data, true) {
+                Ok(true) => (),
+                // Device errors are reported by the next fence wait or submission.
+                Ok(false) | Err(_) => return None,
+            }
+            let period = self.timestamp_period as f64;
+            ((data[0] as f64 * period) as u64, (data[1] as f64 * period) as u64)
+        } else {
+            (0, 0)
+        };
+
+        let submit = times.submit.unwrap_or(times.begin);
+        let present = times.present.unwrap_or(submit);
+        let mut report = FrameReport {
+            frame: times.frame,
+            cpu_submit_time: submit.duration_since(times.begin),
+            gpu_start,
+            gpu_end,
+            present_time: present.duration_since(submit),
+            frame_time: present.duration_since(times.begin),
+            bottleneck: Bottleneck::Cpu,
+        };
+
+        let gpu_time = report.gpu_time();
+        report.bottleneck = if gpu_time > report.cpu_submit_time && gpu_time > report.present_time {
+            Bottleneck::Gpu
+        } else if report.present_time > report.cpu_submit_time {
+            Bottleneck::Present
+        } else {
+            Bottleneck::Cpu
+        };
+        Some(report)
+    }
+}
+
+/// GPU timing of a command buffer section, with the sections nested in it.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ScopeTiming {
+    /// Name passed to `ScopeProfiler::begin_scope`.
+    pub name: Cow<'static, str>,
+    /// GPU timestamp at the start of the section, in nanoseconds.
+    pub start: u64,
+    /// GPU timestamp at the end of the section, in nanoseconds.
+    pub end: u64,
+    /// Sections begun and ended inside this one, in recording order.
+    pub children: Vec<ScopeTiming>,
+}
+
+impl ScopeTiming {
+    /// GPU execution time of the section.
+    pub fn duration(&self) -> Duration {
+        nanos_between(self.start, self.end)
+    }
+}
+
+/// Timings of the sections of a single completed frame.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ScopeReport {
+    /// Number of the frame, counting from 1.
+    pub frame: u64,
+    /// Outermost sections, in recording order.
+    pub scopes: Vec<ScopeTiming>,
+    /// Sections not timed because the frame exceeded the scope limit of the profiler.
+    pub dropped_scopes: usize,
+}
+
+#[derive(Debug)]
+struct RawScope {
+    name: Cow<'static, str>,
+    parent: Option<usize>,
+}
+
+#[derive(Debug)]
+struct ScopeFrame {
+    frame: u64,
+    scopes: Vec<RawScope>,
+    // Scopes begun and not ended yet, innermost last. `None` for dropped scopes.
+    open: Vec<Option<usize>>,
+    dropped: usize,
+}
+
+/// Times nested command buffer sections with a ring of `frames_in_flight` slots of
+/// timestamp queries.
+///
+/// Each scope uses two queries of its frame slot, scopes exceeding `max_scopes` in a
+/// frame are dropped. The queries of a slot are reset by the first scope of the frame,
+/// which therefore has to be begun outside of a render pass.
+#[derive(Debug)]
+pub struct ScopeProfiler<B: Backend> {
+    query_pool: B::QueryPool,
+    max_scopes: usize,
+    frames: Vec<Option<ScopeFrame>>,
+    current: usize,
+    frame_count: u64,
+    timestamp_period: f32,
+}
+
+impl<B: Backend> ScopeProfiler<B> {
+    /// Create a new profiler, timing up to `max_scopes` sections per frame.
+    ///
+    /// `timestamp_period` is the number of nanoseconds per timestamp tick,
+    /// as returned by `PhysicalDevice::timestamp_period`.
+    pub fn new(
+        device: &B::Device,
+        frames_in_flight: usize,
+        max_scopes: usize,
+        timestamp_period: f32,
+    ) -> Self {
+        assert!(frames_in_flight > 0 && max_scopes > 0);
+        let query_count = 2 * max_scopes * frames_in_flight;
+        ScopeProfiler {
+            query_pool: device.create_query_pool(QueryType::Timestamp, query_count as u32),
+            max_scopes,
+            frames: (0 .. frames_in_flight).map(|_| None).collect(),
+            current: frames_in_flight - 1,
+            frame_count: 0,
+            timestamp_period,
+        }
+    }
+
+    fn base_query(&self) -> QueryId {
+        (2 * self.max_scopes * self.current) as QueryId
+    }
+
+    /// Begin profiling a new frame.
+    ///
+    /// Returns the report of the frame previously profiled in the same slot,
+    /// blocking until its timestamps are available.
+    pub fn begin_frame(&mut self, device: &B::Device) -> Option<ScopeReport> {
+        if let Some(ref frame) = self.frames[self.current] {
+            assert!(frame.open.is_empty(), "frame ended with {} open scopes", frame.open.len());
+        }
+        self.current = (self.current + 1) % self.frames.len();
+        self.frame_count += 1;
+
+        let report = match self.frames[self.current].take() {
+            Some(frame) => self.resolve(device, frame),
+            None => None,
+        };
+        self.frames[self.current] = Some(ScopeFrame {
+            frame: self.frame_count,
+            scopes: Vec::new(),
+            open: Vec::new(),
+            dropped: 0,
+        });
+        report
+    }
+
+    /// Begin a section, nested in the innermost open one.
+    pub fn begin_scope<C, S, L, N>(&mut self, cmd_buffer: &mut CommandBuffer<B, C, S, L>, name: N)
+    where
+        C: Supports<GraphicsOrCompute>,
+        S: Shot,
+        L: Level,
+        N: Into<Cow<'static, str>>,
+    {
+        let base = self.base_query();
+        let max_scopes = self.max_scopes;
+        let frame = self.frames[self.current]
+            .as_mut()
+            .expect("`begin_frame` has not been called");
+
+        if frame.scopes.len() == max_scopes {
+            frame.dropped += 1;
+            frame.open.push(None);
+            return;
+        }
+        if frame.scopes.is_empty() {
+            cmd_buffer.reset_query_pool(&self.query_pool, base .. base + 2 * max_scopes as QueryId);
+        }
+        let index = frame.scopes.len();
+        frame.scopes.push(RawScope {
+            name: name.into(),
+            parent: frame.open.iter().rev().filter_map(|&scope| scope).next(),
+        });
+        frame.open.push(Some(index));
+        cmd_buffer.write_timestamp(
+            PipelineStage::TOP_OF_PIPE,
+            Query { pool: &self.query_pool, id: base + 2 * index as QueryId },
+        );
+    }
+
+    /// End the innermost open section.
+    pub fn end_scope<C, S, L>(&mut self, cmd_buffer: &mut CommandBuffer<B, C, S, L>)
+    where
+        C: Supports<GraphicsOrCompute>,
+        S: Shot,
+        L: Level,
+    {
+        let base = self.base_query();
+        let frame = self.frames[self.current]
+            .as_mut()
+            .expect("`begin_frame` has not been called");
+        let scope = frame.open.pop().expect("no scope to end");
+        if let Some(index) = scope {
+            cmd_buffer.write_timestamp(
+                PipelineStage::BOTTOM_OF_PIPE,
+                Query { pool: &self.query_pool, id: base + 2 * index as QueryId + 1 },
+            );
+        }
+    }
+
+    /// Returns the number of frames begun so far.
+    pub fn frame_count(&self) -> u64 {
+        self.frame_count
+    }
+
+    /// Destroy the profiler. The device must not use the timestamp queries anymore.
+    pub fn destroy(self, device: &B::Device) {
+        device.destroy_query_pool(self.query_pool);
+    }
+
+    fn resolve(&self, device: &B::Device, frame: ScopeFrame) -> Option<ScopeReport> {
+        let mut data = vec![0u64; 2 * frame.scopes.len()];
+        if !data.is_empty() {
+            let base = self.base_query();
+            let queries = base .. base + data.len() as QueryId;
+            match device.get_query_pool_results(&self.query_pool, queries, &mut data, true) {
+                Ok(true) => (),
+                // Device errors are reported by the next fence wait or submission.
+                Ok(false) | Err(_) => return None,
+            }
+        }
+
+        // Children always follow their parents, so the tree is built back to front.
+        let period = self.timestamp_period as f64;
+        let mut timings = frame.scopes
+            .iter()
+            .enumerate()
+            .map(|(i, scope)| Some(ScopeTiming {
+                name: scope.name.clone(),
+                start: (data[2 * i] as f64 * period) as u64,
+                end: (data[2 * i + 1] as f64 * period) as u64,
+                children: Vec::new(),
+            }))
+            .collect::<Vec<_>>();
+        let mut scopes = Vec::new();
+        for (i, scope) in frame.scopes.iter().enumerate().rev() {
+            let timing = timings[i].take().unwrap();
+            match scope.parent {
+                Some(parent) => timings[parent].as_mut().unwrap().children.insert(0, timing),
+                None => scopes.insert(0, timing),
+            }
+        }
+
+        Some(ScopeReport {
+            frame: frame.frame,
+            scopes,
+            dropped_scopes: frame.dropped,
+        })
+    }
+}
@@ -0,0 +1,440 @@
+//! Dependency-driven submission across queues.
+//!
+//! A `Schedule` collects submissions with their dependencies on each other. Each
+//! submission is placed on a lane matching the capability of its command buffers:
+//! transfer command buffers run on the transfer queue, compute command buffers on
+//! the compute queue and everything else on the general queue. Missing queues fall
+//! back to the general queue.
+//!
+//! `Scheduler::submit` batches the submissions of each lane into as few queue
+//! submissions as the dependencies allow, and inserts semaphores between lanes.
+//! Dependencies within a lane are satisfied by the submission order, memory
+//! dependencies still require barriers in the command buffers as usual.
+//!
+//! ```ignore
+//! let mut schedule = Schedule::new();
+//! let upload = schedule.add(Some(upload_submit), &[]);
+//! let simulation = schedule.add(Some(compute_submit), &[]);
+//! let frame = schedule.add(Some(frame_submit), &[
+//!     (upload, PipelineStage::VERTEX_INPUT),
+//!     (simulation, PipelineStage::VERTEX_SHADER),
+//! ]);
+//! schedule.wait_on(frame, &[(&acquire_semaphore, PipelineStage::COLOR_ATTACHMENT_OUTPUT)]);
+//! schedule.signal(frame, &[&present_semaphore]);
+//! scheduler.submit(&device, schedule, &mut queues, Some(&fence));
+//! ```
+
+use std::borrow::Cow;
+use std::collections::VecDeque;
+use std::fmt;
+
+use hal::command::{Primary, Submittable};
+use hal::device::Device;
+use hal::pso::PipelineStage;
+use hal::queue::{Capability, CommandQueue, Compute, General, QueueType, RawCommandQueue, RawSubmission, Transfer};
+use hal::Backend;
+use smallvec::SmallVec;
+
+/// Identifier of a submission added to a `Schedule`.
+#[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub struct SubmissionId(usize);
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum Lane {
+    General,
+    Compute,
+    Transfer,
+}
+
+struct Node<'a, B: Backend> {
+    lane: Lane,
+    cmd_buffers: SmallVec<[Cow<'a, B::CommandBuffer>; 4]>,
+    dependencies: Vec<(SubmissionId, PipelineStage)>,
+    wait_semaphores: Vec<(&'a B::Semaphore, PipelineStage)>,
+    signal_semaphores: Vec<&'a B::Semaphore>,
+}
+
+/// Submissions with their dependencies, submitted together by a `Scheduler`.
+pub struct Schedule<'a, B: Backend> {
+    nodes: Vec<Node<'a, B>>,
+}
+
+impl<'a, B: Backend> fmt::Debug for Schedule<'a, B> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Schedule")
+            .field("submissions", &self.nodes.len())
+            .finish()
+    }
+}
+
+impl<'a, B: Backend> Schedule<'a, B> {
+    /// Create an empty schedule.
+    pub fn new() -> Self {
+        Schedule {
+            nodes: Vec::new(),
+        }
+    }
+
+    /// Add a submission of command buffers, which is executed after the `dependencies`
+    /// have completed up to the given pipeline stages.
+    ///
+    /// Dependencies have to be added before their dependents.
+    pub fn add<I, K>(&mut self, submits: I, dependencies: &[(SubmissionId, PipelineStage)]) -> SubmissionId
+    where
+        I: IntoIterator,
+        I::Item: Submittable<'a, B, K, Primary>,
+        K: Capability,
+    {
+        assert!(dependencies.iter().all(|&(id, _)| id.0 < self.nodes.len()), "Dependency added after its dependent");
+        let lane = if K::supported_by(QueueType::Transfer) {
+            Lane::Transfer
+        } else if K::supported_by(QueueType::Compute) {
+            Lane::Compute
+        } else {
+            Lane::General
+        };
+        self.nodes.push(Node {
+            lane,
+            cmd_buffers: submits.into_iter().map(|s| unsafe { s.into_buffer() }).collect(),
+            dependencies: dependencies.to_vec(),
+            wait_semaphores: Vec::new(),
+            signal_semaphores: Vec::new(),
+        });
+        SubmissionId(self.nodes.len() - 1)
+    }
+
+    /// Add semaphores waited on before the submission is executed, e.g. the one of
+    /// an acquired swapchain image.
+    pub fn wait_on(&mut self, id: SubmissionId, semaphores: &[(&'a B::Semaphore, PipelineStage)]) {
+        self.nodes[id.0].wait_semaphores.extend_from_slice(semaphores);
+    }
+
+    /// Add semaphores signalled once the submission has finished executing, e.g. one
+    /// waited on by the presentation.
+    pub fn signal(&mut self, id: SubmissionId, semaphores: &[&'a B::Semaphore]) {
+        self.nodes[id.0].signal_semaphores.extend_from_slice(semaphores);
+    }
+}
+
+/// Queues of the lanes of a `Scheduler`.
+pub struct SchedulerQueues<'q, B: Backend> {
+    /// Queue of the graphics submissions and of the lanes without a queue.
+    pub general: &'q mut CommandQueue<B, General>,
+    /// Queue of the compute submissions.
+    pub compute: Option<&'q mut CommandQueue<B, Compute>>,
+    /// Queue of the transfer submissions.
+    pub transfer: Option<&'q mut CommandQueue<B, Transfer>>,
+}
+
+impl<'q, B: Backend> SchedulerQueues<'q, B> {
+    fn resolve(&self, lane: Lane) -> Lane {
+        match lane {
+            Lane::Compute if self.compute.is_none() => Lane::General,
+            Lane::Transfer if self.transfer.is_none() => Lane::General,
+            lane => lane,
+        }
+    }
+
+    fn queue(&mut self, lane: Lane) -> &mut B::CommandQueue {
+        match lane {
+            Lane::General => self.general.as_raw_mut(),
+            Lane::Compute => self.compute.as_mut().unwrap().as_raw_mut(),
+            Lane::Transfer => self.transfer.as_mut().unwrap().as_raw_mut(),
+        }
+    }
+}
+
+/// Consecutive submissions of a lane, submitted at once.
+#[derive(Debug)]
+struct Batch {
+    lane: Lane,
+    nodes: Vec<usize>,
+    // Batches of other lanes waited on, with the stages waiting.
+    waits: Vec<(usize, PipelineStage)>,
+}
+
+/// Submits schedules, reusing the semaphores inserted between the lanes.
+pub struct Scheduler<B: Backend> {
+    free_semaphores: Vec<B::Semaphore>,
+    // Semaphores used by the schedule submitted on the given frame.
+    in_flight: VecDeque<(u64, Vec<B::Semaphore>)>,
+    frame: u64,
+    frames_in_flight: u64,
+}
+
+impl<B: Backend> fmt::Debug for Scheduler<B> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Scheduler")
+            .field("free_semaphores", &self.free_semaphores.len())
+            .field("frame", &self.frame)
+            .finish()
+    }
+}
+
+impl<B: Backend> Scheduler<B> {
+    /// Create a scheduler for `frames_in_flight` schedules executing at the same time.
+    ///
+    /// The semaphores of a schedule are reused once `submit` has been called
+    /// `frames_in_flight` more times, the schedule must have completed by then.
+    pub fn new(frames_in_flight: usize) -> Self {
+        Scheduler {
+            free_semaphores: Vec::new(),
+            in_flight: VecDeque::new(),
+            frame: 0,
+            frames_in_flight: frames_in_flight as u64,
+        }
+    }
+
+    // Group the submissions into batches, submitted in order. `resolve` maps the
+    // lanes without a queue to the general lane.
+    fn plan<F>(schedule: &Schedule<B>, resolve: F, fence: bool) -> Vec<Batch>
+    where
+        F: Fn(Lane) -> Lane,
+    {
+        let mut batches = Vec::<Batch>::new();
+        let mut node_batches = Vec::with_capacity(schedule.nodes.len());
+        let mut open = [None; 3];
+
+        for (index, node) in schedule.nodes.iter().enumerate() {
+            let lane = resolve(node.lane);
+            let mut waits = Vec::<(usize, PipelineStage)>::new();
+            for &(SubmissionId(dependency), stages) in &node.dependencies {
+                let producer: usize = node_batches[dependency];
+                if batches[producer].lane == lane {
+                    continue;
+                }
+                // Later submissions of the producer lane aren't waited on.
+                let producer_lane = batches[producer].lane as usize;
+                if open[producer_lane] == Some(producer) {
+                    open[producer_lane] = None;
+                }
+                match waits.iter_mut().find(|&&mut (batch, _)| batch == producer) {
+                    Some(wait) => wait.1 |= stages,
+                    None => waits.push((producer, stages)),
+                }
+            }
+
+            // The batch is submitted after the ones it waits on.
+            let latest = waits.iter().map(|&(batch, _)| batch).max();
+            let target = match open[lane as usize] {
+                Some(batch) if latest.map_or(true, |latest| latest < batch) => batch,
+                _ => {
+                    batches.push(Batch {
+                        lane,
+                        nodes: Vec::new(),
+                        waits: Vec::new(),
+                    });
+                    open[lane as usize] = Some(batches.len() - 1);
+                    batches.len() - 1
+                }
+            };
+
+            let batch = &mut batches[target];
+            batch.nodes.push(index);
+            for (producer, stages) in waits {
+                match batch.waits.iter_mut().find(|&&mut (batch, _)| batch == producer) {
+                    Some(wait) => wait.1 |= stages,
+                    None => batch.waits.push((producer, stages)),
+                }
+            }
+            node_batches.push(target);
+        }
+
+        // The fence is signalled by a final batch on the general queue, which waits
+        // for the last batch of each other lane.
+        if fence {
+            let waits = [Lane::Compute, Lane::Transfer]
+                .iter()
+                .filter_map(|&lane| batches.iter().rposition(|batch| batch.lane == lane))
+                .map(|batch| (batch, PipelineStage::TOP_OF_PIPE))
+                .collect::<Vec<_>>();
+            if !waits.is_empty() || batches.is_empty() {
+                batches.push(Batch {
+                    lane: Lane::General,
+                    nodes: Vec::new(),
+                    waits,
+                });
+            }
+        }
+
+        batches
+    }
+
+    /// Submit the schedule to the queues.
+    ///
+    /// `fence` is signalled once all submissions of the schedule have completed.
+    pub fn submit(
+        &mut self,
+        device: &B::Device,
+        schedule: Schedule<B>,
+        queues: &mut SchedulerQueues<B>,
+        fence: Option<&B::Fence>,
+    ) {
+        let frames_in_flight = self.frames_in_flight;
+        let frame = self.frame;
+        while self.in_flight.front().map_or(false, |&(submitted, _)| submitted + frames_in_flight <= frame) {
+            let (_, semaphores) = self.in_flight.pop_front().unwrap();
+            self.free_semaphores.extend(semaphores);
+        }
+        self.frame += 1;
+
+        let batches = Self::plan(&schedule, |lane| queues.resolve(lane), fence.is_some());
+
+        // A semaphore for every pair of batches of different lanes.
+        let edges = batches
+            .iter()
+            .enumerate()
+            .flat_map(|(consumer, batch)| batch.waits.iter().map(move |&(producer, stages)| (producer, consumer, stages)))
+            .collect::<Vec<_>>();
+        let mut semaphores = Vec::with_capacity(edges.len());
+        for _ in 0 .. edges.len() {
+            let semaphore = self.free_semaphores
+                .pop()
+                .unwrap_or_else(|| device.create_semaphore());
+            semaphores.push(semaphore);
+        }
+
+        let last = batches.len().saturating_sub(1);
+        for (index, batch) in batches.iter().enumerate() {
+            let nodes = batch.nodes.iter().map(|&node| &schedule.nodes[node]);
+            let cmd_buffers = nodes
+                .clone()
+                .flat_map(|node| node.cmd_buffers.iter().map(|buffer| &**buffer))
+                .collect::<Vec<_>>();
+            let wait_semaphores = edges
+                .iter()
+                .zip(&semaphores)
+                .filter(|&(&(_, consumer, _), _)| consumer == index)
+                .map(|(&(_, _, stages), semaphore)| (semaphore, stages))
+                .chain(nodes.clone().flat_map(|node| node.wait_semaphores.iter().cloned()))
+                .collect::<Vec<_>>();
+            let signal_semaphores = edges
+                .iter()
+                .zip(&semaphores)
+                .filter(|&(&(producer, _, _), _)| producer == index)
+                .map(|(_, semaphore)| semaphore)
+                .chain(nodes.flat_map(|node| node.signal_semaphores.iter().cloned()))
+                .collect::<Vec<_>>();
+
+            let submission = RawSubmission {
+                cmd_buffers,
+                wait_semaphores: &wait_semaphores,
+                signal_semaphores: &signal_semaphores,
+                wait_timeline_semaphores: &[],
+                signal_timeline_semaphores: &[],
+            };
+            // The lanes only receive command buffers of capabilities supported by their queue.
+            unsafe {
+                queues
+                    .queue(batch.lane)
+                    .submit_raw(submission, if index == last { fence } else { None });
+            }
+        }
+
+        self.in_flight.push_back((frame, semaphores));
+    }
+
+    /// Destroy the scheduler and its semaphores, all schedules must have completed.
+    pub fn destroy(self, device: &B::Device) {
+        let in_flight = self.in_flight.into_iter().flat_map(|(_, semaphores)| semaphores);
+        for semaphore in self.free_semaphores.into_iter().chain(in_flight) {
+            device.destroy_semaphore(semaphore);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use hal::command::{OneShot, Submit};
+    use hal::queue::Graphics;
+    use software;
+
+    type Backend = software::Backend;
+    type PlannedBatch = (Lane, Vec<usize>, Vec<(usize, PipelineStage)>);
+
+    fn add<C: Capability>(schedule: &mut Schedule<Backend>, dependencies: &[(SubmissionId, PipelineStage)]) -> SubmissionId {
+        schedule.add(Vec::<Submit<Backend, C, OneShot, Primary>>::new(), dependencies)
+    }
+
+    fn plan(schedule: &Schedule<Backend>, fallback: bool, fence: bool) -> Vec<PlannedBatch> {
+        let resolve = |lane| if fallback { Lane::General } else { lane };
+        Scheduler::plan(schedule, resolve, fence)
+            .into_iter()
+            .map(|batch| (batch.lane, batch.nodes, batch.waits))
+            .collect()
+    }
+
+    #[test]
+    fn test_cross_lane_dependencies() {
+        let mut schedule = Schedule::new();
+        let upload = add::<Transfer>(&mut schedule, &[]);
+        let simulation = add::<Compute>(&mut schedule, &[(upload, PipelineStage::COMPUTE_SHADER)]);
+        let frame = add::<Graphics>(&mut schedule, &[
+            (upload, PipelineStage::VERTEX_INPUT),
+            (simulation, PipelineStage::VERTEX_SHADER),
+        ]);
+        add::<General>(&mut schedule, &[(frame, PipelineStage::TOP_OF_PIPE)]);
+
+        assert_eq!(plan(&schedule, false, false), vec![
+            (Lane::Transfer, vec![0], vec![]),
+            (Lane::Compute, vec![1], vec![(0, PipelineStage::COMPUTE_SHADER)]),
+            (Lane::General, vec![2, 3], vec![(0, PipelineStage::VERTEX_INPUT), (1, PipelineStage::VERTEX_SHADER)]),
+        ]);
+    }
+
+    #[test]
+    fn test_dependency_on_later_batch() {
+        // The general lane is split, its second batch waits on the transfer
+        // batch which itself waits on the first one.
+        let mut schedule = Schedule::new();
+        let first = add::<Graphics>(&mut schedule, &[]);
+        let copy = add::<Transfer>(&mut schedule, &[(first, PipelineStage::TRANSFER)]);
+        add::<Graphics>(&mut schedule, &[(copy, PipelineStage::FRAGMENT_SHADER)]);
+
+        assert_eq!(plan(&schedule, false, false), vec![
+            (Lane::General, vec![0], vec![]),
+            (Lane::Transfer, vec![1], vec![(0, PipelineStage::TRANSFER)]),
+            (Lane::General, vec![2], vec![(1, PipelineStage::FRAGMENT_SHADER)]),
+        ]);
+    }
+
+    #[test]
+    fn test_lane_fallback() {
+        let mut schedule = Schedule::new();
+        let upload = add::<Transfer>(&mut schedule, &[]);
+        let simulation = add::<Compute>(&mut schedule, &[(upload, PipelineStage::COMPUTE_SHADER)]);
+        add::<Graphics>(&mut schedule, &[(simulation, PipelineStage::VERTEX_SHADER)]);
+
+        assert_eq!(plan(&schedule, true, false), vec![
+            (Lane::General, vec![0, 1, 2], vec![]),
+        ]);
+    }
+
+    #[test]
+    fn test_fence_placement() {
+        let mut schedule = Schedule::new();
+        let upload = add::<Transfer>(&mut schedule, &[]);
+        add::<Graphics>(&mut schedule, &[(upload, PipelineStage::VERTEX_INPUT)]);
+        add::<Compute>(&mut schedule, &[]);
+
+        assert_eq!(plan(&schedule, false, true), vec![
+            (Lane::Transfer, vec![0], vec![]),
+            (Lane::General, vec![1], vec![(0, PipelineStage::VERTEX_INPUT)]),
+            (Lane::Compute, vec![2], vec![]),
+            (Lane::General, vec![], vec![(2, PipelineStage::TOP_OF_PIPE), (0, PipelineStage::TOP_OF_PIPE)]),
+        ]);
+
+        // Schedules ending on the general lane signal the fence with their last batch.
+        let mut schedule = Schedule::new();
+        add::<Graphics>(&mut schedule, &[]);
+        assert_eq!(plan(&schedule, false, true), vec![
+            (Lane::General, vec![0], vec![]),
+        ]);
+
+        // Empty schedules still signal the fence.
+        assert_eq!(plan(&Schedule::new(), false, true), vec![
+            (Lane::General, vec![], vec![]),
+        ]);
+    }
+}
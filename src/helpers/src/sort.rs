@@ -0,0 +1,297 @@
+//! Sort keys for ordering draws by state, to minimize state changes.
+//!
+//! Pipelines, descriptor sets and buffers are registered with a `DrawSorter`, which
+//! assigns them small integer IDs. The IDs are packed into a `DrawKey`, ordering the
+//! draws by layer, pipeline, descriptor set, vertex buffer and depth. Keys are plain
+//! integers, so they can also be radix sorted by the user.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::ops::Range;
+
+use hal::buffer;
+use hal::command::RenderSubpassCommon;
+use hal::{Backend, IndexCount, IndexType, InstanceCount, Primitive, VertexCount, VertexOffset};
+use coalesce::{CoalescingStatistics, DrawCoalescer};
+
+/// Maximum number of pipelines registered with a `DrawSorter`.
+pub const MAX_SORTED_PIPELINES: usize = 1 << 12;
+/// Maximum number of descriptor sets registered with a `DrawSorter`.
+pub const MAX_SORTED_DESCRIPTOR_SETS: usize = (1 << 16) - 1;
+/// Maximum number of buffers registered with a `DrawSorter`.
+pub const MAX_SORTED_BUFFERS: usize = (1 << 12) - 1;
+
+/// ID of a pipeline registered with a `DrawSorter`.
+#[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub struct PipelineId(u16);
+
+/// ID of a descriptor set registered with a `DrawSorter`.
+#[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub struct DescriptorSetId(u16);
+
+/// ID of a buffer registered with a `DrawSorter`.
+#[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub struct BufferId(u16);
+
+macro_rules! impl_raw_id {
+    ($($name:ident),*) => {
+        $(
+            impl $name {
+                /// Raw value of the ID, stable while the handle stays registered.
+                pub fn raw(&self) -> u16 {
+                    self.0
+                }
+            }
+        )*
+    };
+}
+
+impl_raw_id!(PipelineId, DescriptorSetId, BufferId);
+
+/// Draw sort key.
+///
+/// From the most to the least significant bits: layer (8 bits), pipeline (12 bits),
+/// descriptor set (16 bits), vertex buffer (12 bits) and depth (16 bits). Draws
+/// without a descriptor set or vertex buffer sort before the ones which have one.
+#[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub struct DrawKey(pub u64);
+
+impl DrawKey {
+    /// Build a key, the depth should be quantized by the user, e.g. to sort front to
+    /// back or back to front depending on the layer.
+    pub fn new(
+        layer: u8,
+        pipeline: PipelineId,
+        descriptor_set: Option<DescriptorSetId>,
+        vertex_buffer: Option<BufferId>,
+        depth: u16,
+    ) -> Self {
+        // IDs are shifted by one in the key, zero stands for no set or buffer.
+        let descriptor_set = descriptor_set.map_or(0, |id| id.0 as u64 + 1) & 0xFFFF;
+        let vertex_buffer = vertex_buffer.map_or(0, |id| id.0 as u64 + 1) & 0xFFF;
+        DrawKey(
+            (layer as u64) << 56 |
+            (pipeline.0 as u64 & 0xFFF) << 44 |
+            descriptor_set << 28 |
+            vertex_buffer << 16 |
+            depth as u64
+        )
+    }
+
+    /// Layer of the key.
+    pub fn layer(&self) -> u8 {
+        (self.0 >> 56) as u8
+    }
+
+    /// Depth of the key.
+    pub fn depth(&self) -> u16 {
+        self.0 as u16
+    }
+}
+
+/// Draw call issued by a `DrawSorter`.
+#[derive(Clone, Debug, PartialEq)]
+pub enum SortedDrawCall {
+    /// Non-indexed draw.
+    Vertices {
+        /// Vertex range.
+        vertices: Range<VertexCount>,
+        /// Instance range.
+        instances: Range<InstanceCount>,
+    },
+    /// Indexed draw.
+    Indexed {
+        /// Index buffer.
+        buffer: BufferId,
+        /// Offset of the indices in the buffer.
+        offset: buffer::Offset,
+        /// Type of the indices.
+        index_type: IndexType,
+        /// Index range.
+        indices: Range<IndexCount>,
+        /// Vertex offset added to the indices.
+        base_vertex: VertexOffset,
+        /// Instance range.
+        instances: Range<InstanceCount>,
+    },
+}
+
+/// Draw recorded into a `DrawSorter`, the state is bound when it differs from the
+/// state of the previous draw.
+#[derive(Clone, Debug, PartialEq)]
+pub struct SortedDraw {
+    /// Graphics pipeline.
+    pub pipeline: PipelineId,
+    /// Descriptor set, bound at the given set index of the pipeline layout.
+    pub descriptor_set: Option<(usize, DescriptorSetId)>,
+    /// Vertex buffer, bound to the first binding.
+    pub vertex_buffer: Option<(BufferId, buffer::Offset)>,
+    /// Draw call.
+    pub call: SortedDrawCall,
+}
+
+// Register a handle by address, returning its existing ID if it's registered already.
+fn register<'a, T>(
+    handles: &mut Vec<&'a T>,
+    ids: &mut HashMap<*const T, u16>,
+    handle: &'a T,
+    max: usize,
+) -> u16 {
+    let next = handles.len();
+    *ids.entry(handle as *const _).or_insert_with(|| {
+        assert!(next < max, "too many handles registered with the draw sorter");
+        handles.push(handle);
+        next as u16
+    })
+}
+
+/// Collects draws with their sort keys, and issues them sorted with redundant state
+/// changes removed.
+///
+/// Registrations are kept across submissions, so the IDs are stable and keys can be
+/// cached by the user. Handles are identified by address, they must not be destroyed
+/// while the sorter is alive.
+pub struct DrawSorter<'a, B: Backend> {
+    pipelines: Vec<&'a B::GraphicsPipeline>,
+    // Layout and topology of each pipeline.
+    pipeline_infos: Vec<(&'a B::PipelineLayout, Primitive)>,
+    pipeline_ids: HashMap<*const B::GraphicsPipeline, u16>,
+    descriptor_sets: Vec<&'a B::DescriptorSet>,
+    descriptor_set_ids: HashMap<*const B::DescriptorSet, u16>,
+    buffers: Vec<&'a B::Buffer>,
+    buffer_ids: HashMap<*const B::Buffer, u16>,
+    draws: Vec<(DrawKey, SortedDraw)>,
+}
+
+impl<'a, B: Backend> fmt::Debug for DrawSorter<'a, B> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("DrawSorter")
+            .field("pipelines", &self.pipelines.len())
+            .field("descriptor_sets", &self.descriptor_sets.len())
+            .field("buffers", &self.buffers.len())
+            .field("draws", &self.draws.len())
+            .finish()
+    }
+}
+
+impl<'a, B: Backend> DrawSorter<'a, B> {
+    /// Create an empty sorter.
+    pub fn new() -> Self {
+        DrawSorter {
+            pipelines: Vec::new(),
+            pipeline_infos: Vec::new(),
+            pipeline_ids: HashMap::new(),
+            descriptor_sets: Vec::new(),
+            descriptor_set_ids: HashMap::new(),
+            buffers: Vec::new(),
+            buffer_ids: HashMap::new(),
+            draws: Vec::new(),
+        }
+    }
+
+    /// Register a graphics pipeline with the layout its descriptor sets are bound with,
+    /// and the primitive topology it was created with, see `DrawCoalescer`.
+    ///
+    /// Registering a pipeline again returns its existing ID, the layout isn't updated.
+    pub fn register_pipeline(
+        &mut self,
+        pipeline: &'a B::GraphicsPipeline,
+        layout: &'a B::PipelineLayout,
+        primitive: Primitive,
+    ) -> PipelineId {
+        let id = register(&mut self.pipelines, &mut self.pipeline_ids, pipeline, MAX_SORTED_PIPELINES);
+        if id as usize == self.pipeline_infos.len() {
+            self.pipeline_infos.push((layout, primitive));
+        }
+        PipelineId(id)
+    }
+
+    /// Register a descriptor set.
+    pub fn register_descriptor_set(&mut self, descriptor_set: &'a B::DescriptorSet) -> DescriptorSetId {
+        DescriptorSetId(register(
+            &mut self.descriptor_sets,
+            &mut self.descriptor_set_ids,
+            descriptor_set,
+            MAX_SORTED_DESCRIPTOR_SETS,
+        ))
+    }
+
+    /// Register a vertex or index buffer.
+    pub fn register_buffer(&mut self, buffer: &'a B::Buffer) -> BufferId {
+        BufferId(register(&mut self.buffers, &mut self.buffer_ids, buffer, MAX_SORTED_BUFFERS))
+    }
+
+    /// Record a draw, issued at the position of its key on submission.
+    pub fn push(&mut self, key: DrawKey, draw: SortedDraw) {
+        self.draws.push((key, draw));
+    }
+
+    /// Number of draws recorded since the last submission.
+    pub fn len(&self) -> usize {
+        self.draws.len()
+    }
+
+    /// Check if there are no draws recorded since the last submission.
+    pub fn is_empty(&self) -> bool {
+        self.draws.is_empty()
+    }
+
+    /// Sort the recorded draws and issue them into the encoder.
+    ///
+    /// Draws with the same key keep their recording order. The state bound by the
+    /// encoder before is assumed unknown, and the draws are cleared afterwards.
+    pub fn submit(&mut self, encoder: &mut RenderSubpassCommon<B>) -> CoalescingStatistics {
+        self.draws.sort_by_key(|&(key, _)| key);
+
+        let mut coalescer = DrawCoalescer::new(encoder);
+        let mut vertex_buffer = None;
+        let mut index_buffer = None;
+        let mut skipped_buffers = 0;
+        for (_, draw) in self.draws.drain(..) {
+            let (layout, primitive) = self.pipeline_infos[draw.pipeline.0 as usize];
+            coalescer.bind_graphics_pipeline(self.pipelines[draw.pipeline.0 as usize], primitive);
+            if let Some((set, id)) = draw.descriptor_set {
+                coalescer.bind_graphics_descriptor_set(
+                    layout,
+                    set,
+                    self.descriptor_sets[id.0 as usize],
+                    &[],
+                );
+            }
+            if let Some(binding) = draw.vertex_buffer {
+                if vertex_buffer == Some(binding) {
+                    skipped_buffers += 1;
+                } else {
+                    let (id, offset) = binding;
+                    coalescer
+                        .encoder()
+                        .bind_vertex_buffers(0, Some((self.buffers[id.0 as usize], offset)));
+                    vertex_buffer = Some(binding);
+                }
+            }
+            match draw.call {
+                SortedDrawCall::Vertices { vertices, instances } => {
+                    coalescer.draw(vertices, instances);
+                }
+                SortedDrawCall::Indexed { buffer, offset, index_type, indices, base_vertex, instances } => {
+                    let binding = (buffer, offset, index_type);
+                    if index_buffer == Some(binding) {
+                        skipped_buffers += 1;
+                    } else {
+                        coalescer.encoder().bind_index_buffer(buffer::IndexBufferView {
+                            buffer: self.buffers[buffer.0 as usize],
+                            offset,
+                            index_type,
+                        });
+                        index_buffer = Some(binding);
+                    }
+                    coalescer.draw_indexed(indices, base_vertex, instances);
+                }
+            }
+        }
+
+        let mut statistics = coalescer.finish();
+        statistics.skipped_state_changes += skipped_buffers;
+        statistics
+    }
+}
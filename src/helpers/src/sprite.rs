@@ -0,0 +1,540 @@
+//! Batched rendering of textured 2D quads.
+//!
+//! `SpriteBatch` collects quads in pixel coordinates, e.g. for debug HUDs or the output
+//! of immediate mode UI libraries, and draws them with a single pipeline. The vertices are
+//! written into a persistently mapped ring buffer, split into one region per frame in flight,
+//! and indexed through a static quad index buffer. Consecutive quads sharing a texture slot
+//! and scissor rectangle are merged into a single draw call.
+//!
+//! Textures are registered as descriptor sets allocated with `SpriteBatch::descriptor_set_layout`,
+//! holding a sampled image at binding 0 and a sampler at binding 1. Sprites address them in
+//! texels, so regions of a texture atlas can be drawn without computing coordinates by hand.
+//! `SPRITE_VERTEX_SHADER` and `SPRITE_FRAGMENT_SHADER` are matching shaders in GLSL, to be
+//! compiled to SPIR-V by the application.
+//!
+//! ```ignore
+//! let mut batch = SpriteBatch::new(&device, &physical_device, frames_in_flight, 4096);
+//! batch.create_pipeline(&device, &vs_module, &fs_module, subpass)?;
+//! let atlas = batch.add_texture(atlas_set, 1024, 1024);
+//!
+//! loop {
+//!     batch.begin_frame();
+//!     batch.sprite(atlas, glyph_rect, [x, y], [w, h], [0xFF; 4]);
+//!     batch.push_scissor(panel_rect);
+//!     // draw the panel contents
+//!     batch.pop_scissor();
+//!
+//!     let mut encoder = cmd_buffer.begin_render_pass_inline(&render_pass, &framebuffer, area, &[]);
+//!     batch.draw(&device, &mut encoder, extent);
+//! }
+//! ```
+
+use std::{fmt, mem, ptr};
+
+use hal::adapter::PhysicalDevice;
+use hal::buffer::{self, IndexBufferView};
+use hal::command::RenderSubpassCommon;
+use hal::device::Device;
+use hal::format::Format;
+use hal::memory::Properties;
+use hal::pass::Subpass;
+use hal::pso::{
+    AttributeDesc, BlendState, ColorBlendDesc, ColorMask, CreationError, DescriptorSetLayoutBinding,
+    DescriptorType, Element, EntryPoint, GraphicsPipelineDesc, GraphicsShaderSet, Rasterizer, Rect,
+    ShaderStageFlags, VertexBufferDesc, Viewport,
+};
+use hal::window::Extent2D;
+use hal::{Backend, IndexType, Primitive};
+
+/// Maximum number of quads drawn by a single indexed draw call, limited by 16-bit indices.
+pub const MAX_QUADS_PER_DRAW: usize = 0x4000;
+
+/// GLSL source of a vertex shader matching the sprite vertex layout and push constants.
+pub const SPRITE_VERTEX_SHADER: &str = "#version 450
+layout(push_constant) uniform Transform {
+    vec2 scale;
+    vec2 translate;
+};
+layout(location = 0) in vec2 a_position;
+layout(location = 1) in vec2 a_uv;
+layout(location = 2) in vec4 a_color;
+layout(location = 0) out vec2 v_uv;
+layout(location = 1) out vec4 v_color;
+out gl_PerVertex {
+    vec4 gl_Position;
+};
+void main() {
+    v_uv = a_uv;
+    v_color = a_color;
+    gl_Position = vec4(a_position * scale + translate, 0.0, 1.0);
+}
+";
+
+/// GLSL source of a fragment shader matching the sprite descriptor set layout.
+pub const SPRITE_FRAGMENT_SHADER: &str = "#version 450
+layout(set = 0, binding = 0) uniform texture2D u_texture;
+layout(set = 0, binding = 1) uniform sampler u_sampler;
+layout(location = 0) in vec2 v_uv;
+layout(location = 1) in vec4 v_color;
+layout(location = 0) out vec4 o_color;
+void main() {
+    o_color = texture(sampler2D(u_texture, u_sampler), v_uv) * v_color;
+}
+";
+
+/// Vertex of a sprite quad.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SpriteVertex {
+    /// Position in pixels, relative to the top left corner of the render target.
+    pub position: [f32; 2],
+    /// Normalized texture coordinates.
+    pub uv: [f32; 2],
+    /// Color multiplied with the texture sample.
+    pub color: [u8; 4],
+}
+
+/// Identifier of a texture registered to a `SpriteBatch`.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub struct TextureSlot(usize);
+
+// Helpers shared with the `imgui` renderer, which draws vertices of the same layout.
+
+/// Descriptor set layout with a sampled image and a sampler, and a pipeline layout
+/// with the vertex transform in push constants.
+pub(crate) fn create_layouts<B: Backend>(device: &B::Device) -> (B::DescriptorSetLayout, B::PipelineLayout) {
+    let set_layout = device.create_descriptor_set_layout(
+        &[
+            DescriptorSetLayoutBinding {
+                binding: 0,
+                ty: DescriptorType::SampledImage,
+                count: 1,
+                stage_flags: ShaderStageFlags::FRAGMENT,
+                immutable_samplers: false,
+            },
+            DescriptorSetLayoutBinding {
+                binding: 1,
+                ty: DescriptorType::Sampler,
+                count: 1,
+                stage_flags: ShaderStageFlags::FRAGMENT,
+                immutable_samplers: false,
+            },
+        ],
+        &[],
+    );
+    let pipeline_layout = device.create_pipeline_layout(
+        Some(&set_layout),
+        &[(ShaderStageFlags::VERTEX, 0 .. 4)],
+    );
+    (set_layout, pipeline_layout)
+}
+
+/// Alpha blended pipeline drawing `SpriteVertex` triangles.
+pub(crate) fn create_pipeline<B: Backend>(
+    device: &B::Device,
+    layout: &B::PipelineLayout,
+    vertex: &B::ShaderModule,
+    fragment: &B::ShaderModule,
+    subpass: Subpass<B>,
+) -> Result<B::GraphicsPipeline, CreationError> {
+    let shaders = GraphicsShaderSet {
+        vertex: EntryPoint { entry: "main", module: vertex, specialization: &[] },
+        hull: None,
+        domain: None,
+        geometry: None,
+        fragment: Some(EntryPoint { entry: "main", module: fragment, specialization: &[] }),
+    };
+    let mut desc = GraphicsPipelineDesc::new(
+        shaders,
+        Primitive::TriangleList,
+        Rasterizer::FILL,
+        layout,
+        subpass,
+    );
+    desc.blender.targets.push(ColorBlendDesc(ColorMask::ALL, BlendState::ALPHA));
+    desc.vertex_buffers.push(VertexBufferDesc {
+        binding: 0,
+        stride: mem::size_of::<SpriteVertex>() as u32,
+        rate: 0,
+    });
+    let attributes = [(Format::Rg32Float, 0), (Format::Rg32Float, 8), (Format::Rgba8Unorm, 16)];
+    for (location, &(format, offset)) in attributes.iter().enumerate() {
+        desc.attributes.push(AttributeDesc {
+            location: location as u32,
+            binding: 0,
+            element: Element { format, offset },
+        });
+    }
+    device.create_graphics_pipeline(&desc)
+}
+
+/// Push constants mapping pixel coordinates relative to `origin` to normalized device coordinates.
+pub(crate) fn transform(origin: [f32; 2], size: [f32; 2]) -> [u32; 4] {
+    let scale = [2.0 / size[0], 2.0 / size[1]];
+    [
+        scale[0].to_bits(),
+        scale[1].to_bits(),
+        (-1.0 - origin[0] * scale[0]).to_bits(),
+        (-1.0 - origin[1] * scale[1]).to_bits(),
+    ]
+}
+
+struct Texture<B: Backend> {
+    set: B::DescriptorSet,
+    width: u32,
+    height: u32,
+}
+
+/// Quads sharing a texture slot and scissor, drawn together.
+#[derive(Debug)]
+struct DrawCall {
+    slot: TextureSlot,
+    // `None` covers the whole render target.
+    scissor: Option<Rect>,
+    first_quad: usize,
+    quad_count: usize,
+}
+
+/// Batches textured quads into few draw calls.
+pub struct SpriteBatch<B: Backend> {
+    buffer: B::Buffer,
+    memory: B::Memory,
+    mapping: *mut u8,
+    coherent: bool,
+    atom_size: u64,
+    // Offset and size of the vertex region of each frame in flight, after the indices.
+    vertex_offset: u64,
+    region_size: u64,
+    max_quads: usize,
+    frame: usize,
+    frames_in_flight: usize,
+    set_layout: B::DescriptorSetLayout,
+    pipeline_layout: B::PipelineLayout,
+    pipeline: Option<B::GraphicsPipeline>,
+    textures: Vec<Option<Texture<B>>>,
+    free_slots: Vec<usize>,
+    vertices: Vec<SpriteVertex>,
+    calls: Vec<DrawCall>,
+    scissors: Vec<Rect>,
+}
+
+impl<B: Backend> fmt::Debug for SpriteBatch<B> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("SpriteBatch")
+            .field("max_quads", &self.max_quads)
+            .field("frames_in_flight", &self.frames_in_flight)
+            .field("quads", &self.quad_count())
+            .field("draw_calls", &self.calls.len())
+            .finish()
+    }
+}
+
+impl<B: Backend> SpriteBatch<B> {
+    /// Create a sprite batch holding up to `max_quads` quads per frame.
+    ///
+    /// The vertices of a frame are only overwritten `frames_in_flight` frames later, the
+    /// application has to wait for the command buffers of that frame to complete first.
+    pub fn new(
+        device: &B::Device,
+        physical_device: &B::PhysicalDevice,
+        frames_in_flight: usize,
+        max_quads: usize,
+    ) -> Self {
+        assert!(frames_in_flight > 0 && max_quads > 0);
+        let memory_properties = physical_device.memory_properties();
+        let atom_size = (physical_device.limits().non_coherent_atom_size as u64).max(4);
+        let align = |offset: u64| (offset + atom_size - 1) / atom_size * atom_size;
+
+        let quads_per_draw = max_quads.min(MAX_QUADS_PER_DRAW);
+        let index_size = (quads_per_draw * 6 * mem::size_of::<u16>()) as u64;
+        let vertex_offset = align(index_size);
+        let region_size = align((max_quads * 4 * mem::size_of::<SpriteVertex>()) as u64);
+        let size = vertex_offset + region_size * frames_in_flight as u64;
+
+        let buffer = device
+            .create_buffer(size, buffer::Usage::VERTEX | buffer::Usage::INDEX)
+            .expect("Unable to create the sprite buffer");
+        let requirements = device.get_buffer_requirements(&buffer);
+        let memory_type = memory_properties
+            .upload_type(requirements.type_mask)
+            .expect("No host visible memory type");
+        let coherent = memory_properties.memory_types[memory_type.0]
+            .properties
+            .contains(Properties::COHERENT);
+        let memory = device
+            .allocate_memory(memory_type, requirements.size)
+            .expect("Unable to allocate the sprite buffer");
+        let buffer = device.bind_buffer_memory(&memory, 0, buffer).unwrap();
+        let mapping = device
+            .map_memory(&memory, 0 .. size)
+            .expect("Unable to map the sprite buffer");
+
+        // Two triangles per quad, written once and shared by all frames.
+        let indices = (0 .. quads_per_draw as u16)
+            .flat_map(|quad| {
+                let base = quad * 4;
+                vec![base, base + 1, base + 2, base, base + 2, base + 3]
+            })
+            .collect::<Vec<_>>();
+        unsafe {
+            ptr::copy_nonoverlapping(indices.as_ptr() as *const u8, mapping, index_size as usize);
+        }
+        if !coherent {
+            device.flush_mapped_memory_ranges(Some((&memory, 0 .. vertex_offset)));
+        }
+
+        let (set_layout, pipeline_layout) = create_layouts::<B>(device);
+
+        SpriteBatch {
+            buffer,
+            memory,
+            mapping,
+            coherent,
+            atom_size,
+            vertex_offset,
+            region_size,
+            max_quads,
+            frame: 0,
+            frames_in_flight,
+            set_layout,
+            pipeline_layout,
+            pipeline: None,
+            textures: Vec::new(),
+            free_slots: Vec::new(),
+            vertices: Vec::with_capacity(max_quads * 4),
+            calls: Vec::new(),
+            scissors: Vec::new(),
+        }
+    }
+
+    /// Layout of the descriptor sets registered as textures.
+    pub fn descriptor_set_layout(&self) -> &B::DescriptorSetLayout {
+        &self.set_layout
+    }
+
+    /// Layout of the sprite pipeline, with the vertex transform in push constants.
+    pub fn pipeline_layout(&self) -> &B::PipelineLayout {
+        &self.pipeline_layout
+    }
+
+    /// Create the sprite pipeline for the given subpass, replacing the previous one.
+    ///
+    /// The shaders have to match `SPRITE_VERTEX_SHADER` and `SPRITE_FRAGMENT_SHADER`.
+    /// Quads are alpha blended into the first color attachment.
+    pub fn create_pipeline(
+        &mut self,
+        device: &B::Device,
+        vertex: &B::ShaderModule,
+        fragment: &B::ShaderModule,
+        subpass: Subpass<B>,
+    ) -> Result<(), CreationError> {
+        let pipeline = create_pipeline::<B>(device, &self.pipeline_layout, vertex, fragment, subpass)?;
+        if let Some(old) = mem::replace(&mut self.pipeline, Some(pipeline)) {
+            device.destroy_graphics_pipeline(old);
+        }
+        Ok(())
+    }
+
+    /// Register a texture, bound through a descriptor set of `descriptor_set_layout`.
+    ///
+    /// `width` and `height` are the size of the texture in texels, used to normalize
+    /// the source rectangles of sprites.
+    pub fn add_texture(&mut self, set: B::DescriptorSet, width: u32, height: u32) -> TextureSlot {
+        let texture = Some(Texture { set, width, height });
+        match self.free_slots.pop() {
+            Some(index) => {
+                self.textures[index] = texture;
+                TextureSlot(index)
+            }
+            None => {
+                self.textures.push(texture);
+                TextureSlot(self.textures.len() - 1)
+            }
+        }
+    }
+
+    /// Unregister a texture, returning its descriptor set.
+    ///
+    /// The descriptor set may still be used by the frames in flight.
+    pub fn remove_texture(&mut self, slot: TextureSlot) -> B::DescriptorSet {
+        let texture = self.textures[slot.0].take().expect("Texture slot is empty");
+        self.free_slots.push(slot.0);
+        texture.set
+    }
+
+    /// Start recording the quads of the next frame, reusing the vertex region
+    /// written `frames_in_flight` frames ago.
+    pub fn begin_frame(&mut self) {
+        self.frame = (self.frame + 1) % self.frames_in_flight;
+        self.vertices.clear();
+        self.calls.clear();
+        self.scissors.clear();
+    }
+
+    /// Number of quads recorded in the current frame.
+    pub fn quad_count(&self) -> usize {
+        self.vertices.len() / 4
+    }
+
+    /// Restrict the following quads to a rectangle in pixels, intersected with the current scissor.
+    pub fn push_scissor(&mut self, rect: Rect) {
+        let rect = match self.scissors.last() {
+            Some(top) => {
+                let x = rect.x.max(top.x);
+                let y = rect.y.max(top.y);
+                let right = (rect.x + rect.w).min(top.x + top.w);
+                let bottom = (rect.y + rect.h).min(top.y + top.h);
+                Rect { x, y, w: (right - x).max(0), h: (bottom - y).max(0) }
+            }
+            None => rect,
+        };
+        self.scissors.push(rect);
+    }
+
+    /// Restore the scissor active before the last `push_scissor`.
+    pub fn pop_scissor(&mut self) {
+        self.scissors.pop().expect("Scissor stack is empty");
+    }
+
+    /// Add a quad with explicit vertices, in the order top left, top right, bottom right
+    /// and bottom left.
+    ///
+    /// Returns `false` if the batch is full and the quad was dropped.
+    pub fn quad(&mut self, slot: TextureSlot, vertices: [SpriteVertex; 4]) -> bool {
+        if self.quad_count() == self.max_quads {
+            return false;
+        }
+        let scissor = self.scissors.last().cloned();
+        if scissor.map_or(false, |rect| rect.w == 0 || rect.h == 0) {
+            // Fully clipped, drawing it would only cost fill rate.
+            return true;
+        }
+
+        let first_quad = self.quad_count();
+        self.vertices.extend_from_slice(&vertices);
+        match self.calls.last_mut() {
+            Some(ref mut call) if call.slot == slot && call.scissor == scissor => {
+                call.quad_count += 1;
+                return true;
+            }
+            _ => {}
+        }
+        self.calls.push(DrawCall {
+            slot,
+            scissor,
+            first_quad,
+            quad_count: 1,
+        });
+        true
+    }
+
+    /// Add an axis aligned sprite, drawing the `source` rectangle of a texture in texels
+    /// at `position` with the given `size` in pixels.
+    ///
+    /// Returns `false` if the batch is full and the sprite was dropped.
+    pub fn sprite(
+        &mut self,
+        slot: TextureSlot,
+        source: Rect,
+        position: [f32; 2],
+        size: [f32; 2],
+        color: [u8; 4],
+    ) -> bool {
+        let (u0, v0, u1, v1) = {
+            let texture = self.textures[slot.0].as_ref().expect("Texture slot is empty");
+            let (width, height) = (texture.width as f32, texture.height as f32);
+            (
+                source.x as f32 / width,
+                source.y as f32 / height,
+                (source.x + source.w) as f32 / width,
+                (source.y + source.h) as f32 / height,
+            )
+        };
+        let (x0, y0) = (position[0], position[1]);
+        let (x1, y1) = (x0 + size[0], y0 + size[1]);
+        self.quad(slot, [
+            SpriteVertex { position: [x0, y0], uv: [u0, v0], color },
+            SpriteVertex { position: [x1, y0], uv: [u1, v0], color },
+            SpriteVertex { position: [x1, y1], uv: [u1, v1], color },
+            SpriteVertex { position: [x0, y1], uv: [u0, v1], color },
+        ])
+    }
+
+    /// Upload the quads of the current frame and record their draw calls.
+    ///
+    /// Sets the viewport to cover the whole render target of the given extent, as well as
+    /// the scissor rectangles. Panics if `create_pipeline` wasn't called.
+    pub fn draw(&mut self, device: &B::Device, encoder: &mut RenderSubpassCommon<B>, extent: Extent2D) {
+        if self.calls.is_empty() {
+            return;
+        }
+        let pipeline = self.pipeline.as_ref().expect("Sprite pipeline wasn't created");
+
+        let offset = self.vertex_offset + self.region_size * self.frame as u64;
+        let size = (self.vertices.len() * mem::size_of::<SpriteVertex>()) as u64;
+        unsafe {
+            ptr::copy_nonoverlapping(
+                self.vertices.as_ptr() as *const u8,
+                self.mapping.offset(offset as isize),
+                size as usize,
+            );
+        }
+        if !self.coherent {
+            let end = (offset + size + self.atom_size - 1) / self.atom_size * self.atom_size;
+            device.flush_mapped_memory_ranges(Some((&self.memory, offset .. end)));
+        }
+
+        let full = Rect {
+            x: 0,
+            y: 0,
+            w: extent.width as i16,
+            h: extent.height as i16,
+        };
+        let constants = transform([0.0, 0.0], [extent.width as f32, extent.height as f32]);
+
+        encoder.bind_graphics_pipeline(pipeline);
+        encoder.set_viewports(0, &[Viewport { rect: full, depth: 0.0 .. 1.0 }]);
+        encoder.push_graphics_constants(&self.pipeline_layout, ShaderStageFlags::VERTEX, 0, &constants);
+        encoder.bind_index_buffer(IndexBufferView {
+            buffer: &self.buffer,
+            offset: 0,
+            index_type: IndexType::U16,
+        });
+        encoder.bind_vertex_buffers(0, Some((&self.buffer, offset)));
+
+        let mut bound = (None, None);
+        for call in &self.calls {
+            if bound.0 != Some(call.slot) {
+                let texture = self.textures[call.slot.0].as_ref().expect("Texture slot is empty");
+                encoder.bind_graphics_descriptor_sets(&self.pipeline_layout, 0, Some(&texture.set), &[]);
+                bound.0 = Some(call.slot);
+            }
+            let scissor = call.scissor.unwrap_or(full);
+            if bound.1 != Some(scissor) {
+                encoder.set_scissors(0, &[scissor]);
+                bound.1 = Some(scissor);
+            }
+            let mut first = call.first_quad;
+            let end = call.first_quad + call.quad_count;
+            while first < end {
+                let count = (end - first).min(MAX_QUADS_PER_DRAW);
+                encoder.draw_indexed(0 .. count as u32 * 6, first as i32 * 4, 0 .. 1);
+                first += count;
+            }
+        }
+    }
+
+    /// Destroy the buffer, layouts and pipeline.
+    ///
+    /// The descriptor sets of the registered textures are owned by their pool.
+    pub fn destroy(self, device: &B::Device) {
+        device.unmap_memory(&self.memory);
+        device.destroy_buffer(self.buffer);
+        device.free_memory(self.memory);
+        if let Some(pipeline) = self.pipeline {
+            device.destroy_graphics_pipeline(pipeline);
+        }
+        device.destroy_pipeline_layout(self.pipeline_layout);
+        device.destroy_descriptor_set_layout(self.set_layout);
+    }
+}
@@ -0,0 +1,538 @@
+//! Budget-aware texture streaming.
+//!
+//! `TextureStreamer` keeps the full mip chains of 2D textures in host memory and only
+//! makes the mips the application requested resident on the device, as long as they fit
+//! into a memory budget. When the requests exceed the budget, the most detailed mips of
+//! the least recently requested textures are evicted first. The smallest mip of a texture
+//! is never evicted.
+//!
+//! Without sparse residency, changing the resident mips of a texture creates a new image
+//! holding exactly these mips and uploads them through a transfer queue. The previous image
+//! is retired and destroyed once the frames which may still sample it have completed.
+//! Image views therefore change with the residency, `update` returns the textures whose
+//! views have to be written into descriptor sets again.
+//!
+//! ```ignore
+//! let texture = streamer.add_texture(width, height, format, mips);
+//! loop {
+//!     streamer.request(texture, level_for_screen_size);
+//!     for texture in streamer.update(&device, &mut queue) {
+//!         // rewrite descriptors using `streamer.view(texture)`
+//!     }
+//!     // render the frame
+//! }
+//! ```
+
+use std::collections::VecDeque;
+use std::{fmt, mem};
+
+use hal::adapter::{MemoryType, MemoryTypeId, PhysicalDevice};
+use hal::command::{BufferImageCopy, OneShot};
+use hal::device::Device;
+use hal::format::{Aspects, Format, Swizzle};
+use hal::memory::{Barrier, Dependencies, Properties};
+use hal::pool::{CommandPool, CommandPoolCreateFlags};
+use hal::pso::PipelineStage;
+use hal::queue::{CommandQueue, QueueGroup, Submission, Supports, Transfer};
+use hal::{buffer, image, Backend};
+
+/// Identifier of a texture registered to a `TextureStreamer`.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub struct TextureId(usize);
+
+/// Device image holding the mips `base_level ..` of a texture.
+struct Residency<B: Backend> {
+    image: B::Image,
+    memory: B::Memory,
+    view: B::ImageView,
+    base_level: image::Level,
+    size: u64,
+}
+
+impl<B: Backend> Residency<B> {
+    fn destroy(self, device: &B::Device) {
+        device.destroy_image_view(self.view);
+        device.destroy_image(self.image);
+        device.free_memory(self.memory);
+    }
+}
+
+struct Texture<B: Backend> {
+    width: u32,
+    height: u32,
+    format: Format,
+    // Mip data, most detailed level first, rows tightly packed.
+    mips: Vec<Vec<u8>>,
+    resident: Option<Residency<B>>,
+    // Residency being uploaded, replacing `resident` once the upload completed.
+    pending: Option<Residency<B>>,
+    requested: image::Level,
+    last_request: u64,
+}
+
+impl<B: Backend> Texture<B> {
+    fn smallest_level(&self) -> image::Level {
+        (self.mips.len() - 1) as image::Level
+    }
+
+    // Size of the mips `base_level ..`, used to account for the budget.
+    fn size(&self, base_level: image::Level) -> u64 {
+        self.mips[base_level as usize ..]
+            .iter()
+            .map(|mip| mip.len() as u64)
+            .sum()
+    }
+
+    fn resident_level(&self) -> Option<image::Level> {
+        self.resident.as_ref().map(|r| r.base_level)
+    }
+}
+
+/// Layout of an image region in a staging buffer, returns the row pitch in bytes and
+/// in texels, and the number of rows of texel blocks.
+pub(crate) fn staging_layout(format: Format, width: u32, height: u32, pitch_alignment: u64) -> (u64, u32, u32) {
+    let desc = format.surface_desc();
+    let (block_width, block_height) = (desc.dim.0 as u32, desc.dim.1 as u32);
+    let block_size = (desc.bits / 8) as u64;
+    let row_size = ((width + block_width - 1) / block_width) as u64 * block_size;
+    let pitch = (row_size + pitch_alignment - 1) / pitch_alignment * pitch_alignment;
+    let rows = (height + block_height - 1) / block_height;
+    (pitch, (pitch / block_size) as u32 * block_width, rows)
+}
+
+/// Staging buffer of the upload in flight.
+struct Upload<B: Backend> {
+    buffer: B::Buffer,
+    memory: B::Memory,
+    textures: Vec<TextureId>,
+}
+
+/// Streams mips of textures in and out of device memory within a budget.
+pub struct TextureStreamer<B: Backend> {
+    budget: u64,
+    memory_types: Vec<MemoryType>,
+    copy_offset_alignment: u64,
+    copy_pitch_alignment: u64,
+    textures: Vec<Option<Texture<B>>>,
+    free_ids: Vec<usize>,
+    command_pool: CommandPool<B, Transfer>,
+    fence: B::Fence,
+    upload: Option<Upload<B>>,
+    // Residencies replaced on the given frame, destroyed after `frames_in_flight` frames.
+    retired: VecDeque<(u64, Residency<B>)>,
+    frame: u64,
+    frames_in_flight: u64,
+}
+
+impl<B: Backend> fmt::Debug for TextureStreamer<B> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("TextureStreamer")
+            .field("budget", &self.budget)
+            .field("used", &self.used())
+            .field("textures", &(self.textures.len() - self.free_ids.len()))
+            .field("frame", &self.frame)
+            .finish()
+    }
+}
+
+impl<B: Backend> TextureStreamer<B> {
+    /// Create a streamer keeping at most `budget` bytes of mips resident.
+    ///
+    /// Uploads are recorded into a command pool of the given queue group, which
+    /// should be the family of the queues sampling the textures. A replaced image
+    /// is destroyed once `update` has been called `frames_in_flight` more times.
+    pub fn new<C: Supports<Transfer>>(
+        device: &B::Device,
+        physical_device: &B::PhysicalDevice,
+        group: &QueueGroup<B, C>,
+        budget: u64,
+        frames_in_flight: usize,
+    ) -> Self {
+        let limits = physical_device.limits();
+        let raw_pool = device.create_command_pool(group.family(), CommandPoolCreateFlags::TRANSIENT);
+
+        TextureStreamer {
+            budget,
+            memory_types: physical_device.memory_properties().memory_types,
+            copy_offset_alignment: limits.min_buffer_copy_offset_alignment.max(1),
+            copy_pitch_alignment: limits.min_buffer_copy_pitch_alignment.max(1),
+            textures: Vec::new(),
+            free_ids: Vec::new(),
+            // Every queue family supports transfer operations.
+            command_pool: unsafe { CommandPool::new(raw_pool) },
+            fence: device.create_fence(false),
+            upload: None,
+            retired: VecDeque::new(),
+            frame: 0,
+            frames_in_flight: frames_in_flight as u64,
+        }
+    }
+
+    /// Returns the memory budget in bytes.
+    pub fn budget(&self) -> u64 {
+        self.budget
+    }
+
+    /// Change the memory budget, e.g. when the application is notified about
+    /// memory pressure. Takes effect on the next `update`.
+    pub fn set_budget(&mut self, budget: u64) {
+        self.budget = budget;
+    }
+
+    /// Returns the size of the mips resident or being uploaded, in bytes.
+    ///
+    /// Retired images waiting for destruction aren't accounted for.
+    pub fn used(&self) -> u64 {
+        self.textures
+            .iter()
+            .filter_map(|t| t.as_ref())
+            .flat_map(|t| t.pending.as_ref().or(t.resident.as_ref()))
+            .map(|r| r.size)
+            .sum()
+    }
+
+    /// Register a 2D texture with its full mip chain, most detailed level first.
+    ///
+    /// Rows of the mip data are tightly packed. Initially only the smallest mip is
+    /// requested, nothing is resident before the next `update`.
+    pub fn add_texture(&mut self, width: u32, height: u32, format: Format, mips: Vec<Vec<u8>>) -> TextureId {
+        assert!(!mips.is_empty() && mips.len() <= image::MAX_LEVEL as usize + 1);
+        let texture = Texture {
+            width,
+            height,
+            format,
+            requested: (mips.len() - 1) as image::Level,
+            mips,
+            resident: None,
+            pending: None,
+            last_request: self.frame,
+        };
+
+        match self.free_ids.pop() {
+            Some(index) => {
+                self.textures[index] = Some(texture);
+                TextureId(index)
+            }
+            None => {
+                self.textures.push(Some(texture));
+                TextureId(self.textures.len() - 1)
+            }
+        }
+    }
+
+    /// Unregister a texture. Its images are destroyed once no frame uses them anymore.
+    pub fn remove_texture(&mut self, id: TextureId) {
+        let texture = self.textures[id.0].take().expect("Texture was already removed");
+        for residency in texture.resident.into_iter().chain(texture.pending) {
+            self.retired.push_back((self.frame, residency));
+        }
+        if let Some(ref mut upload) = self.upload {
+            upload.textures.retain(|&t| t != id);
+        }
+        self.free_ids.push(id.0);
+    }
+
+    /// Request the mips of a texture starting at `level` to be resident.
+    ///
+    /// Textures requested recently are the last to be evicted when over budget.
+    pub fn request(&mut self, id: TextureId, level: image::Level) {
+        let frame = self.frame;
+        let texture = self.textures[id.0].as_mut().expect("Texture was removed");
+        texture.requested = level.min(texture.smallest_level());
+        texture.last_request = frame;
+    }
+
+    /// Returns the most detailed resident level of a texture, if any.
+    pub fn resident_level(&self, id: TextureId) -> Option<image::Level> {
+        self.textures[id.0].as_ref().and_then(|t| t.resident_level())
+    }
+
+    /// Returns the view of the resident mips of a texture, if any.
+    ///
+    /// The mip level 0 of the view corresponds to `resident_level` of the texture.
+    pub fn view(&self, id: TextureId) -> Option<&B::ImageView> {
+        self.textures[id.0]
+            .as_ref()
+            .and_then(|t| t.resident.as_ref())
+            .map(|r| &r.view)
+    }
+
+    /// Advance to the next frame, completing the previous upload and starting the next one.
+    ///
+    /// Returns the textures whose view changed. Only a single upload is in flight at a
+    /// time, if it hasn't completed yet nothing changes.
+    pub fn update<C: Supports<Transfer>>(
+        &mut self,
+        device: &B::Device,
+        queue: &mut CommandQueue<B, C>,
+    ) -> Vec<TextureId> {
+        self.frame += 1;
+        let frame = self.frame;
+
+        let mut changed = Vec::new();
+        if let Some(upload) = self.upload.take() {
+            if !device.get_fence_status(&self.fence) {
+                self.upload = Some(upload);
+                return changed;
+            }
+            device.reset_fence(&self.fence);
+            self.command_pool.reset();
+            device.destroy_buffer(upload.buffer);
+            device.free_memory(upload.memory);
+
+            for id in upload.textures {
+                let texture = self.textures[id.0].as_mut().unwrap();
+                let pending = texture.pending.take().unwrap();
+                if let Some(old) = mem::replace(&mut texture.resident, Some(pending)) {
+                    self.retired.push_back((frame, old));
+                }
+                changed.push(id);
+            }
+        }
+
+        // Images of removed textures may still have been written by the upload,
+        // which has completed at this point.
+        let frames_in_flight = self.frames_in_flight;
+        while self.retired.front().map_or(false, |&(retired, _)| retired + frames_in_flight <= frame) {
+            self.retired.pop_front().unwrap().1.destroy(device);
+        }
+
+        let targets = self.plan();
+        let textures = self.textures
+            .iter()
+            .enumerate()
+            .filter_map(|(index, texture)| {
+                let texture = texture.as_ref()?;
+                let target = targets[index]?;
+                if texture.resident_level() == Some(target) {
+                    None
+                } else {
+                    Some((TextureId(index), target))
+                }
+            })
+            .collect::<Vec<_>>();
+        if !textures.is_empty() {
+            self.upload = Some(self.start_upload(device, queue, textures));
+        }
+
+        changed
+    }
+
+    // Choose the most detailed level to keep resident for every texture,
+    // evicting mips of the least recently requested textures to fit the budget.
+    fn plan(&self) -> Vec<Option<image::Level>> {
+        let mut targets = self.textures
+            .iter()
+            .map(|t| t.as_ref().map(|t| t.requested))
+            .collect::<Vec<_>>();
+        let mut total: u64 = self.textures
+            .iter()
+            .filter_map(|t| t.as_ref())
+            .map(|t| t.size(t.requested))
+            .sum();
+
+        let mut lru = self.textures
+            .iter()
+            .enumerate()
+            .filter_map(|(index, t)| t.as_ref().map(|t| (t.last_request, index)))
+            .collect::<Vec<_>>();
+        lru.sort();
+
+        for (_, index) in lru {
+            if total <= self.budget {
+                break;
+            }
+            let texture = self.textures[index].as_ref().unwrap();
+            let mut level = targets[index].unwrap();
+            while total > self.budget && level < texture.smallest_level() {
+                total -= texture.size(level) - texture.size(level + 1);
+                level += 1;
+            }
+            targets[index] = Some(level);
+        }
+
+        targets
+    }
+
+    fn find_memory(memory_types: &[MemoryType], type_mask: u64, properties: Properties) -> MemoryTypeId {
+        memory_types
+            .iter()
+            .enumerate()
+            .position(|(id, ty)| type_mask & (1 << id) != 0 && ty.properties.contains(properties))
+            .expect("No suitable memory type")
+            .into()
+    }
+
+    fn start_upload<C: Supports<Transfer>>(
+        &mut self,
+        device: &B::Device,
+        queue: &mut CommandQueue<B, C>,
+        textures: Vec<(TextureId, image::Level)>,
+    ) -> Upload<B> {
+        let alignment = self.copy_offset_alignment;
+        let align = |offset: u64| (offset + alignment - 1) / alignment * alignment;
+
+        // Copies of all the mips, in the order of `textures`.
+        let mut copies = Vec::new();
+        let mut staging_size = 0;
+        for &(id, target) in &textures {
+            let texture = self.textures[id.0].as_ref().unwrap();
+            for level in target .. texture.smallest_level() + 1 {
+                let width = (texture.width >> level).max(1);
+                let height = (texture.height >> level).max(1);
+                let (pitch, buffer_width, rows) =
+                    staging_layout(texture.format, width, height, self.copy_pitch_alignment);
+                staging_size = align(staging_size);
+                copies.push((staging_size, pitch, rows, BufferImageCopy {
+                    buffer_offset: staging_size,
+                    buffer_width,
+                    buffer_height: height,
+                    image_layers: image::SubresourceLayers {
+                        aspects: Aspects::COLOR,
+                        level: level - target,
+                        layers: 0 .. 1,
+                    },
+                    image_offset: image::Offset { x: 0, y: 0, z: 0 },
+                    image_extent: image::Extent { width, height, depth: 1 },
+                }));
+                staging_size += pitch * rows as u64;
+            }
+        }
+
+        let buffer = device
+            .create_buffer(staging_size, buffer::Usage::TRANSFER_SRC)
+            .expect("Unable to create the staging buffer");
+        let requirements = device.get_buffer_requirements(&buffer);
+        let memory = device
+            .allocate_memory(Self::find_memory(&self.memory_types, requirements.type_mask, Properties::CPU_VISIBLE), requirements.size)
+            .expect("Unable to allocate the staging buffer");
+        let buffer = device.bind_buffer_memory(&memory, 0, buffer).unwrap();
+
+        {
+            let mut writer = device
+                .acquire_mapping_writer::<u8>(&memory, 0 .. staging_size)
+                .expect("Unable to map the staging buffer");
+            let mut copy = copies.iter();
+            for &(id, target) in &textures {
+                let texture = self.textures[id.0].as_ref().unwrap();
+                for mip in &texture.mips[target as usize ..] {
+                    let &(offset, pitch, rows, _) = copy.next().unwrap();
+                    let row_size = mip.len() / rows as usize;
+                    for (row, data) in mip.chunks(row_size).enumerate() {
+                        let start = (offset + row as u64 * pitch) as usize;
+                        writer[start .. start + row_size].copy_from_slice(data);
+                    }
+                }
+            }
+            device.release_mapping_writer(writer);
+        }
+
+        let submit = {
+            let mut cmd_buffer = self.command_pool.acquire_command_buffer::<OneShot>(false);
+            cmd_buffer.pipeline_barrier(
+                PipelineStage::HOST .. PipelineStage::TRANSFER,
+                Dependencies::empty(),
+                &[Barrier::Buffer {
+                    states: buffer::Access::HOST_WRITE .. buffer::Access::TRANSFER_READ,
+                    target: &buffer,
+                    families: None,
+                }],
+            );
+
+            let mut copy = copies.iter();
+            for &(id, target) in &textures {
+                let texture = self.textures[id.0].as_mut().unwrap();
+                let levels = texture.smallest_level() - target + 1;
+                let range = image::SubresourceRange {
+                    aspects: Aspects::COLOR,
+                    levels: 0 .. levels,
+                    layers: 0 .. 1,
+                };
+
+                let unbound = device
+                    .create_image(
+                        image::Kind::D2((texture.width >> target).max(1), (texture.height >> target).max(1), 1, 1),
+                        levels,
+                        texture.format,
+                        image::Tiling::Optimal,
+                        image::Usage::TRANSFER_DST | image::Usage::SAMPLED,
+                        image::StorageFlags::empty(),
+                    )
+                    .expect("Unable to create a streamed image");
+                let requirements = device.get_image_requirements(&unbound);
+                let image_memory = device
+                    .allocate_memory(Self::find_memory(&self.memory_types, requirements.type_mask, Properties::DEVICE_LOCAL), requirements.size)
+                    .expect("Unable to allocate a streamed image");
+                let image = device.bind_image_memory(&image_memory, 0, unbound).unwrap();
+                let view = device
+                    .create_image_view(&image, image::ViewKind::D2, texture.format, Swizzle::NO, range.clone())
+                    .unwrap();
+
+                cmd_buffer.pipeline_barrier(
+                    PipelineStage::TOP_OF_PIPE .. PipelineStage::TRANSFER,
+                    Dependencies::empty(),
+                    &[Barrier::Image {
+                        states: (image::Access::empty(), image::Layout::Undefined) ..
+                            (image::Access::TRANSFER_WRITE, image::Layout::TransferDstOptimal),
+                        target: &image,
+                        range: range.clone(),
+                        families: None,
+                    }],
+                );
+                let regions = copy
+                    .by_ref()
+                    .take(levels as usize)
+                    .map(|&(_, _, _, ref region)| region.clone());
+                cmd_buffer.copy_buffer_to_image(&buffer, &image, image::Layout::TransferDstOptimal, regions);
+                cmd_buffer.pipeline_barrier(
+                    PipelineStage::TRANSFER .. PipelineStage::BOTTOM_OF_PIPE,
+                    Dependencies::empty(),
+                    &[Barrier::Image {
+                        states: (image::Access::TRANSFER_WRITE, image::Layout::TransferDstOptimal) ..
+                            (image::Access::empty(), image::Layout::ShaderReadOnlyOptimal),
+                        target: &image,
+                        range,
+                        families: None,
+                    }],
+                );
+
+                texture.pending = Some(Residency {
+                    image,
+                    memory: image_memory,
+                    view,
+                    base_level: target,
+                    size: texture.size(target),
+                });
+            }
+
+            cmd_buffer.finish()
+        };
+
+        queue.submit(Submission::new().submit(Some(submit)), Some(&self.fence));
+
+        Upload {
+            buffer,
+            memory,
+            textures: textures.into_iter().map(|(id, _)| id).collect(),
+        }
+    }
+
+    /// Wait for the upload in flight and destroy the streamer with all its images.
+    pub fn destroy(mut self, device: &B::Device) {
+        if let Some(upload) = self.upload.take() {
+            device.wait_for_fence(&self.fence, !0);
+            device.destroy_buffer(upload.buffer);
+            device.free_memory(upload.memory);
+        }
+        for (_, residency) in self.retired {
+            residency.destroy(device);
+        }
+        for texture in self.textures.into_iter().filter_map(|t| t) {
+            for residency in texture.resident.into_iter().chain(texture.pending) {
+                residency.destroy(device);
+            }
+        }
+        device.destroy_fence(self.fence);
+        device.destroy_command_pool(self.command_pool.into_raw());
+    }
+}
@@ -0,0 +1,399 @@
+//! Staging belt for uploading data into buffers and images.
+//!
+//! `UploadBelt` writes the data into a ring of persistently mapped staging chunks and
+//! records the copies into the application's command buffer right away. Once the
+//! uploads of a submission are written, `finish` returns a fence which has to be
+//! signaled by that submission, the chunks are recycled by `recall` after it signaled.
+//!
+//! ```ignore
+//! loop {
+//!     belt.recall(&device);
+//!     belt.write_buffer(&device, &mut cmd_buffer, &vertex_buffer, 0, vertices);
+//!     // record the barriers and the commands using the uploaded data
+//!     let submit = cmd_buffer.finish();
+//!     let fence = belt.finish(&device);
+//!     queue.submit(Submission::new().submit(Some(submit)), Some(fence));
+//! }
+//! ```
+//!
+//! The uploads are ordered after the host writes by the submission itself, the barriers
+//! between the copies and their consumers are recorded by the application.
+//!
+//! Buffers in host visible memory, e.g. fast-upload memory on systems with resizable BAR
+//! or unified memory (see `MemoryProperties::fast_upload_type`), can be passed as a
+//! `BufferTarget::Mapped`, which `write_buffer` writes directly instead of staging.
+
+use std::collections::VecDeque;
+use std::{cmp, fmt, ptr};
+
+use hal::adapter::{MemoryProperties, PhysicalDevice};
+use hal::command::{BufferCopy, BufferImageCopy, CommandBuffer, Level, Shot};
+use hal::device::Device;
+use hal::format::Format;
+use hal::memory::Properties;
+use hal::queue::{Supports, Transfer};
+use hal::{buffer, image, Backend};
+use streaming::staging_layout;
+
+/// Persistently mapped staging chunk, written up to `offset`.
+struct Chunk<B: Backend> {
+    buffer: B::Buffer,
+    memory: B::Memory,
+    mapping: *mut u8,
+    coherent: bool,
+    size: u64,
+    offset: u64,
+}
+
+impl<B: Backend> Chunk<B> {
+    fn destroy(self, device: &B::Device) {
+        device.unmap_memory(&self.memory);
+        device.destroy_buffer(self.buffer);
+        device.free_memory(self.memory);
+    }
+}
+
+/// Destination of `UploadBelt::write_buffer`.
+pub enum BufferTarget<'a, B: Backend> {
+    /// Buffer written by a copy from the staging chunks, recorded into the command buffer.
+    Staged(&'a B::Buffer),
+    /// Buffer in host visible memory, written directly by the host without staging.
+    ///
+    /// The data is written immediately rather than when the command buffer executes, so
+    /// the written range must not be in use by the device, e.g. by having a region of the
+    /// buffer per frame in flight.
+    Mapped {
+        /// Memory bound to the buffer.
+        memory: &'a B::Memory,
+        /// Host address of the start of the buffer, in a persistent mapping of `memory`.
+        mapping: *mut u8,
+        /// Offset of the buffer in `memory`.
+        memory_offset: u64,
+        /// Size of `memory`, non-coherent flushes are expanded to the atom size within it.
+        memory_size: u64,
+        /// Whether `memory` is coherent, otherwise the writes are flushed.
+        coherent: bool,
+    },
+}
+
+impl<'a, B: Backend> From<&'a B::Buffer> for BufferTarget<'a, B> {
+    fn from(buffer: &'a B::Buffer) -> Self {
+        BufferTarget::Staged(buffer)
+    }
+}
+
+impl<'a, B: Backend> fmt::Debug for BufferTarget<'a, B> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            BufferTarget::Staged(_) => write!(f, "BufferTarget::Staged"),
+            BufferTarget::Mapped { memory_offset, coherent, .. } => f
+                .debug_struct("BufferTarget::Mapped")
+                .field("memory_offset", &memory_offset)
+                .field("coherent", &coherent)
+                .finish(),
+        }
+    }
+}
+
+/// Chunks of a finished submission, recycled once its fence signaled.
+struct InFlight<B: Backend> {
+    fence: B::Fence,
+    chunks: Vec<Chunk<B>>,
+}
+
+/// Ring of staging chunks recording buffer and image uploads into command buffers.
+pub struct UploadBelt<B: Backend> {
+    memory_properties: MemoryProperties,
+    copy_offset_alignment: u64,
+    copy_pitch_alignment: u64,
+    atom_size: u64,
+    chunk_size: u64,
+    // Chunks written since the last `finish`.
+    active: Vec<Chunk<B>>,
+    in_flight: VecDeque<InFlight<B>>,
+    free_chunks: Vec<Chunk<B>>,
+    free_fences: Vec<B::Fence>,
+}
+
+impl<B: Backend> fmt::Debug for UploadBelt<B> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("UploadBelt")
+            .field("chunk_size", &self.chunk_size)
+            .field("staging_size", &self.staging_size())
+            .field("active", &self.active.len())
+            .field("in_flight", &self.in_flight.len())
+            .finish()
+    }
+}
+
+impl<B: Backend> UploadBelt<B> {
+    /// Create a belt staging the uploads in chunks of at least `chunk_size` bytes.
+    pub fn new(physical_device: &B::PhysicalDevice, chunk_size: u64) -> Self {
+        assert!(chunk_size > 0);
+        let limits = physical_device.limits();
+
+        UploadBelt {
+            memory_properties: physical_device.memory_properties(),
+            copy_offset_alignment: limits.min_buffer_copy_offset_alignment.max(4),
+            copy_pitch_alignment: limits.min_buffer_copy_pitch_alignment.max(1),
+            atom_size: (limits.non_coherent_atom_size as u64).max(1),
+            chunk_size,
+            active: Vec::new(),
+            in_flight: VecDeque::new(),
+            free_chunks: Vec::new(),
+            free_fences: Vec::new(),
+        }
+    }
+
+    /// Returns the total size of the staging chunks in bytes, free or in use.
+    pub fn staging_size(&self) -> u64 {
+        self.active
+            .iter()
+            .chain(self.in_flight.iter().flat_map(|flight| flight.chunks.iter()))
+            .chain(self.free_chunks.iter())
+            .map(|chunk| chunk.size)
+            .sum()
+    }
+
+    fn create_chunk(&self, device: &B::Device, size: u64) -> Chunk<B> {
+        let buffer = device
+            .create_buffer(size, buffer::Usage::TRANSFER_SRC)
+            .expect("Unable to create a staging chunk");
+        let requirements = device.get_buffer_requirements(&buffer);
+        let memory_type = self.memory_properties
+            .upload_type(requirements.type_mask)
+            .expect("No host visible memory type");
+        let coherent = self.memory_properties.memory_types[memory_type.0]
+            .properties
+            .contains(Properties::COHERENT);
+        let memory = device
+            .allocate_memory(memory_type, requirements.size)
+            .expect("Unable to allocate a staging chunk");
+        let buffer = device.bind_buffer_memory(&memory, 0, buffer).unwrap();
+        let mapping = device
+            .map_memory(&memory, 0 .. size)
+            .expect("Unable to map a staging chunk");
+
+        Chunk {
+            buffer,
+            memory,
+            mapping,
+            coherent,
+            size,
+            offset: 0,
+        }
+    }
+
+    // Reserve `size` bytes in the active chunks, returns the chunk index and the offset.
+    fn allocate(&mut self, device: &B::Device, size: u64) -> (usize, u64) {
+        let alignment = self.copy_offset_alignment;
+        if let Some(chunk) = self.active.last_mut() {
+            let offset = (chunk.offset + alignment - 1) / alignment * alignment;
+            if offset + size <= chunk.size {
+                chunk.offset = offset + size;
+                return (self.active.len() - 1, offset);
+            }
+        }
+
+        let mut chunk = match self.free_chunks.iter().position(|chunk| chunk.size >= size) {
+            Some(index) => self.free_chunks.swap_remove(index),
+            None => self.create_chunk(device, cmp::max(size, self.chunk_size)),
+        };
+        chunk.offset = size;
+        self.active.push(chunk);
+        (self.active.len() - 1, 0)
+    }
+
+    /// Upload `data` into a buffer at the given offset.
+    ///
+    /// The data is staged immediately, the copy is recorded into the command buffer.
+    /// Mapped targets are written directly instead, nothing is recorded for them.
+    pub fn write_buffer<'t, C, S, L, T>(
+        &mut self,
+        device: &B::Device,
+        cmd_buffer: &mut CommandBuffer<B, C, S, L>,
+        target: T,
+        offset: buffer::Offset,
+        data: &[u8],
+    ) where
+        C: Supports<Transfer>,
+        S: Shot,
+        L: Level,
+        T: Into<BufferTarget<'t, B>>,
+        B::Buffer: 't,
+        B::Memory: 't,
+    {
+        if data.is_empty() {
+            return;
+        }
+        let size = data.len() as u64;
+        let target = match target.into() {
+            BufferTarget::Staged(buffer) => buffer,
+            BufferTarget::Mapped { memory, mapping, memory_offset, memory_size, coherent } => {
+                unsafe {
+                    ptr::copy_nonoverlapping(data.as_ptr(), mapping.offset(offset as isize), data.len());
+                }
+                if !coherent {
+                    let atom_size = self.atom_size;
+                    let start = memory_offset + offset;
+                    let range = start / atom_size * atom_size ..
+                        cmp::min((start + size + atom_size - 1) / atom_size * atom_size, memory_size);
+                    device.flush_mapped_memory_ranges(Some((memory, range)));
+                }
+                return;
+            }
+        };
+        let (index, src) = self.allocate(device, size);
+        let chunk = &self.active[index];
+        unsafe {
+            ptr::copy_nonoverlapping(data.as_ptr(), chunk.mapping.offset(src as isize), data.len());
+        }
+        cmd_buffer.copy_buffer(
+            &chunk.buffer,
+            target,
+            Some(BufferCopy {
+                src,
+                dst: offset,
+                size,
+            }),
+        );
+    }
+
+    /// Upload the texels of an image region, rows of texel blocks tightly packed.
+    ///
+    /// The image has to be in `layout` when the copy executes, which must be
+    /// `TransferDstOptimal` or `General`.
+    pub fn write_image<C, S, L>(
+        &mut self,
+        device: &B::Device,
+        cmd_buffer: &mut CommandBuffer<B, C, S, L>,
+        target: &B::Image,
+        layout: image::Layout,
+        format: Format,
+        layers: image::SubresourceLayers,
+        offset: image::Offset,
+        extent: image::Extent,
+        data: &[u8],
+    ) where
+        C: Supports<Transfer>,
+        S: Shot,
+        L: Level,
+    {
+        let layer_count = (layers.layers.end - layers.layers.start) as u64 * extent.depth as u64;
+        let (pitch, buffer_width, rows) = staging_layout(format, extent.width, extent.height, self.copy_pitch_alignment);
+        let slices = rows as u64 * layer_count;
+        let row_size = if slices == 0 { 0 } else { data.len() / slices as usize };
+        assert!(
+            row_size > 0 && row_size as u64 <= pitch && row_size as u64 * slices == data.len() as u64,
+            "Texel data doesn't match the image region"
+        );
+        let (index, buffer_offset) = self.allocate(device, pitch * slices);
+        let chunk = &self.active[index];
+        for (row, texels) in data.chunks(row_size).enumerate() {
+            unsafe {
+                ptr::copy_nonoverlapping(
+                    texels.as_ptr(),
+                    chunk.mapping.offset((buffer_offset + row as u64 * pitch) as isize),
+                    row_size,
+                );
+            }
+        }
+        cmd_buffer.copy_buffer_to_image(
+            &chunk.buffer,
+            target,
+            layout,
+            Some(BufferImageCopy {
+                buffer_offset,
+                buffer_width,
+                buffer_height: extent.height,
+                image_layers: layers,
+                image_offset: offset,
+                image_extent: extent,
+            }),
+        );
+    }
+
+    /// Finish the uploads written since the last call, flushing non-coherent chunks.
+    ///
+    /// The returned fence has to be signaled by the submission of the command buffers
+    /// the copies were recorded into. Returns `None` if nothing was uploaded.
+    pub fn finish(&mut self, device: &B::Device) -> Option<&B::Fence> {
+        if self.active.is_empty() {
+            return None;
+        }
+        let atom_size = self.atom_size;
+        let ranges = self.active
+            .iter()
+            .filter(|chunk| !chunk.coherent)
+            .map(|chunk| {
+                let end = (chunk.offset + atom_size - 1) / atom_size * atom_size;
+                (&chunk.memory, 0 .. cmp::min(end, chunk.size))
+            })
+            .collect::<Vec<_>>();
+        if !ranges.is_empty() {
+            device.flush_mapped_memory_ranges(ranges);
+        }
+
+        let fence = match self.free_fences.pop() {
+            Some(fence) => fence,
+            None => device.create_fence(false),
+        };
+        self.in_flight.push_back(InFlight {
+            fence,
+            chunks: self.active.drain(..).collect(),
+        });
+        self.in_flight.back().map(|flight| &flight.fence)
+    }
+
+    /// Recycle the chunks of the submissions which have completed, returns the number
+    /// of submissions recycled.
+    pub fn recall(&mut self, device: &B::Device) -> usize {
+        let mut count = 0;
+        while self.in_flight.front().map_or(false, |flight| device.get_fence_status(&flight.fence)) {
+            let flight = self.in_flight.pop_front().unwrap();
+            device.reset_fence(&flight.fence);
+            self.free_fences.push(flight.fence);
+            self.free_chunks.extend(flight.chunks);
+            count += 1;
+        }
+        count
+    }
+
+    /// Recycle all chunks without checking their fences, for when the completion of
+    /// the submissions using them is known otherwise, e.g. from a frame fence.
+    ///
+    /// The uploads written since the last `finish` are recycled as well, the submissions
+    /// they were recorded for must have completed.
+    pub fn reset(&mut self, device: &B::Device) {
+        for flight in self.in_flight.drain(..) {
+            device.reset_fence(&flight.fence);
+            self.free_fences.push(flight.fence);
+            self.free_chunks.extend(flight.chunks);
+        }
+        self.free_chunks.extend(self.active.drain(..));
+    }
+
+    /// Destroy the free chunks, releasing their memory.
+    pub fn trim(&mut self, device: &B::Device) {
+        for chunk in self.free_chunks.drain(..) {
+            chunk.destroy(device);
+        }
+    }
+
+    /// Destroy the belt, waiting for the submissions in flight to complete.
+    pub fn destroy(mut self, device: &B::Device) {
+        for flight in self.in_flight.drain(..) {
+            device.wait_for_fence(&flight.fence, !0);
+            device.destroy_fence(flight.fence);
+            for chunk in flight.chunks {
+                chunk.destroy(device);
+            }
+        }
+        for chunk in self.active.drain(..).chain(self.free_chunks.drain(..)) {
+            chunk.destroy(device);
+        }
+        for fence in self.free_fences.drain(..) {
+            device.destroy_fence(fence);
+        }
+    }
+}
@@ -0,0 +1,421 @@
+//! Headless video frame production.
+//!
+//! `FrameProducer` renders into an offscreen image and reads every frame back into
+//! host visible memory, handing the frames out in order and timestamped at a fixed
+//! frame rate, e.g. to feed a video encoder without any window or swapchain.
+//!
+//! Frames are only handed out as mapped memory, exporting them as external
+//! memory handles isn't supported yet.
+//!
+//! ```ignore
+//! let mut producer = FrameProducer::new(&device, &physical_device, &queue_group, extent, format, 60, 3);
+//! loop {
+//!     producer.pace();
+//!     while producer.render(&mut queue, ClearColor::Float([0.0; 4]), |encoder| { /* draw */ }).is_none() {
+//!         producer.read_frame(&device, true, |frame| encoder.push(frame.timestamp, frame.data));
+//!     }
+//! }
+//! ```
+
+use std::collections::VecDeque;
+use std::fmt;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use hal::adapter::{MemoryTypeId, PhysicalDevice};
+use hal::command::{BufferImageCopy, ClearColor, ClearValue, OneShot, Primary, RenderPassInlineEncoder};
+use hal::device::{Device, WaitFor};
+use hal::format::{Aspects, Format, Swizzle};
+use hal::memory::{Barrier, Dependencies, Properties};
+use hal::pool::{CommandPool, CommandPoolCreateFlags};
+use hal::pso::{PipelineStage, Rect};
+use hal::queue::{CommandQueue, Graphics, QueueGroup, Submission};
+use hal::window::Extent2D;
+use hal::{buffer, image, pass, Backend};
+
+const COLOR_RANGE: image::SubresourceRange = image::SubresourceRange {
+    aspects: Aspects::COLOR,
+    levels: 0 .. 1,
+    layers: 0 .. 1,
+};
+
+/// A frame read back into host memory.
+#[derive(Debug)]
+pub struct VideoFrame<'a> {
+    /// Index of the frame, counting from the first frame rendered.
+    pub index: u64,
+    /// Presentation time of the frame, relative to the first frame.
+    pub timestamp: Duration,
+    /// Size of the frame in pixels.
+    pub extent: Extent2D,
+    /// Format of the pixels.
+    pub format: Format,
+    /// Number of bytes between the starts of two consecutive rows.
+    pub row_pitch: usize,
+    /// Pixel data of `row_pitch * extent.height` bytes.
+    pub data: &'a [u8],
+}
+
+/// Readback buffer and submission resources of a frame in flight.
+struct Slot<B: Backend> {
+    command_pool: CommandPool<B, Graphics>,
+    fence: B::Fence,
+    buffer: B::Buffer,
+    memory: B::Memory,
+}
+
+/// Renders frames offscreen and reads them back at a fixed cadence.
+pub struct FrameProducer<B: Backend> {
+    extent: Extent2D,
+    format: Format,
+    frame_rate: u32,
+    image: B::Image,
+    image_memory: B::Memory,
+    view: B::ImageView,
+    render_pass: B::RenderPass,
+    framebuffer: B::Framebuffer,
+    row_pitch: u64,
+    // Row length of the readback buffers in texels.
+    buffer_width: u32,
+    slots: Vec<Slot<B>>,
+    free_slots: Vec<usize>,
+    // Slots and indices of the rendered frames, oldest first.
+    pending: VecDeque<(usize, u64)>,
+    frame_count: u64,
+    start: Option<Instant>,
+}
+
+impl<B: Backend> fmt::Debug for FrameProducer<B> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("FrameProducer")
+            .field("extent", &self.extent)
+            .field("format", &self.format)
+            .field("frame_rate", &self.frame_rate)
+            .field("frame_count", &self.frame_count)
+            .field("pending", &self.pending.len())
+            .finish()
+    }
+}
+
+impl<B: Backend> FrameProducer<B> {
+    /// Create a producer of frames of the given size and format, at `frame_rate` frames per second.
+    ///
+    /// Up to `frames_in_flight` frames may be rendered before they have to be read back.
+    /// The command pools are allocated from the given queue group.
+    pub fn new(
+        device: &B::Device,
+        physical_device: &B::PhysicalDevice,
+        group: &QueueGroup<B, Graphics>,
+        extent: Extent2D,
+        format: Format,
+        frame_rate: u32,
+        frames_in_flight: usize,
+    ) -> Self {
+        assert!(frame_rate > 0);
+        assert!(frames_in_flight > 0);
+
+        let memory_types = physical_device.memory_properties().memory_types;
+        let find_memory = |type_mask: u64, properties: Properties| -> MemoryTypeId {
+            memory_types
+                .iter()
+                .enumerate()
+                .position(|(id, ty)| type_mask & (1 << id) != 0 && ty.properties.contains(properties))
+                .expect("No suitable memory type")
+                .into()
+        };
+
+        let image_extent = image::Extent {
+            width: extent.width,
+            height: extent.height,
+            depth: 1,
+        };
+        let unbound = device
+            .create_image(
+                image::Kind::D2(extent.width, extent.height, 1, 1),
+                1,
+                format,
+                image::Tiling::Optimal,
+                image::Usage::COLOR_ATTACHMENT | image::Usage::TRANSFER_SRC,
+                image::StorageFlags::empty(),
+            )
+            .expect("Unable to create the frame image");
+        let requirements = device.get_image_requirements(&unbound);
+        let image_memory = device
+            .allocate_memory(find_memory(requirements.type_mask, Properties::DEVICE_LOCAL), requirements.size)
+            .expect("Unable to allocate the frame image");
+        let image = device.bind_image_memory(&image_memory, 0, unbound).unwrap();
+        let view = device
+            .create_image_view(&image, image::ViewKind::D2, format, Swizzle::NO, COLOR_RANGE.clone())
+            .unwrap();
+
+        let render_pass = {
+            let attachment = pass::Attachment {
+                format: Some(format),
+                samples: 1,
+                ops: pass::AttachmentOps::new(pass::AttachmentLoadOp::Clear, pass::AttachmentStoreOp::Store),
+                stencil_ops: pass::AttachmentOps::DONT_CARE,
+                layouts: image::Layout::Undefined .. image::Layout::TransferSrcOptimal,
+            };
+            let subpass = pass::SubpassDesc {
+                colors: &[(0, image::Layout::ColorAttachmentOptimal)],
+                depth_stencil: None,
+                inputs: &[],
+                resolves: &[],
+                preserves: &[],
+            };
+            // The previous frame needs to be copied out before being overwritten.
+            let dependency = pass::SubpassDependency {
+                passes: pass::SubpassRef::External .. pass::SubpassRef::Pass(0),
+                stages: PipelineStage::TRANSFER .. PipelineStage::COLOR_ATTACHMENT_OUTPUT,
+                accesses: image::Access::empty() ..
+                    (image::Access::COLOR_ATTACHMENT_READ | image::Access::COLOR_ATTACHMENT_WRITE),
+            };
+            device.create_render_pass(&[attachment], &[subpass], &[dependency])
+        };
+        let framebuffer = device
+            .create_framebuffer(&render_pass, Some(&view), image_extent)
+            .unwrap();
+
+        let bytes_per_texel = (format.surface_desc().bits / 8) as u64;
+        let alignment = physical_device.limits().min_buffer_copy_pitch_alignment.max(1);
+        let row_pitch = (extent.width as u64 * bytes_per_texel + alignment - 1) / alignment * alignment;
+        assert_eq!(row_pitch % bytes_per_texel, 0, "Unsupported frame format {:?}", format);
+
+        let slots = (0 .. frames_in_flight)
+            .map(|_| {
+                let buffer = device
+                    .create_buffer(row_pitch * extent.height as u64, buffer::Usage::TRANSFER_DST)
+                    .expect("Unable to create a readback buffer");
+                let requirements = device.get_buffer_requirements(&buffer);
+                let memory = device
+                    .allocate_memory(find_memory(requirements.type_mask, Properties::CPU_VISIBLE), requirements.size)
+                    .expect("Unable to allocate a readback buffer");
+                let buffer = device.bind_buffer_memory(&memory, 0, buffer).unwrap();
+
+                Slot {
+                    command_pool: device.create_command_pool_typed(group, CommandPoolCreateFlags::TRANSIENT, 1),
+                    fence: device.create_fence(false),
+                    buffer,
+                    memory,
+                }
+            })
+            .collect();
+
+        FrameProducer {
+            extent,
+            format,
+            frame_rate,
+            image,
+            image_memory,
+            view,
+            render_pass,
+            framebuffer,
+            row_pitch,
+            buffer_width: (row_pitch / bytes_per_texel) as u32,
+            slots,
+            free_slots: (0 .. frames_in_flight).rev().collect(),
+            pending: VecDeque::new(),
+            frame_count: 0,
+            start: None,
+        }
+    }
+
+    /// Returns the size of the frames.
+    pub fn extent(&self) -> Extent2D {
+        self.extent
+    }
+
+    /// Returns the format of the frames.
+    pub fn format(&self) -> Format {
+        self.format
+    }
+
+    /// Returns the number of frames per second.
+    pub fn frame_rate(&self) -> u32 {
+        self.frame_rate
+    }
+
+    /// Returns the number of frames rendered so far.
+    pub fn frame_count(&self) -> u64 {
+        self.frame_count
+    }
+
+    /// Returns the number of rendered frames which haven't been read back yet.
+    pub fn pending_frames(&self) -> usize {
+        self.pending.len()
+    }
+
+    /// Returns the presentation time of a frame, relative to the first frame.
+    pub fn timestamp(&self, index: u64) -> Duration {
+        let rate = self.frame_rate as u64;
+        Duration::new(index / rate, ((index % rate) * 1_000_000_000 / rate) as u32)
+    }
+
+    /// Block until the presentation time of the next frame, to produce frames in real time.
+    ///
+    /// The cadence starts with the first frame rendered.
+    pub fn pace(&self) {
+        if let Some(start) = self.start {
+            let deadline = start + self.timestamp(self.frame_count);
+            let now = Instant::now();
+            if deadline > now {
+                thread::sleep(deadline - now);
+            }
+        }
+    }
+
+    /// Render the next frame and submit its readback.
+    ///
+    /// The frame is cleared to `clear_color` before `record` records the draws into the
+    /// render pass. The viewport and scissor are left for `record` to set.
+    ///
+    /// Returns the index of the frame, or `None` if all frames in flight are still
+    /// waiting to be read back, in which case nothing is rendered.
+    pub fn render<F>(
+        &mut self,
+        queue: &mut CommandQueue<B, Graphics>,
+        clear_color: ClearColor,
+        record: F,
+    ) -> Option<u64>
+    where
+        F: FnOnce(&mut RenderPassInlineEncoder<B, Primary>),
+    {
+        let slot_index = self.free_slots.pop()?;
+        let index = self.frame_count;
+        self.frame_count += 1;
+        if self.start.is_none() {
+            self.start = Some(Instant::now());
+        }
+
+        let slot = &mut self.slots[slot_index];
+        let submit = {
+            let mut cmd_buffer = slot.command_pool.acquire_command_buffer::<OneShot>(false);
+            {
+                let area = Rect {
+                    x: 0,
+                    y: 0,
+                    w: self.extent.width as _,
+                    h: self.extent.height as _,
+                };
+                let mut encoder = cmd_buffer.begin_render_pass_inline(
+                    &self.render_pass,
+                    &self.framebuffer,
+                    area,
+                    &[ClearValue::Color(clear_color)],
+                );
+                record(&mut encoder);
+            }
+
+            cmd_buffer.pipeline_barrier(
+                PipelineStage::COLOR_ATTACHMENT_OUTPUT .. PipelineStage::TRANSFER,
+                Dependencies::empty(),
+                &[Barrier::Image {
+                    states: (image::Access::COLOR_ATTACHMENT_WRITE, image::Layout::TransferSrcOptimal) ..
+                        (image::Access::TRANSFER_READ, image::Layout::TransferSrcOptimal),
+                    target: &self.image,
+                    range: COLOR_RANGE.clone(),
+                    families: None,
+                }],
+            );
+            cmd_buffer.copy_image_to_buffer(
+                &self.image,
+                image::Layout::TransferSrcOptimal,
+                &slot.buffer,
+                &[BufferImageCopy {
+                    buffer_offset: 0,
+                    buffer_width: self.buffer_width,
+                    buffer_height: self.extent.height,
+                    image_layers: image::SubresourceLayers {
+                        aspects: Aspects::COLOR,
+                        level: 0,
+                        layers: 0 .. 1,
+                    },
+                    image_offset: image::Offset { x: 0, y: 0, z: 0 },
+                    image_extent: image::Extent {
+                        width: self.extent.width,
+                        height: self.extent.height,
+                        depth: 1,
+                    },
+                }],
+            );
+            cmd_buffer.pipeline_barrier(
+                PipelineStage::TRANSFER .. PipelineStage::HOST,
+                Dependencies::empty(),
+                &[Barrier::Buffer {
+                    states: buffer::Access::TRANSFER_WRITE .. buffer::Access::HOST_READ,
+                    target: &slot.buffer,
+                    families: None,
+                }],
+            );
+            cmd_buffer.finish()
+        };
+
+        queue.submit(Submission::new().submit(Some(submit)), Some(&slot.fence));
+        self.pending.push_back((slot_index, index));
+        Some(index)
+    }
+
+    /// Read back the oldest rendered frame and pass it to `read`.
+    ///
+    /// Blocks until the frame has been rendered if `wait` is set, otherwise returns `None`
+    /// if it isn't ready yet. Also returns `None` if there are no frames to read.
+    pub fn read_frame<F, R>(&mut self, device: &B::Device, wait: bool, read: F) -> Option<R>
+    where
+        F: FnOnce(VideoFrame) -> R,
+    {
+        let (slot_index, index) = *self.pending.front()?;
+        let timestamp = self.timestamp(index);
+        let size = self.row_pitch * self.extent.height as u64;
+
+        let slot = &mut self.slots[slot_index];
+        if wait {
+            device.wait_for_fence(&slot.fence, !0);
+        } else if !device.get_fence_status(&slot.fence) {
+            return None;
+        }
+        self.pending.pop_front();
+
+        let result = {
+            let reader = device
+                .acquire_mapping_reader::<u8>(&slot.memory, 0 .. size)
+                .expect("Unable to map a readback buffer");
+            let result = read(VideoFrame {
+                index,
+                timestamp,
+                extent: self.extent,
+                format: self.format,
+                row_pitch: self.row_pitch as usize,
+                data: &reader,
+            });
+            device.release_mapping_reader(reader);
+            result
+        };
+
+        device.reset_fence(&slot.fence);
+        slot.command_pool.reset();
+        self.free_slots.push(slot_index);
+        Some(result)
+    }
+
+    /// Wait for all frames to complete and destroy the producer, dropping unread frames.
+    pub fn destroy(self, device: &B::Device) {
+        {
+            let slots = &self.slots;
+            let fences = self.pending
+                .iter()
+                .map(|&(slot, _)| &slots[slot].fence);
+            device.wait_for_fences(fences, WaitFor::All, !0);
+        }
+        for slot in self.slots {
+            device.destroy_fence(slot.fence);
+            device.destroy_command_pool(slot.command_pool.into_raw());
+            device.destroy_buffer(slot.buffer);
+            device.free_memory(slot.memory);
+        }
+        device.destroy_framebuffer(self.framebuffer);
+        device.destroy_render_pass(self.render_pass);
+        device.destroy_image_view(self.view);
+        device.destroy_image(self.image);
+        device.free_memory(self.image_memory);
+    }
+}
@@ -0,0 +1,504 @@
+//! Pipeline warm-up from serialized descriptions.
+//!
+//! Creating a pipeline the first time it's drawn with stalls the frame while the driver
+//! compiles its shaders. Shipped applications record the pipelines they use as
+//! `SerializedPipelineDesc`s, referencing shader modules by the hash of their SPIR-V and
+//! layouts and render passes by index, and compile them all at startup with
+//! `warm_pipelines`, which returns while background threads create the pipelines:
+//!
+//! ```ignore
+//! let mut resources = PipelineResources::new();
+//! resources.insert_shader(&vs_spirv, vs_module.clone());
+//! resources.insert_shader(&fs_spirv, fs_module.clone());
+//! resources.layouts.push(layout.clone());
+//! resources.render_passes.push(render_pass.clone());
+//! let warmup = warm_pipelines(device.clone(), descs, resources, 4);
+//! while !warmup.is_finished() {
+//!     // draw the loading screen
+//! }
+//! let pipelines = warmup.wait();
+//! ```
+//!
+//! The device objects are shared with the threads through `Arc`s, which are released
+//! once the warm-up has completed.
+
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+use hal::device::Device;
+use hal::pass::{Subpass, SubpassId};
+use hal::pso::{
+    fnv1a, BakedStates, BasePipeline, BlendDesc, CreationError, DepthStencilDesc, EntryPoint,
+    GraphicsPipelineDesc, GraphicsShaderSet, InputAssemblerDesc, Multisampling,
+    PipelineCreationFlags, Rasterizer, Specialization, AttributeDesc, VertexBufferDesc,
+};
+use hal::Backend;
+
+/// FNV-1a hash of the SPIR-V code of a shader module.
+#[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+#[cfg_attr(feature = "serialize", derive(Serialize, Deserialize))]
+pub struct ShaderHash(pub u64);
+
+impl ShaderHash {
+    /// Hash SPIR-V code, as passed to `Device::create_shader_module`.
+    pub fn of(spirv: &[u8]) -> Self {
+        ShaderHash(fnv1a(spirv))
+    }
+}
+
+/// Serializable shader entry point, referencing its module by hash.
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serialize", derive(Serialize, Deserialize))]
+pub struct SerializedEntryPoint {
+    /// Hash of the shader module.
+    pub shader: ShaderHash,
+    /// Name of the entry point.
+    pub entry: String,
+    /// Specialization constants of the entry point.
+    pub specialization: Vec<Specialization>,
+}
+
+/// Serializable graphics pipeline description.
+///
+/// Layouts and render passes are referenced by their index in `PipelineResources`,
+/// which the application fills in the same order it recorded the descriptions with.
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serialize", derive(Serialize, Deserialize))]
+pub struct SerializedPipelineDesc {
+    /// Vertex shader.
+    pub vertex: SerializedEntryPoint,
+    /// Hull shader.
+    pub hull: Option<SerializedEntryPoint>,
+    /// Domain shader.
+    pub domain: Option<SerializedEntryPoint>,
+    /// Geometry shader.
+    pub geometry: Option<SerializedEntryPoint>,
+    /// Fragment shader.
+    pub fragment: Option<SerializedEntryPoint>,
+    /// Rasterizer setup.
+    pub rasterizer: Rasterizer,
+    /// Vertex buffers.
+    pub vertex_buffers: Vec<VertexBufferDesc>,
+    /// Vertex attributes.
+    pub attributes: Vec<AttributeDesc>,
+    /// Input assembler setup.
+    pub input_assembler: InputAssemblerDesc,
+    /// Blending setup.
+    pub blender: BlendDesc,
+    /// Depth stencil setup.
+    pub depth_stencil: DepthStencilDesc,
+    /// Multisampling setup.
+    pub multisampling: Option<Multisampling>,
+    /// Static pipeline states.
+    pub baked_states: BakedStates,
+    /// Index of the pipeline layout in `PipelineResources::layouts`.
+    pub layout: usize,
+    /// Index of the render pass in `PipelineResources::render_passes`.
+    pub render_pass: usize,
+    /// Subpass of the render pass.
+    pub subpass: SubpassId,
+}
+
+/// Error on warming up a pipeline.
+#[derive(Fail, Debug, Clone, PartialEq)]
+pub enum WarmupError {
+    /// No shader module with the hash was provided.
+    #[fail(display = "Missing shader module {:?}.", _0)]
+    MissingShader(ShaderHash),
+    /// The layout index is out of range.
+    #[fail(display = "Missing pipeline layout {}.", _0)]
+    MissingLayout(usize),
+    /// The render pass index is out of range.
+    #[fail(display = "Missing render pass {}.", _0)]
+    MissingRenderPass(usize),
+    /// The backend failed to create the pipeline.
+    #[fail(display = "{}", _0)]
+    Creation(CreationError),
+}
+
+/// Device objects the serialized pipeline descriptions are resolved against.
+pub struct PipelineResources<B: Backend> {
+    /// Shader modules by hash of their SPIR-V code.
+    pub shaders: HashMap<ShaderHash, Arc<B::ShaderModule>>,
+    /// Pipeline layouts.
+    pub layouts: Vec<Arc<B::PipelineLayout>>,
+    /// Render passes.
+    pub render_passes: Vec<Arc<B::RenderPass>>,
+    /// Pipeline cache the pipelines are created with, e.g. loaded from a previous run.
+    pub cache: Option<Arc<B::PipelineCache>>,
+}
+
+impl<B: Backend> Default for PipelineResources<B> {
+    fn default() -> Self {
+        PipelineResources {
+            shaders: HashMap::new(),
+            layouts: Vec::new(),
+            render_passes: Vec::new(),
+            cache: None,
+        }
+    }
+}
+
+impl<B: Backend> PipelineResources<B> {
+    /// Create empty resources.
+    pub fn new() -> Self {
+        PipelineResources::default()
+    }
+
+    /// Add a shader module created from `spirv`, returning its hash.
+    pub fn insert_shader(&mut self, spirv: &[u8], module: Arc<B::ShaderModule>) -> ShaderHash {
+        let hash = ShaderHash::of(spirv);
+        self.shaders.insert(hash, module);
+        hash
+    }
+
+    fn entry_point<'a>(&'a self, entry: &'a SerializedEntryPoint) -> Result<EntryPoint<'a, B>, WarmupError> {
+        match self.shaders.get(&entry.shader) {
+            Some(module) => Ok(EntryPoint {
+                entry: &entry.entry,
+                module,
+                specialization: &entry.specialization,
+            }),
+            None => Err(WarmupError::MissingShader(entry.shader)),
+        }
+    }
+}
+
+impl SerializedPipelineDesc {
+    /// Record a pipeline description, `shader` returning the hash of each module.
+    pub fn new<B, F>(
+        desc: &GraphicsPipelineDesc<B>,
+        layout: usize,
+        render_pass: usize,
+        shader: F,
+    ) -> Self
+    where
+        B: Backend,
+        F: Fn(&B::ShaderModule) -> ShaderHash,
+    {
+        let entry_point = |entry: &EntryPoint<B>| SerializedEntryPoint {
+            shader: shader(entry.module),
+            entry: entry.entry.to_owned(),
+            specialization: entry.specialization.to_vec(),
+        };
+        let shaders = &desc.shaders;
+        SerializedPipelineDesc {
+            vertex: entry_point(&shaders.vertex),
+            hull: shaders.hull.as_ref().map(&entry_point),
+            domain: shaders.domain.as_ref().map(&entry_point),
+            geometry: shaders.geometry.as_ref().map(&entry_point),
+            fragment: shaders.fragment.as_ref().map(&entry_point),
+            rasterizer: desc.rasterizer.clone(),
+            vertex_buffers: desc.vertex_buffers.clone(),
+            attributes: desc.attributes.clone(),
+            input_assembler: desc.input_assembler.clone(),
+            blender: desc.blender.clone(),
+            depth_stencil: desc.depth_stencil,
+            multisampling: desc.multisampling.clone(),
+            baked_states: desc.baked_states.clone(),
+            layout,
+            render_pass,
+            subpass: desc.subpass.index,
+        }
+    }
+
+    /// Resolve the description against the device objects.
+    pub fn resolve<'a, B: Backend>(
+        &'a self, resources: &'a PipelineResources<B>
+    ) -> Result<GraphicsPipelineDesc<'a, B>, WarmupError> {
+        let optional = |entry: &'a Option<SerializedEntryPoint>| match *entry {
+            Some(ref entry) => resources.entry_point(entry).map(Some),
+            None => Ok(None),
+        };
+        let shaders = GraphicsShaderSet {
+            vertex: resources.entry_point(&self.vertex)?,
+            hull: optional(&self.hull)?,
+            domain: optional(&self.domain)?,
+            geometry: optional(&self.geometry)?,
+            fragment: optional(&self.fragment)?,
+        };
+        let layout = resources.layouts
+            .get(self.layout)
+            .ok_or(WarmupError::MissingLayout(self.layout))?;
+        let main_pass = resources.render_passes
+            .get(self.render_pass)
+            .ok_or(WarmupError::MissingRenderPass(self.render_pass))?;
+
+        Ok(GraphicsPipelineDesc {
+            shaders,
+            rasterizer: self.rasterizer.clone(),
+            vertex_buffers: self.vertex_buffers.clone(),
+            attributes: self.attributes.clone(),
+            input_assembler: self.input_assembler.clone(),
+            blender: self.blender.clone(),
+            depth_stencil: self.depth_stencil,
+            multisampling: self.multisampling.clone(),
+            baked_states: self.baked_states.clone(),
+            layout,
+            subpass: Subpass {
+                index: self.subpass,
+                main_pass,
+            },
+            flags: PipelineCreationFlags::empty(),
+            parent: BasePipeline::None,
+        })
+    }
+}
+
+type WarmupResult<B> = Result<<B as Backend>::GraphicsPipeline, WarmupError>;
+
+// Pipelines shared between the warm-up threads, which take the next one by index.
+struct Work<B: Backend, D> {
+    device: Arc<D>,
+    descs: Vec<SerializedPipelineDesc>,
+    resources: PipelineResources<B>,
+    next: AtomicUsize,
+    done: AtomicUsize,
+    results: Mutex<Vec<Option<WarmupResult<B>>>>,
+}
+
+impl<B: Backend, D: Device<B>> Work<B, D> {
+    fn run(&self) {
+        loop {
+            let index = self.next.fetch_add(1, Ordering::Relaxed);
+            let desc = match self.descs.get(index) {
+                Some(desc) => desc,
+                None => break,
+            };
+            let result = desc.resolve(&self.resources).and_then(|desc| {
+                self.device
+                    .create_graphics_pipeline_with_cache(&desc, self.resources.cache.as_ref().map(|cache| &**cache))
+                    .map_err(WarmupError::Creation)
+            });
+            self.results.lock().unwrap()[index] = Some(result);
+            self.done.fetch_add(1, Ordering::Release);
+        }
+    }
+}
+
+/// Pipeline warm-up in progress, returned by `warm_pipelines`.
+///
+/// Dropping the warm-up without waiting for it stops the threads once they have
+/// created their current pipeline. The pipelines created so far are dropped without
+/// being destroyed.
+pub struct Warmup<B: Backend, D> {
+    work: Arc<Work<B, D>>,
+    threads: Vec<thread::JoinHandle<()>>,
+}
+
+impl<B: Backend, D: Device<B>> Warmup<B, D> {
+    /// Number of pipelines created so far, out of the total.
+    pub fn progress(&self) -> (usize, usize) {
+        (self.work.done.load(Ordering::Acquire), self.work.descs.len())
+    }
+
+    /// Check if all pipelines have been created.
+    pub fn is_finished(&self) -> bool {
+        let (done, total) = self.progress();
+        done == total
+    }
+
+    /// Wait for all pipelines, returning them in the order of the descriptions.
+    ///
+    /// The calling thread helps creating the remaining pipelines, which makes it
+    /// create all of them if the warm-up was started without threads.
+    ///
+    /// # Panics
+    ///
+    /// Panics if a warm-up thread panicked.
+    pub fn wait(mut self) -> Vec<WarmupResult<B>> {
+        self.work.run();
+        let mut panicked = false;
+        for handle in self.threads.drain(..) {
+            panicked |= handle.join().is_err();
+        }
+        if panicked {
+            panic!("A pipeline warm-up thread panicked");
+        }
+
+        let mut results = self.work.results.lock().unwrap();
+        results
+            .drain(..)
+            .map(|result| result.unwrap())
+            .collect()
+    }
+}
+
+impl<B: Backend, D> Drop for Warmup<B, D> {
+    fn drop(&mut self) {
+        // Remaining pipelines aren't handed out anymore.
+        self.work.next.store(self.work.descs.len(), Ordering::Relaxed);
+    }
+}
+
+/// Create the pipelines of `descs` on `threads` background threads.
+///
+/// Returns immediately, the pipelines are retrieved with `Warmup::wait`. Meant to be
+/// called at startup, with the pipeline cache of the previous run in `resources`.
+///
+/// Backends with a device bound to the thread which created it, like GL, must be
+/// passed no threads, so that all pipelines are created by `wait` on the calling thread.
+pub fn warm_pipelines<B, D>(
+    device: Arc<D>,
+    descs: Vec<SerializedPipelineDesc>,
+    resources: PipelineResources<B>,
+    threads: usize,
+) -> Warmup<B, D>
+where
+    B: Backend,
+    D: Device<B>,
+{
+    let work = Arc::new(Work {
+        device,
+        results: Mutex::new(descs.iter().map(|_| None).collect()),
+        descs,
+        resources,
+        next: AtomicUsize::new(0),
+        done: AtomicUsize::new(0),
+    });
+
+    let mut spawned = Vec::new();
+    for _ in 0 .. threads.min(work.descs.len()) {
+        let work = Arc::clone(&work);
+        let result = thread::Builder::new()
+            .name("gfx-pipeline-warmup".to_owned())
+            .spawn(move || work.run());
+        match result {
+            Ok(handle) => spawned.push(handle),
+            // The remaining work is done by the threads spawned so far and `wait`.
+            Err(_) => break,
+        }
+    }
+
+    Warmup {
+        work,
+        threads: spawned,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use hal::pass::{Attachment, SubpassDependency, SubpassDesc};
+    use hal::pso::ShaderStageFlags;
+    use hal::queue::General;
+    use hal::{Instance, Primitive};
+    use software;
+    use std::ops::Range;
+
+    type Backend = software::Backend;
+
+    fn inst(opcode: u32, operands: &[u32]) -> Vec<u32> {
+        let mut words = vec![(operands.len() as u32 + 1) << 16 | opcode];
+        words.extend_from_slice(operands);
+        words
+    }
+
+    // Vertex shader with an empty `main`.
+    fn vertex_module() -> Vec<u8> {
+        let mut words = vec![0x0723_0203, 0x0001_0000, 0, 5, 0];
+        words.extend(inst(17, &[1])); // OpCapability Shader
+        words.extend(inst(14, &[0, 1])); // OpMemoryModel Logical GLSL450
+        words.extend(inst(15, &[0, 1, 0x6E69_616D, 0])); // OpEntryPoint Vertex %1 "main"
+        words.extend(inst(19, &[2])); // OpTypeVoid
+        words.extend(inst(33, &[3, 2])); // OpTypeFunction
+        words.extend(inst(54, &[2, 1, 0, 3])); // OpFunction
+        words.extend(inst(248, &[4])); // OpLabel
+        words.extend(inst(253, &[])); // OpReturn
+        words.extend(inst(56, &[])); // OpFunctionEnd
+        words
+            .iter()
+            .flat_map(|word| (0 .. 4).map(move |i| (word >> (i * 8)) as u8))
+            .collect()
+    }
+
+    fn setup() -> (Arc<software::Device>, Vec<SerializedPipelineDesc>, PipelineResources<Backend>) {
+        let mut adapter = software::Instance::create("warmup", 1).enumerate_adapters().remove(0);
+        let (device, _) = adapter.open_with::<_, General>(1, |_| true).unwrap();
+        let spirv = vertex_module();
+        let module = device.create_shader_module(&spirv).unwrap();
+        let layout = device.create_pipeline_layout(
+            Vec::<<Backend as hal::Backend>::DescriptorSetLayout>::new(),
+            Vec::<(ShaderStageFlags, Range<u32>)>::new(),
+        );
+        let subpass = SubpassDesc {
+            colors: &[],
+            depth_stencil: None,
+            inputs: &[],
+            resolves: &[],
+            preserves: &[],
+        };
+        let render_pass = device.create_render_pass(
+            Vec::<Attachment>::new(),
+            &[subpass],
+            Vec::<SubpassDependency>::new(),
+        );
+
+        let hash = ShaderHash::of(&spirv);
+        let descs = {
+            let desc = GraphicsPipelineDesc::<Backend>::new(
+                GraphicsShaderSet {
+                    vertex: EntryPoint { entry: "main", module: &module, specialization: &[] },
+                    hull: None,
+                    domain: None,
+                    geometry: None,
+                    fragment: None,
+                },
+                Primitive::TriangleList,
+                Rasterizer::FILL,
+                &layout,
+                Subpass { index: 0, main_pass: &render_pass },
+            );
+            let valid = SerializedPipelineDesc::new(&desc, 0, 0, |_| hash);
+            let mut missing_shader = valid.clone();
+            missing_shader.vertex.shader = ShaderHash(0);
+            let mut missing_layout = valid.clone();
+            missing_layout.layout = 1;
+            let mut missing_pass = valid.clone();
+            missing_pass.render_pass = 1;
+            vec![valid.clone(), missing_shader, valid.clone(), missing_layout, missing_pass, valid]
+        };
+
+        let mut resources = PipelineResources::new();
+        resources.insert_shader(&spirv, Arc::new(module));
+        resources.layouts.push(Arc::new(layout));
+        resources.render_passes.push(Arc::new(render_pass));
+        (Arc::new(device), descs, resources)
+    }
+
+    fn check(results: Vec<WarmupResult<Backend>>) {
+        let errors = results
+            .into_iter()
+            .map(|result| result.err())
+            .collect::<Vec<_>>();
+        assert_eq!(errors, vec![
+            None,
+            Some(WarmupError::MissingShader(ShaderHash(0))),
+            None,
+            Some(WarmupError::MissingLayout(1)),
+            Some(WarmupError::MissingRenderPass(1)),
+            None,
+        ]);
+    }
+
+    #[test]
+    fn test_warmup_threads() {
+        let (device, descs, resources) = setup();
+        let layout = Arc::clone(&resources.layouts[0]);
+        let warmup = warm_pipelines(device, descs, resources, 2);
+        check(warmup.wait());
+        // The resources are released along with the threads.
+        assert!(Arc::try_unwrap(layout).is_ok());
+    }
+
+    #[test]
+    fn test_warmup_without_threads() {
+        let (device, descs, resources) = setup();
+        let warmup = warm_pipelines(device, descs, resources, 0);
+        assert_eq!(warmup.progress(), (0, 6));
+        assert!(!warmup.is_finished());
+        check(warmup.wait());
+    }
+}
@@ -25,6 +25,7 @@ use std::fs::File;
 use std::path::PathBuf;
 
 use ron::de;
+use serde::Deserializer;
 
 
 #[derive(Debug, Deserialize)]
@@ -35,11 +36,23 @@ enum Expectation {
 
 #[derive(Debug, Deserialize)]
 struct Test {
+    #[serde(deserialize_with = "deserialize_features")]
     features: hal::Features,
     jobs: Vec<String>,
     expect: Expectation,
 }
 
+// RON doesn't support 128-bit integers, so the feature bits are written
+// out as a 64-bit value, covering the core and portability features.
+fn deserialize_features<'de, D: Deserializer<'de>>(deserializer: D) -> Result<hal::Features, D::Error> {
+    #[derive(Deserialize)]
+    struct Bits {
+        bits: u64,
+    }
+    let Bits { bits } = serde::Deserialize::deserialize(deserializer)?;
+    Ok(hal::Features::from_bits_truncate(bits as u128))
+}
+
 type Suite = HashMap<String, HashMap<String, Test>>;
 
 struct TestGroup {